@@ -0,0 +1,271 @@
+//! # an egui debugger
+//!
+//! a ROM in a window, with its registers, stack and memory beside it, to
+//! run, pause and step through an instruction or a frame at a time,
+//! through nothing but the stable API. a starting point for tools that
+//! want a proper GUI, rather than the terminal's:
+//!
+//! ```text
+//! cargo run --example debugger --features egui -- game.ch8 [--schip] [--quirks modern]
+//! ```
+//!
+//! the keypad's on the usual keys, 1234/QWER/ASDF/ZXCV, while the screen's
+//! being hovered over (so they don't get in the way of anything typed)
+use chip8::stable::{Config, Emulator, Frame, KeyEvent};
+use eframe::egui::{self, Color32, Key, RichText, Sense, TextStyle};
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::time::{Duration, Instant};
+
+/// the VIP's 60Hz
+const FRAME_TIME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+/// the most frames to catch up on at once, after the window's been held up
+const MAX_CATCH_UP_FRAMES: u32 = 4;
+
+/// memory's shown this many bytes to a row
+const MEMORY_ROW_BYTES: usize = 16;
+
+/// the COSMAC keypad, laid over the left of a QWERTY keyboard
+const KEYS: [(Key, u8); 16] = [
+    (Key::Num1, 0x1),
+    (Key::Num2, 0x2),
+    (Key::Num3, 0x3),
+    (Key::Num4, 0xc),
+    (Key::Q, 0x4),
+    (Key::W, 0x5),
+    (Key::E, 0x6),
+    (Key::R, 0xd),
+    (Key::A, 0x7),
+    (Key::S, 0x8),
+    (Key::D, 0x9),
+    (Key::F, 0xe),
+    (Key::Z, 0xa),
+    (Key::X, 0x0),
+    (Key::C, 0xb),
+    (Key::V, 0xf),
+];
+
+struct Debugger {
+    // kept to start again from
+    rom: Vec<u8>,
+    config: Config,
+    emulator: Emulator,
+    frame: Option<Frame>,
+    running: bool,
+    // when the next frame's due, while running
+    next: Instant,
+    follow_pc: bool,
+    error: Option<String>,
+}
+
+impl Debugger {
+    fn new(rom: Vec<u8>, config: Config) -> Result<Self, chip8::stable::Error> {
+        let emulator = Emulator::new(&rom, &config)?;
+        Ok(Debugger {
+            rom,
+            config,
+            emulator,
+            frame: None,
+            running: false,
+            next: Instant::now(),
+            follow_pc: true,
+            error: None,
+        })
+    }
+
+    /// do something to the emulator, stopping if it goes wrong
+    fn run(&mut self, what: impl FnOnce(&mut Emulator) -> Result<Frame, chip8::stable::Error>) {
+        match what(&mut self.emulator) {
+            Ok(f) => self.frame = Some(f),
+            Err(e) => {
+                self.error = Some(e.to_string());
+                self.running = false;
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        match Emulator::new(&self.rom, &self.config) {
+            Ok(e) => (self.emulator, self.frame, self.error) = (e, None, None),
+            Err(e) => self.error = Some(e.to_string()),
+        }
+    }
+
+    fn controls(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            let label = if self.running { "pause" } else { "run" };
+            if ui.button(label).clicked() {
+                self.running = !self.running;
+                self.next = Instant::now();
+            }
+            ui.add_enabled_ui(!self.running, |ui| {
+                if ui.button("step").clicked() {
+                    self.run(Emulator::step);
+                }
+                if ui.button("frame").clicked() {
+                    self.run(Emulator::run_frame);
+                }
+            });
+            if ui.button("reset").clicked() {
+                self.reset();
+            }
+            ui.checkbox(&mut self.follow_pc, "follow PC");
+            if let Some(e) = &self.error {
+                ui.colored_label(Color32::LIGHT_RED, e);
+            }
+        });
+    }
+
+    fn registers(&self, ui: &mut egui::Ui) {
+        let r = self.emulator.registers();
+        let pc = r.pc as usize;
+        let memory = self.emulator.memory();
+        let next = memory
+            .get(pc..pc + 2)
+            .map_or(0, |w| (w[0] as u16) << 8 | w[1] as u16);
+        ui.monospace(format!("PC {:04x}  ({:04x})", r.pc, next));
+        ui.monospace(format!("I  {:04x}", r.i));
+        egui::Grid::new("v").show(ui, |ui| {
+            for row in r.v.chunks(4).enumerate() {
+                for (n, v) in row.1.iter().enumerate() {
+                    ui.monospace(format!("V{:X} {:02x}", row.0 * 4 + n, v));
+                }
+                ui.end_row();
+            }
+        });
+        ui.monospace(format!("DT {:02x}  ST {:02x}", r.delay, r.sound));
+        ui.separator();
+        ui.label("stack");
+        for addr in &r.stack {
+            ui.monospace(format!("{:04x}", addr));
+        }
+    }
+
+    fn memory(&self, ui: &mut egui::Ui) {
+        let memory = self.emulator.memory();
+        let pc = self.emulator.registers().pc as usize;
+        let row_height = ui.text_style_height(&TextStyle::Monospace);
+        let rows = memory.len().div_ceil(MEMORY_ROW_BYTES);
+        let mut area = egui::ScrollArea::vertical().auto_shrink(false);
+        if self.follow_pc {
+            let row = pc / MEMORY_ROW_BYTES;
+            let offset = row as f32 * (row_height + ui.spacing().item_spacing.y);
+            area = area.vertical_scroll_offset(offset);
+        }
+        area.show_rows(ui, row_height, rows, |ui, shown| {
+            for row in shown {
+                let start = row * MEMORY_ROW_BYTES;
+                let end = (start + MEMORY_ROW_BYTES).min(memory.len());
+                ui.horizontal(|ui| {
+                    ui.spacing_mut().item_spacing.x = 4.0;
+                    ui.monospace(format!("{:04x}", start));
+                    for (addr, byte) in memory[start..end].iter().enumerate() {
+                        let text = RichText::new(format!("{:02x}", byte)).monospace();
+                        match start + addr {
+                            a if a == pc || a == pc + 1 => ui.label(
+                                text.color(Color32::BLACK).background_color(Color32::YELLOW),
+                            ),
+                            _ => ui.label(text),
+                        };
+                    }
+                });
+            }
+        });
+    }
+
+    fn screen(&mut self, ui: &mut egui::Ui) {
+        let (width, height) = self
+            .frame
+            .as_ref()
+            .map_or((64, 32), |f| (f.width(), f.height()));
+        let available = ui.available_size();
+        let scale = (available.x / width as f32)
+            .min(available.y / height as f32)
+            .floor()
+            .max(1.0);
+        let size = egui::vec2(width as f32 * scale, height as f32 * scale);
+        let (response, painter) = ui.allocate_painter(size, Sense::hover());
+        painter.rect_filled(response.rect, 0.0, Color32::BLACK);
+        if let Some(frame) = &self.frame {
+            for y in 0..height {
+                for x in (0..width).filter(|x| frame.pixel(*x, y)) {
+                    let min = response.rect.min + egui::vec2(x as f32, y as f32) * scale;
+                    let pixel = egui::Rect::from_min_size(min, egui::vec2(scale, scale));
+                    painter.rect_filled(pixel, 0.0, Color32::WHITE);
+                }
+            }
+        }
+        // the keypad, while it's being pointed at
+        if response.hovered() {
+            ui.input(|i| {
+                for event in &i.events {
+                    if let egui::Event::Key {
+                        key,
+                        pressed,
+                        repeat: false,
+                        ..
+                    } = event
+                    {
+                        if let Some((_, k)) = KEYS.iter().find(|(h, _)| h == key) {
+                            let event = match pressed {
+                                true => KeyEvent::Down(*k),
+                                false => KeyEvent::Up(*k),
+                            };
+                            self.emulator.key(event);
+                        }
+                    }
+                }
+            });
+        }
+    }
+}
+
+impl eframe::App for Debugger {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        if self.running {
+            // however many frames are due, within reason
+            let mut frames = 0;
+            while self.running && self.next <= Instant::now() && frames < MAX_CATCH_UP_FRAMES {
+                self.run(Emulator::run_frame);
+                self.next += FRAME_TIME;
+                frames += 1;
+            }
+            if frames == MAX_CATCH_UP_FRAMES {
+                self.next = Instant::now();
+            }
+            ctx.request_repaint_after(self.next.saturating_duration_since(Instant::now()));
+        }
+        egui::TopBottomPanel::top("controls").show(ctx, |ui| self.controls(ui));
+        egui::SidePanel::left("registers").show(ctx, |ui| self.registers(ui));
+        egui::SidePanel::right("memory")
+            .min_width(420.0)
+            .show(ctx, |ui| self.memory(ui));
+        egui::CentralPanel::default().show(ctx, |ui| self.screen(ui));
+    }
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut config = Config::default();
+    let mut rom_path = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--schip" => config.schip = true,
+            "--quirks" => match args.next() {
+                Some(q) => config.quirks = q,
+                None => return Err("--quirks needs a profile, e.g. --quirks modern".into()),
+            },
+            _ => rom_path = Some(arg),
+        }
+    }
+    let rom_path = rom_path.ok_or("usage: debugger ROM [--schip] [--quirks PROFILE]")?;
+    let debugger = Debugger::new(fs::read(&rom_path)?, config)?;
+    eframe::run_native(
+        &rom_path,
+        eframe::NativeOptions::default(),
+        Box::new(|_| Ok(Box::new(debugger))),
+    )?;
+    Ok(())
+}
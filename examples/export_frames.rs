@@ -0,0 +1,71 @@
+//! # exporting frames
+//!
+//! runs a ROM with nobody at the keys and writes each frame out as a
+//! picture, e.g. to make a video of it, or to compare against another
+//! emulator's, through nothing but the stable API:
+//!
+//! ```text
+//! cargo run --example export_frames -- game.ch8 frames/ 600
+//! ```
+//!
+//! writes frames/00000.pbm to frames/00599.pbm (or as many frames as
+//! asked for, or as there are before the ROM stops itself). PBM's about the simplest picture format there is, and most
+//! things read it: ffmpeg -i frames/%05d.pbm game.mp4, say. --schip runs
+//! it with the SUPER-CHIP's instructions, and --every N writes only every
+//! Nth frame
+use chip8::stable::{Config, Emulator, Frame};
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+/// a second's worth, if nobody says
+const DEFAULT_FRAMES: usize = 60;
+
+/// frame as a binary PBM: a bit a pixel, a row at a time, set for black,
+/// so lit pixels are cleared to come out white
+fn pbm(frame: &Frame) -> Vec<u8> {
+    let mut out = format!("P4\n{} {}\n", frame.width(), frame.height()).into_bytes();
+    for y in 0..frame.height() {
+        for byte in 0..frame.width().div_ceil(8) {
+            let lit = (0..8)
+                .filter(|bit| frame.pixel(byte * 8 + bit, y))
+                .fold(0, |b, bit| b | 0x80 >> bit);
+            out.push(!lit);
+        }
+    }
+    out
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let usage = "usage: export_frames ROM DIR [FRAMES] [--schip] [--every N]";
+    let mut config = Config::default();
+    let mut every = 1;
+    let mut positional = Vec::new();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--schip" => config.schip = true,
+            "--every" => match args.next().map(|n| n.parse()) {
+                Some(Ok(n)) if n > 0 => every = n,
+                _ => return Err("--every needs a number of frames".into()),
+            },
+            _ => positional.push(arg),
+        }
+    }
+    let (rom, dir, frames) = match positional.as_slice() {
+        [rom, dir] => (rom, dir, DEFAULT_FRAMES),
+        [rom, dir, frames] => (rom, dir, frames.parse()?),
+        _ => return Err(usage.into()),
+    };
+
+    let mut emulator = Emulator::new(&fs::read(rom)?, &config)?;
+    fs::create_dir_all(dir)?;
+    let mut written = 0;
+    for (n, frame) in emulator.frames().take(frames).enumerate().step_by(every) {
+        fs::write(Path::new(dir).join(format!("{:05}.pbm", n)), pbm(&frame?))?;
+        written += 1;
+    }
+    println!("{} frame(s) in {}", written, dir);
+    Ok(())
+}
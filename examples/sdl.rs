@@ -0,0 +1,263 @@
+//! # an SDL frontend
+//!
+//! about the least it takes to play a ROM in a window of its own, through
+//! nothing but the stable API (and the window module, to fit the picture
+//! into the window): SDL2 for the window, the keys and the buzzer, and an
+//! Emulator for everything else. a starting point for a frontend of your
+//! own, rather than a rival for the terminal's:
+//!
+//! ```text
+//! cargo run --example sdl --features sdl -- game.ch8 [--schip] [--quirks modern]
+//!     [--scaling integer|aspect|stretch[+smooth]] [--monitor N] [--fullscreen]
+//! ```
+//!
+//! (SDL2 itself needs installing first, e.g. libsdl2-dev.) the keypad's on
+//! the usual keys, 1234/QWER/ASDF/ZXCV, escape quits, F2 fits the picture
+//! into the window the next way along, F3 switches smoothing on or off, and
+//! F11 takes the window to the whole of its monitor (the first, unless
+//! --monitor says otherwise) and back
+use chip8::stable::{Config, Emulator, Frame, KeyEvent};
+use chip8::window::{Fit, Scaling};
+use sdl2::audio::{AudioCallback, AudioSpecDesired};
+use sdl2::event::Event;
+use sdl2::keyboard::Scancode;
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::render::{TextureCreator, WindowCanvas};
+use sdl2::video::{FullscreenType, WindowContext};
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// how many of the screen's pixels to a CHIP-8 one, at the VIP's 64x32
+const WINDOW_SCALE: u32 = 12;
+
+/// the VIP's 60Hz
+const FRAME_TIME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+/// the buzzer's note, in Hz, and how loud, out of 1
+const TONE_PITCH: f32 = 440.0;
+const TONE_VOLUME: f32 = 0.1;
+
+/// the COSMAC keypad, laid over the left of a QWERTY keyboard by position
+const KEYS: [(Scancode, u8); 16] = [
+    (Scancode::Num1, 0x1),
+    (Scancode::Num2, 0x2),
+    (Scancode::Num3, 0x3),
+    (Scancode::Num4, 0xc),
+    (Scancode::Q, 0x4),
+    (Scancode::W, 0x5),
+    (Scancode::E, 0x6),
+    (Scancode::R, 0xd),
+    (Scancode::A, 0x7),
+    (Scancode::S, 0x8),
+    (Scancode::D, 0x9),
+    (Scancode::F, 0xe),
+    (Scancode::Z, 0xa),
+    (Scancode::X, 0x0),
+    (Scancode::C, 0xb),
+    (Scancode::V, 0xf),
+];
+
+fn keypad(scancode: Scancode) -> Option<u8> {
+    KEYS.iter().find(|(s, _)| *s == scancode).map(|(_, k)| *k)
+}
+
+/// a square wave, while the buzzer's on
+struct Buzzer {
+    on: Arc<AtomicBool>,
+    phase: f32,
+    step: f32,
+}
+
+impl AudioCallback for Buzzer {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        let on = self.on.load(Ordering::Relaxed);
+        for sample in out.iter_mut() {
+            *sample = match (on, self.phase < 0.5) {
+                (false, _) => 0.0,
+                (true, true) => TONE_VOLUME,
+                (true, false) => -TONE_VOLUME,
+            };
+            self.phase = (self.phase + self.step) % 1.0;
+        }
+    }
+}
+
+/// frame, fitted into the window as scaling says
+fn draw(
+    canvas: &mut WindowCanvas,
+    textures: &TextureCreator<WindowContext>,
+    frame: &Frame,
+    scaling: Scaling,
+) -> Result<(), Box<dyn Error>> {
+    let (width, height) = (frame.width(), frame.height());
+    // SDL takes how to scale a texture from this when it's made
+    let quality = if scaling.smooth { "linear" } else { "nearest" };
+    sdl2::hint::set("SDL_RENDER_SCALE_QUALITY", quality);
+    let mut texture =
+        textures.create_texture_streaming(PixelFormatEnum::RGB24, width as u32, height as u32)?;
+    texture.with_lock(None, |pixels, pitch| {
+        for y in 0..height {
+            for x in 0..width {
+                let lit = if frame.pixel(x, y) { 0xff } else { 0 };
+                pixels[y * pitch + x * 3..][..3].fill(lit);
+            }
+        }
+    })?;
+    let placed = scaling.place((width as u32, height as u32), canvas.output_size()?);
+    canvas.set_draw_color(Color::BLACK);
+    canvas.clear();
+    let to = Rect::new(
+        placed.x as i32,
+        placed.y as i32,
+        placed.width,
+        placed.height,
+    );
+    canvas.copy(&texture, None, to)?;
+    canvas.present();
+    Ok(())
+}
+
+/// the way of fitting the picture in after fit
+fn next_fit(fit: Fit) -> Fit {
+    let i = Fit::ALL.iter().position(|f| *f == fit).unwrap_or(0);
+    Fit::ALL[(i + 1) % Fit::ALL.len()]
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut config = Config::default();
+    let mut rom_path = None;
+    let mut scaling = Scaling::default();
+    let mut monitor = 0;
+    let mut fullscreen = false;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--schip" => config.schip = true,
+            "--quirks" => match args.next() {
+                Some(q) => config.quirks = q,
+                None => return Err("--quirks needs a profile, e.g. --quirks modern".into()),
+            },
+            "--scaling" => match args.next() {
+                Some(s) => scaling = Scaling::parse(&s)?,
+                None => return Err("--scaling needs integer, aspect or stretch".into()),
+            },
+            // counting from 0, as SDL does
+            "--monitor" => match args.next().map(|m| m.parse()) {
+                Some(Ok(m)) => monitor = m,
+                _ => return Err("--monitor needs a number, e.g. --monitor 1".into()),
+            },
+            "--fullscreen" => fullscreen = true,
+            _ => rom_path = Some(arg),
+        }
+    }
+    let rom_path =
+        rom_path.ok_or("usage: sdl ROM [--schip] [--quirks PROFILE] [--scaling SCALING] ...")?;
+    let mut emulator = Emulator::new(&fs::read(&rom_path)?, &config)?;
+
+    let sdl = sdl2::init()?;
+    let video = sdl.video()?;
+    // in the middle of the monitor asked for, which fullscreen then fills
+    let bounds = video.display_bounds(monitor)?;
+    let (width, height) = (64 * WINDOW_SCALE, 32 * WINDOW_SCALE);
+    let mut window = video
+        .window(&rom_path, width, height)
+        .position(
+            bounds.x() + (bounds.width() as i32 - width as i32) / 2,
+            bounds.y() + (bounds.height() as i32 - height as i32) / 2,
+        )
+        .resizable()
+        .build()?;
+    if fullscreen {
+        window.set_fullscreen(FullscreenType::Desktop)?;
+    }
+    let mut canvas = window.into_canvas().accelerated().build()?;
+    let textures = canvas.texture_creator();
+    let mut events = sdl.event_pump()?;
+
+    let beeping = Arc::new(AtomicBool::new(false));
+    let wanted = AudioSpecDesired {
+        freq: Some(44_100),
+        channels: Some(1),
+        samples: None,
+    };
+    let buzzer = sdl.audio()?.open_playback(None, &wanted, |spec| Buzzer {
+        on: beeping.clone(),
+        phase: 0.0,
+        step: TONE_PITCH / spec.freq as f32,
+    })?;
+    buzzer.resume();
+
+    let mut next = Instant::now();
+    'playing: loop {
+        for event in events.poll_iter() {
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    scancode: Some(Scancode::Escape),
+                    ..
+                } => break 'playing,
+                Event::KeyDown {
+                    scancode: Some(Scancode::F2),
+                    repeat: false,
+                    ..
+                } => scaling.fit = next_fit(scaling.fit),
+                Event::KeyDown {
+                    scancode: Some(Scancode::F3),
+                    repeat: false,
+                    ..
+                } => scaling.smooth = !scaling.smooth,
+                // borderless, at the desktop's own resolution, rather than
+                // changing it
+                Event::KeyDown {
+                    scancode: Some(Scancode::F11),
+                    repeat: false,
+                    ..
+                } => {
+                    let window = canvas.window_mut();
+                    match window.fullscreen_state() {
+                        FullscreenType::Off => window.set_fullscreen(FullscreenType::Desktop)?,
+                        _ => window.set_fullscreen(FullscreenType::Off)?,
+                    }
+                }
+                Event::KeyDown {
+                    scancode: Some(s),
+                    repeat: false,
+                    ..
+                } => {
+                    if let Some(k) = keypad(s) {
+                        emulator.key(KeyEvent::Down(k));
+                    }
+                }
+                Event::KeyUp {
+                    scancode: Some(s), ..
+                } => {
+                    if let Some(k) = keypad(s) {
+                        emulator.key(KeyEvent::Up(k));
+                    }
+                }
+                _ => {}
+            }
+        }
+        let frame = emulator.run_frame()?;
+        beeping.store(emulator.beeping(), Ordering::Relaxed);
+        draw(&mut canvas, &textures, &frame, scaling)?;
+
+        // the emulator runs frames as fast as it's asked for them
+        next += FRAME_TIME;
+        match next.checked_duration_since(Instant::now()) {
+            Some(wait) => thread::sleep(wait),
+            // fallen behind, e.g. the window was being dragged: carry on
+            // from now rather than rushing to catch up
+            None => next = Instant::now(),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,255 @@
+use crate::error::Chip8Error;
+use crate::memory::{Chip8MemoryMap, MemoryMap};
+use crate::paths;
+use crate::persist;
+use crate::storage::Storage;
+use crate::watch::MemoryWatch;
+use std::path::{Path, PathBuf};
+
+/// how to compare a byte of memory against a value
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "==" | "=" => Some(Comparison::Eq),
+            "!=" | "≠" => Some(Comparison::Ne),
+            "<" => Some(Comparison::Lt),
+            "<=" | "≤" => Some(Comparison::Le),
+            ">" => Some(Comparison::Gt),
+            ">=" | "≥" => Some(Comparison::Ge),
+            _ => None,
+        }
+    }
+
+    pub fn test(&self, a: u8, b: u8) -> bool {
+        match self {
+            Comparison::Eq => a == b,
+            Comparison::Ne => a != b,
+            Comparison::Lt => a < b,
+            Comparison::Le => a <= b,
+            Comparison::Gt => a > b,
+            Comparison::Ge => a >= b,
+        }
+    }
+}
+
+/// e.g. `0x3a0 >= 100`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Condition {
+    pub addr: u16,
+    pub comparison: Comparison,
+    pub value: u8,
+}
+
+impl Condition {
+    pub fn holds(&self, memory: &Chip8MemoryMap) -> Result<bool, Chip8Error> {
+        let byte = memory.get_ro_slice(self.addr, 1)?[0];
+        Ok(self.comparison.test(byte, self.value))
+    }
+}
+
+/// unlocked the first frame all its conditions hold
+#[derive(Clone, Debug, PartialEq)]
+pub struct Achievement {
+    pub name: String,
+    pub conditions: Vec<Condition>,
+}
+
+/// decimal, or hex with a 0x prefix
+fn parse_number(s: &str) -> Option<u16> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+impl Achievement {
+    /// one achievement per line, e.g.
+    ///   `0x3a0 >= 100 -> achievement: Century!`
+    ///   `0x3a0 >= 100 && 0x3a1 == 0 -> Flawless century`
+    pub fn parse(line: &str) -> Result<Self, Chip8Error> {
+        let bad = || Chip8Error::ConfigError(format!("bad achievement \"{}\"", line));
+        let (conditions, name) = line
+            .split_once("->")
+            .or_else(|| line.split_once('→'))
+            .ok_or_else(bad)?;
+        let name = name.trim();
+        let name = name.strip_prefix("achievement:").unwrap_or(name).trim();
+        if name.is_empty() {
+            return Err(bad());
+        }
+        let conditions = conditions
+            .split("&&")
+            .map(|c| {
+                let mut words = c.split_whitespace();
+                let (addr, comparison, value) = (words.next(), words.next(), words.next());
+                if words.next().is_some() {
+                    return None;
+                }
+                Some(Condition {
+                    addr: parse_number(addr?)?,
+                    comparison: Comparison::parse(comparison?)?,
+                    value: u8::try_from(parse_number(value?)?).ok()?,
+                })
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(bad)?;
+        Ok(Achievement {
+            name: name.to_string(),
+            conditions,
+        })
+    }
+}
+
+/// a ROM's achievements, and which have been unlocked so far
+#[derive(Default)]
+pub struct AchievementSet {
+    achievements: Vec<Achievement>,
+    unlocked: Vec<bool>,
+}
+
+impl AchievementSet {
+    pub fn new(achievements: Vec<Achievement>) -> Self {
+        AchievementSet {
+            unlocked: vec![false; achievements.len()],
+            achievements,
+        }
+    }
+
+    /// one achievement per line; blank lines and lines starting with # are
+    /// ignored
+    pub fn parse(s: &str) -> Result<Self, Chip8Error> {
+        let achievements = s
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .map(Achievement::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self::new(achievements))
+    }
+
+    /// load from path; a missing file just means no achievements
+    pub fn load(storage: &dyn Storage, path: &Path) -> Result<Self, Chip8Error> {
+        match persist::read_text(storage, path)? {
+            Some(s) => Self::parse(&s),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// where a ROM's achievements live, in the data directory
+    pub fn default_path(rom_name: &str) -> PathBuf {
+        paths::achievements_dir().join(format!("{}.txt", paths::file_name(rom_name)))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.achievements.is_empty()
+    }
+
+    pub fn push(&mut self, achievement: Achievement) {
+        self.achievements.push(achievement);
+        self.unlocked.push(false);
+    }
+
+    /// add a line to an achievements file, creating it if need be. it's
+    /// written out whole, so a crash part way through can't leave half a line
+    pub fn append_line(storage: &dyn Storage, path: &Path, line: &str) -> Result<(), Chip8Error> {
+        let mut text = persist::read_text(storage, path)?.unwrap_or_default();
+        if !text.is_empty() && !text.ends_with('\n') {
+            text.push('\n');
+        }
+        text.push_str(line);
+        text.push('\n');
+        storage.write(path, text.as_bytes())
+    }
+
+    /// names of everything unlocked so far
+    pub fn unlocked(&self) -> impl Iterator<Item = &str> {
+        self.achievements
+            .iter()
+            .zip(&self.unlocked)
+            .filter(|(_, u)| **u)
+            .map(|(a, _)| a.name.as_str())
+    }
+}
+
+impl MemoryWatch for AchievementSet {
+    fn frame(&mut self, memory: &Chip8MemoryMap) -> Result<Vec<String>, Chip8Error> {
+        let mut notices = Vec::new();
+        for (a, unlocked) in self.achievements.iter().zip(self.unlocked.iter_mut()) {
+            if *unlocked {
+                continue;
+            }
+            let mut holds = true;
+            for c in &a.conditions {
+                holds = holds && c.holds(memory)?;
+            }
+            if holds {
+                *unlocked = true;
+                notices.push(format!("Achievement unlocked: {}", a.name));
+            }
+        }
+        Ok(notices)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() -> Result<(), Chip8Error> {
+        assert_eq!(
+            Achievement::parse("0x3A0 ≥ 100 → achievement: Century!")?,
+            Achievement {
+                name: "Century!".to_string(),
+                conditions: vec![Condition {
+                    addr: 0x3a0,
+                    comparison: Comparison::Ge,
+                    value: 100
+                }]
+            }
+        );
+        let a = Achievement::parse("0x3a0 >= 0x64 && 928 == 0 -> Flawless")?;
+        assert_eq!(a.conditions.len(), 2);
+        assert_eq!(a.conditions[1].addr, 0x3a0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_rejects_junk() {
+        assert!(Achievement::parse("0x3a0 >= 100").is_err());
+        assert!(Achievement::parse("0x3a0 >= 100 ->").is_err());
+        assert!(Achievement::parse("0x3a0 ~ 100 -> x").is_err());
+        assert!(Achievement::parse("0x3a0 >= 300 -> x").is_err());
+        assert!(Achievement::parse("0x3a0 >= 1 2 -> x").is_err());
+    }
+
+    #[test]
+    fn test_unlocks_once() -> Result<(), Chip8Error> {
+        let mut set =
+            AchievementSet::parse("# scores\n\n0x300 >= 3 -> Three\n0x300 == 9 -> Nine\n")?;
+        let mut m = Chip8MemoryMap::new()?;
+        assert!(set.frame(&m)?.is_empty());
+        m.get_rw_slice(0x300, 1)?[0] = 4;
+        assert_eq!(set.frame(&m)?, vec!["Achievement unlocked: Three"]);
+        assert!(set.frame(&m)?.is_empty());
+        assert_eq!(set.unlocked().collect::<Vec<_>>(), vec!["Three"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_bad_address_faults() -> Result<(), Chip8Error> {
+        let mut set = AchievementSet::parse("0xffff == 0 -> Nowhere")?;
+        assert!(set.frame(&Chip8MemoryMap::new()?).is_err());
+        Ok(())
+    }
+}
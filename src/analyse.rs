@@ -0,0 +1,544 @@
+//! # static analysis
+//!
+//! what can be worked out about a ROM without running it. starting from
+//! 0x200 and following every path (both ways past a skip, and into every
+//! subroutine called), it finds which words are code, where the subroutines
+//! start and whether they return, and what I gets pointed at. BNNN jumps by
+//! a register, so it can't be followed: code only reached that way looks
+//! unreachable.
+//!
+//! chip8 lint turns that into warnings for a ROM's author, from notes
+//! (this relies on a quirk) up to errors (this will crash)
+use crate::isa;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// where programs start
+const ANALYSE_ORIGIN: u16 = 0x200;
+
+/// how many calls deep the VIP's stack goes, by its manual
+const LINT_STACK_DEPTH: usize = 12;
+
+/// how many unreachable instructions in a row it takes to look like code
+/// rather than data that happens to decode
+const LINT_DEAD_RUN: usize = 3;
+
+/// how far either side of an instruction to look for what happens to I
+const LINT_LOOKAROUND: usize = 8;
+
+/// everything worked out about a ROM
+#[derive(Debug, Default, PartialEq)]
+pub struct Analysis {
+    /// every instruction that can be reached
+    pub code: BTreeSet<u16>,
+    /// where each subroutine starts, and whether it can return
+    pub subroutines: BTreeMap<u16, bool>,
+    /// each subroutine (and 0x200, for the program itself) and what it calls
+    pub calls: BTreeMap<u16, BTreeSet<u16>>,
+    /// addresses loaded into I, which are probably data
+    pub data: BTreeSet<u16>,
+    /// returns that can be reached from the program itself, not a subroutine
+    pub stray_returns: Vec<u16>,
+    /// words that can be reached but aren't instructions
+    pub not_code: Vec<u16>,
+    /// BNNN jumps, which can't be followed
+    pub computed_jumps: Vec<u16>,
+    /// stores that land on code, and the code they land on
+    pub code_writes: Vec<(u16, u16)>,
+    /// what gets drawn: where I's pointed just before a DXYN, and how many
+    /// rows the sprite has and how many bytes a row (two for SUPER-CHIP's
+    /// 16x16). the biggest, if it's drawn more than one way
+    pub sprites: BTreeMap<u16, (u16, u16)>,
+}
+
+/// does inst skip the next instruction (sometimes)?
+pub fn is_skip(inst: u16) -> bool {
+    matches!(inst & 0xf000, 0x3000 | 0x4000 | 0x5000 | 0x9000)
+        || matches!(inst & 0xf0ff, 0xe09e | 0xe0a1)
+}
+
+/// the instruction at addr, if it's in rom
+pub fn word_at(rom: &[u8], addr: u16) -> Option<u16> {
+    let n = addr.checked_sub(ANALYSE_ORIGIN)? as usize;
+    Some(u16::from_be_bytes([*rom.get(n)?, *rom.get(n + 1)?]))
+}
+
+/// follow every path through rom
+pub fn analyse(rom: &[u8]) -> Analysis {
+    let mut a = Analysis::default();
+    let mut entries = vec![ANALYSE_ORIGIN];
+    while let Some(entry) = entries.pop() {
+        if a.calls.contains_key(&entry) {
+            continue;
+        }
+        let mut callees = BTreeSet::new();
+        // a subroutine that jumps by a register gets the benefit of the doubt
+        let mut returns = false;
+        let mut seen = BTreeSet::new();
+        let mut todo = vec![entry];
+        while let Some(addr) = todo.pop() {
+            if !seen.insert(addr) {
+                continue;
+            }
+            let inst = match word_at(rom, addr) {
+                Some(i) if isa::lookup(i).is_some() => i,
+                _ => {
+                    a.not_code.push(addr);
+                    continue;
+                }
+            };
+            a.code.insert(addr);
+            // there's nothing after the end of memory
+            let (next, target) = (addr.checked_add(2), inst & 0xfff);
+            match inst & 0xf000 {
+                _ if inst == 0x00ee && entry == ANALYSE_ORIGIN => a.stray_returns.push(addr),
+                _ if inst == 0x00ee => returns = true,
+                // the program's over
+                _ if inst == 0x00fd => {}
+                0x1000 => todo.push(target),
+                0x2000 => {
+                    callees.insert(target);
+                    entries.push(target);
+                    todo.extend(next);
+                }
+                0xb000 => {
+                    a.computed_jumps.push(addr);
+                    returns = true;
+                }
+                0xa000 => {
+                    a.data.insert(target);
+                    todo.extend(next);
+                }
+                _ if is_skip(inst) => {
+                    todo.extend(next);
+                    todo.extend(next.and_then(|n| n.checked_add(2)));
+                }
+                _ => todo.extend(next),
+            }
+        }
+        if entry != ANALYSE_ORIGIN {
+            a.subroutines.insert(entry, returns);
+        }
+        a.calls.insert(entry, callees);
+    }
+    a.stray_returns.sort_unstable();
+    a.stray_returns.dedup();
+    a.not_code.sort_unstable();
+    a.not_code.dedup();
+    a.computed_jumps.sort_unstable();
+    a.computed_jumps.dedup();
+    a.code_writes = a
+        .code
+        .iter()
+        .filter_map(|addr| {
+            let inst = word_at(rom, *addr)?;
+            writes_into_code(rom, &a, *addr, inst).map(|written| (*addr, written))
+        })
+        .collect();
+    for addr in &a.code {
+        let inst = word_at(rom, *addr).unwrap_or_default();
+        if inst & 0xf000 != 0xa000 {
+            continue;
+        }
+        let size = match drawn_next(rom, &a, *addr) {
+            Some(0) => (16, 2),
+            Some(rows) => (rows, 1),
+            None => continue,
+        };
+        let biggest = a.sprites.entry(inst & 0xfff).or_insert(size);
+        if size.0 * size.1 > biggest.0 * biggest.1 {
+            *biggest = size;
+        }
+    }
+    a
+}
+
+/// how much a lint matters
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// fine on some interpreters, not on others
+    Note,
+    /// probably a mistake
+    Warning,
+    /// will go wrong when it's run
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Note => "note",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+/// something wrong (or dubious) at addr
+#[derive(Debug, PartialEq)]
+pub struct Lint {
+    pub addr: u16,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04x}: {}: {}", self.addr, self.severity, self.message)
+    }
+}
+
+/// everything dubious about rom, in address order
+pub fn lint(rom: &[u8]) -> Vec<Lint> {
+    let a = analyse(rom);
+    let mut lints = Vec::new();
+    let mut add = |addr: u16, severity: Severity, message: String| {
+        lints.push(Lint {
+            addr,
+            severity,
+            message,
+        })
+    };
+
+    for addr in &a.not_code {
+        match word_at(rom, *addr) {
+            Some(0x0000) => add(*addr, Severity::Warning, "runs into 0000".to_string()),
+            Some(inst) => add(
+                *addr,
+                Severity::Error,
+                format!("runs into {:04x}, which isn't an instruction", inst),
+            ),
+            None => add(
+                *addr,
+                Severity::Warning,
+                "runs off the end of the program".to_string(),
+            ),
+        }
+    }
+    for addr in &a.stray_returns {
+        add(
+            *addr,
+            Severity::Error,
+            "returns without being called, with nothing on the stack".to_string(),
+        );
+    }
+    for (addr, _) in a.subroutines.iter().filter(|(_, returns)| !**returns) {
+        add(
+            *addr,
+            Severity::Warning,
+            "is called but never returns, so each call leaves the stack deeper".to_string(),
+        );
+    }
+    match call_depth(&a.calls, ANALYSE_ORIGIN, &mut Vec::new()) {
+        Err(addr) => add(
+            addr,
+            Severity::Warning,
+            "can end up calling itself, which could overflow the stack".to_string(),
+        ),
+        Ok(depth) if depth > LINT_STACK_DEPTH => add(
+            ANALYSE_ORIGIN,
+            Severity::Error,
+            format!(
+                "calls can go {} deep, more than the VIP's stack holds ({})",
+                depth, LINT_STACK_DEPTH
+            ),
+        ),
+        Ok(_) => {}
+    }
+    for (addr, len) in dead_code(rom, &a) {
+        let (severity, unless) = match a.computed_jumps.is_empty() {
+            true => (Severity::Warning, ""),
+            false => (Severity::Note, " (unless a BNNN jumps there)"),
+        };
+        add(
+            addr,
+            severity,
+            format!("{} instructions nothing gets to{}", len, unless),
+        );
+    }
+    for (addr, written) in &a.code_writes {
+        add(
+            *addr,
+            Severity::Warning,
+            format!("writes over the code at {:04x}", written),
+        );
+    }
+    for addr in &a.code {
+        let inst = word_at(rom, *addr).unwrap_or_default();
+        let (x, y) = ((inst >> 8) & 0xf, (inst >> 4) & 0xf);
+        match inst & 0xf00f {
+            0x8006 | 0x800e if x != y => add(
+                *addr,
+                Severity::Note,
+                format!(
+                    "shifts V{:X} into V{:X}, where later interpreters shift V{:X} in place",
+                    y, x, x
+                ),
+            ),
+            _ => {}
+        }
+        if matches!(inst & 0xf0ff, 0xf055 | 0xf065) && uses_i_next(rom, &a, *addr) {
+            add(
+                *addr,
+                Severity::Note,
+                "uses I afterwards, which the VIP moves on and later interpreters don't"
+                    .to_string(),
+            );
+        }
+        if inst & 0xf000 == 0xb000 {
+            add(
+                *addr,
+                Severity::Note,
+                format!(
+                    "jumps by V0, where SUPER-CHIP jumps by V{:X}",
+                    (inst >> 8) & 0xf
+                ),
+            );
+        }
+    }
+    lints.sort_by_key(|l| l.addr);
+    lints
+}
+
+/// how deep calls can go from entry, or the subroutine that's part of a
+/// loop of calls if there is one
+fn call_depth(
+    calls: &BTreeMap<u16, BTreeSet<u16>>,
+    entry: u16,
+    path: &mut Vec<u16>,
+) -> Result<usize, u16> {
+    if path.contains(&entry) {
+        return Err(entry);
+    }
+    path.push(entry);
+    let mut deepest = 0;
+    for callee in calls.get(&entry).into_iter().flatten() {
+        deepest = deepest.max(call_depth(calls, *callee, path)? + 1);
+    }
+    path.pop();
+    Ok(deepest)
+}
+
+/// runs of instructions nothing gets to, as where they start and how many.
+/// anything after an address I's pointed at is taken to be data, up to the
+/// next bit of code
+fn dead_code(rom: &[u8], a: &Analysis) -> Vec<(u16, usize)> {
+    // as far as the ROM goes, or memory does if it's bigger than that
+    let end = (ANALYSE_ORIGIN as usize + rom.len()).min(u16::MAX as usize + 1);
+    let is_data = |addr: u16| {
+        a.data
+            .range(..=addr)
+            .next_back()
+            .is_some_and(|start| a.code.range(*start..=addr).next().is_none())
+    };
+    let mut runs = Vec::new();
+    let mut run: Option<(u16, usize)> = None;
+    for addr in (ANALYSE_ORIGIN as usize..end).step_by(2) {
+        let addr = addr as u16;
+        let dead = !a.code.contains(&addr)
+            && !is_data(addr)
+            && word_at(rom, addr).is_some_and(|i| i != 0 && isa::lookup(i).is_some());
+        run = match (run, dead) {
+            (Some((start, n)), true) => Some((start, n + 1)),
+            (None, true) => Some((addr, 1)),
+            (Some(r), false) => {
+                runs.push(r);
+                None
+            }
+            (None, false) => None,
+        };
+    }
+    runs.extend(run);
+    runs.retain(|(_, n)| *n >= LINT_DEAD_RUN);
+    runs
+}
+
+/// the instructions running up to addr, nearest first, for as long as
+/// nothing could have jumped into the middle of them
+fn before(rom: &[u8], a: &Analysis, addr: u16) -> Vec<u16> {
+    (1..=LINT_LOOKAROUND as u16)
+        .map_while(|n| addr.checked_sub(2 * n))
+        .take_while(|prev| a.code.contains(prev))
+        .filter_map(|prev| word_at(rom, prev))
+        .take_while(|inst| !matches!(inst & 0xf000, 0x1000 | 0xb000) && *inst != 0x00ee)
+        .collect()
+}
+
+/// if inst (at addr) stores to memory at an I set just before it, and that
+/// lands on code, where
+fn writes_into_code(rom: &[u8], a: &Analysis, addr: u16, inst: u16) -> Option<u16> {
+    let len = match inst & 0xf0ff {
+        0xf055 => ((inst >> 8) & 0xf) + 1,
+        0xf033 => 3,
+        _ => return None,
+    };
+    // the last thing to have set I, as long as nothing's moved it since
+    let i = before(rom, a, addr)
+        .into_iter()
+        .find(|i| i & 0xf000 == 0xa000 || matches!(i & 0xf0ff, 0xf01e | 0xf029 | 0xf030))
+        .filter(|i| i & 0xf000 == 0xa000)?
+        & 0xfff;
+    // an instruction's two bytes, so one starting just before counts too
+    a.code.range(i.saturating_sub(1)..i + len).next().copied()
+}
+
+/// how many rows the DXYN drawing with the I set at addr draws, if there's
+/// one before I's moved
+fn drawn_next(rom: &[u8], a: &Analysis, addr: u16) -> Option<u16> {
+    (1..=LINT_LOOKAROUND as u16)
+        .map_while(|n| addr.checked_add(2 * n))
+        .take_while(|next| a.code.contains(next))
+        .filter_map(|next| word_at(rom, next))
+        .take_while(|i| {
+            !matches!(i & 0xf000, 0x1000 | 0x2000 | 0xa000 | 0xb000)
+                && *i != 0x00ee
+                && !matches!(
+                    i & 0xf0ff,
+                    0xf01e | 0xf029 | 0xf030 | 0xf033 | 0xf055 | 0xf065
+                )
+        })
+        .find(|i| i & 0xf000 == 0xd000)
+        .map(|i| i & 0xf)
+}
+
+/// is I used, before anything sets it again, after the FX55 or FX65 at addr?
+fn uses_i_next(rom: &[u8], a: &Analysis, addr: u16) -> bool {
+    let next = (1..=LINT_LOOKAROUND as u16)
+        .map_while(|n| addr.checked_add(2 * n))
+        .take_while(|next| a.code.contains(next))
+        .filter_map(|next| word_at(rom, next))
+        .take_while(|i| !matches!(i & 0xf000, 0x1000 | 0xb000) && *i != 0x00ee)
+        .find(|i| {
+            matches!(i & 0xf000, 0xa000 | 0xd000)
+                || matches!(
+                    i & 0xf0ff,
+                    0xf01e | 0xf029 | 0xf030 | 0xf033 | 0xf055 | 0xf065
+                )
+        });
+    next.is_some_and(|i| i & 0xf000 != 0xa000 && !matches!(i & 0xf0ff, 0xf029 | 0xf030))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(rom: &[u8]) -> Vec<String> {
+        lint(rom).iter().map(|l| l.to_string()).collect()
+    }
+
+    #[test]
+    fn test_analyse() {
+        // call a subroutine, skip over a jump, loop; the subroutine points I
+        // at some data and returns
+        #[rustfmt::skip]
+        let rom = [
+            0x22, 0x0a, 0x30, 0x00, 0x12, 0x02, 0x12, 0x08,
+            0x12, 0x08, 0xa2, 0x10, 0x00, 0xee, 0x00, 0x00,
+            0xf0, 0x90,
+        ];
+        let a = analyse(&rom);
+        assert_eq!(
+            a.code.iter().copied().collect::<Vec<_>>(),
+            [0x200, 0x202, 0x204, 0x206, 0x208, 0x20a, 0x20c]
+        );
+        assert_eq!(a.subroutines, BTreeMap::from([(0x20a, true)]));
+        assert_eq!(a.data, BTreeSet::from([0x210]));
+        assert!(a.stray_returns.is_empty() && a.not_code.is_empty());
+        assert!(messages(&rom).is_empty());
+    }
+
+    #[test]
+    fn test_stack() {
+        // a return with nothing to return to
+        assert_eq!(
+            messages(&[0x60, 0x01, 0x00, 0xee]),
+            ["0202: error: returns without being called, with nothing on the stack"]
+        );
+        // a "subroutine" that's really a jump, and one that calls itself
+        let leaks = messages(&[0x22, 0x04, 0x12, 0x02, 0x12, 0x02]);
+        assert_eq!(
+            leaks,
+            ["0204: warning: is called but never returns, so each call leaves the stack deeper"]
+        );
+        let recursive = messages(&[0x22, 0x04, 0x12, 0x02, 0x22, 0x04, 0x00, 0xee]);
+        assert_eq!(
+            recursive,
+            ["0204: warning: can end up calling itself, which could overflow the stack"]
+        );
+        // a chain of thirteen calls
+        let deep = lint(&[&[0x22u8, 0x04, 0x12, 0x02][..], &chain(13)].concat());
+        assert!(deep.iter().any(|l| l.severity == Severity::Error));
+    }
+
+    /// n subroutines from 0x204 on, each calling the next
+    fn chain(n: u16) -> Vec<u8> {
+        let mut rom = Vec::new();
+        for k in 0..n {
+            let sub = 0x204 + 4 * k;
+            match k + 1 < n {
+                true => rom.extend((0x2000 | (sub + 4)).to_be_bytes()),
+                false => rom.extend([0x60, 0x00]),
+            }
+            rom.extend([0x00, 0xee]);
+        }
+        rom
+    }
+
+    #[test]
+    fn test_dead_code() {
+        // loop forever, then three instructions nothing jumps to, then data
+        // I points at (which would decode, but isn't code)
+        #[rustfmt::skip]
+        let rom = [
+            0xa2, 0x0a, 0x12, 0x02, 0x60, 0x01, 0x61, 0x02,
+            0x00, 0xe0, 0x60, 0x60, 0x60, 0x60, 0x60, 0x60,
+        ];
+        assert_eq!(
+            messages(&rom),
+            ["0204: warning: 3 instructions nothing gets to"]
+        );
+        // and not when it could be reached by BNNN
+        let computed = lint(&[0xb2, 0x04, 0x60, 0x01, 0x61, 0x02, 0x00, 0xe0]);
+        assert!(computed.iter().all(|l| l.severity == Severity::Note));
+    }
+
+    #[test]
+    fn test_runs_off() {
+        assert_eq!(
+            messages(&[0x60, 0x01, 0x80, 0x08]),
+            ["0202: error: runs into 8008, which isn't an instruction"]
+        );
+        assert_eq!(
+            messages(&[0x60, 0x01]),
+            ["0202: warning: runs off the end of the program"]
+        );
+        // or the end of memory, with nothing after it to run into
+        let mut rom = [0x60, 0x01].repeat((0x10000 - 0x200) / 2);
+        assert!(analyse(&rom).code.contains(&0xfffe));
+        lint(&rom);
+        // skipping past it too
+        rom.splice(..2, [0x3f, 0x01]);
+        rom.splice(rom.len() - 2.., [0x3f, 0x01]);
+        assert!(analyse(&rom).code.contains(&0xfffe));
+        lint(&rom);
+    }
+
+    #[test]
+    fn test_writes_and_quirks() {
+        // point I at the code and save over it; then shift V1 into V0; then
+        // load and step I on
+        #[rustfmt::skip]
+        let rom = [
+            0xa2, 0x00, 0xf1, 0x55, 0x80, 0x16, 0xa3, 0x00,
+            0xf0, 0x65, 0xf1, 0x1e, 0x12, 0x0c,
+        ];
+        assert_eq!(
+            messages(&rom),
+            [
+                "0202: warning: writes over the code at 0200",
+                "0204: note: shifts V1 into V0, where later interpreters shift V0 in place",
+                "0208: note: uses I afterwards, which the VIP moves on and later interpreters don't",
+            ]
+        );
+    }
+}
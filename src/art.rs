@@ -0,0 +1,322 @@
+//! # ANSI art
+//!
+//! a savestate's screen as a picture for a blog post or the docs, without
+//! a terminal to take a screenshot of: chip8 export-art STATE OUT, where
+//! STATE's a session (a savestate slot's, or --save-session's) and OUT's
+//! where to put it (- for stdout). it comes out as
+//!
+//! * ansi: the terminal's characters, in the theme's colours
+//! * text: the same characters without the colours
+//! * ascii: '#' for a lit pixel and '.' for an unlit one, for anywhere
+//!   that can't be trusted with anything else
+//! * svg: squares, for anywhere that takes pictures
+//!
+//! whichever --art-format says, or OUT's extension does (.ans, .txt,
+//! .svg; ANSI otherwise). the characters are worked out by the display
+//! module, just as they are for the terminal, so --cells, --scale, --theme
+//! and --invert all do what they'd do there
+use crate::display::{self, Cells, DummyDisplay, Scale, Theme};
+use crate::error::Chip8Error;
+use crate::input::DummyInput;
+use crate::interpreter::Chip8Interpreter;
+use crate::memory::MemoryMap;
+use crate::schip::Schip;
+use crate::session::Session;
+use crate::sound::Mute;
+use std::fmt::Write;
+use std::path::Path;
+use tui::style::Color;
+
+/// what the art's made of
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArtFormat {
+    Ansi,
+    Text,
+    Ascii,
+    Svg,
+}
+
+impl ArtFormat {
+    pub fn parse(s: &str) -> Result<Self, Chip8Error> {
+        match s {
+            "ansi" => Ok(ArtFormat::Ansi),
+            "text" => Ok(ArtFormat::Text),
+            "ascii" => Ok(ArtFormat::Ascii),
+            "svg" => Ok(ArtFormat::Svg),
+            _ => Err(Chip8Error::ConfigError(format!(
+                "can't make art as \"{}\" (try ansi, text, ascii or svg)",
+                s
+            ))),
+        }
+    }
+
+    /// by the file's extension, ANSI if it's not one we know
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("svg") => ArtFormat::Svg,
+            Some("txt") => ArtFormat::Text,
+            _ => ArtFormat::Ansi,
+        }
+    }
+
+    /// how big a pixel is, if there's no --scale: square in most
+    /// terminals, and for an SVG, big enough to see
+    pub fn default_scale(self) -> Scale {
+        match self {
+            ArtFormat::Svg => Scale(8, 8),
+            _ => Scale(2, 1),
+        }
+    }
+}
+
+/// how to draw it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Art {
+    pub format: ArtFormat,
+    pub cells: Cells,
+    pub scale: Scale,
+    pub theme: Theme,
+}
+
+impl Art {
+    /// in format, as the terminal draws by default
+    pub fn new(format: ArtFormat) -> Self {
+        Art {
+            format,
+            cells: Cells::Block,
+            scale: format.default_scale(),
+            theme: Theme::default(),
+        }
+    }
+
+    /// a width by height picture (a bit a pixel, as the display's given it)
+    pub fn render(&self, data: &[u8], width: usize, height: usize) -> String {
+        let lines = |cells| display::mosaic(data, width, height, cells, self.scale);
+        match self.format {
+            ArtFormat::Text => lines(self.cells)
+                .iter()
+                .map(|l| l.to_string() + "\n")
+                .collect(),
+            ArtFormat::Ascii => lines(Cells::Block)
+                .iter()
+                .map(|l| l.replace('█', "#").replace(' ', ".") + "\n")
+                .collect(),
+            ArtFormat::Ansi => {
+                let colours = match self.theme.glyphs {
+                    true => sgr(self.theme.lit, false),
+                    false => format!(
+                        "{};{}",
+                        sgr(self.theme.lit, false),
+                        sgr(self.theme.unlit, true)
+                    ),
+                };
+                lines(self.cells)
+                    .iter()
+                    .map(|l| format!("\x1b[{}m{}\x1b[0m\n", colours, l))
+                    .collect()
+            }
+            ArtFormat::Svg => self.svg(data, width, height),
+        }
+    }
+
+    fn svg(&self, data: &[u8], width: usize, height: usize) -> String {
+        let Scale(sx, sy) = self.scale;
+        let (w, h) = (width * sx, height * sy);
+        let lit = |x: usize, y: usize| data[(y * width + x) / 8] & (0x80 >> (x % 8)) != 0;
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" \
+             viewBox=\"0 0 {0} {1}\" shape-rendering=\"crispEdges\">\n",
+            w, h
+        );
+        if !self.theme.glyphs {
+            let _ = writeln!(
+                svg,
+                "<rect width=\"{}\" height=\"{}\" fill=\"{}\"/>",
+                w,
+                h,
+                hex(self.theme.unlit, "#000000")
+            );
+        }
+        // a rectangle for each run of lit pixels along a row
+        let mut path = String::new();
+        for y in 0..height {
+            let mut x = 0;
+            while x < width {
+                let start = x;
+                while x < width && lit(x, y) {
+                    x += 1;
+                }
+                if x > start {
+                    let run = (x - start) * sx;
+                    let _ = write!(path, "M{} {}h{}v{}h-{}z", start * sx, y * sy, run, sy, run);
+                }
+                x += 1;
+            }
+        }
+        let _ = writeln!(
+            svg,
+            "<path fill=\"{}\" d=\"{}\"/>",
+            hex(self.theme.lit, "#ffffff"),
+            path
+        );
+        svg + "</svg>\n"
+    }
+}
+
+/// the colour's number in the terminal's palette, if it's got one
+fn index(colour: Color) -> Option<u8> {
+    let named = [
+        Color::Black,
+        Color::Red,
+        Color::Green,
+        Color::Yellow,
+        Color::Blue,
+        Color::Magenta,
+        Color::Cyan,
+        Color::Gray,
+        Color::DarkGray,
+        Color::LightRed,
+        Color::LightGreen,
+        Color::LightYellow,
+        Color::LightBlue,
+        Color::LightMagenta,
+        Color::LightCyan,
+        Color::White,
+    ];
+    match colour {
+        Color::Indexed(n) => Some(n),
+        c => named.iter().position(|n| *n == c).map(|n| n as u8),
+    }
+}
+
+/// the escape code's parameters to set the foreground (or background) to
+/// colour
+fn sgr(colour: Color, background: bool) -> String {
+    let base = if background { 48 } else { 38 };
+    match (colour, index(colour)) {
+        (Color::Rgb(r, g, b), _) => format!("{};2;{};{};{}", base, r, g, b),
+        (_, Some(n)) => format!("{};5;{}", base, n),
+        _ => (base + 1).to_string(),
+    }
+}
+
+/// colour as #rrggbb, going by xterm's palette, or otherwise if it's the
+/// terminal's default
+fn hex(colour: Color, otherwise: &str) -> String {
+    const BASIC: [u32; 16] = [
+        0x000000, 0x800000, 0x008000, 0x808000, 0x000080, 0x800080, 0x008080, 0xc0c0c0, 0x808080,
+        0xff0000, 0x00ff00, 0xffff00, 0x0000ff, 0xff00ff, 0x00ffff, 0xffffff,
+    ];
+    // the 6x6x6 cube's levels
+    let level = |v: u32| if v == 0 { 0 } else { 55 + 40 * v };
+    let rgb = match (colour, index(colour)) {
+        (Color::Rgb(r, g, b), _) => (r as u32) << 16 | (g as u32) << 8 | b as u32,
+        (_, Some(n @ 0..=15)) => BASIC[n as usize],
+        (_, Some(n @ 16..=231)) => {
+            let n = n as u32 - 16;
+            level(n / 36) << 16 | level(n / 6 % 6) << 8 | level(n % 6)
+        }
+        (_, Some(n)) => (8 + 10 * (n as u32 - 232)) * 0x010101,
+        (_, None) => return otherwise.to_string(),
+    };
+    format!("#{:06x}", rgb)
+}
+
+/// what was on the screen when session was saved, and how wide and tall
+/// it is
+pub fn screen(session: &Session) -> Result<(Vec<u8>, usize, usize), Chip8Error> {
+    let mut display = DummyDisplay::new()?;
+    let mut input = DummyInput::new(&[]);
+    let mut sound = Mute::new();
+    let mut schip = Schip::new();
+    let mut interpreter = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+    if session.schip {
+        interpreter.add_extension(&mut schip);
+    }
+    interpreter.restore(session.state.clone())?;
+    let (addr, width, height) = interpreter.display_geometry();
+    let data = interpreter
+        .memory()
+        .get_ro_slice(addr, width * height / 8)?;
+    Ok((data.to_vec(), width, height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a 16x2 picture: one lit pixel top left, a run of three below
+    const PICTURE: [u8; 4] = [0x80, 0x00, 0x70, 0x00];
+
+    #[test]
+    fn test_formats() -> Result<(), Chip8Error> {
+        assert_eq!(ArtFormat::parse("svg")?, ArtFormat::Svg);
+        assert!(ArtFormat::parse("gif").is_err());
+        assert_eq!(ArtFormat::from_path(Path::new("pong.txt")), ArtFormat::Text);
+        assert_eq!(ArtFormat::from_path(Path::new("-")), ArtFormat::Ansi);
+        Ok(())
+    }
+
+    #[test]
+    fn test_text() {
+        let mut art = Art::new(ArtFormat::Ascii);
+        art.scale = Scale(1, 1);
+        assert_eq!(
+            art.render(&PICTURE, 16, 2),
+            "#...............\n.###............\n"
+        );
+        art.scale = Scale(2, 1);
+        assert!(art.render(&PICTURE, 16, 2).starts_with("##......"));
+        art.format = ArtFormat::Text;
+        art.cells = Cells::Quadrant;
+        art.scale = Scale(1, 1);
+        assert_eq!(art.render(&PICTURE, 16, 2), "▚▄      \n");
+    }
+
+    #[test]
+    fn test_ansi() {
+        let mut art = Art::new(ArtFormat::Ansi);
+        art.theme = Theme::HIGH_CONTRAST;
+        let ansi = art.render(&PICTURE, 16, 2);
+        assert!(ansi.starts_with("\x1b[38;2;255;255;255;48;2;0;0;0m██  "));
+        assert_eq!(ansi.lines().count(), 2);
+        assert!(ansi.ends_with("\x1b[0m\n"));
+        art.theme = Theme::CLASSIC.inverted();
+        art.theme.glyphs = true;
+        assert!(art.render(&PICTURE, 16, 2).starts_with("\x1b[38;5;0m"));
+    }
+
+    #[test]
+    fn test_screen() -> Result<(), Chip8Error> {
+        // I := the font's 0; draw it at 0,0; then loop
+        let rom = [0xf0, 0x29, 0xd0, 0x05, 0x12, 0x04];
+        let (mut display, mut input, mut sound) =
+            (DummyDisplay::new()?, DummyInput::new(&[]), Mute::new());
+        let mut interpreter = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+        interpreter.load_program(&mut &rom[..])?;
+        interpreter.run_frames(3)?;
+        let session = Session {
+            rom_name: "zero".to_string(),
+            rom: rom.to_vec(),
+            speed: 1.0,
+            hud: false,
+            schip: false,
+            state: interpreter.machine_state().clone(),
+        };
+        let (data, width, height) = screen(&session)?;
+        assert_eq!((width, height), (64, 32));
+        assert_eq!((data[0], data[8], data.len()), (0xf0, 0x90, 256));
+        Ok(())
+    }
+
+    #[test]
+    fn test_svg() {
+        let art = Art::new(ArtFormat::Svg);
+        let svg = art.render(&PICTURE, 16, 2);
+        assert!(svg.contains("width=\"128\" height=\"16\""));
+        assert!(svg.contains("<rect width=\"128\" height=\"16\" fill=\"#000000\"/>"));
+        assert!(svg.contains("d=\"M0 0h8v8h-8zM8 8h24v8h-24z\""));
+        assert_eq!(hex(Color::Indexed(196), ""), "#ff0000");
+        assert_eq!(hex(Color::Indexed(232), ""), "#080808");
+    }
+}
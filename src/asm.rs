@@ -0,0 +1,1040 @@
+//! # asm
+//!
+//! an assembler for Octo's syntax, or the part of it that's one instruction
+//! per statement, so programs can be written (and debugged) here without
+//! reaching for another tool. a ROM path ending .8o is assembled before
+//! it's run, and chip8 asm game.8o game.ch8 writes the ROM out.
+//!
+//! as well as the ROM, the assembler keeps a line table: which line of
+//! which file each instruction came from. stepping through an assembled
+//! program in the pause menu shows the source around the instruction as
+//! well as its disassembly, which is about as much DWARF as CHIP-8 needs.
+//!
+//! there's enough on top of the instructions for a whole game:
+//!
+//! * `:const name value` names a number
+//! * `:macro name param... { body }` is stamped out, with the parameters
+//!   swapped for what it's given, wherever `name arg...` appears
+//! * `:align n` pads with zeros to a multiple of n bytes, and `:org addr`
+//!   to addr itself
+//! * `:include file.8o` assembles another file there, found next to the
+//!   one including it
+//!
+//! and enough of Octo's structured statements for most of what's written
+//! for it to assemble as it is:
+//!
+//! * `:alias name vX` gives a register a name
+//! * `if vX == vY then statement` runs the statement only if the condition
+//!   holds, and `if ... begin ... else ... end` runs a block. conditions
+//!   are `==`, `!=`, `<`, `>`, `<=` and `>=` against a register or a
+//!   number (the last four use vF), or `key` and `-key`
+//! * `loop ... again` goes round forever, or until a `while condition`
+//!   inside it doesn't hold
+//!
+//! ```text
+//! :const SPEED 1
+//! :alias count v0
+//! :macro bump reg { reg += SPEED }
+//! : main
+//!   count := 0
+//!   i := digits
+//!   loop
+//!     bump count
+//!     while count != 10
+//!   again
+//!   if count == 10 then clear
+//! :align 2
+//! : digits
+//!   0xf0 0x90
+//! ```
+use crate::error::Chip8Error;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fs;
+use std::path::Path;
+
+/// where programs are loaded, and so where the first byte assembled goes
+const ASM_ORIGIN: u16 = 0x200;
+
+/// the last address a ROM can fill
+const ASM_END: u16 = 0xfff;
+
+/// how many macros can be expanded, all told, before it looks like one's
+/// calling itself
+const ASM_MAX_EXPANSIONS: usize = 10_000;
+
+/// likewise files included
+const ASM_MAX_FILES: usize = 256;
+
+/// the register conditions that compare by subtracting get to use
+const ASM_VF: u16 = 0xf;
+
+/// a whitespace-separated word of source, and where it was
+#[derive(Debug, Clone)]
+struct Token {
+    text: String,
+    file: usize,
+    line: usize,
+}
+
+/// which source line each instruction came from
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct LineTable {
+    /// each file's name and its lines
+    files: Vec<(String, Vec<String>)>,
+    /// instruction address to file (by index) and line (from 1)
+    lines: BTreeMap<u16, (usize, usize)>,
+}
+
+impl LineTable {
+    fn add_file(&mut self, name: &str, source: &str) -> usize {
+        self.files
+            .push((name.to_string(), source.lines().map(String::from).collect()));
+        self.files.len() - 1
+    }
+
+    /// the file and line the instruction at addr came from
+    pub fn source_line(&self, addr: u16) -> Option<(&str, usize)> {
+        let (file, line) = self.lines.get(&addr)?;
+        Some((&self.files[*file].0, *line))
+    }
+
+    /// the source around the instruction at addr, context lines either
+    /// side, headed by where it is and with its own line marked
+    pub fn excerpt(&self, addr: u16, context: usize) -> Option<Vec<String>> {
+        let (file, line) = *self.lines.get(&addr)?;
+        let (name, text) = &self.files[file];
+        let first = line.saturating_sub(context).max(1);
+        let last = (line + context).min(text.len());
+        let width = last.to_string().len();
+        let mut excerpt = vec![format!("{}:{}", name, line)];
+        for n in first..=last {
+            let marker = if n == line { '>' } else { ' ' };
+            excerpt.push(format!("{} {:>w$} | {}", marker, n, text[n - 1], w = width));
+        }
+        Some(excerpt)
+    }
+}
+
+/// what assembling a program makes
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assembled {
+    pub rom: Vec<u8>,
+    pub lines: LineTable,
+    /// where each label ended up
+    pub labels: BTreeMap<String, u16>,
+}
+
+/// assemble source, calling it name in any errors and in the line table
+pub fn assemble(name: &str, source: &str) -> Result<Assembled, Chip8Error> {
+    let mut asm = Assembler::default();
+    let file = asm.lines.add_file(name, source);
+    let tokens = tokenise(source, file);
+    asm.statements(&mut tokens.into())?;
+    asm.finish()
+}
+
+/// assemble the file at path
+pub fn assemble_file(path: &Path) -> Result<Assembled, Chip8Error> {
+    let source = fs::read_to_string(path)?;
+    assemble(&path.to_string_lossy(), &source)
+}
+
+/// is this a path to source, rather than to a ROM?
+pub fn is_source(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e == "8o")
+}
+
+fn tokenise(source: &str, file: usize) -> Vec<Token> {
+    source
+        .lines()
+        .enumerate()
+        .flat_map(|(n, line)| {
+            // a comment runs to the end of the line
+            let code = line.split_once('#').map_or(line, |(code, _)| code);
+            code.split_whitespace().map(move |word| Token {
+                text: word.to_string(),
+                file,
+                line: n + 1,
+            })
+        })
+        .collect()
+}
+
+/// a number in decimal, hex (0x) or binary (0b), maybe negative
+fn number(text: &str) -> Option<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(d) => (true, d),
+        None => (false, text),
+    };
+    let n = if let Some(hex) = digits.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(bin) = digits.strip_prefix("0b") {
+        i64::from_str_radix(bin, 2).ok()?
+    } else {
+        digits.parse().ok()?
+    };
+    Some(if negative { -n } else { n })
+}
+
+/// v0 to vF (or V0 to VF)
+fn register(text: &str) -> Option<u8> {
+    let digit = text.strip_prefix('v').or_else(|| text.strip_prefix('V'))?;
+    match digit.len() {
+        1 => u8::from_str_radix(digit, 16).ok(),
+        _ => None,
+    }
+}
+
+/// words that mean something already, so can't be labels
+const KEYWORDS: &[&str] = &[
+    ":",
+    ";",
+    "clear",
+    "return",
+    "hires",
+    "lores",
+    "exit",
+    "scroll-down",
+    "scroll-left",
+    "scroll-right",
+    "jump",
+    "jump0",
+    "sprite",
+    "save",
+    "load",
+    "bcd",
+    "delay",
+    "buzzer",
+    "i",
+    "random",
+    "key",
+    "hex",
+    "bighex",
+    ":const",
+    ":macro",
+    ":align",
+    ":org",
+    ":include",
+    ":alias",
+    "{",
+    "}",
+    "if",
+    "then",
+    "begin",
+    "else",
+    "end",
+    "loop",
+    "while",
+    "again",
+];
+
+/// what's left to assemble. macros and includes put theirs on the front
+type Tokens = VecDeque<Token>;
+
+/// a macro's parameters, and the tokens they're swapped into
+#[derive(Clone)]
+struct Macro {
+    params: Vec<String>,
+    body: Vec<Token>,
+}
+
+/// a block that's been opened and not yet closed, and the jumps in it
+/// waiting to know where it finishes
+enum Block {
+    /// if ... begin: the jump past the block
+    Begin(usize),
+    /// ... else: the jump past what's left
+    Else(usize),
+    /// loop: where it starts, and its whiles' jumps out
+    Loop(u16, Vec<usize>),
+}
+
+/// an address to fill in once the label it's waiting for is found
+struct Fixup {
+    /// where the instruction is in the ROM
+    at: usize,
+    label: String,
+    token: Token,
+}
+
+#[derive(Default)]
+struct Assembler {
+    rom: Vec<u8>,
+    labels: HashMap<String, u16>,
+    consts: HashMap<String, i64>,
+    macros: HashMap<String, Macro>,
+    aliases: HashMap<String, u8>,
+    expansions: usize,
+    /// blocks open, innermost last, with where they were opened
+    blocks: Vec<(Block, Token)>,
+    fixups: Vec<Fixup>,
+    lines: LineTable,
+    /// the last token read, for errors at the end of a file
+    last: Option<Token>,
+}
+
+impl Assembler {
+    fn error(&self, at: &Token, message: String) -> Chip8Error {
+        Chip8Error::AssemblyError {
+            file: self.lines.files[at.file].0.clone(),
+            line: at.line,
+            message,
+        }
+    }
+
+    /// where the next byte goes
+    fn here(&self) -> u16 {
+        ASM_ORIGIN + self.rom.len() as u16
+    }
+
+    /// a register, by its own name or an alias
+    fn reg(&self, text: &str) -> Option<u8> {
+        register(text).or_else(|| self.aliases.get(text).copied())
+    }
+
+    /// a number, or a constant's
+    fn value(&self, text: &str) -> Option<i64> {
+        number(text).or_else(|| self.consts.get(text).copied())
+    }
+
+    fn next(&mut self, tokens: &mut Tokens, wanted: &str) -> Result<Token, Chip8Error> {
+        match tokens.pop_front() {
+            Some(t) => {
+                self.last = Some(t.clone());
+                Ok(t)
+            }
+            None => {
+                let last = self.last.clone().unwrap_or(Token {
+                    text: String::new(),
+                    file: 0,
+                    line: 1,
+                });
+                Err(self.error(
+                    &last,
+                    format!("expected {} after \"{}\"", wanted, last.text),
+                ))
+            }
+        }
+    }
+
+    fn expect(&mut self, tokens: &mut Tokens, text: &str) -> Result<(), Chip8Error> {
+        let t = self.next(tokens, &format!("\"{}\"", text))?;
+        match t.text == text {
+            true => Ok(()),
+            false => Err(self.error(&t, format!("expected \"{}\", not \"{}\"", text, t.text))),
+        }
+    }
+
+    fn register(&mut self, tokens: &mut Tokens) -> Result<u16, Chip8Error> {
+        let t = self.next(tokens, "a register")?;
+        match self.reg(&t.text) {
+            Some(r) => Ok(r as u16),
+            None => Err(self.error(&t, format!("\"{}\" isn't a register", t.text))),
+        }
+    }
+
+    /// a number that has to fit in bits bits. negative ones count back
+    /// from the top, so -1 is 0xff as a byte
+    fn immediate(&self, t: &Token, bits: u32) -> Result<u16, Chip8Error> {
+        let n = self
+            .value(&t.text)
+            .ok_or_else(|| self.error(t, format!("\"{}\" isn't a number", t.text)))?;
+        let top = 1 << bits;
+        match n {
+            n if (0..top).contains(&n) => Ok(n as u16),
+            n if n < 0 && -n <= top / 2 => Ok((top + n) as u16),
+            _ => Err(self.error(t, format!("{} doesn't fit in {} bits", t.text, bits))),
+        }
+    }
+
+    fn byte(&mut self, tokens: &mut Tokens) -> Result<u16, Chip8Error> {
+        let t = self.next(tokens, "a number")?;
+        self.immediate(&t, 8)
+    }
+
+    fn nibble(&mut self, tokens: &mut Tokens) -> Result<u16, Chip8Error> {
+        let t = self.next(tokens, "a number")?;
+        self.immediate(&t, 4)
+    }
+
+    /// a 12-bit address: a number, or a label, which might not have turned
+    /// up yet. the instruction it's for has to be the next thing emitted
+    fn address(&mut self, tokens: &mut Tokens) -> Result<u16, Chip8Error> {
+        let t = self.next(tokens, "an address")?;
+        self.address_of(t)
+    }
+
+    fn address_of(&mut self, t: Token) -> Result<u16, Chip8Error> {
+        if self.value(&t.text).is_some() {
+            return self.immediate(&t, 12);
+        }
+        if let Some(addr) = self.labels.get(&t.text) {
+            return Ok(*addr);
+        }
+        self.fixups.push(Fixup {
+            at: self.rom.len(),
+            label: t.text.clone(),
+            token: t,
+        });
+        Ok(0)
+    }
+
+    /// an instruction, from the statement starting at token
+    fn word(&mut self, token: &Token, word: u16) -> Result<(), Chip8Error> {
+        self.lines
+            .lines
+            .insert(self.here(), (token.file, token.line));
+        self.emit(token, &word.to_be_bytes())
+    }
+
+    fn emit(&mut self, token: &Token, bytes: &[u8]) -> Result<(), Chip8Error> {
+        if self.here() as usize + bytes.len() > ASM_END as usize + 1 {
+            return Err(self.error(token, "the program's too big for memory".to_string()));
+        }
+        self.rom.extend(bytes);
+        Ok(())
+    }
+
+    fn statements(&mut self, tokens: &mut Tokens) -> Result<(), Chip8Error> {
+        while let Some(t) = tokens.pop_front() {
+            self.last = Some(t.clone());
+            self.statement(t, tokens)?;
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, t: Token, tokens: &mut Tokens) -> Result<(), Chip8Error> {
+        let word = match t.text.as_str() {
+            ":" => {
+                let name = self.next(tokens, "a label")?;
+                return self.label(name);
+            }
+            ":const" => {
+                let name = self.next(tokens, "a name")?;
+                let value = self.next(tokens, "a value")?;
+                let n = self.value(&value.text).ok_or_else(|| {
+                    self.error(&value, format!("\"{}\" isn't a number", value.text))
+                })?;
+                self.check_name(&name, "a constant")?;
+                self.consts.insert(name.text, n);
+                return Ok(());
+            }
+            ":macro" => return self.define_macro(tokens),
+            ":include" => return self.include(&t, tokens),
+            ":align" => {
+                let n = self.next(tokens, "a number")?;
+                let align = self.immediate(&n, 12)?.max(1);
+                let padding = (align - self.here() % align) % align;
+                return self.emit(&t, &vec![0; padding as usize]);
+            }
+            ":org" => {
+                let a = self.next(tokens, "an address")?;
+                let addr = self.immediate(&a, 12)?;
+                if addr < self.here() {
+                    return Err(self.error(
+                        &a,
+                        format!("can't go back to {:#05x} from {:#05x}", addr, self.here()),
+                    ));
+                }
+                return self.emit(&t, &vec![0; (addr - self.here()) as usize]);
+            }
+            text if self.macros.contains_key(text) => return self.expand(t, tokens),
+            "clear" => 0x00e0,
+            "return" | ";" => 0x00ee,
+            // SUPER-CHIP's
+            "scroll-down" => 0x00c0 | self.nibble(tokens)?,
+            "scroll-right" => 0x00fb,
+            "scroll-left" => 0x00fc,
+            "exit" => 0x00fd,
+            "lores" => 0x00fe,
+            "hires" => 0x00ff,
+            "jump" => 0x1000 | self.address(tokens)?,
+            "jump0" => 0xb000 | self.address(tokens)?,
+            "sprite" => {
+                let x = self.register(tokens)?;
+                let y = self.register(tokens)?;
+                0xd000 | x << 8 | y << 4 | self.nibble(tokens)?
+            }
+            "bcd" => 0xf033 | self.register(tokens)? << 8,
+            "save" => 0xf055 | self.register(tokens)? << 8,
+            "load" => 0xf065 | self.register(tokens)? << 8,
+            "delay" => {
+                self.expect(tokens, ":=")?;
+                0xf015 | self.register(tokens)? << 8
+            }
+            "buzzer" => {
+                self.expect(tokens, ":=")?;
+                0xf018 | self.register(tokens)? << 8
+            }
+            "i" => self.index(tokens)?,
+            ":alias" => {
+                let name = self.next(tokens, "a name")?;
+                let x = self.register(tokens)?;
+                // an alias can be moved to another register, but nothing
+                // else can be called the same
+                if !self.aliases.contains_key(&name.text) {
+                    self.check_name(&name, "an alias")?;
+                }
+                self.aliases.insert(name.text, x as u8);
+                return Ok(());
+            }
+            "if" => return self.conditional(&t, tokens),
+            "else" => {
+                let at = match self.blocks.pop() {
+                    Some((Block::Begin(at), _)) => at,
+                    _ => return Err(self.error(&t, "\"else\" without \"begin\"".to_string())),
+                };
+                let past = self.rom.len();
+                self.word(&t, 0x1000)?;
+                self.patch(at, self.here());
+                self.blocks.push((Block::Else(past), t));
+                return Ok(());
+            }
+            "end" => {
+                match self.blocks.pop() {
+                    Some((Block::Begin(at), _)) | Some((Block::Else(at), _)) => {
+                        self.patch(at, self.here())
+                    }
+                    _ => return Err(self.error(&t, "\"end\" without \"begin\"".to_string())),
+                }
+                return Ok(());
+            }
+            "loop" => {
+                self.blocks.push((Block::Loop(self.here(), Vec::new()), t));
+                return Ok(());
+            }
+            "while" => {
+                if !self
+                    .blocks
+                    .iter()
+                    .any(|(b, _)| matches!(b, Block::Loop(..)))
+                {
+                    return Err(self.error(&t, "\"while\" outside a loop".to_string()));
+                }
+                let skip = self.condition(tokens)?;
+                self.word(&t, negate(skip))?;
+                let out = self.rom.len();
+                self.word(&t, 0x1000)?;
+                // whiles can be inside ifs inside the loop
+                if let Some((Block::Loop(_, outs), _)) = self
+                    .blocks
+                    .iter_mut()
+                    .rev()
+                    .find(|(b, _)| matches!(b, Block::Loop(..)))
+                {
+                    outs.push(out);
+                }
+                return Ok(());
+            }
+            "again" => {
+                let (start, outs) = match self.blocks.pop() {
+                    Some((Block::Loop(start, outs), _)) => (start, outs),
+                    _ => return Err(self.error(&t, "\"again\" without \"loop\"".to_string())),
+                };
+                self.word(&t, 0x1000 | start)?;
+                for out in outs {
+                    self.patch(out, self.here());
+                }
+                return Ok(());
+            }
+            text if self.reg(text).is_some() => {
+                let x = self.reg(text).unwrap_or_default() as u16;
+                self.arithmetic(x, tokens)?
+            }
+            // a byte of data
+            text if self.value(text).is_some() => {
+                let byte = self.immediate(&t, 8)? as u8;
+                return self.emit(&t, &[byte]);
+            }
+            // anything else is a subroutine to call
+            _ => 0x2000 | self.address_of(t.clone())?,
+        };
+        self.word(&t, word)
+    }
+
+    /// is name free to be what, e.g. "a label"?
+    fn check_name(&self, name: &Token, what: &str) -> Result<(), Chip8Error> {
+        let text = name.text.as_str();
+        if KEYWORDS.contains(&text) || register(text).is_some() || number(text).is_some() {
+            return Err(self.error(name, format!("\"{}\" can't be {}", text, what)));
+        }
+        let taken = if self.labels.contains_key(text) {
+            "a label"
+        } else if self.consts.contains_key(text) {
+            "a constant"
+        } else if self.macros.contains_key(text) {
+            "a macro"
+        } else if self.aliases.contains_key(text) {
+            "an alias"
+        } else {
+            return Ok(());
+        };
+        Err(self.error(name, format!("there's already {} \"{}\"", taken, text)))
+    }
+
+    fn label(&mut self, name: Token) -> Result<(), Chip8Error> {
+        self.check_name(&name, "a label")?;
+        self.labels.insert(name.text, self.here());
+        Ok(())
+    }
+
+    /// :macro name params... { body }. braces in the body have to match
+    fn define_macro(&mut self, tokens: &mut Tokens) -> Result<(), Chip8Error> {
+        let name = self.next(tokens, "a name")?;
+        self.check_name(&name, "a macro")?;
+        let mut params = Vec::new();
+        loop {
+            let t = self.next(tokens, "\"{\"")?;
+            match t.text.as_str() {
+                "{" => break,
+                _ => params.push(t.text),
+            }
+        }
+        let mut body = Vec::new();
+        let mut depth = 1;
+        loop {
+            let t = self.next(tokens, "\"}\"")?;
+            match t.text.as_str() {
+                "{" => depth += 1,
+                "}" => depth -= 1,
+                _ => {}
+            }
+            if depth == 0 {
+                break;
+            }
+            body.push(t);
+        }
+        self.macros.insert(name.text, Macro { params, body });
+        Ok(())
+    }
+
+    /// the macro called t, with its arguments filled in, ready to assemble
+    /// next. what it makes is put down to the macro's own lines
+    fn expand(&mut self, t: Token, tokens: &mut Tokens) -> Result<(), Chip8Error> {
+        self.expansions += 1;
+        if self.expansions > ASM_MAX_EXPANSIONS {
+            return Err(self.error(
+                &t,
+                format!("too many macros expanded (does \"{}\" use itself?)", t.text),
+            ));
+        }
+        let m = self.macros[&t.text].clone();
+        let mut args = Vec::new();
+        for _ in &m.params {
+            args.push(self.next(tokens, "an argument")?.text);
+        }
+        for body in m.body.into_iter().rev() {
+            let text = match m.params.iter().position(|p| *p == body.text) {
+                Some(n) => args[n].clone(),
+                None => body.text,
+            };
+            tokens.push_front(Token { text, ..body });
+        }
+        Ok(())
+    }
+
+    /// :include path, relative to the file it's in
+    fn include(&mut self, t: &Token, tokens: &mut Tokens) -> Result<(), Chip8Error> {
+        let name = self.next(tokens, "a file")?;
+        if self.lines.files.len() >= ASM_MAX_FILES {
+            return Err(self.error(
+                &name,
+                "too many files included (is one including itself?)".to_string(),
+            ));
+        }
+        let including = Path::new(&self.lines.files[t.file].0);
+        let path = including
+            .parent()
+            .unwrap_or(Path::new(""))
+            .join(name.text.trim_matches('"'));
+        let source = fs::read_to_string(&path)
+            .map_err(|e| self.error(&name, format!("can't read {}: {}", path.display(), e)))?;
+        let file = self.lines.add_file(&path.to_string_lossy(), &source);
+        for included in tokenise(&source, file).into_iter().rev() {
+            tokens.push_front(included);
+        }
+        Ok(())
+    }
+
+    /// i := and i +=
+    fn index(&mut self, tokens: &mut Tokens) -> Result<u16, Chip8Error> {
+        let op = self.next(tokens, "\":=\" or \"+=\"")?;
+        match op.text.as_str() {
+            ":=" => match tokens.front().map(|t| t.text.as_str()) {
+                Some("hex") => {
+                    tokens.pop_front();
+                    Ok(0xf029 | self.register(tokens)? << 8)
+                }
+                Some("bighex") => {
+                    tokens.pop_front();
+                    Ok(0xf030 | self.register(tokens)? << 8)
+                }
+                _ => Ok(0xa000 | self.address(tokens)?),
+            },
+            "+=" => Ok(0xf01e | self.register(tokens)? << 8),
+            _ => Err(self.error(&op, format!("can't do \"{}\" to i", op.text))),
+        }
+    }
+
+    /// vX := and the rest of the operators
+    fn arithmetic(&mut self, x: u16, tokens: &mut Tokens) -> Result<u16, Chip8Error> {
+        let op = self.next(tokens, "an operator")?;
+        let rhs = self.next(tokens, "something to operate with")?;
+        let vy = self.reg(&rhs.text).map(|y| x << 8 | (y as u16) << 4);
+        let word = match (op.text.as_str(), vy) {
+            (":=", Some(xy)) => 0x8000 | xy,
+            ("|=", Some(xy)) => 0x8001 | xy,
+            ("&=", Some(xy)) => 0x8002 | xy,
+            ("^=", Some(xy)) => 0x8003 | xy,
+            ("+=", Some(xy)) => 0x8004 | xy,
+            ("-=", Some(xy)) => 0x8005 | xy,
+            (">>=", Some(xy)) => 0x8006 | xy,
+            ("=-", Some(xy)) => 0x8007 | xy,
+            ("<<=", Some(xy)) => 0x800e | xy,
+            (":=", None) => match rhs.text.as_str() {
+                "random" => 0xc000 | x << 8 | self.byte(tokens)?,
+                "delay" => 0xf007 | x << 8,
+                "key" => 0xf00a | x << 8,
+                _ => 0x6000 | x << 8 | self.immediate(&rhs, 8)?,
+            },
+            ("+=", None) => 0x7000 | x << 8 | self.immediate(&rhs, 8)?,
+            // there's no instruction for it, but adding the negative will do
+            ("-=", None) => 0x7000 | x << 8 | (0x100 - self.immediate(&rhs, 8)?) & 0xff,
+            _ => {
+                return Err(self.error(
+                    &op,
+                    format!("can't do \"{} {}\" to a register", op.text, rhs.text),
+                ))
+            }
+        };
+        Ok(word)
+    }
+
+    /// if condition then statement, or if condition begin
+    fn conditional(&mut self, t: &Token, tokens: &mut Tokens) -> Result<(), Chip8Error> {
+        let skip = self.condition(tokens)?;
+        let how = self.next(tokens, "\"then\" or \"begin\"")?;
+        match how.text.as_str() {
+            // the statement after's the one that's skipped
+            "then" => self.word(t, skip),
+            "begin" => {
+                self.word(t, negate(skip))?;
+                let past = self.rom.len();
+                self.word(t, 0x1000)?;
+                self.blocks.push((Block::Begin(past), t.clone()));
+                Ok(())
+            }
+            _ => Err(self.error(
+                &how,
+                format!("expected \"then\" or \"begin\", not \"{}\"", how.text),
+            )),
+        }
+    }
+
+    /// a condition: whatever working out it needs goes in now, and what's
+    /// returned is the skip to put after it, which skips if it's false
+    fn condition(&mut self, tokens: &mut Tokens) -> Result<u16, Chip8Error> {
+        let x = self.register(tokens)?;
+        let op = self.next(tokens, "a comparison")?;
+        match op.text.as_str() {
+            "key" => return Ok(0xe0a1 | x << 8),
+            "-key" => return Ok(0xe09e | x << 8),
+            "==" | "!=" | "<" | ">" | "<=" | ">=" => {}
+            _ => return Err(self.error(&op, format!("can't compare with \"{}\"", op.text))),
+        }
+        let rhs = self.next(tokens, "something to compare with")?;
+        let y = self.reg(&rhs.text).map(u16::from);
+        let word = match (op.text.as_str(), y) {
+            ("==", Some(y)) => 0x9000 | x << 8 | y << 4,
+            ("!=", Some(y)) => 0x5000 | x << 8 | y << 4,
+            ("==", None) => 0x4000 | x << 8 | self.immediate(&rhs, 8)?,
+            ("!=", None) => 0x3000 | x << 8 | self.immediate(&rhs, 8)?,
+            (cmp, _) => {
+                // vF := rhs, then subtract one from the other and keep the
+                // flag, which says whether it didn't borrow
+                let load = match y {
+                    Some(y) => 0x8000 | ASM_VF << 8 | y << 4,
+                    None => 0x6000 | ASM_VF << 8 | self.immediate(&rhs, 8)?,
+                };
+                self.word(&op, load)?;
+                let (subtract, holds_if) = match cmp {
+                    // vF := rhs - vX: no borrow if vX <= rhs
+                    ">" => (0x8005, 0),
+                    "<=" => (0x8005, 1),
+                    // vF := vX - rhs: no borrow if vX >= rhs
+                    "<" => (0x8007, 0),
+                    _ => (0x8007, 1),
+                };
+                self.word(&op, subtract | ASM_VF << 8 | x << 4)?;
+                0x4000 | ASM_VF << 8 | holds_if
+            }
+        };
+        Ok(word)
+    }
+
+    /// point the jump (or whatever) at offset at to addr
+    fn patch(&mut self, at: usize, addr: u16) {
+        self.rom[at] |= (addr >> 8) as u8;
+        self.rom[at + 1] = addr as u8;
+    }
+
+    /// fill in the labels found late, and hand over the ROM
+    fn finish(mut self) -> Result<Assembled, Chip8Error> {
+        if let Some((block, t)) = self.blocks.last() {
+            let missing = match block {
+                Block::Loop(..) => "again",
+                _ => "end",
+            };
+            return Err(self.error(t, format!("\"{}\" without \"{}\"", t.text, missing)));
+        }
+        for fixup in std::mem::take(&mut self.fixups) {
+            let addr = match self.labels.get(&fixup.label) {
+                Some(a) => *a,
+                None => {
+                    return Err(
+                        self.error(&fixup.token, format!("no label called \"{}\"", fixup.label))
+                    )
+                }
+            };
+            self.patch(fixup.at, addr);
+        }
+        Ok(Assembled {
+            rom: self.rom,
+            lines: self.lines,
+            labels: self.labels.into_iter().collect(),
+        })
+    }
+}
+
+/// a skip that skips when skip wouldn't, and the other way round
+pub(crate) fn negate(skip: u16) -> u16 {
+    match skip & 0xf000 {
+        0x3000 => skip + 0x1000,
+        0x4000 => skip - 0x1000,
+        0x5000 => skip + 0x4000,
+        0x9000 => skip - 0x4000,
+        // ExA1 and Ex9E
+        _ => skip ^ (0xa1 ^ 0x9e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COUNTER: &str = "\
+# counts up in v0, forever
+: main
+  v0 := 0
+  i := digits
+: tick
+  v0 += 1
+  v1 := v0
+  sprite v1 v2 5
+  count   # a call, to a label further on
+  jump tick
+: count
+  v3 := random 0xff
+  return
+: digits
+  0xf0 0x90 -1
+";
+
+    #[test]
+    fn test_assemble() -> Result<(), Chip8Error> {
+        let program = assemble("counter.8o", COUNTER)?;
+        assert_eq!(
+            program.rom,
+            [
+                0x60, 0x00, 0xa2, 0x12, 0x70, 0x01, 0x81, 0x00, 0xd1, 0x25, 0x22, 0x0e, 0x12, 0x04,
+                0xc3, 0xff, 0x00, 0xee, 0xf0, 0x90, 0xff
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_operators() -> Result<(), Chip8Error> {
+        let source = "v1 |= v2 v1 &= v2 v1 ^= v2 v1 -= v2 v1 >>= v2 v1 =- v2 v1 <<= v2 \
+                      va := delay vb := key delay := vc buzzer := vd i += ve \
+                      i := hex v5 i := bighex v6 bcd v7 save v8 load v9 jump0 0x300";
+        let words: Vec<u16> = assemble("ops.8o", source)?
+            .rom
+            .chunks(2)
+            .map(|w| u16::from_be_bytes([w[0], w[1]]))
+            .collect();
+        assert_eq!(
+            words,
+            [
+                0x8121, 0x8122, 0x8123, 0x8125, 0x8126, 0x8127, 0x812e, 0xfa07, 0xfb0a, 0xfc15,
+                0xfd18, 0xfe1e, 0xf529, 0xf630, 0xf733, 0xf855, 0xf965, 0xb300
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_line_table() -> Result<(), Chip8Error> {
+        let program = assemble("counter.8o", COUNTER)?;
+        assert_eq!(program.lines.source_line(0x200), Some(("counter.8o", 3)));
+        assert_eq!(program.lines.source_line(0x20a), Some(("counter.8o", 9)));
+        // data isn't an instruction, and labels take no room
+        assert_eq!(program.lines.source_line(0x212), None);
+        assert_eq!(
+            program.lines.excerpt(0x204, 1),
+            Some(vec![
+                "counter.8o:6".to_string(),
+                "  5 | : tick".to_string(),
+                "> 6 |   v0 += 1".to_string(),
+                "  7 |   v1 := v0".to_string(),
+            ])
+        );
+        // the first line has nothing before it
+        let first = assemble("one.8o", "clear\nreturn")?.lines.excerpt(0x200, 2);
+        assert_eq!(first.map(|e| e.len()), Some(3));
+        Ok(())
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = |source: &str| match assemble("bad.8o", source) {
+            Err(e) => e.to_string(),
+            Ok(_) => "assembled".to_string(),
+        };
+        assert_eq!(
+            error("clear\nv0 := 256"),
+            "bad.8o:2: 256 doesn't fit in 8 bits"
+        );
+        assert_eq!(
+            error("jump nowhere"),
+            "bad.8o:1: no label called \"nowhere\""
+        );
+        assert_eq!(error(": a\n: a"), "bad.8o:2: there's already a label \"a\"");
+        assert_eq!(error(": v3"), "bad.8o:1: \"v3\" can't be a label");
+        assert_eq!(
+            error("sprite v0"),
+            "bad.8o:1: expected a register after \"v0\""
+        );
+        assert_eq!(
+            error("v0 *= v1"),
+            "bad.8o:1: can't do \"*= v1\" to a register"
+        );
+        assert_eq!(
+            error(&"0 ".repeat(3585)),
+            "bad.8o:1: the program's too big for memory"
+        );
+        assert_eq!(
+            error(":const A 1\n: A"),
+            "bad.8o:2: there's already a constant \"A\""
+        );
+        assert_eq!(
+            error(":macro forever { forever }\nforever"),
+            "bad.8o:1: too many macros expanded (does \"forever\" use itself?)"
+        );
+        assert_eq!(
+            error(":macro m { clear"),
+            "bad.8o:1: expected \"}\" after \"clear\""
+        );
+        assert_eq!(error("loop\nclear"), "bad.8o:1: \"loop\" without \"again\"");
+        assert_eq!(error("end"), "bad.8o:1: \"end\" without \"begin\"");
+        assert_eq!(error("while v0 == 1"), "bad.8o:1: \"while\" outside a loop");
+        assert_eq!(
+            error("if v0 =~ 1 then clear"),
+            "bad.8o:1: can't compare with \"=~\""
+        );
+        assert_eq!(
+            error(":alias a v1\n:const a 2"),
+            "bad.8o:2: there's already an alias \"a\""
+        );
+        assert_eq!(
+            error("clear :org 0x200"),
+            "bad.8o:1: can't go back to 0x200 from 0x202"
+        );
+    }
+
+    #[test]
+    fn test_const_and_macro() -> Result<(), Chip8Error> {
+        let source = "\
+:const SPEED 3
+:const TOP 0x3a0
+:macro move reg by { reg += by }
+:macro twice body { body body }
+  move v1 SPEED
+  twice clear
+  i := TOP
+  SPEED
+";
+        let program = assemble("macro.8o", source)?;
+        assert_eq!(
+            program.rom,
+            [0x71, 0x03, 0x00, 0xe0, 0x00, 0xe0, 0xa3, 0xa0, 0x03]
+        );
+        // an expansion's instructions come from the macro's own line
+        assert_eq!(program.lines.source_line(0x200), Some(("macro.8o", 3)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_structured() -> Result<(), Chip8Error> {
+        let source = "\
+:alias x v1
+:alias y v2
+  if x == 3 then clear
+  if x != y then return
+  if x key then x += 1
+  if x > 5 begin
+    y := 1
+  else
+    y := 2
+  end
+  loop
+    x -= 1
+    while x >= y
+  again
+";
+        let program = assemble("if.8o", source)?;
+        let words: Vec<u16> = program
+            .rom
+            .chunks(2)
+            .map(|w| u16::from_be_bytes([w[0], w[1]]))
+            .collect();
+        assert_eq!(
+            words,
+            [
+                0x4103, 0x00e0, 0x5120, 0x00ee, 0xe1a1, 0x7101, 0x6f05, 0x8f15, 0x3f00, 0x1218,
+                0x6201, 0x121a, 0x6202, 0x71ff, 0x8f20, 0x8f17, 0x3f01, 0x1226, 0x121a
+            ]
+        );
+        // all of a comparison's working out is down to its line
+        assert_eq!(program.lines.source_line(0x20e), Some(("if.8o", 6)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_align_and_org() -> Result<(), Chip8Error> {
+        let program = assemble("align.8o", "1 :align 4 2 :org 0x208 : done 3 jump done")?;
+        assert_eq!(program.rom, [1, 0, 0, 0, 2, 0, 0, 0, 3, 0x12, 0x08]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_include() -> Result<(), Chip8Error> {
+        let dir = std::env::temp_dir().join(format!("chip8-asm-{}", std::process::id()));
+        fs::create_dir_all(dir.join("lib"))?;
+        fs::write(dir.join("lib").join("sprites.8o"), ": ball\n  0x60 0x60\n")?;
+        fs::write(
+            dir.join("main.8o"),
+            "i := ball\n:include lib/sprites.8o\nclear\n",
+        )?;
+        let program = assemble_file(&dir.join("main.8o"))?;
+        assert_eq!(program.rom, [0xa2, 0x02, 0x60, 0x60, 0x00, 0xe0]);
+        // and the line table carries on in the file that did the including
+        let (file, line) = program.lines.source_line(0x204).unwrap_or_default();
+        assert!(file.ends_with("main.8o"));
+        assert_eq!(line, 3);
+        let missing = assemble("main.8o", ":include nothing.8o");
+        assert!(missing.is_err_and(|e| e.to_string().contains("can't read nothing.8o")));
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
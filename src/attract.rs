@@ -0,0 +1,415 @@
+//! # attract mode
+//!
+//! `chip8 attract playlist.toml`: for a kiosk, or an exhibition of CHIP-8's
+//! history. it runs each ROM on the playlist for a while, then the next,
+//! round and round until escape. a ROM can come with a demo so it gets
+//! played rather than sitting on its title screen: a replay, as
+//! --record-replay makes, or failing that the one --record-demo keeps in
+//! the ROM's config. the playlist's TOML:
+//!
+//! ```toml
+//! # how long each ROM runs for, unless it says
+//! seconds = 30
+//!
+//! [[rom]]
+//! path = "invaders.ch8"
+//! demo = "invaders.replay"
+//! seconds = 60
+//!
+//! # one of the gallery's demos
+//! [[rom]]
+//! path = "bounce"
+//! ```
+//!
+//! paths are from the playlist's own directory, and a ROM that isn't a file
+//! there can be one of the gallery's, or one installed in the ROMs directory
+use crate::clock::Clock;
+use crate::config::Config;
+use crate::display::Display;
+use crate::error::Chip8Error;
+use crate::gallery::GALLERY;
+use crate::input::Input;
+use crate::interpreter::{Chip8Interpreter, RunOutcome};
+use crate::interrupt::RefreshRate;
+use crate::paths;
+use crate::replay::{Demo, PlaybackInput, Replay};
+use crate::rominfo;
+use crate::sound::Sound;
+use serde::Deserialize;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::Path;
+use std::time::Duration;
+
+/// how long each ROM runs for, if the playlist doesn't say
+const ATTRACT_SECONDS: u32 = 30;
+
+/// what a playlist file says
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct Playlist {
+    #[serde(default = "default_seconds")]
+    pub seconds: u32,
+    #[serde(default, rename = "rom")]
+    pub roms: Vec<PlaylistEntry>,
+}
+
+fn default_seconds() -> u32 {
+    ATTRACT_SECONDS
+}
+
+/// how many frames of a demo attract mode gets through, showing a ROM for
+/// as long as it usually does at the usual refresh rate: as much as
+/// --record-demo needs to keep
+pub fn demo_frames() -> usize {
+    (ATTRACT_SECONDS as f64 * RefreshRate::default().hz()) as usize
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct PlaylistEntry {
+    /// a ROM file, or the name of one of the gallery's
+    pub path: String,
+    /// how long to run it for, if not the playlist's usual
+    #[serde(default)]
+    pub seconds: Option<u32>,
+    /// a replay to play it with
+    #[serde(default)]
+    pub demo: Option<String>,
+}
+
+impl Playlist {
+    pub fn from_toml(s: &str) -> Result<Self, Chip8Error> {
+        let playlist: Playlist =
+            toml::from_str(s).map_err(|e| Chip8Error::ConfigError(e.to_string()))?;
+        if playlist.roms.is_empty() {
+            return Err(Chip8Error::ConfigError(
+                "the playlist hasn't got any ROMs on it".to_string(),
+            ));
+        }
+        Ok(playlist)
+    }
+
+    /// everything on the playlist at path, loaded and ready to show, with
+    /// demos from config for ROMs the playlist hasn't got one for
+    pub fn load(path: &Path, config: &Config) -> Result<Vec<Show>, Chip8Error> {
+        let playlist = Self::from_toml(&fs::read_to_string(path)?)?;
+        let dir = path.parent().unwrap_or(Path::new("."));
+        playlist
+            .roms
+            .iter()
+            .map(|entry| Show::load(entry, playlist.seconds, dir, config))
+            .collect()
+    }
+}
+
+/// a ROM on the playlist, loaded
+#[derive(Debug, PartialEq)]
+pub struct Show {
+    pub title: String,
+    pub rom: Vec<u8>,
+    pub seconds: u32,
+    pub demo: Option<Demo>,
+}
+
+impl Show {
+    fn load(
+        entry: &PlaylistEntry,
+        seconds: u32,
+        dir: &Path,
+        config: &Config,
+    ) -> Result<Self, Chip8Error> {
+        let path = dir.join(&entry.path);
+        let name = rominfo::rom_name(Path::new(&entry.path));
+        let (title, rom) = if path.is_file() {
+            (None, fs::read(&path)?)
+        } else if let Some(g) = GALLERY.iter().find(|g| g.name == entry.path) {
+            (Some(g.title.to_string()), g.rom.to_vec())
+        } else if let Some(p) = paths::find_rom(&entry.path) {
+            (None, fs::read(p)?)
+        } else {
+            return Err(Chip8Error::ConfigError(format!(
+                "can't find {} for the playlist",
+                entry.path
+            )));
+        };
+        let demo = match &entry.demo {
+            Some(d) => Some(Demo::from(&Replay::read(BufReader::new(File::open(
+                dir.join(d),
+            )?))?)),
+            None => config.roms.get(&name).and_then(|r| r.demo.clone()),
+        };
+        // a damaged demo's better found now than part way round
+        if let Some(d) = &demo {
+            d.keys()?;
+        }
+        Ok(Show {
+            title: title
+                .or_else(|| rominfo::lookup(&name).map(|info| info.title.to_string()))
+                .unwrap_or(name),
+            rom,
+            seconds: entry.seconds.unwrap_or(seconds),
+            demo,
+        })
+    }
+}
+
+/// the demo's keys, if there is one, and nobody's if not. the host's input
+/// is only listened to for escape
+struct AttractInput<'a> {
+    demo: Option<PlaybackInput>,
+    host: &'a mut dyn Input,
+}
+
+impl Input for AttractInput<'_> {
+    fn flush_keys(&mut self) -> Result<(), Chip8Error> {
+        match &mut self.demo {
+            Some(d) => d.flush_keys(),
+            None => Ok(()),
+        }
+    }
+
+    fn read_key(&mut self) -> Result<Option<u8>, Chip8Error> {
+        match &mut self.demo {
+            Some(d) => d.read_key(),
+            None => Ok(None),
+        }
+    }
+
+    fn tick(&mut self) -> Result<(), Chip8Error> {
+        if let Some(d) = &mut self.demo {
+            d.tick()?;
+        }
+        self.host.tick()
+    }
+
+    fn wait_for_event(&mut self, timeout: Duration) -> Result<bool, Chip8Error> {
+        self.host.wait_for_event(timeout)
+    }
+
+    fn take_menu_request(&mut self) -> Result<bool, Chip8Error> {
+        self.host.take_menu_request()
+    }
+}
+
+/// show each of shows in turn, over and over, until escape. clock keeps
+/// time, if not the interpreter's own. a ROM that faults gives way to the
+/// next, and the last fault each one had is handed back at the end, with
+/// its title, for the log
+pub fn run(
+    shows: &[Show],
+    display: &mut dyn Display,
+    input: &mut dyn Input,
+    sound: &mut dyn Sound,
+    clock: Option<&dyn Clock>,
+) -> Result<Vec<(String, Chip8Error)>, Chip8Error> {
+    let mut faults: Vec<Option<Chip8Error>> = shows.iter().map(|_| None).collect();
+    loop {
+        let mut faulted = 0;
+        for (show, fault) in shows.iter().zip(faults.iter_mut()) {
+            match play(show, display, input, sound, clock) {
+                Ok(RunOutcome::MenuRequested) => {
+                    return Ok(shows
+                        .iter()
+                        .zip(faults)
+                        .filter_map(|(s, f)| Some((s.title.clone(), f?)))
+                        .collect())
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    faulted += 1;
+                    *fault = Some(e);
+                }
+            }
+        }
+        // nothing left to show, and nowhere to hear escape from
+        if faulted == shows.len() {
+            let (title, e) = shows
+                .iter()
+                .zip(faults)
+                .find_map(|(s, f)| Some((&s.title, f?)))
+                .ok_or_else(|| Chip8Error::BadRom("the playlist's empty".to_string()))?;
+            return Err(Chip8Error::BadRom(format!(
+                "every ROM on the playlist faulted ({}: {})",
+                title, e
+            )));
+        }
+    }
+}
+
+/// one show's turn
+fn play(
+    show: &Show,
+    display: &mut dyn Display,
+    input: &mut dyn Input,
+    sound: &mut dyn Sound,
+    clock: Option<&dyn Clock>,
+) -> Result<RunOutcome, Chip8Error> {
+    display.set_status(&format!("{} (escape to stop)", show.title));
+    let mut attract_input = AttractInput {
+        demo: match &show.demo {
+            Some(d) => Some(PlaybackInput::new(d.keys()?)),
+            None => None,
+        },
+        host: input,
+    };
+    let mut machine = Chip8Interpreter::new(display, &mut attract_input, sound)?;
+    if let Some(c) = clock {
+        machine.set_clock(c);
+    }
+    if let Some(d) = &show.demo {
+        machine.set_seed(d.seed);
+    }
+    machine.load_program(&mut &show.rom[..])?;
+    let frames = show.seconds as f64 * machine.refresh_rate().hz();
+    machine.main_loop(frames as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::replay::{frame_hash, FrameHasher};
+    use crate::sound::Mute;
+    use std::cell::Cell;
+    use std::time::Duration;
+
+    #[test]
+    fn test_playlist() -> Result<(), Chip8Error> {
+        let playlist = Playlist::from_toml(
+            "seconds = 10\n\
+             [[rom]]\npath = \"invaders.ch8\"\ndemo = \"invaders.replay\"\nseconds = 60\n\
+             [[rom]]\npath = \"bounce\"\n",
+        )?;
+        assert_eq!(playlist.seconds, 10);
+        assert_eq!(
+            playlist.roms[0],
+            PlaylistEntry {
+                path: "invaders.ch8".to_string(),
+                seconds: Some(60),
+                demo: Some("invaders.replay".to_string()),
+            }
+        );
+        assert_eq!(playlist.roms[1].seconds, None);
+
+        // the gallery's, by name, with the demo from the config
+        let mut config = Config::default();
+        let show = Show::load(&playlist.roms[1], playlist.seconds, Path::new("."), &config)?;
+        assert_eq!(show.title, "Bounce");
+        assert_eq!(show.seconds, 10);
+        assert_eq!(show.demo, None);
+        let demo = Demo::new(1, &[None, Some(0x5)]);
+        config.rom_mut("bounce").demo = Some(demo.clone());
+        let show = Show::load(&playlist.roms[1], playlist.seconds, Path::new("."), &config)?;
+        assert_eq!(show.demo, Some(demo));
+
+        assert!(Playlist::from_toml("seconds = 10").is_err());
+        let missing = PlaylistEntry {
+            path: "nowhere.ch8".to_string(),
+            seconds: None,
+            demo: None,
+        };
+        assert!(Show::load(&missing, 10, Path::new("."), &config).is_err());
+        Ok(())
+    }
+
+    /// escape, after so many frames
+    struct EscapeAfter(Cell<u32>);
+
+    impl Input for EscapeAfter {
+        fn flush_keys(&mut self) -> Result<(), Chip8Error> {
+            Ok(())
+        }
+
+        fn read_key(&mut self) -> Result<Option<u8>, Chip8Error> {
+            Ok(None)
+        }
+
+        fn tick(&mut self) -> Result<(), Chip8Error> {
+            self.0.set(self.0.get().saturating_sub(1));
+            Ok(())
+        }
+
+        fn take_menu_request(&mut self) -> Result<bool, Chip8Error> {
+            Ok(self.0.get() == 0)
+        }
+    }
+
+    #[test]
+    fn test_run() -> Result<(), Chip8Error> {
+        // waits for a key, and draws it
+        let rom = vec![0xf0, 0x0a, 0xf0, 0x29, 0x60, 0x00, 0xd0, 0x05, 0x12, 0x08];
+        // 7, held long enough for FX0A
+        let keys = [[None; 2].as_slice(), &[Some(0x7); 10]].concat();
+        let demo = Demo::new(0, &keys);
+        let shows = [
+            Show {
+                title: "one".to_string(),
+                rom: rom.clone(),
+                seconds: 1,
+                demo: None,
+            },
+            Show {
+                title: "two".to_string(),
+                rom,
+                seconds: 1,
+                demo: Some(demo),
+            },
+        ];
+        let clock = ManualClock::new();
+        let mut display = FrameHasher::new(None);
+        // round once and a half
+        let mut input = EscapeAfter(Cell::new(150));
+        run(
+            &shows,
+            &mut display,
+            &mut input,
+            &mut Mute::new(),
+            Some(&clock),
+        )?;
+        let seconds = clock.now();
+        assert!(seconds > Duration::from_millis(2400) && seconds < Duration::from_millis(2600));
+        // nobody pressed anything in the first, and the demo did in the
+        // second
+        let blank = frame_hash(&[0; 0x100]);
+        let hashes = display.hashes();
+        assert!(hashes[..60].iter().all(|h| *h == blank));
+        assert!(hashes[60..120].iter().any(|h| *h != blank));
+        Ok(())
+    }
+
+    #[test]
+    fn test_fault() -> Result<(), Chip8Error> {
+        let show = |title: &str, rom: &[u8]| Show {
+            title: title.to_string(),
+            rom: rom.to_vec(),
+            seconds: 1,
+            demo: None,
+        };
+        // returns from nowhere, next to one that loops forever
+        let (broken, fine) = (show("broken", &[0x00, 0xee]), show("fine", &[0x12, 0x00]));
+        let clock = ManualClock::new();
+        let mut input = EscapeAfter(Cell::new(90));
+        let faults = run(
+            &[broken, fine],
+            &mut FrameHasher::new(None),
+            &mut input,
+            &mut Mute::new(),
+            Some(&clock),
+        )?;
+        // the broken one's passed over, and the fine one gets its turn
+        assert_eq!(faults.len(), 1);
+        assert_eq!(faults[0].0, "broken");
+        assert!(clock.now() > Duration::from_millis(1400));
+
+        // and with nothing that runs, there's no going round for ever
+        let mut input = EscapeAfter(Cell::new(90));
+        assert!(run(
+            &[show("broken", &[0x00, 0xee])],
+            &mut FrameHasher::new(None),
+            &mut input,
+            &mut Mute::new(),
+            Some(&clock),
+        )
+        .is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,398 @@
+//! # frame-synchronised audio
+//!
+//! beep turns the PC speaker on and off as the program says, whenever the
+//! host gets round to it, so tones come out with ragged edges and clicks.
+//! instead, RingSound renders each emulated frame's worth of samples (with
+//! sound::Synth, like ToneRecorder) into an AudioRing, and the audio device
+//! takes them out at its own pace. the ring keeps a few frames at most, so
+//! if the emulator falls behind the device plays silence, and if it gets
+//! ahead the oldest samples go
+//!
+//! the ring mixes two channels: the buzzer, and the emulator's own UI
+//! sounds, which come whenever they happen
+//!
+//! there's no audio library to hand, so the device is a player (aplay, by
+//! default) reading raw samples from a pipe: writing blocks while its
+//! buffer's full, which is as good as being called back for more
+use crate::error::Chip8Error;
+use crate::interrupt::RefreshRate;
+use crate::sound::{Sound, Synth, UiCue, Volume, TONE_SAMPLES_PER_FRAME, TONE_SAMPLE_RATE};
+use std::collections::VecDeque;
+use std::io::Write;
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// how many frames of samples the ring holds before dropping the oldest
+const AUDIO_RING_FRAMES: usize = 4;
+/// how long the player's own buffer is, unless --audio-buffer says: about a
+/// frame and a half, so it doesn't add much latency
+pub const AUDIO_PLAYER_BUFFER: Duration = Duration::from_millis(25);
+/// aplay with no sound card to play on gives up about straight away
+const AUDIO_PLAYER_STARTUP: Duration = Duration::from_millis(50);
+
+/// what a ring's samples are for. each has its own queue, and they're
+/// mixed together on the way out to the device
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Channel {
+    /// the CHIP-8 buzzer, a frame at a time
+    Buzzer,
+    /// the emulator's own clicks and chimes, whenever they happen (even
+    /// while the program's paused)
+    Ui,
+}
+
+impl Channel {
+    pub const ALL: [Channel; 2] = [Channel::Buzzer, Channel::Ui];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// the channels' queues, behind the one lock
+struct Mix {
+    queues: [VecDeque<i16>; 2],
+    /// how many buzzer samples the device wanted that weren't there
+    underruns: usize,
+}
+
+/// samples on their way from the emulator to the audio device
+pub struct AudioRing {
+    mix: Mutex<Mix>,
+    /// how many samples the frame-at-a-time channels keep
+    capacity: usize,
+}
+
+impl AudioRing {
+    pub fn new(frames: usize) -> Self {
+        let capacity = frames * TONE_SAMPLES_PER_FRAME as usize;
+        AudioRing {
+            mix: Mutex::new(Mix {
+                queues: Default::default(),
+                underruns: 0,
+            }),
+            capacity,
+        }
+    }
+
+    /// add samples to a channel. if there's no room, the oldest go, except
+    /// for UI sounds, which are short and shouldn't be cut off
+    pub fn push(&self, channel: Channel, samples: &[i16]) {
+        let mut mix = self.mix.lock().unwrap();
+        let queue = &mut mix.queues[channel.index()];
+        queue.extend(samples);
+        if channel != Channel::Ui {
+            let excess = queue.len().saturating_sub(self.capacity);
+            queue.drain(..excess);
+        }
+    }
+
+    /// what the device calls back for: fill out with all the channels mixed
+    /// together, and silence for any the emulator hasn't rendered yet
+    pub fn fill(&self, out: &mut [i16]) {
+        let mut mix = self.mix.lock().unwrap();
+        let mut mixed = vec![0i32; out.len()];
+        for channel in Channel::ALL {
+            let queue = &mut mix.queues[channel.index()];
+            let n = queue.len().min(out.len());
+            for (m, s) in mixed.iter_mut().zip(queue.drain(..n)) {
+                *m += s as i32;
+            }
+            if channel == Channel::Buzzer {
+                mix.underruns += out.len() - n;
+            }
+        }
+        for (o, m) in out.iter_mut().zip(mixed) {
+            *o = m.clamp(i16::MIN as i32, i16::MAX as i32) as i16;
+        }
+    }
+
+    /// how many samples a channel has waiting
+    pub fn queued(&self, channel: Channel) -> usize {
+        self.mix.lock().unwrap().queues[channel.index()].len()
+    }
+
+    /// nothing waiting on any channel
+    pub fn is_empty(&self) -> bool {
+        Channel::ALL.iter().all(|c| self.queued(*c) == 0)
+    }
+
+    pub fn underruns(&self) -> usize {
+        self.mix.lock().unwrap().underruns
+    }
+}
+
+/// renders the buzzer a frame at a time into an AudioRing
+pub struct RingSound {
+    ring: Arc<AudioRing>,
+    synth: Synth,
+    frame: Vec<i16>,
+}
+
+impl RingSound {
+    pub fn new(ring: Arc<AudioRing>) -> Self {
+        RingSound {
+            ring,
+            synth: Synth::new(),
+            frame: Vec::with_capacity(TONE_SAMPLES_PER_FRAME as usize),
+        }
+    }
+
+    /// the synth, e.g. to give it a pattern to play
+    pub fn synth_mut(&mut self) -> &mut Synth {
+        &mut self.synth
+    }
+}
+
+impl Sound for RingSound {
+    fn beep(&mut self) -> Result<(), Chip8Error> {
+        self.synth.beep();
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<(), Chip8Error> {
+        self.synth.stop();
+        Ok(())
+    }
+
+    fn set_volume(&mut self, volume: Volume) -> Result<(), Chip8Error> {
+        self.synth.set_volume(volume);
+        Ok(())
+    }
+
+    fn tick(&mut self) -> Result<(), Chip8Error> {
+        self.frame.clear();
+        self.synth.render_frame(&mut self.frame);
+        self.ring.push(Channel::Buzzer, &self.frame);
+        Ok(())
+    }
+
+    fn set_refresh_rate(&mut self, rate: RefreshRate) {
+        self.synth.set_refresh_rate(rate);
+    }
+
+    /// everything queued ahead of the frame just pushed
+    fn latency(&self) -> Option<Duration> {
+        let ahead = self
+            .ring
+            .queued(Channel::Buzzer)
+            .saturating_sub(self.frame.len());
+        Some(Duration::from_secs_f64(
+            ahead as f64 / TONE_SAMPLE_RATE as f64,
+        ))
+    }
+
+    fn cue(&mut self, cue: UiCue) -> Result<(), Chip8Error> {
+        self.ring
+            .push(Channel::Ui, &cue.samples(self.synth.volume()));
+        Ok(())
+    }
+}
+
+/// an audio player reading mono 16-bit samples at TONE_SAMPLE_RATE on its
+/// stdin, fed from an AudioRing by a thread of its own
+pub struct PipeDevice {
+    player: Child,
+    running: Arc<AtomicBool>,
+    feed: Option<JoinHandle<()>>,
+}
+
+impl PipeDevice {
+    /// aplay, from alsa-utils, buffering buffer's worth of samples itself
+    pub fn aplay(ring: Arc<AudioRing>, buffer: Duration) -> Result<Self, Chip8Error> {
+        let mut aplay = Command::new("aplay");
+        aplay.args([
+            "-q",
+            "-t",
+            "raw",
+            "-f",
+            "S16_LE",
+            "-c",
+            "1",
+            "-r",
+            &TONE_SAMPLE_RATE.to_string(),
+            &format!("--buffer-time={}", buffer.as_micros()),
+        ]);
+        let mut device = Self::spawn(&mut aplay, ring)?;
+        thread::sleep(AUDIO_PLAYER_STARTUP);
+        match device.player.try_wait()? {
+            Some(status) => Err(Chip8Error::AudioError(format!(
+                "aplay stopped straight away ({})",
+                status
+            ))),
+            None => Ok(device),
+        }
+    }
+
+    /// start player, and keep it fed until dropped
+    pub fn spawn(player: &mut Command, ring: Arc<AudioRing>) -> Result<Self, Chip8Error> {
+        let mut player = player
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| Chip8Error::AudioError(format!("can't start the player: {}", e)))?;
+        let mut pipe = player.stdin.take().unwrap();
+        let running = Arc::new(AtomicBool::new(true));
+        let feed = {
+            let running = running.clone();
+            thread::spawn(move || {
+                let mut samples = [0; TONE_SAMPLES_PER_FRAME as usize];
+                let mut bytes = Vec::with_capacity(2 * samples.len());
+                while running.load(Ordering::Relaxed) {
+                    ring.fill(&mut samples);
+                    bytes.clear();
+                    bytes.extend(samples.iter().flat_map(|s| s.to_le_bytes()));
+                    // the player's gone: nothing more to do
+                    if pipe.write_all(&bytes).is_err() {
+                        break;
+                    }
+                }
+            })
+        };
+        Ok(PipeDevice {
+            player,
+            running,
+            feed: Some(feed),
+        })
+    }
+}
+
+impl Drop for PipeDevice {
+    fn drop(&mut self) {
+        // the feed's probably waiting on the player, so stop that first
+        self.running.store(false, Ordering::Relaxed);
+        let _ = self.player.kill();
+        let _ = self.player.wait();
+        if let Some(feed) = self.feed.take() {
+            let _ = feed.join();
+        }
+    }
+}
+
+/// a RingSound played through aplay, so it can be the terminal's sound
+pub struct PipeSound {
+    sound: RingSound,
+    // kept for as long as the sound, to keep playing it
+    _device: PipeDevice,
+    buffer: Duration,
+}
+
+impl PipeSound {
+    /// with aplay buffering buffer's worth, or AUDIO_PLAYER_BUFFER
+    pub fn aplay(buffer: Option<Duration>) -> Result<Self, Chip8Error> {
+        let ring = Arc::new(AudioRing::new(AUDIO_RING_FRAMES));
+        let buffer = buffer.unwrap_or(AUDIO_PLAYER_BUFFER);
+        Ok(PipeSound {
+            _device: PipeDevice::aplay(ring.clone(), buffer)?,
+            sound: RingSound::new(ring),
+            buffer,
+        })
+    }
+}
+
+impl Sound for PipeSound {
+    fn beep(&mut self) -> Result<(), Chip8Error> {
+        self.sound.beep()
+    }
+
+    fn stop(&mut self) -> Result<(), Chip8Error> {
+        self.sound.stop()
+    }
+
+    fn set_volume(&mut self, volume: Volume) -> Result<(), Chip8Error> {
+        self.sound.set_volume(volume)
+    }
+
+    fn tick(&mut self) -> Result<(), Chip8Error> {
+        self.sound.tick()
+    }
+
+    fn set_refresh_rate(&mut self, rate: RefreshRate) {
+        self.sound.set_refresh_rate(rate);
+    }
+
+    /// the ring's, and then the player's buffer on top
+    fn latency(&self) -> Option<Duration> {
+        self.sound.latency().map(|l| l + self.buffer)
+    }
+
+    fn cue(&mut self, cue: UiCue) -> Result<(), Chip8Error> {
+        self.sound.cue(cue)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    #[test]
+    fn test_ring() {
+        let ring = AudioRing::new(1);
+        let frame = TONE_SAMPLES_PER_FRAME as usize;
+        ring.push(Channel::Buzzer, &vec![1; frame]);
+        ring.push(Channel::Buzzer, &[2, 3]);
+        // the oldest two went to make room
+        assert_eq!(ring.queued(Channel::Buzzer), frame);
+        let mut out = vec![9; frame + 2];
+        ring.fill(&mut out);
+        assert_eq!(&out[frame - 2..], [2, 3, 0, 0]);
+        assert_eq!(ring.underruns(), 2);
+        assert!(ring.is_empty());
+    }
+
+    #[test]
+    fn test_mix() {
+        let ring = AudioRing::new(1);
+        ring.push(Channel::Buzzer, &[1000, 1000, i16::MAX]);
+        ring.push(Channel::Ui, &[50, 50, 50, 50]);
+        let mut out = [0; 4];
+        ring.fill(&mut out);
+        // added together, without wrapping round
+        assert_eq!(out, [1050, 1050, i16::MAX, 50]);
+        assert_eq!(ring.underruns(), 1);
+    }
+
+    #[test]
+    fn test_ring_sound() -> Result<(), Chip8Error> {
+        let ring = Arc::new(AudioRing::new(AUDIO_RING_FRAMES));
+        let mut sound = RingSound::new(ring.clone());
+        sound.tick()?;
+        sound.beep()?;
+        sound.tick()?;
+        // the beep's a frame of silence behind
+        let frame =
+            Duration::from_secs_f64(TONE_SAMPLES_PER_FRAME as f64 / TONE_SAMPLE_RATE as f64);
+        assert_eq!(sound.latency(), Some(frame));
+        let mut out = vec![0; 2 * TONE_SAMPLES_PER_FRAME as usize];
+        ring.fill(&mut out);
+        let (silent, beeping) = out.split_at(TONE_SAMPLES_PER_FRAME as usize);
+        assert!(silent.iter().all(|s| *s == 0));
+        assert!(beeping.iter().all(|s| *s != 0));
+        // a click goes straight in, ticking or not
+        sound.cue(UiCue::Click)?;
+        assert!(ring.queued(Channel::Ui) > 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_pipe_device() -> Result<(), Chip8Error> {
+        // a player that takes a frame and stops
+        let ring = Arc::new(AudioRing::new(AUDIO_RING_FRAMES));
+        ring.push(Channel::Buzzer, &[0x0102; 4]);
+        let mut head = Command::new("head");
+        head.args(["-c", "8"]);
+        let device = PipeDevice::spawn(&mut head, ring.clone())?;
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !ring.is_empty() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(1));
+        }
+        drop(device);
+        assert!(ring.is_empty());
+        Ok(())
+    }
+}
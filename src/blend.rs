@@ -0,0 +1,241 @@
+//! # frame blending
+//!
+//! CHIP-8 games rub a sprite out and draw it again to move it, XORing it
+//! off and on, so it's missing from every other frame and flickers. a
+//! phosphor screen hid that by glowing for a while after it was lit; Blend
+//! does the same, sitting in front of the real display and lighting every
+//! pixel that was lit in any of the last few frames. the interpreter and
+//! the program never see it, and recordings, replays and the like still get
+//! the frames as they were drawn.
+//!
+//! --blend 2 or 3 says how many frames, for whichever frontend's running;
+//! the config file's [blend] table can say it for each frontend by name,
+//! e.g. `terminal = 2` and `gui = 3`
+use crate::display::{changed_ranges, Display, Hud, Theme};
+use crate::error::Chip8Error;
+use crate::heat::Heat;
+use crate::input::Focus;
+use crate::sound::Volume;
+use std::collections::VecDeque;
+use std::ops::Range;
+
+/// the most frames it'll blend: much more and moving things smear
+pub const BLEND_MAX_FRAMES: usize = 3;
+
+/// how many frames to blend, from 1 (which is none) to BLEND_MAX_FRAMES
+pub fn parse_frames(s: &str) -> Result<usize, Chip8Error> {
+    match s.parse() {
+        Ok(n) if (1..=BLEND_MAX_FRAMES).contains(&n) => Ok(n),
+        _ => Err(Chip8Error::ConfigError(format!(
+            "can't blend \"{}\" frames (try 2 or 3, or 1 for none)",
+            s
+        ))),
+    }
+}
+
+/// passes each frame on to another display with the last few lit into it
+pub struct Blend<'a> {
+    inner: &'a mut dyn Display,
+    frames: usize,
+    // the last few frames, as drawn, newest last
+    drawn: VecDeque<Vec<u8>>,
+    // what inner was last given, to tell it what's changed
+    shown: Option<Vec<u8>>,
+}
+
+impl<'a> Blend<'a> {
+    /// blend the last frames frames (1 doesn't blend at all)
+    pub fn new(inner: &'a mut dyn Display, frames: usize) -> Self {
+        Blend {
+            inner,
+            frames: frames.clamp(1, BLEND_MAX_FRAMES),
+            drawn: VecDeque::new(),
+            shown: None,
+        }
+    }
+
+    /// start again, e.g. for a new mode, so nothing's blended from before
+    fn forget(&mut self) {
+        self.drawn.clear();
+        self.shown = None;
+    }
+}
+
+impl<'a> Display for Blend<'a> {
+    fn draw(&mut self, data: &[u8]) -> Result<(), Chip8Error> {
+        self.draw_changes(data, None)
+    }
+
+    fn draw_changes(
+        &mut self,
+        data: &[u8],
+        changed: Option<&[Range<usize>]>,
+    ) -> Result<(), Chip8Error> {
+        if self.frames == 1 {
+            return self.inner.draw_changes(data, changed);
+        }
+        if self.drawn.back().is_some_and(|d| d.len() != data.len()) {
+            self.forget();
+        }
+        if self.drawn.len() == self.frames {
+            self.drawn.pop_front();
+        }
+        self.drawn.push_back(data.to_vec());
+        let mut blended = data.to_vec();
+        for frame in &self.drawn {
+            for (b, d) in blended.iter_mut().zip(frame) {
+                *b |= d;
+            }
+        }
+        let changed = self
+            .shown
+            .as_ref()
+            .map(|shown| changed_ranges(shown, &blended));
+        self.inner.draw_changes(&blended, changed.as_deref())?;
+        self.shown = Some(blended);
+        Ok(())
+    }
+
+    fn get_display_size_bytes(&mut self) -> usize {
+        self.inner.get_display_size_bytes()
+    }
+
+    fn set_planes(&mut self, planes: usize) -> Result<(), Chip8Error> {
+        self.inner.set_planes(planes)
+    }
+
+    fn set_mode(&mut self, width: usize, height: usize) -> Result<(), Chip8Error> {
+        self.forget();
+        self.inner.set_mode(width, height)
+    }
+
+    fn set_status(&mut self, status: &str) {
+        self.inner.set_status(status);
+    }
+
+    fn notify(&mut self, notice: &str) {
+        self.inner.notify(notice);
+    }
+
+    fn set_speed(&mut self, speed: f64) {
+        self.inner.set_speed(speed);
+    }
+
+    fn set_volume(&mut self, volume: Volume) {
+        self.inner.set_volume(volume);
+    }
+
+    fn set_hud(&mut self, hud: Option<Hud>) {
+        self.inner.set_hud(hud);
+    }
+
+    fn set_heat(&mut self, heat: Option<Heat>) {
+        self.inner.set_heat(heat);
+    }
+
+    fn set_help(&mut self, help: Option<Vec<String>>) {
+        self.inner.set_help(help);
+    }
+
+    fn set_slots(&mut self, slots: Option<Vec<String>>) {
+        self.inner.set_slots(slots);
+    }
+
+    fn set_palette(&mut self, palette: Option<Vec<String>>) {
+        self.inner.set_palette(palette);
+    }
+
+    fn set_theme(&mut self, theme: Theme) {
+        self.inner.set_theme(theme);
+    }
+
+    fn set_focus(&mut self, focus: Focus) {
+        self.inner.set_focus(focus);
+    }
+
+    fn refresh(&mut self) -> Result<(), Chip8Error> {
+        self.inner.refresh()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// keeps what it's given to draw, and which bytes it's told changed
+    #[derive(Default)]
+    struct Drawn(Vec<(Vec<u8>, Option<Vec<usize>>)>);
+
+    impl Display for Drawn {
+        fn draw(&mut self, data: &[u8]) -> Result<(), Chip8Error> {
+            self.draw_changes(data, None)
+        }
+
+        fn draw_changes(
+            &mut self,
+            data: &[u8],
+            changed: Option<&[Range<usize>]>,
+        ) -> Result<(), Chip8Error> {
+            let changed = changed.map(|c| c.iter().flat_map(|r| r.clone()).collect());
+            self.0.push((data.to_vec(), changed));
+            Ok(())
+        }
+
+        fn get_display_size_bytes(&mut self) -> usize {
+            2
+        }
+
+        fn set_mode(&mut self, _width: usize, _height: usize) -> Result<(), Chip8Error> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_parse() -> Result<(), Chip8Error> {
+        assert_eq!(parse_frames("2")?, 2);
+        assert_eq!(parse_frames("1")?, 1);
+        assert!(parse_frames("0").is_err());
+        assert!(parse_frames("4").is_err());
+        assert!(parse_frames("lots").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_blend() -> Result<(), Chip8Error> {
+        let mut drawn = Drawn::default();
+        let mut blend = Blend::new(&mut drawn, 2);
+        // a sprite that's there every other frame, and one that moves
+        for frame in [[0x80, 0x01], [0x00, 0x02], [0x80, 0x04], [0x00, 0x08]] {
+            blend.draw(&frame)?;
+        }
+        // a new mode starts again
+        blend.set_mode(64, 32)?;
+        blend.draw(&[0x00, 0x10])?;
+        let frames: Vec<Vec<u8>> = drawn.0.iter().map(|(f, _)| f.clone()).collect();
+        assert_eq!(
+            frames,
+            [
+                [0x80, 0x01],
+                [0x80, 0x03],
+                [0x80, 0x06],
+                [0x80, 0x0c],
+                [0x00, 0x10]
+            ]
+        );
+        // and it says what's changed since what it blended last
+        assert_eq!(drawn.0[0].1, None);
+        assert_eq!(drawn.0[2].1, Some(vec![1]));
+        assert_eq!(drawn.0[4].1, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_no_blend() -> Result<(), Chip8Error> {
+        let mut drawn = Drawn::default();
+        let mut blend = Blend::new(&mut drawn, 1);
+        blend.draw(&[0x80, 0x00])?;
+        blend.draw_changes(&[0x00, 0x00], Some(&[0..1, 1..2]))?;
+        assert_eq!(drawn.0[1], (vec![0x00, 0x00], Some(vec![0, 1])));
+        Ok(())
+    }
+}
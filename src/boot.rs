@@ -0,0 +1,63 @@
+//! # booting
+//!
+//! a real VIP doesn't start a program the moment it's switched on: the ROM
+//! sizes up memory, then the interpreter sets itself up and runs a couple
+//! of CHIP-8 instructions of its own (00E0 to clear the screen, and 004B to
+//! turn it on) before it gets to 0200. nor is RAM empty at power-on, and
+//! the interpreter only clears the display, so the V registers and
+//! anything the program doesn't load over hold whatever they came up as.
+//! a program that forgets to set up a register, or that reads memory it
+//! never wrote, can behave quite differently from one run to the next.
+//! --boot cold has all that happen; --boot fast (as usual) starts the
+//! program straight away, with memory clear
+use crate::error::Chip8Error;
+use crate::interpreter::CHIP8_FRAME_CYCLES;
+
+/// machine cycles from power-on to the VIP's interpreter being ready to
+/// fetch from 0200, having just turned the display on, as long as the ROM
+/// and interpreter take on the 1802 (see the vip module's tests)
+pub const VIP_BOOT_CYCLES: u64 = 3244;
+
+/// the 1861's first interrupt: line 78 of the first frame after the
+/// display's been turned on, which was towards the end of the first
+const VIP_FIRST_INTERRUPT_CYCLES: u64 = CHIP8_FRAME_CYCLES + 78 * 14;
+
+/// machine cycles the program gets to run before its first interrupt,
+/// after a cold boot. after a fast one, the interrupt comes first
+pub const VIP_BOOT_INTERRUPT_DELAY: u64 = VIP_FIRST_INTERRUPT_CYCLES - VIP_BOOT_CYCLES;
+
+/// how the machine comes up
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Boot {
+    /// straight into the program, with memory clear
+    #[default]
+    Fast,
+    /// as a VIP does from being switched on
+    Cold,
+}
+
+impl Boot {
+    pub fn parse(s: &str) -> Result<Self, Chip8Error> {
+        match s {
+            "fast" => Ok(Boot::Fast),
+            "cold" => Ok(Boot::Cold),
+            _ => Err(Chip8Error::ConfigError(format!(
+                "can't boot \"{}\" (try fast or cold)",
+                s
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() -> Result<(), Chip8Error> {
+        assert_eq!(Boot::parse("cold")?, Boot::Cold);
+        assert_eq!(Boot::parse("fast")?, Boot::default());
+        assert!(Boot::parse("warm").is_err());
+        Ok(())
+    }
+}
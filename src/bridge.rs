@@ -0,0 +1,148 @@
+//! # the host bridge
+//!
+//! a page of memory a ROM can use to talk to the emulator, for testing
+//! ROMs: bytes it writes there come out as debug prints and signals for a
+//! test harness, and the emulator keeps the frame count and random seed
+//! there for it to read. no real machine had one, so it's off unless asked
+//! for with --host-bridge
+//!
+//! the page, from BRIDGE_ADDR (just under the stack, and above all but the
+//! biggest programs):
+//!   +0     print: write a character at a time; a newline ends the line
+//!   +1     signal: write anything but 0, e.g. 1 for passed, 2 for failed
+//!   +4..8  frames since the program started, big-endian
+//!   +8..a  the random seed, big-endian
+use crate::error::Chip8Error;
+use crate::memory::{Chip8MemoryMap, MemoryMap};
+use crate::watch::{MemoryPatch, MemoryWatch};
+use std::fmt;
+
+pub const BRIDGE_ADDR: u16 = 0x0e80;
+const BRIDGE_LEN: usize = 0x20;
+const BRIDGE_PRINT: usize = 0x0;
+const BRIDGE_SIGNAL: usize = 0x1;
+const BRIDGE_FRAMES: usize = 0x4;
+const BRIDGE_SEED: usize = 0x8;
+
+/// something the ROM told us through the bridge
+#[derive(Debug, PartialEq, Clone)]
+pub enum BridgeEvent {
+    Print(String),
+    Signal(u8),
+}
+
+impl fmt::Display for BridgeEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BridgeEvent::Print(s) => write!(f, "ROM says: {}", s),
+            BridgeEvent::Signal(n) => write!(f, "ROM signalled {}", n),
+        }
+    }
+}
+
+/// hand it to the interpreter as both a watch (to count frames and pass on
+/// what the ROM says) and a patch (to pick up writes as they happen)
+pub struct HostBridge {
+    seed: u16,
+    frames: u32,
+    /// the print line so far
+    line: String,
+    events: Vec<BridgeEvent>,
+    /// how many events have gone out as notices
+    shown: usize,
+}
+
+impl HostBridge {
+    pub fn new(seed: u16) -> Self {
+        HostBridge {
+            seed,
+            frames: 0,
+            line: String::new(),
+            events: Vec::new(),
+            shown: 0,
+        }
+    }
+
+    /// everything the ROM's said since last time
+    pub fn take_events(&mut self) -> Vec<BridgeEvent> {
+        self.shown = 0;
+        std::mem::take(&mut self.events)
+    }
+}
+
+impl MemoryPatch for HostBridge {
+    fn after_instruction(&mut self, memory: &mut Chip8MemoryMap) -> Result<(), Chip8Error> {
+        let page = memory.get_rw_slice(BRIDGE_ADDR, BRIDGE_LEN)?;
+        match std::mem::take(&mut page[BRIDGE_PRINT]) {
+            0 => {}
+            b'\n' => self
+                .events
+                .push(BridgeEvent::Print(std::mem::take(&mut self.line))),
+            c => self.line.push(c as char),
+        }
+        match std::mem::take(&mut page[BRIDGE_SIGNAL]) {
+            0 => {}
+            n => self.events.push(BridgeEvent::Signal(n)),
+        }
+        page[BRIDGE_FRAMES..BRIDGE_FRAMES + 4].copy_from_slice(&self.frames.to_be_bytes());
+        page[BRIDGE_SEED..BRIDGE_SEED + 2].copy_from_slice(&self.seed.to_be_bytes());
+        Ok(())
+    }
+}
+
+impl MemoryWatch for HostBridge {
+    fn frame(&mut self, _memory: &Chip8MemoryMap) -> Result<Vec<String>, Chip8Error> {
+        self.frames = self.frames.wrapping_add(1);
+        let notices = self.events[self.shown..]
+            .iter()
+            .map(|e| e.to_string())
+            .collect();
+        self.shown = self.events.len();
+        Ok(notices)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::DummyDisplay;
+    use crate::input::DummyInput;
+    use crate::interpreter::Chip8Interpreter;
+    use crate::sound::Mute;
+    use std::cell::RefCell;
+
+    #[test]
+    fn test_bridge() -> Result<(), Chip8Error> {
+        // print "hi", signal 3, read the seed into v0 and v1, stop
+        #[rustfmt::skip]
+        let rom = [
+            0xae, 0x80, 0x60, 0x68, 0xf0, 0x55,
+            0xae, 0x80, 0x60, 0x69, 0xf0, 0x55,
+            0xae, 0x80, 0x60, 0x0a, 0xf0, 0x55,
+            0xae, 0x81, 0x60, 0x03, 0xf0, 0x55,
+            0xae, 0x88, 0xf1, 0x65, 0x12, 0x1c,
+        ];
+        let bridge = RefCell::new(HostBridge::new(0x1234));
+        let mut watch = &bridge;
+        let mut patch = &bridge;
+        let mut display = DummyDisplay;
+        let mut input = DummyInput::new(&[]);
+        let mut sound = Mute::new();
+        let mut machine = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+        machine.add_watch(&mut watch);
+        machine.add_patch(&mut patch);
+        machine.load_program(&mut &rom[..])?;
+        machine.run_frames(3)?;
+        assert_eq!((machine.v(0), machine.v(1)), (0x12, 0x34));
+        let frames = machine
+            .memory()
+            .get_ro_slice(BRIDGE_ADDR + BRIDGE_FRAMES as u16, 4)?;
+        assert_eq!(frames, 3u32.to_be_bytes());
+        drop(machine);
+        assert_eq!(
+            bridge.borrow_mut().take_events(),
+            [BridgeEvent::Print("hi".to_string()), BridgeEvent::Signal(3)]
+        );
+        Ok(())
+    }
+}
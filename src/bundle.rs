@@ -0,0 +1,198 @@
+//! # bundles
+//!
+//! a game to give to someone who's never heard of CHIP-8: chip8 bundle
+//! game.ch8 --out mygame writes a copy of this emulator with the ROM, and
+//! the quirks, variant and keys it plays with (as a profile), tacked onto
+//! the end. run, it finds them there and plays the game straight away, as
+//! if it had been started with the ROM and --profile, so there's just the
+//! one file to send round and nothing to install or type.
+//!
+//! the end of a bundle's executable looks like:
+//!
+//! ```text
+//! [the emulator as built] [the bundle, as TOML] [its length, 8 bytes LE] "chip8bnd"
+//! ```
+//!
+//! operating systems load executables by the headers at the start, so
+//! whatever's after them doesn't get in the way. bundling a bundle replaces
+//! what it had on the end
+use crate::error::Chip8Error;
+use crate::profile::Profile;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// the very end of a bundle's executable, to know it's one
+const BUNDLE_MAGIC: &[u8; 8] = b"chip8bnd";
+
+/// the magic, and the length before it
+const TRAILER_SIZE: u64 = 16;
+
+/// anything bigger on the end isn't a bundle, whatever it says: no ROM's
+/// anywhere near, even as base64
+const BUNDLE_MAX: u64 = 1 << 20;
+
+/// a ROM and how to play it, on the end of an executable
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Bundle {
+    /// rominfo::rom_name of the ROM it was made from, for the stats and
+    /// anything else kept by ROM
+    pub rom_name: String,
+    #[serde(with = "crate::clipboard::as_base64")]
+    pub rom: Vec<u8>,
+    /// its quirks, variant, speed and keys
+    pub profile: Profile,
+}
+
+impl Bundle {
+    pub fn from_toml(s: &str) -> Result<Self, Chip8Error> {
+        let bundle: Bundle = toml::from_str(s).map_err(|e| bundle_error(&e.to_string()))?;
+        bundle.profile.validate()?;
+        Ok(bundle)
+    }
+
+    pub fn to_toml(&self) -> Result<String, Chip8Error> {
+        toml::to_string(self).map_err(|e| bundle_error(&e.to_string()))
+    }
+
+    /// the one on the end of exe, if there is one
+    pub fn read(exe: &mut (impl Read + Seek)) -> Result<Option<Self>, Chip8Error> {
+        let len = exe.seek(SeekFrom::End(0))?;
+        let payload = match payload_len(exe, len)? {
+            Some(p) => p,
+            None => return Ok(None),
+        };
+        exe.seek(SeekFrom::Start(len - TRAILER_SIZE - payload))?;
+        let mut text = String::new();
+        exe.take(payload).read_to_string(&mut text)?;
+        Self::from_toml(&text).map(Some)
+    }
+
+    /// the one on the end of the executable that's running, if it's a
+    /// bundle
+    pub fn current() -> Result<Option<Self>, Chip8Error> {
+        Self::read(&mut File::open(env::current_exe()?)?)
+    }
+
+    /// exe (less anything already bundled on it) with this on the end
+    pub fn write(
+        &self,
+        exe: &mut (impl Read + Seek),
+        out: &mut impl Write,
+    ) -> Result<(), Chip8Error> {
+        let len = exe.seek(SeekFrom::End(0))?;
+        let own = match payload_len(exe, len)? {
+            Some(p) => len - TRAILER_SIZE - p,
+            None => len,
+        };
+        exe.seek(SeekFrom::Start(0))?;
+        std::io::copy(&mut exe.take(own), out)?;
+        let text = self.to_toml()?;
+        out.write_all(text.as_bytes())?;
+        out.write_all(&(text.len() as u64).to_le_bytes())?;
+        out.write_all(BUNDLE_MAGIC)?;
+        Ok(())
+    }
+
+    /// write a copy of the running executable to out, with this on the
+    /// end, runnable as it is. it's given the executable suffix it needs,
+    /// if any (.exe on Windows), and that's where it goes
+    pub fn save(&self, out: &Path) -> Result<PathBuf, Chip8Error> {
+        let exe = env::current_exe()?;
+        let mut out = out.to_path_buf();
+        let suffix = env::consts::EXE_EXTENSION;
+        if !suffix.is_empty() && out.extension().is_none() {
+            out.set_extension(suffix);
+        }
+        if fs::canonicalize(&out).ok() == fs::canonicalize(&exe).ok() {
+            return Err(bundle_error("it can't be written over the emulator itself"));
+        }
+        self.write(&mut File::open(&exe)?, &mut File::create(&out)?)?;
+        // runnable by whoever could run this one
+        fs::set_permissions(&out, fs::metadata(&exe)?.permissions())?;
+        Ok(out)
+    }
+}
+
+/// how long the bundle on the end of exe (len bytes) is, if it's got one
+fn payload_len(exe: &mut (impl Read + Seek), len: u64) -> Result<Option<u64>, Chip8Error> {
+    if len < TRAILER_SIZE {
+        return Ok(None);
+    }
+    let mut trailer = [0; TRAILER_SIZE as usize];
+    exe.seek(SeekFrom::Start(len - TRAILER_SIZE))?;
+    exe.read_exact(&mut trailer)?;
+    let (size, magic) = trailer.split_at(8);
+    if magic != BUNDLE_MAGIC {
+        return Ok(None);
+    }
+    match u64::from_le_bytes(size.try_into().unwrap_or_default()) {
+        p if p <= BUNDLE_MAX && p <= len - TRAILER_SIZE => Ok(Some(p)),
+        _ => Err(bundle_error(
+            "what's on the end says it's longer than it is",
+        )),
+    }
+}
+
+fn bundle_error(what: &str) -> Chip8Error {
+    Chip8Error::ConfigError(format!("bad bundle: {}", what))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn bundle() -> Bundle {
+        let mut profile = Profile::new("bundle");
+        profile.schip = true;
+        profile.quirks = "modern".to_string();
+        profile.keymap.insert("j".to_string(), 4);
+        Bundle {
+            rom_name: "brix".to_string(),
+            rom: vec![0x12, 0x00],
+            profile,
+        }
+    }
+
+    #[test]
+    fn test_bundle() -> Result<(), Chip8Error> {
+        let exe = b"\x7fELF and the rest of the emulator".to_vec();
+        assert_eq!(Bundle::read(&mut Cursor::new(&exe))?, None);
+
+        let mut bundled = Vec::new();
+        bundle().write(&mut Cursor::new(&exe), &mut bundled)?;
+        assert!(bundled.starts_with(&exe));
+        assert!(bundled.ends_with(BUNDLE_MAGIC));
+        assert_eq!(Bundle::read(&mut Cursor::new(&bundled))?, Some(bundle()));
+
+        // a bundle of a bundle has just the one on the end
+        let mut again = Bundle {
+            rom_name: "pong".to_string(),
+            ..bundle()
+        };
+        again.rom = vec![0x00, 0xe0];
+        let mut rebundled = Vec::new();
+        again.write(&mut Cursor::new(&bundled), &mut rebundled)?;
+        assert_eq!(Bundle::read(&mut Cursor::new(&rebundled))?, Some(again));
+        assert!(rebundled.starts_with(&exe));
+        assert!(!rebundled.windows(4).any(|w| w == b"brix"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_bad_bundle() {
+        // says it's longer than the whole file
+        let mut exe = b"emulator".to_vec();
+        exe.extend_from_slice(&1000u64.to_le_bytes());
+        exe.extend_from_slice(BUNDLE_MAGIC);
+        assert!(Bundle::read(&mut Cursor::new(&exe)).is_err());
+        // and a profile that won't do
+        let mut b = bundle();
+        b.profile.speed = 3.0;
+        let text = b.to_toml().unwrap_or_default();
+        assert!(Bundle::from_toml(&text).is_err());
+    }
+}
@@ -0,0 +1,149 @@
+//! # timing calibration
+//!
+//! how well the host sleeps and how long it takes to draw vary a lot: a
+//! desktop's sleep is good to a few microseconds, a Raspberry Pi's or WSL's
+//! can be a millisecond or more out, and a terminal over SSH can take most
+//! of a frame to draw. a fraction of a second's measuring at startup
+//! (with --calibrate) picks how long the main loop spins for instead of
+//! trusting sleep, and whether to start off skipping frames, so neither
+//! needs setting by hand
+use crate::clock::Clock;
+use crate::display::{Display, CHIP8_TEST_CARD};
+use crate::error::Chip8Error;
+use crate::frameskip::{SkipPolicy, FRAMESKIP_AUTO_MAX, FRAMESKIP_BUDGET};
+use std::fmt;
+use std::time::Duration;
+
+/// how many times to try each thing, taking the worst
+const CALIBRATE_SLEEPS: u32 = 10;
+const CALIBRATE_DRAWS: usize = 6;
+/// how long the test sleeps are
+const CALIBRATE_SLEEP: Duration = Duration::from_millis(1);
+/// spinning for less than a machine cycle isn't worth it, and spinning for
+/// more than a frame might as well be spinning all the time
+const CALIBRATE_MIN_SPIN: Duration = Duration::from_nanos(4540);
+const CALIBRATE_MAX_SPIN: Duration = Duration::from_micros(1_000_000 / 60);
+
+/// what the measuring found
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Calibration {
+    /// the most a sleep went on past when it should have stopped
+    pub sleep_overshoot: Duration,
+    /// the longest a frame took to draw
+    pub draw_time: Duration,
+}
+
+impl Calibration {
+    /// time some short sleeps, and some draws of the test card (ending on a
+    /// blank screen) on display
+    pub fn measure(clock: &dyn Clock, display: &mut dyn Display) -> Result<Self, Chip8Error> {
+        let mut sleep_overshoot = Duration::ZERO;
+        for _ in 0..CALIBRATE_SLEEPS {
+            let start = clock.now();
+            clock.sleep(CALIBRATE_SLEEP);
+            let overshoot = (clock.now() - start).saturating_sub(CALIBRATE_SLEEP);
+            sleep_overshoot = sleep_overshoot.max(overshoot);
+        }
+        let blank = [0; CHIP8_TEST_CARD.len()];
+        let mut draw_time = Duration::ZERO;
+        for n in 0..CALIBRATE_DRAWS {
+            // something different every time, so it's all redrawn
+            let frame = if n % 2 == 0 { &CHIP8_TEST_CARD } else { &blank };
+            let start = clock.now();
+            display.draw(frame)?;
+            draw_time = draw_time.max(clock.now() - start);
+        }
+        Ok(Calibration {
+            sleep_overshoot,
+            draw_time,
+        })
+    }
+
+    /// how close SpinClock should count on sleep getting, with some to spare
+    pub fn spin(&self) -> Duration {
+        (self.sleep_overshoot * 3 / 2).clamp(CALIBRATE_MIN_SPIN, CALIBRATE_MAX_SPIN)
+    }
+
+    /// skip frames from the start if drawing's too slow to fit in a frame
+    /// alongside the program
+    pub fn skip_policy(&self) -> Option<SkipPolicy> {
+        (self.draw_time > FRAMESKIP_BUDGET).then_some(SkipPolicy::Auto {
+            max: FRAMESKIP_AUTO_MAX,
+        })
+    }
+}
+
+impl fmt::Display for Calibration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // short enough for a notice under the picture
+        write!(
+            f,
+            "sleep +{}µs, draw {}µs: spin {}µs",
+            self.sleep_overshoot.as_micros(),
+            self.draw_time.as_micros(),
+            self.spin().as_micros()
+        )?;
+        match self.skip_policy() {
+            Some(_) => write!(f, ", skipping frames"),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::display::DummyDisplay;
+
+    /// a display that takes a while to draw
+    struct Slow<'c>(&'c ManualClock);
+
+    impl<'c> Display for Slow<'c> {
+        fn draw(&mut self, _data: &[u8]) -> Result<(), Chip8Error> {
+            self.0.advance(FRAMESKIP_BUDGET * 2);
+            Ok(())
+        }
+
+        fn get_display_size_bytes(&mut self) -> usize {
+            0x100
+        }
+
+        fn set_mode(&mut self, _width: usize, _height: usize) -> Result<(), Chip8Error> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_measure() -> Result<(), Chip8Error> {
+        // sleeps exactly, draws instantly
+        let clock = ManualClock::new();
+        let c = Calibration::measure(&clock, &mut DummyDisplay)?;
+        assert_eq!(c.sleep_overshoot, Duration::ZERO);
+        assert_eq!(c.spin(), CALIBRATE_MIN_SPIN);
+        assert_eq!(c.skip_policy(), None);
+
+        let c = Calibration::measure(&clock, &mut Slow(&clock))?;
+        assert_eq!(c.draw_time, FRAMESKIP_BUDGET * 2);
+        assert!(c.skip_policy().is_some());
+        assert_eq!(
+            c.to_string(),
+            "sleep +0µs, draw 16666µs: spin 4µs, skipping frames"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_spin() {
+        let c = Calibration {
+            sleep_overshoot: Duration::from_millis(2),
+            draw_time: Duration::ZERO,
+        };
+        assert_eq!(c.spin(), Duration::from_millis(3));
+        let c = Calibration {
+            sleep_overshoot: Duration::from_millis(50),
+            draw_time: Duration::ZERO,
+        };
+        assert_eq!(c.spin(), CALIBRATE_MAX_SPIN);
+    }
+}
@@ -0,0 +1,54 @@
+//! # stopping from outside
+//!
+//! a GUI's stop button, or a server with a request that's taken too long,
+//! wants the interpreter to give up now, not once the frame (or the
+//! thousand frames) it was asked for are done. a CancelToken's handed to
+//! the interpreter, and a clone of it kept wherever the asking comes from,
+//! e.g. another thread: once it's cancelled, the interpreter stops before
+//! its next cycle with a Cancelled error. the machine's left as it was, so
+//! it can carry on from there, once the token's reset
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// cancelled, or not, wherever it's been cloned to
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// ask whatever's got a clone of this to stop
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// take it back, to carry on
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_clones_share() {
+        let token = CancelToken::new();
+        let other = token.clone();
+        assert!(!other.is_cancelled());
+        thread::spawn(move || token.cancel())
+            .join()
+            .expect("it cancels");
+        assert!(other.is_cancelled());
+        other.reset();
+        assert!(!other.is_cancelled());
+    }
+}
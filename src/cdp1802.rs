@@ -0,0 +1,496 @@
+//! # RCA CDP1802
+//!
+//! the COSMAC's CPU, for running the machine code that CHIP-8 programs call
+//! with 0NNN. timings are in machine cycles (8 clock cycles each) like
+//! everything else: two per instruction, or three for the long branches
+//! and skips
+use crate::error::Chip8Error;
+use crate::memory::MemoryMap;
+#[cfg(feature = "full")]
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// clock cycles in each machine cycle
+pub const CLOCKS_PER_MACHINE_CYCLE: u64 = 8;
+
+/// the slowest and fastest clocks --clock-rate takes, in MHz
+const CLOCK_MIN_MHZ: f64 = 0.1;
+const CLOCK_MAX_MHZ: f64 = 100.0;
+
+/// how fast the 1802's clock runs, which is what everything the machine
+/// does is timed by: the VIP's crystal is 1.76064 MHz, but people have
+/// swapped theirs for faster ones. the 1861 puts the picture out at the
+/// refresh rate whatever the CPU's doing, so a faster clock gets more done
+/// each frame, where --speed runs everything faster, timers and all
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "full", derive(Serialize, Deserialize))]
+pub struct ClockRate {
+    hertz: u32,
+}
+
+impl ClockRate {
+    pub const VIP: ClockRate = ClockRate { hertz: 1_760_640 };
+
+    /// e.g. "3.52", "3.52MHz", or "vip"
+    pub fn parse(s: &str) -> Result<Self, Chip8Error> {
+        if s == "vip" {
+            return Ok(Self::VIP);
+        }
+        match s.trim_end_matches("MHz").parse::<f64>() {
+            Ok(mhz) if (CLOCK_MIN_MHZ..=CLOCK_MAX_MHZ).contains(&mhz) => Ok(ClockRate {
+                hertz: (mhz * 1_000_000.0).round() as u32,
+            }),
+            _ => Err(Chip8Error::ConfigError(format!(
+                "a clock rate is vip or between {} and {}MHz, not {}",
+                CLOCK_MIN_MHZ, CLOCK_MAX_MHZ, s
+            ))),
+        }
+    }
+
+    pub fn mhz(&self) -> f64 {
+        self.hertz as f64 / 1_000_000.0
+    }
+
+    /// is it one parse would have given? one read back from a saved state
+    /// may not be, and a clock that doesn't tick has no cycle time
+    pub fn check(&self) -> Result<(), Chip8Error> {
+        match (CLOCK_MIN_MHZ..=CLOCK_MAX_MHZ).contains(&self.mhz()) {
+            true => Ok(()),
+            false => Err(Chip8Error::ConfigError(format!(
+                "a clock rate is between {} and {}MHz, not {}",
+                CLOCK_MIN_MHZ, CLOCK_MAX_MHZ, self
+            ))),
+        }
+    }
+
+    /// how long a machine cycle takes, in nanoseconds (to the one below)
+    pub const fn cycle_ns(&self) -> u64 {
+        CLOCKS_PER_MACHINE_CYCLE * 1_000_000_000 / self.hertz as u64
+    }
+}
+
+impl Default for ClockRate {
+    fn default() -> Self {
+        Self::VIP
+    }
+}
+
+impl fmt::Display for ClockRate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}MHz", self.mhz())
+    }
+}
+
+/// the 1802's view of the rest of the machine, beyond memory
+pub trait Cdp1802Io {
+    /// OUT 1-7 put a byte on the bus for a device
+    fn output(&mut self, _port: u8, _value: u8) -> Result<(), Chip8Error> {
+        Ok(())
+    }
+
+    /// INP 1-7 read a byte off the bus from a device
+    fn input(&mut self, _port: u8) -> Result<u8, Chip8Error> {
+        Ok(0)
+    }
+
+    /// the state of external flag EF1-4
+    fn flag(&mut self, _line: u8) -> bool {
+        false
+    }
+}
+
+/// nothing attached
+pub struct NoIo;
+
+impl Cdp1802Io for NoIo {}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Cdp1802 {
+    /// the sixteen scratchpad registers, any of which can be the program
+    /// counter (R(P)) or data pointer (R(X))
+    pub r: [u16; 16],
+    pub d: u8,
+    /// carry/borrow
+    pub df: bool,
+    pub p: u8,
+    pub x: u8,
+    /// X and P saved by an interrupt or MARK
+    pub t: u8,
+    /// interrupts enabled
+    pub ie: bool,
+    /// the Q output, which drives the VIP's buzzer
+    pub q: bool,
+    /// waiting in IDL for an interrupt or DMA
+    pub idle: bool,
+}
+
+impl Cdp1802 {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// what the CLEAR line does: start from R0 = 0000 with interrupts on.
+    /// the other registers keep whatever they had
+    pub fn reset(&mut self) {
+        self.r[0] = 0;
+        self.p = 0;
+        self.x = 0;
+        self.q = false;
+        self.ie = true;
+        self.idle = false;
+    }
+
+    fn read(memory: &impl MemoryMap, addr: u16) -> Result<u8, Chip8Error> {
+        Ok(memory.get_ro_slice(addr, 1)?[0])
+    }
+
+    fn write(memory: &mut impl MemoryMap, addr: u16, value: u8) -> Result<(), Chip8Error> {
+        memory.get_rw_slice(addr, 1)?[0] = value;
+        Ok(())
+    }
+
+    /// the byte after the opcode, moving the program counter past it
+    fn immediate(&mut self, memory: &impl MemoryMap) -> Result<u8, Chip8Error> {
+        let p = self.p as usize;
+        let byte = Self::read(memory, self.r[p])?;
+        self.r[p] = self.r[p].wrapping_add(1);
+        Ok(byte)
+    }
+
+    /// D = a - b - borrow, setting DF if there was no borrow
+    fn subtract(&mut self, a: u8, b: u8, borrow: bool) {
+        let result = a as i16 - b as i16 - borrow as i16;
+        self.d = result as u8;
+        self.df = result >= 0;
+    }
+
+    /// D = a + b + carry, setting DF on overflow
+    fn add(&mut self, a: u8, b: u8, carry: bool) {
+        let result = a as u16 + b as u16 + carry as u16;
+        self.d = result as u8;
+        self.df = result > 0xff;
+    }
+
+    /// run one instruction, returning the machine cycles it took
+    pub fn step(
+        &mut self,
+        memory: &mut impl MemoryMap,
+        io: &mut impl Cdp1802Io,
+    ) -> Result<usize, Chip8Error> {
+        if self.idle {
+            return Ok(1);
+        }
+        let op = self.immediate(memory)?;
+        let n = (op & 0xf) as usize;
+        let p = self.p as usize;
+        let x = self.x as usize;
+        match op >> 4 {
+            0x0 if n == 0 => self.idle = true,
+            0x0 => self.d = Self::read(memory, self.r[n])?,
+            0x1 => self.r[n] = self.r[n].wrapping_add(1),
+            0x2 => self.r[n] = self.r[n].wrapping_sub(1),
+            0x3 => {
+                let take = match n & 0x7 {
+                    0x0 => true,
+                    0x1 => self.q,
+                    0x2 => self.d == 0,
+                    0x3 => self.df,
+                    line => io.flag(line as u8 - 3),
+                };
+                // 38 is SKP rather than "never branch", but that's the same
+                // thing: step over the branch address
+                if take != (n & 0x8 != 0) {
+                    let target = Self::read(memory, self.r[p])?;
+                    self.r[p] = (self.r[p] & 0xff00) | target as u16;
+                } else {
+                    self.r[p] = self.r[p].wrapping_add(1);
+                }
+            }
+            0x4 => {
+                self.d = Self::read(memory, self.r[n])?;
+                self.r[n] = self.r[n].wrapping_add(1);
+            }
+            0x5 => Self::write(memory, self.r[n], self.d)?,
+            0x6 => match n {
+                0x0 => self.r[x] = self.r[x].wrapping_add(1),
+                0x1..=0x7 => {
+                    io.output(n as u8, Self::read(memory, self.r[x])?)?;
+                    self.r[x] = self.r[x].wrapping_add(1);
+                }
+                0x9..=0xf => {
+                    self.d = io.input(n as u8 - 8)?;
+                    Self::write(memory, self.r[x], self.d)?;
+                }
+                // 68 is only an instruction on the 1804/5/6
+                _ => {}
+            },
+            0x7 => match n {
+                0x0 | 0x1 => {
+                    let xp = Self::read(memory, self.r[x])?;
+                    self.r[x] = self.r[x].wrapping_add(1);
+                    self.x = xp >> 4;
+                    self.p = xp & 0xf;
+                    self.ie = n == 0;
+                }
+                0x2 => {
+                    self.d = Self::read(memory, self.r[x])?;
+                    self.r[x] = self.r[x].wrapping_add(1);
+                }
+                0x3 => {
+                    Self::write(memory, self.r[x], self.d)?;
+                    self.r[x] = self.r[x].wrapping_sub(1);
+                }
+                0x4 => self.add(Self::read(memory, self.r[x])?, self.d, self.df),
+                0x5 => self.subtract(Self::read(memory, self.r[x])?, self.d, !self.df),
+                0x6 => {
+                    let carry = self.df;
+                    self.df = self.d & 1 != 0;
+                    self.d = (self.d >> 1) | ((carry as u8) << 7);
+                }
+                0x7 => self.subtract(self.d, Self::read(memory, self.r[x])?, !self.df),
+                0x8 => Self::write(memory, self.r[x], self.t)?,
+                0x9 => {
+                    self.t = (self.x << 4) | self.p;
+                    Self::write(memory, self.r[2], self.t)?;
+                    self.x = self.p;
+                    self.r[2] = self.r[2].wrapping_sub(1);
+                }
+                0xa => self.q = false,
+                0xb => self.q = true,
+                0xc => {
+                    let m = self.immediate(memory)?;
+                    self.add(m, self.d, self.df);
+                }
+                0xd => {
+                    let m = self.immediate(memory)?;
+                    self.subtract(m, self.d, !self.df);
+                }
+                0xe => {
+                    let carry = self.df;
+                    self.df = self.d & 0x80 != 0;
+                    self.d = (self.d << 1) | carry as u8;
+                }
+                _ => {
+                    let m = self.immediate(memory)?;
+                    self.subtract(self.d, m, !self.df);
+                }
+            },
+            0x8 => self.d = self.r[n] as u8,
+            0x9 => self.d = (self.r[n] >> 8) as u8,
+            0xa => self.r[n] = (self.r[n] & 0xff00) | self.d as u16,
+            0xb => self.r[n] = (self.r[n] & 0x00ff) | ((self.d as u16) << 8),
+            0xc => {
+                let test = match n & 0x3 {
+                    0x0 => true,
+                    0x1 => self.q,
+                    0x2 => self.d == 0,
+                    _ => self.df,
+                };
+                match n {
+                    // NOP
+                    0x4 => {}
+                    // long branches, with the sense of the test flipped
+                    // for the top half
+                    0x0..=0x3 | 0x9..=0xb => {
+                        if test != (n & 0x8 != 0) {
+                            let hi = Self::read(memory, self.r[p])?;
+                            let lo = Self::read(memory, self.r[p].wrapping_add(1))?;
+                            self.r[p] = ((hi as u16) << 8) | lo as u16;
+                        } else {
+                            self.r[p] = self.r[p].wrapping_add(2);
+                        }
+                    }
+                    // long skips
+                    _ => {
+                        let skip = match n {
+                            0x8 => true,
+                            0xc => self.ie,
+                            0x5..=0x7 => !test,
+                            _ => test,
+                        };
+                        if skip {
+                            self.r[p] = self.r[p].wrapping_add(2);
+                        }
+                    }
+                }
+                return Ok(3);
+            }
+            0xd => self.p = n as u8,
+            0xe => self.x = n as u8,
+            // F6 and FE are the shifts, which don't take an operand
+            _ if n & 0x7 == 0x6 => {
+                if n == 0x6 {
+                    self.df = self.d & 1 != 0;
+                    self.d >>= 1;
+                } else {
+                    self.df = self.d & 0x80 != 0;
+                    self.d <<= 1;
+                }
+            }
+            _ => {
+                // the bottom half works on M(R(X)), the top half on the
+                // immediate byte
+                let m = match n {
+                    0x0..=0x7 => Self::read(memory, self.r[x])?,
+                    _ => self.immediate(memory)?,
+                };
+                match n & 0x7 {
+                    0x0 => self.d = m,
+                    0x1 => self.d |= m,
+                    0x2 => self.d &= m,
+                    0x3 => self.d ^= m,
+                    0x4 => self.add(m, self.d, false),
+                    0x5 => self.subtract(m, self.d, false),
+                    _ => self.subtract(self.d, m, false),
+                }
+            }
+        }
+        Ok(2)
+    }
+
+    /// respond to an interrupt request, if interrupts are enabled, returning
+    /// the machine cycles it took
+    pub fn interrupt(&mut self) -> usize {
+        if !self.ie {
+            return 0;
+        }
+        self.t = (self.x << 4) | self.p;
+        self.x = 2;
+        self.p = 1;
+        self.ie = false;
+        self.idle = false;
+        1
+    }
+
+    /// a DMA out cycle, for a device like the 1861 reading the byte at R0
+    pub fn dma_out(&mut self, memory: &impl MemoryMap) -> Result<u8, Chip8Error> {
+        let byte = Self::read(memory, self.r[0])?;
+        self.r[0] = self.r[0].wrapping_add(1);
+        self.idle = false;
+        Ok(byte)
+    }
+
+    /// run until done says so, returning the machine cycles taken. gives up
+    /// with an error after max_cycles, in case done never happens
+    pub fn run_until(
+        &mut self,
+        memory: &mut impl MemoryMap,
+        io: &mut impl Cdp1802Io,
+        max_cycles: usize,
+        done: impl Fn(&Self) -> bool,
+    ) -> Result<usize, Chip8Error> {
+        let mut cycles = 0;
+        while !done(self) {
+            if cycles >= max_cycles || self.idle {
+                return Err(Chip8Error::RunawayMachineCode {
+                    addr: self.r[self.p as usize],
+                });
+            }
+            cycles += self.step(memory, io)?;
+        }
+        Ok(cycles)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::Chip8MemoryMap;
+
+    /// run code at 0x300 with R3 as the program counter until it does a
+    /// SEP R4
+    fn run(code: &[u8]) -> Result<(Cdp1802, Chip8MemoryMap, usize), Chip8Error> {
+        let mut m = Chip8MemoryMap::new()?;
+        m.write(code, 0x300, code.len())?;
+        let mut cpu = Cdp1802::new();
+        cpu.p = 3;
+        cpu.r[3] = 0x300;
+        cpu.x = 2;
+        cpu.r[2] = 0x3ff;
+        let cycles = cpu.run_until(&mut m, &mut NoIo, 1000, |c| c.p == 4)?;
+        Ok((cpu, m, cycles))
+    }
+
+    #[test]
+    fn test_clock_rate() -> Result<(), Chip8Error> {
+        assert_eq!(ClockRate::parse("vip")?, ClockRate::VIP);
+        assert_eq!(ClockRate::parse("1.76064MHz")?, ClockRate::VIP);
+        assert_eq!(ClockRate::VIP.to_string(), "1.76064MHz");
+        // a machine cycle's 8 clocks
+        assert_eq!(ClockRate::VIP.cycle_ns(), 4543);
+        assert_eq!(ClockRate::parse("4")?.cycle_ns(), 2000);
+        assert!(ClockRate::parse("0").is_err());
+        assert!(ClockRate::parse("4")?.check().is_ok());
+        assert!(ClockRate { hertz: 0 }.check().is_err());
+        assert!(ClockRate::parse("fast").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_store() -> Result<(), Chip8Error> {
+        // LDI 42; PLO RA; LDI 03; PHI RA; LDI 7; STR RA; LDN RA; SEP R4
+        #[rustfmt::skip]
+        let (cpu, m, cycles) = run(&[
+            0xf8, 0x42, 0xaa, 0xf8, 0x03, 0xba, 0xf8, 0x07, 0x5a, 0x0a, 0xd4,
+        ])?;
+        assert_eq!(cpu.r[0xa], 0x342);
+        assert_eq!(m.get_ro_slice(0x342, 1)?, &[7]);
+        assert_eq!(cpu.d, 7);
+        assert_eq!(cycles, 2 * 8);
+        Ok(())
+    }
+
+    #[test]
+    fn test_arithmetic() -> Result<(), Chip8Error> {
+        // LDI ff; ADI 02; (D = 1, DF); ADCI 00 (D = 2); SMI 03 (D = ff, borrow)
+        let (cpu, _, _) = run(&[0xf8, 0xff, 0xfc, 0x02, 0x7c, 0x00, 0xff, 0x03, 0xd4])?;
+        assert_eq!(cpu.d, 0xff);
+        assert!(!cpu.df);
+        // LDI 81; SHL (D = 02, DF); SHRC (D = 81, DF)
+        let (cpu, _, _) = run(&[0xf8, 0x81, 0xfe, 0x76, 0xd4])?;
+        assert_eq!(cpu.d, 0x81);
+        assert!(!cpu.df);
+        // LDI 05; STXD; SDI 07 (D = 7 - 5)
+        let (cpu, _, _) = run(&[0xf8, 0x05, 0x73, 0xfd, 0x07, 0xd4])?;
+        assert_eq!(cpu.d, 2);
+        assert!(cpu.df);
+        assert_eq!(cpu.r[2], 0x3fe);
+        Ok(())
+    }
+
+    #[test]
+    fn test_branches() -> Result<(), Chip8Error> {
+        // LDI 03; PLO R1; DEC R1; GLO R1; BNZ 03; SEP R4
+        let (cpu, _, cycles) = run(&[0xf8, 0x03, 0xa1, 0x21, 0x81, 0x3a, 0x03, 0xd4])?;
+        assert_eq!(cpu.r[1], 0);
+        assert_eq!(cycles, 2 * (2 + 3 * 3 + 1));
+        // LBR 0310; ...; 0310: LSKP; LDI 1; SEP R4
+        let mut code = vec![0xc0, 0x03, 0x10];
+        code.resize(0x10, 0);
+        code.extend([0xc8, 0xf8, 0x01, 0xd4]);
+        let (cpu, _, cycles) = run(&code)?;
+        assert_eq!(cpu.d, 0);
+        assert_eq!(cycles, 3 + 3 + 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_mark_and_ret() -> Result<(), Chip8Error> {
+        // MARK (T = 23, stacked; X = 3); SEX R2; INC R2; RET (X = 2, P = 3);
+        // SEP R4
+        let (cpu, m, _) = run(&[0x79, 0xe2, 0x12, 0x70, 0xd4])?;
+        assert_eq!(cpu.t, 0x23);
+        assert_eq!(m.get_ro_slice(0x3ff, 1)?, &[0x23]);
+        assert_eq!((cpu.x, cpu.ie), (2, true));
+        Ok(())
+    }
+
+    #[test]
+    fn test_runaway() {
+        // BR to itself
+        assert!(matches!(
+            run(&[0x30, 0x00]),
+            Err(Chip8Error::RunawayMachineCode { addr: 0x300 })
+        ));
+    }
+}
@@ -0,0 +1,326 @@
+//! # chat plays
+//!
+//! a stream's chat playing the game between them. a bot sits in the chat
+//! (Twitch, Discord, wherever) and passes its lines on, either over TCP
+//! (--chat ADDR, and the bot connects) or down a pipe (--chat -); any line
+//! that says KEY 5 is a vote for key 5. votes are counted over a window of
+//! frames (--chat-window), and once it's up, the key with the most is held
+//! down for the whole of the next window, so it's the same however fast
+//! the chat's typing, and a replay of it plays back the same. a line that
+//! starts with who said it, e.g. alice KEY 5, only counts alice's latest
+//! vote in each window, so no one can win by spamming; ties go to the key
+//! voted for first. anything else in the chat is just talk, and ignored
+//!
+//! the same key winning twice running stays down (which is what a game
+//! wants for moving), and a window nobody votes in lets go. the keyboard
+//! still works, for whoever's streaming, whenever the chat's quiet, and
+//! with the chat on stdin, the pause menu's typed at the terminal rather
+//! than read off the pipe
+use crate::error::Chip8Error;
+use crate::input::{Feedback, Focus, Input, SlotRequest, SpeedRequest, Typed, VolumeRequest};
+use crate::keypad::KeyTransition;
+use std::io::{self, BufRead, BufReader};
+use std::net::TcpListener;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::Duration;
+
+/// frames a vote's counted over, if there's no --chat-window: half a
+/// second, so chat's got time to react
+pub const CHAT_DEFAULT_WINDOW: u32 = 30;
+
+/// who voted (if the line said) for which key, e.g. "alice KEY 5", or
+/// "KEY a". None if it's not a vote
+pub fn parse_vote(line: &str) -> Option<(Option<&str>, u8)> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let (voter, key) = match words[..] {
+        [k, key] if k.eq_ignore_ascii_case("key") => (None, key),
+        [voter, k, key] if k.eq_ignore_ascii_case("key") => (Some(voter), key),
+        _ => return None,
+    };
+    match key.len() {
+        1 => u8::from_str_radix(key, 16).ok().map(|k| (voter, k)),
+        _ => None,
+    }
+}
+
+/// chat lines from every bot that connects to listener, for as long as
+/// the program runs
+pub fn listen(listener: TcpListener) -> Receiver<String> {
+    let (send, lines) = mpsc::channel();
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let send = send.clone();
+            thread::spawn(move || {
+                // a bot that goes away just stops voting
+                for line in BufReader::new(stream).lines().map_while(Result::ok) {
+                    if send.send(line).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+    lines
+}
+
+/// chat lines piped in on stdin, until the pipe's closed. nothing else can
+/// read stdin after this, so anything else typed comes through
+/// input::read_line
+pub fn stdin() -> Receiver<String> {
+    let (send, lines) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines().map_while(Result::ok) {
+            if send.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    lines
+}
+
+/// keys voted for by chat, as well as from another input, which still gets
+/// asked about everything else (the menu, the speed and so on)
+pub struct ChatInput<'a> {
+    inner: &'a mut dyn Input,
+    lines: Receiver<String>,
+    window: u32,
+    // frames into the window
+    frame: u32,
+    // who voted for what, in the order they did
+    votes: Vec<(Option<String>, u8)>,
+    // last window's winner
+    held: Option<u8>,
+    transitions: Vec<KeyTransition>,
+    // the chat's gone, e.g. the pipe's been closed
+    closed: bool,
+}
+
+impl<'a> ChatInput<'a> {
+    pub fn new(inner: &'a mut dyn Input, lines: Receiver<String>, window: u32) -> Self {
+        ChatInput {
+            inner,
+            lines,
+            window: window.max(1),
+            frame: 0,
+            votes: Vec::new(),
+            held: None,
+            transitions: Vec::new(),
+            closed: false,
+        }
+    }
+
+    /// count line, if it's a vote
+    pub fn vote(&mut self, line: &str) {
+        let Some((voter, key)) = parse_vote(line) else {
+            return;
+        };
+        if let Some(voter) = voter {
+            self.votes.retain(|(v, _)| v.as_deref() != Some(voter));
+        }
+        self.votes.push((voter.map(str::to_string), key));
+    }
+
+    /// the key with the most votes so far this window, and how many it's got
+    pub fn leader(&self) -> Option<(u8, usize)> {
+        let mut tally: Vec<(u8, usize)> = Vec::new();
+        for (_, key) in &self.votes {
+            match tally.iter_mut().find(|(k, _)| k == key) {
+                Some((_, n)) => *n += 1,
+                None => tally.push((*key, 1)),
+            }
+        }
+        // the first of the most, as max_by_key would give the last
+        tally.into_iter().fold(None, |best, (k, n)| match best {
+            Some((_, most)) if most >= n => best,
+            _ => Some((k, n)),
+        })
+    }
+
+    /// read what's come in from chat since last time
+    fn catch_up(&mut self) {
+        loop {
+            match self.lines.try_recv() {
+                Ok(line) => self.vote(&line),
+                Err(TryRecvError::Empty) => return,
+                Err(TryRecvError::Disconnected) => {
+                    self.closed = true;
+                    return;
+                }
+            }
+        }
+    }
+}
+
+impl<'a> Input for ChatInput<'a> {
+    fn flush_keys(&mut self) -> Result<(), Chip8Error> {
+        // chat's key is down until the window's up
+        self.inner.flush_keys()
+    }
+
+    fn read_key(&mut self) -> Result<Option<u8>, Chip8Error> {
+        match self.held {
+            Some(key) if self.inner.focus() == Focus::Game => Ok(Some(key)),
+            _ => self.inner.read_key(),
+        }
+    }
+
+    fn tick(&mut self) -> Result<(), Chip8Error> {
+        self.catch_up();
+        self.inner.tick()?;
+        self.frame += 1;
+        let was = self.held;
+        if self.frame >= self.window {
+            self.held = self.leader().map(|(key, _)| key);
+            self.votes.clear();
+            self.frame = 0;
+        }
+        self.transitions = match (was, self.held) {
+            (Some(w), Some(h)) if w == h => vec![KeyTransition::Hold(h)],
+            (w, h) => w
+                .map(KeyTransition::Release)
+                .into_iter()
+                .chain(h.map(KeyTransition::Press))
+                .collect(),
+        };
+        self.transitions.extend(self.inner.transitions());
+        Ok(())
+    }
+
+    fn transitions(&self) -> &[KeyTransition] {
+        &self.transitions
+    }
+
+    fn out_of_keys(&self) -> bool {
+        self.closed && self.held.is_none() && self.inner.out_of_keys()
+    }
+
+    fn wait_for_event(&mut self, timeout: Duration) -> Result<bool, Chip8Error> {
+        self.inner.wait_for_event(timeout)
+    }
+
+    fn take_menu_request(&mut self) -> Result<bool, Chip8Error> {
+        self.inner.take_menu_request()
+    }
+
+    fn take_speed_request(&mut self) -> Result<Option<SpeedRequest>, Chip8Error> {
+        self.inner.take_speed_request()
+    }
+
+    fn take_hud_toggle(&mut self) -> Result<bool, Chip8Error> {
+        self.inner.take_hud_toggle()
+    }
+
+    fn take_help_toggle(&mut self) -> Result<bool, Chip8Error> {
+        self.inner.take_help_toggle()
+    }
+
+    fn take_volume_request(&mut self) -> Result<Option<VolumeRequest>, Chip8Error> {
+        self.inner.take_volume_request()
+    }
+
+    fn take_slot_request(&mut self) -> Result<Option<SlotRequest>, Chip8Error> {
+        self.inner.take_slot_request()
+    }
+
+    fn take_palette_request(&mut self) -> Result<bool, Chip8Error> {
+        self.inner.take_palette_request()
+    }
+
+    fn set_typing(&mut self, typing: bool) {
+        self.inner.set_typing(typing)
+    }
+
+    fn take_typed(&mut self) -> Result<Vec<Typed>, Chip8Error> {
+        self.inner.take_typed()
+    }
+
+    fn focus(&self) -> Focus {
+        self.inner.focus()
+    }
+
+    fn set_focus(&mut self, focus: Focus) {
+        self.inner.set_focus(focus)
+    }
+
+    fn feedback(&mut self, feedback: Feedback) {
+        self.inner.feedback(feedback)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::DummyInput;
+    use std::io::Write;
+    use std::net::TcpStream;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_parse_vote() {
+        assert_eq!(parse_vote("KEY 5"), Some((None, 5)));
+        assert_eq!(parse_vote("  key   A "), Some((None, 0xa)));
+        assert_eq!(parse_vote("alice KEY f"), Some((Some("alice"), 0xf)));
+        assert_eq!(parse_vote("KEY 10"), None);
+        assert_eq!(parse_vote("KEY g"), None);
+        assert_eq!(parse_vote("press key 5 please"), None);
+        assert_eq!(parse_vote("hello chat"), None);
+    }
+
+    #[test]
+    fn test_windows() -> Result<(), Chip8Error> {
+        let mut dummy = DummyInput::new(&[]);
+        let (send, lines) = mpsc::channel();
+        let mut chat = ChatInput::new(&mut dummy, lines, 3);
+        let mut frame = |votes: &[&str]| -> Result<_, Chip8Error> {
+            for v in votes {
+                send.send(v.to_string()).expect("chat's listening");
+            }
+            chat.tick()?;
+            Ok((chat.read_key()?, chat.transitions().to_vec()))
+        };
+        // nothing's down until the first window's counted
+        assert_eq!(frame(&["KEY 5", "KEY 6"])?, (None, vec![]));
+        assert_eq!(frame(&["KEY 6", "lol"])?, (None, vec![]));
+        assert_eq!(frame(&[])?, (Some(6), vec![KeyTransition::Press(6)]));
+
+        // alice changes her mind, and her spamming counts once; 4 and 5
+        // tie, and 4 was first
+        frame(&["alice KEY 5", "alice KEY 4", "alice KEY 4", "bob KEY 5"])?;
+        assert_eq!(frame(&[])?.0, Some(6));
+        let (key, transitions) = frame(&[])?;
+        assert_eq!(key, Some(4));
+        assert_eq!(
+            transitions,
+            [KeyTransition::Release(6), KeyTransition::Press(4)]
+        );
+
+        // the same winner stays down; a quiet window lets go
+        frame(&["KEY 4"])?;
+        frame(&[])?;
+        assert_eq!(frame(&[])?, (Some(4), vec![KeyTransition::Hold(4)]));
+        frame(&[])?;
+        frame(&[])?;
+        assert_eq!(frame(&[])?, (None, vec![KeyTransition::Release(4)]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_chat_over_tcp() -> Result<(), Chip8Error> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        let lines = listen(listener);
+        let mut dummy = DummyInput::new(&[]);
+        let mut chat = ChatInput::new(&mut dummy, lines, 1);
+        let mut bot = TcpStream::connect(addr)?;
+        writeln!(bot, "carol KEY b")?;
+        // the bot's line takes a moment to come through
+        let start = Instant::now();
+        while chat.read_key()?.is_none() && start.elapsed() < Duration::from_secs(5) {
+            thread::sleep(Duration::from_millis(1));
+            chat.tick()?;
+        }
+        assert_eq!(chat.read_key()?, Some(0xb));
+        Ok(())
+    }
+}
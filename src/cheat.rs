@@ -0,0 +1,177 @@
+use crate::error::Chip8Error;
+use crate::memory::{Chip8MemoryMap, MemoryMap};
+use crate::watch::MemoryPatch;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// hold a byte of memory at a value, e.g. to keep the lives counter topped
+/// up. stored per-ROM in the config
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Cheat {
+    pub addr: u16,
+    pub value: u8,
+    #[serde(default)]
+    pub enabled: bool,
+}
+
+/// decimal, or hex with a 0x prefix
+fn parse_number(s: &str) -> Option<u16> {
+    let s = s.trim();
+    match s.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+/// parse an address, e.g. "0x3a0" or "928"
+pub fn parse_addr(s: &str) -> Result<u16, Chip8Error> {
+    parse_number(s).ok_or_else(|| Chip8Error::ConfigError(format!("\"{}\" isn't an address", s)))
+}
+
+/// parse "addr=value", e.g. "0x3a0=3"
+pub fn parse_poke(s: &str) -> Result<(u16, u8), Chip8Error> {
+    let bad = || Chip8Error::ConfigError(format!("\"{}\" should look like 0x3a0=3", s));
+    let (addr, value) = s.split_once('=').ok_or_else(bad)?;
+    let addr = parse_number(addr).ok_or_else(bad)?;
+    let value = parse_number(value)
+        .and_then(|v| u8::try_from(v).ok())
+        .ok_or_else(bad)?;
+    Ok((addr, value))
+}
+
+/// parse "name" or "name=addr:value", e.g. "Infinite lives=0x3a0:3"
+pub fn parse_cheat(s: &str) -> Result<(String, Option<Cheat>), Chip8Error> {
+    match s.split_once('=') {
+        None => Ok((s.to_string(), None)),
+        Some((name, poke)) => {
+            let (addr, value) = parse_poke(&poke.replacen(':', "=", 1)).map_err(|_| {
+                Chip8Error::ConfigError(format!("\"{}\" should look like lives=0x3a0:3", s))
+            })?;
+            Ok((
+                name.to_string(),
+                Some(Cheat {
+                    addr,
+                    value,
+                    enabled: true,
+                }),
+            ))
+        }
+    }
+}
+
+/// applies cheats and pokes after every instruction, so whatever the
+/// program does, the frozen addresses never appear to change
+#[derive(Default)]
+pub struct CheatEngine {
+    cheats: BTreeMap<String, Cheat>,
+    pokes: Vec<(u16, u8)>,
+}
+
+impl CheatEngine {
+    pub fn new(cheats: BTreeMap<String, Cheat>) -> Self {
+        CheatEngine {
+            cheats,
+            pokes: Vec::new(),
+        }
+    }
+
+    pub fn cheats(&self) -> &BTreeMap<String, Cheat> {
+        &self.cheats
+    }
+
+    /// add a cheat, replacing any of the same name
+    pub fn insert(&mut self, name: &str, cheat: Cheat) {
+        self.cheats.insert(name.to_string(), cheat);
+    }
+
+    /// turn a cheat on or off, returning whether it's now on
+    pub fn toggle(&mut self, name: &str) -> Result<bool, Chip8Error> {
+        match self.cheats.get_mut(name) {
+            Some(c) => {
+                c.enabled = !c.enabled;
+                Ok(c.enabled)
+            }
+            None => Err(Chip8Error::ConfigError(format!(
+                "no cheat called \"{}\"",
+                name
+            ))),
+        }
+    }
+
+    /// write value to addr once, after the next instruction
+    pub fn poke(&mut self, addr: u16, value: u8) {
+        self.pokes.push((addr, value));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pokes.is_empty() && !self.cheats.values().any(|c| c.enabled)
+    }
+}
+
+impl MemoryPatch for CheatEngine {
+    fn after_instruction(&mut self, memory: &mut Chip8MemoryMap) -> Result<(), Chip8Error> {
+        for (addr, value) in self.pokes.drain(..) {
+            memory.get_rw_slice(addr, 1)?[0] = value;
+        }
+        for c in self.cheats.values().filter(|c| c.enabled) {
+            memory.get_rw_slice(c.addr, 1)?[0] = c.value;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() -> Result<(), Chip8Error> {
+        assert_eq!(parse_poke("0x3a0=3")?, (0x3a0, 3));
+        assert_eq!(parse_poke("928 = 0xff")?, (0x3a0, 0xff));
+        assert!(parse_poke("0x3a0=256").is_err());
+        assert!(parse_poke("0x3a0").is_err());
+        assert_eq!(parse_cheat("lives")?, ("lives".to_string(), None));
+        let (name, cheat) = parse_cheat("Infinite lives=0x3a0:3")?;
+        assert_eq!(name, "Infinite lives");
+        assert_eq!(
+            cheat,
+            Some(Cheat {
+                addr: 0x3a0,
+                value: 3,
+                enabled: true
+            })
+        );
+        assert!(parse_cheat("lives=0x3a0").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_freeze_and_poke() -> Result<(), Chip8Error> {
+        let mut cheats = BTreeMap::new();
+        cheats.insert(
+            "lives".to_string(),
+            Cheat {
+                addr: 0x300,
+                value: 9,
+                enabled: true,
+            },
+        );
+        let mut engine = CheatEngine::new(cheats);
+        engine.poke(0x301, 5);
+        let mut m = Chip8MemoryMap::new()?;
+        engine.after_instruction(&mut m)?;
+        assert_eq!(m.get_ro_slice(0x300, 2)?, &[9, 5]);
+
+        // pokes happen once; frozen values stay frozen
+        m.write(&[1, 1], 0x300, 2)?;
+        engine.after_instruction(&mut m)?;
+        assert_eq!(m.get_ro_slice(0x300, 2)?, &[9, 1]);
+
+        assert!(!engine.toggle("lives")?);
+        m.write(&[1], 0x300, 1)?;
+        engine.after_instruction(&mut m)?;
+        assert_eq!(m.get_ro_slice(0x300, 1)?, &[1]);
+        assert!(engine.toggle("nope").is_err());
+        Ok(())
+    }
+}
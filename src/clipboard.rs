@@ -0,0 +1,173 @@
+//! # sharing states as text
+//!
+//! a machine state as one line of text, to paste into a bug report or a
+//! chat rather than attach a file: the session's TOML in base64, after a
+//! prefix saying what it is. with --features clipboard, the pause menu's
+//! copy and paste go through the system clipboard (X11 or Wayland); without,
+//! copy prints the line and paste needs it given
+use crate::error::Chip8Error;
+
+/// what a shared state starts with, so a paste of something else is caught
+/// before it's decoded
+pub const SHARE_PREFIX: &str = "chip8-state:";
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// standard base64, with padding
+pub fn base64_encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = u32::from_be_bytes([0, b[0], b[1], b[2]]);
+        for i in 0..4 {
+            match i <= chunk.len() {
+                true => out.push(BASE64_ALPHABET[(n >> (18 - 6 * i)) as usize & 0x3f] as char),
+                false => out.push('='),
+            }
+        }
+    }
+    out
+}
+
+/// the other way, ignoring whitespace (which chat programs like to add)
+pub fn base64_decode(text: &str) -> Result<Vec<u8>, Chip8Error> {
+    let bad = || Chip8Error::ConfigError("that's not base64".to_string());
+    let digits = text
+        .bytes()
+        .filter(|c| !c.is_ascii_whitespace())
+        .take_while(|c| *c != b'=')
+        .map(|c| {
+            BASE64_ALPHABET
+                .iter()
+                .position(|a| *a == c)
+                .map(|d| d as u32)
+                .ok_or_else(bad)
+        })
+        .collect::<Result<Vec<u32>, Chip8Error>>()?;
+    if digits.len() % 4 == 1 {
+        return Err(bad());
+    }
+    let mut out = Vec::with_capacity(digits.len() * 3 / 4);
+    for chunk in digits.chunks(4) {
+        let n = chunk
+            .iter()
+            .chain([0, 0, 0].iter())
+            .take(4)
+            .fold(0, |n, d| n << 6 | d);
+        out.extend(&n.to_be_bytes()[1..chunk.len()]);
+    }
+    Ok(out)
+}
+
+/// for serde: bytes as base64 rather than a long list of numbers, e.g.
+/// `#[serde(with = "crate::clipboard::as_base64")]`. the long list of
+/// numbers is still read, as everything was written that way once
+pub mod as_base64 {
+    use super::{base64_decode, base64_encode};
+    use serde::{de, Deserializer, Serializer};
+    use std::fmt;
+
+    pub fn serialize<B: AsRef<[u8]>, S: Serializer>(bytes: &B, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&base64_encode(bytes.as_ref()))
+    }
+
+    pub fn deserialize<'de, T: From<Vec<u8>>, D: Deserializer<'de>>(d: D) -> Result<T, D::Error> {
+        d.deserialize_any(Bytes).map(T::from)
+    }
+
+    struct Bytes;
+
+    impl<'de> de::Visitor<'de> for Bytes {
+        type Value = Vec<u8>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("bytes as base64, or a list of numbers")
+        }
+
+        fn visit_str<E: de::Error>(self, text: &str) -> Result<Vec<u8>, E> {
+            base64_decode(text).map_err(E::custom)
+        }
+
+        fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<u8>, A::Error> {
+            let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+            while let Some(b) = seq.next_element()? {
+                bytes.push(b);
+            }
+            Ok(bytes)
+        }
+    }
+}
+
+/// put text on the system clipboard
+#[cfg(feature = "clipboard")]
+pub fn copy(text: &str) -> Result<(), Chip8Error> {
+    arboard::Clipboard::new()
+        .and_then(|mut c| c.set_text(text))
+        .map_err(|e| Chip8Error::ConfigError(format!("can't copy: {}", e)))
+}
+
+#[cfg(not(feature = "clipboard"))]
+pub fn copy(_text: &str) -> Result<(), Chip8Error> {
+    Err(Chip8Error::ConfigError(
+        "built without clipboard support (--features clipboard)".to_string(),
+    ))
+}
+
+/// what's on the system clipboard
+#[cfg(feature = "clipboard")]
+pub fn paste() -> Result<String, Chip8Error> {
+    arboard::Clipboard::new()
+        .and_then(|mut c| c.get_text())
+        .map_err(|e| Chip8Error::ConfigError(format!("can't paste: {}", e)))
+}
+
+#[cfg(not(feature = "clipboard"))]
+pub fn paste() -> Result<String, Chip8Error> {
+    Err(Chip8Error::ConfigError(
+        "built without clipboard support (--features clipboard), so paste needs the state given"
+            .to_string(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base64() -> Result<(), Chip8Error> {
+        assert_eq!(base64_encode(b"chip"), "Y2hpcA==");
+        assert_eq!(base64_encode(b"chip8"), "Y2hpcDg=");
+        assert_eq!(base64_encode(b"chip-8"), "Y2hpcC04");
+        for s in ["", "c", "ch", "chi", "chip-8 \u{1802}"] {
+            assert_eq!(base64_decode(&base64_encode(s.as_bytes()))?, s.as_bytes());
+        }
+        assert_eq!(base64_decode("Y2hp\n cA==")?, b"chip");
+        assert!(base64_decode("Y2h!").is_err());
+        assert!(base64_decode("Y2hpc").is_err());
+        Ok(())
+    }
+
+    #[cfg(feature = "full")]
+    #[test]
+    fn test_as_base64() {
+        #[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+        struct Rom {
+            #[serde(with = "as_base64")]
+            rom: Vec<u8>,
+        }
+        let rom = Rom {
+            rom: vec![0x00, 0xe0, 0x12, 0x00],
+        };
+        assert_eq!(toml::to_string(&rom).unwrap(), "rom = \"AOASAA==\"\n");
+        assert_eq!(toml::from_str::<Rom>("rom = \"AOASAA==\"").unwrap(), rom);
+        // and as it was written before
+        assert_eq!(toml::from_str::<Rom>("rom = [0, 224, 18, 0]").unwrap(), rom);
+        assert!(toml::from_str::<Rom>("rom = [0, 224, 256]").is_err());
+        assert!(toml::from_str::<Rom>("rom = \"AOA!\"").is_err());
+    }
+}
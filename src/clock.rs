@@ -0,0 +1,117 @@
+//! # keeping time
+//!
+//! the main loop runs each instruction as fast as it can, then sleeps off
+//! the rest of the time it would've taken on the VIP. how it tells the time
+//! and how it sleeps depends on the host: spinning gets closest on a desktop,
+//! wasm can't block at all, and tests don't want to wait
+use spin_sleep::SpinSleeper;
+use std::cell::Cell;
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub trait Clock {
+    /// how long since the clock started
+    fn now(&self) -> Duration;
+
+    /// wait for duration (or at least pretend to)
+    fn sleep(&self, duration: Duration);
+}
+
+/// the OS's sleep: easy on the CPU, but only as accurate as the scheduler
+pub struct SystemClock {
+    start: Instant,
+}
+
+impl SystemClock {
+    pub fn new() -> Self {
+        SystemClock {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+/// sleeps most of the way, then spins for the rest. accurate to within a
+/// machine cycle or so; this is what the main loop uses unless told otherwise
+pub struct SpinClock {
+    start: Instant,
+    sleeper: SpinSleeper,
+}
+
+impl SpinClock {
+    /// accuracy is how close the OS's sleep gets on its own, in ns
+    pub fn new(accuracy: u32) -> Self {
+        SpinClock {
+            start: Instant::now(),
+            sleeper: SpinSleeper::new(accuracy),
+        }
+    }
+}
+
+impl Clock for SpinClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.sleeper.sleep(duration);
+    }
+}
+
+/// time only passes when something sleeps or says so, so nothing ever waits.
+/// for tests, and for hosts (like wasm) that run a frame's worth at a time
+/// and do their own waiting
+#[derive(Default)]
+pub struct ManualClock {
+    now: Cell<Duration>,
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// let time pass without sleeping, e.g. as if the host were slow
+    pub fn advance(&self, duration: Duration) {
+        self.now.set(self.now.get() + duration);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        self.now.get()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manual_clock() {
+        let clock = ManualClock::new();
+        assert_eq!(clock.now(), Duration::ZERO);
+        clock.sleep(Duration::from_millis(5));
+        clock.advance(Duration::from_millis(2));
+        assert_eq!(clock.now(), Duration::from_millis(7));
+    }
+}
@@ -0,0 +1,388 @@
+use crate::cheat::Cheat;
+use crate::error::Chip8Error;
+use crate::paths;
+use crate::persist;
+use crate::quirks::Quirks;
+use crate::replay::Demo;
+use crate::sound::Volume;
+use crate::stats::Score;
+use crate::storage::Storage;
+use crate::window::Geometry;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// how many recently played ROMs to remember
+const RECENT_MAX: usize = 10;
+
+/// user configuration, persisted as TOML
+#[derive(Serialize, Deserialize, Default, Debug, PartialEq)]
+pub struct Config {
+    /// paths of the ROMs played lately, latest first
+    #[serde(default)]
+    pub recent: Vec<String>,
+    /// paths of ROMs the user's marked, to go at the top of the gallery
+    #[serde(default)]
+    pub favourites: Vec<String>,
+    /// how loud to beep, as a percentage; full if it's not set
+    #[serde(default)]
+    pub volume: Option<u8>,
+    /// which language to talk in, e.g. "fr"; the locale's if it's not set
+    #[serde(default)]
+    pub language: Option<String>,
+    /// what the interpreter's own work costs, e.g. "vip+interrupt=850"
+    /// (see the timing module); the VIP's if it's not set
+    #[serde(default)]
+    pub timing: Option<String>,
+    /// what the program's instructions cost, e.g. "flat" or
+    /// "vip+DXYN=200" (see the costs module); the VIP's if it's not set
+    #[serde(default)]
+    pub costs: Option<String>,
+    /// how fast the 1802's clock runs, in MHz, e.g. "3.52"; the VIP's
+    /// 1.76064 if it's not set
+    #[serde(default)]
+    pub clock_rate: Option<String>,
+    /// which of display::Theme::PRESETS to draw with, kept from changing it
+    /// while playing (see the settings module)
+    #[serde(default)]
+    pub theme: Option<String>,
+    /// what the terminal can do, where it's taken for something it isn't,
+    /// e.g. "256,unicode" (see the termcaps module); looked at if not
+    #[serde(default)]
+    pub terminal_caps: Option<String>,
+    /// XO-CHIP's four colours, as --plane-colours takes them
+    #[serde(default)]
+    pub plane_colours: Option<String>,
+    /// how a window fits the picture in, e.g. "integer" or "aspect+smooth"
+    /// (see the window module); whole multiples if it's not set
+    #[serde(default)]
+    pub scaling: Option<String>,
+    /// what to do if the emulator panics, "recover" or "unwind" (see the
+    /// recover module); recover if it's not set
+    #[serde(default)]
+    pub on_panic: Option<String>,
+    /// where the gui's window was left, to open it there again (before the
+    /// tables, but a table itself, as TOML needs)
+    #[serde(default)]
+    pub window: Option<Geometry>,
+    /// hotkey action -> the keys for it, separated by spaces, over the top
+    /// of the defaults (see the hotkey module)
+    #[serde(default)]
+    pub hotkeys: BTreeMap<String, String>,
+    /// frontend (as --frontend names it) -> how many frames to blend for
+    /// it, when --blend doesn't say (see the blend module)
+    #[serde(default)]
+    pub blend: BTreeMap<String, usize>,
+    /// per-ROM settings, keyed on rominfo::rom_name
+    #[serde(default)]
+    pub roms: BTreeMap<String, RomConfig>,
+}
+
+/// settings that only apply to one ROM
+#[derive(Serialize, Deserialize, Default, Debug, PartialEq)]
+pub struct RomConfig {
+    /// keep this one quiet, whatever the volume (before the tables, as
+    /// TOML needs)
+    #[serde(default)]
+    pub mute: bool,
+    /// the profile to play it with (see the profile module), by name
+    #[serde(default)]
+    pub profile: Option<String>,
+    /// the speed it was last kept at, over the profile's
+    #[serde(default)]
+    pub speed: Option<f64>,
+    /// host key -> COSMAC key, applied over the top of the default keymap
+    #[serde(default)]
+    pub keymap: BTreeMap<String, u8>,
+    /// cheats by name, and whether they're switched on
+    #[serde(default)]
+    pub cheats: BTreeMap<String, Cheat>,
+    /// someone playing it, recorded with --record-demo, for attract mode
+    #[serde(default)]
+    pub demo: Option<Demo>,
+    /// the quirks it was last kept with, over the profile's
+    #[serde(default)]
+    pub quirks: Option<Quirks>,
+    /// where it keeps its score, for the stats to keep the best, over the
+    /// ROM database's
+    #[serde(default)]
+    pub score: Option<Score>,
+}
+
+impl Config {
+    /// the volume for a ROM: the global level, muted if it's been muted
+    pub fn volume(&self, name: &str) -> Volume {
+        Volume {
+            level: self.volume.unwrap_or(Volume::default().level),
+            muted: self.roms.get(name).is_some_and(|r| r.mute),
+        }
+    }
+
+    /// remember the volume a ROM was left at: the level for all of them,
+    /// whether it's muted for just this one
+    pub fn set_volume(&mut self, name: &str, volume: Volume) {
+        self.volume = Some(volume.level);
+        if volume.muted || self.roms.contains_key(name) {
+            self.rom_mut(name).mute = volume.muted;
+        }
+    }
+
+    /// where the config lives if nobody says otherwise
+    pub fn default_path() -> PathBuf {
+        paths::config_file()
+    }
+
+    /// read config from a file, or give back the defaults if there isn't one
+    pub fn load(storage: &dyn Storage, path: &Path) -> Result<Self, Chip8Error> {
+        Ok(persist::load_text(storage, path, Self::from_toml)?.unwrap_or_default())
+    }
+
+    /// write config to a file, creating its directory if needed
+    pub fn save(&self, storage: &dyn Storage, path: &Path) -> Result<(), Chip8Error> {
+        persist::save_text(storage, path, &self.to_toml()?)
+    }
+
+    pub fn from_toml(s: &str) -> Result<Self, Chip8Error> {
+        toml::from_str(s).map_err(|e| Chip8Error::ConfigError(e.to_string()))
+    }
+
+    pub fn to_toml(&self) -> Result<String, Chip8Error> {
+        toml::to_string(self).map_err(|e| Chip8Error::ConfigError(e.to_string()))
+    }
+
+    /// settings for a ROM, creating an empty entry if there wasn't one
+    pub fn rom_mut(&mut self, name: &str) -> &mut RomConfig {
+        self.roms.entry(name.to_string()).or_default()
+    }
+
+    /// remember path as the latest ROM played
+    pub fn add_recent(&mut self, path: &str) {
+        self.recent.retain(|p| p != path);
+        self.recent.insert(0, path.to_string());
+        self.recent.truncate(RECENT_MAX);
+    }
+
+    /// mark path as a favourite, or unmark it if it was one. true if it's
+    /// a favourite now
+    pub fn toggle_favourite(&mut self, path: &str) -> bool {
+        match self.favourites.iter().position(|p| p == path) {
+            Some(i) => {
+                self.favourites.remove(i);
+                false
+            }
+            None => {
+                self.favourites.push(path.to_string());
+                true
+            }
+        }
+    }
+}
+
+impl RomConfig {
+    /// remap a host key to a COSMAC key for this ROM
+    pub fn remap(&mut self, host_key: char, key: u8) -> Result<(), Chip8Error> {
+        if key > 0xf {
+            return Err(Chip8Error::ConfigError(format!(
+                "{:#x} is not a COSMAC key",
+                key
+            )));
+        }
+        self.keymap.insert(host_key.to_string(), key);
+        Ok(())
+    }
+
+    /// the keymap overrides, validated into something the input can use
+    pub fn keymap_overrides(&self) -> Result<Vec<(char, u8)>, Chip8Error> {
+        keymap_overrides(&self.keymap)
+    }
+}
+
+/// a keymap table (host key -> COSMAC key), validated into something the
+/// input can use
+pub fn keymap_overrides(keymap: &BTreeMap<String, u8>) -> Result<Vec<(char, u8)>, Chip8Error> {
+    keymap
+        .iter()
+        .map(|(host, key)| {
+            let mut chars = host.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) if *key <= 0xf => Ok((c, *key)),
+                _ => Err(Chip8Error::ConfigError(format!(
+                    "bad keymap entry {:?} = {:#x}",
+                    host, key
+                ))),
+            }
+        })
+        .collect()
+}
+
+/// parse a "host=key" remapping, e.g. "j=4" or "j=0xa"
+pub fn parse_remap(s: &str) -> Result<(char, u8), Chip8Error> {
+    let bad = || Chip8Error::ConfigError(format!("expected <host key>=<COSMAC key>, got {:?}", s));
+    let (host, key) = s.split_once('=').ok_or_else(bad)?;
+    let mut chars = host.chars();
+    let host = match (chars.next(), chars.next()) {
+        (Some(c), None) => c,
+        _ => return Err(bad()),
+    };
+    let key = key.trim_start_matches("0x");
+    let key = u8::from_str_radix(key, 16).map_err(|_| bad())?;
+    if key > 0xf {
+        return Err(bad());
+    }
+    Ok((host, key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() -> Result<(), Chip8Error> {
+        let mut c = Config::default();
+        c.rom_mut("tetris").remap('j', 0x4)?;
+        let c2 = Config::from_toml(&c.to_toml()?)?;
+        assert_eq!(c, c2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_keymap() -> Result<(), Chip8Error> {
+        let c = Config::from_toml("[roms.brix.keymap]\nj = 4\nl = 0x6\n")?;
+        assert_eq!(
+            c.roms["brix"].keymap_overrides()?,
+            vec![('j', 0x4), ('l', 0x6)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_bad_keymap() -> Result<(), Chip8Error> {
+        let c = Config::from_toml("[roms.brix.keymap]\njk = 4\n")?;
+        assert!(c.roms["brix"].keymap_overrides().is_err());
+        let c = Config::from_toml("[roms.brix.keymap]\nj = 16\n")?;
+        assert!(c.roms["brix"].keymap_overrides().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_cheats() -> Result<(), Chip8Error> {
+        let mut c = Config::from_toml(
+            "[roms.brix.cheats]\n\"Infinite lives\" = { addr = 0x3a0, value = 3 }\n",
+        )?;
+        assert_eq!(
+            c.roms["brix"].cheats["Infinite lives"],
+            Cheat {
+                addr: 0x3a0,
+                value: 3,
+                enabled: false
+            }
+        );
+        c.rom_mut("brix").remap('j', 4)?;
+        assert_eq!(Config::from_toml(&c.to_toml()?)?, c);
+        Ok(())
+    }
+
+    #[test]
+    fn test_volume() -> Result<(), Chip8Error> {
+        let mut c = Config::from_toml("volume = 40\n[roms.brix]\nmute = true\n")?;
+        assert_eq!(c.volume("brix").audible(), 0);
+        assert_eq!(c.volume("tetris").audible(), 40);
+        c.set_volume("brix", c.volume("brix").louder());
+        assert_eq!(c.volume, Some(50));
+        // unmuting a ROM nobody's muted doesn't need an entry for it
+        c.set_volume("tetris", c.volume("tetris"));
+        assert!(!c.roms.contains_key("tetris"));
+        assert_eq!(Config::from_toml(&c.to_toml()?)?, c);
+        Ok(())
+    }
+
+    #[test]
+    fn test_demo() -> Result<(), Chip8Error> {
+        let mut c = Config::default();
+        c.rom_mut("brix").remap('j', 4)?;
+        c.rom_mut("brix").demo = Some(Demo::new(0x1234, &[None, Some(0x4), Some(0xa)]));
+        let toml = c.to_toml()?;
+        assert!(toml.contains("keys = \"-4a\""), "{}", toml);
+        let c2 = Config::from_toml(&toml)?;
+        assert_eq!(c2, c);
+        let demo = c2.roms["brix"].demo.as_ref().unwrap();
+        assert_eq!(demo.keys()?, [None, Some(0x4), Some(0xa)]);
+        let bad = Demo {
+            seed: 0,
+            keys: "-4x".to_string(),
+        };
+        assert!(bad.keys().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_kept_settings() -> Result<(), Chip8Error> {
+        let mut c = Config::default();
+        c.rom_mut("brix").remap('j', 4)?;
+        c.rom_mut("brix").speed = Some(2.0);
+        c.rom_mut("brix").quirks = Some(Quirks {
+            shift_vx: true,
+            ..Quirks::VIP
+        });
+        c.theme = Some("high-contrast".to_string());
+        assert_eq!(Config::from_toml(&c.to_toml()?)?, c);
+        Ok(())
+    }
+
+    #[test]
+    fn test_window() -> Result<(), Chip8Error> {
+        let mut c = Config::default();
+        c.rom_mut("brix").remap('j', 4)?;
+        c.scaling = Some("aspect".to_string());
+        c.on_panic = Some("unwind".to_string());
+        c.blend.insert("gui".to_string(), 3);
+        c.window = Some(Geometry {
+            x: -1920,
+            y: 40,
+            width: 1200,
+            height: 760,
+            fullscreen: true,
+        });
+        let toml = c.to_toml()?;
+        assert!(toml.contains("[window]"));
+        assert_eq!(Config::from_toml(&toml)?, c);
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty_config() -> Result<(), Chip8Error> {
+        assert_eq!(Config::from_toml("")?, Config::default());
+        Ok(())
+    }
+
+    #[test]
+    fn test_recent_and_favourites() -> Result<(), Chip8Error> {
+        let mut c = Config::default();
+        for n in 0..12 {
+            c.add_recent(&format!("{}.ch8", n));
+        }
+        c.add_recent("5.ch8");
+        assert_eq!(c.recent.len(), 10);
+        assert_eq!(c.recent[..3], ["5.ch8", "11.ch8", "10.ch8"]);
+
+        assert!(c.toggle_favourite("brix.ch8"));
+        assert!(c.toggle_favourite("pong.ch8"));
+        assert!(!c.toggle_favourite("brix.ch8"));
+        assert_eq!(c.favourites, ["pong.ch8"]);
+
+        // lists have to come before tables in TOML
+        c.rom_mut("brix").remap('j', 4)?;
+        assert_eq!(Config::from_toml(&c.to_toml()?)?, c);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_remap() {
+        assert_eq!(parse_remap("j=4").unwrap(), ('j', 0x4));
+        assert_eq!(parse_remap("j=0xa").unwrap(), ('j', 0xa));
+        assert_eq!(parse_remap("j=A").unwrap(), ('j', 0xa));
+        assert!(parse_remap("j=10").is_err());
+        assert!(parse_remap("jk=1").is_err());
+        assert!(parse_remap("j").is_err());
+    }
+}
@@ -0,0 +1,296 @@
+//! # the crash corpus
+//!
+//! inputs that have made the emulator panic, kept so they can't again
+//! without anyone noticing. `chip8 stress` hammering a ROM is how they're
+//! found: when it panics, the ROM, the seed and the keys held each frame
+//! are cut down to as little as still does it (the frames after it, the
+//! presses that don't matter, as much of the end of the ROM as can go),
+//! and kept in the data directory's corpus/. `chip8 selftest` plays every
+//! one back, along with the ones here from before (each a fix to the
+//! decoder or the memory layer), through the stable API as a frontend
+//! would, and fails any that still panic. a ROM stopping with an error is
+//! fine: it's what it's meant to do with something it can't run
+use crate::error::Chip8Error;
+use crate::paths;
+use crate::recover;
+use crate::replay::Demo;
+use crate::stable::{Config, Emulator, KeyEvent};
+use crate::storage::Storage;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// what the corpus started with: name, ROM in hex, quirks, whether it's
+/// for the SUPER-CHIP, and the keys held each frame (as a Demo has them)
+const BUILTIN: &[(&str, &str, &str, bool, &str)] = &[
+    ("empty", "", "vip", false, ""),
+    ("zeros", "0000", "vip", false, "--"),
+    ("odd length", "12", "vip", false, "-"),
+    ("fetch off the end", "1fff", "vip", false, "--"),
+    ("return with nothing called", "00ee", "vip", false, "-"),
+    ("calls without returning", "2200", "vip", false, "--"),
+    ("bcd at the top", "afff f033", "vip", false, "-"),
+    ("load at the top", "afff ff65", "modern", false, "-"),
+    ("store at the top", "aff8 ff55", "vip", false, "-"),
+    ("sprite off the end", "afff d01f", "vip", false, "-"),
+    ("big sprite off the end", "aff0 d000", "modern", true, "-"),
+    ("jump past the end", "60ff bfff", "vip", false, "-"),
+    ("key beyond f", "60ff e09e 1200", "vip", false, "5-5-"),
+    ("digit beyond f", "60ff f029 d005", "vip", false, "-"),
+    ("big digit beyond 9", "60ff f030 d00a", "vip", true, "-"),
+    ("flags beyond v7", "ff75 ff85", "vip", true, "-"),
+    ("scroll on and on", "00cf 00fb 1202", "vip", true, "----"),
+];
+
+/// something that once made the emulator panic, and how to have it again
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Case {
+    pub name: String,
+    /// what it did, when it was found
+    pub found: String,
+    #[serde(with = "crate::clipboard::as_base64")]
+    pub rom: Vec<u8>,
+    /// as --quirks takes them
+    pub quirks: String,
+    pub schip: bool,
+    /// the seed, and the key held each frame
+    pub demo: Demo,
+}
+
+impl Case {
+    /// the ones that came with the emulator
+    pub fn builtin() -> Vec<Self> {
+        BUILTIN
+            .iter()
+            .map(|(name, rom, quirks, schip, keys)| Case {
+                name: name.to_string(),
+                found: "a fix that came with the emulator".to_string(),
+                rom: hex(rom),
+                quirks: quirks.to_string(),
+                schip: *schip,
+                demo: Demo {
+                    seed: 0,
+                    keys: keys.to_string(),
+                },
+            })
+            .collect()
+    }
+
+    /// play it through a frame at a time, as a frontend would: how many
+    /// frames it got through, and what it panicked with, if it did
+    fn run(&self) -> Result<(u64, Option<String>), Chip8Error> {
+        let keys = self.demo.keys()?;
+        let config = Config {
+            quirks: self.quirks.clone(),
+            schip: self.schip,
+            seed: Some(self.demo.seed),
+        };
+        let mut frames = 0;
+        let run = recover::catch(|| {
+            // a ROM it won't run, or one that stops, is no panic
+            let mut emulator = match Emulator::new(&self.rom, &config) {
+                Ok(e) => e,
+                Err(_) => return Ok(()),
+            };
+            let mut held = None;
+            for key in &keys {
+                if *key != held {
+                    if let Some(k) = held {
+                        emulator.key(KeyEvent::Up(k));
+                    }
+                    if let Some(k) = key {
+                        emulator.key(KeyEvent::Down(*k));
+                    }
+                    held = *key;
+                }
+                if emulator.run_frame().is_err() {
+                    break;
+                }
+                frames += 1;
+            }
+            Ok(())
+        });
+        match run {
+            Ok(()) => Ok((frames, None)),
+            Err(Chip8Error::Panicked(s)) => Ok((frames, Some(s))),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// play it back, failing if it panics
+    pub fn replay(&self) -> Result<(), Chip8Error> {
+        match self.run()? {
+            (_, None) => Ok(()),
+            (frame, Some(s)) => Err(Chip8Error::TestFailure(format!(
+                "panicked in frame {}: {}",
+                frame, s
+            ))),
+        }
+    }
+
+    /// does it still panic?
+    pub fn panics(&self) -> bool {
+        self.panicked_in().is_some()
+    }
+
+    /// the frame it panics in, if it does
+    fn panicked_in(&self) -> Option<u64> {
+        match self.run() {
+            Ok((frame, Some(_))) => Some(frame),
+            _ => None,
+        }
+    }
+
+    /// as little of it as still panics: up to the frame it panicked in,
+    /// with the keys that don't matter let go, and as much of the end of
+    /// the ROM gone as can be. one that doesn't panic is left as it is
+    pub fn minimise(self) -> Self {
+        self.minimise_by(Case::panicked_in)
+    }
+
+    /// minimise, with panicked_in saying whether (and when) it panics
+    fn minimise_by(mut self, panicked_in: impl Fn(&Case) -> Option<u64>) -> Self {
+        let (Some(frame), Ok(mut keys)) = (panicked_in(&self), self.demo.keys()) else {
+            return self;
+        };
+        let seed = self.demo.seed;
+        keys.truncate(frame as usize + 1);
+        // let go of the keys a stretch at a time, halving the stretches
+        let mut stretch = keys.len().next_power_of_two();
+        while stretch > 0 {
+            for start in (0..keys.len()).step_by(stretch) {
+                let end = (start + stretch).min(keys.len());
+                if keys[start..end].iter().all(Option::is_none) {
+                    continue;
+                }
+                let mut fewer = keys.clone();
+                fewer[start..end].fill(None);
+                let tried = Case {
+                    demo: Demo::new(seed, &fewer),
+                    ..self.clone()
+                };
+                if panicked_in(&tried).is_some() {
+                    keys = fewer;
+                }
+            }
+            stretch /= 2;
+        }
+        self.demo = Demo::new(seed, &keys);
+        // and the end of the ROM, an instruction at a time at the least
+        let mut cut = self.rom.len().next_power_of_two();
+        while cut >= 2 {
+            let len = self.rom.len();
+            let tried = Case {
+                rom: self.rom[..len.saturating_sub(cut)].to_vec(),
+                ..self.clone()
+            };
+            match cut < len && panicked_in(&tried).is_some() {
+                true => self = tried,
+                false => cut /= 2,
+            }
+        }
+        self
+    }
+
+    pub fn from_toml(s: &str) -> Result<Self, Chip8Error> {
+        toml::from_str(s).map_err(|e| Chip8Error::ConfigError(e.to_string()))
+    }
+
+    pub fn to_toml(&self) -> Result<String, Chip8Error> {
+        toml::to_string(self).map_err(|e| Chip8Error::ConfigError(e.to_string()))
+    }
+
+    /// keep it in dir, named for itself
+    pub fn save(&self, storage: &dyn Storage, dir: &Path) -> Result<PathBuf, Chip8Error> {
+        let path = dir.join(format!("{}.toml", self.name));
+        storage.write(&path, self.to_toml()?.as_bytes())?;
+        Ok(path)
+    }
+
+    /// everything kept in dir, by name. any that can't be read are left
+    /// out, with a warning
+    pub fn list(storage: &dyn Storage, dir: &Path) -> Result<Vec<Self>, Chip8Error> {
+        let mut cases = Vec::new();
+        for path in storage.list(dir)? {
+            if path.extension().is_some_and(|e| e == "toml") {
+                let text = storage.read(&path)?.unwrap_or_default();
+                match Self::from_toml(&String::from_utf8_lossy(&text)) {
+                    Ok(c) => cases.push(c),
+                    Err(e) => eprintln!("Warning: skipping {}: {}", path.display(), e),
+                }
+            }
+        }
+        cases.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(cases)
+    }
+
+    /// where they're kept if nobody says otherwise
+    pub fn default_dir() -> PathBuf {
+        paths::corpus_dir()
+    }
+}
+
+/// the bytes spelt out in hex, with spaces between for reading
+fn hex(s: &str) -> Vec<u8> {
+    let digits: Vec<u8> = s
+        .chars()
+        .filter_map(|c| c.to_digit(16))
+        .map(|d| d as u8)
+        .collect();
+    digits
+        .chunks(2)
+        .map(|d| d.iter().fold(0, |b, d| b << 4 | d))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    #[test]
+    fn test_builtin() -> Result<(), Chip8Error> {
+        for case in Case::builtin() {
+            case.replay()?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_minimise() {
+        let case = Case {
+            name: "stress".to_string(),
+            found: String::new(),
+            rom: (0..64).collect(),
+            quirks: "vip".to_string(),
+            schip: false,
+            demo: Demo::new(
+                7,
+                &[Some(1), Some(7), None, Some(7), Some(2), Some(3), None],
+            ),
+        };
+        // as if it went wrong with 7 held in frame 3, with the first 10
+        // bytes of the ROM there
+        let panicked_in = |c: &Case| {
+            let keys = c.demo.keys().unwrap_or_default();
+            (keys.get(3) == Some(&Some(7)) && c.rom.len() >= 10).then_some(3)
+        };
+        let small = case.clone().minimise_by(panicked_in);
+        assert_eq!(small.demo, Demo::new(7, &[None, None, None, Some(7)]));
+        assert_eq!(small.rom, case.rom[..10]);
+        // and one that doesn't, as it is
+        assert_eq!(case.clone().minimise_by(|_| None), case);
+        assert!(!case.panics());
+    }
+
+    #[test]
+    fn test_save() -> Result<(), Chip8Error> {
+        let storage = MemoryStorage::default();
+        let dir = Path::new("corpus");
+        let case = Case::builtin().swap_remove(3);
+        let path = case.save(&storage, dir)?;
+        assert_eq!(path, dir.join("fetch off the end.toml"));
+        assert_eq!(Case::list(&storage, dir)?, [case]);
+        assert_eq!(hex("afff f0"), [0xaf, 0xff, 0xf0]);
+        Ok(())
+    }
+}
@@ -0,0 +1,162 @@
+//! # instruction costs
+//!
+//! how many machine cycles each of the program's instructions takes. the
+//! handlers work out what the VIP's interpreter spends on each (which can
+//! depend on the data: a skip taken, a page crossed, a sprite's rows and
+//! how far it's shifted), and the interpreter asks its CostModel what to
+//! charge for that instead. the VIP's own charges it as it is; other
+//! variants can have a table of their own, or one flat cost for every
+//! instruction, the way a modern interpreter runs so many instructions a
+//! frame whatever they are.
+//!
+//! the presets are "vip" and "flat" (every instruction costs nothing but
+//! its fetch, which the timing says; "flat=20" adds 20), and either can
+//! have instructions' costs given after it by their pattern in the isa
+//! table, e.g. --costs vip+DXYN=200, or costs = "flat+00E0=100" in the
+//! config. nobody's published what the HP48's interpreters spend on each
+//! instruction, so CHIP-48 and SCHIP ROMs run at the VIP's costs unless
+//! they're given a table like that
+use crate::error::Chip8Error;
+use crate::isa::{self, Opcode};
+
+/// what the interpreter charges for running an instruction
+pub trait CostModel {
+    /// machine cycles to charge for running (part of) inst, which took the
+    /// VIP's interpreter vip. instructions that run in parts (DXYN either
+    /// side of its interrupt, FX0A while it waits, anything split across
+    /// frames) are asked about each, with done only on the last
+    fn cost(&self, inst: u16, vip: usize, done: bool) -> usize;
+}
+
+/// the VIP's own costs, as the handlers work them out
+pub struct VipCosts;
+
+impl CostModel for VipCosts {
+    fn cost(&self, _inst: u16, vip: usize, _done: bool) -> usize {
+        vip
+    }
+}
+
+/// a preset, with any instructions' costs changed after it
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct Costs {
+    /// every instruction costs this, rather than the VIP's, if it's set
+    pub flat: Option<usize>,
+    /// instructions that cost something else, whichever the preset
+    pub table: Vec<(&'static Opcode, usize)>,
+}
+
+impl Costs {
+    /// the presets there are, by name
+    pub const PRESETS: [&'static str; 2] = ["vip", "flat"];
+
+    /// look up a preset by name, e.g. "flat" or "flat=20", with any
+    /// instructions' costs after it, e.g. "vip+DXYN=200+8XY4=20"
+    pub fn preset(name: &str) -> Result<Costs, Chip8Error> {
+        let mut parts = name.split('+');
+        let preset = parts.next().unwrap_or_default();
+        let mut costs = match preset.split_once('=') {
+            None if preset == "vip" => Costs::default(),
+            None if preset == "flat" => Costs::flat(0),
+            Some(("flat", cycles)) => Costs::flat(Self::cycles("flat", cycles)?),
+            _ => {
+                return Err(Chip8Error::ConfigError(format!(
+                    "no costs preset called \"{}\" (try {})",
+                    preset,
+                    Self::PRESETS.join(" or ")
+                )))
+            }
+        };
+        for figure in parts {
+            costs.set(figure)?;
+        }
+        Ok(costs)
+    }
+
+    /// every instruction costs cycles, on top of its fetch
+    pub fn flat(cycles: usize) -> Costs {
+        Costs {
+            flat: Some(cycles),
+            table: Vec::new(),
+        }
+    }
+
+    /// change one instruction's cost, given as e.g. "DXYN=200"
+    fn set(&mut self, figure: &str) -> Result<(), Chip8Error> {
+        let (pattern, cycles) = figure.split_once('=').unwrap_or((figure, ""));
+        let opcode = isa::ISA
+            .iter()
+            .find(|o| o.pattern.eq_ignore_ascii_case(pattern))
+            .ok_or_else(|| {
+                Chip8Error::ConfigError(format!(
+                    "no instruction \"{}\" to cost (try one from the isa table, e.g. DXYN)",
+                    pattern
+                ))
+            })?;
+        let cycles = Self::cycles(pattern, cycles)?;
+        self.table.retain(|(o, _)| *o != opcode);
+        self.table.push((opcode, cycles));
+        Ok(())
+    }
+
+    fn cycles(name: &str, cycles: &str) -> Result<usize, Chip8Error> {
+        cycles.parse().map_err(|_| {
+            Chip8Error::ConfigError(format!(
+                "{} needs a number of machine cycles, e.g. {}=20",
+                name, name
+            ))
+        })
+    }
+}
+
+impl CostModel for Costs {
+    fn cost(&self, inst: u16, vip: usize, done: bool) -> usize {
+        // the most specific match, as the decoder would find it
+        let given = isa::lookup(inst)
+            .and_then(|o| self.table.iter().find(|(t, _)| *t == o))
+            .map(|(_, c)| *c)
+            .or(self.flat);
+        match given {
+            // charged once, as the instruction finishes
+            Some(c) => c * done as usize,
+            None => vip,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_preset() -> Result<(), Chip8Error> {
+        assert_eq!(Costs::preset("vip")?, Costs::default());
+        assert_eq!(Costs::preset("flat")?, Costs::flat(0));
+        assert_eq!(Costs::preset("flat=20")?.flat, Some(20));
+        let costs = Costs::preset("vip+dxyn=200+DXYN=300+8XY4=20")?;
+        assert_eq!(costs.table.len(), 2);
+        assert!(Costs::preset("hp48").is_err());
+        assert!(Costs::preset("flat=lots").is_err());
+        assert!(Costs::preset("vip+DXYN").is_err());
+        assert!(Costs::preset("vip+ZZZZ=1").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_cost() -> Result<(), Chip8Error> {
+        // the VIP's as they are
+        assert_eq!(VipCosts.cost(0x6012, 6, true), 6);
+        assert_eq!(Costs::default().cost(0xd125, 298, false), 298);
+        // a flat cost once an instruction's done, however it went
+        let flat = Costs::preset("flat=20")?;
+        assert_eq!(flat.cost(0x6012, 6, true), 20);
+        assert_eq!(flat.cost(0xd125, 298, false), 0);
+        assert_eq!(flat.cost(0xd125, 276, true), 20);
+        // and the table's over either
+        let table = Costs::preset("vip+DXYN=200+6XNN=1")?;
+        assert_eq!(table.cost(0xd125, 276, true), 200);
+        assert_eq!(table.cost(0x6012, 6, true), 1);
+        assert_eq!(table.cost(0x7012, 10, true), 10);
+        Ok(())
+    }
+}
@@ -0,0 +1,303 @@
+//! # debugging from another thread
+//!
+//! a frontend with a debugger of its own (the gui's, say) can't get at the
+//! interpreter while it's running: it's on a thread of its own, and the
+//! interpreter's busy. so the two talk through a pair of ends instead:
+//!
+//! * the Debugger, which the emulator keeps. it shows the frontend the
+//!   machine (as a DebugState) after every frame, and every step while
+//!   it's paused, and keeps a trace of the last few instructions
+//! * the DebugLink, which the frontend keeps. it reads the DebugState, and
+//!   sends Controls back down a channel: pause, run, step an instruction
+//!   or a frame, or quit
+//!
+//! neither end knows anything about how the other's drawn, so a frontend
+//! that's a web page or a socket can use them just the same
+use crate::error::Chip8Error;
+use crate::interpreter::{Chip8Interpreter, InterpreterState};
+use crate::memory::MemoryMap;
+use crate::quirks::Quirks;
+use crate::trace::{TraceEntry, Tracer};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// how many of the last instructions the trace keeps
+pub const DEBUG_TRACE_LINES: usize = 64;
+
+/// what the frontend asks the emulator to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Control {
+    /// stop after this frame
+    Pause,
+    /// carry on, having been paused
+    Run,
+    /// run one instruction, while paused
+    Step,
+    /// run one frame, while paused
+    Frame,
+    /// stop for good
+    Quit,
+}
+
+/// the machine, as the emulator last showed it
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DebugState {
+    pub pc: u16,
+    pub i: u16,
+    pub v: [u8; 16],
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    /// return addresses, outermost call first
+    pub stack: Vec<u16>,
+    pub frames: u64,
+    /// all of it, the interpreter's own workings included
+    pub memory: Vec<u8>,
+    /// the last few instructions run, the latest last
+    pub trace: VecDeque<TraceEntry>,
+    pub paused: bool,
+    /// what went wrong stepping, if anything did
+    pub error: Option<String>,
+    /// how the instructions behave, to say what they do
+    pub quirks: Quirks,
+}
+
+/// what both ends can get at
+#[derive(Default)]
+struct Shared {
+    state: Mutex<DebugState>,
+    // the frontend's asked to pause (or quit) while it was running
+    pause: AtomicBool,
+    // tells the frontend there's something new to show
+    #[allow(clippy::type_complexity)]
+    on_change: Mutex<Option<Box<dyn Fn() + Send>>>,
+}
+
+impl Shared {
+    fn state(&self) -> MutexGuard<'_, DebugState> {
+        // a panic elsewhere doesn't make the state any less worth showing
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn changed(&self) {
+        if let Some(f) = self
+            .on_change
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .as_ref()
+        {
+            f();
+        }
+    }
+}
+
+/// a Debugger for the emulator, and the DebugLink to it for the frontend
+pub fn channel() -> (Debugger, DebugLink) {
+    let shared = Arc::new(Shared::default());
+    let (controls, received) = mpsc::channel();
+    (
+        Debugger {
+            shared: shared.clone(),
+            controls: received,
+        },
+        DebugLink { shared, controls },
+    )
+}
+
+/// the emulator's end
+pub struct Debugger {
+    shared: Arc<Shared>,
+    controls: Receiver<Control>,
+}
+
+impl Debugger {
+    /// something to add to the interpreter, to keep the trace
+    pub fn tracer(&self) -> DebugTracer {
+        DebugTracer(self.shared.clone())
+    }
+
+    /// has the frontend asked to pause since we last looked?
+    pub fn take_pause_request(&self) -> bool {
+        self.shared.pause.swap(false, Ordering::Relaxed)
+    }
+
+    /// show the frontend the machine as it is now
+    pub fn publish(&self, interpreter: &Chip8Interpreter) -> Result<(), Chip8Error> {
+        let memory = interpreter.memory();
+        let memory = memory.get_ro_slice(0, memory.size())?.to_vec();
+        {
+            let mut state = self.shared.state();
+            state.pc = interpreter.pc();
+            state.i = interpreter.i();
+            state.v = std::array::from_fn(|r| interpreter.v(r as u8));
+            state.delay_timer = interpreter.delay_timer();
+            state.sound_timer = interpreter.sound_timer();
+            state.stack = interpreter.stack()?;
+            state.frames = interpreter.frames();
+            state.memory = memory;
+            state.quirks = interpreter.quirks();
+        }
+        self.shared.changed();
+        Ok(())
+    }
+
+    /// stay paused, doing what the frontend asks, until it says to run
+    /// (true) or quit (false). it's taken as quitting if it's gone away
+    pub fn pause(&self, interpreter: &mut Chip8Interpreter) -> Result<bool, Chip8Error> {
+        self.set_paused(true);
+        self.publish(interpreter)?;
+        loop {
+            let result = match self.controls.recv() {
+                Ok(Control::Run) => {
+                    self.shared.pause.store(false, Ordering::Relaxed);
+                    self.set_paused(false);
+                    self.shared.changed();
+                    return Ok(true);
+                }
+                Ok(Control::Quit) | Err(_) => return Ok(false),
+                // already are
+                Ok(Control::Pause) => continue,
+                Ok(Control::Step) => interpreter.step(),
+                Ok(Control::Frame) => interpreter.run_frames(1),
+            };
+            match result {
+                // stepping into a fault's often the point, so it's shown
+                Err(e) if matches!(interpreter.state(), InterpreterState::Faulted { .. }) => {
+                    self.shared.state().error = Some(e.to_string())
+                }
+                r => r?,
+            }
+            self.publish(interpreter)?;
+        }
+    }
+
+    fn set_paused(&self, paused: bool) {
+        let mut state = self.shared.state();
+        state.paused = paused;
+        state.error = None;
+    }
+}
+
+/// keeps the trace for the frontend to show
+pub struct DebugTracer(Arc<Shared>);
+
+impl Tracer for DebugTracer {
+    fn trace(&mut self, entry: &TraceEntry) -> Result<(), Chip8Error> {
+        let mut state = self.0.state();
+        if state.trace.len() == DEBUG_TRACE_LINES {
+            state.trace.pop_front();
+        }
+        state.trace.push_back(entry.clone());
+        Ok(())
+    }
+}
+
+/// the frontend's end
+#[derive(Clone)]
+pub struct DebugLink {
+    shared: Arc<Shared>,
+    controls: Sender<Control>,
+}
+
+impl DebugLink {
+    /// the machine, as the emulator last showed it. it's held up until
+    /// this is let go, so don't keep it
+    pub fn state(&self) -> MutexGuard<'_, DebugState> {
+        self.shared.state()
+    }
+
+    /// ask the emulator to do something, when it next looks
+    pub fn send(&self, control: Control) {
+        if matches!(control, Control::Pause | Control::Quit) {
+            self.shared.pause.store(true, Ordering::Relaxed);
+        }
+        // nothing to do if the emulator's stopped
+        let _ = self.controls.send(control);
+    }
+
+    /// call f whenever there's something new to show, e.g. to have a
+    /// window redrawn
+    pub fn on_change(&self, f: impl Fn() + Send + 'static) {
+        *self
+            .shared
+            .on_change
+            .lock()
+            .unwrap_or_else(|e| e.into_inner()) = Some(Box::new(f));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::DummyDisplay;
+    use crate::input::DummyInput;
+    use crate::sound::Mute;
+    use std::sync::atomic::AtomicUsize;
+
+    #[test]
+    fn test_pause() -> Result<(), Chip8Error> {
+        let (debugger, link) = channel();
+        let changes = Arc::new(AtomicUsize::new(0));
+        let counted = changes.clone();
+        link.on_change(move || {
+            counted.fetch_add(1, Ordering::Relaxed);
+        });
+
+        // V0 += 1, for ever
+        let (mut display, mut input, mut sound) =
+            (DummyDisplay::new()?, DummyInput::new(&[]), Mute::new());
+        let mut tracer = debugger.tracer();
+        let mut interpreter = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+        interpreter.add_tracer(&mut tracer);
+        interpreter.load_program(&mut &[0x70, 0x01, 0x12, 0x00][..])?;
+
+        assert!(!debugger.take_pause_request());
+        link.send(Control::Pause);
+        assert!(debugger.take_pause_request());
+        for control in [Control::Step, Control::Step, Control::Step, Control::Run] {
+            link.send(control);
+        }
+        assert!(debugger.pause(&mut interpreter)?);
+        {
+            let state = link.state();
+            assert_eq!((state.pc, state.v[0]), (0x202, 2));
+            assert_eq!(state.trace.len(), 3);
+            assert!(!state.paused);
+        }
+        // when it paused, after each step, and when it carried on
+        assert_eq!(changes.load(Ordering::Relaxed), 5);
+
+        interpreter.run_frames(5)?;
+        debugger.publish(&interpreter)?;
+        assert_eq!(link.state().trace.len(), DEBUG_TRACE_LINES);
+        assert_eq!(&link.state().memory[0x200..0x204], [0x70, 0x01, 0x12, 0x00]);
+
+        // the frontend quitting, or going away, stops it
+        link.send(Control::Quit);
+        assert!(!debugger.pause(&mut interpreter)?);
+        drop(link);
+        assert!(!debugger.pause(&mut interpreter)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_step_waiting() -> Result<(), Chip8Error> {
+        // V0 := key, with no key ever coming, and a draw with no frame
+        // ever coming to draw it in: both steps come back
+        for (program, vsync) in [([0xf0, 0x0a], false), ([0xd0, 0x01], true)] {
+            let (debugger, link) = channel();
+            let (mut display, mut input, mut sound) =
+                (DummyDisplay::new()?, DummyInput::new(&[]), Mute::new());
+            let mut interpreter = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+            interpreter.load_program(&mut &program[..])?;
+            interpreter.set_host_vsync(vsync);
+            for control in [Control::Step, Control::Step, Control::Run] {
+                link.send(control);
+            }
+            assert!(debugger.pause(&mut interpreter)?);
+            assert_eq!(link.state().pc, 0x202);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,646 @@
+//! # decompile
+//!
+//! a ROM back into source the assembler can build, for changing games that
+//! only ever came as ROMs. it goes further than a disassembly: the analyser
+//! has worked out which words are code, so a skip over a jump forwards
+//! turns back into if ... begin (and else ... end), a jump backwards into
+//! loop ... again with whiles for the ways out, and the skips left over
+//! into if ... then. labels are named for what they point at and where:
+//! sub-2a4 for a subroutine, sprite-2a4 for something drawn (written out a
+//! row at a time in binary, so it can be edited by eye), and code-2a4 or
+//! data-2a4 for anything else.
+//!
+//! however it guesses, assembling what it writes gives back the same ROM,
+//! byte for byte. anything it can't put that way (an instruction the
+//! assembler hasn't a word for, a jump into the middle of another
+//! instruction) is written as numbers instead
+use crate::analyse;
+use crate::asm;
+use crate::isa;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// where programs start
+const DECOMPILE_ORIGIN: u16 = 0x200;
+
+/// how many bytes of data go on a line, when they're not a sprite
+const DECOMPILE_DATA_LINE: usize = 8;
+
+/// where structure stops being looked for: far enough short of the end of
+/// memory to look an instruction or two past anything before it. whatever's
+/// after (only ever in a ROM that fills memory) is written out as bytes
+const DECOMPILE_END: u16 = 0xfffa;
+
+/// a byte or two of the ROM, as it'll be written out
+#[derive(Clone, Copy)]
+enum Item {
+    Code(u16),
+    Byte(u8),
+}
+
+/// some instructions put back into one of the assembler's structures
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Shape {
+    /// if ... begin at a skip, maybe an else at a jump, and end at end
+    If {
+        at: u16,
+        otherwise: Option<u16>,
+        end: u16,
+    },
+    /// loop at start, and again at the jump back to it
+    Loop { start: u16, again: u16 },
+    /// a skip over a jump out of the innermost loop
+    While { at: u16 },
+}
+
+impl Shape {
+    /// the addresses it covers
+    fn span(&self) -> (u16, u16) {
+        match *self {
+            Shape::If { at, end, .. } => (at, end),
+            Shape::Loop { start, again } => (start, again + 2),
+            Shape::While { at } => (at, at + 4),
+        }
+    }
+
+    /// where other structures can go inside it
+    fn bodies(&self) -> Vec<(u16, u16)> {
+        match *self {
+            Shape::If {
+                at,
+                otherwise: None,
+                end,
+            } => vec![(at + 4, end)],
+            Shape::If {
+                at,
+                otherwise: Some(e),
+                end,
+            } => vec![(at + 4, e), (e + 2, end)],
+            Shape::Loop { start, again } => vec![(start, again)],
+            Shape::While { .. } => Vec::new(),
+        }
+    }
+
+    /// can both be written out, apart or one inside the other?
+    fn fits_with(&self, other: &Shape) -> bool {
+        let inside = |(start, end): (u16, u16), bodies: Vec<(u16, u16)>| {
+            bodies.iter().any(|(s, e)| *s <= start && end <= *e)
+        };
+        let (a, b) = (self.span(), other.span());
+        a.1 <= b.0 || b.1 <= a.0 || inside(a, other.bodies()) || inside(b, self.bodies())
+    }
+}
+
+/// lines of source, indented by how many blocks are open, with the data
+/// bytes waiting to go on the current one
+struct Writer {
+    lines: Vec<String>,
+    depth: usize,
+    data: Vec<String>,
+}
+
+impl Writer {
+    fn line(&mut self, text: &str) {
+        self.flush();
+        self.lines
+            .push(format!("{}{}", "  ".repeat(self.depth), text));
+    }
+
+    /// labels go at the start of the line, like the examples'
+    fn label(&mut self, name: &str) {
+        self.flush();
+        self.lines.push(format!(": {}", name));
+    }
+
+    fn flush(&mut self) {
+        if !self.data.is_empty() {
+            let data = std::mem::take(&mut self.data).join(" ");
+            self.lines
+                .push(format!("{}{}", "  ".repeat(self.depth), data));
+        }
+    }
+}
+
+/// rom as source, saying it came from name
+pub fn decompile(name: &str, rom: &[u8]) -> String {
+    let (rom, rest) = rom.split_at(rom.len().min((DECOMPILE_END - DECOMPILE_ORIGIN) as usize));
+    let a = analyse::analyse(rom);
+    let end = DECOMPILE_ORIGIN + rom.len() as u16;
+
+    // where each instruction or byte goes. instructions that overlap the
+    // one before can't be written as both, so they're left as bytes
+    let mut items = BTreeMap::new();
+    let mut addr = DECOMPILE_ORIGIN;
+    while addr < end {
+        match analyse::word_at(rom, addr) {
+            Some(inst) if a.code.contains(&addr) => {
+                items.insert(addr, Item::Code(inst));
+                addr += 2;
+            }
+            _ => {
+                items.insert(addr, Item::Byte(rom[(addr - DECOMPILE_ORIGIN) as usize]));
+                addr += 1;
+            }
+        }
+    }
+    let boundary = |addr: u16| items.contains_key(&addr) || addr == end;
+    let code = |addr: u16| match items.get(&addr) {
+        Some(Item::Code(inst)) => Some(*inst),
+        _ => None,
+    };
+    let jump_at = |addr: u16| {
+        code(addr)
+            .filter(|i| i & 0xf000 == 0x1000)
+            .map(|i| i & 0xfff)
+    };
+    // a skip that can be written as if ... begin or while
+    let skip_at = |addr: u16| {
+        code(addr).filter(|i| analyse::is_skip(*i) && condition(asm::negate(*i)).is_some())
+    };
+    // a structure that starts just after a skip would only be half skipped
+    let after_skip = |addr: u16| {
+        addr.checked_sub(2)
+            .and_then(code)
+            .is_some_and(analyse::is_skip)
+    };
+    let targets: BTreeSet<u16> = items
+        .values()
+        .filter_map(|item| match item {
+            Item::Code(inst) if matches!(inst & 0xf000, 0x1000 | 0x2000 | 0xa000 | 0xb000) => {
+                Some(inst & 0xfff)
+            }
+            _ => None,
+        })
+        .collect();
+
+    // loops first, outermost first, then ifs, and whiles once it's known
+    // which loop they're in. anything that won't nest with what's already
+    // there stays as it is
+    let mut shapes: Vec<Shape> = Vec::new();
+    let add = |shapes: &mut Vec<Shape>, shape: Shape| {
+        let fits = shapes.iter().all(|s| s.fits_with(&shape));
+        if fits {
+            shapes.push(shape);
+        }
+        fits
+    };
+    let mut loops: Vec<(u16, u16)> = items
+        .keys()
+        .filter_map(|j| Some((jump_at(*j)?, *j)))
+        .filter(|(start, again)| {
+            start <= again && *start >= DECOMPILE_ORIGIN && boundary(*start) && !after_skip(*again)
+        })
+        .collect();
+    loops.sort_by_key(|(start, again)| (*start, std::cmp::Reverse(*again)));
+    for (start, again) in loops {
+        add(&mut shapes, Shape::Loop { start, again });
+    }
+    for at in items.keys().copied().filter(|at| skip_at(*at).is_some()) {
+        let past = match jump_at(at + 2) {
+            Some(e) if e >= at + 4 && boundary(e) => e,
+            _ => continue,
+        };
+        if after_skip(at) || targets.contains(&(at + 2)) {
+            continue;
+        }
+        let otherwise = Some(past - 2).filter(|e| {
+            *e >= at + 4
+                && jump_at(*e).is_some_and(|f| f > past && boundary(f))
+                && !after_skip(*e)
+                && !targets.contains(e)
+        });
+        let with_else = otherwise.is_some_and(|e| {
+            let end = jump_at(e).unwrap_or_default();
+            add(&mut shapes, Shape::If { at, otherwise, end })
+        });
+        if !with_else {
+            add(
+                &mut shapes,
+                Shape::If {
+                    at,
+                    otherwise: None,
+                    end: past,
+                },
+            );
+        }
+    }
+    for at in items.keys().copied().filter(|at| skip_at(*at).is_some()) {
+        if after_skip(at) || targets.contains(&(at + 2)) {
+            continue;
+        }
+        let innermost = shapes
+            .iter()
+            .filter_map(|s| match s {
+                Shape::Loop { start, again } if *start <= at && at + 4 <= *again => {
+                    Some((*start, *again))
+                }
+                _ => None,
+            })
+            .min_by_key(|(start, again)| again - start);
+        if innermost.is_some_and(|(_, again)| jump_at(at + 2) == Some(again + 2)) {
+            add(&mut shapes, Shape::While { at });
+        }
+    }
+
+    // the jumps that are part of a structure don't need labels
+    let mut starts = BTreeMap::new();
+    let mut elses = BTreeSet::new();
+    let mut agains = BTreeSet::new();
+    let mut ends: BTreeMap<u16, usize> = BTreeMap::new();
+    let mut opens: BTreeMap<u16, usize> = BTreeMap::new();
+    let mut structural = BTreeSet::new();
+    for shape in &shapes {
+        match *shape {
+            Shape::If { at, otherwise, end } => {
+                starts.insert(at, *shape);
+                structural.insert(at + 2);
+                if let Some(e) = otherwise {
+                    elses.insert(e);
+                    structural.insert(e);
+                }
+                *ends.entry(end).or_default() += 1;
+            }
+            Shape::Loop { start, again } => {
+                agains.insert(again);
+                structural.insert(again);
+                *opens.entry(start).or_default() += 1;
+            }
+            Shape::While { at } => {
+                starts.insert(at, *shape);
+                structural.insert(at + 2);
+            }
+        }
+    }
+
+    // sprites get written a row at a time, if they're in the data
+    let sprites: BTreeMap<u16, (u16, u16)> = a
+        .sprites
+        .iter()
+        .filter(|(addr, _)| matches!(items.get(addr), Some(Item::Byte(_))))
+        .map(|(addr, size)| (*addr, *size))
+        .collect();
+
+    let mut labels = BTreeMap::from([(DECOMPILE_ORIGIN, "main".to_string())]);
+    for (addr, item) in &items {
+        match item {
+            Item::Code(inst)
+                if !structural.contains(addr)
+                    && matches!(inst & 0xf000, 0x1000 | 0x2000 | 0xa000 | 0xb000) =>
+            {
+                let target = inst & 0xfff;
+                if target < DECOMPILE_ORIGIN || !boundary(target) || labels.contains_key(&target) {
+                    continue;
+                }
+                let kind = if a.subroutines.contains_key(&target) {
+                    "sub"
+                } else if sprites.contains_key(&target) {
+                    "sprite"
+                } else if code(target).is_some() {
+                    "code"
+                } else {
+                    "data"
+                };
+                labels.insert(target, format!("{}-{:03x}", kind, target));
+            }
+            _ => {}
+        }
+    }
+    let target = |addr: u16| labels.get(&addr).cloned();
+
+    let mut w = Writer {
+        lines: vec![
+            format!(
+                "# {}, decompiled: the structure's guessed from its jumps,",
+                name
+            ),
+            "# and the labels are named for where they were".to_string(),
+            String::new(),
+        ],
+        depth: 1,
+        data: Vec::new(),
+    };
+    let mut sprite: Option<(u16, usize)> = None;
+    let mut skip_to = DECOMPILE_ORIGIN;
+    for addr in items.keys().copied().chain([end]) {
+        if addr < skip_to {
+            continue;
+        }
+        for _ in 0..ends.get(&addr).copied().unwrap_or_default() {
+            w.depth -= 1;
+            w.line("end");
+        }
+        if let Some(name) = labels.get(&addr) {
+            w.label(name);
+            sprite = sprites
+                .get(&addr)
+                .map(|(rows, width)| (addr.saturating_add(rows * width), *width as usize));
+        }
+        for _ in 0..opens.get(&addr).copied().unwrap_or_default() {
+            w.line("loop");
+            w.depth += 1;
+        }
+        let inst = match items.get(&addr) {
+            Some(Item::Code(inst)) => *inst,
+            Some(Item::Byte(b)) => {
+                let row = match sprite {
+                    Some((end, width)) if addr < end => Some(width),
+                    _ => None,
+                };
+                if row.is_none() && sprite.take().is_some() {
+                    w.flush();
+                }
+                w.data.push(match row {
+                    Some(_) => format!("0b{:08b}", b),
+                    None => format!("0x{:02x}", b),
+                });
+                if w.data.len() >= row.unwrap_or(DECOMPILE_DATA_LINE) {
+                    w.flush();
+                }
+                continue;
+            }
+            None => break,
+        };
+        match starts.get(&addr) {
+            Some(Shape::If { .. }) => {
+                let holds = condition(asm::negate(inst)).unwrap_or_default();
+                w.line(&format!("if {} begin", holds));
+                w.depth += 1;
+                skip_to = addr + 4;
+            }
+            Some(_) => {
+                let holds = condition(asm::negate(inst)).unwrap_or_default();
+                w.line(&format!("while {}", holds));
+                skip_to = addr + 4;
+            }
+            None if elses.contains(&addr) => {
+                w.depth -= 1;
+                w.line("else");
+                w.depth += 1;
+            }
+            None if agains.contains(&addr) => {
+                w.depth -= 1;
+                w.line("again");
+            }
+            None => match (condition(inst), statement(inst, &target)) {
+                (Some(holds), _) => {
+                    let next = addr + 2;
+                    // the statement skipped goes on the same line, if
+                    // there's nothing between them
+                    let then = code(next)
+                        .filter(|_| {
+                            !labels.contains_key(&next)
+                                && !ends.contains_key(&next)
+                                && !opens.contains_key(&next)
+                                && !structural.contains(&next)
+                                && !starts.contains_key(&next)
+                        })
+                        .and_then(|i| statement(i, &target));
+                    match then {
+                        Some(s) => {
+                            w.line(&format!("if {} then {}", holds, s));
+                            skip_to = addr + 4;
+                        }
+                        None => w.line(&format!("if {} then", holds)),
+                    }
+                }
+                (None, Some(s)) => w.line(&s),
+                (None, None) => w.line(&raw(inst)),
+            },
+        }
+    }
+    w.flush();
+    for line in rest.chunks(DECOMPILE_DATA_LINE) {
+        let bytes: Vec<_> = line.iter().map(|b| format!("0x{:02x}", b)).collect();
+        w.line(&bytes.join(" "));
+    }
+    w.lines.push(String::new());
+    w.lines.join("\n")
+}
+
+/// the condition an if ... then with skip in front of it tests, if there's
+/// a way to write it
+fn condition(skip: u16) -> Option<String> {
+    let (x, y, nn) = ((skip >> 8) & 0xf, (skip >> 4) & 0xf, skip & 0xff);
+    let text = match skip & 0xf000 {
+        0x3000 => format!("v{:x} != {}", x, nn),
+        0x4000 => format!("v{:x} == {}", x, nn),
+        0x5000 if skip & 0xf == 0 => format!("v{:x} != v{:x}", x, y),
+        0x9000 if skip & 0xf == 0 => format!("v{:x} == v{:x}", x, y),
+        _ => match skip & 0xf0ff {
+            0xe09e => format!("v{:x} -key", x),
+            0xe0a1 => format!("v{:x} key", x),
+            _ => return None,
+        },
+    };
+    Some(text)
+}
+
+/// inst as a statement, if it's one the assembler would make exactly. skips
+/// aren't: they need the statement after them
+fn statement(inst: u16, target: &dyn Fn(u16) -> Option<String>) -> Option<String> {
+    let (x, y, n, nn, nnn) = (
+        (inst >> 8) & 0xf,
+        (inst >> 4) & 0xf,
+        inst & 0xf,
+        inst & 0xff,
+        inst & 0xfff,
+    );
+    let address = || target(nnn).unwrap_or_else(|| format!("0x{:03x}", nnn));
+    let text = match inst & 0xf000 {
+        0x0000 => match inst {
+            0x00e0 => "clear".to_string(),
+            0x00ee => "return".to_string(),
+            0x00c0..=0x00cf => format!("scroll-down {}", n),
+            0x00fb => "scroll-right".to_string(),
+            0x00fc => "scroll-left".to_string(),
+            0x00fd => "exit".to_string(),
+            0x00fe => "lores".to_string(),
+            0x00ff => "hires".to_string(),
+            // the assembler doesn't do machine code
+            _ => return None,
+        },
+        0x1000 => format!("jump {}", address()),
+        // a call's just the label, so it needs one
+        0x2000 => target(nnn)?,
+        0x6000 => format!("v{:x} := {}", x, nn),
+        // adding the negative is how subtracting's written
+        0x7000 if nn >= 0x80 => format!("v{:x} -= {}", x, 0x100 - nn),
+        0x7000 => format!("v{:x} += {}", x, nn),
+        0x8000 => {
+            let op = match n {
+                0x0 => ":=",
+                0x1 => "|=",
+                0x2 => "&=",
+                0x3 => "^=",
+                0x4 => "+=",
+                0x5 => "-=",
+                0x6 => ">>=",
+                0x7 => "=-",
+                0xe => "<<=",
+                _ => return None,
+            };
+            format!("v{:x} {} v{:x}", x, op, y)
+        }
+        0xa000 => format!("i := {}", address()),
+        0xb000 => format!("jump0 {}", address()),
+        0xc000 => format!("v{:x} := random 0x{:02x}", x, nn),
+        0xd000 => format!("sprite v{:x} v{:x} {}", x, y, n),
+        0xf000 => match nn {
+            0x07 => format!("v{:x} := delay", x),
+            0x0a => format!("v{:x} := key", x),
+            0x15 => format!("delay := v{:x}", x),
+            0x18 => format!("buzzer := v{:x}", x),
+            0x1e => format!("i += v{:x}", x),
+            0x29 => format!("i := hex v{:x}", x),
+            0x30 => format!("i := bighex v{:x}", x),
+            0x33 => format!("bcd v{:x}", x),
+            0x55 => format!("save v{:x}", x),
+            0x65 => format!("load v{:x}", x),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some(text)
+}
+
+/// inst as its two bytes, saying what it does
+fn raw(inst: u16) -> String {
+    let [hi, lo] = inst.to_be_bytes();
+    match isa::explain(inst, None) {
+        Some(what) => format!("0x{:02x} 0x{:02x}  # {}", hi, lo, what),
+        None => format!("0x{:02x} 0x{:02x}", hi, lo),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Chip8Error;
+
+    /// decompile rom, check it builds back into rom, and hand over the
+    /// source without its header
+    fn round_trip(rom: &[u8]) -> Result<String, Chip8Error> {
+        let source = decompile("test.ch8", rom);
+        let rebuilt = asm::assemble("test.8o", &source)?;
+        assert_eq!(rebuilt.rom, rom, "{}", source);
+        Ok(source.lines().skip(3).collect::<Vec<_>>().join("\n"))
+    }
+
+    #[test]
+    fn test_structured() -> Result<(), Chip8Error> {
+        let program = asm::assemble(
+            "test.8o",
+            "\
+: main
+  v0 := 0
+  loop
+    v0 += 1
+    if v0 == 3 begin
+      v1 := key
+    else
+      v1 := 0
+    end
+    if v1 != 2 then v2 := random 0xf0
+    while v0 != 10
+    draw
+  again
+: draw
+  i := ball
+  sprite v0 v1 2
+  return
+: ball
+  0xf0 0x90 0x12 0x34",
+        )?;
+        assert_eq!(
+            round_trip(&program.rom)?,
+            "\
+: main
+  v0 := 0
+  loop
+    v0 += 1
+    if v0 == 3 begin
+      v1 := key
+    else
+      v1 := 0
+    end
+    if v1 != 2 then v2 := random 0xf0
+    while v0 != 10
+    sub-21a
+  again
+: sub-21a
+  i := sprite-220
+  sprite v0 v1 2
+  return
+: sprite-220
+  0b11110000
+  0b10010000
+  0x12 0x34"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_awkward() -> Result<(), Chip8Error> {
+        // a jump into the middle of an instruction
+        let rom = [0x30, 0x00, 0x12, 0x05, 0x60, 0x12, 0x12, 0x06];
+        assert_eq!(
+            round_trip(&rom)?,
+            "\
+: main
+  if v0 != 0 then jump 0x205
+  v0 := 18
+  loop
+  again"
+        );
+        // a call out of the ROM, a 5XY0 that isn't quite, and machine code
+        let rom = [0x23, 0x00, 0x51, 0x23, 0x60, 0x01, 0x01, 0x23, 0x12, 0x00];
+        assert_eq!(
+            round_trip(&rom)?,
+            "\
+: main
+  loop
+    0x23 0x00  # call subroutine at 0x300
+    0x51 0x23  # skip if V1 == V2
+    v0 := 1
+    0x01 0x23  # call 1802 machine code at 0x123 (not 000)
+  again"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_anything_round_trips() -> Result<(), Chip8Error> {
+        // whatever's in a ROM, it comes back the same. these are mostly
+        // jumps and skips around the ROM, to give it structure to find
+        let mut seed: u32 = 0x2468;
+        let mut random = |n: u32| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (seed >> 16) % n
+        };
+        for len in 0..300 {
+            let rom: Vec<u8> = (0..len)
+                .flat_map(|_| {
+                    let to = 0x200 + random(len + 1) as u16 * 2 + (random(8) == 0) as u16;
+                    let word = match random(6) {
+                        0 => 0x1000 | to,
+                        1 => 0x2000 | to,
+                        2 => 0x3000 | random(2) as u16,
+                        3 => 0x6000 | random(0x1000) as u16,
+                        _ => random(0x10000) as u16,
+                    };
+                    word.to_be_bytes()
+                })
+                .collect();
+            round_trip(&rom)?;
+        }
+        // right up to the end of what the assembler fills, skips and all
+        let mut rom = [0x3f, 0x01].repeat((0x1000 - 0x200) / 2);
+        rom.splice(rom.len() - 4.., [0x12, 0x00, 0x3f, 0x01]);
+        round_trip(&rom)?;
+        // and the end of memory, and past it, which won't assemble, but is
+        // still written out
+        let mut rom = [0x3f, 0x01].repeat((0x10000 - 0x200) / 2);
+        rom.extend([0x12, 0x34]);
+        assert!(decompile("test.ch8", &rom).ends_with("0x12 0x34\n"));
+        Ok(())
+    }
+}
@@ -0,0 +1,207 @@
+//! # guessing which quirks a ROM needs
+//!
+//! two passes. first, look through the code for habits that only make sense
+//! one way (e.g. stepping I with FX1E after FX55 means the author didn't
+//! expect FX55 to move it). then, run the ROM for a few seconds under each
+//! profile: one that crashes under a profile probably wasn't written for it
+use crate::display::DummyDisplay;
+use crate::error::Chip8Error;
+use crate::input::DummyInput;
+use crate::interpreter::Chip8Interpreter;
+use crate::quirks::Quirks;
+use crate::sound::Mute;
+use std::fmt;
+
+/// how long the probe runs go on for
+const DETECT_PROBE_FRAMES: u64 = 300;
+/// a crash counts for more than any one habit
+const DETECT_CRASH_WEIGHT: u32 = 3;
+/// how far after FX55/FX65 to look for what happens to I next
+const DETECT_LOOKAHEAD: usize = 8;
+
+/// what detect thinks, and why
+#[derive(Debug, PartialEq)]
+pub struct QuirkGuess {
+    pub quirks: Quirks,
+    pub reasons: Vec<String>,
+}
+
+impl fmt::Display for QuirkGuess {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match Quirks::PROFILES.iter().find(|(_, q)| *q == self.quirks) {
+            Some((name, _)) => writeln!(f, "looks like it wants --quirks {}", name)?,
+            None => writeln!(f, "looks like it wants {:?}", self.quirks)?,
+        }
+        if self.reasons.is_empty() {
+            writeln!(f, "  (nothing to go on, so guessing)")?;
+        }
+        for r in &self.reasons {
+            writeln!(f, "  {}", r)?;
+        }
+        Ok(())
+    }
+}
+
+/// votes for and against a quirk
+#[derive(Default)]
+struct Votes {
+    vip: u32,
+    modern: u32,
+}
+
+impl Votes {
+    fn modern(&self) -> bool {
+        self.modern > self.vip
+    }
+}
+
+/// guess the quirks rom needs. with no evidence either way, it's the VIP's
+pub fn detect(rom: &[u8]) -> Result<QuirkGuess, Chip8Error> {
+    let mut shift = Votes::default();
+    let mut load_store = Votes::default();
+    let mut reasons = Vec::new();
+
+    let words: Vec<u16> = rom
+        .chunks_exact(2)
+        .map(|w| u16::from_be_bytes([w[0], w[1]]))
+        .collect();
+    for (n, inst) in words.iter().enumerate() {
+        let addr = 0x200 + 2 * n;
+        let (x, y) = ((inst >> 8) & 0xf, (inst >> 4) & 0xf);
+        match inst & 0xf00f {
+            // shifting "VX by V0" is how assemblers that ignore VY write it
+            0x8006 | 0x800e if x != y && y == 0 => {
+                shift.modern += 1;
+                reasons.push(format!(
+                    "{:04x}: {:04x} looks like a shift in place",
+                    addr, inst
+                ));
+            }
+            0x8006 | 0x800e if x != y => {
+                shift.vip += 1;
+                reasons.push(format!(
+                    "{:04x}: {:04x} shifts one register into another",
+                    addr, inst
+                ));
+            }
+            _ => {}
+        }
+        if inst & 0xf0ff == 0xf055 || inst & 0xf0ff == 0xf065 {
+            // what's the next thing to happen to I?
+            let next = words[n + 1..]
+                .iter()
+                .take(DETECT_LOOKAHEAD)
+                .take_while(|i| !matches!(*i & 0xf000, 0x1000 | 0xb000) && **i != 0x00ee)
+                .find(|i| {
+                    *i & 0xf000 == 0xa000
+                        || *i & 0xf000 == 0xd000
+                        || matches!(*i & 0xf0ff, 0xf01e | 0xf029 | 0xf033 | 0xf055 | 0xf065)
+                });
+            match next.map(|i| (i & 0xf000, i & 0xf0ff)) {
+                Some((_, 0xf01e)) => {
+                    load_store.modern += 1;
+                    reasons.push(format!(
+                        "{:04x}: {:04x} is followed by stepping I",
+                        addr, inst
+                    ));
+                }
+                Some((_, 0xf055 | 0xf065 | 0xf033)) | Some((0xd000, _)) => {
+                    load_store.vip += 1;
+                    reasons.push(format!(
+                        "{:04x}: {:04x} is followed by using I as left",
+                        addr, inst
+                    ));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // then see what survives
+    let vip = probe(rom, Quirks::VIP);
+    let modern = probe(rom, Quirks::MODERN);
+    match (vip, modern) {
+        (Err(e), Ok(())) => {
+            shift.modern += DETECT_CRASH_WEIGHT;
+            load_store.modern += DETECT_CRASH_WEIGHT;
+            reasons.push(format!("crashed with the VIP's quirks: {}", e));
+        }
+        (Ok(()), Err(e)) => {
+            shift.vip += DETECT_CRASH_WEIGHT;
+            load_store.vip += DETECT_CRASH_WEIGHT;
+            reasons.push(format!("crashed with modern quirks: {}", e));
+        }
+        _ => {}
+    }
+
+    Ok(QuirkGuess {
+        quirks: Quirks {
+            shift_vx: shift.modern(),
+            load_store_leaves_i: load_store.modern(),
+            // nothing in the code gives these away, but a ROM written for a
+            // later interpreter will have been run (and listened to) on one
+            logic_leaves_vf: shift.modern() || load_store.modern(),
+            short_tone: shift.modern() || load_store.modern(),
+            // and nobody's guessing at the rarer ones
+            ..Quirks::VIP
+        },
+        reasons,
+    })
+}
+
+/// run rom for a few seconds with no keys pressed, to see if it crashes
+fn probe(rom: &[u8], quirks: Quirks) -> Result<(), Chip8Error> {
+    let mut display = DummyDisplay;
+    let mut input = DummyInput::new(&[]);
+    let mut sound = Mute::new();
+    let mut machine = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+    machine.set_seed(0);
+    machine.set_quirks(quirks);
+    machine.load_program(&mut &rom[..])?;
+    machine.run_frames(DETECT_PROBE_FRAMES)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nothing_to_go_on() -> Result<(), Chip8Error> {
+        let guess = detect(&[0x60, 0x01, 0x12, 0x02])?;
+        assert_eq!(guess.quirks, Quirks::VIP);
+        assert!(guess.reasons.is_empty());
+        assert!(guess
+            .to_string()
+            .starts_with("looks like it wants --quirks vip"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_habits() -> Result<(), Chip8Error> {
+        // i = 0x300; save v0-v1; i += v2; save v0-v1; v3 >>= "v0"; stop
+        #[rustfmt::skip]
+        let rom = [
+            0xa3, 0x00, 0xf1, 0x55, 0xf2, 0x1e, 0xf1, 0x55,
+            0x83, 0x06, 0x12, 0x0a,
+        ];
+        let guess = detect(&rom)?;
+        assert_eq!(guess.quirks, Quirks::MODERN);
+        assert_eq!(guess.reasons.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_crashes() -> Result<(), Chip8Error> {
+        // i = 0x20a; load v0; illegal instruction unless v0 is 0; repeat.
+        // only the VIP's version moves on to the 0xff
+        #[rustfmt::skip]
+        let rom = [
+            0xa2, 0x0a, 0xf0, 0x65, 0x30, 0x00, 0x80, 0x08,
+            0x12, 0x02, 0x00, 0xff,
+        ];
+        let guess = detect(&rom)?;
+        assert!(guess.quirks.load_store_leaves_i);
+        assert!(guess.reasons.iter().any(|r| r.starts_with("crashed")));
+        Ok(())
+    }
+}
@@ -0,0 +1,515 @@
+//! # diagnostics
+//!
+//! `chip8 diag display|input|audio|av`: quick checks that the terminal
+//! draws, the keyboard's being read and mapped as expected, the speaker
+//! works, and the picture and the sound keep together, without needing a
+//! ROM that exercises them
+use crate::asm;
+use crate::clock::Clock;
+use crate::display::{Display, CHIP8_TEST_CARD};
+use crate::error::Chip8Error;
+use crate::input::{Input, Keymap};
+use crate::interpreter::{Chip8Interpreter, RunOutcome};
+use crate::keypad::KeyTransition;
+use crate::sound::{Sound, Volume};
+use beep::beep;
+use std::fmt;
+use std::io::Write;
+use std::ops::Range;
+use std::time::Duration;
+
+/// how long a frame is, while the diagnostics run
+const DIAG_FRAME: Duration = Duration::from_micros(1_000_000 / 60);
+/// the pitches diag audio goes through, in Hz
+const DIAG_AUDIO_PITCHES: [u16; 6] = [262, 523, 1047, 2093, 4186, 0];
+/// and how long it plays each one for
+const DIAG_AUDIO_NOTE: Duration = Duration::from_millis(400);
+/// how many times diag av flashes and beeps
+const DIAG_AV_FLASHES: usize = 10;
+/// and how many frames apart, as AV_SYNC_SOURCE has it
+const DIAG_AV_PERIOD: usize = 60;
+
+/// the COSMAC keypad, as laid out on the VIP
+pub const KEYPAD_LAYOUT: [[u8; 4]; 4] = [
+    [0x1, 0x2, 0x3, 0xc],
+    [0x4, 0x5, 0x6, 0xd],
+    [0x7, 0x8, 0x9, 0xe],
+    [0xa, 0x0, 0xb, 0xf],
+];
+
+/// a 64x32 picture of the keypad: a 16x8 box per key, filled in if it's
+/// the one pressed
+pub fn keypad_frame(pressed: Option<u8>) -> Vec<u8> {
+    let mut frame = vec![0; 0x100];
+    for (row, keys) in KEYPAD_LAYOUT.iter().enumerate() {
+        for (column, key) in keys.iter().enumerate() {
+            let lit = pressed == Some(*key);
+            for y in row * 8..row * 8 + 7 {
+                for x in column * 16..column * 16 + 15 {
+                    let edge = y == row * 8 || y == row * 8 + 6 || x % 16 == 0 || x % 16 == 14;
+                    if lit || edge {
+                        frame[y * 8 + x / 8] |= 0x80 >> (x % 8);
+                    }
+                }
+            }
+        }
+    }
+    frame
+}
+
+/// draws the keypad the way keypad_frame does, waits for a key with FX0A,
+/// then fills in each key while EX9E says it's held. hand-assembled, like
+/// the gallery's
+#[rustfmt::skip]
+pub const KEYPAD_TEST_ROM: [u8; 154] = [
+    0x64, 0x00,                                     // 200: key = 0
+    0x22, 0x48, 0xa2, 0x52, 0xd0, 0x17,             // 202: outline it
+    0x70, 0x08, 0xa2, 0x59, 0xd0, 0x17,
+    0x74, 0x01, 0x34, 0x10, 0x12, 0x02,             // 20e: next key
+    0xf2, 0x0a,                                     // 214: wait for a key
+    0x64, 0x00,                                     // 216: key = 0
+    0x63, 0x01, 0xe4, 0x9e, 0x63, 0x00,             // 218: held = EX9E
+    0xa2, 0x8a, 0xf4, 0x1e, 0xf0, 0x65,             // 21e: was it?
+    0x50, 0x30, 0x22, 0x30,                         // 224: fill if not
+    0x74, 0x01, 0x34, 0x10, 0x12, 0x18, 0x12, 0x16, // 228: next key
+    0xa2, 0x8a, 0xf4, 0x1e, 0x80, 0x30, 0xf0, 0x55, // 230: fill: note it
+    0x22, 0x48, 0x71, 0x01,                         // 238: flip inside
+    0xa2, 0x60, 0xd0, 0x15,
+    0x70, 0x08, 0xa2, 0x65, 0xd0, 0x15,
+    0x00, 0xee,
+    0xa2, 0x6a, 0xf4, 0x1e, 0xf4, 0x1e, 0xf1, 0x65, // 248: v0, v1 = key's
+    0x00, 0xee,                                     //      x, y
+    0xff, 0x80, 0x80, 0x80, 0x80, 0x80, 0xff,       // 252: outline
+    0xfe, 0x02, 0x02, 0x02, 0x02, 0x02, 0xfe,
+    0x7f, 0x7f, 0x7f, 0x7f, 0x7f,                   // 260: inside
+    0xfc, 0xfc, 0xfc, 0xfc, 0xfc,
+    0x10, 0x18, 0x00, 0x00, 0x10, 0x00, 0x20, 0x00, // 26a: where keys go
+    0x00, 0x08, 0x10, 0x08, 0x20, 0x08, 0x00, 0x10,
+    0x10, 0x10, 0x20, 0x10, 0x00, 0x18, 0x20, 0x18,
+    0x30, 0x00, 0x30, 0x08, 0x30, 0x10, 0x30, 0x18,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // 28a: which are held
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+/// lights a block in the middle of the screen in the same frame as it
+/// starts a beep, for a tenth of a second every second, so any gap between
+/// seeing one and hearing the other is the host's doing
+pub const AV_SYNC_SOURCE: &str = "
+: main
+  i := block
+  v0 := 28
+  v1 := 8
+  # how long the flash and the beep last, and the gap till the next
+  v2 := 6
+  v3 := 54
+: flash
+  # the sprite waits for the interrupt and goes out with the next one, as
+  # does the beep's first frame
+  sprite v0 v1 15
+  buzzer := v2
+  delay := v2
+  loop
+    v4 := delay
+    while v4 != 0
+  again
+  sprite v0 v1 15
+  delay := v3
+  loop
+    v4 := delay
+    while v4 != 0
+  again
+  jump flash
+: block
+  0xff 0xff 0xff 0xff 0xff 0xff 0xff 0xff
+  0xff 0xff 0xff 0xff 0xff 0xff 0xff
+";
+
+/// AV_SYNC_SOURCE, assembled
+pub fn av_sync_rom() -> Result<Vec<u8>, Chip8Error> {
+    Ok(asm::assemble("av-sync", AV_SYNC_SOURCE)?.rom)
+}
+
+/// which host keys map to a COSMAC key, e.g. "4 (Q)", or "4 (unmapped)"
+pub fn describe_key(key: u8, keymap: &Keymap) -> String {
+    let mut host: Vec<String> = keymap
+        .iter()
+        .filter(|(_, k)| **k == key)
+        .map(|(c, _)| c.to_ascii_uppercase().to_string())
+        .collect();
+    host.sort();
+    match host.len() {
+        0 => format!("{:X} (unmapped)", key),
+        _ => format!("{:X} ({})", key, host.join("/")),
+    }
+}
+
+/// show the test card until escape
+pub fn display(
+    display: &mut dyn Display,
+    input: &mut dyn Input,
+    clock: &dyn Clock,
+) -> Result<(), Chip8Error> {
+    display.set_status("test card: every edge and corner should show (escape to finish)");
+    while !input.take_menu_request()? {
+        display.draw(&CHIP8_TEST_CARD)?;
+        input.tick()?;
+        clock.sleep(DIAG_FRAME);
+    }
+    Ok(())
+}
+
+/// show the keys being pressed, and which host keys they came from, until
+/// escape. a key that's held should stay down, however the keyboard repeats
+/// it, or --key-repeat wants adjusting
+pub fn input(
+    display: &mut dyn Display,
+    input: &mut dyn Input,
+    keymap: &Keymap,
+    clock: &dyn Clock,
+) -> Result<(), Chip8Error> {
+    display.set_status("press some keys (escape to finish)");
+    while !input.take_menu_request()? {
+        let key = input.read_key()?;
+        display.draw(&keypad_frame(key))?;
+        input.tick()?;
+        for transition in input.transitions() {
+            let (k, what) = match *transition {
+                KeyTransition::Press(k) => (k, "down"),
+                KeyTransition::Release(k) => (k, "let go"),
+                KeyTransition::Hold(_) => continue,
+            };
+            display.set_status(&format!(
+                "key {} {} (escape to finish)",
+                describe_key(k, keymap),
+                what
+            ));
+        }
+        clock.sleep(DIAG_FRAME);
+    }
+    Ok(())
+}
+
+/// run KEYPAD_TEST_ROM until escape, to check keys get all the way through
+/// to a program, not just out of the keyboard
+pub fn emulated_input(
+    display: &mut dyn Display,
+    input: &mut dyn Input,
+    sound: &mut dyn Sound,
+) -> Result<(), Chip8Error> {
+    display.set_status("press a key to start, then hold some down (escape to finish)");
+    let mut machine = Chip8Interpreter::new(display, input, sound)?;
+    machine.load_program(&mut &KEYPAD_TEST_ROM[..])?;
+    while machine.main_loop(usize::MAX)? == RunOutcome::Finished {}
+    Ok(())
+}
+
+/// beep from low to high through the speaker, saying what it's doing
+pub fn audio(out: &mut dyn Write, clock: &dyn Clock) -> Result<(), Chip8Error> {
+    for pitch in DIAG_AUDIO_PITCHES {
+        match pitch {
+            0 => writeln!(out, "done")?,
+            p => writeln!(out, "{} Hz", p)?,
+        }
+        beep(pitch).map_err(|e| Chip8Error::AudioError(e.to_string()))?;
+        clock.sleep(DIAG_AUDIO_NOTE);
+    }
+    Ok(())
+}
+
+/// a display that notes when each flash finished drawing
+struct FlashProbe<'a> {
+    display: &'a mut dyn Display,
+    clock: &'a dyn Clock,
+    lit: bool,
+    flashes: Vec<Duration>,
+}
+
+impl FlashProbe<'_> {
+    fn drawn(&mut self, data: &[u8]) {
+        let lit = data.iter().any(|b| *b != 0);
+        if lit && !self.lit {
+            self.flashes.push(self.clock.now());
+        }
+        self.lit = lit;
+    }
+}
+
+impl Display for FlashProbe<'_> {
+    fn draw(&mut self, data: &[u8]) -> Result<(), Chip8Error> {
+        self.display.draw(data)?;
+        self.drawn(data);
+        Ok(())
+    }
+
+    fn draw_changes(
+        &mut self,
+        data: &[u8],
+        changed: Option<&[Range<usize>]>,
+    ) -> Result<(), Chip8Error> {
+        self.display.draw_changes(data, changed)?;
+        self.drawn(data);
+        Ok(())
+    }
+
+    fn get_display_size_bytes(&mut self) -> usize {
+        self.display.get_display_size_bytes()
+    }
+
+    fn set_status(&mut self, status: &str) {
+        self.display.set_status(status);
+    }
+
+    fn set_mode(&mut self, width: usize, height: usize) -> Result<(), Chip8Error> {
+        self.display.set_mode(width, height)
+    }
+
+    fn refresh(&mut self) -> Result<(), Chip8Error> {
+        self.display.refresh()
+    }
+}
+
+/// a sound device that notes when each beep should get to the speaker: when
+/// its first frame was handed over, and however long the device says it
+/// takes to play that
+struct BeepProbe<'a> {
+    sound: &'a mut dyn Sound,
+    clock: &'a dyn Clock,
+    started: bool,
+    beeps: Vec<Duration>,
+}
+
+impl Sound for BeepProbe<'_> {
+    fn beep(&mut self) -> Result<(), Chip8Error> {
+        self.started = true;
+        self.sound.beep()
+    }
+
+    fn stop(&mut self) -> Result<(), Chip8Error> {
+        self.sound.stop()
+    }
+
+    fn set_volume(&mut self, volume: Volume) -> Result<(), Chip8Error> {
+        self.sound.set_volume(volume)
+    }
+
+    fn tick(&mut self) -> Result<(), Chip8Error> {
+        self.sound.tick()?;
+        if self.started {
+            let latency = self.sound.latency().unwrap_or_default();
+            self.beeps.push(self.clock.now() + latency);
+            self.started = false;
+        }
+        Ok(())
+    }
+
+    fn latency(&self) -> Option<Duration> {
+        self.sound.latency()
+    }
+}
+
+/// how far apart diag av found the picture and the sound
+#[derive(Debug, PartialEq)]
+pub struct AvReport {
+    /// how long after each flash was drawn its beep got to the speaker, in
+    /// seconds: negative if it was heard first
+    pub offsets: Vec<f64>,
+    /// whether the sound device said how long it takes to play things. if
+    /// not, the offsets only go as far as handing the samples over
+    pub device_latency: bool,
+}
+
+impl AvReport {
+    pub fn mean(&self) -> Option<f64> {
+        match self.offsets.len() {
+            0 => None,
+            n => Some(self.offsets.iter().sum::<f64>() / n as f64),
+        }
+    }
+}
+
+impl fmt::Display for AvReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mean = match self.mean() {
+            Some(m) => m,
+            None => return write!(f, "nothing measured: it stopped before the first flash"),
+        };
+        let ms = |s: f64| format!("{:.1}ms", s.abs() * 1000.0);
+        let min = self.offsets.iter().copied().fold(f64::MAX, f64::min);
+        let max = self.offsets.iter().copied().fold(f64::MIN, f64::max);
+        write!(
+            f,
+            "the sound comes {} {} the picture on average ({}{} to {}{}, over {} flashes)",
+            ms(mean),
+            if mean < 0.0 { "before" } else { "after" },
+            if min < 0.0 { "-" } else { "" },
+            ms(min),
+            if max < 0.0 { "-" } else { "" },
+            ms(max),
+            self.offsets.len()
+        )?;
+        if !self.device_latency {
+            write!(
+                f,
+                "\n(the sound device can't say how long it takes to play things, so that's \
+                 only as far as handing it the samples)"
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// run the AV sync ROM for a few seconds (or until escape), and say how far
+/// apart each flash and its beep got to the player
+pub fn av(
+    display: &mut dyn Display,
+    input: &mut dyn Input,
+    sound: &mut dyn Sound,
+    clock: &dyn Clock,
+) -> Result<AvReport, Chip8Error> {
+    measure_av(display, input, sound, clock, DIAG_AV_FLASHES)
+}
+
+/// run the AV sync ROM for flashes flashes, timing each flash and its beep
+/// with clock
+fn measure_av(
+    display: &mut dyn Display,
+    input: &mut dyn Input,
+    sound: &mut dyn Sound,
+    clock: &dyn Clock,
+    flashes: usize,
+) -> Result<AvReport, Chip8Error> {
+    display.set_status("measuring how far apart the picture and the sound are (escape to stop)");
+    let mut flash_probe = FlashProbe {
+        display,
+        clock,
+        lit: false,
+        flashes: Vec::new(),
+    };
+    let mut beep_probe = BeepProbe {
+        sound,
+        clock,
+        started: false,
+        beeps: Vec::new(),
+    };
+    {
+        let mut machine = Chip8Interpreter::new(&mut flash_probe, input, &mut beep_probe)?;
+        machine.set_clock(clock);
+        machine.load_program(&mut &av_sync_rom()?[..])?;
+        // a frame over, for the last flash to get drawn
+        machine.main_loop(flashes * DIAG_AV_PERIOD + 1)?;
+    }
+    let device_latency = beep_probe.latency().is_some();
+    Ok(AvReport {
+        offsets: flash_probe
+            .flashes
+            .iter()
+            .zip(&beep_probe.beeps)
+            .map(|(flash, beep)| beep.as_secs_f64() - flash.as_secs_f64())
+            .collect(),
+        device_latency,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::{AudioRing, RingSound};
+    use crate::clock::ManualClock;
+    use crate::display::DummyDisplay;
+    use crate::input::{conventional_keymap, DummyInput};
+    use crate::ocr::{framebuffer, pixel};
+    use crate::romtest::RomTest;
+    use crate::sound::Mute;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_keypad_frame() {
+        let frame = keypad_frame(Some(0x6));
+        // 6 is third along, second down: its middle's filled in
+        assert!(pixel(&frame, 2 * 16 + 7, 8 + 3));
+        // 5's isn't, but its edges are there
+        assert!(!pixel(&frame, 16 + 7, 8 + 3));
+        assert!(pixel(&frame, 16, 8 + 3));
+        // and there's a gap between boxes
+        assert!(!pixel(&frame, 15, 3));
+        assert!(!pixel(&frame, 7, 7));
+    }
+
+    #[test]
+    fn test_keypad_test_rom() -> Result<(), Chip8Error> {
+        RomTest::run(&KEYPAD_TEST_ROM, |t| {
+            // a frame per sprite for the outlines
+            t.run_frames(40)?;
+            assert_eq!(framebuffer(t.interpreter().memory())?, keypad_frame(None));
+            // get past FX0A, then hold 6. going round all the keys takes
+            // the VIP about eight frames
+            t.press('1').for_frames(3)?.run_frames(3)?;
+            t.press('6').for_frames(12)?;
+            assert_eq!(
+                framebuffer(t.interpreter().memory())?,
+                keypad_frame(Some(0x6))
+            );
+            t.press('0').for_frames(12)?;
+            assert_eq!(
+                framebuffer(t.interpreter().memory())?,
+                keypad_frame(Some(0x0))
+            );
+            t.run_frames(12)?;
+            assert_eq!(framebuffer(t.interpreter().memory())?, keypad_frame(None));
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_av() -> Result<(), Chip8Error> {
+        let clock = ManualClock::new();
+        let mut input = DummyInput::new(&[]);
+        // a beep's heard the moment the frame it starts in is drawn
+        let report = measure_av(&mut DummyDisplay, &mut input, &mut Mute::new(), &clock, 3)?;
+        assert_eq!(report.offsets, [0.0; 3]);
+        assert!(!report.device_latency);
+        assert!(report.to_string().contains("0.0ms after"));
+
+        // nothing's taking samples out of the ring, so it's full by the
+        // first flash, and the beeps wait behind a frame of silence
+        let ring = Arc::new(AudioRing::new(2));
+        let mut sound = RingSound::new(ring);
+        let report = measure_av(&mut DummyDisplay, &mut input, &mut sound, &clock, 3)?;
+        assert_eq!(report.offsets.len(), 3);
+        for offset in &report.offsets {
+            assert!((offset - 1.0 / 60.0).abs() < 1e-6, "{}", offset);
+        }
+        assert_eq!(
+            report.to_string(),
+            "the sound comes 16.7ms after the picture on average (16.7ms to 16.7ms, over 3 flashes)"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_av_report() {
+        let report = AvReport {
+            offsets: vec![-0.01, 0.002],
+            device_latency: true,
+        };
+        assert_eq!(
+            report.to_string(),
+            "the sound comes 4.0ms before the picture on average (-10.0ms to 2.0ms, over 2 flashes)"
+        );
+        let nothing = AvReport {
+            offsets: vec![],
+            device_latency: true,
+        };
+        assert!(nothing.to_string().starts_with("nothing measured"));
+    }
+
+    #[test]
+    fn test_describe_key() {
+        let mut keymap = conventional_keymap();
+        assert_eq!(describe_key(0x4, &keymap), "4 (Q)");
+        keymap.insert('j', 0x4);
+        assert_eq!(describe_key(0x4, &keymap), "4 (J/Q)");
+        keymap.retain(|_, k| *k != 0xf);
+        assert_eq!(describe_key(0xf, &keymap), "F (unmapped)");
+    }
+}
@@ -0,0 +1,311 @@
+//! # where two quirks profiles part ways
+//!
+//! runs a ROM on two machines in lockstep, one instruction at a time, and
+//! finds the first instruction after which their registers or screens
+//! disagree. that's the instruction that needs one of the quirks, which
+//! helps figure out what an unknown ROM was written for.
+//!
+//! the same goes for traces, e.g. one of ours against one exported from
+//! another emulator: chip8 trace-diff lines them up and finds the first
+//! instruction after which they disagree
+use crate::display::DummyDisplay;
+use crate::error::Chip8Error;
+use crate::input::DummyInput;
+use crate::interpreter::{Chip8Interpreter, InterpreterState};
+use crate::memory::MemoryMap;
+use crate::ocr;
+use crate::quirks::Quirks;
+use crate::sound::Mute;
+use crate::trace::TraceEntry;
+use std::fmt;
+
+/// the first point at which two runs disagree
+#[derive(Debug, PartialEq)]
+pub struct Divergence {
+    /// instructions both ran before the one that disagreed
+    pub instructions: u64,
+    /// the instruction that disagreed, and where it was
+    pub addr: u16,
+    pub inst: u16,
+    /// what's different afterwards, e.g. "I: 0x0300 vs 0x0302"
+    pub differences: Vec<String>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "diverged after {} instructions, at {:04x} ({:04x}):",
+            self.instructions, self.addr, self.inst
+        )?;
+        for d in &self.differences {
+            writeln!(f, "  {}", d)?;
+        }
+        Ok(())
+    }
+}
+
+/// run rom under both quirks profiles for up to max_instructions or
+/// max_frames, whichever comes first, with no keys pressed. None if they
+/// never disagree in that time, or if both end up waiting for a key (which
+/// would be for ever) or stopped
+pub fn first_divergence(
+    rom: &[u8],
+    quirks: [Quirks; 2],
+    max_instructions: u64,
+    max_frames: u64,
+) -> Result<Option<Divergence>, Chip8Error> {
+    let mut displays = [DummyDisplay, DummyDisplay];
+    let mut inputs = [DummyInput::new(&[]), DummyInput::new(&[])];
+    let mut sounds = [Mute::new(), Mute::new()];
+    let [da, db] = &mut displays;
+    let [ia, ib] = &mut inputs;
+    let [sa, sb] = &mut sounds;
+    let mut a = Chip8Interpreter::new(da, ia, sa)?;
+    let mut b = Chip8Interpreter::new(db, ib, sb)?;
+    for (machine, quirks) in [&mut a, &mut b].into_iter().zip(quirks) {
+        machine.set_seed(0);
+        machine.set_quirks(quirks);
+        machine.load_program(&mut &rom[..])?;
+    }
+
+    let stuck = |m: &Chip8Interpreter| {
+        matches!(
+            m.state(),
+            InterpreterState::WaitingForKey | InterpreterState::Halted
+        )
+    };
+    for instructions in 0..max_instructions {
+        if a.frames() >= max_frames || (stuck(&a) && stuck(&b)) {
+            break;
+        }
+        let addr = a.pc();
+        let word = a.memory().get_ro_slice(addr, 2)?;
+        let inst = u16::from_be_bytes([word[0], word[1]]);
+        let differences = match (a.step(), b.step()) {
+            (Ok(()), Ok(())) => differences(&a, &b)?,
+            (Err(e), Err(_)) => return Err(e),
+            (Err(e), Ok(())) => vec![format!("first stopped: {}", e)],
+            (Ok(()), Err(e)) => vec![format!("second stopped: {}", e)],
+        };
+        if !differences.is_empty() {
+            return Ok(Some(Divergence {
+                instructions,
+                addr,
+                inst,
+                differences,
+            }));
+        }
+    }
+    Ok(None)
+}
+
+/// how far into either trace to look for where the other starts
+const TRACE_ALIGN_WINDOW: usize = 1000;
+
+/// skip whatever one trace has before the other starts, e.g. an emulator
+/// that traces its own start-up code. they're left as they are if neither
+/// start turns up near the beginning of the other
+pub fn align<'t>(
+    mine: &'t [TraceEntry],
+    theirs: &'t [TraceEntry],
+) -> (&'t [TraceEntry], &'t [TraceEntry]) {
+    let same = |a: &TraceEntry, b: &TraceEntry| (a.pc, a.opcode) == (b.pc, b.opcode);
+    let find = |trace: &[TraceEntry], start: &TraceEntry| {
+        trace
+            .iter()
+            .take(TRACE_ALIGN_WINDOW)
+            .position(|e| same(e, start))
+    };
+    match (mine.first(), theirs.first()) {
+        (Some(m), Some(t)) => match (find(theirs, m), find(mine, t)) {
+            (Some(skip), _) => (mine, &theirs[skip..]),
+            (None, Some(skip)) => (&mine[skip..], theirs),
+            (None, None) => (mine, theirs),
+        },
+        _ => (mine, theirs),
+    }
+}
+
+/// the first entry at which two (aligned) traces disagree, and what about.
+/// frames aren't compared, since emulators fit different numbers of
+/// instructions in a frame. None if they agree for as long as both go on
+pub fn trace_divergence(mine: &[TraceEntry], theirs: &[TraceEntry]) -> Option<(usize, Divergence)> {
+    let (n, differences) = mine
+        .iter()
+        .zip(theirs)
+        .map(|(a, b)| entry_differences(a, b))
+        .enumerate()
+        .find(|(_, d)| !d.is_empty())?;
+    // each entry's what went into its instruction, so it's the one before
+    // that got something different out
+    let culprit = &mine[n.saturating_sub(1)];
+    Some((
+        n,
+        Divergence {
+            instructions: n.saturating_sub(1) as u64,
+            addr: culprit.pc,
+            inst: culprit.opcode,
+            differences,
+        },
+    ))
+}
+
+fn entry_differences(a: &TraceEntry, b: &TraceEntry) -> Vec<String> {
+    let mut found = Vec::new();
+    let mut compare = |what: String, a: String, b: String| {
+        if a != b {
+            found.push(format!("{}: {} vs {}", what, a, b));
+        }
+    };
+    for reg in 0..16 {
+        compare(
+            format!("V{:X}", reg),
+            format!("{:#04x}", a.v[reg]),
+            format!("{:#04x}", b.v[reg]),
+        );
+    }
+    compare("I".into(), format!("{:#06x}", a.i), format!("{:#06x}", b.i));
+    compare(
+        "PC".into(),
+        format!("{:#06x}", a.pc),
+        format!("{:#06x}", b.pc),
+    );
+    compare(
+        "opcode".into(),
+        format!("{:#06x}", a.opcode),
+        format!("{:#06x}", b.opcode),
+    );
+    found
+}
+
+/// everything a ROM can see that's different between a and b
+fn differences(a: &Chip8Interpreter, b: &Chip8Interpreter) -> Result<Vec<String>, Chip8Error> {
+    let mut found = Vec::new();
+    let mut compare = |what: String, a: String, b: String| {
+        if a != b {
+            found.push(format!("{}: {} vs {}", what, a, b));
+        }
+    };
+    for reg in 0..16 {
+        compare(
+            format!("V{:X}", reg),
+            format!("{:#04x}", a.v(reg)),
+            format!("{:#04x}", b.v(reg)),
+        );
+    }
+    compare(
+        "I".into(),
+        format!("{:#06x}", a.i()),
+        format!("{:#06x}", b.i()),
+    );
+    compare(
+        "PC".into(),
+        format!("{:#06x}", a.pc()),
+        format!("{:#06x}", b.pc()),
+    );
+    compare(
+        "stack".into(),
+        format!("{:04x?}", a.stack()?),
+        format!("{:04x?}", b.stack()?),
+    );
+    compare(
+        "delay timer".into(),
+        a.delay_timer().to_string(),
+        b.delay_timer().to_string(),
+    );
+    compare(
+        "sound timer".into(),
+        a.sound_timer().to_string(),
+        b.sound_timer().to_string(),
+    );
+    let (fa, fb) = (ocr::framebuffer(a.memory())?, ocr::framebuffer(b.memory())?);
+    if let Some(byte) = (0..fa.len()).find(|i| fa[*i] != fb[*i]) {
+        found.push(format!(
+            "screen: first differs at ({}, {})",
+            byte % 8 * 8,
+            byte / 8
+        ));
+    }
+    Ok(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_finds_the_quirky_instruction() -> Result<(), Chip8Error> {
+        // v0 = 1; v1 = 4; i = 0x300; save v0-v1; loop forever
+        #[rustfmt::skip]
+        let rom = [
+            0x60, 0x01, 0x61, 0x04, 0xa3, 0x00, 0xf1, 0x55,
+            0x12, 0x08,
+        ];
+        let d = first_divergence(&rom, [Quirks::VIP, Quirks::MODERN], 100, 100)?.unwrap();
+        assert_eq!(d.instructions, 3);
+        assert_eq!((d.addr, d.inst), (0x206, 0xf155));
+        assert_eq!(d.differences, vec!["I: 0x0302 vs 0x0300"]);
+        assert!(d.to_string().starts_with("diverged after 3 instructions"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_trace_divergence() {
+        let entry = |pc: u16, opcode: u16, v0: u8| TraceEntry {
+            frame: 1,
+            pc,
+            opcode,
+            v: [v0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+            i: 0,
+        };
+        let mine = [
+            entry(0x200, 0x7001, 0),
+            entry(0x202, 0x7001, 1),
+            entry(0x204, 0x1200, 2),
+        ];
+        // some start-up code first, then a different second add
+        let theirs = [
+            entry(0x000, 0x00e0, 0),
+            entry(0x200, 0x7001, 0),
+            entry(0x202, 0x7001, 1),
+            entry(0x204, 0x1200, 3),
+        ];
+        let (a, b) = align(&mine, &theirs);
+        assert_eq!((a.len(), b.len()), (3, 3));
+        let (n, d) = trace_divergence(a, b).unwrap();
+        assert_eq!(n, 2);
+        assert_eq!(d.instructions, 1);
+        assert_eq!((d.addr, d.inst), (0x202, 0x7001));
+        assert_eq!(d.differences, vec!["V0: 0x02 vs 0x03"]);
+
+        // the other way round, and agreeing as far as they go
+        let (b, a) = align(&theirs[..3], &mine[..2]);
+        assert_eq!((a.len(), b.len()), (2, 2));
+        assert_eq!(trace_divergence(a, b), None);
+    }
+
+    #[test]
+    fn test_no_divergence() -> Result<(), Chip8Error> {
+        // nothing quirky about an endless loop
+        let rom = [0x60, 0x01, 0x12, 0x02];
+        assert_eq!(
+            first_divergence(&rom, [Quirks::VIP, Quirks::MODERN], 100, 100)?,
+            None
+        );
+        // nor in waiting for a key that never comes, however long it's
+        // given
+        let rom = [0xf0, 0x0a, 0x12, 0x00];
+        assert_eq!(
+            first_divergence(&rom, [Quirks::VIP, Quirks::MODERN], u64::MAX, u64::MAX)?,
+            None
+        );
+        // and a busy loop stops at the frames it's given
+        let rom = [0x70, 0x01, 0x12, 0x00];
+        assert_eq!(
+            first_divergence(&rom, [Quirks::VIP, Quirks::MODERN], u64::MAX, 2)?,
+            None
+        );
+        Ok(())
+    }
+}
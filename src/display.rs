@@ -1,27 +1,326 @@
+use crate::error::Chip8Error;
+use crate::heat::Heat;
+use crate::input::Focus;
+use crate::sound::Volume;
+#[cfg(feature = "full")]
+use crate::termcaps::Colours;
+#[cfg(feature = "full")]
+use crate::touch;
+#[cfg(feature = "full")]
+use std::cell::RefCell;
+#[cfg(feature = "full")]
 use std::io;
+use std::io::Write;
+use std::ops::Range;
+#[cfg(feature = "full")]
 use tui::backend::CrosstermBackend;
-use tui::layout::Rect;
-use tui::style::{Color, Style};
+#[cfg(feature = "full")]
+use tui::buffer::Buffer;
+#[cfg(feature = "full")]
+use tui::layout::{Alignment, Rect};
+#[cfg(feature = "full")]
+use tui::style::{Color, Modifier, Style};
+#[cfg(feature = "full")]
 use tui::symbols::Marker;
-use tui::widgets::canvas::{Canvas, Points};
-use tui::widgets::{Block, Borders};
-use tui::Terminal;
+#[cfg(feature = "full")]
+use tui::text::Span;
+#[cfg(feature = "full")]
+use tui::widgets::canvas::{Canvas, Context, Points};
+#[cfg(feature = "full")]
+use tui::widgets::{Block, Borders, Clear, Paragraph, Widget};
+#[cfg(feature = "full")]
+use tui::{Frame, Terminal};
 
 /// Display is used by the interpreter to draw things on the screen. It should
 /// abstract the implementation details, so a variety of kinds of screen would
 /// work.
 pub trait Display {
     /// draw data based on internal resolution of display
-    fn draw(&mut self, data: &[u8]) -> Result<(), io::Error>;
+    fn draw(&mut self, data: &[u8]) -> Result<(), Chip8Error>;
+
+    /// draw data, knowing which bytes of it changed since the last frame
+    /// (or None if that's not known, e.g. after a mode change). displays
+    /// that can update just those bytes should; the rest just draw it all
+    fn draw_changes(
+        &mut self,
+        data: &[u8],
+        _changed: Option<&[Range<usize>]>,
+    ) -> Result<(), Chip8Error> {
+        self.draw(data)
+    }
 
     /// how big the display data should be
     fn get_display_size_bytes(&mut self) -> usize;
+
+    /// show a line of text (e.g. the controls) alongside the display, if
+    /// the display has anywhere to put it
+    fn set_status(&mut self, _status: &str) {}
+
+    /// briefly tell the player something (e.g. an achievement), if the
+    /// display has anywhere to put it
+    fn notify(&mut self, _notice: &str) {}
+
+    /// show how fast the emulator's running, relative to a real VIP, if the
+    /// display has anywhere to put it
+    fn set_speed(&mut self, _speed: f64) {}
+
+    /// show how loud the buzzer is, if the display has anywhere to put it
+    fn set_volume(&mut self, _volume: Volume) {}
+
+    /// show whether the keys are going to the program or the emulator, if
+    /// the display has anywhere to put it
+    fn set_focus(&mut self, _focus: Focus) {}
+
+    /// show what the machine's up to beside the picture, or stop (None), if
+    /// the display has anywhere to put it. comes before each frame's draw
+    fn set_hud(&mut self, _hud: Option<Hud>) {}
+
+    /// draw planes bitplanes over each other from now on, one after
+    /// another in what's drawn, each pixel's colour made of its bit in
+    /// each, as XO-CHIP does. it starts with 1, which is all most displays
+    /// can do
+    fn set_planes(&mut self, planes: usize) -> Result<(), Chip8Error> {
+        match planes {
+            1 => Ok(()),
+            _ => Err(Chip8Error::DisplayError(format!(
+                "this display can't draw {} planes",
+                planes
+            ))),
+        }
+    }
+
+    /// colour each pixel by how hot it is (how often it's been drawn on
+    /// lately) rather than by the theme, or stop (None), if the display
+    /// can. comes before each frame's draw, as the hud does
+    fn set_heat(&mut self, _heat: Option<Heat>) {}
+
+    /// show lines of help (the hotkeys) over the picture, or take them away
+    /// (None), if the display has anywhere to put them
+    fn set_help(&mut self, _help: Option<Vec<String>>) {}
+
+    /// show the savestate slot picker over the picture, or take it away
+    /// (None), like the help
+    fn set_slots(&mut self, _slots: Option<Vec<String>>) {}
+
+    /// show the command palette over the picture, or take it away (None),
+    /// like the help
+    fn set_palette(&mut self, _palette: Option<Vec<String>>) {}
+
+    /// colour things in differently from now on, if there's any colour
+    #[cfg(feature = "full")]
+    fn set_theme(&mut self, _theme: Theme) {}
+
+    /// change resolution, e.g. when a SCHIP program switches to 128x64.
+    /// draws from then on are sized to match. it starts at 64x32, which is
+    /// all a display that doesn't say otherwise can do
+    fn set_mode(&mut self, width: usize, height: usize) -> Result<(), Chip8Error> {
+        match (width, height) {
+            (64, 32) => Ok(()),
+            _ => Err(Chip8Error::DisplayError(format!(
+                "this display can't draw {}x{}",
+                width, height
+            ))),
+        }
+    }
+
+    /// forget what's on screen so the next draw repaints all of it, e.g.
+    /// after something else has written over the terminal
+    fn refresh(&mut self) -> Result<(), Chip8Error> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "full")]
+/// how long a notice stays up, in frames
+const NOTICE_FRAMES: u32 = 180;
+
+/// the runs of bytes that differ between old and new, which are the same size
+pub fn changed_ranges(old: &[u8], new: &[u8]) -> Vec<Range<usize>> {
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    for i in (0..new.len()).filter(|i| old[*i] != new[*i]) {
+        match ranges.last_mut() {
+            Some(r) if r.end == i => r.end += 1,
+            _ => ranges.push(i..i + 1),
+        }
+    }
+    ranges
+}
+
+/// how many characters the HUD's timer bars go up to
+const HUD_BAR_WIDTH: usize = 8;
+
+/// how many frames back the HUD's scope goes
+pub const HUD_SCOPE_FRAMES: usize = 32;
+
+/// where a frame's machine cycles went: fetching and decoding
+/// instructions, running them, the interrupt (and the DMA), and waiting
+/// (for the next frame, or for a key)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameCycles {
+    pub fetch: u32,
+    pub execute: u32,
+    pub interrupt: u32,
+    pub idle: u32,
+}
+
+impl FrameCycles {
+    pub fn total(&self) -> u32 {
+        self.fetch + self.execute + self.interrupt + self.idle
+    }
+}
+
+/// the heads-up display: some of what the machine's up to, for the curious
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hud {
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    /// the random number generator's seed
+    pub random: u16,
+    /// the last instruction fetched
+    pub opcode: u16,
+    /// whether the buzzer was sounding in each of the last 32 frames, the
+    /// latest in the lowest bit
+    pub beeps: u32,
+    /// the opcode's FX0A, and it's still waiting
+    pub waiting_for_key: bool,
+    /// where the cycles went in each of the last HUD_SCOPE_FRAMES frames,
+    /// the latest last
+    pub cycles: [FrameCycles; HUD_SCOPE_FRAMES],
+}
+
+impl Hud {
+    /// a line for each thing, the timers with bars
+    pub fn lines(&self) -> Vec<String> {
+        // a timer that's running at all gets at least a bit of bar
+        let bar = |t: u8| {
+            let full = (t as usize * HUD_BAR_WIDTH).div_ceil(0xff);
+            format!("{}{}", "█".repeat(full), "░".repeat(HUD_BAR_WIDTH - full))
+        };
+        vec![
+            format!("DT {:02x} {}", self.delay_timer, bar(self.delay_timer)),
+            format!("ST {:02x} {}", self.sound_timer, bar(self.sound_timer)),
+            format!("RND {:04x}", self.random),
+            match self.waiting_for_key {
+                true => format!("OP  {:04x} KEY?", self.opcode),
+                false => format!("OP  {:04x}", self.opcode),
+            },
+            // scrolling left, so sounds line up with what's on screen
+            format!(
+                "BZ  {}",
+                (0..32)
+                    .rev()
+                    .map(|f| match self.beeps >> f & 1 {
+                        1 => '█',
+                        _ => '▁',
+                    })
+                    .collect::<String>()
+            ),
+            scope("FE", &self.cycles, |c| c.fetch),
+            scope("EX", &self.cycles, |c| c.execute),
+            scope("IR", &self.cycles, |c| c.interrupt),
+            scope("ID", &self.cycles, |c| c.idle),
+        ]
+    }
+}
+
+/// a line of the scope: how much of each frame went on part, as a bar a
+/// frame (scrolling left, like the buzzer's), then the latest frame's as
+/// a percentage. it's why a ROM that feels slow on the VIP is: all fetch
+/// and execute, and it's doing too much each frame
+fn scope(name: &str, frames: &[FrameCycles], part: fn(&FrameCycles) -> u32) -> String {
+    const LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    let share = |c: &FrameCycles| match c.total() {
+        0 => 0,
+        total => part(c) as u64 * 100 / total as u64,
+    };
+    let bars: String = frames
+        .iter()
+        .map(|c| LEVELS[(share(c) as usize * LEVELS.len() / 100).min(LEVELS.len() - 1)])
+        .collect();
+    let latest = frames.last().map_or(0, share);
+    format!("{}  {} {:3}%", name, bars, latest)
+}
+
+#[cfg(feature = "full")]
+/// the status, with the speed, volume and focus after it if they're not as
+/// usual
+fn status_line(status: &str, speed: f64, volume: Volume, focus: Focus) -> String {
+    let mut line = status.to_string();
+    if speed != 1.0 {
+        line += &format!("  [{}x]", speed);
+    }
+    match volume {
+        Volume { muted: true, .. } => line += "  [muted]",
+        Volume { level: 100, .. } => {}
+        Volume { level, .. } => line += &format!("  [vol {}%]", level),
+    }
+    if focus == Focus::Emulator {
+        line += "  [keys: emulator]";
+    }
+    line
+}
+
+#[cfg(feature = "full")]
+/// the HUD goes to the right of the canvas, if the terminal has room
+fn render_hud(f: &mut Frame<CrosstermBackend<io::Stdout>>, canvas: Rect, hud: &Hud) {
+    let lines = hud.lines();
+    let width = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0) as u16;
+    let hud_size = clip(
+        Rect::new(
+            canvas.right() + 1,
+            canvas.top() + 1,
+            width,
+            lines.len() as u16,
+        ),
+        f.size(),
+    );
+    if hud_size.area() > 0 {
+        f.render_widget(Paragraph::new(lines.join("\n")), hud_size);
+    }
+}
+
+#[cfg(feature = "full")]
+/// the keypad, to be touched
+fn render_keypad(f: &mut Frame<CrosstermBackend<io::Stdout>>, theme: &Theme) {
+    for (button, key) in touch::buttons(f.size()) {
+        f.render_widget(Clear, button);
+        f.render_widget(
+            Paragraph::new(format!("{:X}", key))
+                .style(theme.text_style())
+                .alignment(Alignment::Center)
+                .block(Block::default().borders(Borders::ALL)),
+            button,
+        );
+    }
+}
+
+#[cfg(feature = "full")]
+/// help (or the slot picker) goes in a box in the middle, over whatever's
+/// there
+fn render_overlay(
+    f: &mut Frame<CrosstermBackend<io::Stdout>>,
+    lines: &[String],
+    title: &str,
+    theme: &Theme,
+) {
+    let width = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0) as u16;
+    let size = centred(f.size(), width + 4, lines.len() as u16 + 2);
+    if size.area() > 0 {
+        f.render_widget(Clear, size);
+        f.render_widget(
+            Paragraph::new(lines.join("\n"))
+                .style(theme.text_style())
+                .block(Block::default().borders(Borders::ALL).title(title)),
+            size,
+        );
+    }
 }
 
 // store useful metadata about the terminal
 struct Resolution(usize, usize, usize);
 
 impl Resolution {
+    #[cfg(feature = "full")]
     fn pixel_count(&self) -> usize {
         self.0 * self.1
     }
@@ -29,14 +328,17 @@ impl Resolution {
         self.0 * self.1 * self.2 / 8
     }
 
+    #[cfg(feature = "full")]
     fn x_bounds(&self) -> [f64; 2] {
         [0.0, (self.0 - 1) as f64]
     }
 
+    #[cfg(feature = "full")]
     fn y_bounds(&self) -> [f64; 2] {
-        [-1.0 * (self.1 - 1) as f64, 0.0]
+        [-((self.1 - 1) as f64), 0.0]
     }
 
+    #[cfg(feature = "full")]
     #[allow(dead_code)]
     fn points_from_data<'a>(
         &self,
@@ -51,8 +353,8 @@ impl Resolution {
                     count -= 1;
                     let bit = 1 & (data[count / 8] >> (7 - count % 8));
                     Some((
-                        (count % w) as f64,        // x
-                        -1.0 * (count / w) as f64, // y
+                        (count % w) as f64,    // x
+                        -((count / w) as f64), // y
                         if bit == 1 { Color::White } else { Color::Black },
                     ))
                 }
@@ -60,21 +362,47 @@ impl Resolution {
         })
     }
 
-    fn bitplane_from_data<'a>(
-        &self,
+    /// the colour of the pixel x across and y down: its bit in the first
+    /// plane, plus twice its bit in the second, and so on
+    #[cfg(feature = "full")]
+    fn colour(&self, data: &[u8], x: usize, y: usize) -> usize {
+        let (pixel, plane_size) = (y * self.0 + x, self.pixel_count() / 8);
+        (0..self.2)
+            .filter(|p| {
+                data.get(p * plane_size + pixel / 8)
+                    .is_some_and(|b| b & (0x80 >> (pixel % 8)) != 0)
+            })
+            .fold(0, |c, p| c | 1 << p)
+    }
+
+    /// every plane lit into one, for what only cares whether a pixel's lit
+    #[cfg(feature = "full")]
+    fn lit<'a>(&self, data: &'a [u8]) -> std::borrow::Cow<'a, [u8]> {
+        let plane_size = self.pixel_count() / 8;
+        match self.2 {
+            1 => data.into(),
+            _ => (0..plane_size)
+                .map(|i| (0..self.2).fold(0, |b, p| b | data.get(p * plane_size + i).unwrap_or(&0)))
+                .collect::<Vec<u8>>()
+                .into(),
+        }
+    }
+
+    #[cfg(feature = "full")]
+    fn colour_from_data<'a>(
+        &'a self,
         data: &'a [u8],
-        bitplane: u8,
+        colour: usize,
     ) -> impl std::iter::Iterator<Item = (f64, f64)> + 'a {
         let mut count = self.pixel_count();
         let w = self.0;
         std::iter::from_fn(move || {
             while count > 0 {
                 count -= 1;
-                let bit = 1 & (data[count / 8] >> (7 - count % 8));
-                if bit == bitplane {
+                if self.colour(data, count % w, count / w) == colour {
                     return Some((
-                        (count % w) as f64,        // x
-                        -1.0 * (count / w) as f64, // y
+                        (count % w) as f64,    // x
+                        -((count / w) as f64), // y
                     ));
                 }
             }
@@ -83,89 +411,948 @@ impl Resolution {
     }
 }
 
+#[cfg(feature = "full")]
+/// how to colour in the picture, and the text around it
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Theme {
+    pub lit: Color,
+    pub unlit: Color,
+    /// the border, titles and status line
+    pub text: Color,
+    pub bold: bool,
+    /// leave unlit pixels blank rather than painting them, so a lit pixel
+    /// is a block and an unlit one isn't: no telling colours apart needed
+    pub glyphs: bool,
+}
+
+#[cfg(feature = "full")]
+impl Theme {
+    /// the themes there are, by name
+    pub const PRESETS: [(&'static str, Theme); 2] = [
+        ("classic", Theme::CLASSIC),
+        ("high-contrast", Theme::HIGH_CONTRAST),
+    ];
+
+    /// white on black, as it's always been
+    pub const CLASSIC: Theme = Theme {
+        lit: Color::White,
+        unlit: Color::Black,
+        text: Color::Reset,
+        bold: false,
+        glyphs: false,
+    };
+
+    /// true white and black, whatever the terminal's palette makes of its
+    /// named colours, with bold yellow text
+    pub const HIGH_CONTRAST: Theme = Theme {
+        lit: Color::Rgb(0xff, 0xff, 0xff),
+        unlit: Color::Rgb(0, 0, 0),
+        text: Color::Rgb(0xff, 0xff, 0),
+        bold: true,
+        glyphs: false,
+    };
+
+    /// a theme by name, e.g. from --theme
+    pub fn parse(name: &str) -> Result<Self, Chip8Error> {
+        Self::PRESETS
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, t)| *t)
+            .ok_or_else(|| {
+                Chip8Error::ConfigError(format!(
+                    "no theme called \"{}\" (try classic or high-contrast)",
+                    name
+                ))
+            })
+    }
+
+    /// dark pixels on a light background
+    pub fn inverted(self) -> Self {
+        Theme {
+            lit: self.unlit,
+            unlit: self.lit,
+            ..self
+        }
+    }
+
+    fn text_style(&self) -> Style {
+        let style = Style::default().fg(self.text);
+        match self.bold {
+            true => style.add_modifier(Modifier::BOLD),
+            false => style,
+        }
+    }
+}
+
+#[cfg(feature = "full")]
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::CLASSIC
+    }
+}
+
+#[cfg(feature = "full")]
+/// the four colours XO-CHIP's two planes make, for pixels in neither, the
+/// first, the second and both
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct PlaneColours(pub [Color; 4]);
+
+#[cfg(feature = "full")]
+impl PlaneColours {
+    /// Octo's, which is what most XO-CHIP games were made to look right in
+    pub const OCTO: PlaneColours = PlaneColours([
+        Color::Rgb(0x99, 0x66, 0x00),
+        Color::Rgb(0xff, 0xcc, 0x00),
+        Color::Rgb(0xff, 0x66, 0x00),
+        Color::Rgb(0x66, 0x22, 0x00),
+    ]);
+
+    /// four colours as #rrggbb, separated by commas, e.g. from
+    /// --plane-colours
+    pub fn parse(s: &str) -> Result<Self, Chip8Error> {
+        let bad = || {
+            Chip8Error::ConfigError(format!(
+                "can't colour planes \"{}\" (try four colours, e.g. #000000,#ffffff,#ff6600,#662200)",
+                s
+            ))
+        };
+        let colours: Vec<Color> = s
+            .split(',')
+            .map(|c| {
+                let hex = c
+                    .trim()
+                    .strip_prefix('#')
+                    .filter(|h| h.len() == 6)
+                    .ok_or_else(bad)?;
+                let rgb = u32::from_str_radix(hex, 16).map_err(|_| bad())?;
+                Ok(Color::Rgb((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8))
+            })
+            .collect::<Result<_, Chip8Error>>()?;
+        colours.try_into().map(PlaneColours).map_err(|_| bad())
+    }
+
+    /// the colour for each colour a pixel can be with planes planes: one
+    /// plane's the theme's, and two are these
+    fn inks(&self, theme: &Theme, planes: usize) -> Vec<Color> {
+        match planes {
+            1 => vec![theme.unlit, theme.lit],
+            _ => self.0.to_vec(),
+        }
+    }
+}
+
+#[cfg(feature = "full")]
+impl Default for PlaneColours {
+    fn default() -> Self {
+        PlaneColours::OCTO
+    }
+}
+
+#[cfg(feature = "full")]
+/// the framebuffer as a TUI canvas, in a box with a title
+fn canvas<'a>(
+    resolution: &'a Resolution,
+    data: &'a [u8],
+    title: &'a str,
+    theme: &'a Theme,
+    inks: Vec<Color>,
+    scale: Scale,
+) -> Canvas<'a, impl Fn(&mut Context) + 'a> {
+    let Scale(sx, sy) = scale;
+    let scaled = Resolution(resolution.0 * sx, resolution.1 * sy, 1);
+    // each pixel as sx by sy points
+    let points = move |colour| {
+        resolution
+            .colour_from_data(data, colour)
+            .flat_map(move |(x, y)| {
+                (0..sx * sy).map(move |n| {
+                    (
+                        x * sx as f64 + (n % sx) as f64,
+                        y * sy as f64 - (n / sx) as f64,
+                    )
+                })
+            })
+            .collect::<Vec<_>>()
+    };
+    Canvas::default()
+        .block(
+            Block::default()
+                .title(Span::styled(title, theme.text_style()))
+                .borders(Borders::ALL)
+                .border_style(theme.text_style())
+                .style(Style::default().bg(inks[0])),
+        )
+        .x_bounds(scaled.x_bounds())
+        .y_bounds(scaled.y_bounds())
+        .marker(Marker::Block) //Braille
+        .paint(move |ctx| {
+            // expand each colour into x, y float coords, suitable for
+            // rendering with TUI. this just prints blocky points for now
+            let unlit = theme.glyphs as usize;
+            for (colour, ink) in inks.iter().enumerate().skip(unlit) {
+                ctx.draw(&Points {
+                    coords: &points(colour),
+                    color: *ink,
+                });
+            }
+        })
+}
+
+#[cfg(feature = "full")]
+/// how many times over each pixel's drawn, across and down. a terminal's
+/// characters are about twice as tall as they are wide, so 2x1 makes the
+/// pixels (roughly) square, and circles come out round
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Scale(pub usize, pub usize);
+
+#[cfg(feature = "full")]
+impl Scale {
+    /// e.g. "2x1", or "2" for 2x2
+    pub fn parse(s: &str) -> Result<Self, Chip8Error> {
+        let bad = || Chip8Error::ConfigError(format!("can't scale by \"{}\" (try 2x1)", s));
+        let (x, y) = s.split_once('x').unwrap_or((s, s));
+        match (x.parse(), y.parse()) {
+            (Ok(x), Ok(y)) if x > 0 && y > 0 => Ok(Scale(x, y)),
+            _ => Err(bad()),
+        }
+    }
+}
+
+#[cfg(feature = "full")]
+impl Default for Scale {
+    fn default() -> Self {
+        Scale(1, 1)
+    }
+}
+
+#[cfg(feature = "full")]
+/// how many pixels go in each character cell
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Cells {
+    /// one pixel per cell, drawn with a block: big, and 128x64 won't fit in
+    /// an 80x25 terminal
+    Block,
+    /// 2x2 pixels per cell, with quadrant blocks
+    Quadrant,
+    /// 2x3 pixels per cell, with Unicode 13's sextants, which not every
+    /// font has yet
+    Sextant,
+    /// one pixel per cell, drawn with a #, for terminals without Unicode
+    Ascii,
+}
+
+#[cfg(feature = "full")]
+impl Cells {
+    pub fn parse(s: &str) -> Result<Self, Chip8Error> {
+        match s {
+            "block" => Ok(Cells::Block),
+            "quadrant" => Ok(Cells::Quadrant),
+            "sextant" => Ok(Cells::Sextant),
+            "ascii" => Ok(Cells::Ascii),
+            _ => Err(Chip8Error::ConfigError(format!(
+                "can't draw with \"{}\" (try block, quadrant, sextant or ascii)",
+                s
+            ))),
+        }
+    }
+
+    /// pixels per cell, across and down
+    pub fn size(&self) -> (usize, usize) {
+        match self {
+            Cells::Block | Cells::Ascii => (1, 1),
+            Cells::Quadrant => (2, 2),
+            Cells::Sextant => (2, 3),
+        }
+    }
+
+    /// the character for the cell column across and row down, in a
+    /// width by height picture drawn scale times over. off the picture's
+    /// unlit
+    fn cell(
+        &self,
+        data: &[u8],
+        width: usize,
+        height: usize,
+        scale: Scale,
+        column: u16,
+        row: u16,
+    ) -> char {
+        let (w, h) = self.size();
+        let Scale(sx, sy) = scale;
+        let pixel = |x: usize, y: usize| {
+            let (x, y) = (x / sx, y / sy);
+            x < width && y < height && data[(y * width + x) / 8] & (0x80 >> (x % 8)) != 0
+        };
+        let mut pixels = 0;
+        for dy in 0..h {
+            for dx in 0..w {
+                let (x, y) = (column as usize * w + dx, row as usize * h + dy);
+                if pixel(x, y) {
+                    pixels |= 1 << (dy * w + dx);
+                }
+            }
+        }
+        self.glyph(pixels)
+    }
+
+    /// the colour most of the cell column across and row down's lit pixels
+    /// are, drawn scale times over (0 if none are lit)
+    fn ink(
+        &self,
+        colour: impl Fn(usize, usize) -> usize,
+        scale: Scale,
+        column: u16,
+        row: u16,
+    ) -> usize {
+        let (w, h) = self.size();
+        let Scale(sx, sy) = scale;
+        let mut counts = [0; 4];
+        for (dx, dy) in (0..h).flat_map(|dy| (0..w).map(move |dx| (dx, dy))) {
+            let (x, y) = (column as usize * w + dx, row as usize * h + dy);
+            if let Some(c) = counts.get_mut(colour(x / sx, y / sy)) {
+                *c += 1;
+            }
+        }
+        (1..counts.len())
+            .rev()
+            .max_by_key(|c| counts[*c])
+            .filter(|c| counts[*c] > 0)
+            .unwrap_or(0)
+    }
+
+    /// how hot the hottest pixel in the cell column across and row down
+    /// is, drawn scale times over
+    fn hottest(&self, heat: &Heat, scale: Scale, column: u16, row: u16) -> u8 {
+        let (w, h) = self.size();
+        let Scale(sx, sy) = scale;
+        (0..h)
+            .flat_map(|dy| (0..w).map(move |dx| (dx, dy)))
+            .map(|(dx, dy)| {
+                let (x, y) = (column as usize * w + dx, row as usize * h + dy);
+                heat.level(x / sx, y / sy)
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// the character for a cell's pixels, numbered from the top left
+    /// across then down, so bit 0 is top left and bit 1 top right
+    fn glyph(&self, pixels: u8) -> char {
+        const QUADRANTS: [char; 16] = [
+            ' ', '▘', '▝', '▀', '▖', '▌', '▞', '▛', '▗', '▚', '▐', '▜', '▄', '▙', '▟', '█',
+        ];
+        match (self, pixels) {
+            (Cells::Block, 0) => ' ',
+            (Cells::Block, _) => '█',
+            (Cells::Ascii, 0) => ' ',
+            (Cells::Ascii, _) => '#',
+            (Cells::Quadrant, p) => QUADRANTS[p as usize & 0xf],
+            // the sextants leave out the ones that already had characters
+            (Cells::Sextant, 0) => ' ',
+            (Cells::Sextant, 0b010101) => '▌',
+            (Cells::Sextant, 0b101010) => '▐',
+            (Cells::Sextant, p) if p >= 0b111111 => '█',
+            (Cells::Sextant, p) => {
+                let skipped = (p > 0b010101) as u32 + (p > 0b101010) as u32;
+                char::from_u32(0x1fb00 + p as u32 - 1 - skipped).unwrap_or('?')
+            }
+        }
+    }
+}
+
+#[cfg(feature = "full")]
+/// a width by height picture, drawn scale times over with cells, as lines
+/// of text: what the terminal shows, without needing a terminal
+pub fn mosaic(data: &[u8], width: usize, height: usize, cells: Cells, scale: Scale) -> Vec<String> {
+    let (w, h) = cells.size();
+    let Scale(sx, sy) = scale;
+    let (columns, rows) = ((width * sx).div_ceil(w), (height * sy).div_ceil(h));
+    (0..rows as u16)
+        .map(|row| {
+            (0..columns as u16)
+                .map(|column| cells.cell(data, width, height, scale, column, row))
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(feature = "full")]
+/// the framebuffer drawn a few pixels to a character cell, in a box with a
+/// title
+struct Mosaic<'a> {
+    resolution: &'a Resolution,
+    data: &'a [u8],
+    title: &'a str,
+    theme: &'a Theme,
+    // the colour for each colour a pixel can be
+    inks: &'a [Color],
+    cells: Cells,
+    scale: Scale,
+    // colours it in by heat instead of the theme, if there is some
+    heat: Option<&'a Heat>,
+    // what the heat's colours come out as
+    colours: Colours,
+}
+
+#[cfg(feature = "full")]
+impl<'a> Widget for Mosaic<'a> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let block = Block::default()
+            .title(Span::styled(self.title, self.theme.text_style()))
+            .borders(Borders::ALL)
+            .border_style(self.theme.text_style());
+        let inner = block.inner(area);
+        block.render(area, buf);
+        let (width, height) = (self.resolution.0, self.resolution.1);
+        let background = match (self.heat, self.theme.glyphs) {
+            (Some(_), _) => Color::Black,
+            (None, true) => Color::Reset,
+            (None, false) => self.inks[0],
+        };
+        let lit_data = self.resolution.lit(self.data);
+        let colour = |x, y| match x < width && y < height {
+            true => self.resolution.colour(self.data, x, y),
+            false => 0,
+        };
+        for row in 0..inner.height {
+            for column in 0..inner.width {
+                let mut glyph = self
+                    .cells
+                    .cell(&lit_data, width, height, self.scale, column, row);
+                // a cell's one colour, so the one most of it is
+                let mut lit = match self.inks.len() {
+                    2 => self.inks[1],
+                    _ => self.inks[self.cells.ink(colour, self.scale, column, row)],
+                };
+                if let Some(heat) = self.heat {
+                    // anything warm's a block of its colour, whether it's
+                    // lit or not, and what's lit but cold is greyed out
+                    lit = match self.cells.hottest(heat, self.scale, column, row) {
+                        0 => Color::DarkGray,
+                        l => {
+                            glyph = self.cells.glyph(u8::MAX);
+                            let (r, g, b) = Heat::colour(l);
+                            self.colours.nearest(Color::Rgb(r, g, b))
+                        }
+                    };
+                }
+                buf.get_mut(inner.x + column, inner.y + row)
+                    .set_char(glyph)
+                    .set_fg(lit)
+                    .set_bg(background);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "full")]
+/// the part of rect inside area: empty if none of it is, where tui's
+/// Rect::intersection would underflow instead
+fn clip(rect: Rect, area: Rect) -> Rect {
+    match rect.intersects(area) {
+        true => rect.intersection(area),
+        false => Rect::default(),
+    }
+}
+
+#[cfg(feature = "full")]
+/// where a width x height box goes to sit in the middle of area, or at its
+/// top left if it doesn't fit
+fn centred(area: Rect, width: u16, height: u16) -> Rect {
+    let rect = Rect::new(
+        area.x + area.width.saturating_sub(width) / 2,
+        area.y + area.height.saturating_sub(height) / 2,
+        width,
+        height,
+    );
+    clip(rect, area)
+}
+
+#[cfg(feature = "full")]
+/// status line goes underneath the canvas, if the terminal has room
+fn render_status(
+    f: &mut Frame<CrosstermBackend<io::Stdout>>,
+    canvas: Rect,
+    status: &str,
+    theme: &Theme,
+) {
+    let status_size = clip(
+        Rect::new(
+            canvas.x,
+            canvas.bottom(),
+            f.size().width.saturating_sub(canvas.x),
+            1,
+        ),
+        f.size(),
+    );
+    if !status.is_empty() && status_size.area() > 0 {
+        f.render_widget(
+            Paragraph::new(Span::styled(status, theme.text_style())),
+            status_size,
+        );
+    }
+}
+
+#[cfg(feature = "full")]
 /// monochrome display in a terminal, rendered using TUI and Crossterm
 pub struct MonoTermDisplay {
     terminal: Terminal<CrosstermBackend<io::Stdout>>,
     resolution: Resolution,
+    status: String,
+    // shown instead of the status for a while
+    notice: String,
+    notice_frames: u32,
+    // shown after the status, unless it's 1x
+    speed: f64,
+    // ditto, unless it's full
+    volume: Volume,
+    // ditto, unless it's the game's
+    focus: Focus,
+    // something besides the frame needs redrawing
+    stale: bool,
+    hud: Option<Hud>,
+    heat: Option<Heat>,
+    help: Option<Vec<String>>,
+    slots: Option<Vec<String>>,
+    palette: Option<Vec<String>>,
+    // draw the keypad, for --touch
+    keypad: bool,
+    theme: Theme,
+    // what XO-CHIP's planes come out as
+    plane_colours: PlaneColours,
+    cells: Cells,
+    scale: Scale,
+    // how many colours the terminal has, for the theme's to come out as
+    colours: Colours,
+    // the terminal's size as of the last draw, to recentre when it changes
+    terminal_size: Rect,
 }
 
+#[cfg(feature = "full")]
 impl MonoTermDisplay {
-    pub fn new(x: usize, y: usize) -> Result<MonoTermDisplay, io::Error> {
+    pub fn new(x: usize, y: usize) -> Result<MonoTermDisplay, Chip8Error> {
         let stdout = io::stdout();
         let backend = CrosstermBackend::new(stdout);
         let terminal = Terminal::new(backend)?;
         Ok(MonoTermDisplay {
             terminal,
             resolution: Resolution(x, y, 1),
+            status: String::new(),
+            notice: String::new(),
+            notice_frames: 0,
+            speed: 1.0,
+            volume: Volume::default(),
+            focus: Focus::Game,
+            stale: true,
+            hud: None,
+            heat: None,
+            help: None,
+            slots: None,
+            palette: None,
+            keypad: false,
+            theme: Theme::default(),
+            plane_colours: PlaneColours::default(),
+            cells: Cells::Block,
+            scale: Scale::default(),
+            colours: Colours::TrueColour,
+            terminal_size: Rect::default(),
         })
     }
 
-    pub fn test_card(&mut self) -> Result<(), io::Error> {
+    /// draw the keypad in the corner, for touchscreens
+    pub fn set_keypad(&mut self, keypad: bool) {
+        self.keypad = keypad;
+        self.stale = true;
+    }
+
+    /// draw more than one pixel to each character, e.g. so SCHIP's hires
+    /// mode fits a small terminal
+    pub fn set_cells(&mut self, cells: Cells) -> Result<(), Chip8Error> {
+        self.cells = cells;
+        self.refresh()
+    }
+
+    /// colour XO-CHIP's planes in with these
+    pub fn set_plane_colours(&mut self, colours: PlaneColours) {
+        self.plane_colours = colours;
+        self.stale = true;
+    }
+
+    /// draw each pixel bigger
+    pub fn set_scale(&mut self, scale: Scale) -> Result<(), Chip8Error> {
+        self.scale = scale;
+        self.refresh()
+    }
+
+    /// draw in no more colours than these, the nearest to the theme's
+    pub fn set_colours(&mut self, colours: Colours) {
+        self.colours = colours;
+        self.stale = true;
+    }
+
+    pub fn test_card(&mut self) -> Result<(), Chip8Error> {
         self.draw(&CHIP8_TEST_CARD)
     }
 }
 
+#[cfg(feature = "full")]
 impl Display for MonoTermDisplay {
-    fn draw(&mut self, data: &[u8]) -> Result<(), io::Error> {
+    fn draw(&mut self, data: &[u8]) -> Result<(), Chip8Error> {
         // make sure we're given exactly the right amount of data to draw
-        assert_eq!(
-            data.len(),
-            self.resolution.byte_count(),
-            "MonoTermDisplay must have correct-sized data to draw"
-        );
-        // i don't know how to draw things that aren't mono
-        assert_eq!(
-            self.resolution.2, 1,
-            "MonoTermDisplay can only render one bitplane"
-        );
-
-        // for now this assumes a 1:1 ratio between terminal, chip8 and the
-        // internal TUI canvas
+        if data.len() != self.resolution.byte_count() {
+            return Err(Chip8Error::DisplayError(format!(
+                "MonoTermDisplay must have correct-sized data to draw (got {} bytes, not {})",
+                data.len(),
+                self.resolution.byte_count()
+            )));
+        }
+        // as near to the theme's colours as the terminal gets
+        let theme = self.colours.theme(self.theme);
+        let inks: Vec<Color> = self
+            .plane_colours
+            .inks(&theme, self.resolution.2)
+            .into_iter()
+            .map(|c| self.colours.nearest(c))
+            .collect();
         self.terminal.draw(|f| {
-            let size = Rect::new(
-                0,
-                0,
-                2 + self.resolution.0 as u16,
-                2 + self.resolution.1 as u16,
+            // each character cell's cells.size() scaled-up pixels, in a box
+            let (w, h) = self.cells.size();
+            let Scale(sx, sy) = self.scale;
+            let width = 2 + (self.resolution.0 * sx).div_ceil(w) as u16;
+            let height = 2 + (self.resolution.1 * sy).div_ceil(h) as u16;
+            // centred along with the HUD beside it and the status under it,
+            // with black bars round the lot
+            let hud_width = self.hud.as_ref().map_or(0, |hud| {
+                1 + hud
+                    .lines()
+                    .iter()
+                    .map(|l| l.chars().count())
+                    .max()
+                    .unwrap_or(0) as u16
+            });
+            let whole = centred(f.size(), width + hud_width, height + 1);
+            let size = clip(Rect::new(whole.x, whole.y, width, height), f.size());
+            f.render_widget(
+                Block::default().style(Style::default().bg(Color::Black)),
+                f.size(),
             );
-
-            let canvas = Canvas::default()
-                .block(
-                    Block::default()
-                        .title("CHIP-8")
-                        .borders(Borders::ALL)
-                        .style(Style::default().bg(Color::Black)),
-                )
-                .x_bounds(self.resolution.x_bounds())
-                .y_bounds(self.resolution.y_bounds())
-                .marker(Marker::Block) //Braille
-                .paint(|ctx| {
-                    // expand each bitplane into x, y float coords, suitable for
-                    // rendering with TUI. this just prints blocky points for now
-                    ctx.draw(&Points {
-                        coords: &self
-                            .resolution
-                            .bitplane_from_data(&data, 0)
-                            .collect::<Vec<_>>(),
-                        color: Color::Black,
-                    });
-                    ctx.draw(&Points {
-                        coords: &self
-                            .resolution
-                            .bitplane_from_data(&data, 1)
-                            .collect::<Vec<_>>(),
-                        color: Color::White,
-                    });
-                });
-            f.render_widget(canvas, size);
+            match self.cells {
+                // tui's canvas can't cope with nothing inside its border
+                Cells::Block if size.width < 3 || size.height < 3 => {}
+                // nor with a colour a pixel, for the heat map
+                Cells::Block if self.heat.is_none() => f.render_widget(
+                    canvas(
+                        &self.resolution,
+                        data,
+                        "CHIP-8",
+                        &theme,
+                        inks.clone(),
+                        self.scale,
+                    ),
+                    size,
+                ),
+                cells => f.render_widget(
+                    Mosaic {
+                        resolution: &self.resolution,
+                        data,
+                        title: "CHIP-8",
+                        theme: &theme,
+                        inks: &inks,
+                        cells,
+                        scale: self.scale,
+                        heat: self.heat.as_ref(),
+                        colours: self.colours,
+                    },
+                    size,
+                ),
+            }
+            let status = match self.notice_frames {
+                0 => status_line(&self.status, self.speed, self.volume, self.focus),
+                _ => self.notice.clone(),
+            };
+            render_status(f, size, &status, &theme);
+            if let Some(hud) = &self.hud {
+                render_hud(f, size, hud);
+            }
+            if self.keypad {
+                render_keypad(f, &theme);
+            }
+            if let Some(help) = &self.help {
+                render_overlay(f, help, " keys ", &theme);
+            }
+            if let Some(slots) = &self.slots {
+                render_overlay(f, slots, " savestates ", &theme);
+            }
+            if let Some(palette) = &self.palette {
+                render_overlay(f, palette, " commands ", &theme);
+            }
         })?;
+        // the status comes back when the notice runs out
+        self.stale = self.notice_frames == 1;
+        self.terminal_size = self.terminal.size()?;
+        self.notice_frames = self.notice_frames.saturating_sub(1);
         Ok(())
     }
 
+    /// a frame with nothing new in it isn't worth going through the terminal
+    /// for, unless a notice is counting down or the terminal's been resized
+    fn draw_changes(
+        &mut self,
+        data: &[u8],
+        changed: Option<&[Range<usize>]>,
+    ) -> Result<(), Chip8Error> {
+        let resized = self.terminal.size()? != self.terminal_size;
+        if changed.is_some_and(|c| c.is_empty())
+            && !self.stale
+            && !resized
+            && self.notice_frames == 0
+        {
+            return Ok(());
+        }
+        self.draw(data)
+    }
+
     /// how big the display data should be
     fn get_display_size_bytes(&mut self) -> usize {
         self.resolution.byte_count()
     }
+
+    fn set_mode(&mut self, width: usize, height: usize) -> Result<(), Chip8Error> {
+        self.resolution = Resolution(width, height, self.resolution.2);
+        self.stale = true;
+        // the old, differently-sized frame would be left around the new one
+        self.refresh()
+    }
+
+    /// one plane's drawn in the theme's colours, and two in the plane
+    /// colours
+    fn set_planes(&mut self, planes: usize) -> Result<(), Chip8Error> {
+        if !(1..=2).contains(&planes) {
+            return Err(Chip8Error::DisplayError(format!(
+                "MonoTermDisplay can't draw {} planes",
+                planes
+            )));
+        }
+        self.resolution.2 = planes;
+        self.stale = true;
+        Ok(())
+    }
+
+    fn set_status(&mut self, status: &str) {
+        self.status = status.to_string();
+        self.stale = true;
+    }
+
+    fn notify(&mut self, notice: &str) {
+        self.notice = notice.to_string();
+        self.notice_frames = NOTICE_FRAMES;
+    }
+
+    fn set_speed(&mut self, speed: f64) {
+        self.speed = speed;
+        self.stale = true;
+    }
+
+    fn set_volume(&mut self, volume: Volume) {
+        self.volume = volume;
+        self.stale = true;
+    }
+
+    fn set_focus(&mut self, focus: Focus) {
+        self.focus = focus;
+        self.stale = true;
+    }
+
+    fn set_hud(&mut self, hud: Option<Hud>) {
+        if hud != self.hud {
+            self.stale = true;
+        }
+        // taking it away leaves it on the terminal otherwise
+        if hud.is_none() && self.hud.is_some() {
+            let _ = self.terminal.clear();
+        }
+        self.hud = hud;
+    }
+
+    fn set_heat(&mut self, heat: Option<Heat>) {
+        // it changes as it cools, even when the picture doesn't
+        self.stale |= heat.is_some() || self.heat.is_some();
+        self.heat = heat;
+    }
+
+    fn set_help(&mut self, help: Option<Vec<String>>) {
+        // like the HUD, it'd be left behind
+        if help.is_none() && self.help.is_some() {
+            let _ = self.terminal.clear();
+        }
+        self.help = help;
+        self.stale = true;
+    }
+
+    fn set_slots(&mut self, slots: Option<Vec<String>>) {
+        if slots.is_none() && self.slots.is_some() {
+            let _ = self.terminal.clear();
+        }
+        self.slots = slots;
+        self.stale = true;
+    }
+
+    fn set_palette(&mut self, palette: Option<Vec<String>>) {
+        if palette.is_none() && self.palette.is_some() {
+            let _ = self.terminal.clear();
+        }
+        self.palette = palette;
+        self.stale = true;
+    }
+
+    fn set_theme(&mut self, theme: Theme) {
+        self.theme = theme;
+        self.stale = true;
+    }
+
+    fn refresh(&mut self) -> Result<(), Chip8Error> {
+        self.stale = true;
+        self.terminal.clear()?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "full")]
+/// two machines' displays side by side in a terminal, e.g. to compare how a
+/// ROM runs on each. each machine draws through its own SplitHalf
+pub struct SplitTermDisplay {
+    terminal: Terminal<CrosstermBackend<io::Stdout>>,
+    // each half can be in a different mode
+    resolutions: [Resolution; 2],
+    titles: [String; 2],
+    // the last thing each half drew
+    frames: [Vec<u8>; 2],
+    status: String,
+    notice: String,
+    notice_frames: u32,
+    theme: Theme,
+}
+
+#[cfg(feature = "full")]
+impl SplitTermDisplay {
+    pub fn new(x: usize, y: usize, titles: [&str; 2]) -> Result<SplitTermDisplay, Chip8Error> {
+        let blank = vec![0; Resolution(x, y, 1).byte_count()];
+        Ok(SplitTermDisplay {
+            terminal: Terminal::new(CrosstermBackend::new(io::stdout()))?,
+            titles: titles.map(String::from),
+            frames: [blank.clone(), blank],
+            resolutions: [Resolution(x, y, 1), Resolution(x, y, 1)],
+            status: String::new(),
+            notice: String::new(),
+            notice_frames: 0,
+            theme: Theme::default(),
+        })
+    }
+
+    pub fn set_theme(&mut self, theme: Theme) {
+        self.theme = theme;
+    }
+
+    fn draw_half(&mut self, side: usize, data: &[u8]) -> Result<(), Chip8Error> {
+        if data.len() != self.resolutions[side].byte_count() {
+            return Err(Chip8Error::DisplayError(format!(
+                "SplitTermDisplay must have correct-sized data to draw (got {} bytes, not {})",
+                data.len(),
+                self.resolutions[side].byte_count()
+            )));
+        }
+        self.frames[side] = data.to_vec();
+        // the right half draws last, so wait for it and paint both at once
+        if side == 0 {
+            return Ok(());
+        }
+        self.terminal.draw(|f| {
+            // both halves get room for the bigger of the two
+            let width = 2 + self.resolutions.iter().map(|r| r.0).max().unwrap_or(0) as u16;
+            let height = 2 + self.resolutions.iter().map(|r| r.1).max().unwrap_or(0) as u16;
+            for (side, frame) in self.frames.iter().enumerate() {
+                let r = &self.resolutions[side];
+                let size = clip(
+                    Rect::new(side as u16 * width, 0, 2 + r.0 as u16, 2 + r.1 as u16),
+                    f.size(),
+                );
+                if size.area() == 0 {
+                    continue;
+                }
+                f.render_widget(
+                    canvas(
+                        r,
+                        frame,
+                        &self.titles[side],
+                        &self.theme,
+                        vec![self.theme.unlit, self.theme.lit],
+                        Scale::default(),
+                    ),
+                    size,
+                );
+            }
+            let status = if self.notice_frames > 0 {
+                &self.notice
+            } else {
+                &self.status
+            };
+            render_status(f, Rect::new(0, 0, width, height), status, &self.theme);
+        })?;
+        self.notice_frames = self.notice_frames.saturating_sub(1);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "full")]
+/// one machine's half of a SplitTermDisplay
+pub struct SplitHalf<'s> {
+    screen: &'s RefCell<SplitTermDisplay>,
+    side: usize,
+}
+
+#[cfg(feature = "full")]
+impl<'s> SplitHalf<'s> {
+    pub fn left(screen: &'s RefCell<SplitTermDisplay>) -> Self {
+        SplitHalf { screen, side: 0 }
+    }
+
+    pub fn right(screen: &'s RefCell<SplitTermDisplay>) -> Self {
+        SplitHalf { screen, side: 1 }
+    }
+}
+
+#[cfg(feature = "full")]
+impl<'s> Display for SplitHalf<'s> {
+    fn draw(&mut self, data: &[u8]) -> Result<(), Chip8Error> {
+        self.screen.borrow_mut().draw_half(self.side, data)
+    }
+
+    fn get_display_size_bytes(&mut self) -> usize {
+        self.screen.borrow().resolutions[self.side].byte_count()
+    }
+
+    fn set_mode(&mut self, width: usize, height: usize) -> Result<(), Chip8Error> {
+        let mut screen = self.screen.borrow_mut();
+        screen.resolutions[self.side] = Resolution(width, height, 1);
+        screen.frames[self.side] = vec![0; screen.resolutions[self.side].byte_count()];
+        screen.terminal.clear()?;
+        Ok(())
+    }
+
+    fn set_status(&mut self, status: &str) {
+        self.screen.borrow_mut().status = status.to_string();
+    }
+
+    fn notify(&mut self, notice: &str) {
+        let mut screen = self.screen.borrow_mut();
+        screen.notice = notice.to_string();
+        screen.notice_frames = NOTICE_FRAMES;
+    }
+
+    fn refresh(&mut self) -> Result<(), Chip8Error> {
+        self.screen.borrow_mut().terminal.clear()?;
+        Ok(())
+    }
 }
 
 /// useful for testing non-display routines
@@ -173,85 +1360,209 @@ pub struct DummyDisplay;
 
 impl DummyDisplay {
     #[allow(dead_code)]
-    pub fn new() -> Result<DummyDisplay, io::Error> {
+    pub fn new() -> Result<DummyDisplay, Chip8Error> {
         Ok(DummyDisplay {})
     }
 }
 
 impl Display for DummyDisplay {
     #[allow(unused)]
-    fn draw(&mut self, data: &[u8]) -> Result<(), io::Error> {
+    fn draw(&mut self, data: &[u8]) -> Result<(), Chip8Error> {
         Ok(())
     }
     fn get_display_size_bytes(&mut self) -> usize {
         0x100
     }
+
+    #[allow(unused)]
+    fn set_mode(&mut self, width: usize, height: usize) -> Result<(), Chip8Error> {
+        Ok(())
+    }
 }
 
+/// displays for tests, that remember what they were given
 #[cfg(test)]
-mod tests {
-    use super::*;
+pub(crate) mod fixtures {
+    use super::Display;
+    use crate::error::Chip8Error;
+    use std::sync::{Arc, Mutex};
 
-    // Resolution tests
-    #[test]
-    fn test_pixel_count() {
-        let r = Resolution(64, 32, 1);
-        assert_eq!(r.pixel_count(), 2048)
+    /// remembers the last frame drawn (only the full emulator's tests want
+    /// it)
+    #[cfg(feature = "full")]
+    pub struct LastFrame(pub Vec<u8>);
+
+    #[cfg(feature = "full")]
+    impl Display for LastFrame {
+        fn draw(&mut self, data: &[u8]) -> Result<(), Chip8Error> {
+            self.0 = data.to_vec();
+            Ok(())
+        }
+
+        fn get_display_size_bytes(&mut self) -> usize {
+            0x100
+        }
+
+        fn set_mode(&mut self, _width: usize, _height: usize) -> Result<(), Chip8Error> {
+            Ok(())
+        }
     }
 
-    #[test]
-    fn test_byte_count() {
-        let r = Resolution(64, 32, 1);
-        assert_eq!(r.byte_count(), 256)
+    /// remembers every frame drawn, where a clone of it can see them, even
+    /// from another thread
+    #[derive(Clone, Default)]
+    pub struct Frames(Arc<Mutex<Vec<Vec<u8>>>>);
+
+    impl Frames {
+        /// the frames drawn so far
+        pub fn drawn(&self) -> Vec<Vec<u8>> {
+            self.0.lock().unwrap().clone()
+        }
     }
 
-    #[test]
-    fn test_x_bounds() {
-        let r = Resolution(64, 32, 1);
-        assert_eq!(r.x_bounds(), [0.0, 63.0]);
+    impl Display for Frames {
+        fn draw(&mut self, data: &[u8]) -> Result<(), Chip8Error> {
+            self.0.lock().unwrap().push(data.to_vec());
+            Ok(())
+        }
+
+        fn get_display_size_bytes(&mut self) -> usize {
+            0x100
+        }
+
+        fn set_mode(&mut self, _width: usize, _height: usize) -> Result<(), Chip8Error> {
+            Ok(())
+        }
     }
+}
 
-    #[test]
-    fn test_y_bounds() {
-        let r = Resolution(64, 32, 1);
-        assert_eq!(r.y_bounds(), [-31.0, 0.0]);
+/// a frame as rows of '#' for a lit pixel and '.' for an unlit one, a line
+/// each, e.g. for snapshots in tests that show what went wrong when they
+/// don't match
+pub fn to_ascii(frame: &[u8], width: usize) -> String {
+    frame
+        .chunks(width / 8)
+        .map(|row| {
+            row.iter()
+                .flat_map(|b| (0..8).map(move |x| if b & (0x80 >> x) == 0 { '.' } else { '#' }))
+                .chain(std::iter::once('\n'))
+                .collect::<String>()
+        })
+        .collect()
+}
+
+/// how often TextDisplay describes the screen, in frames: once a second
+const TEXT_DISPLAY_FRAMES: u32 = 60;
+
+/// experimental: describes the screen as lines of text, '#' for a lit pixel
+/// and '.' for an unlit one, once a second (and only if it's changed), with
+/// the status and notices as lines of their own. nothing's redrawn in
+/// place, so it works in a plain scrolling terminal, with a screen reader,
+/// or into a log file
+pub struct TextDisplay<W: Write> {
+    out: W,
+    // what ends a line: a terminal in raw mode wants "\r\n"
+    line_end: &'static str,
+    resolution: Resolution,
+    frames: u32,
+    // the last screen described
+    last: Vec<u8>,
+    status: String,
+}
+
+impl<W: Write> TextDisplay<W> {
+    pub fn new(out: W, line_end: &'static str) -> Self {
+        TextDisplay {
+            out,
+            line_end,
+            resolution: Resolution(64, 32, 1),
+            frames: 0,
+            last: Vec::new(),
+            status: String::new(),
+        }
     }
 
-    #[test]
-    fn test_px_iterator() {
-        let r = Resolution(64, 32, 1);
-        let px = r.points_from_data(&[0; 256]);
-        for (_x, _y, colour) in px {
-            assert_eq!(colour, Color::Black);
+    fn line(&mut self, text: &str) -> Result<(), Chip8Error> {
+        write!(self.out, "{}{}", text, self.line_end)?;
+        Ok(())
+    }
+}
+
+impl<W: Write> Display for TextDisplay<W> {
+    fn draw(&mut self, data: &[u8]) -> Result<(), Chip8Error> {
+        self.frames += 1;
+        if self.frames < TEXT_DISPLAY_FRAMES || data == self.last {
+            return Ok(());
+        }
+        self.frames = 0;
+        self.last = data.to_vec();
+        let ascii = to_ascii(data, self.resolution.0);
+        self.line("")?;
+        for row in ascii.lines() {
+            self.line(row)?;
         }
+        self.out.flush()?;
+        Ok(())
     }
 
-    // MonoTermDisplay tests
-    #[test]
-    fn test_display_size() {
-        let mut d = MonoTermDisplay::new(64, 32).unwrap();
-        assert_eq!(d.get_display_size_bytes(), 256);
+    fn get_display_size_bytes(&mut self) -> usize {
+        self.resolution.byte_count()
     }
 
-    #[test]
-    #[should_panic]
-    fn test_draw_rejects_wrong_data() {
-        let mut d = MonoTermDisplay::new(64, 32).unwrap();
-        let _ = d.draw(&[0; 257]);
+    fn set_mode(&mut self, width: usize, height: usize) -> Result<(), Chip8Error> {
+        self.resolution = Resolution(width, height, 1);
+        self.line(&format!("mode: {}x{}", width, height))?;
+        self.refresh()
     }
 
-    #[test]
-    #[ignore]
-    // NB. figure out how to stop rendering during tests
-    fn test_draw_accepts_test_card() -> Result<(), io::Error> {
-        let mut d = MonoTermDisplay::new(64, 32).unwrap();
-        d.draw(&CHIP8_TEST_CARD)
+    fn set_status(&mut self, status: &str) {
+        if status != self.status {
+            self.status = status.to_string();
+            // errors turn up on the next draw
+            let _ = self.line(&format!("status: {}", status));
+        }
+    }
+
+    fn notify(&mut self, notice: &str) {
+        let _ = self.line(&format!("notice: {}", notice));
+    }
+
+    fn set_focus(&mut self, focus: Focus) {
+        let _ = self.line(match focus {
+            Focus::Game => "focus: game",
+            Focus::Emulator => "focus: emulator",
+        });
+    }
+
+    fn set_help(&mut self, help: Option<Vec<String>>) {
+        for line in help.unwrap_or_default() {
+            let _ = self.line(&format!("help: {}", line));
+        }
+    }
+
+    fn set_slots(&mut self, slots: Option<Vec<String>>) {
+        for line in slots.unwrap_or_default() {
+            let _ = self.line(&format!("slots: {}", line));
+        }
+    }
+
+    fn set_palette(&mut self, palette: Option<Vec<String>>) {
+        for line in palette.unwrap_or_default() {
+            let _ = self.line(&format!("commands: {}", line));
+        }
+    }
+
+    fn refresh(&mut self) -> Result<(), Chip8Error> {
+        // describe the next frame, whatever it looks like
+        self.last.clear();
+        self.frames = TEXT_DISPLAY_FRAMES;
+        Ok(())
     }
 }
 
 /// this is a display test card suitable for CHIP8, for testing display routines
 #[rustfmt::skip]
-const CHIP8_TEST_CARD: [u8; 256] = [
+pub const CHIP8_TEST_CARD: [u8; 256] = [
     0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, // 00 XXXXXXX|XXXXXXX|XXXXXXX|XXXXXXX|XXXXXXX|XXXXXXX|XXXXXXX|XXXXXXX|
     0x80, 0x00, 0x00, 0x01, 0x80, 0x00, 0x00, 0x01, // 01 X                              |X                              |
     0x80, 0x00, 0x00, 0x03, 0xc2, 0x41, 0x55, 0x55, // 02 X                             X|XX    X  X     | X X X | X X X |
@@ -285,3 +1596,402 @@ const CHIP8_TEST_CARD: [u8; 256] = [
     0x80, 0x00, 0x00, 0x01, 0x80, 0x00, 0x00, 0x01, // 30 X                              |X                              |
     0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, // 31 XXXXXXX|XXXXXXX|XXXXXXX|XXXXXXX|XXXXXXX|XXXXXXX|XXXXXXX|XXXXXXX|
 ]; //                                                  .. 0......78......f0......78......f0......78......f0......78......f
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a display written against the trait as it was, before set_mode
+    struct Plain;
+
+    impl Display for Plain {
+        fn draw(&mut self, _data: &[u8]) -> Result<(), Chip8Error> {
+            Ok(())
+        }
+
+        fn get_display_size_bytes(&mut self) -> usize {
+            0x100
+        }
+    }
+
+    #[test]
+    fn test_default_mode() {
+        // gets on with the VIP's screen, and says so about anything else
+        assert!(Plain.set_mode(64, 32).is_ok());
+        assert!(Plain.set_mode(128, 64).is_err());
+    }
+
+    #[test]
+    fn test_hud_lines() {
+        let hud = Hud {
+            delay_timer: 0xff,
+            sound_timer: 0x01,
+            random: 0x1234,
+            opcode: 0xd015,
+            beeps: 0x8000_0003,
+            waiting_for_key: false,
+            cycles: [FrameCycles::default(); HUD_SCOPE_FRAMES],
+        };
+        assert_eq!(
+            hud.lines(),
+            [
+                "DT ff ████████",
+                "ST 01 █░░░░░░░",
+                "RND 1234",
+                "OP  d015",
+                "BZ  █▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁██",
+                "FE  ▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁   0%",
+                "EX  ▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁   0%",
+                "IR  ▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁   0%",
+                "ID  ▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁▁   0%",
+            ]
+        );
+        // a quarter of the cycles fetching, half executing, a quarter in the
+        // interrupt, in the latest frame
+        let mut busy = hud;
+        busy.cycles[HUD_SCOPE_FRAMES - 1] = FrameCycles {
+            fetch: 900,
+            execute: 1800,
+            interrupt: 900,
+            idle: 0,
+        };
+        let lines = busy.lines();
+        assert!(lines[5].ends_with("▁▃  25%"));
+        assert!(lines[6].ends_with("▁▅  50%"));
+        assert!(lines[8].ends_with("▁▁   0%"));
+        let waiting = Hud {
+            opcode: 0xf30a,
+            waiting_for_key: true,
+            ..hud
+        };
+        assert_eq!(waiting.lines()[3], "OP  f30a KEY?");
+    }
+
+    #[test]
+    fn test_changed_ranges() {
+        let old = [0, 1, 2, 3, 4, 5];
+        assert_eq!(changed_ranges(&old, &old), []);
+        assert_eq!(
+            changed_ranges(&old, &[9, 1, 9, 9, 4, 9]),
+            [0..1, 2..4, 5..6]
+        );
+    }
+
+    #[test]
+    fn test_byte_count() {
+        let r = Resolution(64, 32, 1);
+        assert_eq!(r.byte_count(), 256)
+    }
+
+    #[test]
+    fn test_text_display() -> Result<(), Chip8Error> {
+        let mut out = Vec::new();
+        let mut d = TextDisplay::new(&mut out, "\n");
+        d.set_status("Pong");
+        let mut frame = [0u8; 256];
+        frame[0] = 0xc0;
+        // the first second's described at the end of it, and the next
+        // second not at all, as nothing's changed
+        for _ in 0..120 {
+            d.draw(&frame)?;
+        }
+        d.notify("hello");
+        d.refresh()?;
+        d.draw(&frame)?;
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 1 + 2 * 33 + 1);
+        assert_eq!(lines[0], "status: Pong");
+        assert_eq!(lines[2], format!("##{}", ".".repeat(62)));
+        assert_eq!(lines[3], ".".repeat(64));
+        assert_eq!(lines[34], "notice: hello");
+        Ok(())
+    }
+
+    #[test]
+    fn test_to_ascii() {
+        assert_eq!(
+            to_ascii(&[0x80, 0x01, 0xff, 0x00], 16),
+            "#..............#\n########........\n"
+        );
+        let card = to_ascii(&CHIP8_TEST_CARD, 64);
+        assert_eq!(card.lines().count(), 32);
+        assert_eq!(card.lines().next(), Some("#".repeat(64).as_str()));
+    }
+
+    #[cfg(feature = "full")]
+    mod full {
+        use super::*;
+
+        #[test]
+        fn test_status_line() {
+            let full = Volume::default();
+            let game = Focus::Game;
+            assert_eq!(status_line("brix", 1.0, full, game), "brix");
+            assert_eq!(status_line("brix", 2.0, full, game), "brix  [2x]");
+            let quiet = full.quieter();
+            assert_eq!(status_line("brix", 1.0, quiet, game), "brix  [vol 90%]");
+            let muted = Volume {
+                muted: true,
+                ..quiet
+            };
+            assert_eq!(
+                status_line("brix", 0.5, muted, game),
+                "brix  [0.5x]  [muted]"
+            );
+            assert_eq!(
+                status_line("brix", 1.0, full, Focus::Emulator),
+                "brix  [keys: emulator]"
+            );
+        }
+
+        // Resolution tests
+        #[test]
+        fn test_pixel_count() {
+            let r = Resolution(64, 32, 1);
+            assert_eq!(r.pixel_count(), 2048)
+        }
+
+        #[test]
+        fn test_x_bounds() {
+            let r = Resolution(64, 32, 1);
+            assert_eq!(r.x_bounds(), [0.0, 63.0]);
+        }
+
+        #[test]
+        fn test_y_bounds() {
+            let r = Resolution(64, 32, 1);
+            assert_eq!(r.y_bounds(), [-31.0, 0.0]);
+        }
+
+        #[test]
+        fn test_px_iterator() {
+            let r = Resolution(64, 32, 1);
+            let px = r.points_from_data(&[0; 256]);
+            for (_x, _y, colour) in px {
+                assert_eq!(colour, Color::Black);
+            }
+        }
+
+        #[test]
+        fn test_theme() -> Result<(), Chip8Error> {
+            assert_eq!(Theme::parse("classic")?, Theme::default());
+            let t = Theme::parse("high-contrast")?.inverted();
+            assert_eq!(t.lit, Color::Rgb(0, 0, 0));
+            assert_eq!(t.unlit, Color::Rgb(0xff, 0xff, 0xff));
+            assert!(t.bold);
+            assert!(Theme::parse("neon").is_err());
+            Ok(())
+        }
+
+        #[test]
+        fn test_cells() -> Result<(), Chip8Error> {
+            assert_eq!(Cells::parse("sextant")?, Cells::Sextant);
+            assert!(Cells::parse("hex").is_err());
+            assert_eq!(Cells::parse("ascii")?.glyph(0b1), '#');
+            assert_eq!(Cells::Ascii.glyph(0), ' ');
+            // top left alone, then the first and last of the sextant block
+            assert_eq!(Cells::Sextant.glyph(0b000001), '\u{1fb00}');
+            assert_eq!(Cells::Sextant.glyph(0b111110), '\u{1fb3b}');
+            // either side of the gaps for the half blocks
+            assert_eq!(Cells::Sextant.glyph(0b010100), '\u{1fb13}');
+            assert_eq!(Cells::Sextant.glyph(0b010110), '\u{1fb14}');
+            assert_eq!(Cells::Sextant.glyph(0b101001), '\u{1fb27}');
+            assert_eq!(Cells::Sextant.glyph(0b101011), '\u{1fb28}');
+            assert_eq!(Cells::Sextant.glyph(0b010101), '▌');
+            assert_eq!(Cells::Quadrant.glyph(0b1001), '▚');
+            Ok(())
+        }
+
+        #[test]
+        fn test_scale() -> Result<(), Chip8Error> {
+            assert_eq!(Scale::parse("2x1")?, Scale(2, 1));
+            assert_eq!(Scale::parse("3")?, Scale(3, 3));
+            assert!(Scale::parse("0x1").is_err());
+            assert!(Scale::parse("wide").is_err());
+
+            // a pixel 2x1 with quadrants is the top half of a cell
+            let resolution = Resolution(64, 32, 1);
+            let mut data = [0u8; 256];
+            data[0] = 0x80;
+            let area = Rect::new(0, 0, 66, 18);
+            let mut buf = Buffer::empty(area);
+            Mosaic {
+                resolution: &resolution,
+                data: &data,
+                title: "",
+                theme: &Theme::default(),
+                inks: &[Color::Black, Color::White],
+                cells: Cells::Quadrant,
+                scale: Scale(2, 1),
+                heat: None,
+                colours: Colours::TrueColour,
+            }
+            .render(area, &mut buf);
+            assert_eq!(buf.get(1, 1).symbol, "▀");
+            assert_eq!(buf.get(2, 1).symbol, " ");
+
+            // and with blocks, two cells across
+            let mut buf = Buffer::empty(Rect::new(0, 0, 130, 34));
+            canvas(
+                &resolution,
+                &data,
+                "",
+                &Theme::default(),
+                vec![Color::Black, Color::White],
+                Scale(2, 1),
+            )
+            .render(buf.area, &mut buf);
+            assert_eq!(buf.get(1, 1).fg, Color::White);
+            assert_eq!(buf.get(2, 1).fg, Color::White);
+            assert_eq!(buf.get(3, 1).fg, Color::Black);
+            assert_eq!(buf.get(1, 2).fg, Color::Black);
+            Ok(())
+        }
+
+        #[test]
+        fn test_centred() {
+            let area = Rect::new(0, 0, 80, 25);
+            assert_eq!(centred(area, 66, 35), Rect::new(7, 0, 66, 25));
+            assert_eq!(centred(area, 34, 13), Rect::new(23, 6, 34, 13));
+            assert_eq!(centred(area, 100, 10), Rect::new(0, 7, 80, 10));
+        }
+
+        #[test]
+        fn test_mosaic() {
+            let resolution = Resolution(64, 32, 1);
+            let mut data = [0u8; 256];
+            // a 2x3 block in the top left, and one pixel in the cell beside it
+            data[0] = 0xc8;
+            data[8] = 0xc0;
+            data[16] = 0xc0;
+            let area = Rect::new(0, 0, 34, 13);
+            let mut buf = Buffer::empty(area);
+            Mosaic {
+                resolution: &resolution,
+                data: &data,
+                title: "",
+                theme: &Theme::default(),
+                inks: &[Color::Black, Color::White],
+                cells: Cells::Sextant,
+                scale: Scale::default(),
+                heat: None,
+                colours: Colours::TrueColour,
+            }
+            .render(area, &mut buf);
+            assert_eq!(buf.get(1, 1).symbol, "█");
+            assert_eq!(buf.get(3, 1).symbol, "\u{1fb00}");
+            assert_eq!(buf.get(2, 1).symbol, " ");
+            assert_eq!(buf.get(1, 2).symbol, " ");
+        }
+
+        #[test]
+        fn test_planes() -> Result<(), Chip8Error> {
+            let resolution = Resolution(64, 32, 2);
+            assert_eq!(resolution.byte_count(), 512);
+            // in the first plane, the second, and both
+            let mut data = [0u8; 512];
+            data[0] = 0xa0;
+            data[256] = 0x60;
+            let colours: Vec<usize> = (0..4).map(|x| resolution.colour(&data, x, 0)).collect();
+            assert_eq!(colours, [1, 2, 3, 0]);
+            assert_eq!(resolution.lit(&data)[..1], [0xe0]);
+
+            // a quadrant's drawn in the colour most of it is
+            let area = Rect::new(0, 0, 34, 18);
+            let mut buf = Buffer::empty(area);
+            let plane_colours = PlaneColours::default();
+            Mosaic {
+                resolution: &resolution,
+                data: &data,
+                title: "",
+                theme: &Theme::default(),
+                inks: &plane_colours.inks(&Theme::default(), 2),
+                cells: Cells::Quadrant,
+                scale: Scale::default(),
+                heat: None,
+                colours: Colours::TrueColour,
+            }
+            .render(area, &mut buf);
+            assert_eq!(buf.get(1, 1).symbol, "▀");
+            assert_eq!(buf.get(1, 1).fg, plane_colours.0[1]);
+            assert_eq!(buf.get(1, 1).bg, plane_colours.0[0]);
+            assert_eq!(buf.get(2, 1).symbol, "▘");
+            assert_eq!(buf.get(2, 1).fg, plane_colours.0[3]);
+
+            assert_eq!(
+                PlaneColours::parse("#000000, #ffffff,#ff0000,#0000ff")?,
+                PlaneColours([
+                    Color::Rgb(0, 0, 0),
+                    Color::Rgb(0xff, 0xff, 0xff),
+                    Color::Rgb(0xff, 0, 0),
+                    Color::Rgb(0, 0, 0xff)
+                ])
+            );
+            assert!(PlaneColours::parse("#000000,#ffffff").is_err());
+            assert!(PlaneColours::parse("black,white,red,blue").is_err());
+            Ok(())
+        }
+
+        #[test]
+        fn test_mosaic_heat() {
+            let resolution = Resolution(64, 32, 1);
+            let mut data = [0u8; 256];
+            // two pixels lit, the second of them cold; and one unlit but hot
+            data[0] = 0xc0;
+            let mut levels = vec![0; 64 * 32];
+            levels[0] = 255;
+            levels[64 * 2] = 128;
+            let heat = Heat {
+                width: 64,
+                height: 32,
+                levels,
+            };
+            let area = Rect::new(0, 0, 66, 34);
+            let mut buf = Buffer::empty(area);
+            Mosaic {
+                resolution: &resolution,
+                data: &data,
+                title: "",
+                theme: &Theme::default(),
+                inks: &[Color::Black, Color::White],
+                cells: Cells::Block,
+                scale: Scale::default(),
+                heat: Some(&heat),
+                colours: Colours::TrueColour,
+            }
+            .render(area, &mut buf);
+            assert_eq!(buf.get(1, 1).fg, Color::Rgb(255, 255, 255));
+            assert_eq!(buf.get(2, 1).fg, Color::DarkGray);
+            assert_eq!(buf.get(2, 1).symbol, "█");
+            assert_eq!(buf.get(1, 3).fg, Color::Rgb(224, 0, 0));
+            assert_eq!(buf.get(1, 3).symbol, "█");
+            assert_eq!(buf.get(3, 1).symbol, " ");
+            assert_eq!(buf.get(3, 1).bg, Color::Black);
+        }
+
+        // MonoTermDisplay tests
+        #[test]
+        fn test_display_size() {
+            let mut d = MonoTermDisplay::new(64, 32).unwrap();
+            assert_eq!(d.get_display_size_bytes(), 256);
+        }
+
+        #[test]
+        fn test_draw_rejects_wrong_data() {
+            let mut d = MonoTermDisplay::new(64, 32).unwrap();
+            assert!(matches!(
+                d.draw(&[0; 257]),
+                Err(Chip8Error::DisplayError(_))
+            ));
+        }
+
+        #[test]
+        #[ignore]
+        // NB. figure out how to stop rendering during tests
+        fn test_draw_accepts_test_card() -> Result<(), Chip8Error> {
+            let mut d = MonoTermDisplay::new(64, 32).unwrap();
+            d.draw(&CHIP8_TEST_CARD)
+        }
+    }
+}
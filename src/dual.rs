@@ -0,0 +1,109 @@
+use crate::display::Display;
+use crate::error::Chip8Error;
+use crate::input::{HeldKey, Input};
+use crate::interpreter::{Chip8Interpreter, RunOutcome};
+use crate::quirks::Quirks;
+use crate::sound::{Mute, Sound};
+use std::cell::Cell;
+use std::time;
+
+/// run two machines in step, a frame at a time, both seeing the same keys
+/// and random numbers, each with its own ROM and quirks: e.g. one ROM under
+/// two quirks profiles, to see which it wants, or two builds of a ROM side
+/// by side. the left one gets the sound. stops after frame_count frames, or
+/// when the player asks for the menu
+pub fn run_side_by_side(
+    machines: [(&[u8], Quirks); 2],
+    displays: [&mut dyn Display; 2],
+    input: &mut dyn Input,
+    sound: &mut dyn Sound,
+    seed: u16,
+    frame_count: u64,
+) -> Result<RunOutcome, Chip8Error> {
+    let held = Cell::new(None);
+    let mut inputs = [HeldKey(&held), HeldKey(&held)];
+    let mut mute = Mute::new();
+    let [left_display, right_display] = displays;
+    let [left_input, right_input] = &mut inputs;
+    let mut left = Chip8Interpreter::new(left_display, left_input, sound)?;
+    let mut right = Chip8Interpreter::new(right_display, right_input, &mut mute)?;
+    for (machine, (rom, quirks)) in [&mut left, &mut right].into_iter().zip(machines) {
+        machine.set_seed(seed);
+        machine.set_quirks(quirks);
+        machine.load_program(&mut &rom[..])?;
+    }
+
+    let frame = time::Duration::from_secs(1) / 60;
+    let mut next = time::Instant::now();
+    for _ in 0..frame_count {
+        // one read of the keyboard a frame, shared between them
+        input.tick()?;
+        held.set(input.read_key()?);
+        if input.take_menu_request()? {
+            return Ok(RunOutcome::MenuRequested);
+        }
+        left.run_frames(1)?;
+        right.run_frames(1)?;
+        next += frame;
+        spin_sleep::sleep(next.saturating_duration_since(time::Instant::now()));
+    }
+    Ok(RunOutcome::Finished)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::fixtures::LastFrame;
+    use crate::input::DummyInput;
+    use crate::memory::Chip8MemoryMap;
+    use crate::ocr::DigitReader;
+
+    #[test]
+    fn test_both_see_the_same_key() -> Result<(), Chip8Error> {
+        // wait for a key; draw its glyph at (key, key) or (key, 0); stop
+        #[rustfmt::skip]
+        let roms: [&[u8]; 2] = [
+            &[0xf0, 0x0a, 0xf0, 0x29, 0xd0, 0x05, 0x12, 0x06],
+            &[0xf0, 0x0a, 0xf0, 0x29, 0xd0, 0x15, 0x12, 0x06],
+        ];
+        let mut left = LastFrame(vec![]);
+        let mut right = LastFrame(vec![]);
+        let mut input = DummyInput::new(&[5, 5, 5, 5]);
+        let outcome = run_side_by_side(
+            roms.map(|r| (r, Quirks::VIP)),
+            [&mut left, &mut right],
+            &mut input,
+            &mut Mute::new(),
+            0,
+            10,
+        )?;
+        assert_eq!(outcome, RunOutcome::Finished);
+        let reader = DigitReader::from_memory(&Chip8MemoryMap::new()?)?;
+        assert_eq!(reader.find_digits(&left.0), vec![(5, 5, 5)]);
+        assert_eq!(reader.find_digits(&right.0), vec![(5, 0, 5)]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_one_rom_two_quirks() -> Result<(), Chip8Error> {
+        // V0 = 8; V1 = 4; V0 = V1 >> 1 (or V0 >> 1); draw V0's glyph; stop
+        let rom: &[u8] = &[
+            0x60, 0x08, 0x61, 0x04, 0x80, 0x16, 0xf0, 0x29, 0xd2, 0x25, 0x12, 0x0a,
+        ];
+        let mut left = LastFrame(vec![]);
+        let mut right = LastFrame(vec![]);
+        run_side_by_side(
+            [(rom, Quirks::VIP), (rom, Quirks::MODERN)],
+            [&mut left, &mut right],
+            &mut DummyInput::new(&[]),
+            &mut Mute::new(),
+            0,
+            3,
+        )?;
+        // the VIP shifts VY into VX, and a modern interpreter VX in place
+        let reader = DigitReader::from_memory(&Chip8MemoryMap::new()?)?;
+        assert_eq!(reader.find_digits(&left.0), vec![(0, 0, 2)]);
+        assert_eq!(reader.find_digits(&right.0), vec![(0, 0, 4)]);
+        Ok(())
+    }
+}
@@ -0,0 +1,162 @@
+use std::{error, fmt, io};
+
+/// everything that can go wrong in the emulator, so that library consumers
+/// can tell failure modes apart
+#[derive(Debug)]
+pub enum Chip8Error {
+    /// something went wrong talking to the host
+    Io(io::Error),
+    /// the interpreter found something it can't decode at addr
+    IllegalInstruction { addr: u16, inst: u16 },
+    /// something tried to access memory that doesn't exist, from addr
+    /// (which can be past anything a u16 holds, counting from the top of it)
+    MemoryFault { addr: usize, len: usize },
+    /// a program tried to write to memory it can't, like the VIP's ROM
+    WriteProtected { addr: u16 },
+    /// inst, at pc, couldn't get count bytes from I, or put them there,
+    /// for the reason given
+    AccessFault {
+        inst: u16,
+        pc: u16,
+        i: u16,
+        count: usize,
+        reason: Box<Chip8Error>,
+    },
+    /// the display couldn't draw
+    DisplayError(String),
+    /// the sound device couldn't make (or stop) a noise
+    AudioError(String),
+    /// the config or command line doesn't make sense
+    ConfigError(String),
+    /// a netplay peer's emulator no longer matches ours
+    Desync { frame: u32 },
+    /// machine code called from a program never returned to the interpreter
+    RunawayMachineCode { addr: u16 },
+    /// a ROM didn't do what its test expected
+    TestFailure(String),
+    /// a ROM that can't be run at all, like an empty file
+    BadRom(String),
+    /// a picture that can't be read, like a sprite sheet saved as a GIF
+    BadImage(String),
+    /// a ROM patch that's damaged, or is for some other ROM
+    BadPatch(String),
+    /// FX0A at addr has waited frames frames for a key, and there's nothing
+    /// left that could press one
+    KeyDeadlock { addr: u16, frames: u64 },
+    /// the interpreter was asked to stop by its CancelToken
+    Cancelled,
+    /// something panicked while running frames, and was caught (see the
+    /// recover module): a bug in the emulator, not the program
+    Panicked(String),
+    /// a program's source didn't make sense to the assembler
+    AssemblyError {
+        file: String,
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Chip8Error::Io(e) => write!(f, "I/O error: {}", e),
+            Chip8Error::IllegalInstruction { addr, inst } => {
+                write!(f, "illegal instruction {:04x?} at {:04x?}", inst, addr)
+            }
+            Chip8Error::MemoryFault { addr, len } => {
+                write!(f, "memory fault accessing {} byte(s) at {:04x?}", len, addr)
+            }
+            Chip8Error::WriteProtected { addr } => {
+                write!(f, "{:04x?} is write-protected", addr)
+            }
+            Chip8Error::AccessFault {
+                inst,
+                pc,
+                i,
+                count,
+                reason,
+            } => write!(
+                f,
+                "{:04x?} at {:04x?}, with I = {:04x?}, for {} byte(s): {}",
+                inst, pc, i, count, reason
+            ),
+            Chip8Error::DisplayError(s) => write!(f, "display error: {}", s),
+            Chip8Error::AudioError(s) => write!(f, "audio error: {}", s),
+            Chip8Error::ConfigError(s) => write!(f, "config error: {}", s),
+            Chip8Error::Desync { frame } => write!(f, "peers desynced at frame {}", frame),
+            Chip8Error::RunawayMachineCode { addr } => {
+                write!(f, "machine code still running at {:04x?}", addr)
+            }
+            Chip8Error::TestFailure(s) => write!(f, "test failed: {}", s),
+            Chip8Error::BadRom(s) => write!(f, "bad ROM: {}", s),
+            Chip8Error::BadImage(s) => write!(f, "bad image: {}", s),
+            Chip8Error::BadPatch(s) => write!(f, "bad patch: {}", s),
+            Chip8Error::KeyDeadlock { addr, frames } => write!(
+                f,
+                "waited {} frames at {:04x?} for a key nothing's going to press",
+                frames, addr
+            ),
+            Chip8Error::Cancelled => write!(f, "cancelled"),
+            Chip8Error::Panicked(s) => write!(f, "the emulator panicked: {}", s),
+            Chip8Error::AssemblyError {
+                file,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", file, line, message),
+        }
+    }
+}
+
+impl error::Error for Chip8Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Chip8Error::Io(e) => Some(e),
+            Chip8Error::AccessFault { reason, .. } => Some(reason.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Chip8Error {
+    fn from(e: io::Error) -> Self {
+        Chip8Error::Io(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+
+    #[test]
+    fn test_io_error_source() {
+        let e = Chip8Error::from(io::Error::new(io::ErrorKind::NotFound, "oops"));
+        assert!(e.source().is_some());
+    }
+
+    #[test]
+    fn test_display() {
+        let e = Chip8Error::IllegalInstruction {
+            addr: 0x200,
+            inst: 0x8008,
+        };
+        assert_eq!(e.to_string(), "illegal instruction 8008 at 0200");
+        let e = Chip8Error::MemoryFault {
+            addr: 0x9000,
+            len: 2,
+        };
+        assert_eq!(e.to_string(), "memory fault accessing 2 byte(s) at 9000");
+        let e = Chip8Error::AccessFault {
+            inst: 0xf355,
+            pc: 0x208,
+            i: 0x8000,
+            count: 4,
+            reason: Box::new(Chip8Error::WriteProtected { addr: 0x8000 }),
+        };
+        assert_eq!(
+            e.to_string(),
+            "f355 at 0208, with I = 8000, for 4 byte(s): 8000 is write-protected"
+        );
+        assert!(e.source().is_some());
+    }
+}
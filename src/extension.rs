@@ -0,0 +1,179 @@
+use crate::error::Chip8Error;
+use crate::interpreter::Chip8Interpreter;
+
+/// handles instructions the interpreter can't decode itself (e.g. the 0NNN
+/// machine-code calls), so new instructions, extra syscalls or escapes to the
+/// host can be tried out without touching the decoder
+pub trait OpcodeExtension {
+    /// is inst one of ours? only asked about instructions the interpreter
+    /// doesn't already know, and DXY0, which it draws as nothing
+    fn handles(&self, inst: u16) -> bool;
+
+    /// run inst, returning the machine cycles it took. the program counter
+    /// already points at the next instruction
+    fn execute(
+        &mut self,
+        inst: u16,
+        interpreter: &mut Chip8Interpreter,
+    ) -> Result<usize, Chip8Error>;
+
+    /// an External(reason) interrupt's come (see the interrupt module), if
+    /// it's ours, returning the machine cycles it took. the first
+    /// extension to take any time over it is the last to hear of it
+    fn interrupt(
+        &mut self,
+        _reason: u8,
+        _interpreter: &mut Chip8Interpreter,
+    ) -> Result<usize, Chip8Error> {
+        Ok(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interrupt::External;
+    use crate::{display, input, sound};
+
+    /// 0FNN: VF = NN, as if the host had something to say
+    struct HostCall {
+        calls: usize,
+    }
+
+    impl OpcodeExtension for HostCall {
+        fn handles(&self, inst: u16) -> bool {
+            inst & 0xff00 == 0x0f00
+        }
+
+        fn execute(
+            &mut self,
+            inst: u16,
+            interpreter: &mut Chip8Interpreter,
+        ) -> Result<usize, Chip8Error> {
+            self.calls += 1;
+            interpreter.set_v(0xf, inst as u8);
+            Ok(10)
+        }
+
+        /// an interrupt 1 is a host call too, of 0F01
+        fn interrupt(
+            &mut self,
+            reason: u8,
+            interpreter: &mut Chip8Interpreter,
+        ) -> Result<usize, Chip8Error> {
+            match reason {
+                1 => self.execute(0x0f01, interpreter),
+                _ => Ok(0),
+            }
+        }
+    }
+
+    fn run(program: &[u8], ext: &mut HostCall) -> Result<u8, Chip8Error> {
+        let mut display = display::DummyDisplay::new()?;
+        let mut input = input::DummyInput::new(&[]);
+        let mut sound = sound::Mute::new();
+        let mut i = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+        i.add_extension(ext);
+        i.load_program(&mut &program[..])?;
+        i.run_frames(1)?;
+        Ok(i.v(0xf))
+    }
+
+    #[test]
+    fn test_extension_runs_unknown_instructions() -> Result<(), Chip8Error> {
+        let mut ext = HostCall { calls: 0 };
+        // 0f2a; loop forever
+        assert_eq!(run(&[0x0f, 0x2a, 0x12, 0x02], &mut ext)?, 0x2a);
+        assert_eq!(ext.calls, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_external_interrupts() -> Result<(), Chip8Error> {
+        let mut ext = HostCall { calls: 0 };
+        let mut display = display::DummyDisplay::new()?;
+        let mut input = input::DummyInput::new(&[]);
+        let mut sound = sound::Mute::new();
+        let mut i = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+        i.add_extension(&mut ext);
+        // loop forever; interrupt 2 isn't ours, and 1 is, a frame in
+        i.load_program(&mut &[0x12, 0x00][..])?;
+        for reason in [2, 1] {
+            i.add_interrupt_source(&External {
+                reason,
+                at: 1000,
+                every: 0,
+            });
+        }
+        i.run_cycles(500)?;
+        assert_eq!(i.v(0xf), 0);
+        i.run_cycles(1000)?;
+        assert_eq!(i.v(0xf), 1);
+        drop(i);
+        assert_eq!(ext.calls, 1);
+        Ok(())
+    }
+
+    /// something that goes wrong whenever there's an interrupt
+    struct Broken;
+
+    impl OpcodeExtension for Broken {
+        fn handles(&self, _inst: u16) -> bool {
+            false
+        }
+
+        fn execute(
+            &mut self,
+            _inst: u16,
+            _interpreter: &mut Chip8Interpreter,
+        ) -> Result<usize, Chip8Error> {
+            Ok(0)
+        }
+
+        fn interrupt(
+            &mut self,
+            _reason: u8,
+            _interpreter: &mut Chip8Interpreter,
+        ) -> Result<usize, Chip8Error> {
+            Err(Chip8Error::Panicked("the host's gone".to_string()))
+        }
+    }
+
+    #[test]
+    fn test_external_interrupt_errors() -> Result<(), Chip8Error> {
+        let (mut broken, mut ext) = (Broken, HostCall { calls: 0 });
+        let mut display = display::DummyDisplay::new()?;
+        let mut input = input::DummyInput::new(&[]);
+        let mut sound = sound::Mute::new();
+        let mut i = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+        i.add_extension(&mut broken);
+        i.add_extension(&mut ext);
+        i.load_program(&mut &[0x12, 0x00][..])?;
+        i.add_interrupt_source(&External {
+            reason: 1,
+            at: 1000,
+            every: 0,
+        });
+        // the error's not lost under the next extension's taking it
+        i.run_cycles(500)?;
+        let run = i.run_cycles(1000);
+        assert!(matches!(run, Err(Chip8Error::Panicked(_))), "{:?}", run);
+        drop(i);
+        assert_eq!(ext.calls, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_unhandled_instructions_are_still_illegal() -> Result<(), Chip8Error> {
+        let mut ext = HostCall { calls: 0 };
+        // 8008 isn't ours, so is still illegal
+        assert!(matches!(
+            run(&[0x80, 0x08], &mut ext),
+            Err(Chip8Error::IllegalInstruction {
+                addr: 0x200,
+                inst: 0x8008
+            })
+        ));
+        Ok(())
+    }
+}
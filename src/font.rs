@@ -0,0 +1,119 @@
+//! # where FX29 and FX30 find their glyphs
+//!
+//! the VIP's interpreter keeps its font in ROM, overlapping glyphs to save
+//! space, with a table of where each one starts; the SUPER-CHIP brings a font
+//! of its own, plus a big one for FX30. a FontLocator puts a variant's fonts
+//! into memory and says where each glyph is
+use crate::error::Chip8Error;
+use crate::memory::{Chip8MemoryMap, MemoryMap, CHIP8_CONTEMPORARY_FONT};
+
+/// where the VIP's font, and the table of where its glyphs start, are
+const VIP_FONT_ADDR: u16 = 0x8100;
+
+/// SCHIP fonts go just past the top of the VIP's 4k of RAM, where nothing
+/// running on it could expect to find anything else
+const SCHIP_SMALL_FONT_ADDR: u16 = 0x1000;
+const SCHIP_BIG_FONT_ADDR: u16 = 0x1050;
+const SMALL_GLYPH_BYTES: u16 = 5;
+const BIG_GLYPH_BYTES: u16 = 10;
+
+/// SCHIP 1.1's big font only went up to 9; A-F are as later interpreters
+/// drew them
+#[rustfmt::skip]
+const SCHIP_BIG_FONT: [u8; 160] = [
+    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+    0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+    0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
+pub trait FontLocator {
+    /// put the fonts where they go. the VIP's are already there, in ROM
+    fn install(&self, _memory: &mut Chip8MemoryMap) -> Result<(), Chip8Error> {
+        Ok(())
+    }
+
+    /// where the 4x5 glyph for digit (0-f) starts
+    fn small(&self, memory: &Chip8MemoryMap, digit: u8) -> Result<u16, Chip8Error>;
+
+    /// where the 8x10 glyph for digit (0-f) starts, if there's a big font
+    fn big(&self, memory: &Chip8MemoryMap, digit: u8) -> Result<Option<u16>, Chip8Error>;
+}
+
+/// the VIP's own font, from its ROM
+pub struct VipFont;
+
+impl FontLocator for VipFont {
+    fn small(&self, memory: &Chip8MemoryMap, digit: u8) -> Result<u16, Chip8Error> {
+        // since we have the _actual_ VIP ROM anyway, use its lookup table
+        let offset = memory.get_ro_slice(VIP_FONT_ADDR + (digit & 0xf) as u16, 1)?[0];
+        Ok(VIP_FONT_ADDR + offset as u16)
+    }
+
+    fn big(&self, _memory: &Chip8MemoryMap, _digit: u8) -> Result<Option<u16>, Chip8Error> {
+        Ok(None)
+    }
+}
+
+/// the SUPER-CHIP's fonts, small and big
+pub struct SchipFont;
+
+impl FontLocator for SchipFont {
+    fn install(&self, memory: &mut Chip8MemoryMap) -> Result<(), Chip8Error> {
+        memory.write(
+            &CHIP8_CONTEMPORARY_FONT,
+            SCHIP_SMALL_FONT_ADDR,
+            CHIP8_CONTEMPORARY_FONT.len(),
+        )?;
+        memory.write(&SCHIP_BIG_FONT, SCHIP_BIG_FONT_ADDR, SCHIP_BIG_FONT.len())
+    }
+
+    fn small(&self, _memory: &Chip8MemoryMap, digit: u8) -> Result<u16, Chip8Error> {
+        Ok(SCHIP_SMALL_FONT_ADDR + (digit & 0xf) as u16 * SMALL_GLYPH_BYTES)
+    }
+
+    fn big(&self, _memory: &Chip8MemoryMap, digit: u8) -> Result<Option<u16>, Chip8Error> {
+        Ok(Some(
+            SCHIP_BIG_FONT_ADDR + (digit & 0xf) as u16 * BIG_GLYPH_BYTES,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vip_font() -> Result<(), Chip8Error> {
+        let memory = Chip8MemoryMap::new()?;
+        // 1 is the VIP's only glyph that's 0x60 at the top
+        let one = VipFont.small(&memory, 1)?;
+        assert_eq!(memory.get_ro_slice(one, 5)?, [0x60, 0x20, 0x20, 0x20, 0x70]);
+        assert_eq!(VipFont.big(&memory, 1)?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_schip_font() -> Result<(), Chip8Error> {
+        let mut memory = Chip8MemoryMap::new()?;
+        SchipFont.install(&mut memory)?;
+        let one = SchipFont.small(&memory, 1)?;
+        assert_eq!(memory.get_ro_slice(one, 5)?, [0x20, 0x60, 0x20, 0x20, 0x70]);
+        // 9's last row, then A's first
+        let nine = SchipFont.big(&memory, 9)?.unwrap();
+        assert_eq!(memory.get_ro_slice(nine + 9, 2)?, [0xff, 0x7e]);
+        Ok(())
+    }
+}
@@ -0,0 +1,239 @@
+//! # frame skipping
+//!
+//! drawing to a terminal can take longer than a frame, especially over SSH,
+//! and then the whole emulator runs slow. FrameSkip sits in front of the
+//! real display and drops some of the draws instead. the interpreter still
+//! runs every interrupt, so the timers, sound and input keep time; only the
+//! picture gets choppier
+use crate::clock::Clock;
+use crate::display::{Display, Hud, Theme};
+use crate::error::Chip8Error;
+use crate::heat::Heat;
+use crate::input::Focus;
+use crate::sound::Volume;
+use std::time::Duration;
+
+/// how much of a frame drawing can have before we start skipping: the rest
+/// is for running the program
+pub(crate) const FRAMESKIP_BUDGET: Duration = Duration::from_micros(1_000_000 / 60 / 2);
+/// the most --frame-skip auto will drop in a row: 10 fps
+pub(crate) const FRAMESKIP_AUTO_MAX: u32 = 5;
+
+/// how many draws to drop
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SkipPolicy {
+    /// always draw one frame then skip this many
+    Fixed(u32),
+    /// skip as many as it takes for drawing to fit its budget, up to max
+    Auto { max: u32 },
+}
+
+impl SkipPolicy {
+    /// "auto", or how many to skip after each frame drawn
+    pub fn parse(s: &str) -> Result<Self, Chip8Error> {
+        match s {
+            "auto" => Ok(SkipPolicy::Auto {
+                max: FRAMESKIP_AUTO_MAX,
+            }),
+            _ => s.parse().map(SkipPolicy::Fixed).map_err(|_| {
+                Chip8Error::ConfigError(format!(
+                    "can't skip \"{}\" frames (try auto or a number)",
+                    s
+                ))
+            }),
+        }
+    }
+}
+
+/// passes some frames on to another display, and drops the others
+pub struct FrameSkip<'a> {
+    inner: &'a mut dyn Display,
+    policy: SkipPolicy,
+    clock: &'a dyn Clock,
+    // how many to skip after each draw, and how many more to skip now
+    skip: u32,
+    to_skip: u32,
+}
+
+impl<'a> FrameSkip<'a> {
+    /// clock times the draws, for SkipPolicy::Auto
+    pub fn new(inner: &'a mut dyn Display, policy: SkipPolicy, clock: &'a dyn Clock) -> Self {
+        let skip = match policy {
+            SkipPolicy::Fixed(n) => n,
+            SkipPolicy::Auto { .. } => 0,
+        };
+        FrameSkip {
+            inner,
+            policy,
+            clock,
+            skip,
+            to_skip: 0,
+        }
+    }
+
+    /// how many frames are being skipped after each one drawn
+    pub fn skip(&self) -> u32 {
+        self.skip
+    }
+}
+
+impl<'a> Display for FrameSkip<'a> {
+    fn draw(&mut self, data: &[u8]) -> Result<(), Chip8Error> {
+        if self.to_skip > 0 {
+            self.to_skip -= 1;
+            return Ok(());
+        }
+        let start = self.clock.now();
+        self.inner.draw(data)?;
+        if let SkipPolicy::Auto { max } = self.policy {
+            // a draw that took 2.5 budgets wants 2 skipped after it
+            let took = self.clock.now().saturating_sub(start);
+            let budgets = took.as_nanos() / FRAMESKIP_BUDGET.as_nanos();
+            self.skip = (budgets as u32).min(max);
+        }
+        self.to_skip = self.skip;
+        Ok(())
+    }
+
+    fn get_display_size_bytes(&mut self) -> usize {
+        self.inner.get_display_size_bytes()
+    }
+
+    fn set_planes(&mut self, planes: usize) -> Result<(), Chip8Error> {
+        self.inner.set_planes(planes)
+    }
+
+    fn set_mode(&mut self, width: usize, height: usize) -> Result<(), Chip8Error> {
+        // the next frame's the first in the new mode, so it shouldn't wait
+        self.to_skip = 0;
+        self.inner.set_mode(width, height)
+    }
+
+    fn set_status(&mut self, status: &str) {
+        self.inner.set_status(status);
+    }
+
+    fn notify(&mut self, notice: &str) {
+        self.inner.notify(notice);
+    }
+
+    fn set_speed(&mut self, speed: f64) {
+        self.inner.set_speed(speed);
+    }
+
+    fn set_volume(&mut self, volume: Volume) {
+        self.inner.set_volume(volume);
+    }
+
+    fn set_hud(&mut self, hud: Option<Hud>) {
+        self.inner.set_hud(hud);
+    }
+
+    fn set_heat(&mut self, heat: Option<Heat>) {
+        self.inner.set_heat(heat);
+    }
+
+    fn set_help(&mut self, help: Option<Vec<String>>) {
+        self.inner.set_help(help);
+    }
+
+    fn set_slots(&mut self, slots: Option<Vec<String>>) {
+        self.inner.set_slots(slots);
+    }
+
+    fn set_palette(&mut self, palette: Option<Vec<String>>) {
+        self.inner.set_palette(palette);
+    }
+
+    fn set_theme(&mut self, theme: Theme) {
+        self.inner.set_theme(theme);
+    }
+
+    fn set_focus(&mut self, focus: Focus) {
+        self.inner.set_focus(focus);
+    }
+
+    fn refresh(&mut self) -> Result<(), Chip8Error> {
+        self.to_skip = 0;
+        self.inner.refresh()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    /// takes as long as it's told to draw, and counts what it drew
+    struct SlowDisplay<'c> {
+        clock: &'c ManualClock,
+        takes: Duration,
+        drawn: Vec<u8>,
+    }
+
+    impl<'c> Display for SlowDisplay<'c> {
+        fn draw(&mut self, data: &[u8]) -> Result<(), Chip8Error> {
+            self.clock.advance(self.takes);
+            self.drawn.push(data[0]);
+            Ok(())
+        }
+
+        fn get_display_size_bytes(&mut self) -> usize {
+            1
+        }
+
+        fn set_mode(&mut self, _width: usize, _height: usize) -> Result<(), Chip8Error> {
+            Ok(())
+        }
+    }
+
+    fn draw_frames(policy: SkipPolicy, takes: Duration) -> Result<Vec<u8>, Chip8Error> {
+        let clock = ManualClock::new();
+        let mut slow = SlowDisplay {
+            clock: &clock,
+            takes,
+            drawn: vec![],
+        };
+        let mut skipper = FrameSkip::new(&mut slow, policy, &clock);
+        for frame in 0..8 {
+            skipper.draw(&[frame])?;
+        }
+        Ok(slow.drawn)
+    }
+
+    #[test]
+    fn test_parse() -> Result<(), Chip8Error> {
+        assert_eq!(SkipPolicy::parse("2")?, SkipPolicy::Fixed(2));
+        assert_eq!(SkipPolicy::parse("auto")?, SkipPolicy::Auto { max: 5 });
+        assert!(SkipPolicy::parse("lots").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_fixed() -> Result<(), Chip8Error> {
+        assert_eq!(
+            draw_frames(SkipPolicy::Fixed(2), Duration::ZERO)?,
+            [0, 3, 6]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_auto() -> Result<(), Chip8Error> {
+        // quick enough: draw them all
+        let quick = FRAMESKIP_BUDGET / 2;
+        assert_eq!(
+            draw_frames(SkipPolicy::Auto { max: 4 }, quick)?,
+            [0, 1, 2, 3, 4, 5, 6, 7]
+        );
+        // a draw takes more than a frame, so skip 2 after each
+        let slow = FRAMESKIP_BUDGET * 2 + FRAMESKIP_BUDGET / 2;
+        assert_eq!(draw_frames(SkipPolicy::Auto { max: 4 }, slow)?, [0, 3, 6]);
+        // but never more than max
+        assert_eq!(
+            draw_frames(SkipPolicy::Auto { max: 1 }, slow)?,
+            [0, 2, 4, 6]
+        );
+        Ok(())
+    }
+}
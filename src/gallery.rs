@@ -0,0 +1,280 @@
+//! # the ROM gallery
+//!
+//! a few small demos that come with the emulator, so there's something to
+//! run without finding a ROM first. they were written for this project, so
+//! they're under its licence. the gallery screen shows each one's title
+//! and a thumbnail of how it looks after a couple of seconds' running, and
+//! how much it's been played (see the stats module)
+use crate::config::Config;
+use crate::error::Chip8Error;
+use crate::rominfo;
+use crate::stats::Stats;
+use crate::thumbnail::{self, ThumbnailCache};
+use crossterm::event::{read, Event, KeyCode};
+use crossterm::terminal;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use tui::backend::CrosstermBackend;
+use tui::layout::{Constraint, Direction, Layout};
+use tui::style::{Modifier, Style};
+use tui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph};
+use tui::Terminal;
+
+#[derive(Debug, PartialEq)]
+pub struct GalleryRom {
+    /// what the ROM's called for config and controls, like a file name
+    pub name: &'static str,
+    pub title: &'static str,
+    pub description: &'static str,
+    pub rom: &'static [u8],
+}
+
+#[rustfmt::skip]
+pub const GALLERY: [GalleryRom; 3] = [
+    GalleryRom {
+        name: "bounce",
+        title: "Bounce",
+        description: "a ball bouncing off the edges of the screen",
+        rom: &[
+            0x60, 0x00, 0x61, 0x00, 0x62, 0x01, 0x63, 0x01, // x, y, dx, dy
+            0xa2, 0x38, 0xd0, 0x12,                         // draw the ball
+            0x64, 0x01, 0xf4, 0x15, 0xf4, 0x07, 0x34, 0x00, // wait a frame
+            0x12, 0x10,
+            0xd0, 0x12, 0x80, 0x24, 0x81, 0x34,             // rub out, move
+            0x30, 0x3e, 0x12, 0x22, 0x62, 0xff,             // off the walls
+            0x30, 0x00, 0x12, 0x28, 0x62, 0x01,
+            0x31, 0x1e, 0x12, 0x2e, 0x63, 0xff,
+            0x31, 0x00, 0x12, 0x34, 0x63, 0x01,
+            0xd0, 0x12, 0x12, 0x0c,                         // draw, repeat
+            0xc0, 0xc0,                                     // the ball
+        ],
+    },
+    GalleryRom {
+        name: "counter",
+        title: "Counter",
+        description: "counts up, four times a second, in decimal",
+        rom: &[
+            0x65, 0x00,                                     // count = 0
+            0x00, 0xe0, 0xa3, 0x00, 0xf5, 0x33, 0xf2, 0x65, // split into digits
+            0x6a, 0x18, 0x6b, 0x0d,                         // draw them
+            0xf0, 0x29, 0xda, 0xb5, 0x7a, 0x05,
+            0xf1, 0x29, 0xda, 0xb5, 0x7a, 0x05,
+            0xf2, 0x29, 0xda, 0xb5,
+            0x75, 0x01,                                     // count += 1
+            0x6c, 0x0f, 0xfc, 0x15, 0xfc, 0x07, 0x3c, 0x00, // wait 1/4s
+            0x12, 0x24, 0x12, 0x02,                         // repeat
+        ],
+    },
+    GalleryRom {
+        name: "keypad",
+        title: "Keypad",
+        description: "shows the last key pressed",
+        rom: &[
+            0x6a, 0x1c, 0x6b, 0x0d, 0x60, 0x00,             // show 0
+            0xf0, 0x29, 0xda, 0xb5,
+            0xf1, 0x0a, 0xda, 0xb5, 0x80, 0x10,             // wait for a key
+            0xf0, 0x29, 0xda, 0xb5, 0x12, 0x0a,             // show it, repeat
+        ],
+    },
+];
+
+/// something in the gallery's list
+#[derive(Debug, PartialEq)]
+pub enum Choice {
+    /// one of the demos above
+    Builtin(&'static GalleryRom),
+    /// a ROM file: a favourite, or one played lately
+    File(String),
+}
+
+impl Choice {
+    /// what it's called, for config and controls
+    pub fn name(&self) -> String {
+        match self {
+            Choice::Builtin(g) => g.name.to_string(),
+            Choice::File(path) => rominfo::rom_name(Path::new(path)),
+        }
+    }
+
+    fn title(&self) -> String {
+        match self {
+            Choice::Builtin(g) => g.title.to_string(),
+            Choice::File(_) => match rominfo::lookup(&self.name()) {
+                Some(info) => info.title.to_string(),
+                None => self.name(),
+            },
+        }
+    }
+
+    fn description(&self) -> &str {
+        match self {
+            Choice::Builtin(g) => g.description,
+            Choice::File(path) => path,
+        }
+    }
+
+    /// the program itself
+    pub fn rom(&self) -> Result<Vec<u8>, Chip8Error> {
+        match self {
+            Choice::Builtin(g) => Ok(g.rom.to_vec()),
+            Choice::File(path) => Ok(fs::read(path)?),
+        }
+    }
+}
+
+/// what the gallery lists: favourites first, then the ROMs played lately,
+/// then the demos
+pub fn entries(config: &Config) -> Vec<Choice> {
+    let recent = config
+        .recent
+        .iter()
+        .filter(|p| !config.favourites.contains(p));
+    config
+        .favourites
+        .iter()
+        .chain(recent)
+        .map(|p| Choice::File(p.clone()))
+        .chain(GALLERY.iter().map(Choice::Builtin))
+        .collect()
+}
+
+/// show the gallery in the terminal until the player picks something
+/// (enter) or gives up (escape). f marks a file as a favourite, or
+/// unmarks it, in config; saving that is up to the caller
+pub fn choose(
+    cache: &ThumbnailCache,
+    config: &mut Config,
+    stats: &Stats,
+) -> Result<Option<Choice>, Chip8Error> {
+    let mut thumbnails: HashMap<String, String> = HashMap::new();
+    let mut terminal = Terminal::new(CrosstermBackend::new(io::stdout()))?;
+    let _raw = RawMode::on()?;
+    terminal.clear()?;
+    let mut state = ListState::default();
+    state.select(Some(0));
+    let chosen = loop {
+        let mut entries = entries(config);
+        let selected = state.selected().unwrap_or(0).min(entries.len() - 1);
+        let key = format!("{:?}", entries[selected]);
+        if !thumbnails.contains_key(&key) {
+            let thumbnail = match entries[selected].rom().and_then(|r| cache.get(&r)) {
+                Ok(frame) => thumbnail::braille(&frame).join("\n"),
+                Err(e) => format!("can't run it: {}", e),
+            };
+            thumbnails.insert(key.clone(), thumbnail);
+        }
+        terminal.draw(|f| {
+            let columns = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([Constraint::Length(24), Constraint::Length(34)])
+                .split(f.size());
+            let items: Vec<ListItem> = entries
+                .iter()
+                .map(|e| match e {
+                    Choice::File(p) if config.favourites.contains(p) => {
+                        ListItem::new(format!("* {}", e.title()))
+                    }
+                    _ => ListItem::new(e.title()),
+                })
+                .collect();
+            let list = List::new(items)
+                .block(Block::default().borders(Borders::ALL).title("ROMs"))
+                .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+            f.render_stateful_widget(list, columns[0], &mut state);
+            let preview = format!(
+                "{}\n\n{}\n\n{}",
+                thumbnails[&key],
+                entries[selected].description(),
+                stats.rom(&entries[selected].name()).describe()
+            );
+            let preview = Paragraph::new(preview).block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title("enter to play, f to favourite, esc to quit"),
+            );
+            f.render_widget(preview, columns[1]);
+        })?;
+        if let Event::Key(k) = read()? {
+            match k.code {
+                KeyCode::Up => state.select(Some(selected.saturating_sub(1))),
+                KeyCode::Down => state.select(Some((selected + 1).min(entries.len() - 1))),
+                KeyCode::Char('f') => {
+                    if let Choice::File(p) = &entries[selected] {
+                        config.toggle_favourite(p);
+                    }
+                }
+                KeyCode::Enter => break Some(entries.swap_remove(selected)),
+                KeyCode::Esc => break None,
+                _ => {}
+            }
+        }
+    };
+    terminal.clear()?;
+    Ok(chosen)
+}
+
+/// the terminal in raw mode for as long as this is around, and back out of
+/// it however choose stops, so an error doesn't leave the shell unusable
+struct RawMode;
+
+impl RawMode {
+    fn on() -> Result<RawMode, Chip8Error> {
+        terminal::enable_raw_mode()?;
+        Ok(RawMode)
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        // nothing useful to do if this fails, and we mustn't panic in drop
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::Chip8MemoryMap;
+    use crate::ocr;
+    use crate::thumbnail::capture;
+
+    #[test]
+    fn test_previews() -> Result<(), Chip8Error> {
+        let digits = ocr::DigitReader::from_memory(&Chip8MemoryMap::new()?)?;
+        let [bounce, counter, keypad] = &GALLERY;
+
+        // the ball's somewhere, and only 2x2
+        let frame = capture(bounce.rom)?;
+        let lit: u32 = frame.iter().map(|b| b.count_ones()).sum();
+        assert_eq!(lit, 4);
+
+        // counting a few times a second
+        let frame = capture(counter.rom)?;
+        let n = digits.read_number(&frame, 24, 13, 3, 5).unwrap();
+        assert!((5..10).contains(&n), "{}", n);
+
+        // no key's been pressed
+        let frame = capture(keypad.rom)?;
+        assert_eq!(digits.find_digits(&frame), [(28, 13, 0)]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_entries() {
+        let mut config = Config::default();
+        assert_eq!(entries(&config).len(), GALLERY.len());
+
+        config.add_recent("roms/pong.ch8");
+        config.add_recent("roms/brix.ch8");
+        config.toggle_favourite("roms/pong.ch8");
+        let e = entries(&config);
+        assert_eq!(e.len(), GALLERY.len() + 2);
+        assert_eq!(e[0], Choice::File("roms/pong.ch8".to_string()));
+        assert_eq!(e[1], Choice::File("roms/brix.ch8".to_string()));
+        assert_eq!(e[2], Choice::Builtin(&GALLERY[0]));
+        assert_eq!(e[0].title(), "Pong");
+        assert_eq!(e[2].name(), "bounce");
+    }
+}
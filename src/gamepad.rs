@@ -0,0 +1,605 @@
+//! # gamepads
+//!
+//! a joystick or gamepad, read through Linux's joystick interface
+//! (/dev/input/js0 and so on), standing in for keypad keys alongside the
+//! keyboard. which keys the stick and buttons press depends on the sort of
+//! game it is: a paddle game wants up and down on 1 and 4, where a maze or
+//! a shooter wants the 2/4/6/8 diamond, and a shooter wants a fire button
+//! too. each genre has a profile of the keys it conventionally uses, and
+//! where rominfo knows which key does what in a particular ROM (its
+//! "up", "fire" and so on), that wins
+//!
+//! a pad that can rumble can be made to when the buzzer sounds or sprites
+//! collide (--rumble buzzer,collision), through the event device (evdev)
+//! behind the joystick, as the joystick interface only reads
+use crate::error::Chip8Error;
+use crate::input::{Feedback, Focus, Input, SlotRequest, SpeedRequest, Typed, VolumeRequest};
+use crate::keypad::{Assist, KeyFilter, KeyRepeat, KeyTransition};
+use crate::rominfo::RomInfo;
+use std::io::{self, Read};
+use std::time::Duration;
+
+/// the parts of a gamepad we listen to
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Pad {
+    Up,
+    Down,
+    Left,
+    Right,
+    /// the first button (A on most pads, cross on others)
+    A,
+    /// the second
+    B,
+}
+
+/// the sorts of game with their own conventions
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Genre {
+    /// up and down (or left and right) and nothing else, e.g. Pong, Brix
+    Paddle,
+    /// four ways round, e.g. Blinky
+    Maze,
+    /// moving and firing, e.g. Space Invaders
+    Shooter,
+}
+
+impl Genre {
+    pub fn parse(s: &str) -> Result<Self, Chip8Error> {
+        match s {
+            "paddle" => Ok(Genre::Paddle),
+            "maze" => Ok(Genre::Maze),
+            "shooter" => Ok(Genre::Shooter),
+            _ => Err(Chip8Error::ConfigError(format!(
+                "no gamepad profile called \"{}\" (try paddle, maze or shooter)",
+                s
+            ))),
+        }
+    }
+
+    /// each control, what rominfo would call it, and the key it presses if
+    /// the ROM doesn't say
+    fn profile(self) -> &'static [(Pad, &'static str, u8)] {
+        match self {
+            Genre::Paddle => &[
+                (Pad::Up, "up", 0x1),
+                (Pad::Down, "down", 0x4),
+                (Pad::Left, "left", 0x4),
+                (Pad::Right, "right", 0x6),
+                (Pad::A, "fire", 0x5),
+            ],
+            Genre::Maze => &[
+                (Pad::Up, "up", 0x2),
+                (Pad::Down, "down", 0x8),
+                (Pad::Left, "left", 0x4),
+                (Pad::Right, "right", 0x6),
+                (Pad::A, "fire", 0x5),
+            ],
+            Genre::Shooter => &[
+                (Pad::Up, "up", 0x2),
+                (Pad::Down, "down", 0x8),
+                (Pad::Left, "left", 0x4),
+                (Pad::Right, "right", 0x6),
+                (Pad::A, "fire", 0x5),
+                (Pad::B, "fire", 0x5),
+            ],
+        }
+    }
+}
+
+/// which key each control presses
+#[derive(Debug, PartialEq, Clone)]
+pub struct PadMap(pub Vec<(Pad, u8)>);
+
+impl PadMap {
+    /// genre's profile, with whatever info says about the ROM over the top.
+    /// a control's found by name, or by a name ending in it, so "p1 up"
+    /// counts as up (and player one comes first)
+    pub fn for_rom(genre: Genre, info: Option<&RomInfo>) -> Self {
+        let controls = info.map_or(&[][..], |i| i.controls);
+        PadMap(
+            genre
+                .profile()
+                .iter()
+                .map(|(pad, name, default)| {
+                    let key = controls
+                        .iter()
+                        .find(|(_, action)| {
+                            *action == *name || action.ends_with(&format!(" {}", name))
+                        })
+                        .map_or(*default, |(key, _)| *key);
+                    (*pad, key)
+                })
+                .collect(),
+        )
+    }
+
+    fn key(&self, pad: Pad) -> Option<u8> {
+        self.0.iter().find(|(p, _)| *p == pad).map(|(_, k)| *k)
+    }
+}
+
+/// how far a stick has to go before it counts, out of 32767
+const DEADZONE: i16 = 16384;
+
+/// the joystick interface's events: what happened, when, and to what
+const JS_EVENT_SIZE: usize = 8;
+const JS_EVENT_BUTTON: u8 = 0x01;
+const JS_EVENT_AXIS: u8 = 0x02;
+
+/// which of the things the program does shake the gamepad
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct RumbleOn {
+    pub buzzer: bool,
+    pub collision: bool,
+}
+
+impl RumbleOn {
+    /// e.g. "buzzer,collision"
+    pub fn parse(s: &str) -> Result<Self, Chip8Error> {
+        let mut on = RumbleOn::default();
+        for name in s.split(',') {
+            match name.trim() {
+                "buzzer" => on.buzzer = true,
+                "collision" => on.collision = true,
+                _ => {
+                    return Err(Chip8Error::ConfigError(format!(
+                        "can't rumble on \"{}\" (try buzzer, collision or both)",
+                        name
+                    )))
+                }
+            }
+        }
+        Ok(on)
+    }
+
+    fn wants(&self, feedback: Feedback) -> bool {
+        match feedback {
+            Feedback::Buzzer => self.buzzer,
+            Feedback::Collision => self.collision,
+        }
+    }
+}
+
+/// something that can give the player a short shake
+pub trait Rumble {
+    fn rumble(&mut self) -> Result<(), Chip8Error>;
+}
+
+/// how long a shake lasts, in ms: long enough to feel, short enough not to
+/// run into the next
+const RUMBLE_MS: u16 = 120;
+
+/// the event interface's force feedback, a struct ff_effect and an input
+/// event to play it, as laid out on 64-bit Linux
+const FF_EFFECT_SIZE: usize = 48;
+const FF_RUMBLE: u16 = 0x50;
+const EV_FF: u16 = 0x15;
+const INPUT_EVENT_SIZE: usize = 24;
+/// _IOW('E', 0x80, struct ff_effect)
+const EVIOCSFF: u64 = 0x4030_4580;
+
+/// a gamepad's rumble motors, driven through the event device that goes
+/// with its joystick device
+pub struct ForceFeedback {
+    device: std::fs::File,
+    // the kernel's name for the shake, once it's been told about it
+    effect: i16,
+}
+
+impl ForceFeedback {
+    /// the motors in the pad at joystick, e.g. /dev/input/js0, which is
+    /// /dev/input/event<n> as far as force feedback is concerned
+    #[cfg(all(target_os = "linux", target_pointer_width = "64"))]
+    pub fn open(joystick: &str) -> Result<Self, Chip8Error> {
+        use std::os::unix::io::AsRawFd;
+        extern "C" {
+            fn ioctl(fd: i32, request: u64, ...) -> i32;
+        }
+
+        let no_rumble = || Chip8Error::ConfigError(format!("{} can't rumble", joystick));
+        let js = std::path::Path::new(joystick)
+            .file_name()
+            .ok_or_else(no_rumble)?;
+        let sys = std::path::Path::new("/sys/class/input")
+            .join(js)
+            .join("device");
+        let event = std::fs::read_dir(sys)
+            .map_err(|_| no_rumble())?
+            .flatten()
+            .map(|e| e.file_name())
+            .find(|n| n.to_string_lossy().starts_with("event"))
+            .ok_or_else(no_rumble)?;
+        let device = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(std::path::Path::new("/dev/input").join(event))?;
+
+        let mut effect = [0u8; FF_EFFECT_SIZE];
+        effect[0..2].copy_from_slice(&FF_RUMBLE.to_ne_bytes());
+        // -1 asks for a new one, and the kernel writes back what it's called
+        effect[2..4].copy_from_slice(&(-1i16).to_ne_bytes());
+        effect[10..12].copy_from_slice(&RUMBLE_MS.to_ne_bytes());
+        // both motors, the heavy one at half
+        effect[16..18].copy_from_slice(&0x8000u16.to_ne_bytes());
+        effect[18..20].copy_from_slice(&0xffffu16.to_ne_bytes());
+        // safe: effect's as big as the kernel expects, and lives past the call
+        if unsafe { ioctl(device.as_raw_fd(), EVIOCSFF, effect.as_mut_ptr()) } < 0 {
+            return Err(no_rumble());
+        }
+        Ok(ForceFeedback {
+            device,
+            effect: i16::from_ne_bytes([effect[2], effect[3]]),
+        })
+    }
+
+    #[cfg(not(all(target_os = "linux", target_pointer_width = "64")))]
+    pub fn open(_joystick: &str) -> Result<Self, Chip8Error> {
+        Err(Chip8Error::ConfigError(
+            "gamepads can only rumble on 64-bit Linux".to_string(),
+        ))
+    }
+}
+
+impl Rumble for ForceFeedback {
+    fn rumble(&mut self) -> Result<(), Chip8Error> {
+        use std::io::Write;
+        // no time (the kernel fills it in), then the effect to play, once
+        let mut event = [0u8; INPUT_EVENT_SIZE];
+        event[16..18].copy_from_slice(&EV_FF.to_ne_bytes());
+        event[18..20].copy_from_slice(&(self.effect as u16).to_ne_bytes());
+        event[20..24].copy_from_slice(&1i32.to_ne_bytes());
+        self.device.write_all(&event)?;
+        Ok(())
+    }
+}
+
+/// a gamepad, and which of its controls are being held
+pub struct Joystick<R: Read> {
+    source: R,
+    held: Vec<Pad>,
+    // an event read in part
+    partial: Vec<u8>,
+}
+
+impl Joystick<std::fs::File> {
+    /// e.g. /dev/input/js0, read without waiting
+    #[cfg(target_os = "linux")]
+    pub fn open(path: &str) -> Result<Self, Chip8Error> {
+        use std::os::unix::fs::OpenOptionsExt;
+        // O_NONBLOCK, on everything but a few architectures we won't meet
+        const O_NONBLOCK: i32 = 0o4000;
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .custom_flags(O_NONBLOCK)
+            .open(path)?;
+        Ok(Joystick::new(file))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn open(_path: &str) -> Result<Self, Chip8Error> {
+        Err(Chip8Error::ConfigError(
+            "gamepads can only be read on Linux".to_string(),
+        ))
+    }
+}
+
+impl<R: Read> Joystick<R> {
+    pub fn new(source: R) -> Self {
+        Joystick {
+            source,
+            held: Vec::new(),
+            partial: Vec::new(),
+        }
+    }
+
+    /// catch up with everything that's happened since we last looked
+    pub fn poll(&mut self) -> Result<(), Chip8Error> {
+        let mut buf = [0; JS_EVENT_SIZE * 16];
+        loop {
+            let n = match self.source.read(&mut buf) {
+                Ok(0) => return Ok(()),
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(e) => return Err(e.into()),
+            };
+            self.partial.extend(&buf[..n]);
+            let whole = self.partial.len() / JS_EVENT_SIZE * JS_EVENT_SIZE;
+            let events: Vec<u8> = self.partial.drain(..whole).collect();
+            for event in events.chunks(JS_EVENT_SIZE) {
+                // the time, which we don't need, then value, type and number.
+                // the type's high bit says it's the state at start-up
+                let value = i16::from_le_bytes([event[4], event[5]]);
+                self.event(event[6] & 0x7f, event[7], value);
+            }
+        }
+    }
+
+    fn event(&mut self, kind: u8, number: u8, value: i16) {
+        let (pad, held) = match (kind, number) {
+            // the left stick or the d-pad (which most pads call axes 6 and 7)
+            (JS_EVENT_AXIS, 0 | 6) => {
+                self.release(&[Pad::Left, Pad::Right]);
+                match value {
+                    v if v <= -DEADZONE => (Pad::Left, true),
+                    v if v >= DEADZONE => (Pad::Right, true),
+                    _ => return,
+                }
+            }
+            (JS_EVENT_AXIS, 1 | 7) => {
+                self.release(&[Pad::Up, Pad::Down]);
+                match value {
+                    v if v <= -DEADZONE => (Pad::Up, true),
+                    v if v >= DEADZONE => (Pad::Down, true),
+                    _ => return,
+                }
+            }
+            (JS_EVENT_BUTTON, 0) => (Pad::A, value != 0),
+            (JS_EVENT_BUTTON, 1) => (Pad::B, value != 0),
+            _ => return,
+        };
+        self.release(&[pad]);
+        if held {
+            self.held.push(pad);
+        }
+    }
+
+    fn release(&mut self, pads: &[Pad]) {
+        self.held.retain(|p| !pads.contains(p));
+    }
+
+    /// what's held down, earliest first
+    pub fn held(&self) -> &[Pad] {
+        &self.held
+    }
+}
+
+/// keys from a gamepad as well as from another input, which still gets
+/// asked about everything else (the menu, the speed and so on)
+pub struct PadInput<'a, R: Read> {
+    inner: &'a mut dyn Input,
+    joystick: Joystick<R>,
+    map: PadMap,
+    // a stick jittering round the deadzone mustn't let go of its key
+    keys: KeyFilter,
+    transitions: Vec<KeyTransition>,
+    rumble: Option<(Box<dyn Rumble + 'a>, RumbleOn)>,
+    // something's happened this frame that wants a shake
+    shake: bool,
+}
+
+impl<'a, R: Read> PadInput<'a, R> {
+    pub fn new(inner: &'a mut dyn Input, joystick: Joystick<R>, map: PadMap) -> Self {
+        PadInput {
+            inner,
+            joystick,
+            map,
+            keys: KeyFilter::new(KeyRepeat::BUTTONS),
+            transitions: Vec::new(),
+            rumble: None,
+            shake: false,
+        }
+    }
+
+    /// shake the pad when the program does what on says
+    pub fn set_rumble(&mut self, rumble: Box<dyn Rumble + 'a>, on: RumbleOn) {
+        self.rumble = Some((rumble, on));
+    }
+
+    /// sticky keys, or slow motion, for the gamepad's keys
+    pub fn set_assist(&mut self, assist: Assist) {
+        self.keys.set_assist(assist);
+    }
+
+    /// catch up with the gamepad, and see which key it's holding
+    fn sample(&mut self) -> Result<(), Chip8Error> {
+        self.joystick.poll()?;
+        // the buttons are the program's too, so they go quiet with the keys
+        let held = match self.inner.focus() {
+            Focus::Game => self.joystick.held(),
+            Focus::Emulator => &[],
+        };
+        let key = held.iter().find_map(|p| self.map.key(*p));
+        self.keys.sample(key);
+        Ok(())
+    }
+}
+
+impl<'a, R: Read> Input for PadInput<'a, R> {
+    fn flush_keys(&mut self) -> Result<(), Chip8Error> {
+        // a button's still down until it's let go
+        self.inner.flush_keys()
+    }
+
+    fn read_key(&mut self) -> Result<Option<u8>, Chip8Error> {
+        self.sample()?;
+        match self.keys.read() {
+            Some(key) => Ok(Some(key)),
+            None => self.inner.read_key(),
+        }
+    }
+
+    fn tick(&mut self) -> Result<(), Chip8Error> {
+        self.sample()?;
+        self.inner.tick()?;
+        self.transitions = self.keys.tick();
+        self.transitions.extend(self.inner.transitions());
+        // at most once a frame, however many sprites hit each other
+        if std::mem::take(&mut self.shake) {
+            if let Some((rumble, _)) = &mut self.rumble {
+                rumble.rumble()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn transitions(&self) -> &[KeyTransition] {
+        &self.transitions
+    }
+
+    fn wait_for_event(&mut self, timeout: Duration) -> Result<bool, Chip8Error> {
+        self.inner.wait_for_event(timeout)
+    }
+
+    fn take_menu_request(&mut self) -> Result<bool, Chip8Error> {
+        self.inner.take_menu_request()
+    }
+
+    fn take_speed_request(&mut self) -> Result<Option<SpeedRequest>, Chip8Error> {
+        match self.keys.take_slow_motion() {
+            Some(slowed) => Ok(Some(SpeedRequest::SlowMotion(slowed))),
+            None => self.inner.take_speed_request(),
+        }
+    }
+
+    fn take_hud_toggle(&mut self) -> Result<bool, Chip8Error> {
+        self.inner.take_hud_toggle()
+    }
+
+    fn take_help_toggle(&mut self) -> Result<bool, Chip8Error> {
+        self.inner.take_help_toggle()
+    }
+
+    fn take_volume_request(&mut self) -> Result<Option<VolumeRequest>, Chip8Error> {
+        self.inner.take_volume_request()
+    }
+
+    fn take_slot_request(&mut self) -> Result<Option<SlotRequest>, Chip8Error> {
+        self.inner.take_slot_request()
+    }
+
+    fn take_palette_request(&mut self) -> Result<bool, Chip8Error> {
+        self.inner.take_palette_request()
+    }
+
+    fn set_typing(&mut self, typing: bool) {
+        self.inner.set_typing(typing)
+    }
+
+    fn take_typed(&mut self) -> Result<Vec<Typed>, Chip8Error> {
+        self.inner.take_typed()
+    }
+
+    fn focus(&self) -> Focus {
+        self.inner.focus()
+    }
+
+    fn set_focus(&mut self, focus: Focus) {
+        self.inner.set_focus(focus)
+    }
+
+    fn feedback(&mut self, feedback: Feedback) {
+        if let Some((_, on)) = &self.rumble {
+            self.shake |= on.wants(feedback);
+        }
+        self.inner.feedback(feedback)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::DummyInput;
+    use crate::rominfo;
+
+    fn event(kind: u8, number: u8, value: i16) -> Vec<u8> {
+        let mut e = 1234u32.to_le_bytes().to_vec();
+        e.extend(value.to_le_bytes());
+        e.extend([kind, number]);
+        e
+    }
+
+    #[test]
+    fn test_pad_map() {
+        // pong says where player one's up and down are
+        let pong = PadMap::for_rom(Genre::Paddle, rominfo::lookup("pong"));
+        assert_eq!(pong.key(Pad::Up), Some(0x1));
+        assert_eq!(pong.key(Pad::Down), Some(0x4));
+        // blinky's maze isn't on the usual diamond
+        let blinky = PadMap::for_rom(Genre::Maze, rominfo::lookup("blinky"));
+        assert_eq!(blinky.key(Pad::Up), Some(0x3));
+        assert_eq!(blinky.key(Pad::Right), Some(0x8));
+        // and without anything to go on, it's the genre's own
+        let unknown = PadMap::for_rom(Genre::Shooter, None);
+        assert_eq!(unknown.key(Pad::Up), Some(0x2));
+        assert_eq!(unknown.key(Pad::B), Some(0x5));
+        assert_eq!(PadMap::for_rom(Genre::Paddle, None).key(Pad::B), None);
+    }
+
+    #[test]
+    fn test_joystick() -> Result<(), Chip8Error> {
+        let mut events = Vec::new();
+        // stick hard left, button A down (at start-up), then the stick
+        // drifting back towards the middle
+        events.extend(event(JS_EVENT_AXIS, 0, -32767));
+        events.extend(event(JS_EVENT_BUTTON | 0x80, 0, 1));
+        events.extend(event(JS_EVENT_AXIS, 0, -1000));
+        let mut joystick = Joystick::new(&events[..]);
+        joystick.poll()?;
+        assert_eq!(joystick.held(), [Pad::A]);
+
+        let mut events = event(JS_EVENT_AXIS, 7, 32767);
+        events.extend(event(JS_EVENT_BUTTON, 0, 0));
+        // an event split over two reads
+        events.extend(&event(JS_EVENT_AXIS, 6, 32767)[..3]);
+        joystick.source = &events[..];
+        joystick.poll()?;
+        assert_eq!(joystick.held(), [Pad::Down]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_pad_input() -> Result<(), Chip8Error> {
+        let events = event(JS_EVENT_AXIS, 1, -32767);
+        let mut keyboard = DummyInput::new(&[0xa]);
+        let map = PadMap::for_rom(Genre::Maze, None);
+        let mut input = PadInput::new(&mut keyboard, Joystick::new(&events[..]), map);
+        assert_eq!(input.read_key()?, Some(0x2));
+        // let go, for longer than a jitter, and it's the keyboard's turn
+        input.joystick.release(&[Pad::Up]);
+        input.tick()?;
+        assert_eq!(input.read_key()?, Some(0x2));
+        input.tick()?;
+        input.tick()?;
+        assert_eq!(input.transitions(), [KeyTransition::Release(0x2)]);
+        assert_eq!(input.read_key()?, Some(0xa));
+        Ok(())
+    }
+
+    /// counts its shakes
+    struct Shaker<'c>(&'c std::cell::Cell<u32>);
+
+    impl Rumble for Shaker<'_> {
+        fn rumble(&mut self) -> Result<(), Chip8Error> {
+            self.0.set(self.0.get() + 1);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_rumble() -> Result<(), Chip8Error> {
+        assert_eq!(
+            RumbleOn::parse("collision")?,
+            RumbleOn {
+                buzzer: false,
+                collision: true
+            }
+        );
+        assert!(RumbleOn::parse("buzzer,bump").is_err());
+
+        let shakes = std::cell::Cell::new(0);
+        let mut keyboard = DummyInput::new(&[]);
+        let map = PadMap::for_rom(Genre::Maze, None);
+        let mut input = PadInput::new(&mut keyboard, Joystick::new(&[][..]), map);
+        input.set_rumble(Box::new(Shaker(&shakes)), RumbleOn::parse("collision")?);
+        input.feedback(Feedback::Buzzer);
+        input.tick()?;
+        assert_eq!(shakes.get(), 0);
+        // a few collisions in a frame are the one shake
+        input.feedback(Feedback::Collision);
+        input.feedback(Feedback::Collision);
+        input.tick()?;
+        input.tick()?;
+        assert_eq!(shakes.get(), 1);
+        Ok(())
+    }
+}
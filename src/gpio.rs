@@ -0,0 +1,415 @@
+//! # Raspberry Pi GPIO
+//!
+//! turns a Pi into a CHIP-8 cabinet: the picture on a 64x32 HUB75 LED
+//! matrix wired to the GPIO pins, and the keys on buttons. only built with
+//! --features gpio. the pins are driven through /dev/gpiomem, which the
+//! Pis up to the 4 have (the 5's GPIO is behind its RP1 chip, so isn't
+//! supported)
+//!
+//! the matrix is wired as rpi-rgb-led-matrix's "regular" mapping. each
+//! button connects a pin to ground, with the pin's pull-up turned on, e.g.
+//! with `gpio=5,6,12,13,16,19,26=ip,pu` in config.txt
+use crate::error::Chip8Error;
+use crate::input::Input;
+use crate::keypad::{KeyFilter, KeyRepeat, KeyTransition};
+use crate::{display::Display, error::Chip8Error::DisplayError};
+use std::ffi::CString;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+/// the BCM283x's GPIO registers, as 32-bit words from the start of the page
+const GPIO_MAP_LEN: usize = 4096;
+const GPIO_FSEL: usize = 0;
+const GPIO_SET: usize = 7;
+const GPIO_CLR: usize = 10;
+const GPIO_LEV: usize = 13;
+
+/// the panel's size, and how many rows it lights at once
+const HUB75_WIDTH: usize = 64;
+const HUB75_HEIGHT: usize = 32;
+const HUB75_ROWS: usize = HUB75_HEIGHT / 2;
+
+/// buttons for a cabinet: up, down, left, right and fire on the keys most
+/// games use for them, and a spare
+pub const CABINET_BUTTONS: [(u8, u8); 6] = [
+    (5, 0x2),
+    (6, 0x8),
+    (12, 0x4),
+    (13, 0x6),
+    (16, 0x5),
+    (19, 0xa),
+];
+/// and one for the emulator's menu
+pub const CABINET_MENU_BUTTON: u8 = 26;
+
+/// the GPIO registers, mapped into memory
+pub struct Gpio {
+    regs: *mut u32,
+}
+
+// the registers are hardware: every access is volatile, and setting and
+// clearing pins are single writes that can't tear
+unsafe impl Send for Gpio {}
+unsafe impl Sync for Gpio {}
+
+impl Gpio {
+    pub fn open() -> Result<Self, Chip8Error> {
+        let path = CString::new("/dev/gpiomem").unwrap();
+        // SAFETY: a fresh mapping of the GPIO page, checked before use and
+        // unmapped on drop
+        unsafe {
+            let fd = libc::open(path.as_ptr(), libc::O_RDWR | libc::O_SYNC);
+            if fd < 0 {
+                return Err(io::Error::last_os_error().into());
+            }
+            let regs = libc::mmap(
+                std::ptr::null_mut(),
+                GPIO_MAP_LEN,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                fd,
+                0,
+            );
+            libc::close(fd);
+            if regs == libc::MAP_FAILED {
+                return Err(io::Error::last_os_error().into());
+            }
+            Ok(Gpio {
+                regs: regs as *mut u32,
+            })
+        }
+    }
+
+    fn read(&self, reg: usize) -> u32 {
+        // SAFETY: reg is one of the GPIO_ words, all inside the mapping
+        unsafe { self.regs.add(reg).read_volatile() }
+    }
+
+    fn write(&self, reg: usize, value: u32) {
+        // SAFETY: as read
+        unsafe { self.regs.add(reg).write_volatile(value) }
+    }
+
+    /// make pin an output (true) or an input
+    pub fn set_output(&self, pin: u8, output: bool) {
+        let (reg, shift) = (GPIO_FSEL + pin as usize / 10, (pin as u32 % 10) * 3);
+        let fsel = self.read(reg) & !(0b111 << shift);
+        self.write(reg, fsel | ((output as u32) << shift));
+    }
+
+    /// drive the pins in mask high
+    pub fn set(&self, mask: u32) {
+        self.write(GPIO_SET, mask);
+    }
+
+    /// and low
+    pub fn clear(&self, mask: u32) {
+        self.write(GPIO_CLR, mask);
+    }
+
+    /// which pins are high, a bit each
+    pub fn levels(&self) -> u32 {
+        self.read(GPIO_LEV)
+    }
+}
+
+impl Drop for Gpio {
+    fn drop(&mut self) {
+        // SAFETY: the mapping open made, which nothing uses any more
+        unsafe {
+            libc::munmap(self.regs as *mut libc::c_void, GPIO_MAP_LEN);
+        }
+    }
+}
+
+/// which GPIO pins the panel's on
+#[derive(Debug, Clone, Copy)]
+pub struct Hub75Pins {
+    pub output_enable: u8,
+    pub clock: u8,
+    pub strobe: u8,
+    /// A, B, C and D
+    pub address: [u8; 4],
+    /// red, green and blue for the top half's row, then the bottom half's
+    pub top: [u8; 3],
+    pub bottom: [u8; 3],
+}
+
+impl Hub75Pins {
+    /// rpi-rgb-led-matrix's "regular" wiring
+    pub const REGULAR: Hub75Pins = Hub75Pins {
+        output_enable: 18,
+        clock: 17,
+        strobe: 4,
+        address: [22, 23, 24, 25],
+        top: [11, 27, 7],
+        bottom: [8, 9, 10],
+    };
+
+    fn mask(pins: &[u8]) -> u32 {
+        pins.iter().fold(0, |m, p| m | 1 << p)
+    }
+
+    fn all(&self) -> Vec<u8> {
+        let mut pins = vec![self.output_enable, self.clock, self.strobe];
+        pins.extend(self.address);
+        pins.extend(self.top);
+        pins.extend(self.bottom);
+        pins
+    }
+}
+
+/// the latest frame, and its resolution
+struct Picture {
+    data: Vec<u8>,
+    width: usize,
+    height: usize,
+}
+
+/// is the panel's pixel at x, y lit? a hires frame is squashed into it, so
+/// any pixel lit in a 2x2 block lights the panel's
+fn panel_pixel(picture: &Picture, x: usize, y: usize) -> bool {
+    let (sx, sy) = (picture.width / HUB75_WIDTH, picture.height / HUB75_HEIGHT);
+    (0..sy).any(|dy| {
+        (0..sx).any(|dx| {
+            let (px, py) = (x * sx + dx, y * sy + dy);
+            picture
+                .data
+                .get((py * picture.width + px) / 8)
+                .is_some_and(|b| b & (0x80 >> (px % 8)) != 0)
+        })
+    })
+}
+
+/// draws on a HUB75 LED matrix, lit pixels white. the panel only lights
+/// two rows at a time, so a thread of its own keeps scanning the latest
+/// frame down it
+pub struct Hub75Display {
+    picture: Arc<Mutex<Picture>>,
+    running: Arc<AtomicBool>,
+    scan: Option<JoinHandle<()>>,
+}
+
+impl Hub75Display {
+    pub fn new(gpio: Arc<Gpio>, pins: Hub75Pins) -> Self {
+        for pin in pins.all() {
+            gpio.set_output(pin, true);
+        }
+        let picture = Arc::new(Mutex::new(Picture {
+            data: vec![0; HUB75_WIDTH * HUB75_HEIGHT / 8],
+            width: HUB75_WIDTH,
+            height: HUB75_HEIGHT,
+        }));
+        let running = Arc::new(AtomicBool::new(true));
+        let scan = {
+            let (picture, running) = (picture.clone(), running.clone());
+            thread::spawn(move || {
+                while running.load(Ordering::Relaxed) {
+                    Self::scan(&gpio, &pins, &picture);
+                }
+                // leave it dark
+                gpio.set(1 << pins.output_enable);
+            })
+        };
+        Hub75Display {
+            picture,
+            running,
+            scan: Some(scan),
+        }
+    }
+
+    /// put one whole frame out, a pair of rows at a time
+    fn scan(gpio: &Gpio, pins: &Hub75Pins, picture: &Mutex<Picture>) {
+        let lit: Vec<[bool; 2]> = {
+            let picture = picture.lock().unwrap();
+            (0..HUB75_ROWS * HUB75_WIDTH)
+                .map(|n| {
+                    let (x, y) = (n % HUB75_WIDTH, n / HUB75_WIDTH);
+                    [
+                        panel_pixel(&picture, x, y),
+                        panel_pixel(&picture, x, y + HUB75_ROWS),
+                    ]
+                })
+                .collect()
+        };
+        let colours = Hub75Pins::mask(&pins.top) | Hub75Pins::mask(&pins.bottom);
+        let address = Hub75Pins::mask(&pins.address);
+        for (row, columns) in lit.chunks(HUB75_WIDTH).enumerate() {
+            for [top, bottom] in columns {
+                gpio.clear(colours | 1 << pins.clock);
+                let mut on = 0;
+                if *top {
+                    on |= Hub75Pins::mask(&pins.top);
+                }
+                if *bottom {
+                    on |= Hub75Pins::mask(&pins.bottom);
+                }
+                gpio.set(on);
+                gpio.set(1 << pins.clock);
+            }
+            // blank while the row changes, then latch the new one in
+            gpio.set(1 << pins.output_enable);
+            gpio.clear(address);
+            let row_bits = pins
+                .address
+                .iter()
+                .enumerate()
+                .filter(|(bit, _)| row & (1 << bit) != 0)
+                .fold(0, |m, (_, p)| m | 1 << p);
+            gpio.set(row_bits);
+            gpio.set(1 << pins.strobe);
+            gpio.clear(1 << pins.strobe);
+            gpio.clear(1 << pins.output_enable);
+        }
+    }
+}
+
+impl Display for Hub75Display {
+    fn draw(&mut self, data: &[u8]) -> Result<(), Chip8Error> {
+        let mut picture = self
+            .picture
+            .lock()
+            .map_err(|_| DisplayError("the matrix's scan thread has died".to_string()))?;
+        if data.len() != picture.width * picture.height / 8 {
+            return Err(DisplayError(format!(
+                "expected {} bytes, got {}",
+                picture.width * picture.height / 8,
+                data.len()
+            )));
+        }
+        picture.data.copy_from_slice(data);
+        Ok(())
+    }
+
+    fn get_display_size_bytes(&mut self) -> usize {
+        self.picture.lock().map_or(0, |p| p.width * p.height / 8)
+    }
+
+    fn set_mode(&mut self, width: usize, height: usize) -> Result<(), Chip8Error> {
+        let mut picture = self
+            .picture
+            .lock()
+            .map_err(|_| DisplayError("the matrix's scan thread has died".to_string()))?;
+        *picture = Picture {
+            data: vec![0; width * height / 8],
+            width,
+            height,
+        };
+        Ok(())
+    }
+}
+
+impl Drop for Hub75Display {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(scan) = self.scan.take() {
+            let _ = scan.join();
+        }
+    }
+}
+
+/// reads buttons wired to GPIO pins, each held down for as long as it's
+/// pressed (and a bounce or two after)
+pub struct GpioInput {
+    gpio: Arc<Gpio>,
+    /// (pin, key)
+    buttons: Vec<(u8, u8)>,
+    menu: u8,
+    menu_down: bool,
+    keys: KeyFilter,
+    transitions: Vec<KeyTransition>,
+}
+
+impl GpioInput {
+    pub fn new(gpio: Arc<Gpio>, buttons: &[(u8, u8)], menu: u8) -> Self {
+        for (pin, _) in buttons {
+            gpio.set_output(*pin, false);
+        }
+        gpio.set_output(menu, false);
+        GpioInput {
+            gpio,
+            buttons: buttons.to_vec(),
+            menu,
+            menu_down: false,
+            keys: KeyFilter::new(KeyRepeat::BUTTONS),
+            transitions: Vec::new(),
+        }
+    }
+
+    /// the first button that's down now
+    fn sample(&mut self) {
+        let levels = self.gpio.levels();
+        let key = self
+            .buttons
+            .iter()
+            .find(|(pin, _)| Self::down(levels, *pin))
+            .map(|(_, key)| *key);
+        self.keys.sample(key);
+    }
+
+    /// buttons pull their pins to ground
+    fn down(levels: u32, pin: u8) -> bool {
+        levels & (1 << pin) == 0
+    }
+}
+
+impl Input for GpioInput {
+    fn flush_keys(&mut self) -> Result<(), Chip8Error> {
+        // a button's still down until it's let go
+        Ok(())
+    }
+
+    fn read_key(&mut self) -> Result<Option<u8>, Chip8Error> {
+        self.sample();
+        Ok(self.keys.read())
+    }
+
+    fn tick(&mut self) -> Result<(), Chip8Error> {
+        self.sample();
+        self.transitions = self.keys.tick();
+        Ok(())
+    }
+
+    fn transitions(&self) -> &[KeyTransition] {
+        &self.transitions
+    }
+
+    fn take_menu_request(&mut self) -> Result<bool, Chip8Error> {
+        // once per press, not for as long as it's held
+        let down = Self::down(self.gpio.levels(), self.menu);
+        let pressed = down && !self.menu_down;
+        self.menu_down = down;
+        Ok(pressed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_panel_pixel() {
+        let mut data = vec![0; 0x100];
+        data[0] = 0x40;
+        let lores = Picture {
+            data,
+            width: 64,
+            height: 32,
+        };
+        assert!(panel_pixel(&lores, 1, 0));
+        assert!(!panel_pixel(&lores, 0, 0));
+        // any of a hires 2x2 block lights the panel's pixel
+        let mut data = vec![0; 0x400];
+        data[16 + 1] = 0x01;
+        let hires = Picture {
+            data,
+            width: 128,
+            height: 64,
+        };
+        assert!(panel_pixel(&hires, 7, 0));
+        assert!(!panel_pixel(&hires, 6, 0));
+        assert!(!panel_pixel(&hires, 7, 1));
+    }
+}
@@ -0,0 +1,697 @@
+//! # a debugger in a window
+//!
+//! chip8 --frontend gui game.ch8 (built with --features gui) plays the game
+//! in a window rather than the terminal, with everything the debugger can
+//! see round it in panels of their own: the screen, the registers, memory,
+//! a disassembly round the program counter, and a trace of the last few
+//! instructions. each panel can be moved, resized, folded up or closed
+//! (and opened again from the View menu), and the game can be paused,
+//! stepped an instruction or a frame at a time, and carried on from the
+//! buttons along the top (or with escape, which pauses it as it would in
+//! the terminal). the screen's fitted into its panel as the window module
+//! says, as --scaling (or the config's scaling) asks, and can be fitted
+//! differently from the View menu while it's running. F11 (or the View
+//! menu) takes the window fullscreen and back, and the window opens where
+//! it was left, fullscreen or not, as the config keeps it.
+//!
+//! the window runs on a thread of its own, as the render thread does, and
+//! sees the machine through the debug module's link, so the emulator runs
+//! just as it would anywhere else. winit lets a window live off the main
+//! thread on Linux (under X11) and Windows, but not on macOS, so there's no
+//! gui frontend there
+use crate::debug::{self, Control, DebugLink, DebugState, Debugger};
+use crate::display::Display;
+use crate::error::Chip8Error;
+use crate::heat::Heat;
+use crate::input::{DummyInput, Input, Keymap};
+use crate::isa;
+use crate::keypad::{KeyFilter, KeyTransition};
+use crate::platform::{Platform, TerminalOptions};
+use crate::sound::{self, Sound};
+use crate::window::{Fit, Geometry, Scaling, Whereabouts};
+use eframe::egui::{self, Color32, Key, RichText, Sense, TextStyle};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+
+/// how big the window starts, in points
+const GUI_WINDOW_SIZE: [f32; 2] = [1200.0, 760.0];
+
+/// how many instructions the disassembly shows before the program counter,
+/// and after it
+const DISASSEMBLY_BEFORE: u16 = 8;
+const DISASSEMBLY_AFTER: u16 = 16;
+
+/// memory's shown this many bytes to a row
+const MEMORY_ROW_BYTES: usize = 16;
+
+/// what's on the screen, for the window to draw
+struct Screen {
+    data: Vec<u8>,
+    width: usize,
+    height: usize,
+    status: String,
+    notice: String,
+    // coloured in by this instead, while there's a heat map
+    heat: Option<Heat>,
+}
+
+impl Screen {
+    fn new(width: usize, height: usize) -> Self {
+        Screen {
+            data: vec![0; width * height / 8],
+            width,
+            height,
+            status: String::new(),
+            notice: String::new(),
+            heat: None,
+        }
+    }
+
+    fn lit(&self, x: usize, y: usize) -> bool {
+        self.data
+            .get((y * self.width + x) / 8)
+            .is_some_and(|b| b & (0x80 >> (x % 8)) != 0)
+    }
+}
+
+fn lock(screen: &Mutex<Screen>) -> MutexGuard<'_, Screen> {
+    screen.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// hands each frame to the window, and has it redrawn
+pub struct GuiDisplay {
+    screen: Arc<Mutex<Screen>>,
+    ctx: egui::Context,
+}
+
+impl Display for GuiDisplay {
+    fn draw(&mut self, data: &[u8]) -> Result<(), Chip8Error> {
+        lock(&self.screen).data = data.to_vec();
+        self.ctx.request_repaint();
+        Ok(())
+    }
+
+    fn get_display_size_bytes(&mut self) -> usize {
+        lock(&self.screen).data.len()
+    }
+
+    fn set_status(&mut self, status: &str) {
+        lock(&self.screen).status = status.to_string();
+    }
+
+    fn notify(&mut self, notice: &str) {
+        lock(&self.screen).notice = notice.to_string();
+        self.ctx.request_repaint();
+    }
+
+    fn set_heat(&mut self, heat: Option<Heat>) {
+        lock(&self.screen).heat = heat;
+    }
+
+    fn set_mode(&mut self, width: usize, height: usize) -> Result<(), Chip8Error> {
+        let mut screen = lock(&self.screen);
+        let (status, notice) = (
+            std::mem::take(&mut screen.status),
+            std::mem::take(&mut screen.notice),
+        );
+        *screen = Screen {
+            status,
+            notice,
+            ..Screen::new(width, height)
+        };
+        Ok(())
+    }
+}
+
+/// the keys pressed in the window, as host keys
+pub struct GuiInput {
+    keymap: Keymap,
+    pressed: Receiver<(char, bool)>,
+    keys: KeyFilter,
+    transitions: Vec<KeyTransition>,
+}
+
+impl GuiInput {
+    /// what the window's sent since we last looked
+    fn read_window(&mut self) {
+        while let Ok((host, down)) = self.pressed.try_recv() {
+            match (self.keymap.get(&host), down) {
+                (Some(k), true) => self.keys.seen(*k),
+                (Some(k), false) => self.keys.let_go(*k),
+                (None, _) => {}
+            }
+        }
+    }
+}
+
+impl Input for GuiInput {
+    fn flush_keys(&mut self) -> Result<(), Chip8Error> {
+        self.keys.flush();
+        Ok(())
+    }
+
+    fn read_key(&mut self) -> Result<Option<u8>, Chip8Error> {
+        self.read_window();
+        Ok(self.keys.read())
+    }
+
+    fn tick(&mut self) -> Result<(), Chip8Error> {
+        self.read_window();
+        self.transitions = self.keys.tick();
+        Ok(())
+    }
+
+    fn transitions(&self) -> &[KeyTransition] {
+        &self.transitions
+    }
+}
+
+/// the host key a window key's taken as, e.g. 'q' for Q, if it's one a
+/// keymap could have
+fn host_key(key: Key) -> Option<char> {
+    let mut chars = key.name().chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c.to_ascii_lowercase()),
+        _ => None,
+    }
+}
+
+/// which panels are open
+struct Panels {
+    screen: bool,
+    registers: bool,
+    memory: bool,
+    disassembly: bool,
+    trace: bool,
+}
+
+/// the window, on its own thread
+struct Window {
+    link: DebugLink,
+    screen: Arc<Mutex<Screen>>,
+    keys: Sender<(char, bool)>,
+    panels: Panels,
+    follow_pc: bool,
+    scaling: Scaling,
+    // the picture, as the graphics card has it
+    texture: Option<egui::TextureHandle>,
+    whereabouts: Whereabouts,
+}
+
+impl Window {
+    /// the buttons along the top
+    fn controls(&mut self, ui: &mut egui::Ui, state: &DebugState) {
+        egui::MenuBar::new().ui(ui, |ui| {
+            ui.menu_button("View", |ui| {
+                ui.checkbox(&mut self.panels.screen, "Screen");
+                ui.checkbox(&mut self.panels.registers, "Registers");
+                ui.checkbox(&mut self.panels.memory, "Memory");
+                ui.checkbox(&mut self.panels.disassembly, "Disassembly");
+                ui.checkbox(&mut self.panels.trace, "Trace");
+                ui.separator();
+                for fit in Fit::ALL {
+                    ui.radio_value(&mut self.scaling.fit, fit, fit.name());
+                }
+                ui.checkbox(&mut self.scaling.smooth, "smooth");
+                ui.separator();
+                let fullscreen = ui.ctx().input(|i| i.viewport().fullscreen.unwrap_or(false));
+                if ui
+                    .selectable_label(fullscreen, "fullscreen (F11)")
+                    .clicked()
+                {
+                    ui.ctx()
+                        .send_viewport_cmd(egui::ViewportCommand::Fullscreen(!fullscreen));
+                }
+            });
+            ui.separator();
+            match state.paused {
+                true if ui.button("run").clicked() => self.link.send(Control::Run),
+                false if ui.button("pause").clicked() => self.link.send(Control::Pause),
+                _ => {}
+            }
+            ui.add_enabled_ui(state.paused, |ui| {
+                if ui.button("step").clicked() {
+                    self.link.send(Control::Step);
+                }
+                if ui.button("frame").clicked() {
+                    self.link.send(Control::Frame);
+                }
+            });
+            ui.separator();
+            let screen = lock(&self.screen);
+            match &state.error {
+                Some(e) => ui.colored_label(Color32::LIGHT_RED, e),
+                None if state.paused => ui.label("paused"),
+                None => ui.label(&screen.notice),
+            };
+        });
+    }
+
+    /// send the keys on, unless something in the window's being typed into
+    fn forward_keys(&self, ctx: &egui::Context, paused: bool) {
+        if ctx.wants_keyboard_input() {
+            return;
+        }
+        // the window can't be told anything while its input's being read
+        let mut toggle = None;
+        ctx.input(|i| {
+            for event in &i.events {
+                match event {
+                    egui::Event::Key {
+                        key: Key::Escape,
+                        pressed: true,
+                        repeat: false,
+                        ..
+                    } if !paused => self.link.send(Control::Pause),
+                    egui::Event::Key {
+                        key: Key::F11,
+                        pressed: true,
+                        repeat: false,
+                        ..
+                    } => toggle = Some(!i.viewport().fullscreen.unwrap_or(false)),
+                    egui::Event::Key { key, pressed, .. } => {
+                        if let Some(c) = host_key(*key) {
+                            // nothing to do if the emulator's stopped
+                            let _ = self.keys.send((c, *pressed));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        });
+        if let Some(fullscreen) = toggle {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Fullscreen(fullscreen));
+        }
+    }
+}
+
+/// where the window is now
+fn track(ctx: &egui::Context, whereabouts: &Whereabouts) {
+    let (outer, inner, fullscreen) = ctx.input(|i| {
+        let viewport = i.viewport();
+        (
+            viewport.outer_rect,
+            viewport.inner_rect,
+            viewport.fullscreen.unwrap_or(false),
+        )
+    });
+    if let (Some(outer), Some(inner)) = (outer, inner) {
+        whereabouts.moved(Geometry {
+            x: outer.min.x as i32,
+            y: outer.min.y as i32,
+            width: inner.width() as u32,
+            height: inner.height() as u32,
+            fullscreen,
+        });
+    }
+}
+
+/// the picture, fitted into the room there is as scaling says
+fn screen(
+    ui: &mut egui::Ui,
+    screen: &Screen,
+    scaling: Scaling,
+    texture: &mut Option<egui::TextureHandle>,
+) {
+    let mut room = ui.available_size();
+    if !screen.status.is_empty() {
+        room.y -= ui.text_style_height(&TextStyle::Body) + ui.spacing().item_spacing.y;
+    }
+    // fitted in the screen's own pixels, not egui's points, or whole
+    // multiples wouldn't be on a high-DPI screen
+    let ppp = ui.ctx().pixels_per_point();
+    let placed = scaling.place(
+        (screen.width as u32, screen.height as u32),
+        ((room.x * ppp) as u32, (room.y * ppp) as u32),
+    );
+    let (response, painter) = ui.allocate_painter(room.max(egui::Vec2::ZERO), Sense::hover());
+    painter.rect_filled(response.rect, 0.0, Color32::BLACK);
+
+    let size = [screen.width, screen.height];
+    let image = match &screen.heat {
+        // warm pixels in their colours, and cold lit ones greyed out
+        Some(heat) => {
+            let rgb: Vec<u8> = (0..screen.height)
+                .flat_map(|y| (0..screen.width).map(move |x| (x, y)))
+                .flat_map(|(x, y)| match (heat.level(x, y), screen.lit(x, y)) {
+                    (0, true) => [0x40; 3],
+                    (0, false) => [0; 3],
+                    (l, _) => {
+                        let (r, g, b) = Heat::colour(l);
+                        [r, g, b]
+                    }
+                })
+                .collect();
+            egui::ColorImage::from_rgb(size, &rgb)
+        }
+        None => {
+            let lit: Vec<u8> = (0..screen.height)
+                .flat_map(|y| {
+                    (0..screen.width).map(move |x| if screen.lit(x, y) { 0xff } else { 0 })
+                })
+                .collect();
+            egui::ColorImage::from_gray(size, &lit)
+        }
+    };
+    let options = match scaling.smooth {
+        true => egui::TextureOptions::LINEAR,
+        false => egui::TextureOptions::NEAREST,
+    };
+    let texture = match texture {
+        Some(t) => {
+            t.set(image, options);
+            t
+        }
+        None => texture.insert(ui.ctx().load_texture("screen", image, options)),
+    };
+    let rect = egui::Rect::from_min_size(
+        response.rect.min + egui::vec2(placed.x as f32, placed.y as f32) / ppp,
+        egui::vec2(placed.width as f32, placed.height as f32) / ppp,
+    );
+    let whole = egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0));
+    painter.image(texture.id(), rect, whole, Color32::WHITE);
+    if !screen.status.is_empty() {
+        ui.label(&screen.status);
+    }
+}
+
+fn registers(ui: &mut egui::Ui, state: &DebugState) {
+    ui.monospace(format!("PC {:04x}   I {:04x}", state.pc, state.i));
+    egui::Grid::new("v").show(ui, |ui| {
+        for (row, v) in state.v.chunks(4).enumerate() {
+            for (n, v) in v.iter().enumerate() {
+                ui.monospace(format!("V{:X} {:02x}", row * 4 + n, v));
+            }
+            ui.end_row();
+        }
+    });
+    ui.monospace(format!(
+        "DT {:02x}  ST {:02x}",
+        state.delay_timer, state.sound_timer
+    ));
+    ui.monospace(format!("frame {}", state.frames));
+    ui.separator();
+    ui.label("stack");
+    for addr in &state.stack {
+        ui.monospace(format!("{:04x}", addr));
+    }
+}
+
+fn memory(ui: &mut egui::Ui, state: &DebugState, follow_pc: &mut bool) {
+    ui.checkbox(follow_pc, "follow PC");
+    let pc = state.pc as usize;
+    let row_height = ui.text_style_height(&TextStyle::Monospace);
+    let rows = state.memory.len().div_ceil(MEMORY_ROW_BYTES);
+    let mut area = egui::ScrollArea::vertical().auto_shrink(false);
+    if *follow_pc {
+        let offset = (pc / MEMORY_ROW_BYTES) as f32 * (row_height + ui.spacing().item_spacing.y);
+        area = area.vertical_scroll_offset(offset);
+    }
+    area.show_rows(ui, row_height, rows, |ui, shown| {
+        for row in shown {
+            let start = row * MEMORY_ROW_BYTES;
+            let end = (start + MEMORY_ROW_BYTES).min(state.memory.len());
+            ui.horizontal(|ui| {
+                ui.spacing_mut().item_spacing.x = 4.0;
+                ui.monospace(format!("{:04x}", start));
+                for (addr, byte) in (start..end).zip(&state.memory[start..end]) {
+                    let text = RichText::new(format!("{:02x}", byte)).monospace();
+                    match addr == pc || addr == pc + 1 {
+                        true => ui.label(text.background_color(Color32::DARK_BLUE)),
+                        false => ui.label(text),
+                    };
+                }
+            });
+        }
+    });
+}
+
+fn disassembly(ui: &mut egui::Ui, state: &DebugState) {
+    let from = state.pc.saturating_sub(2 * DISASSEMBLY_BEFORE);
+    for addr in (from..state.pc.saturating_add(2 * DISASSEMBLY_AFTER)).step_by(2) {
+        let Some(word) = state.memory.get(addr as usize..addr as usize + 2) else {
+            break;
+        };
+        let inst = u16::from_be_bytes([word[0], word[1]]);
+        let line = format!(
+            "{:04x}: {:04x}  {}",
+            addr,
+            inst,
+            isa::explain(inst, Some(&state.quirks)).unwrap_or_default()
+        );
+        let text = RichText::new(line).monospace();
+        match addr == state.pc {
+            true => ui.label(text.background_color(Color32::DARK_BLUE)),
+            false => ui.label(text),
+        };
+    }
+}
+
+fn trace(ui: &mut egui::Ui, state: &DebugState) {
+    egui::ScrollArea::vertical()
+        .stick_to_bottom(true)
+        .auto_shrink(false)
+        .show(ui, |ui| {
+            for entry in &state.trace {
+                ui.monospace(entry.to_string());
+            }
+        });
+}
+
+impl eframe::App for Window {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        if ctx.input(|i| i.viewport().close_requested()) {
+            self.link.send(Control::Quit);
+        }
+        // a copy, so the emulator isn't held up while it's drawn
+        let state = self.link.state().clone();
+        track(ctx, &self.whereabouts);
+        self.forward_keys(ctx, state.paused);
+        egui::TopBottomPanel::top("controls").show(ctx, |ui| self.controls(ui, &state));
+        egui::CentralPanel::default().show(ctx, |_| {});
+
+        egui::Window::new("Screen")
+            .open(&mut self.panels.screen)
+            .default_pos([280.0, 40.0])
+            .default_size([512.0, 256.0])
+            .show(ctx, |ui| {
+                screen(ui, &lock(&self.screen), self.scaling, &mut self.texture)
+            });
+        egui::Window::new("Registers")
+            .open(&mut self.panels.registers)
+            .default_pos([10.0, 40.0])
+            .show(ctx, |ui| registers(ui, &state));
+        let follow_pc = &mut self.follow_pc;
+        egui::Window::new("Memory")
+            .open(&mut self.panels.memory)
+            .default_pos([820.0, 40.0])
+            .default_size([360.0, 400.0])
+            .show(ctx, |ui| memory(ui, &state, follow_pc));
+        egui::Window::new("Disassembly")
+            .open(&mut self.panels.disassembly)
+            .default_pos([10.0, 380.0])
+            .show(ctx, |ui| disassembly(ui, &state));
+        egui::Window::new("Trace")
+            .open(&mut self.panels.trace)
+            .default_pos([420.0, 380.0])
+            .default_size([560.0, 300.0])
+            .show(ctx, |ui| trace(ui, &state));
+    }
+}
+
+/// let the window's event loop run off the main thread
+fn any_thread(builder: &mut eframe::EventLoopBuilder<eframe::UserEvent>) {
+    #[cfg(windows)]
+    {
+        use winit::platform::windows::EventLoopBuilderExtWindows;
+        builder.with_any_thread(true);
+    }
+    #[cfg(all(unix, not(target_os = "macos")))]
+    {
+        use winit::platform::x11::EventLoopBuilderExtX11;
+        builder.with_any_thread(true);
+    }
+}
+
+/// a window with the game and the debugger in it
+pub struct GuiPlatform {
+    display: GuiDisplay,
+    input: Box<dyn Input>,
+    sound: Box<dyn Sound>,
+    debugger: Option<Debugger>,
+    whereabouts: Whereabouts,
+}
+
+impl GuiPlatform {
+    /// open the window. keymap's None when keys come from somewhere else
+    pub fn new(keymap: Option<Keymap>, options: TerminalOptions) -> Result<Self, Chip8Error> {
+        if cfg!(target_os = "macos") {
+            return Err(Chip8Error::ConfigError(
+                "the gui frontend doesn't run on macOS, which wants windows on the main thread"
+                    .to_string(),
+            ));
+        }
+        let (debugger, link) = debug::channel();
+        let screen = Arc::new(Mutex::new(Screen::new(64, 32)));
+        let (keys, pressed) = mpsc::channel();
+        let (made, ready) = mpsc::channel();
+        let whereabouts = Whereabouts::new(options.window);
+        let mut viewport = egui::ViewportBuilder::default()
+            .with_title("chip8")
+            .with_inner_size(GUI_WINDOW_SIZE);
+        if let Some(g) = options.window {
+            viewport = viewport
+                .with_position([g.x as f32, g.y as f32])
+                .with_inner_size([g.width as f32, g.height as f32])
+                .with_fullscreen(g.fullscreen);
+        }
+        let redraw = link.clone();
+        let window = Window {
+            link: link.clone(),
+            screen: screen.clone(),
+            keys,
+            panels: Panels {
+                screen: true,
+                registers: true,
+                memory: true,
+                disassembly: true,
+                trace: true,
+            },
+            follow_pc: true,
+            scaling: options.scaling,
+            texture: None,
+            whereabouts: whereabouts.clone(),
+        };
+        thread::spawn(move || {
+            let failed = made.clone();
+            let mut native = eframe::NativeOptions {
+                viewport,
+                ..Default::default()
+            };
+            native.event_loop_builder = Some(Box::new(any_thread));
+            let shown = eframe::run_native(
+                "chip8",
+                native,
+                Box::new(move |cc| {
+                    let _ = made.send(Ok(cc.egui_ctx.clone()));
+                    Ok(Box::new(window))
+                }),
+            );
+            if let Err(e) = shown {
+                let _ = failed.send(Err(e.to_string()));
+            }
+            // the window's gone, so there's nobody left to play
+            link.send(Control::Quit);
+        });
+        let ctx = match ready.recv() {
+            Ok(Ok(ctx)) => ctx,
+            Ok(Err(e)) => {
+                return Err(Chip8Error::DisplayError(format!(
+                    "couldn't open a window: {}",
+                    e
+                )))
+            }
+            Err(_) => {
+                return Err(Chip8Error::DisplayError(
+                    "the window's thread died starting up".to_string(),
+                ))
+            }
+        };
+        let repaint = ctx.clone();
+        redraw.on_change(move || repaint.request_repaint());
+        let input: Box<dyn Input> = match keymap {
+            Some(keymap) => {
+                let mut keys = KeyFilter::new(options.key_repeat);
+                keys.set_assist(options.assist);
+                Box::new(GuiInput {
+                    keymap,
+                    pressed,
+                    keys,
+                    transitions: Vec::new(),
+                })
+            }
+            None => Box::new(DummyInput::new(&[])),
+        };
+        Ok(GuiPlatform {
+            display: GuiDisplay { screen, ctx },
+            input,
+            sound: sound::best_available(options.audio_buffer),
+            debugger: Some(debugger),
+            whereabouts,
+        })
+    }
+}
+
+impl Platform for GuiPlatform {
+    fn devices(&mut self) -> (&mut dyn Display, &mut dyn Input, &mut dyn Sound) {
+        (&mut self.display, self.input.as_mut(), self.sound.as_mut())
+    }
+
+    fn debugger(&mut self) -> Option<Debugger> {
+        self.debugger.take()
+    }
+
+    fn whereabouts(&self) -> Option<Whereabouts> {
+        Some(self.whereabouts.clone())
+    }
+}
+
+impl Drop for GuiPlatform {
+    fn drop(&mut self) {
+        // the emulator's finished with it
+        self.display
+            .ctx
+            .send_viewport_cmd(egui::ViewportCommand::Close);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::conventional_keymap;
+    use crate::keypad::KeyRepeat;
+
+    #[test]
+    fn test_display() -> Result<(), Chip8Error> {
+        let screen = Arc::new(Mutex::new(Screen::new(64, 32)));
+        let mut display = GuiDisplay {
+            screen: screen.clone(),
+            ctx: egui::Context::default(),
+        };
+        assert_eq!(display.get_display_size_bytes(), 256);
+        display.set_status("press 5");
+        display.set_mode(128, 64)?;
+        assert_eq!(display.get_display_size_bytes(), 1024);
+        let mut data = vec![0; 1024];
+        data[16] = 0x40;
+        display.draw(&data)?;
+        let screen = lock(&screen);
+        assert!(screen.lit(1, 1) && !screen.lit(0, 1));
+        assert_eq!(screen.status, "press 5");
+        Ok(())
+    }
+
+    #[test]
+    fn test_input() -> Result<(), Chip8Error> {
+        assert_eq!(host_key(Key::Q), Some('q'));
+        assert_eq!(host_key(Key::Num4), Some('4'));
+        assert_eq!(host_key(Key::Escape), None);
+
+        let (keys, pressed) = mpsc::channel();
+        let mut input = GuiInput {
+            keymap: conventional_keymap(),
+            pressed,
+            keys: KeyFilter::new(KeyRepeat::TERMINAL),
+            transitions: Vec::new(),
+        };
+        keys.send(('w', true)).unwrap();
+        input.tick()?;
+        assert_eq!(input.read_key()?, Some(0x5));
+        assert_eq!(input.transitions(), [KeyTransition::Press(0x5)]);
+        keys.send(('w', false)).unwrap();
+        input.tick()?;
+        assert_eq!(input.read_key()?, None);
+        Ok(())
+    }
+}
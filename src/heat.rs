@@ -0,0 +1,179 @@
+//! # heat map
+//!
+//! a debugging view of where the drawing's been going on: each pixel
+//! coloured by how often it's been XORed lately, whether it's lit now or
+//! not. a sprite that's rubbed out and drawn again every frame (which is
+//! what flickers) glows hot, one drawn once and left alone goes cold, and
+//! the way a game redraws its screen shows up as the pattern in between.
+//!
+//! the interpreter counts every pixel each sprite XORs as it's written to
+//! the screen's memory, cools them all a little every frame, and hands the
+//! display the result before drawing it, for the display to colour the
+//! picture in with rather than its theme's colours. --heat-map starts with
+//! it on, and the command palette's "heat map" switches it on and off
+use std::fmt;
+
+/// what one XOR adds to a pixel
+const HEAT_PER_XOR: u16 = 64;
+
+/// a pixel's heat is cut by 1/HEAT_COOLING every frame, so something
+/// drawn once fades out over a second or so
+const HEAT_COOLING: u16 = 8;
+
+/// as hot as it's shown: a pixel XORed twice a frame (rubbed out and drawn
+/// again, the way flicker happens) settles here
+const HEAT_MAX: u32 = 2 * HEAT_PER_XOR as u32 * HEAT_COOLING as u32;
+
+/// the colours heat goes through, at the levels they're reached
+const HEAT_COLOURS: [(u8, (u8, u8, u8)); 5] = [
+    (0, (0, 0, 0)),
+    (64, (0, 0, 192)),
+    (128, (224, 0, 0)),
+    (192, (255, 192, 0)),
+    (255, (255, 255, 255)),
+];
+
+/// how hot each pixel of the screen is, as the display sees it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Heat {
+    pub width: usize,
+    pub height: usize,
+    /// a pixel at a time, across then down, from cold (0) to as hot as it
+    /// gets (255)
+    pub levels: Vec<u8>,
+}
+
+impl Heat {
+    /// how hot the pixel x across and y down is (cold off the screen)
+    pub fn level(&self, x: usize, y: usize) -> u8 {
+        match x < self.width {
+            true => self.levels.get(y * self.width + x).copied().unwrap_or(0),
+            false => 0,
+        }
+    }
+
+    /// the colour for a level of heat, as red, green and blue: from black
+    /// through blue, red and yellow to white, so what's hottest stands out
+    /// most
+    pub fn colour(level: u8) -> (u8, u8, u8) {
+        let above = HEAT_COLOURS
+            .iter()
+            .position(|(l, _)| *l >= level)
+            .unwrap_or(0);
+        let (to, high) = HEAT_COLOURS[above];
+        let (from, low) = HEAT_COLOURS[above.saturating_sub(1)];
+        let mix = |a: u8, b: u8| match to - from {
+            0 => b,
+            span => (a as i32 + (b as i32 - a as i32) * (level - from) as i32 / span as i32) as u8,
+        };
+        (mix(low.0, high.0), mix(low.1, high.1), mix(low.2, high.2))
+    }
+}
+
+impl fmt::Display for Heat {
+    /// the levels in a digit a pixel, 0 to 9, for reading in tests
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for row in self.levels.chunks(self.width.max(1)) {
+            for l in row {
+                write!(f, "{}", *l as u32 * 10 / 256)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// keeps count of the XORs, for the interpreter
+#[derive(Debug, Default)]
+pub struct HeatMap {
+    width: usize,
+    height: usize,
+    heat: Vec<u16>,
+}
+
+impl HeatMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// bits were XORed into the index'th byte of a width x height screen.
+    /// a screen of another size than the last starts again cold
+    pub fn xored(&mut self, width: usize, height: usize, index: usize, bits: u8) {
+        if (width, height) != (self.width, self.height) {
+            *self = HeatMap {
+                width,
+                height,
+                heat: vec![0; width * height],
+            };
+        }
+        for b in 0..8 {
+            if bits & (0x80 >> b) != 0 {
+                if let Some(h) = self.heat.get_mut(index * 8 + b) {
+                    *h = h.saturating_add(HEAT_PER_XOR);
+                }
+            }
+        }
+    }
+
+    /// a frame's gone by: everything cools a little, and here's how hot
+    /// it all is now
+    pub fn cool(&mut self) -> Heat {
+        let levels = self
+            .heat
+            .iter_mut()
+            .map(|h| {
+                let level = (*h as u32 * 255 / HEAT_MAX).min(255) as u8;
+                *h -= *h / HEAT_COOLING + (*h % HEAT_COOLING != 0 && *h < HEAT_COOLING) as u16;
+                level
+            })
+            .collect();
+        Heat {
+            width: self.width,
+            height: self.height,
+            levels,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_heat_map() {
+        let mut map = HeatMap::new();
+        for _ in 0..60 {
+            // the left-hand pixel twice a frame, the one beside it once
+            map.xored(16, 1, 0, 0b1100_0000);
+            map.xored(16, 1, 0, 0b1000_0000);
+            map.cool();
+        }
+        // and one just the once, just now, as the others cool
+        map.xored(16, 1, 1, 0b0000_0001);
+        let heat = map.cool();
+        assert_eq!(heat.to_string(), "8400000000000000\n");
+        assert!(heat.level(15, 0) > 0);
+        assert_eq!(heat.level(16, 0), 0);
+        // left alone, it all goes cold
+        for _ in 0..120 {
+            map.cool();
+        }
+        assert!(map.cool().levels.iter().all(|l| *l == 0));
+        // and a new mode starts from nothing
+        map.xored(8, 2, 1, 0xff);
+        let heat = map.cool();
+        assert_eq!(heat.levels.len(), 16);
+        assert!(heat.levels[..8].iter().all(|l| *l == 0));
+        assert!(heat.levels[8..].iter().all(|l| *l > 0));
+    }
+
+    #[test]
+    fn test_colour() {
+        assert_eq!(Heat::colour(0), (0, 0, 0));
+        assert_eq!(Heat::colour(255), (255, 255, 255));
+        // warm's red, hot's yellow, and in between's in between
+        assert_eq!(Heat::colour(128), (224, 0, 0));
+        assert_eq!(Heat::colour(192), (255, 192, 0));
+        assert_eq!(Heat::colour(96), (112, 0, 96));
+    }
+}
@@ -0,0 +1,504 @@
+//! # hotkeys
+//!
+//! the keys that drive the emulator rather than the program: the menu, the
+//! HUD, speed, volume, savestates and which of them the keyboard's talking
+//! to. they all live here, in one registry,
+//! rather than in match arms wherever a key gets read, so the help overlay
+//! can list them and the config can move them about, e.g.
+//!
+//! ```toml
+//! [hotkeys]
+//! faster = "= +"
+//! help = "f1"
+//! mute = ""
+//! ```
+//!
+//! gives faster two keys, moves help to F1 and takes mute away altogether
+//! (--hotkey help=f1 does the same from the command line). each of the ten
+//! savestate slots has a key of its own to save to and load from, e.g.
+//! `save-slot-3 = "#"`: shift and the slot's number to save, and the number
+//! on its own to load, except 0, which was normal speed long before there
+//! were slots (slot 0 loads from the picker instead). a key the keypad's
+//! been mapped to is the keypad's, so a hotkey on it never fires: that gets
+//! a warning, unless it leaves no way to the menu, which is an error. keys
+//! held with ctrl (e.g. ctrl+p, the command palette's) are never the
+//! keypad's.
+//!
+//! keys are HostKeys rather than any one library's idea of a key, so any
+//! frontend that reads a keyboard can turn what it reads into one and ask
+//! the same registry what it does
+use crate::error::Chip8Error;
+use crate::input::Keymap;
+use crate::lang;
+use crate::slots::SLOT_COUNT;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// something a hotkey does
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Action {
+    Menu,
+    Help,
+    Hud,
+    Slower,
+    Faster,
+    NormalSpeed,
+    Quieter,
+    Louder,
+    Mute,
+    /// send the keys to the emulator rather than the program, or back
+    Focus,
+    /// show what's in each savestate slot, to pick one
+    Slots,
+    SaveSlot(u8),
+    LoadSlot(u8),
+    /// search everything there is to do, to do it
+    Palette,
+}
+
+impl Action {
+    /// in the order the help lists them. the slot ones stand for all ten
+    pub const ALL: [Action; 14] = [
+        Action::Menu,
+        Action::Help,
+        Action::Hud,
+        Action::Slower,
+        Action::Faster,
+        Action::NormalSpeed,
+        Action::Quieter,
+        Action::Louder,
+        Action::Mute,
+        Action::Focus,
+        Action::Slots,
+        Action::SaveSlot(0),
+        Action::LoadSlot(0),
+        Action::Palette,
+    ];
+
+    /// what it's called in the config
+    pub fn name(self) -> String {
+        match self {
+            Action::SaveSlot(n) => format!("save-slot-{}", n),
+            Action::LoadSlot(n) => format!("load-slot-{}", n),
+            _ => self.simple_name().to_string(),
+        }
+    }
+
+    fn simple_name(self) -> &'static str {
+        match self {
+            Action::Menu => "menu",
+            Action::Help => "help",
+            Action::Hud => "hud",
+            Action::Slower => "slower",
+            Action::Faster => "faster",
+            Action::NormalSpeed => "normal-speed",
+            Action::Quieter => "quieter",
+            Action::Louder => "louder",
+            Action::Mute => "mute",
+            Action::Focus => "focus",
+            Action::Slots => "slots",
+            Action::SaveSlot(_) => "save-slot",
+            Action::LoadSlot(_) => "load-slot",
+            Action::Palette => "palette",
+        }
+    }
+
+    /// the same for every slot, so the help can put them together
+    fn kind(self) -> Self {
+        match self {
+            Action::SaveSlot(_) => Action::SaveSlot(0),
+            Action::LoadSlot(_) => Action::LoadSlot(0),
+            a => a,
+        }
+    }
+
+    pub fn description(self) -> String {
+        lang::text(&format!("hotkey.{}", self.simple_name())).to_string()
+    }
+
+    pub fn parse(name: &str) -> Result<Self, Chip8Error> {
+        let slot = |prefix: &str| {
+            name.strip_prefix(prefix)
+                .and_then(|n| n.parse::<u8>().ok())
+                .filter(|n| *n < SLOT_COUNT)
+        };
+        if let Some(n) = slot("save-slot-") {
+            return Ok(Action::SaveSlot(n));
+        }
+        if let Some(n) = slot("load-slot-") {
+            return Ok(Action::LoadSlot(n));
+        }
+        Action::ALL
+            .into_iter()
+            .find(|a| a.name() == name)
+            .ok_or_else(|| Chip8Error::ConfigError(format!("no hotkey action called {:?}", name)))
+    }
+}
+
+/// a key on the host's keyboard, as far as hotkeys go
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum HostKey {
+    Char(char),
+    Esc,
+    Tab,
+    /// F1 to F12
+    F(u8),
+    /// a character with ctrl held down
+    Ctrl(char),
+}
+
+impl HostKey {
+    /// a single character, or esc, tab, space or f1-f12, or ctrl+ and a
+    /// letter
+    pub fn parse(s: &str) -> Result<Self, Chip8Error> {
+        let mut chars = s.chars();
+        if let (Some(c), None) = (chars.next(), chars.next()) {
+            return Ok(HostKey::Char(c));
+        }
+        let lower = s.to_ascii_lowercase();
+        if let Some(key) = lower.strip_prefix("ctrl+") {
+            let mut chars = key.chars();
+            if let (Some(c @ 'a'..='z'), None) = (chars.next(), chars.next()) {
+                return Ok(HostKey::Ctrl(c));
+            }
+        }
+        match lower.as_str() {
+            "esc" | "escape" => Ok(HostKey::Esc),
+            "tab" => Ok(HostKey::Tab),
+            "space" => Ok(HostKey::Char(' ')),
+            _ => match lower.strip_prefix('f').and_then(|n| n.parse().ok()) {
+                Some(n @ 1..=12) => Ok(HostKey::F(n)),
+                _ => Err(Chip8Error::ConfigError(format!(
+                    "don't know a key called {:?}",
+                    s
+                ))),
+            },
+        }
+    }
+}
+
+impl fmt::Display for HostKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HostKey::Char(' ') => write!(f, "space"),
+            HostKey::Char(c) => write!(f, "{}", c),
+            HostKey::Esc => write!(f, "esc"),
+            HostKey::Tab => write!(f, "tab"),
+            HostKey::F(n) => write!(f, "f{}", n),
+            HostKey::Ctrl(c) => write!(f, "ctrl+{}", c),
+        }
+    }
+}
+
+/// what they've always been
+const DEFAULT_HOTKEYS: [(HostKey, Action); 10] = [
+    (HostKey::Esc, Action::Menu),
+    (HostKey::Char('?'), Action::Help),
+    (HostKey::Tab, Action::Hud),
+    (HostKey::Char('-'), Action::Slower),
+    (HostKey::Char('='), Action::Faster),
+    (HostKey::Char('+'), Action::Faster),
+    (HostKey::Char('0'), Action::NormalSpeed),
+    (HostKey::Char('['), Action::Quieter),
+    (HostKey::Char(']'), Action::Louder),
+    (HostKey::Char('m'), Action::Mute),
+];
+
+/// the focus's, like a game's console key
+const FOCUS_KEY: HostKey = HostKey::Char('`');
+
+/// the slot picker's
+const SLOTS_KEY: HostKey = HostKey::F(2);
+
+/// the command palette's, as in most editors
+const PALETTE_KEY: HostKey = HostKey::Ctrl('p');
+
+/// shift and each slot's number, on a US keyboard
+const SLOT_SAVE_KEYS: [char; SLOT_COUNT as usize] =
+    [')', '!', '@', '#', '$', '%', '^', '&', '*', '('];
+
+/// which key does what
+#[derive(Debug, PartialEq, Clone)]
+pub struct Hotkeys {
+    bindings: Vec<(HostKey, Action)>,
+}
+
+impl Default for Hotkeys {
+    fn default() -> Self {
+        let mut bindings = DEFAULT_HOTKEYS.to_vec();
+        bindings.push((FOCUS_KEY, Action::Focus));
+        bindings.push((SLOTS_KEY, Action::Slots));
+        bindings.push((PALETTE_KEY, Action::Palette));
+        for (n, key) in SLOT_SAVE_KEYS.into_iter().enumerate() {
+            bindings.push((HostKey::Char(key), Action::SaveSlot(n as u8)));
+        }
+        // 0's normal speed
+        for n in 1..SLOT_COUNT {
+            let key = char::from_digit(n as u32, 10).unwrap_or('0');
+            bindings.push((HostKey::Char(key), Action::LoadSlot(n)));
+        }
+        Hotkeys { bindings }
+    }
+}
+
+impl Hotkeys {
+    /// the defaults, moved about by the config's [hotkeys] table of action
+    /// name -> keys, separated by spaces. keys given to an action are taken
+    /// off whatever had them before, but two actions can't both ask for one
+    pub fn from_config(overrides: &BTreeMap<String, String>) -> Result<Self, Chip8Error> {
+        let mut hotkeys = Hotkeys::default();
+        let mut wanted: Vec<(HostKey, Action)> = Vec::new();
+        for (name, keys) in overrides {
+            let action = Action::parse(name)?;
+            for key in keys.split_whitespace() {
+                let key = HostKey::parse(key)?;
+                if let Some((_, other)) = wanted.iter().find(|(k, _)| *k == key) {
+                    return Err(Chip8Error::ConfigError(format!(
+                        "{} is a hotkey for both {} and {}",
+                        key,
+                        other.name(),
+                        name
+                    )));
+                }
+                wanted.push((key, action));
+            }
+            hotkeys.bindings.retain(|(_, a)| *a != action);
+        }
+        hotkeys
+            .bindings
+            .retain(|(k, _)| !wanted.iter().any(|(w, _)| w == k));
+        hotkeys.bindings.extend(wanted);
+        Ok(hotkeys)
+    }
+
+    /// the hotkeys left once keymap's had first go at the keys, and the
+    /// ones it took. it's an error if that's all of the menu's
+    pub fn beside(&self, keymap: &Keymap) -> Result<(Hotkeys, Vec<(HostKey, Action)>), Chip8Error> {
+        let (shadowed, left): (Vec<_>, Vec<_>) = self
+            .bindings
+            .iter()
+            .partition(|(k, _)| matches!(k, HostKey::Char(c) if keymap.contains_key(c)));
+        let hotkeys = Hotkeys { bindings: left };
+        if hotkeys.keys(Action::Menu).is_empty() {
+            return Err(Chip8Error::ConfigError(
+                "there's no hotkey for the menu that isn't on the keypad".to_string(),
+            ));
+        }
+        Ok((hotkeys, shadowed))
+    }
+
+    /// what key does, if anything
+    pub fn action(&self, key: HostKey) -> Option<Action> {
+        self.bindings
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, a)| *a)
+    }
+
+    /// the keys that do action
+    pub fn keys(&self, action: Action) -> Vec<HostKey> {
+        self.bindings
+            .iter()
+            .filter(|(_, a)| *a == action)
+            .map(|(k, _)| *k)
+            .collect()
+    }
+
+    /// a line for each action with keys, for the help overlay. the slots'
+    /// keys are a row of ten, so they go after what they do
+    pub fn help(&self) -> Vec<String> {
+        let lines: Vec<(String, Action)> = Action::ALL
+            .into_iter()
+            .map(|a| {
+                let mut keys: Vec<(HostKey, Action)> = self
+                    .bindings
+                    .iter()
+                    .filter(|(_, b)| b.kind() == a)
+                    .copied()
+                    .collect();
+                keys.sort_by_key(|(_, b)| match b {
+                    Action::SaveSlot(n) | Action::LoadSlot(n) => *n,
+                    _ => 0,
+                });
+                let keys: Vec<String> = keys.iter().map(|(k, _)| k.to_string()).collect();
+                (keys.join(" "), a)
+            })
+            .filter(|(keys, _)| !keys.is_empty())
+            .collect();
+        let row = |a: Action| matches!(a, Action::SaveSlot(_) | Action::LoadSlot(_));
+        let width = lines
+            .iter()
+            .filter(|(_, a)| !row(*a))
+            .map(|(k, _)| k.len())
+            .max()
+            .unwrap_or(0);
+        lines
+            .into_iter()
+            .map(|(keys, a)| match row(a) {
+                true => format!("{}: {}", a.description(), keys),
+                false => format!("{:width$}  {}", keys, a.description()),
+            })
+            .collect()
+    }
+}
+
+/// parse an "action=keys" rebinding, e.g. "help=f1" or "faster== +",
+/// checking both halves make sense
+pub fn parse_binding(s: &str) -> Result<(String, String), Chip8Error> {
+    let (action, keys) = s
+        .split_once('=')
+        .ok_or_else(|| Chip8Error::ConfigError(format!("expected <action>=<keys>, got {:?}", s)))?;
+    Action::parse(action)?;
+    for key in keys.split_whitespace() {
+        HostKey::parse(key)?;
+    }
+    Ok((action.to_string(), keys.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_host_key() -> Result<(), Chip8Error> {
+        for name in ["m", "?", "esc", "tab", "space", "f1", "f12", "ctrl+p"] {
+            assert_eq!(HostKey::parse(name)?.to_string(), name);
+        }
+        assert_eq!(HostKey::parse("Escape")?, HostKey::Esc);
+        assert_eq!(HostKey::parse("F5")?, HostKey::F(5));
+        assert!(HostKey::parse("f13").is_err());
+        assert_eq!(HostKey::parse("Ctrl+K")?, HostKey::Ctrl('k'));
+        assert!(HostKey::parse("ctrl+1").is_err());
+        assert!(HostKey::parse("ctrl").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_defaults() {
+        let hotkeys = Hotkeys::default();
+        assert_eq!(hotkeys.action(HostKey::Esc), Some(Action::Menu));
+        assert_eq!(hotkeys.action(HostKey::Char('+')), Some(Action::Faster));
+        assert_eq!(hotkeys.action(HostKey::Char('x')), None);
+        assert_eq!(hotkeys.action(HostKey::Ctrl('p')), Some(Action::Palette));
+        assert_eq!(
+            hotkeys.keys(Action::Faster),
+            [HostKey::Char('='), HostKey::Char('+')]
+        );
+        let help = hotkeys.help();
+        assert_eq!(help.len(), Action::ALL.len());
+        // as wide as ctrl+p
+        assert_eq!(help[0], "esc     pause and open the menu");
+        assert_eq!(help[4], "= +     run faster");
+        assert_eq!(
+            help[9],
+            "`       keys to the emulator only, or back to the game"
+        );
+        assert_eq!(help[13], "ctrl+p  search every command");
+    }
+
+    #[test]
+    fn test_from_config() -> Result<(), Chip8Error> {
+        let config = BTreeMap::from([
+            ("help".to_string(), "f1 m".to_string()),
+            ("slower".to_string(), "".to_string()),
+        ]);
+        let hotkeys = Hotkeys::from_config(&config)?;
+        assert_eq!(hotkeys.action(HostKey::F(1)), Some(Action::Help));
+        assert_eq!(hotkeys.action(HostKey::Char('?')), None);
+        // m's been taken off mute, which has nothing left
+        assert_eq!(hotkeys.action(HostKey::Char('m')), Some(Action::Help));
+        assert!(hotkeys.keys(Action::Mute).is_empty());
+        assert_eq!(hotkeys.action(HostKey::Char('-')), None);
+        assert_eq!(hotkeys.help().len(), Action::ALL.len() - 2);
+
+        let clash = BTreeMap::from([
+            ("hud".to_string(), "h".to_string()),
+            ("help".to_string(), "h".to_string()),
+        ]);
+        assert!(Hotkeys::from_config(&clash).is_err());
+        let typo = BTreeMap::from([("fastr".to_string(), "f".to_string())]);
+        assert!(Hotkeys::from_config(&typo).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_beside() -> Result<(), Chip8Error> {
+        let keymap = Keymap::from([('m', 0x1), ('0', 0x0), ('q', 0x4)]);
+        let (hotkeys, shadowed) = Hotkeys::default().beside(&keymap)?;
+        assert_eq!(
+            shadowed,
+            [
+                (HostKey::Char('0'), Action::NormalSpeed),
+                (HostKey::Char('m'), Action::Mute)
+            ]
+        );
+        assert_eq!(hotkeys.action(HostKey::Char('m')), None);
+        assert_eq!(hotkeys.action(HostKey::Esc), Some(Action::Menu));
+        assert_eq!(hotkeys.help().len(), Action::ALL.len() - 2);
+
+        // nothing left to open the menu with
+        let config = BTreeMap::from([("menu".to_string(), "q".to_string())]);
+        assert!(Hotkeys::from_config(&config)?.beside(&keymap).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_slots() -> Result<(), Chip8Error> {
+        let hotkeys = Hotkeys::default();
+        assert_eq!(
+            hotkeys.action(HostKey::Char('#')),
+            Some(Action::SaveSlot(3))
+        );
+        assert_eq!(
+            hotkeys.action(HostKey::Char(')')),
+            Some(Action::SaveSlot(0))
+        );
+        assert_eq!(
+            hotkeys.action(HostKey::Char('3')),
+            Some(Action::LoadSlot(3))
+        );
+        assert_eq!(
+            hotkeys.action(HostKey::Char('0')),
+            Some(Action::NormalSpeed)
+        );
+        assert_eq!(hotkeys.action(HostKey::F(2)), Some(Action::Slots));
+        let help = hotkeys.help();
+        assert_eq!(help[10], "f2      show the savestate slots, to pick one");
+        assert_eq!(help[11], "save to a savestate slot: ) ! @ # $ % ^ & * (");
+        assert_eq!(help[12], "load from a savestate slot: 1 2 3 4 5 6 7 8 9");
+
+        // each slot's keys move on their own
+        assert_eq!(Action::parse("load-slot-0")?, Action::LoadSlot(0));
+        assert!(Action::parse("load-slot-10").is_err());
+        assert!(Action::parse("load-slot").is_err());
+        let config = BTreeMap::from([("load-slot-0".to_string(), "`".to_string())]);
+        let hotkeys = Hotkeys::from_config(&config)?;
+        assert_eq!(
+            hotkeys.action(HostKey::Char('`')),
+            Some(Action::LoadSlot(0))
+        );
+        assert_eq!(
+            hotkeys.action(HostKey::Char('1')),
+            Some(Action::LoadSlot(1))
+        );
+        // and focus, whose key that was, isn't in the help any more
+        assert_eq!(
+            hotkeys.help()[11],
+            "load from a savestate slot: ` 1 2 3 4 5 6 7 8 9"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_binding() -> Result<(), Chip8Error> {
+        assert_eq!(
+            parse_binding("faster== +")?,
+            ("faster".to_string(), "= +".to_string())
+        );
+        assert_eq!(parse_binding("mute=")?, ("mute".to_string(), String::new()));
+        assert!(parse_binding("help").is_err());
+        assert!(parse_binding("help=ctrl").is_err());
+        assert!(parse_binding("hepl=f1").is_err());
+        Ok(())
+    }
+}
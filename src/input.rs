@@ -1,7 +1,26 @@
-use crossterm::event::{poll, read, Event, KeyCode};
+use crate::error::Chip8Error;
+#[cfg(feature = "full")]
+use crate::hotkey::{Action, HostKey, Hotkeys};
+use crate::keypad::KeyTransition;
+#[cfg(feature = "full")]
+use crate::keypad::{Assist, KeyFilter, KeyRepeat};
+#[cfg(feature = "full")]
+use crate::lang;
+#[cfg(feature = "full")]
+use crate::touch;
+#[cfg(feature = "full")]
+use crossterm::event::{
+    poll, read, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyModifiers,
+    MouseEventKind,
+};
+#[cfg(feature = "full")]
+use crossterm::execute;
+#[cfg(feature = "full")]
 use crossterm::terminal;
+use std::cell::Cell;
 use std::collections::HashMap;
-use std::io;
+#[cfg(feature = "full")]
+use std::io::{self, Write};
 use std::time::Duration;
 
 /// map of async bytes read from the keyboard to what the chip8 might expect
@@ -46,90 +65,472 @@ const CHIP8_CONVENTIONAL_KEYMAP: [(char, u8); 16] = [
     ('v', 0x0f), // v
 ];
 
+/// host key -> COSMAC key
+pub type Keymap = HashMap<char, u8>;
+
+/// the default keymap, using the left-hand side of a qwerty keyboard
+pub fn conventional_keymap() -> Keymap {
+    HashMap::from(CHIP8_CONVENTIONAL_KEYMAP)
+}
+
+/// the player wants the emulator to run at a different speed
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SpeedRequest {
+    Slower,
+    Faster,
+    /// back to the VIP's own speed
+    Normal,
+    /// a key's gone down (true) or come up (false) with slow motion on
+    SlowMotion(bool),
+}
+
+/// the player wants the buzzer louder, quieter, or (un)muted
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum VolumeRequest {
+    Quieter,
+    Louder,
+    ToggleMute,
+}
+
+/// the player wants to save or load a savestate, or see what's in them
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SlotRequest {
+    Save(u8),
+    Load(u8),
+    /// show the slots, to pick one
+    Pick,
+}
+
+/// a key pressed while the keyboard's being typed on, e.g. into the
+/// command palette, rather than played with
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Typed {
+    Char(char),
+    Backspace,
+    Up,
+    Down,
+    Enter,
+    Escape,
+}
+
+/// something the program's done that the player might want to feel as
+/// well as see or hear, e.g. through a gamepad's rumble
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Feedback {
+    /// FX18 started a tone
+    Buzzer,
+    /// DXYN drew over something, and set VF
+    Collision,
+}
+
+/// who the keyboard's talking to: the program, through the keypad, or the
+/// emulator, so finding a way round the debugger or a menu doesn't press
+/// the program's keys as well
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum Focus {
+    #[default]
+    Game,
+    /// only the hotkeys, and the keypad's keys do nothing
+    Emulator,
+}
+
+impl Focus {
+    pub fn toggled(self) -> Self {
+        match self {
+            Focus::Game => Focus::Emulator,
+            Focus::Emulator => Focus::Game,
+        }
+    }
+}
+
 /// reads keypresses
 pub trait Input {
     /// forget the latched key
-    fn flush_keys(&mut self) -> Result<(), io::Error>;
+    fn flush_keys(&mut self) -> Result<(), Chip8Error>;
 
     /// read the latched key
-    fn read_key(&mut self) -> Result<Option<u8>, io::Error>;
+    fn read_key(&mut self) -> Result<Option<u8>, Chip8Error>;
 
     /// tell the input that a frame has passed
-    fn tick(&mut self) -> Result<(), io::Error>;
+    fn tick(&mut self) -> Result<(), Chip8Error>;
+
+    /// what the keypad did over the last frame, for anyone who wants more
+    /// than the key that's down now
+    fn transitions(&self) -> &[KeyTransition] {
+        &[]
+    }
+
+    /// will there never be another key? e.g. a script that's finished, or
+    /// nobody there to press anything
+    fn out_of_keys(&self) -> bool {
+        false
+    }
+
+    /// wait for up to timeout for the player to do something, without
+    /// taking it. false if there's no waiting on this input, and it's
+    /// returned straight away
+    fn wait_for_event(&mut self, _timeout: Duration) -> Result<bool, Chip8Error> {
+        Ok(false)
+    }
+
+    /// has the player asked for the emulator's menu since we last looked?
+    fn take_menu_request(&mut self) -> Result<bool, Chip8Error> {
+        Ok(false)
+    }
+
+    /// has the player asked to speed up or slow down since we last looked?
+    fn take_speed_request(&mut self) -> Result<Option<SpeedRequest>, Chip8Error> {
+        Ok(None)
+    }
+
+    /// has the player asked to show or hide the HUD since we last looked?
+    fn take_hud_toggle(&mut self) -> Result<bool, Chip8Error> {
+        Ok(false)
+    }
+
+    /// has the player asked to change the volume since we last looked?
+    fn take_volume_request(&mut self) -> Result<Option<VolumeRequest>, Chip8Error> {
+        Ok(None)
+    }
+
+    /// has the player asked to show or hide the hotkeys since we last looked?
+    fn take_help_toggle(&mut self) -> Result<bool, Chip8Error> {
+        Ok(false)
+    }
+
+    /// has the player asked for a savestate slot since we last looked?
+    fn take_slot_request(&mut self) -> Result<Option<SlotRequest>, Chip8Error> {
+        Ok(None)
+    }
+
+    /// has the player asked for the command palette since we last looked?
+    fn take_palette_request(&mut self) -> Result<bool, Chip8Error> {
+        Ok(false)
+    }
+
+    /// take the keys as typing from now on (true), for take_typed, rather
+    /// than as the keypad's or the hotkeys', or go back to playing (false)
+    fn set_typing(&mut self, _typing: bool) {}
+
+    /// what's been typed since we last looked, while typing
+    fn take_typed(&mut self) -> Result<Vec<Typed>, Chip8Error> {
+        Ok(Vec::new())
+    }
+
+    /// who the keys are going to
+    fn focus(&self) -> Focus {
+        Focus::Game
+    }
+
+    /// send the keys to the program or the emulator from now on, if this
+    /// input has any keys it could send to the emulator
+    fn set_focus(&mut self, _focus: Focus) {}
+
+    /// the program's done something the player could feel, for an input
+    /// that can shake (or buzz, or flash) to tell them
+    fn feedback(&mut self, _feedback: Feedback) {}
 }
 
+#[cfg(feature = "full")]
 /// simple implementation of Input, using STDIN
 pub struct StdinInput {
     keymap: HashMap<char, u8>,
-    latched_key: Option<u8>,
-    timer: usize,
+    hotkeys: Hotkeys,
+    keys: KeyFilter,
+    transitions: Vec<KeyTransition>,
+    menu_requested: bool,
+    speed_requested: Option<SpeedRequest>,
+    hud_toggled: bool,
+    volume_requested: Option<VolumeRequest>,
+    help_toggled: bool,
+    slot_requested: Option<SlotRequest>,
+    palette_requested: bool,
+    // keys go here instead, while something's being typed
+    typing: Option<Vec<Typed>>,
+    focus: Focus,
+    // clicks (or taps) on the keypad touch draws count as key presses
+    touch: bool,
 }
 
+#[cfg(feature = "full")]
 impl StdinInput {
-    pub fn new() -> Self {
-        terminal::enable_raw_mode().unwrap();
-        StdinInput {
-            keymap: HashMap::from(CHIP8_CONVENTIONAL_KEYMAP),
-            latched_key: None,
-            timer: STDIN_DEBOUNCE_FRAMES,
+    pub fn new() -> Result<Self, Chip8Error> {
+        Self::with_keymap(conventional_keymap())
+    }
+
+    pub fn with_keymap(keymap: Keymap) -> Result<Self, Chip8Error> {
+        Self::with_keys(keymap, Hotkeys::default())
+    }
+
+    /// the keypad on keymap, and the emulator's own keys on hotkeys
+    pub fn with_keys(keymap: Keymap, hotkeys: Hotkeys) -> Result<Self, Chip8Error> {
+        terminal::enable_raw_mode()?;
+        Ok(StdinInput {
+            keymap,
+            hotkeys,
+            keys: KeyFilter::new(KeyRepeat::TERMINAL),
+            transitions: Vec::new(),
+            menu_requested: false,
+            speed_requested: None,
+            hud_toggled: false,
+            volume_requested: None,
+            help_toggled: false,
+            slot_requested: None,
+            palette_requested: false,
+            typing: None,
+            focus: Focus::Game,
+            touch: false,
+        })
+    }
+
+    /// take clicks on the on-screen keypad as key presses, as well as keys
+    pub fn set_touch(&mut self, touch: bool) -> Result<(), Chip8Error> {
+        match touch {
+            true => execute!(io::stdout(), EnableMouseCapture)?,
+            false => execute!(io::stdout(), DisableMouseCapture)?,
         }
+        self.touch = touch;
+        Ok(())
     }
 
-    fn read_stdin(&mut self) -> Result<(), io::Error> {
+    /// how this terminal repeats keys, if not the usual way
+    pub fn set_key_repeat(&mut self, repeat: KeyRepeat) {
+        self.keys.set_repeat(repeat);
+    }
+
+    /// sticky keys, or slow motion, for players who need them
+    pub fn set_assist(&mut self, assist: Assist) {
+        self.keys.set_assist(assist);
+    }
+
+    pub fn keymap(&self) -> &Keymap {
+        &self.keymap
+    }
+
+    fn read_stdin(&mut self) -> Result<(), Chip8Error> {
+        // out of raw mode, someone else is reading stdin a line at a time
+        // (e.g. the menu's prompt), and what they're typing isn't for us
+        if !terminal::is_raw_mode_enabled()? {
+            return Ok(());
+        }
+        let game = self.focus == Focus::Game;
         while poll(Duration::from_millis(0))? {
-            match read()? {
+            let key = match read()? {
+                Event::Key(evt) if self.typing.is_some() => {
+                    if let (Some(typing), Some(typed)) = (&mut self.typing, typed(evt)) {
+                        typing.push(typed);
+                    }
+                    continue;
+                }
                 Event::Key(evt) => match evt.code {
-                    KeyCode::Char(key) => match self.keymap.get(&key) {
-                        Some(mapped_key) => self.latched_key = Some(*mapped_key),
-                        None => {
-                            eprintln!("Warning: can't map {:02x?} to a COSMAC key", key);
+                    // never the keypad's, however it's mapped
+                    KeyCode::Char(key) if evt.modifiers.contains(KeyModifiers::CONTROL) => {
+                        HostKey::Ctrl(key.to_ascii_lowercase())
+                    }
+                    // the keypad comes first, if it's listening
+                    KeyCode::Char(key) => match self.keymap.get(&key).filter(|_| game) {
+                        Some(mapped_key) => {
+                            self.keys.seen(*mapped_key);
+                            continue;
                         }
+                        None => HostKey::Char(key),
                     },
-                    KeyCode::Esc => panic!("TODO: proper emulator menus"),
+                    KeyCode::Esc => HostKey::Esc,
+                    KeyCode::Tab => HostKey::Tab,
+                    KeyCode::F(n) => HostKey::F(n),
                     _ => {
-                        eprintln!("Warning: unknown key event received");
+                        eprintln!("{}", lang::text("warning.unknown-key-event"));
+                        continue;
                     }
                 },
+                Event::Mouse(evt) => {
+                    if let (true, true, MouseEventKind::Down(_)) = (self.touch, game, evt.kind) {
+                        let (width, height) = terminal::size()?;
+                        let size = tui::layout::Rect::new(0, 0, width, height);
+                        if let Some(key) = touch::key_at(size, evt.column, evt.row) {
+                            self.keys.seen(key);
+                        }
+                    }
+                    continue;
+                }
                 _ => {
-                    eprintln!("Warning: unknown event received");
+                    eprintln!("{}", lang::text("warning.unknown-event"));
+                    continue;
                 }
+            };
+            match self.hotkeys.action(key) {
+                Some(action) => self.act(action),
+                // with the keypad not listening, there's nothing to warn about
+                None if !game => {}
+                None => eprintln!("{}", lang::format("warning.cant-map", &[&key])),
             }
         }
         Ok(())
     }
+
+    /// remember a hotkey for whoever takes it
+    fn act(&mut self, action: Action) {
+        match action {
+            Action::Menu => self.menu_requested = true,
+            Action::Help => self.help_toggled = !self.help_toggled,
+            Action::Hud => self.hud_toggled = !self.hud_toggled,
+            Action::Slower => self.speed_requested = Some(SpeedRequest::Slower),
+            Action::Faster => self.speed_requested = Some(SpeedRequest::Faster),
+            Action::NormalSpeed => self.speed_requested = Some(SpeedRequest::Normal),
+            Action::Quieter => self.volume_requested = Some(VolumeRequest::Quieter),
+            Action::Louder => self.volume_requested = Some(VolumeRequest::Louder),
+            Action::Mute => self.volume_requested = Some(VolumeRequest::ToggleMute),
+            Action::Slots => self.slot_requested = Some(SlotRequest::Pick),
+            Action::SaveSlot(n) => self.slot_requested = Some(SlotRequest::Save(n)),
+            Action::LoadSlot(n) => self.slot_requested = Some(SlotRequest::Load(n)),
+            Action::Focus => self.set_focus(self.focus.toggled()),
+            Action::Palette => self.palette_requested = true,
+        }
+    }
+}
+
+#[cfg(feature = "full")]
+/// what a key is, typed. ctrl and a letter isn't anything
+fn typed(key: KeyEvent) -> Option<Typed> {
+    match key.code {
+        KeyCode::Char(_) if key.modifiers.contains(KeyModifiers::CONTROL) => None,
+        KeyCode::Char(c) => Some(Typed::Char(c)),
+        KeyCode::Backspace => Some(Typed::Backspace),
+        KeyCode::Up => Some(Typed::Up),
+        KeyCode::Down => Some(Typed::Down),
+        KeyCode::Enter => Some(Typed::Enter),
+        KeyCode::Esc => Some(Typed::Escape),
+        _ => None,
+    }
+}
+
+#[cfg(feature = "full")]
+/// a line typed at the terminal, read a key at a time as the game's keys
+/// are rather than off stdin, for when something else has stdin (chat's
+/// bot, piping its votes in). None if the player gives up with ctrl-d
+pub fn read_line() -> Result<Option<String>, Chip8Error> {
+    let raw = terminal::is_raw_mode_enabled()?;
+    terminal::enable_raw_mode()?;
+    let mut stdout = io::stdout();
+    let mut line = String::new();
+    let read = loop {
+        let Event::Key(key) = read()? else {
+            continue;
+        };
+        match (key.code, typed(key)) {
+            (KeyCode::Char('d'), None) if line.is_empty() => break None,
+            (_, Some(Typed::Char(c))) => {
+                line.push(c);
+                write!(stdout, "{}", c)?;
+            }
+            (_, Some(Typed::Backspace)) if line.pop().is_some() => write!(stdout, "\u{8} \u{8}")?,
+            (_, Some(Typed::Enter)) => break Some(line),
+            _ => {}
+        }
+        stdout.flush()?;
+    };
+    write!(stdout, "\r\n")?;
+    if !raw {
+        terminal::disable_raw_mode()?;
+    }
+    Ok(read)
 }
 
+#[cfg(feature = "full")]
 impl Drop for StdinInput {
     fn drop(&mut self) {
-        terminal::disable_raw_mode().unwrap();
+        // nothing useful to do if this fails, and we mustn't panic in drop
+        if self.touch {
+            let _ = execute!(io::stdout(), DisableMouseCapture);
+        }
+        let _ = terminal::disable_raw_mode();
     }
 }
 
-/// how long to remember a keypress for
-const STDIN_DEBOUNCE_FRAMES: usize = 30; // 1/2 second
-
+#[cfg(feature = "full")]
 impl Input for StdinInput {
-    fn flush_keys(&mut self) -> Result<(), io::Error> {
-        self.latched_key = None;
+    fn flush_keys(&mut self) -> Result<(), Chip8Error> {
+        self.keys.flush();
         Ok(())
     }
 
-    fn read_key(&mut self) -> Result<Option<u8>, io::Error> {
-        if self.latched_key == None {
+    fn read_key(&mut self) -> Result<Option<u8>, Chip8Error> {
+        if self.keys.read().is_none() {
             self.read_stdin()?;
         }
-        Ok(self.latched_key)
+        Ok(self.keys.read())
     }
 
-    fn tick(&mut self) -> Result<(), io::Error> {
-        self.timer -= 1;
-        if self.timer == 0 {
-            self.flush_keys()?;
-            self.read_stdin()?;
-            self.timer = STDIN_DEBOUNCE_FRAMES;
-        }
+    fn tick(&mut self) -> Result<(), Chip8Error> {
+        // every frame, so the repeats keep a held key held
+        self.read_stdin()?;
+        self.transitions = self.keys.tick();
         Ok(())
     }
+
+    fn transitions(&self) -> &[KeyTransition] {
+        &self.transitions
+    }
+
+    fn wait_for_event(&mut self, timeout: Duration) -> Result<bool, Chip8Error> {
+        poll(timeout)?;
+        Ok(true)
+    }
+
+    fn take_menu_request(&mut self) -> Result<bool, Chip8Error> {
+        // NB. picked up whenever stdin gets read, which is at least every
+        //     frame
+        Ok(std::mem::take(&mut self.menu_requested))
+    }
+
+    fn take_speed_request(&mut self) -> Result<Option<SpeedRequest>, Chip8Error> {
+        Ok(self
+            .speed_requested
+            .take()
+            .or_else(|| self.keys.take_slow_motion().map(SpeedRequest::SlowMotion)))
+    }
+
+    fn take_hud_toggle(&mut self) -> Result<bool, Chip8Error> {
+        Ok(std::mem::take(&mut self.hud_toggled))
+    }
+
+    fn take_volume_request(&mut self) -> Result<Option<VolumeRequest>, Chip8Error> {
+        Ok(self.volume_requested.take())
+    }
+
+    fn take_help_toggle(&mut self) -> Result<bool, Chip8Error> {
+        Ok(std::mem::take(&mut self.help_toggled))
+    }
+
+    fn take_slot_request(&mut self) -> Result<Option<SlotRequest>, Chip8Error> {
+        Ok(self.slot_requested.take())
+    }
+
+    fn take_palette_request(&mut self) -> Result<bool, Chip8Error> {
+        Ok(std::mem::take(&mut self.palette_requested))
+    }
+
+    fn set_typing(&mut self, typing: bool) {
+        // as with the focus, nothing pressed before carries on being held
+        self.keys.clear();
+        self.typing = typing.then(Vec::new);
+    }
+
+    fn take_typed(&mut self) -> Result<Vec<Typed>, Chip8Error> {
+        self.read_stdin()?;
+        Ok(self.typing.as_mut().map(std::mem::take).unwrap_or_default())
+    }
+
+    fn focus(&self) -> Focus {
+        self.focus
+    }
+
+    fn set_focus(&mut self, focus: Focus) {
+        // a key pressed for the program mustn't come out after it's stopped
+        // listening
+        self.keys.clear();
+        self.focus = focus;
+    }
 }
 
 /// dummy Input implementation for testing
@@ -146,16 +547,38 @@ impl DummyInput {
 }
 
 impl Input for DummyInput {
-    fn flush_keys(&mut self) -> Result<(), io::Error> {
+    fn flush_keys(&mut self) -> Result<(), Chip8Error> {
         self.bytes.clear();
         Ok(())
     }
 
-    fn read_key(&mut self) -> Result<Option<u8>, io::Error> {
+    fn read_key(&mut self) -> Result<Option<u8>, Chip8Error> {
         Ok(self.bytes.pop())
     }
 
-    fn tick(&mut self) -> Result<(), io::Error> {
+    fn tick(&mut self) -> Result<(), Chip8Error> {
+        Ok(())
+    }
+
+    fn out_of_keys(&self) -> bool {
+        self.bytes.is_empty()
+    }
+}
+
+/// a key held down by someone else (e.g. a test) until they let go, however
+/// often the program flushes the keypad
+pub struct HeldKey<'a>(pub &'a Cell<Option<u8>>);
+
+impl<'a> Input for HeldKey<'a> {
+    fn flush_keys(&mut self) -> Result<(), Chip8Error> {
+        Ok(())
+    }
+
+    fn read_key(&mut self) -> Result<Option<u8>, Chip8Error> {
+        Ok(self.0.get())
+    }
+
+    fn tick(&mut self) -> Result<(), Chip8Error> {
         Ok(())
     }
 }
@@ -2,6 +2,7 @@
 ///
 /// (from: https://laurencescotford.com/chip-8-on-the-cosmac-vip-initialisation/)
 /// RCA1802 has 16 16bit registers, each of which can be a program counter:
+/// ```text
 ///  0. DMA pointer for screen refresh           -- ignore
 ///  1. interrupt program counter                -- ignore
 ///  2. stack pointer                            -- 0x6cf on 2k machine; 0xcf in penultimate page of RAM
@@ -20,1001 +21,3903 @@
 ///  P (4bit register) for determining which of R0-F is the current PC
 ///  X (4bit register) for "           "     "  R0-F is a pointer to a RAM address
 /// ... yes P and X can be set to the same register. yes we can ignore them.
+/// ```
+use crate::boot::{self, Boot};
+use crate::cancel::CancelToken;
+use crate::cdp1802::{Cdp1802, ClockRate, NoIo};
+use crate::clock::{Clock, SpinClock};
+use crate::costs::{CostModel, VipCosts};
+use crate::error::Chip8Error;
+use crate::extension::OpcodeExtension;
+use crate::font::{FontLocator, VipFont};
+use crate::heat::HeatMap;
+use crate::input::Feedback;
+use crate::interrupt::{self, Interrupt, InterruptQueue, InterruptSource, RefreshRate};
+use crate::quirks::{OutOfRange, Quirks};
+use crate::timer::Timers;
+use crate::timing::Timing;
+use crate::trace::{TraceEntry, Tracer};
+use crate::watch::{MemoryPatch, MemoryWatch};
 use crate::{display, input, memory, memory::MemoryMap, sound};
-use rand::Rng;
-use spin_sleep;
-use std::{error::Error, io, time};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+#[cfg(feature = "full")]
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::ops::Range;
+use std::{io, time};
 
 const CHIP8_TARGET_FREQ_NS: u64 = 1_000_000_000 / 60; // 60 fps
-const CHIP8_CYCLE_NS: u64 = 4540; // 4.54 us
+/// the VIP's machine cycle: 8 clocks at 1.76064 MHz, about 4.54us
+const CHIP8_CYCLE_NS: u64 = ClockRate::VIP.cycle_ns();
+/// machine cycles in each emulated frame, at the VIP's 60Hz
+pub const CHIP8_FRAME_CYCLES: u64 = CHIP8_TARGET_FREQ_NS / CHIP8_CYCLE_NS;
+/// the 1861 puts out a line every 14 machine cycles; the 128 lines of the
+/// picture start 2 lines after its interrupt, and DMA 8 bytes each
+const VIP_LINE_CYCLES: u64 = 14;
+const VIP_DISPLAY_START_CYCLES: u64 = 2 * VIP_LINE_CYCLES;
+const VIP_DISPLAY_LINES: u64 = 128;
+/// the speeds the player can pick from, as multiples of the VIP's
+pub const CHIP8_SPEEDS: [f64; 6] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0];
+/// how much slower slow motion runs than the speed picked
+const SLOW_MOTION_SPEED: f64 = 0.5;
+/// how long main_loop runs instructions for before looking at the clock
+/// and sleeping, in ns (at whatever speed). looking once an instruction
+/// costs more than most of them take to run
+const CLOCK_BATCH_NS: f64 = 500_000.0;
+/// the longest main_loop waits on the input at a time while idle, before
+/// catching up on the frames that went by
+const IDLE_WAIT: time::Duration = time::Duration::from_millis(250);
+/// how many frames' worth of checkpoints to keep: this one's start, and the
+/// one before's, which is the one that's any use after a fault
+const CHECKPOINT_FRAMES: usize = 2;
+/// how long machine code gets to hand back to the interpreter: a second
+const CDP1802_MAX_CYCLES: usize = 60 * CHIP8_FRAME_CYCLES as usize;
+/// how many frames a step waits for an instruction to finish: no
+/// instruction takes longer than one, so one that hasn't finished in two
+/// is waiting on something that isn't coming (e.g. a host's vsync)
+const STEP_MAX_FRAMES: u64 = 2;
+
+/// why main_loop stopped
+#[derive(Debug, PartialEq)]
+pub enum RunOutcome {
+    /// ran all the frames it was asked to
+    Finished,
+    /// the player wants the emulator's menu
+    MenuRequested,
+    /// the player wants to save or load a savestate
+    SlotRequested(input::SlotRequest),
+    /// the player wants the command palette
+    PaletteRequested,
+    /// the program asked to stop (SCHIP's 00FD)
+    Exited,
+}
 
-pub struct Chip8Interpreter<'a> {
+/// what the program's up to, as far as anything outside can tell after a
+/// step or a frame: for a GUI to show it's waiting for a key, or a harness
+/// to know when to stop
+#[derive(Debug, Clone, PartialEq)]
+pub enum InterpreterState {
+    /// getting on with it
+    Running,
+    /// FX0A, waiting for a key to be pressed and let go
+    WaitingForKey,
+    /// DXYN, waiting for the display interrupt to draw in
+    WaitingForVblank,
+    /// not going anywhere, ever: stopped by SCHIP's 00FD, or jumping to
+    /// itself (which is how most ROMs finish)
+    Halted,
+    /// an instruction went wrong, e.g. an illegal one, and running it
+    /// again will too
+    Faulted { error: String },
+}
+
+/// how much main_loop says about falling behind the VIP, from nothing at
+/// all up. whatever it says, overruns() counts every time
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// nothing, not even the count at the end
+    Silent,
+    /// nothing as it happens, only how often at the end
+    Quiet,
+    /// a warning the first time it falls behind in a frame, which is enough
+    /// to see it's happening without a flood of them on a slow host
+    Normal,
+    /// a warning for every instruction or interrupt that took too long
+    Verbose,
+}
+
+impl Verbosity {
+    /// the levels, as --verbosity numbers them
+    pub const ALL: [Verbosity; 4] = [
+        Verbosity::Silent,
+        Verbosity::Quiet,
+        Verbosity::Normal,
+        Verbosity::Verbose,
+    ];
+
+    /// a level by number, 0 (silent) to 3 (verbose)
+    pub fn parse(s: &str) -> Result<Self, Chip8Error> {
+        s.parse::<usize>()
+            .ok()
+            .and_then(|n| Self::ALL.get(n).copied())
+            .ok_or_else(|| {
+                Chip8Error::ConfigError(format!(
+                    "no verbosity \"{}\" (try 0 for silent up to {} for verbose)",
+                    s,
+                    Self::ALL.len() - 1
+                ))
+            })
+    }
+
+    /// one quieter, as -q makes it
+    pub fn quieter(self) -> Self {
+        Self::ALL[(self as usize).saturating_sub(1)]
+    }
+
+    /// one louder, as -v makes it
+    pub fn louder(self) -> Self {
+        Self::ALL[(self as usize + 1).min(Self::ALL.len() - 1)]
+    }
+}
+
+/// instructions main_loop's run since it last looked at the clock: when it
+/// did, and how many cycles they came to
+struct Batch {
+    start: time::Duration,
+    cycles: usize,
+}
+
+/// how many times main_loop has fallen behind the VIP
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Overruns {
+    pub instructions: u64,
+    pub interrupts: u64,
+}
+
+/// a decoded instruction handler, returning the machine cycles it consumed
+type Instruction<'a> = fn(&mut Chip8Interpreter<'a>) -> Result<usize, Chip8Error>;
+
+/// everything about a running machine apart from its devices: enough to
+/// carry on exactly where it left off, e.g. from a save state
+#[derive(Clone)]
+#[cfg_attr(feature = "full", derive(Serialize, Deserialize))]
+pub struct MachineState {
     memory: memory::Chip8MemoryMap,
-    display: &'a mut dyn display::Display,
-    input: &'a mut dyn input::Input,
-    sound: &'a mut dyn sound::Sound,
     stack_pointer: u16,
-    // contains the decoded instruction and the original four bytes
-    // TODO use an enum or struct instead of Option?
-    instruction: Option<fn(&mut Chip8Interpreter<'a>) -> Result<usize, io::Error>>,
+    // the original two bytes of the instruction being run
     instruction_data: u16,
+    // DXYN has done its first half and is waiting for the interrupt
+    second_half: bool,
+    // let interrupts land part way through long instructions
+    split_instructions: bool,
+    // how far through a split instruction we are
+    part: usize,
+    // DXYN's collisions so far, when it's drawn in parts
+    collided: bool,
+    // show sprites drawn mid-frame tearing, and where the frame's got to
+    shear: bool,
+    scanning: bool,
+    scan: Vec<u8>,
+    scanned_rows: usize,
+    frame_start: u64,
     program_counter: u16,
     vx: u16,
     vy: u16,
-    tone_timer: u8,
-    general_timer: u8,
+    timers: Timers,
     random: u16,
     i: u16,
     display_pointer: u16,
-    state: InterpreterState,
+    state: CycleState,
+    interrupts: InterruptQueue,
+    // machine cycles elapsed since we started
+    cycles: u64,
+    // display refreshes since we started
+    frames: u64,
+    // how often they happen
+    #[cfg_attr(feature = "full", serde(default))]
+    refresh_rate: RefreshRate,
+    // how fast the 1802's clock runs, and so how long a machine cycle is
+    #[cfg_attr(feature = "full", serde(default))]
+    clock_rate: ClockRate,
+    // what the interpreter's own work costs
+    #[cfg_attr(feature = "full", serde(default))]
+    timing: Timing,
+    quirks: Quirks,
+    // SCHIP's 128x64 mode
+    hires: bool,
+}
+
+impl MachineState {
+    /// how often the machine interrupts, e.g. to record a saved one at
+    /// the right speed
+    pub fn refresh_rate(&self) -> RefreshRate {
+        self.refresh_rate
+    }
+
+    /// what's in its memory, without an interpreter to ask
+    pub(crate) fn memory(&self) -> &memory::Chip8MemoryMap {
+        &self.memory
+    }
+
+    /// could the interpreter carry on from here? a state from a file, or
+    /// an older build, may have anything in it
+    fn check(&self) -> Result<(), Chip8Error> {
+        let bad = |why: &str| {
+            Chip8Error::ConfigError(format!("can't carry on from that machine state: {}", why))
+        };
+        self.memory.check()?;
+        let size = self.memory.size();
+        let mid_instruction = matches!(self.state, CycleState::Execute | CycleState::WaitInterrupt);
+        if self.program_counter as usize + 2 > size || (mid_instruction && self.program_counter < 2)
+        {
+            return Err(bad("the program counter's off the end of memory"));
+        }
+        if self.stack_pointer > self.memory.stack_addr {
+            return Err(bad("the stack pointer's above the stack"));
+        }
+        if self.vx > 0xf || self.vy > 0xf {
+            return Err(bad("an instruction's register isn't one of V0 to VF"));
+        }
+        if self.display_pointer as usize + 0x100 > size {
+            return Err(bad("the display's off the end of memory"));
+        }
+        self.clock_rate.check()
+    }
+}
+
+pub struct Chip8Interpreter<'a> {
+    machine: MachineState,
+    display: &'a mut dyn display::Display,
+    input: &'a mut dyn input::Input,
+    sound: &'a mut dyn sound::Sound,
+    // the decoded instruction; the rest of it is in machine
+    instruction: Option<Instruction<'a>>,
+    // things keeping an eye on memory between frames
+    watches: Vec<&'a mut dyn MemoryWatch>,
+    // things changing memory between instructions
+    patches: Vec<&'a mut dyn MemoryPatch>,
+    // handlers for instructions we don't know
+    extensions: Vec<&'a mut dyn OpcodeExtension>,
+    // things hearing about every instruction
+    tracers: Vec<&'a mut dyn Tracer>,
+    // where FX29 and FX30 find glyphs
+    font: &'a dyn FontLocator,
+    // what each instruction's charged
+    costs: &'a dyn CostModel,
+    // what main_loop tells the time and sleeps with; a SpinClock if none
+    clock: Option<&'a dyn Clock>,
+    // how much faster than the VIP main_loop runs
+    speed: f64,
+    // slower than that, while a key's down, for players who need it
+    slow_motion: bool,
+    // what the display was last given, to work out what's changed since
+    last_frame: Option<Vec<u8>>,
+    // show the display what the machine's up to
+    hud: bool,
+    // how often each pixel's been drawn on lately, while there's a heat map
+    heat: Option<HeatMap>,
+    // whether the buzzer's sounded in each of the last 32 frames, for the HUD
+    beeps: u32,
+    // whether the sound's been told to beep, and not yet to stop
+    buzzing: bool,
+    // where this frame's cycles have gone so far, and the last few frames'
+    spent: display::FrameCycles,
+    scope: [display::FrameCycles; display::HUD_SCOPE_FRAMES],
+    // the hotkeys, and whether they're being shown over the picture
+    help: Vec<String>,
+    showing_help: bool,
+    // how loud the sound device should be
+    volume: sound::Volume,
+    // who the keys were going to, as far as the display knows
+    focus: input::Focus,
+    verbosity: Verbosity,
+    // the last frame main_loop warned about falling behind in
+    warned: Option<u64>,
+    overruns: Overruns,
+    // what went wrong with the last instruction, if it did
+    fault: Option<String>,
+    // how long FX0A can wait with no keys to come, and how long it has
+    key_watchdog: Option<u64>,
+    waited_frames: u64,
+    // whether main_loop waits on the input while idle, and how much time
+    // it's waited that the frames haven't caught up on yet
+    idle_wait: bool,
+    idle_debt: time::Duration,
+    // the machine as it was at the start of the last few frames, oldest
+    // first, if they're being kept
+    checkpoints: Option<VecDeque<MachineState>>,
+    cancel: Option<CancelToken>,
 }
 
 impl<'a> Chip8Interpreter<'a> {
     pub fn new(
-        display: &'a mut impl display::Display,
-        input: &'a mut impl input::Input,
-        sound: &'a mut impl sound::Sound,
-    ) -> Result<Chip8Interpreter<'a>, io::Error> {
-        let m = memory::Chip8MemoryMap::new()?;
-        let mut i = Chip8Interpreter {
-            memory: m,
+        display: &'a mut dyn display::Display,
+        input: &'a mut dyn input::Input,
+        sound: &'a mut dyn sound::Sound,
+    ) -> Result<Chip8Interpreter<'a>, Chip8Error> {
+        let memory = memory::Chip8MemoryMap::new()?;
+        let mut interrupts = InterruptQueue::new();
+        interrupts.add_source(&interrupt::DisplayRefresh::default(), 0, CHIP8_CYCLE_NS);
+        Ok(Self::with_machine(
+            display,
+            input,
+            sound,
+            MachineState {
+                stack_pointer: memory.stack_addr,
+                instruction_data: 0x0000,
+                second_half: false,
+                split_instructions: false,
+                part: 0,
+                collided: false,
+                shear: false,
+                scanning: false,
+                scan: Vec::new(),
+                scanned_rows: 0,
+                frame_start: 0,
+                program_counter: memory.program_addr,
+                vx: 0x0000,
+                vy: 0x0000,
+                timers: Timers::new(),
+                random: rand::thread_rng().gen::<u16>(),
+                i: 0x0000,
+                display_pointer: memory.display_addr,
+                state: CycleState::FetchDecode,
+                interrupts,
+                cycles: 0,
+                frames: 0,
+                refresh_rate: RefreshRate::default(),
+                clock_rate: ClockRate::default(),
+                timing: Timing::default(),
+                quirks: Quirks::default(),
+                hires: false,
+                memory,
+            },
+        ))
+    }
+
+    /// an interpreter for the devices that's to carry on from machine, as
+    /// new then restore would, but without making a whole machine only to
+    /// throw it away. it's to be set up as it was when machine was saved
+    /// (extensions, font, quirks) and then told to resume
+    pub(crate) fn with_machine(
+        display: &'a mut dyn display::Display,
+        input: &'a mut dyn input::Input,
+        sound: &'a mut dyn sound::Sound,
+        machine: MachineState,
+    ) -> Self {
+        Chip8Interpreter {
+            machine,
             display,
             input,
             sound,
-            stack_pointer: 0x0000,
             instruction: None,
-            instruction_data: 0x0000,
-            program_counter: 0x0000,
-            vx: 0x0000,
-            vy: 0x0000,
-            tone_timer: 0x00,
-            general_timer: 0x00,
-            random: rand::thread_rng().gen::<u16>(),
-            i: 0x0000,
-            display_pointer: 0x0000,
-            state: InterpreterState::FetchDecode,
-        };
-        i.stack_pointer = i.memory.stack_addr;
-        i.program_counter = i.memory.program_addr;
-        i.display_pointer = i.memory.display_addr;
-        Ok(i)
+            watches: Vec::new(),
+            patches: Vec::new(),
+            extensions: Vec::new(),
+            tracers: Vec::new(),
+            font: &VipFont,
+            costs: &VipCosts,
+            clock: None,
+            speed: 1.0,
+            slow_motion: false,
+            last_frame: None,
+            hud: false,
+            heat: None,
+            beeps: 0,
+            buzzing: false,
+            spent: display::FrameCycles::default(),
+            scope: [display::FrameCycles::default(); display::HUD_SCOPE_FRAMES],
+            help: Vec::new(),
+            showing_help: false,
+            volume: sound::Volume::default(),
+            focus: input::Focus::Game,
+            verbosity: Verbosity::Normal,
+            warned: None,
+            overruns: Overruns::default(),
+            fault: None,
+            key_watchdog: None,
+            waited_frames: 0,
+            idle_wait: false,
+            idle_debt: time::Duration::ZERO,
+            checkpoints: None,
+            cancel: None,
+        }
     }
 
-    /// load a chip8 program
-    pub fn load_program(&mut self, reader: &mut impl io::Read) -> Result<(), io::Error> {
-        self.memory.load_program(reader)
+    /// everything about the machine, e.g. to save it for later
+    pub fn machine_state(&self) -> &MachineState {
+        &self.machine
     }
 
-    /// external interrupt
-    fn interrupt(&mut self) -> Result<usize, Box<dyn Error>> {
-        // duration
-        // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-interrupts/
-        let mut dur = 807 + 1024;
+    /// carry on from a state saved earlier. the devices, extensions and font
+    /// stay as they are, so should be the ones the state was saved with.
+    /// one that doesn't make sense is an error, and the machine's left as
+    /// it was
+    pub fn restore(&mut self, state: MachineState) -> Result<(), Chip8Error> {
+        state.check()?;
+        let instruction = self.instruction_in(&state)?;
+        self.machine = state;
+        self.resumed(instruction)
+    }
 
-        // increment random seed
-        self.random = self.random.wrapping_add(1);
+    /// carry on from the machine it was made with (see with_machine)
+    pub(crate) fn resume(&mut self) -> Result<(), Chip8Error> {
+        self.machine.check()?;
+        let instruction = self.instruction_in(&self.machine)?;
+        self.resumed(instruction)
+    }
 
-        // update general timer
-        if self.general_timer > 0 {
-            self.general_timer -= 1;
-            dur += 8;
-        }
+    /// the machine, once there's no more running it to do
+    pub(crate) fn into_machine_state(self) -> MachineState {
+        self.machine
+    }
 
-        // update tone timer
-        match self.tone_timer {
-            0 => {}
-            1 => {
-                self.tone_timer = 0;
-                self.sound.stop()?;
-                dur += 4;
-            }
-            _ => {
-                self.tone_timer -= 1;
-                dur += 4;
+    /// what state was in the middle of running, if anything
+    fn instruction_in(&self, state: &MachineState) -> Result<Option<Instruction<'a>>, Chip8Error> {
+        Ok(match state.state {
+            _ if state.second_half => Some(Chip8Interpreter::inst_draw_sprite_pt2 as Instruction),
+            CycleState::Execute | CycleState::WaitInterrupt => {
+                Some(self.decode(state.program_counter - 2, state.instruction_data)?)
             }
+            _ => None,
+        })
+    }
+
+    /// carry on from a new machine, in the middle of instruction
+    fn resumed(&mut self, instruction: Option<Instruction<'a>>) -> Result<(), Chip8Error> {
+        self.instruction = instruction;
+        self.fault = None;
+        if let Some(c) = &mut self.checkpoints {
+            c.clear();
         }
+        let (_, width, height) = self.display_geometry();
+        self.last_frame = None;
+        self.sound.set_refresh_rate(self.machine.refresh_rate);
+        self.display.set_mode(width, height)
+    }
 
-        // tell the input routines that another frame has passed
-        self.input.tick()?;
+    pub fn quirks(&self) -> Quirks {
+        self.machine.quirks
+    }
 
-        // TODO soft-code size
-        self.display
-            .draw(self.memory.get_ro_slice(self.display_pointer, 0x100))?;
+    /// behave like a later interpreter, for ROMs that expect it
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.machine.quirks = quirks;
+    }
 
-        // if we'd been waiting for an interrupt, put the interpreter back into
-        // the Execute state, because it will have been mid-instruction
-        if self.state == InterpreterState::WaitInterrupt {
-            self.state = InterpreterState::Execute;
-        }
-        Ok(dur)
+    /// use another variant's fonts for FX29 and FX30
+    pub fn set_font(&mut self, font: &'a dyn FontLocator) -> Result<(), Chip8Error> {
+        font.install(&mut self.machine.memory)?;
+        self.font = font;
+        Ok(())
     }
 
-    /// step the interpreter forward one state, returning number of machine
-    /// cycles consumed.
-    fn cycle(&mut self) -> Result<usize, io::Error> {
-        match self.state {
-            InterpreterState::FetchDecode => self.fetch_and_decode(),
-            InterpreterState::Execute => self.call(),
-            InterpreterState::WaitInterrupt => Ok(1),
-        }
+    /// charge instructions as costs says rather than as the VIP's
+    /// interpreter took, e.g. another variant's table, or a flat cost
+    pub fn set_cost_model(&mut self, costs: &'a dyn CostModel) {
+        self.costs = costs;
+    }
+
+    /// keep main_loop's time with clock instead of spinning, e.g. where the
+    /// host can't block or a test doesn't want to wait
+    pub fn set_clock(&mut self, clock: &'a dyn Clock) {
+        self.clock = Some(clock);
     }
 
-    /// run the main interpreter loop, including timing and interrupts
-    pub fn main_loop(&mut self, frame_count: usize) -> Result<(), Box<dyn Error>> {
-        let sleep = spin_sleep::SpinSleeper::new(CHIP8_CYCLE_NS as u32);
+    /// how much faster than the VIP main_loop runs
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
 
-        let mut remaining_sleep = time::Duration::from_nanos(0);
+    /// run main_loop speed times faster than the VIP (or slower, below 1).
+    /// only the sleeping changes, so the program can't tell. a speed from a
+    /// saved session or a pasted state could be anything, so it's kept
+    /// between the slowest and fastest of CHIP8_SPEEDS, and one that isn't
+    /// a speed at all (nothing, less, or not a number) is the VIP's
+    pub fn set_speed(&mut self, speed: f64) {
+        let (slowest, fastest) = (CHIP8_SPEEDS[0], CHIP8_SPEEDS[CHIP8_SPEEDS.len() - 1]);
+        self.speed = match speed > 0.0 {
+            true => speed.clamp(slowest, fastest),
+            false => 1.0,
+        };
+        self.display.set_speed(self.pace());
+    }
 
-        // loop of frames
-        for frame in 0..frame_count {
-            // |c......................................................|
-            //  ^-now                                                  ^-frame end
-            let mut now = time::Instant::now();
-            let frame_end = now + time::Duration::from_nanos(CHIP8_TARGET_FREQ_NS);
+    pub fn hud(&self) -> bool {
+        self.hud
+    }
 
-            // interrupt at the top of the loop, so that the time spent in the
-            // isr is inside the frame (rather than frame.time->isr.time->frame.time->etc.)
-            let t = self.interrupt()?;
+    /// show the timers, random seed, last instruction and where the cycles
+    /// have been going beside the picture
+    pub fn set_hud(&mut self, hud: bool) {
+        self.hud = hud;
+        if !hud {
+            self.display.set_hud(None);
+        }
+    }
 
-            // how long we should sleep for, for the interrupt
-            let inst_end =
-                now + time::Duration::from_nanos(CHIP8_CYCLE_NS * t as u64) + remaining_sleep;
-            now = time::Instant::now();
-            // |..c.....|..............................................|
-            //    ^-now ^-inst_end                                     ^-frame end
+    pub fn heat_map(&self) -> bool {
+        self.heat.is_some()
+    }
 
-            if inst_end >= now {
-                sleep.sleep(inst_end - now);
-            } else {
-                eprintln!(
-                    "{:09?}: Warning: ISR took longer than COSMAC by {:?}",
-                    frame,
-                    now - inst_end
-                );
-            }
-            // |........|c.............................................|
-            //    ^-now ^-inst_end                                     ^-frame end
-
-            // loop of instructions within each frame
-            loop {
-                now = time::Instant::now();
-                let t = self.cycle()?;
-                // |........|..c...........................................|
-                //           ^-now                                         ^-frame end
-
-                // how long we should sleep until
-                let inst_end = now + time::Duration::from_nanos(CHIP8_CYCLE_NS * t as u64);
-                now = time::Instant::now();
-                // |........|..c.....|.....................................|
-                //             ^-now ^-inst_end                            ^-frame end
-
-                // if we would sleep past the end of the frame, store the
-                // remainder and interrupt
-                if inst_end >= frame_end {
-                    remaining_sleep = inst_end - frame_end;
-                    // we can legitimately overrun the end of the frame during the instruction
-                    if frame_end >= now {
-                        sleep.sleep(frame_end - now);
-                    }
-                    break;
-                } else {
-                    if inst_end >= now {
-                        sleep.sleep(inst_end - now);
-                    } else {
-                        eprintln!(
-                            "{:09?}: Warning: {:04x?} took longer than COSMAC by {:?}",
-                            frame,
-                            self.instruction_data,
-                            now - inst_end
-                        );
-                    }
-                }
+    /// colour the picture by how often each pixel's been drawn on lately,
+    /// rather than the theme, to see where the drawing's going on
+    pub fn set_heat_map(&mut self, on: bool) {
+        match (on, self.heat.is_some()) {
+            (true, false) => self.heat = Some(HeatMap::new()),
+            (false, true) => {
+                self.heat = None;
+                self.display.set_heat(None);
             }
+            _ => {}
         }
-        Ok(())
     }
 
-    /// fetch the instruction at the program counter, figure out what it is,
-    /// set vx/vy, update the program counter, update the interpreter state
-    fn fetch_and_decode(&mut self) -> Result<usize, io::Error> {
-        let inst = self.memory.get_word(self.program_counter);
+    /// bits were XORed into the index'th byte of the screen, for the heat
+    /// map, if there is one
+    pub(crate) fn heat_xored(&mut self, index: usize, bits: u8) {
+        let (_, width, height) = self.display_geometry();
+        if let Some(h) = &mut self.heat {
+            h.xored(width, height, index, bits);
+        }
+    }
 
-        // first byte, second nybble
-        self.vx = (inst & 0x0f00) >> 8;
-        // second byte, first nybble
-        self.vy = (inst & 0x00f0) >> 4;
+    /// a sprite's been drawn over something, which the player might want
+    /// to feel: DXYN, and any extension's sprites
+    pub(crate) fn collided(&mut self) {
+        self.input.feedback(Feedback::Collision);
+    }
 
-        self.instruction = Some(match inst {
-            0x00e0 => Chip8Interpreter::inst_clear_screen,
-            0x00ee => Chip8Interpreter::inst_ret,
-            0x1000..=0x1fff => Chip8Interpreter::inst_branch,
-            0x2000..=0x2fff => Chip8Interpreter::inst_subroutine,
-            0x3000..=0x3fff => Chip8Interpreter::inst_skip_vx_eq,
-            0x4000..=0x4fff => Chip8Interpreter::inst_skip_vx_ne,
-            0x5000..=0x5fff => Chip8Interpreter::inst_x_eq_y,
-            0x6000..=0x6fff => Chip8Interpreter::inst_load_vx,
-            0x7000..=0x7fff => Chip8Interpreter::inst_add_to_vx,
-            0x8000..=0x8fff => match inst & 0xf {
-                0x0 => Chip8Interpreter::inst_load_x_with_y,
-                0x1 => Chip8Interpreter::inst_x_or_with_y,
-                0x2 => Chip8Interpreter::inst_x_and_with_y,
-                0x3 => Chip8Interpreter::inst_x_xor_with_y,
-                0x4 => Chip8Interpreter::inst_x_add_y,
-                0x5 => Chip8Interpreter::inst_x_minus_y,
-                0x6 => Chip8Interpreter::inst_rshift_y_load_x,
-                0x7 => Chip8Interpreter::inst_y_minus_x,
-                0xe => Chip8Interpreter::inst_lshift_y_load_x,
-                _ => panic!("Failed to decode instruction {:04x?}", inst),
-            },
-            0x9000..=0x9fff => Chip8Interpreter::inst_x_ne_y,
-            0xa000..=0xafff => Chip8Interpreter::inst_set_i,
-            0xb000..=0xbfff => Chip8Interpreter::inst_jump_with_offset,
-            0xc000..=0xcfff => Chip8Interpreter::inst_random,
-            0xd000..=0xdfff => Chip8Interpreter::inst_draw_sprite,
-            0xe000..=0xefff => match inst & 0xff {
-                0x9e => Chip8Interpreter::inst_skip_key_eq,
-                0xa1 => Chip8Interpreter::inst_skip_key_ne,
-                _ => panic!("Failed to decode instruction {:04x?}", inst),
-            },
-            0xf000..=0xffff => match inst & 0xff {
-                0x07 => Chip8Interpreter::inst_get_timer,
-                0x0a => Chip8Interpreter::inst_wait_key,
-                0x15 => Chip8Interpreter::inst_set_timer,
-                0x18 => Chip8Interpreter::inst_set_sound,
-                0x1e => Chip8Interpreter::inst_add_x_to_i,
-                0x29 => Chip8Interpreter::inst_load_char,
-                0x33 => Chip8Interpreter::inst_x_to_bcd,
-                0x55 => Chip8Interpreter::inst_save_v_at_i,
-                0x65 => Chip8Interpreter::inst_load_v_at_i,
-                _ => panic!("Failed to decode instruction {:04x?}", inst),
-            },
-            _ => panic!("Failed to decode instruction {:04x?}", inst),
-        });
+    /// what the help hotkey shows: a line for each of the other hotkeys
+    pub fn set_help(&mut self, help: Vec<String>) {
+        self.help = help;
+    }
 
-        self.instruction_data = inst;
+    pub fn showing_help(&self) -> bool {
+        self.showing_help
+    }
 
-        self.program_counter += 2;
-        self.state = InterpreterState::Execute;
+    /// show the help over the picture, or take it away
+    pub fn show_help(&mut self, show: bool) {
+        self.showing_help = show;
+        self.display.set_help(show.then(|| self.help.clone()));
+    }
 
-        // execution time is 40 cycles for 0xxx and 68 cycles otherwise
-        if inst > 0x0fff {
-            Ok(68)
-        } else {
-            Ok(40)
-        }
+    pub fn volume(&self) -> sound::Volume {
+        self.volume
     }
 
-    /// call the most recently-decoded instruction
-    fn call(&mut self) -> Result<usize, io::Error> {
-        // NB. ordering is important here because instructions can (and need
-        //     to) modify the interpreter state
-        self.state = InterpreterState::FetchDecode;
-        match self.instruction {
-            Some(i) => i(self),
-            None => panic!("Null pointer exception?!"),
+    /// make the sound device louder or quieter, and show how loud it is
+    pub fn set_volume(&mut self, volume: sound::Volume) -> Result<(), Chip8Error> {
+        let was = self.volume.audible();
+        self.volume = volume;
+        self.display.set_volume(volume);
+        self.sound.set_volume(volume)?;
+        // a tone that's going comes and goes with being heard at all
+        match (self.buzzing, was, volume.audible()) {
+            (true, 0, 1..) => self.sound.beep(),
+            (true, 1.., 0) => self.sound.stop(),
+            _ => Ok(()),
         }
     }
 
-    /// 00e0
-    fn inst_clear_screen(&mut self) -> Result<usize, io::Error> {
-        // TODO: soft-code
-        self.memory
-            .write(&[0; 0x0100], self.display_pointer, 0x0100)?;
-        Ok(24)
+    /// quieter, louder or (un)muted, as the volume hotkeys do
+    pub fn change_volume(&mut self, request: input::VolumeRequest) -> Result<(), Chip8Error> {
+        let volume = match request {
+            input::VolumeRequest::Quieter => self.volume.quieter(),
+            input::VolumeRequest::Louder => self.volume.louder(),
+            input::VolumeRequest::ToggleMute => sound::Volume {
+                muted: !self.volume.muted,
+                ..self.volume
+            },
+        };
+        self.set_volume(volume)
     }
 
-    /// 00ee
-    fn inst_ret(&mut self) -> Result<usize, io::Error> {
-        self.stack_pointer += 2;
-        self.program_counter = self.memory.get_word(self.stack_pointer);
-        Ok(10)
+    /// play one of the emulator's own sounds, e.g. when a menu opens
+    pub fn cue(&mut self, cue: sound::UiCue) -> Result<(), Chip8Error> {
+        self.sound.cue(cue)
     }
 
-    /// 1nnn
-    fn inst_branch(&mut self) -> Result<usize, io::Error> {
-        self.program_counter = self.instruction_data & 0xfff;
-        Ok(12)
+    /// say when main_loop falls behind the VIP, or just count it
+    pub fn set_verbosity(&mut self, verbosity: Verbosity) {
+        self.verbosity = verbosity;
     }
 
-    /// 2nnn
-    fn inst_subroutine(&mut self) -> Result<usize, io::Error> {
-        self.memory.write(
-            &[
-                (self.program_counter >> 8) as u8,
-                (self.program_counter & 0xff) as u8,
-            ],
-            self.stack_pointer,
-            2,
-        )?;
-        self.stack_pointer -= 2;
-        self.program_counter = self.instruction_data & 0xfff;
-        Ok(26)
+    pub fn verbosity(&self) -> Verbosity {
+        self.verbosity
     }
 
-    /// 3xnn
-    fn inst_skip_vx_eq(&mut self) -> Result<usize, io::Error> {
-        let lhs = self.memory.get_ro_slice(self.memory.var_addr + self.vx, 1)[0];
-        let rhs = 0xff & self.instruction_data as u8;
-        if lhs == rhs {
-            self.program_counter += 2;
-            Ok(14)
-        } else {
-            Ok(10)
+    /// is falling behind just now worth a warning? at Normal only the first
+    /// time in a frame is
+    fn warn_overrun(&mut self) -> bool {
+        let frame = self.machine.frames;
+        let warn = match self.verbosity {
+            Verbosity::Silent | Verbosity::Quiet => false,
+            Verbosity::Normal => self.warned != Some(frame),
+            Verbosity::Verbose => true,
+        };
+        if warn {
+            self.warned = Some(frame);
         }
+        warn
     }
 
-    /// 4xnn
-    fn inst_skip_vx_ne(&mut self) -> Result<usize, io::Error> {
-        let lhs = self.memory.get_ro_slice(self.memory.var_addr + self.vx, 1)[0];
-        let rhs = 0xff & self.instruction_data as u8;
-        if lhs != rhs {
-            self.program_counter += 2;
-            Ok(14)
-        } else {
-            Ok(10)
-        }
+    /// how often main_loop's fallen behind so far, however quiet it's been
+    pub fn overruns(&self) -> Overruns {
+        self.overruns
     }
 
-    /// 5xy0
-    fn inst_x_eq_y(&mut self) -> Result<usize, io::Error> {
-        let lhs = self.memory.get_ro_slice(self.memory.var_addr + self.vx, 1)[0];
-        let rhs = self.memory.get_ro_slice(self.memory.var_addr + self.vy, 1)[0];
-        if lhs == rhs {
-            self.program_counter += 2;
-            Ok(18)
-        } else {
-            Ok(14)
+    /// the next of CHIP8_SPEEDS along from where we are, as asked, or slow
+    /// motion on or off
+    pub fn change_speed(&mut self, request: input::SpeedRequest) {
+        let current = CHIP8_SPEEDS.iter().position(|s| *s >= self.speed);
+        let speed = match (request, current) {
+            // the speed the player picked stays picked
+            (input::SpeedRequest::SlowMotion(slow_motion), _) => {
+                self.slow_motion = slow_motion;
+                self.speed
+            }
+            (input::SpeedRequest::Normal, _) => 1.0,
+            (input::SpeedRequest::Slower, Some(n)) => CHIP8_SPEEDS[n.saturating_sub(1)],
+            (input::SpeedRequest::Faster, Some(n)) => {
+                CHIP8_SPEEDS[(n + 1).min(CHIP8_SPEEDS.len() - 1)]
+            }
+            (_, None) => CHIP8_SPEEDS[CHIP8_SPEEDS.len() - 1],
+        };
+        self.set_speed(speed);
+    }
+
+    /// how much faster than the VIP main_loop's running right now, which is
+    /// slower than asked for while slow motion's on
+    fn pace(&self) -> f64 {
+        match self.slow_motion {
+            true => self.speed * SLOW_MOTION_SPEED,
+            false => self.speed,
         }
     }
 
-    /// 6xnn
-    fn inst_load_vx(&mut self) -> Result<usize, io::Error> {
-        self.memory.write(
-            &[(self.instruction_data & 0xff) as u8],
-            self.memory.var_addr + self.vx,
-            1,
-        )?;
-        Ok(6)
+    /// let interrupts land part way through long instructions (DXYN, FX55
+    /// and FX65) as they can on the VIP, rather than always between them. a
+    /// sprite whose first half crosses the interrupt then waits a frame
+    /// longer, say
+    pub fn set_split_instructions(&mut self, split: bool) {
+        self.machine.split_instructions = split;
     }
 
-    /// 7xnn
-    fn inst_add_to_vx(&mut self) -> Result<usize, io::Error> {
-        let v = self.memory.get_rw_slice(self.memory.var_addr + self.vx, 1);
-        v[0] = (((v[0] as u16) + (self.instruction_data & 0xff)) & 0xff) as u8;
-        Ok(10)
+    /// show how sprites would tear if they were drawn while the 1861 was
+    /// putting the frame out. the VIP's own interpreter never lets that
+    /// happen: its ISR holds the CPU for the whole picture, and DXYN waits
+    /// for the interrupt so it draws in the gap after. with shear on, the
+    /// ISR hands back once its bookkeeping's done, the picture goes out a
+    /// row at a time (stealing cycles for the DMA) while the program runs,
+    /// and DXYN draws straight away, so a sprite drawn across the row being
+    /// put out shows its top half a frame late
+    pub fn set_shear(&mut self, shear: bool) {
+        self.machine.shear = shear;
     }
 
-    /// 8xy0
-    fn inst_load_x_with_y(&mut self) -> Result<usize, io::Error> {
-        let vy = self.memory.get_ro_slice(self.memory.var_addr + self.vy, 1)[0];
-        self.memory
-            .write(&[vy], self.memory.var_addr + self.vx, 1)?;
-        Ok(12)
+    pub fn refresh_rate(&self) -> RefreshRate {
+        self.machine.refresh_rate
     }
 
-    /// 8xy1
-    fn inst_x_or_with_y(&mut self) -> Result<usize, io::Error> {
-        let vy = self.memory.get_ro_slice(self.memory.var_addr + self.vy, 1)[0];
-        let vx = self.memory.get_rw_slice(self.memory.var_addr + self.vx, 1);
-        vx[0] |= vy;
-        Ok(44)
+    /// interrupt this often instead, e.g. 50Hz for a ROM written for a PAL
+    /// machine. the next interrupt's already scheduled, so it's the one
+    /// after that that's sooner or later
+    pub fn set_refresh_rate(&mut self, rate: RefreshRate) {
+        self.machine.refresh_rate = rate;
+        // the host's vsync starts the frames, if there's no source
+        if !self.machine.interrupts.contains(Interrupt::DisplayRefresh) {
+            self.sound.set_refresh_rate(rate);
+            return;
+        }
+        let next = self
+            .machine
+            .interrupts
+            .next_due()
+            .unwrap_or(self.machine.cycles);
+        self.machine
+            .interrupts
+            .unregister(Interrupt::DisplayRefresh);
+        self.machine
+            .interrupts
+            .register(Interrupt::DisplayRefresh, next, self.frame_cycles());
+        self.sound.set_refresh_rate(rate);
     }
 
-    /// 8xy2
-    fn inst_x_and_with_y(&mut self) -> Result<usize, io::Error> {
-        let vy = self.memory.get_ro_slice(self.memory.var_addr + self.vy, 1)[0];
-        let vx = self.memory.get_rw_slice(self.memory.var_addr + self.vx, 1);
-        vx[0] &= vy;
-        Ok(44)
+    pub fn clock_rate(&self) -> ClockRate {
+        self.machine.clock_rate
     }
 
-    /// 8xy3
-    fn inst_x_xor_with_y(&mut self) -> Result<usize, io::Error> {
-        let vy = self.memory.get_ro_slice(self.memory.var_addr + self.vy, 1)[0];
-        let vx = self.memory.get_rw_slice(self.memory.var_addr + self.vx, 1);
-        vx[0] ^= vy;
-        Ok(44)
+    /// run the 1802 at another clock rate, e.g. an overclocked VIP's. the
+    /// refresh rate stays as it is, so it's how many machine cycles there
+    /// are each frame that changes. the next interrupt's already scheduled,
+    /// so it's the one after that that's sooner or later, and any other
+    /// interrupt sources keep the periods they were added with
+    pub fn set_clock_rate(&mut self, rate: ClockRate) {
+        self.machine.clock_rate = rate;
+        // the host's vsync starts the frames, if there's no source
+        if !self.machine.interrupts.contains(Interrupt::DisplayRefresh) {
+            return;
+        }
+        let next = self
+            .machine
+            .interrupts
+            .next_due()
+            .unwrap_or(self.machine.cycles);
+        self.machine
+            .interrupts
+            .unregister(Interrupt::DisplayRefresh);
+        self.machine
+            .interrupts
+            .register(Interrupt::DisplayRefresh, next, self.frame_cycles());
     }
 
-    /// 8xy4
-    fn inst_x_add_y(&mut self) -> Result<usize, io::Error> {
-        let vy = self.memory.get_ro_slice(self.memory.var_addr + self.vy, 1)[0] as u16;
-        let vx = self.memory.get_rw_slice(self.memory.var_addr + self.vx, 1);
-        let res: u16 = vx[0] as u16 + vy;
-        vx[0] = 0xff & res as u8;
-        self.memory.write(
-            &[if res > 0xff { 0x01 } else { 0x00 }],
-            self.memory.var_addr + 0xf,
-            1,
-        )?;
-        Ok(44)
+    /// how long a machine cycle takes, in nanoseconds, at the clock rate
+    fn cycle_ns(&self) -> u64 {
+        self.machine.clock_rate.cycle_ns()
     }
 
-    /// 8xy5
-    fn inst_x_minus_y(&mut self) -> Result<usize, io::Error> {
-        let vy = self.memory.get_ro_slice(self.memory.var_addr + self.vy, 1)[0] as u16;
-        let vx = self.memory.get_rw_slice(self.memory.var_addr + self.vx, 1);
-        let res: u16 = 0x100 + (vx[0] as u16) - vy;
-        vx[0] = 0xff & res as u8;
-        self.memory.write(
-            &[if res < 0x100 { 0x00 } else { 0x01 }],
-            self.memory.var_addr + 0xf,
-            1,
-        )?;
-        Ok(44)
+    pub fn timing(&self) -> Timing {
+        self.machine.timing
     }
 
-    /// 8xy6
-    fn inst_rshift_y_load_x(&mut self) -> Result<usize, io::Error> {
-        // TODO variations
-        // (see discussion here: https://laurencescotford.com/chip-8-on-the-cosmac-vip-arithmetic-and-logic-instructions/)
-        let vy = self.memory.get_ro_slice(self.memory.var_addr + self.vy, 1)[0];
-        let res = vy >> 1;
-        self.memory
-            .write(&[res], self.memory.var_addr + self.vx, 1)?;
-        self.memory
-            .write(&[res], self.memory.var_addr + self.vy, 1)?;
-        self.memory
-            .write(&[vy & 0x1], self.memory.var_addr + 0xf, 1)?; // vf
-        Ok(44)
+    /// charge the interpreter's own work at these figures instead, e.g. to
+    /// see how a ROM fares with another interpreter's interrupt routine
+    pub fn set_timing(&mut self, timing: Timing) {
+        self.machine.timing = timing;
     }
 
-    /// 8xy7
-    fn inst_y_minus_x(&mut self) -> Result<usize, io::Error> {
-        let vy = self.memory.get_ro_slice(self.memory.var_addr + self.vy, 1)[0] as u16;
-        let vx = self.memory.get_rw_slice(self.memory.var_addr + self.vx, 1);
-        let res: u16 = 0x100 + vy - (vx[0] as u16);
-        vx[0] = 0xff & res as u8;
-        self.memory.write(
-            &[if res < 0x100 { 0x00 } else { 0x01 }],
-            self.memory.var_addr + 0xf,
-            1,
-        )?;
-        Ok(44)
+    /// give up on FX0A, with a KeyDeadlock, once it's waited more than
+    /// frames frames with the input out of keys, so a batch of headless
+    /// runs doesn't get stuck on one waiting for a key nobody will press
+    pub fn set_key_watchdog(&mut self, frames: Option<u64>) {
+        self.key_watchdog = frames;
+        self.waited_frames = 0;
     }
 
-    /// 8xye
-    fn inst_lshift_y_load_x(&mut self) -> Result<usize, io::Error> {
-        // TODO variations
-        // (see discussion here: https://laurencescotford.com/chip-8-on-the-cosmac-vip-arithmetic-and-logic-instructions/)
-        let vy = self.memory.get_ro_slice(self.memory.var_addr + self.vy, 1)[0];
-        let res: u8 = (vy << 1) & 0xff;
-        self.memory
-            .write(&[res], self.memory.var_addr + self.vx, 1)?;
-        self.memory
-            .write(&[res], self.memory.var_addr + self.vy, 1)?;
-        self.memory
-            .write(&[(vy & 0x80) >> 7], self.memory.var_addr + 0xf, 1)?; // vf
-        Ok(44)
+    /// keep the machine as it was at the start of each of the last couple of
+    /// frames, so a fault can be gone back to and stepped into
+    pub fn set_checkpoints(&mut self, checkpoints: bool) {
+        self.checkpoints = checkpoints.then(VecDeque::new);
     }
 
-    /// 9xy0
-    fn inst_x_ne_y(&mut self) -> Result<usize, io::Error> {
-        let lhs = self.memory.get_ro_slice(self.memory.var_addr + self.vx, 1)[0];
-        let rhs = self.memory.get_ro_slice(self.memory.var_addr + self.vy, 1)[0];
-        if lhs != rhs {
-            self.program_counter += 2;
-            Ok(18)
-        } else {
-            Ok(14)
-        }
+    /// the machine from the start of the frame before this one, if
+    /// checkpoints are being kept and it's been going that long: after a
+    /// fault, somewhere to go back to that's a frame or more before it
+    pub fn checkpoint(&self) -> Option<&MachineState> {
+        self.checkpoints
+            .as_ref()
+            .filter(|c| c.len() == CHECKPOINT_FRAMES)
+            .and_then(|c| c.front())
     }
 
-    /// annn
-    fn inst_set_i(&mut self) -> Result<usize, io::Error> {
-        self.i = self.instruction_data & 0xfff;
-        Ok(12)
+    /// while FX0A's waiting with both timers stopped, have main_loop wait on
+    /// the input for a key (or a hotkey) for up to IDLE_WAIT at a time,
+    /// rather than keeping time a frame at a time, then run the frames that
+    /// went by without sleeping. nothing the program can see changes from
+    /// one of those frames to the next, and the host gets some rest on
+    /// menu screens
+    pub fn set_idle_wait(&mut self, idle_wait: bool) {
+        self.idle_wait = idle_wait;
+        self.idle_debt = time::Duration::ZERO;
     }
 
-    /// bnnn
-    fn inst_jump_with_offset(&mut self) -> Result<usize, io::Error> {
-        // TODO CHIP-48 and SUPERCHIP variants
-        let offset = self.memory.get_ro_slice(self.memory.var_addr, 1)[0] as u16; // add self.vx for variations
-        self.program_counter = (self.instruction_data & 0xfff) + offset;
-        if self.instruction_data & 0xf00 != self.program_counter & 0xf00 {
-            // crosses a page boundary
-            Ok(24)
-        } else {
-            Ok(22)
+    /// stop with a Cancelled error before the next cycle once token's
+    /// cancelled, however many frames or cycles are left to run. it's
+    /// looked at between instructions and while waiting, so a frame, or a
+    /// run with nothing to stop it, stops promptly
+    pub fn set_cancel_token(&mut self, token: CancelToken) {
+        self.cancel = Some(token);
+    }
+
+    fn check_cancelled(&self) -> Result<(), Chip8Error> {
+        match &self.cancel {
+            Some(token) if token.is_cancelled() => Err(Chip8Error::Cancelled),
+            _ => Ok(()),
         }
     }
 
-    /// cxnn
-    fn inst_random(&mut self) -> Result<usize, io::Error> {
-        // increment seed
-        self.random = self.random.wrapping_add(1);
+    /// machine cycles in each emulated frame, at the refresh rate
+    pub fn frame_cycles(&self) -> u64 {
+        self.machine.refresh_rate.frame_cycles(self.cycle_ns())
+    }
 
-        // address for random number
-        let rand_addr = 0x100 + (0xff & self.random);
+    /// the seed for the random number generator; the VIP's comes from
+    /// wherever R9 happened to be at power-on
+    pub fn seed(&self) -> u16 {
+        self.machine.random
+    }
 
-        // fetch byte at rand address
-        let rand_val = self.memory.get_ro_slice(rand_addr, 1)[0];
+    /// reseed the random number generator, e.g. to play back a replay
+    pub fn set_seed(&mut self, seed: u16) {
+        self.machine.random = seed;
+    }
 
-        // add to high-order byte of seed
-        let rand_val = ((self.random >> 8) as u8).wrapping_add(rand_val);
+    /// come up as boot says, before the program's loaded. a cold boot
+    /// leaves RAM, the V registers and I with whatever they came up with
+    /// (going by the seed, so a replay's the same), besides the display,
+    /// which the VIP's interpreter clears, and has the program run for a
+    /// while before its first interrupt, as it would after the VIP's
+    /// booted. a fast one leaves everything as it is
+    pub fn power_on(&mut self, boot: Boot) -> Result<(), Chip8Error> {
+        if boot == Boot::Fast {
+            return Ok(());
+        }
+        let mut rng = StdRng::seed_from_u64(self.machine.random as u64);
+        let memory = &mut self.machine.memory;
+        let (start, display) = (memory.program_addr, memory.display_addr);
+        rng.fill(memory.get_rw_slice(start, (display - start) as usize)?);
+        self.machine.i = rng.gen::<u16>() & 0xfff;
+        let first = self.machine.cycles + boot::VIP_BOOT_INTERRUPT_DELAY;
+        self.machine
+            .interrupts
+            .unregister(Interrupt::DisplayRefresh);
+        self.machine
+            .interrupts
+            .register(Interrupt::DisplayRefresh, first, self.frame_cycles());
+        Ok(())
+    }
 
-        // div by 2 and add to itself
-        let rand_val = (rand_val / 2).wrapping_add(rand_val);
-
-        // save in top byte of seed
-        self.random = (self.random & 0xff) + ((rand_val as u16) << 8);
-
-        // mask with nn and store in vx
-        self.memory.write(
-            &[rand_val & (self.instruction_data & 0xff) as u8],
-            self.memory.var_addr + self.vx,
-            1,
-        )?;
+    /// the value of register V`reg`
+    pub fn v(&self, reg: u8) -> u8 {
+        self.machine
+            .memory
+            .get_ro_slice(self.machine.memory.var_addr, 16)
+            .map_or(0, |v| v[reg as usize & 0xf])
+    }
 
-        Ok(36)
+    pub fn set_v(&mut self, reg: u8, value: u8) {
+        if let Ok(v) = self
+            .machine
+            .memory
+            .get_rw_slice(self.machine.memory.var_addr, 16)
+        {
+            v[reg as usize & 0xf] = value;
+        }
     }
 
-    /// dxyn
-    fn inst_draw_sprite(&mut self) -> Result<usize, io::Error> {
-        //
-        //  x_bit_offset
-        // -->|                       (work ram contents)
-        //    .xxxxx...  |            ....xxxx x.......
-        //    x.....x..  |            ...x.... .x......
-        //    x.x.x.x..  | rows  ==>  ...x.x.x .x......
-        //    x.....x..  v            ...x.... .x......
-        //    .x.x.x...  -            ....x.x. x.......
-        //
-        // bit offset from byte margin
-        let x_bit_offset = self.memory.get_ro_slice(self.memory.var_addr + self.vx, 1)[0] & 0x7;
+    pub fn i(&self) -> u16 {
+        self.machine.i
+    }
 
-        // number of rows in the sprite
-        let rows = self.instruction_data & 0xf;
+    pub fn set_i(&mut self, i: u16) {
+        self.machine.i = i;
+    }
 
-        // data to draw (copied to a vec to avoid shenanigans with borrowing)
-        let sprite = self.memory.get_ro_slice(self.i, rows as usize).to_vec();
+    /// len bytes from offset past I, found as the quirks say if they go
+    /// off the end of memory: for instructions (extensions' too) that read
+    /// from I
+    pub fn read_at_i(&mut self, offset: usize, len: usize) -> Result<Vec<u8>, Chip8Error> {
+        let addrs = self.addrs_at_i(offset, len)?;
+        let memory = &self.machine.memory;
+        addrs
+            .into_iter()
+            .map(|addr| Ok(memory.get_ro_slice(addr, 1)?[0]))
+            .collect()
+    }
 
-        // writable work area
-        let work = self.memory.get_rw_slice(self.memory.work_addr, 32);
+    /// write data from offset past I, as read_at_i reads it: none of it, if
+    /// any would land on write-protected memory
+    pub fn write_at_i(&mut self, offset: usize, data: &[u8]) -> Result<(), Chip8Error> {
+        let addrs = self.addrs_at_i(offset, data.len())?;
+        let memory = &mut self.machine.memory;
+        if let Some(addr) = addrs.iter().find(|a| memory.is_protected(**a)) {
+            return Err(Chip8Error::WriteProtected { addr: *addr });
+        }
+        for (addr, byte) in addrs.into_iter().zip(data) {
+            memory.store(*byte, addr)?;
+        }
+        Ok(())
+    }
 
-        // write a correctly left-shifted version of the sprite into the work area
-        for (idx, byte) in sprite.iter().enumerate() {
-            work[idx * 2] = byte >> x_bit_offset;
-            work[idx * 2 + 1] = if x_bit_offset == 0 {
-                0x0
-            } else {
-                byte << (8 - x_bit_offset)
-            };
+    /// reason, for the instruction running, which wanted count bytes from
+    /// I, with what it was doing
+    fn access_fault(&self, count: usize, reason: Chip8Error) -> Chip8Error {
+        Chip8Error::AccessFault {
+            inst: self.machine.instruction_data,
+            pc: self.machine.program_counter.wrapping_sub(2),
+            i: self.machine.i,
+            count,
+            reason: Box::new(reason),
         }
+    }
 
-        // wait for the next display interrupt
-        self.state = InterpreterState::WaitInterrupt;
-        self.instruction = Some(Chip8Interpreter::inst_draw_sprite_pt2);
+    /// where each of len bytes from offset past I is. past the end of
+    /// memory, that's a fault, or wrapped round, or memory grows to reach
+    fn addrs_at_i(&mut self, offset: usize, len: usize) -> Result<Vec<u16>, Chip8Error> {
+        let policy = self.machine.quirks.out_of_range;
+        // I itself wraps round, if it's been put off the end some other way
+        if policy == OutOfRange::Wrap {
+            self.move_i(self.machine.i as usize);
+        }
+        let start = self.machine.i as usize + offset;
+        if start + len > self.machine.memory.size() {
+            match policy {
+                OutOfRange::Fault => return Err(Chip8Error::MemoryFault { addr: start, len }),
+                OutOfRange::Grow => self.machine.memory.grow(),
+                OutOfRange::Wrap => {}
+            }
+        }
+        let size = self.machine.memory.size();
+        let mask = match policy {
+            OutOfRange::Grow => 0xffff,
+            _ => 0xfff,
+        };
+        Ok((start..start + len)
+            .map(|addr| match addr < size {
+                true => addr as u16,
+                false => (addr & mask) as u16,
+            })
+            .collect())
+    }
 
-        // duration is [ROUGHLY!]
-        //     25 for preamble
-        //   + 10 * (rows * x_bit_offset) for instructions for offsetting
-        //   + 7 * (rows) for each row
-        //   + 1 for the interrupt wait instruction
-        Ok((26 + 10 * rows * (x_bit_offset as u16) + 7 * rows) as usize)
+    /// point I at i, which might be off the end of memory: the quirks say
+    /// whether it wraps round, or memory grows to meet it. faulting waits
+    /// until something's read or written there
+    fn move_i(&mut self, i: usize) {
+        if i >= self.machine.memory.size() {
+            match self.machine.quirks.out_of_range {
+                OutOfRange::Fault => {}
+                OutOfRange::Wrap => {
+                    self.machine.i = (i & 0xfff) as u16;
+                    return;
+                }
+                OutOfRange::Grow => self.machine.memory.grow(),
+            }
+        }
+        self.machine.i = i as u16;
     }
 
-    /// dxyn (after the interrupt)
-    fn inst_draw_sprite_pt2(&mut self) -> Result<usize, io::Error> {
-        let mut dur = 12;
+    /// the address of the next instruction
+    pub fn pc(&self) -> u16 {
+        self.machine.program_counter
+    }
 
-        // display x and y coords (in bits) (again)
-        // TODO these are hard-wired to CHIP-8 display dimensions
-        let vx_val = 0x3f & self.memory.get_ro_slice(self.memory.var_addr + self.vx, 1)[0] as usize;
-        let vy_val = 0x1f & self.memory.get_ro_slice(self.memory.var_addr + self.vy, 1)[0] as usize;
+    pub fn set_pc(&mut self, pc: u16) {
+        self.machine.program_counter = pc;
+    }
 
-        // number of rows in the sprite
-        let rows = 0xf & self.instruction_data as usize;
+    /// return addresses, outermost call first. a stack pointer above the
+    /// top of the stack (which a program can't do, but a savestate or a
+    /// debugger can) is a memory fault, not a panic
+    pub fn stack(&self) -> Result<Vec<u16>, Chip8Error> {
+        // the stack grows downward from stack_addr
+        let (top, sp) = (self.machine.memory.stack_addr, self.machine.stack_pointer);
+        let fault = || Chip8Error::MemoryFault {
+            addr: sp.into(),
+            len: 2,
+        };
+        let depth = top.checked_sub(sp).ok_or_else(fault)? as usize / 2;
+        (0..depth)
+            .map(|n| {
+                let addr = top.checked_sub(2 * n as u16).ok_or_else(fault)?;
+                let w = self.machine.memory.get_ro_slice(addr, 2)?;
+                Ok(((w[0] as u16) << 8) + w[1] as u16)
+            })
+            .collect()
+    }
 
-        // address to start drawing sprite in memory
-        let draw_addr = vx_val / 8 // x byte offset
-                      + vy_val * 8; // y byte offset
+    pub fn delay_timer(&self) -> u8 {
+        self.machine.timers.general
+    }
 
-        // readable work area
-        let work = self
-            .memory
-            .get_ro_slice(self.memory.work_addr, rows * 2)
-            .to_vec();
+    pub fn sound_timer(&self) -> u8 {
+        self.machine.timers.tone
+    }
 
-        // writable vram
-        // TODO soft-code size
-        let vram = self.memory.get_rw_slice(self.memory.display_addr, 0x100);
+    /// frames displayed since we started
+    pub fn frames(&self) -> u64 {
+        self.machine.frames
+    }
 
-        // collision flag (gets written to VF when done)
-        let mut collision_flag: u8 = 0;
+    pub fn memory(&self) -> &memory::Chip8MemoryMap {
+        &self.machine.memory
+    }
 
-        // iterate thru pairs of bytes, looking for collisions and whether (for
-        // the right-hand byte) they can be displayed or not.
-        for (idx, byte) in work.iter().enumerate() {
-            // TODO [again] this 8-byte stride is hard-coded to the width of the screen
-            let this_addr = draw_addr + (idx / 2) * 0x8 + idx % 2;
-            if this_addr >= vram.len() {
-                // drawing off the bottom of the screen
-                continue;
-            }
-            if idx % 2 == 1 && (this_addr & 0x7) == 0 {
-                // TODO and this
-                // right-hand byte hangs off the edge of the screen
-                continue;
-            }
-            if (vram[this_addr] & *byte) != 0x0 {
-                collision_flag = 1;
-                dur += 2;
-            }
-            vram[this_addr] ^= byte;
-            dur += if idx % 2 == 0 { 17 } else { 8 }
-        }
+    /// e.g. for poking from the emulator's menu
+    pub fn memory_mut(&mut self) -> &mut memory::Chip8MemoryMap {
+        &mut self.machine.memory
+    }
 
-        // save the collision flag in VF
-        self.memory
-            .write(&[collision_flag], self.memory.var_addr + 0xf, 1)?;
-
-        // duration is:
-        //    (6+6) for preamble/postamble
-        //  + (6+6+5) * rows for left byte
-        //  + 2 * rows for lbyte collision
-        //  + (4 + 4) * rows for right byte (if visible)
-        //  + 2 * rows for rbyte collision
-        Ok(dur)
+    /// e.g. for reading keys while the game's paused
+    pub fn input_mut(&mut self) -> &mut dyn input::Input {
+        self.input
     }
 
-    /// ex9e
-    fn inst_skip_key_eq(&mut self) -> Result<usize, io::Error> {
-        let vx = self.memory.get_ro_slice(self.memory.var_addr + self.vx, 1)[0];
+    /// whatever's done to it, the next frame goes over in full
+    pub fn display_mut(&mut self) -> &mut dyn display::Display {
+        self.last_frame = None;
+        self.display
+    }
 
-        if self.input.read_key()? == Some(vx) {
-            self.input.flush_keys()?;
-            self.program_counter += 2;
-            Ok(18)
-        } else {
-            Ok(14)
-        }
+    /// is the display in SCHIP's 128x64 mode?
+    pub fn hires(&self) -> bool {
+        self.machine.hires
     }
 
-    /// exa1
-    fn inst_skip_key_ne(&mut self) -> Result<usize, io::Error> {
-        let vx = self.memory.get_ro_slice(self.memory.var_addr + self.vx, 1)[0];
+    /// switch between 64x32 and SCHIP's 128x64 (00FE/00FF), starting the
+    /// new mode with a clear screen
+    pub fn set_hires(&mut self, hires: bool) -> Result<(), Chip8Error> {
+        self.machine.hires = hires;
+        let (addr, width, height) = self.display_geometry();
+        self.machine
+            .memory
+            .write(&vec![0; width * height / 8], addr, width * height / 8)?;
+        self.last_frame = None;
+        self.display.set_mode(width, height)
+    }
 
-        if self.input.read_key()? != Some(vx) {
-            self.program_counter += 2;
-            Ok(18)
+    /// where the display page is, and how wide and tall it is in pixels
+    pub fn display_geometry(&self) -> (u16, usize, usize) {
+        if self.machine.hires {
+            (self.machine.memory.hires_display_addr, 128, 64)
         } else {
-            self.input.flush_keys()?;
-            Ok(14)
+            (self.machine.display_pointer, 64, 32)
         }
     }
 
-    /// fx07
-    fn inst_get_timer(&mut self) -> Result<usize, io::Error> {
-        self.memory
-            .write(&[self.general_timer], self.memory.var_addr + self.vx, 1)?;
-        Ok(10)
+    /// stop the program for good, e.g. for SCHIP's 00FD
+    pub fn exit(&mut self) {
+        self.machine.state = CycleState::Exited;
     }
 
-    /// fx0a
-    fn inst_wait_key(&mut self) -> Result<usize, io::Error> {
-        // the plan is to poll for a key after each interrupt, so that wait_key
-        // is interruptable. theoretical timings can therefore be much shorter
-        // than the COSMAC, although the user is likely slower anyway
-        self.state = InterpreterState::WaitInterrupt;
+    /// has the program stopped itself?
+    pub fn exited(&self) -> bool {
+        self.machine.state == CycleState::Exited
+    }
 
-        if let Some(key) = self.input.read_key()? {
-            match self.tone_timer {
-                1 => {
-                    self.memory
-                        .write(&[key], self.memory.var_addr + self.vx, 1)?;
-                    self.input.flush_keys()?;
-                    self.state = InterpreterState::FetchDecode;
-                }
-                2..=3 => {
-                    self.tone_timer -= 1;
-                }
-                _ => {
-                    self.tone_timer = 4;
-                }
+    /// what the program's up to: running, waiting, or not going anywhere
+    pub fn state(&self) -> InterpreterState {
+        if let Some(error) = &self.fault {
+            return InterpreterState::Faulted {
+                error: error.clone(),
+            };
+        }
+        let pc = self.machine.program_counter;
+        match self.machine.state {
+            CycleState::Exited => InterpreterState::Halted,
+            // FX0A goes back and forth between waiting for the interrupt
+            // and checking the keys
+            CycleState::WaitInterrupt | CycleState::Execute
+                if self.machine.instruction_data & 0xf0ff == 0xf00a =>
+            {
+                InterpreterState::WaitingForKey
+            }
+            CycleState::WaitInterrupt if self.machine.second_half => {
+                InterpreterState::WaitingForVblank
             }
+            CycleState::FetchDecode
+                if self.machine.memory.get_ro_slice(pc, 2).ok()
+                    == Some(&(0x1000 | pc).to_be_bytes()) =>
+            {
+                InterpreterState::Halted
+            }
+            _ => InterpreterState::Running,
         }
-        Ok(1000) // dummy value
     }
 
-    /// fx15
-    fn inst_set_timer(&mut self) -> Result<usize, io::Error> {
-        self.general_timer = self.memory.get_ro_slice(self.memory.var_addr + self.vx, 1)[0];
-        Ok(10)
+    /// have watch look over memory at the end of every frame
+    pub fn add_watch(&mut self, watch: &'a mut dyn MemoryWatch) {
+        self.watches.push(watch);
     }
 
-    /// fx18
-    fn inst_set_sound(&mut self) -> Result<usize, io::Error> {
-        self.tone_timer = self.memory.get_ro_slice(self.memory.var_addr + self.vx, 1)[0];
-        Ok(10)
+    /// let patch change memory after every instruction
+    pub fn add_patch(&mut self, patch: &'a mut dyn MemoryPatch) {
+        self.patches.push(patch);
     }
 
-    /// fx1e
-    fn inst_add_x_to_i(&mut self) -> Result<usize, io::Error> {
-        let vx = self.memory.get_ro_slice(self.memory.var_addr + self.vx, 1)[0] as u16;
-        let old_i = self.i;
-        self.i += vx;
-        // 12+4 or 18+4; from https://laurencescotford.com/chip-8-on-the-cosmac-vip-indexing-the-memory/
-        if (old_i & 0xff00) == (self.i & 0xff00) {
-            Ok(16)
-        } else {
-            Ok(22)
-        }
+    /// tell tracer about every instruction before it runs
+    pub fn add_tracer(&mut self, tracer: &'a mut dyn Tracer) {
+        self.tracers.push(tracer);
     }
 
-    /// fx29
-    fn inst_load_char(&mut self) -> Result<usize, io::Error> {
-        let ch = 0xf & self.memory.get_ro_slice(self.memory.var_addr + self.vx, 1)[0] as u16;
-
-        // since we have the _actual_ VIP interpreter in 0x000-0x1ff anyway for
-        // authentic "randomness" ... we can use its lookup to get font addresses
-        let lookup_addr = self.memory.get_ro_slice(0x8100 + ch, 1)[0] as u16;
-
-        self.i = 0x8100 + lookup_addr;
-        Ok(20)
+    /// have extension run any instructions it handles that we can't decode
+    /// ourselves
+    pub fn add_extension(&mut self, extension: &'a mut dyn OpcodeExtension) {
+        self.extensions.push(extension);
     }
 
-    /// fx33
-    fn inst_x_to_bcd(&mut self) -> Result<usize, io::Error> {
-        let input = self.memory.get_ro_slice(self.memory.var_addr + self.vx, 1)[0];
-        let output = self.memory.get_rw_slice(self.i, 3);
-        output[0] = input / 100;
-        output[1] = (input % 100) / 10;
-        output[2] = (input % 100) % 10;
-        Ok(84 + 16 * ((output[0] + output[1] + output[2]) as usize))
+    /// load a chip8 program
+    pub fn load_program(&mut self, reader: &mut impl io::Read) -> Result<(), Chip8Error> {
+        self.fault = None;
+        self.machine.memory.load_program(reader)
     }
 
-    /// fx55
-    fn inst_save_v_at_i(&mut self) -> Result<usize, io::Error> {
-        let v = self
-            .memory
-            .get_ro_slice(self.memory.var_addr, 1 + self.vx as usize)
-            .to_vec();
-        self.memory.write(v.as_slice(), self.i, v.len())?;
-
-        // i points at address after i+vx
-        self.i += self.vx + 1;
-        // 14 + 14 * x + 4
-        Ok(14 + 14 * (1 + self.vx as usize) + 4)
+    /// the interrupt that's come due, if one has. a frame starting gets a
+    /// checkpoint first, while it's still to come, so the machine picks up
+    /// from there exactly as it went
+    fn pop_interrupt(&mut self) -> Option<Interrupt> {
+        let cycles = self.machine.cycles;
+        if let Some(c) = &mut self.checkpoints {
+            if self.machine.interrupts.peek_due(cycles) == Some(Interrupt::DisplayRefresh) {
+                if c.len() == CHECKPOINT_FRAMES {
+                    c.pop_front();
+                }
+                c.push_back(self.machine.clone());
+            }
+        }
+        self.machine.interrupts.pop_due(cycles)
     }
 
-    /// fx65
-    fn inst_load_v_at_i(&mut self) -> Result<usize, io::Error> {
-        let v = self
-            .memory
-            .get_ro_slice(self.i, 1 + self.vx as usize)
-            .to_vec();
-        self.memory
-            .write(v.as_slice(), self.memory.var_addr, v.len())?;
-
-        // i points at address after i+vx
-        self.i += self.vx + 1;
-        // 14 + 14 * x + 4
-        Ok(14 + 14 * (1 + self.vx as usize) + 4)
+    /// external interrupt
+    fn interrupt(&mut self, interrupt: Interrupt) -> Result<usize, Chip8Error> {
+        let t = match interrupt {
+            Interrupt::DisplayRefresh => self.display_interrupt(),
+            Interrupt::ToneTimer => self.timer_interrupt(),
+            Interrupt::External(reason) => {
+                // the extensions get the whole machine, as with an
+                // instruction of theirs. the first to fail stops it there,
+                // once they're all put back
+                let mut extensions = std::mem::take(&mut self.extensions);
+                let t = (|| {
+                    for e in extensions.iter_mut() {
+                        match e.interrupt(reason, self)? {
+                            0 => continue,
+                            t => return Ok(t),
+                        }
+                    }
+                    Ok(0)
+                })();
+                self.extensions = extensions;
+                t
+            }
+        }?;
+        self.spent.interrupt += t as u32;
+        Ok(t)
     }
-}
-
-/// state machine for fetch-decode-execute-interrupt. it's in the state before
-/// and during it's doing the thing. so think "fetch-ing", "ready to fetch", ...
-///
-/// |                  .-----------------------.
-/// |                  v                       |
-/// | .-------.    .----------------.     .---------.
-/// | | start |--->| fetch + decode |---->| execute |
-/// | `-------'    `----------------'     `---------'
-/// |                  ^                       |
-/// |                  |   .---------------.   |
-/// |                  `---| interruptable |<--'
-/// |                      `---------------'
-#[derive(PartialEq)]
-enum InterpreterState {
-    FetchDecode,
-    Execute,
-    WaitInterrupt, // waiting for an interrupt
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
 
-    fn test_with(
-        f: fn(i: &mut Chip8Interpreter) -> Result<(), Box<dyn Error>>,
-    ) -> Result<(), Box<dyn Error>> {
-        let mut display = display::DummyDisplay::new()?;
-        let mut input = input::DummyInput::new(&[0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f]);
-        let mut sound = sound::Mute::new();
-        let mut i = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
-        let mut prog: &[u8] = &[0x00, 0xe0, 0xa2, 0x2a, 0x60, 0x0c];
-        i.load_program(&mut prog)?;
-        f(&mut i)
+    /// where the cycles went in each of the last few frames, the latest
+    /// last (as the HUD's scope shows them)
+    pub fn cycle_history(&self) -> &[display::FrameCycles] {
+        &self.scope
     }
 
-    #[test]
-    fn test_program_load_ok() -> Result<(), Box<dyn Error>> {
-        test_with(|_i| Ok(()))
+    /// have source interrupt the interpreter from now on, e.g. a ToneTimer
+    /// to count the timers down at their own rate, or an External to
+    /// interrupt at exactly the cycle a test wants
+    pub fn add_interrupt_source(&mut self, source: &dyn InterruptSource) {
+        self.machine
+            .interrupts
+            .add_source(source, self.machine.cycles, self.cycle_ns());
     }
 
-    #[test]
-    fn test_fetch_and_decode_moves_pc() -> Result<(), Box<dyn Error>> {
-        test_with(|i| {
-            let _ = i.fetch_and_decode()?;
-            assert_eq!(i.program_counter, 0x202);
-            Ok(())
-        })
+    /// stop interrupt from happening again, e.g. the ToneTimer, to put the
+    /// timers back in the display's ISR
+    pub fn remove_interrupt_source(&mut self, interrupt: Interrupt) {
+        self.machine.interrupts.unregister(interrupt);
     }
 
-    #[test]
-    fn test_fetch_and_decode_sets_state() -> Result<(), Box<dyn Error>> {
-        test_with(|i| {
-            let _ = i.fetch_and_decode()?;
-            assert!(i.state == InterpreterState::Execute);
-            Ok(())
-        })
+    /// interrupt the interpreter now, as if interrupt had come due, rather
+    /// than when the queue says: e.g. DisplayRefresh from a host that's
+    /// driven by its own vsync (with the DisplayRefresh source removed, so
+    /// the frames are only the ones it raises), or an External for an
+    /// extension. anything else that's due is taken as well, and the
+    /// time it all takes goes by before this returns. there's none of this
+    /// in the stable API, which has no extensions to interrupt
+    pub fn raise_interrupt(&mut self, interrupt: Interrupt) -> Result<(), Chip8Error> {
+        self.machine
+            .interrupts
+            .register(interrupt, self.machine.cycles, 0);
+        while let Some(interrupt) = self.pop_interrupt() {
+            let t = self.interrupt(interrupt)?;
+            self.advance(t)?;
+        }
+        Ok(())
     }
 
-    #[test]
-    fn test_fetch_and_decode_zero_inst_duration() -> Result<(), Box<dyn Error>> {
-        // 0xxx instructions take 40 machine cycles on the original chip-8
-        // the first test fixture instruction is 00e0
-        test_with(|i| {
-            assert_eq!(i.fetch_and_decode()?, 40);
-            Ok(())
-        })
+    /// have the host start each frame, by calling on_vblank when its own
+    /// display does, rather than keeping time itself: with a real vsync to
+    /// go by, there's no sleeping to drift from it, even on a display
+    /// whose refresh rate wanders. false goes back to the interrupt
+    /// queue's frames, from now
+    pub fn set_host_vsync(&mut self, vsync: bool) {
+        self.remove_interrupt_source(Interrupt::DisplayRefresh);
+        if !vsync {
+            self.add_interrupt_source(&interrupt::DisplayRefresh {
+                rate: self.machine.refresh_rate,
+            });
+        }
     }
 
-    #[test]
-    fn test_fetch_and_decode_other_inst_duration() -> Result<(), Box<dyn Error>> {
-        // other instructions take 68 machine cycles
-        // the second test fixture instruction is axxx
-        test_with(|i| {
-            let _ = i.fetch_and_decode()?;
-            assert_eq!(i.fetch_and_decode()?, 68);
-            Ok(())
-        })
+    /// the host's display is starting a frame, so start one here, and run
+    /// a frame's worth of cycles (at the refresh rate) as fast as possible.
+    /// the host's to have called set_host_vsync first, or the interrupt
+    /// queue's frames happen as well
+    pub fn on_vblank(&mut self) -> Result<(), Chip8Error> {
+        let end = self.machine.cycles + self.frame_cycles();
+        self.raise_interrupt(Interrupt::DisplayRefresh)?;
+        self.run_cycles(end.saturating_sub(self.machine.cycles))
     }
 
-    #[test]
-    fn test_fetch_and_decode_sets_vx() -> Result<(), Box<dyn Error>> {
-        test_with(|i| {
-            // second test fixture instruction is a22a
-            let _ = i.fetch_and_decode()?;
-            let _ = i.fetch_and_decode()?;
-            assert_eq!(i.vx, 0x02);
-            Ok(())
-        })
+    /// the timers counting down on their own, when there's a ToneTimer
+    fn timer_interrupt(&mut self) -> Result<usize, Chip8Error> {
+        let tick = self.machine.timers.tick();
+        // the sound's told as soon as it runs out, unless it's to wait for
+        // the frame
+        if tick.tone_stopped && !self.machine.quirks.frame_tone {
+            self.buzz(false)?;
+        }
+        Ok(tick.cycles)
     }
 
-    #[test]
-    fn test_fetch_and_decode_sets_vy() -> Result<(), Box<dyn Error>> {
-        test_with(|i| {
-            // first test fixture instruction is 0e00
-            let _ = i.fetch_and_decode()?;
-            assert_eq!(i.vy, 0x0e);
-            Ok(())
-        })
+    /// start or stop the buzzer
+    fn buzz(&mut self, on: bool) -> Result<(), Chip8Error> {
+        match on {
+            true => {
+                // silent's silent, even for devices that can only be on
+                if self.volume.audible() > 0 {
+                    self.sound.beep()?;
+                }
+                self.input.feedback(Feedback::Buzzer);
+            }
+            false => self.sound.stop()?,
+        }
+        self.buzzing = on;
+        Ok(())
     }
 
-    #[test]
-    fn test_call_ok() -> Result<(), Box<dyn Error>> {
-        test_with(|i| {
-            let _ = i.fetch_and_decode()?;
-            assert_eq!(i.call()?, 24); // cycles for 0e00
-            Ok(())
-        })
-    }
+    /// the 1861's interrupt at the start of each frame
+    fn display_interrupt(&mut self) -> Result<usize, Chip8Error> {
+        // duration
+        // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-interrupts/
+        // (when shearing, the DMA's counted as it happens instead)
+        let mut dur = self.machine.timing.interrupt as usize;
+        if !self.machine.shear {
+            dur += self.machine.timing.dma as usize;
+        }
 
-    #[test]
-    fn test_clear_screen() -> Result<(), Box<dyn Error>> {
-        // 0e00
+        // the last frame's over, as far as where its cycles went
+        self.scope.copy_within(1.., 0);
+        self.scope[display::HUD_SCOPE_FRAMES - 1] = self.spent;
+        self.spent = display::FrameCycles::default();
+
+        // increment random seed
+        self.machine.random = self.machine.random.wrapping_add(1);
+        self.machine.frames += 1;
+
+        // update timers, unless they've a ToneTimer of their own
+        let before = self.machine.timers.clone();
+        if !self.machine.interrupts.contains(Interrupt::ToneTimer) {
+            dur += self.timer_interrupt()?;
+        }
+        // with frame-tone, this is the only time the buzzer changes: it
+        // sounds for the frame if the tone timer was going as it started
+        if self.machine.quirks.frame_tone {
+            let on = match self.buzzing {
+                true => before.tone > 0,
+                false => before.tone_audible(self.machine.quirks.short_tone),
+            };
+            if on != self.buzzing {
+                self.buzz(on)?;
+            }
+        }
+
+        // tell the input and sound routines that another frame has passed
+        self.input.tick()?;
+        self.sound.tick()?;
+
+        if let Some(limit) = self.key_watchdog {
+            self.waited_frames = match self.state() {
+                InterpreterState::WaitingForKey if self.input.out_of_keys() => {
+                    self.waited_frames + 1
+                }
+                _ => 0,
+            };
+            if self.waited_frames > limit {
+                return Err(Chip8Error::KeyDeadlock {
+                    addr: self.machine.program_counter - 2,
+                    frames: self.waited_frames,
+                });
+            }
+        }
+
+        if self.machine.shear {
+            // the frame goes out a row at a time once the ISR's done
+            self.machine.frame_start = self.machine.cycles + dur as u64;
+            self.machine.scanned_rows = 0;
+            self.machine.scanning = true;
+        } else {
+            let (addr, width, height) = self.display_geometry();
+            let frame = self
+                .machine
+                .memory
+                .get_ro_slice(addr, width * height / 8)?
+                .to_vec();
+            self.show(frame)?;
+        }
+
+        // the frame's done, so see if anything interesting happened in it
+        for watch in self.watches.iter_mut() {
+            for notice in watch.frame(&self.machine.memory)? {
+                self.display.notify(&notice);
+            }
+        }
+
+        // if we'd been waiting for an interrupt, put the interpreter back into
+        // the Execute state, because it will have been mid-instruction
+        if self.machine.state == CycleState::WaitInterrupt {
+            self.machine.state = CycleState::Execute;
+        }
+        Ok(dur)
+    }
+
+    /// step the interpreter forward one state, returning number of machine
+    /// cycles consumed.
+    fn cycle(&mut self) -> Result<usize, Chip8Error> {
+        // FX0A's idling, whichever state it's in
+        let waiting = self.machine.instruction_data & 0xf0ff == 0xf00a;
+        let state = self.machine.state;
+        let t = self.cycle_state();
+        match (&t, state) {
+            (Err(e), _) => self.fault = Some(e.to_string()),
+            (Ok(t), CycleState::FetchDecode) => self.spent.fetch += *t as u32,
+            (Ok(t), CycleState::Execute) if !waiting => self.spent.execute += *t as u32,
+            (Ok(t), _) => self.spent.idle += *t as u32,
+        }
+        t
+    }
+
+    fn cycle_state(&mut self) -> Result<usize, Chip8Error> {
+        match self.machine.state {
+            CycleState::FetchDecode => self.fetch_and_decode(),
+            CycleState::Execute => {
+                let t = self.call()?;
+                // instructions that wait for an interrupt aren't done yet
+                if self.machine.state == CycleState::FetchDecode {
+                    for patch in self.patches.iter_mut() {
+                        patch.after_instruction(&mut self.machine.memory)?;
+                    }
+                }
+                Ok(t)
+            }
+            CycleState::WaitInterrupt | CycleState::Exited => Ok(1),
+        }
+    }
+
+    /// run the main interpreter loop, including timing and interrupts, for
+    /// `frame_count` frames or until the player asks for the menu. it can be
+    /// called again to carry on where it left off
+    pub fn main_loop(&mut self, frame_count: usize) -> Result<RunOutcome, Chip8Error> {
+        let spin = SpinClock::new(CHIP8_CYCLE_NS as u32);
+        let clock = self.clock.unwrap_or(&spin);
+        let end = self.machine.frames + frame_count as u64;
+        // instructions since the clock was last looked at
+        let mut batch = Batch {
+            start: clock.now(),
+            cycles: 0,
+        };
+
+        loop {
+            if self.exited() {
+                return Ok(RunOutcome::Exited);
+            }
+            self.check_cancelled()?;
+
+            // service whatever interrupts have come due. the isr runs inside
+            // the frame, so the time spent in it counts towards the frame
+            while let Some(interrupt) = self.machine.interrupts.peek_due(self.machine.cycles) {
+                // the interrupt's timed on its own, so catch up with the
+                // instructions before it
+                self.settle(clock, &mut batch);
+                // leave the next frame for next time
+                if interrupt == Interrupt::DisplayRefresh && self.machine.frames == end {
+                    return Ok(RunOutcome::Finished);
+                }
+                self.pop_interrupt();
+                let now = clock.now();
+                let t = self.interrupt(interrupt)?;
+                self.advance(t)?;
+                let (pace, cycle_ns) = (self.pace(), self.cycle_ns());
+                if let Some(overrun) =
+                    Self::sleep_until_done(clock, now, t, cycle_ns, pace, &mut self.idle_debt)
+                {
+                    self.overruns.interrupts += 1;
+                    if self.warn_overrun() {
+                        eprintln!(
+                            "{:09?}: Warning: ISR took longer than COSMAC by {:?}",
+                            self.machine.frames, overrun
+                        );
+                    }
+                }
+                if interrupt == Interrupt::DisplayRefresh {
+                    if let Some(request) = self.input.take_speed_request()? {
+                        self.change_speed(request);
+                    }
+                    if self.input.take_hud_toggle()? {
+                        self.set_hud(!self.hud);
+                    }
+                    if self.input.take_help_toggle()? {
+                        self.show_help(!self.showing_help);
+                    }
+                    if let Some(request) = self.input.take_volume_request()? {
+                        self.change_volume(request)?;
+                    }
+                    // the input switches itself over, and the display's told
+                    if self.input.focus() != self.focus {
+                        self.focus = self.input.focus();
+                        self.display.set_focus(self.focus);
+                    }
+                    if self.input.take_menu_request()? {
+                        return Ok(RunOutcome::MenuRequested);
+                    }
+                    if let Some(request) = self.input.take_slot_request()? {
+                        return Ok(RunOutcome::SlotRequested(request));
+                    }
+                    if self.input.take_palette_request()? {
+                        return Ok(RunOutcome::PaletteRequested);
+                    }
+                    if self.idle_wait {
+                        self.wait_while_idle(clock)?;
+                    }
+                }
+                // the next batch starts after all that
+                batch.start = clock.now();
+            }
+
+            // then carry on with whatever the interpreter was doing, only
+            // looking at the clock once there's enough to be worth a sleep
+            let t = self.cycle()?;
+            self.advance(t)?;
+            batch.cycles += t;
+            if (batch.cycles as u64 * self.cycle_ns()) as f64 / self.pace() >= CLOCK_BATCH_NS {
+                self.settle(clock, &mut batch);
+            }
+        }
+    }
+
+    /// sleep until the batch of instructions would have finished on the
+    /// VIP, and start another
+    fn settle(&mut self, clock: &dyn Clock, batch: &mut Batch) {
+        if batch.cycles > 0 {
+            let (pace, cycle_ns) = (self.pace(), self.cycle_ns());
+            if let Some(overrun) = Self::sleep_until_done(
+                clock,
+                batch.start,
+                batch.cycles,
+                cycle_ns,
+                pace,
+                &mut self.idle_debt,
+            ) {
+                self.overruns.instructions += 1;
+                if self.warn_overrun() {
+                    eprintln!(
+                        "{:09?}: Warning: instructions up to {:04x?} took longer than COSMAC by {:?}",
+                        self.machine.frames, self.machine.instruction_data, overrun
+                    );
+                }
+            }
+        }
+        *batch = Batch {
+            start: clock.now(),
+            cycles: 0,
+        };
+    }
+
+    /// run as fast as possible (no sleeping) for at least `cycles` machine
+    /// cycles, or until the program exits. interrupts, and therefore timers
+    /// and the display, still happen at the emulated rate, so this is good
+    /// for headless runs
+    pub fn run_cycles(&mut self, cycles: u64) -> Result<(), Chip8Error> {
+        let end = self.machine.cycles + cycles;
+        while self.machine.cycles < end && !self.exited() {
+            self.check_cancelled()?;
+            let t = match self.pop_interrupt() {
+                Some(interrupt) => self.interrupt(interrupt)?,
+                None => self.cycle()?,
+            };
+            self.advance(t)?;
+        }
+        Ok(())
+    }
+
+    /// run as fast as possible until the next instruction's finished,
+    /// including any interrupts that come due in the meantime. FX0A only
+    /// finishes once there's a key, which may be never, so a step there
+    /// stops once it's looked for one: the next step looks again. one
+    /// waiting on anything else that doesn't come stops after
+    /// STEP_MAX_FRAMES, to carry on from with the next step
+    pub fn step(&mut self) -> Result<(), Chip8Error> {
+        let end = self.machine.cycles + STEP_MAX_FRAMES * self.frame_cycles();
+        loop {
+            if self.exited() || self.machine.cycles >= end {
+                return Ok(());
+            }
+            self.check_cancelled()?;
+            let t = match self.pop_interrupt() {
+                Some(interrupt) => self.interrupt(interrupt)?,
+                None => {
+                    let executing = self.machine.state == CycleState::Execute;
+                    let t = self.cycle()?;
+                    if executing
+                        && (self.machine.state == CycleState::FetchDecode
+                            || self.state() == InterpreterState::WaitingForKey)
+                    {
+                        self.advance(t)?;
+                        return Ok(());
+                    }
+                    t
+                }
+            };
+            self.advance(t)?;
+        }
+    }
+
+    /// run as fast as possible for `frame_count` emulated frames
+    pub fn run_frames(&mut self, frame_count: u64) -> Result<(), Chip8Error> {
+        self.run_cycles(frame_count * self.frame_cycles())
+    }
+
+    /// at the start of a frame, if FX0A's waiting and the timers have
+    /// stopped, wait on the input for up to IDLE_WAIT, and owe the frames
+    /// the time that took. anything else going on, and the frames go back
+    /// to keeping time from now on
+    fn wait_while_idle(&mut self, clock: &dyn Clock) -> Result<(), Chip8Error> {
+        let timers = &self.machine.timers;
+        if self.state() != InterpreterState::WaitingForKey
+            || timers.general != 0
+            || timers.tone != 0
+        {
+            self.idle_debt = time::Duration::ZERO;
+            return Ok(());
+        }
+        // still catching up on the last wait
+        if !self.idle_debt.is_zero() {
+            return Ok(());
+        }
+        let start = clock.now();
+        if self.input.wait_for_event(IDLE_WAIT)? {
+            self.idle_debt = clock.now() - start;
+        }
+        Ok(())
+    }
+
+    /// sleep until `cycles` machine cycles of cycle_ns (at speed times the
+    /// VIP's rate) after `start`, or say by how much we've overrun if that's
+    /// already passed. any time owed from waiting while idle comes off the
+    /// sleep
+    fn sleep_until_done(
+        clock: &dyn Clock,
+        start: time::Duration,
+        cycles: usize,
+        cycle_ns: u64,
+        speed: f64,
+        debt: &mut time::Duration,
+    ) -> Option<time::Duration> {
+        // |..c.....|..............................................|
+        //    ^-now ^-inst_end                                     ^-next interrupt
+        let inst_end = start + time::Duration::from_nanos(cycle_ns * cycles as u64).div_f64(speed);
+        let now = clock.now();
+        if inst_end >= now {
+            let paid = (inst_end - now).min(*debt);
+            *debt -= paid;
+            clock.sleep(inst_end - now - paid);
+            None
+        } else {
+            Some(now - inst_end)
+        }
+    }
+
+    /// move time on by cycles, putting out any rows of the frame that the
+    /// 1861 has got to in the meantime when shearing
+    fn advance(&mut self, cycles: usize) -> Result<(), Chip8Error> {
+        self.machine.cycles += cycles as u64;
+        if !self.machine.scanning {
+            return Ok(());
+        }
+        let (addr, width, height) = self.display_geometry();
+        let row_bytes = width / 8;
+        let row_cycles = VIP_DISPLAY_LINES * VIP_LINE_CYCLES / height as u64;
+        // the DMA's stolen cycles are part of each row's time, so they don't
+        // bring the next row any sooner
+        let now = self.machine.cycles;
+        while self.machine.scanned_rows < height {
+            let row = self.machine.scanned_rows;
+            let due = self.machine.frame_start + VIP_DISPLAY_START_CYCLES + row as u64 * row_cycles;
+            if due > now {
+                return Ok(());
+            }
+            let data = self
+                .machine
+                .memory
+                .get_ro_slice(addr + (row * row_bytes) as u16, row_bytes)?;
+            self.machine.scan.resize(width * height / 8, 0);
+            self.machine.scan[row * row_bytes..(row + 1) * row_bytes].copy_from_slice(data);
+            self.machine.scanned_rows += 1;
+            let dma = self.machine.timing.dma / height as u32;
+            self.machine.cycles += dma as u64;
+            self.spent.interrupt += dma;
+        }
+        // nothing more until the next interrupt
+        self.machine.scanning = false;
+        self.show(self.machine.scan.clone())
+    }
+
+    /// hand frame to the display, along with what's changed since the last
+    fn show(&mut self, frame: Vec<u8>) -> Result<(), Chip8Error> {
+        self.beeps = self.beeps << 1 | (self.machine.timers.tone > 0) as u32;
+        if self.hud {
+            self.display.set_hud(Some(display::Hud {
+                delay_timer: self.machine.timers.general,
+                sound_timer: self.machine.timers.tone,
+                random: self.machine.random,
+                opcode: self.machine.instruction_data,
+                beeps: self.beeps,
+                cycles: self.scope,
+                waiting_for_key: self.state() == InterpreterState::WaitingForKey,
+            }));
+        }
+        if let Some(h) = &mut self.heat {
+            self.display.set_heat(Some(h.cool()));
+        }
+        let changed = match &self.last_frame {
+            Some(last) if last.len() == frame.len() => Some(display::changed_ranges(last, &frame)),
+            _ => None,
+        };
+        self.display.draw_changes(&frame, changed.as_deref())?;
+        self.last_frame = Some(frame);
+        Ok(())
+    }
+
+    /// which of an instruction's count parts to run now: all of them, or
+    /// when splitting instructions, just the next, with the instruction left
+    /// in Execute so the rest happen on later cycles
+    fn parts(&mut self, count: usize) -> Range<usize> {
+        if !self.machine.split_instructions {
+            return 0..count;
+        }
+        let part = self.machine.part;
+        if part + 1 < count {
+            self.machine.part += 1;
+            self.machine.state = CycleState::Execute;
+        } else {
+            self.machine.part = 0;
+        }
+        part..(part + 1).min(count)
+    }
+
+    /// fetch the instruction at the program counter, figure out what it is,
+    /// set vx/vy, update the program counter, update the interpreter state
+    fn fetch_and_decode(&mut self) -> Result<usize, Chip8Error> {
+        let inst = self.machine.memory.get_word(self.machine.program_counter)?;
+        // before decoding, so an illegal instruction gets traced too
+        if !self.tracers.is_empty() {
+            let v = self
+                .machine
+                .memory
+                .get_ro_slice(self.machine.memory.var_addr, 16)?;
+            let entry = TraceEntry {
+                frame: self.machine.frames,
+                pc: self.machine.program_counter,
+                opcode: inst,
+                v: v.try_into().unwrap(),
+                i: self.machine.i,
+            };
+            for tracer in self.tracers.iter_mut() {
+                tracer.trace(&entry)?;
+            }
+        }
+
+        // first byte, second nybble
+        self.machine.vx = (inst & 0x0f00) >> 8;
+        // second byte, first nybble
+        self.machine.vy = (inst & 0x00f0) >> 4;
+
+        self.instruction = Some(self.decode(self.machine.program_counter, inst)?);
+        self.machine.second_half = false;
+        self.machine.instruction_data = inst;
+
+        self.machine.program_counter += 2;
+        self.machine.state = CycleState::Execute;
+
+        // execution time is 40 cycles for 0xxx and 68 cycles otherwise (on
+        // the VIP)
+        if inst > 0x0fff {
+            Ok(self.machine.timing.fetch as usize)
+        } else {
+            Ok(self.machine.timing.fetch_machine_code as usize)
+        }
+    }
+
+    /// figure out which handler runs inst, found at addr
+    fn decode(&self, addr: u16, inst: u16) -> Result<Instruction<'a>, Chip8Error> {
+        let illegal = Chip8Error::IllegalInstruction { addr, inst };
+        // anything we can't decode might be one of our extensions'
+        let extension = || -> Result<Instruction<'a>, Chip8Error> {
+            match self.extensions.iter().any(|e| e.handles(inst)) {
+                true => Ok(Chip8Interpreter::inst_extension),
+                false => Err(illegal),
+            }
+        };
+
+        Ok(match inst {
+            0x00e0 => Chip8Interpreter::inst_clear_screen,
+            0x00ee => Chip8Interpreter::inst_ret,
+            // the VIP would call the interpreter's own code at 0000 and
+            // restart, but it's almost always a program that's run off its
+            // end into empty memory, so it stops here instead
+            0x0000 => extension()?,
+            0x0001..=0x0fff => extension().unwrap_or(Chip8Interpreter::inst_machine_code),
+            0x1000..=0x1fff => Chip8Interpreter::inst_branch,
+            0x2000..=0x2fff => Chip8Interpreter::inst_subroutine,
+            0x3000..=0x3fff => Chip8Interpreter::inst_skip_vx_eq,
+            0x4000..=0x4fff => Chip8Interpreter::inst_skip_vx_ne,
+            0x5000..=0x5fff => Chip8Interpreter::inst_x_eq_y,
+            0x6000..=0x6fff => Chip8Interpreter::inst_load_vx,
+            0x7000..=0x7fff => Chip8Interpreter::inst_add_to_vx,
+            0x8000..=0x8fff => match inst & 0xf {
+                0x0 => Chip8Interpreter::inst_load_x_with_y,
+                0x1 => Chip8Interpreter::inst_x_or_with_y,
+                0x2 => Chip8Interpreter::inst_x_and_with_y,
+                0x3 => Chip8Interpreter::inst_x_xor_with_y,
+                0x4 => Chip8Interpreter::inst_x_add_y,
+                0x5 => Chip8Interpreter::inst_x_minus_y,
+                0x6 => Chip8Interpreter::inst_rshift_y_load_x,
+                0x7 => Chip8Interpreter::inst_y_minus_x,
+                0xe => Chip8Interpreter::inst_lshift_y_load_x,
+                _ => extension()?,
+            },
+            0x9000..=0x9fff => Chip8Interpreter::inst_x_ne_y,
+            0xa000..=0xafff => Chip8Interpreter::inst_set_i,
+            0xb000..=0xbfff => Chip8Interpreter::inst_jump_with_offset,
+            0xc000..=0xcfff => Chip8Interpreter::inst_random,
+            // the VIP draws nothing for DXY0, but extensions can draw
+            // something bigger
+            0xd000..=0xdfff if inst & 0xf == 0 => {
+                extension().unwrap_or(Chip8Interpreter::inst_draw_sprite)
+            }
+            0xd000..=0xdfff => Chip8Interpreter::inst_draw_sprite,
+            0xe000..=0xefff => match inst & 0xff {
+                0x9e => Chip8Interpreter::inst_skip_key_eq,
+                0xa1 => Chip8Interpreter::inst_skip_key_ne,
+                _ => extension()?,
+            },
+            0xf000..=0xffff => match inst & 0xff {
+                0x07 => Chip8Interpreter::inst_get_timer,
+                0x0a => Chip8Interpreter::inst_wait_key,
+                0x15 => Chip8Interpreter::inst_set_timer,
+                0x18 => Chip8Interpreter::inst_set_sound,
+                0x1e => Chip8Interpreter::inst_add_x_to_i,
+                0x29 => Chip8Interpreter::inst_load_char,
+                0x30 => Chip8Interpreter::inst_load_big_char,
+                0x33 => Chip8Interpreter::inst_x_to_bcd,
+                0x55 => Chip8Interpreter::inst_save_v_at_i,
+                0x65 => Chip8Interpreter::inst_load_v_at_i,
+                _ => extension()?,
+            },
+        })
+    }
+
+    /// call the most recently-decoded instruction
+    fn call(&mut self) -> Result<usize, Chip8Error> {
+        // NB. ordering is important here because instructions can (and need
+        //     to) modify the interpreter state
+        self.machine.state = CycleState::FetchDecode;
+        let vip = match self.instruction {
+            Some(i) => i(self)?,
+            None => {
+                return Err(Chip8Error::IllegalInstruction {
+                    addr: self.machine.program_counter,
+                    inst: self.machine.instruction_data,
+                })
+            }
+        };
+        // the handlers work out what the VIP took; the cost model says what
+        // that comes to here
+        let done = self.machine.state == CycleState::FetchDecode;
+        Ok(self.costs.cost(self.machine.instruction_data, vip, done))
+    }
+
+    /// whatever an extension says it handles
+    fn inst_extension(&mut self) -> Result<usize, Chip8Error> {
+        let inst = self.machine.instruction_data;
+        // the extension gets the whole machine to play with, itself excepted
+        let mut extensions = std::mem::take(&mut self.extensions);
+        let result = match extensions.iter_mut().find(|e| e.handles(inst)) {
+            Some(e) => e.execute(inst, self),
+            None => Err(Chip8Error::IllegalInstruction {
+                addr: self.machine.program_counter - 2,
+                inst,
+            }),
+        };
+        self.extensions = extensions;
+        result
+    }
+
+    /// 0mmm: call 1802 machine code at mmm, which hands back with D4 (SEP R4).
+    /// the registers are set up as the VIP's interpreter leaves them, so the
+    /// machine code can get at the CHIP-8 state
+    fn inst_machine_code(&mut self) -> Result<usize, Chip8Error> {
+        let mut cpu = Cdp1802::new();
+        cpu.x = 2;
+        cpu.p = 3;
+        cpu.r[0x2] = self.machine.stack_pointer;
+        cpu.r[0x3] = self.machine.instruction_data & 0xfff;
+        cpu.r[0x5] = self.machine.program_counter;
+        cpu.r[0x6] = self.machine.memory.var_addr + self.machine.vx;
+        cpu.r[0x7] = self.machine.memory.var_addr + self.machine.vy;
+        cpu.r[0x8] = ((self.machine.timers.general as u16) << 8) | self.machine.timers.tone as u16;
+        cpu.r[0x9] = self.machine.random;
+        cpu.r[0xa] = self.machine.i;
+        cpu.r[0xb] = self.machine.display_pointer;
+        let cycles = cpu.run_until(
+            &mut self.machine.memory,
+            &mut NoIo,
+            CDP1802_MAX_CYCLES,
+            |c| c.p == 4,
+        )?;
+        self.machine.stack_pointer = cpu.r[0x2];
+        self.machine.program_counter = cpu.r[0x5];
+        self.machine.timers.general = (cpu.r[0x8] >> 8) as u8;
+        self.machine.timers.tone = cpu.r[0x8] as u8;
+        self.machine.random = cpu.r[0x9];
+        self.machine.i = cpu.r[0xa];
+        // only RB.1 is the display page; the interpreter uses RB.0 as scratch
+        self.machine.display_pointer = cpu.r[0xb] & 0xff00;
+        Ok(cycles)
+    }
+
+    /// 00e0
+    fn inst_clear_screen(&mut self) -> Result<usize, Chip8Error> {
+        let (addr, width, height) = self.display_geometry();
+        self.machine
+            .memory
+            .write(&vec![0; width * height / 8], addr, width * height / 8)?;
+        Ok(24)
+    }
+
+    /// 00ee
+    fn inst_ret(&mut self) -> Result<usize, Chip8Error> {
+        self.machine.stack_pointer += 2;
+        self.machine.program_counter = self.machine.memory.get_word(self.machine.stack_pointer)?;
+        Ok(10)
+    }
+
+    /// 1nnn
+    fn inst_branch(&mut self) -> Result<usize, Chip8Error> {
+        self.machine.program_counter = self.machine.instruction_data & 0xfff;
+        Ok(12)
+    }
+
+    /// 2nnn
+    fn inst_subroutine(&mut self) -> Result<usize, Chip8Error> {
+        self.machine.memory.write(
+            &[
+                (self.machine.program_counter >> 8) as u8,
+                (self.machine.program_counter & 0xff) as u8,
+            ],
+            self.machine.stack_pointer,
+            2,
+        )?;
+        self.machine.stack_pointer -= 2;
+        self.machine.program_counter = self.machine.instruction_data & 0xfff;
+        Ok(26)
+    }
+
+    /// 3xnn
+    fn inst_skip_vx_eq(&mut self) -> Result<usize, Chip8Error> {
+        let lhs = self
+            .machine
+            .memory
+            .get_ro_slice(self.machine.memory.var_addr + self.machine.vx, 1)?[0];
+        let rhs = self.machine.instruction_data as u8;
+        if lhs == rhs {
+            self.machine.program_counter += 2;
+            Ok(14)
+        } else {
+            Ok(10)
+        }
+    }
+
+    /// 4xnn
+    fn inst_skip_vx_ne(&mut self) -> Result<usize, Chip8Error> {
+        let lhs = self
+            .machine
+            .memory
+            .get_ro_slice(self.machine.memory.var_addr + self.machine.vx, 1)?[0];
+        let rhs = self.machine.instruction_data as u8;
+        if lhs != rhs {
+            self.machine.program_counter += 2;
+            Ok(14)
+        } else {
+            Ok(10)
+        }
+    }
+
+    /// 5xy0
+    fn inst_x_eq_y(&mut self) -> Result<usize, Chip8Error> {
+        let lhs = self
+            .machine
+            .memory
+            .get_ro_slice(self.machine.memory.var_addr + self.machine.vx, 1)?[0];
+        let rhs = self
+            .machine
+            .memory
+            .get_ro_slice(self.machine.memory.var_addr + self.machine.vy, 1)?[0];
+        if lhs == rhs {
+            self.machine.program_counter += 2;
+            Ok(18)
+        } else {
+            Ok(14)
+        }
+    }
+
+    /// 6xnn
+    fn inst_load_vx(&mut self) -> Result<usize, Chip8Error> {
+        self.machine.memory.write(
+            &[(self.machine.instruction_data & 0xff) as u8],
+            self.machine.memory.var_addr + self.machine.vx,
+            1,
+        )?;
+        Ok(6)
+    }
+
+    /// 7xnn
+    fn inst_add_to_vx(&mut self) -> Result<usize, Chip8Error> {
+        let v = self
+            .machine
+            .memory
+            .get_rw_slice(self.machine.memory.var_addr + self.machine.vx, 1)?;
+        v[0] = (((v[0] as u16) + (self.machine.instruction_data & 0xff)) & 0xff) as u8;
+        Ok(10)
+    }
+
+    /// 8xy0
+    fn inst_load_x_with_y(&mut self) -> Result<usize, Chip8Error> {
+        let vy = self
+            .machine
+            .memory
+            .get_ro_slice(self.machine.memory.var_addr + self.machine.vy, 1)?[0];
+        self.machine
+            .memory
+            .write(&[vy], self.machine.memory.var_addr + self.machine.vx, 1)?;
+        Ok(12)
+    }
+
+    /// the VIP runs 8xy1/2/3 by building the instruction in memory and
+    /// running it, and the way it does that clears vf as well
+    fn reset_vf(&mut self) -> Result<(), Chip8Error> {
+        if !self.machine.quirks.logic_leaves_vf {
+            self.machine
+                .memory
+                .write(&[0x00], self.machine.memory.var_addr + 0xf, 1)?; // vf
+        }
+        Ok(())
+    }
+
+    /// 8xy1
+    fn inst_x_or_with_y(&mut self) -> Result<usize, Chip8Error> {
+        let vy = self
+            .machine
+            .memory
+            .get_ro_slice(self.machine.memory.var_addr + self.machine.vy, 1)?[0];
+        let vx = self
+            .machine
+            .memory
+            .get_rw_slice(self.machine.memory.var_addr + self.machine.vx, 1)?;
+        vx[0] |= vy;
+        self.reset_vf()?;
+        Ok(44)
+    }
+
+    /// 8xy2
+    fn inst_x_and_with_y(&mut self) -> Result<usize, Chip8Error> {
+        let vy = self
+            .machine
+            .memory
+            .get_ro_slice(self.machine.memory.var_addr + self.machine.vy, 1)?[0];
+        let vx = self
+            .machine
+            .memory
+            .get_rw_slice(self.machine.memory.var_addr + self.machine.vx, 1)?;
+        vx[0] &= vy;
+        self.reset_vf()?;
+        Ok(44)
+    }
+
+    /// 8xy3
+    fn inst_x_xor_with_y(&mut self) -> Result<usize, Chip8Error> {
+        let vy = self
+            .machine
+            .memory
+            .get_ro_slice(self.machine.memory.var_addr + self.machine.vy, 1)?[0];
+        let vx = self
+            .machine
+            .memory
+            .get_rw_slice(self.machine.memory.var_addr + self.machine.vx, 1)?;
+        vx[0] ^= vy;
+        self.reset_vf()?;
+        Ok(44)
+    }
+
+    /// 8xy4
+    fn inst_x_add_y(&mut self) -> Result<usize, Chip8Error> {
+        let vy = self
+            .machine
+            .memory
+            .get_ro_slice(self.machine.memory.var_addr + self.machine.vy, 1)?[0]
+            as u16;
+        let vx = self
+            .machine
+            .memory
+            .get_rw_slice(self.machine.memory.var_addr + self.machine.vx, 1)?;
+        let res: u16 = vx[0] as u16 + vy;
+        vx[0] = res as u8;
+        self.machine.memory.write(
+            &[if res > 0xff { 0x01 } else { 0x00 }],
+            self.machine.memory.var_addr + 0xf,
+            1,
+        )?;
+        Ok(44)
+    }
+
+    /// 8xy5
+    fn inst_x_minus_y(&mut self) -> Result<usize, Chip8Error> {
+        let vy = self
+            .machine
+            .memory
+            .get_ro_slice(self.machine.memory.var_addr + self.machine.vy, 1)?[0]
+            as u16;
+        let vx = self
+            .machine
+            .memory
+            .get_rw_slice(self.machine.memory.var_addr + self.machine.vx, 1)?;
+        let res: u16 = 0x100 + (vx[0] as u16) - vy;
+        vx[0] = res as u8;
+        self.machine.memory.write(
+            &[if res < 0x100 { 0x00 } else { 0x01 }],
+            self.machine.memory.var_addr + 0xf,
+            1,
+        )?;
+        Ok(44)
+    }
+
+    /// 8xy6
+    fn inst_rshift_y_load_x(&mut self) -> Result<usize, Chip8Error> {
+        // (see discussion here: https://laurencescotford.com/chip-8-on-the-cosmac-vip-arithmetic-and-logic-instructions/)
+        // the VIP shifts vy into vx; CHIP-48 onwards shift vx in place, and
+        // most ROMs since expect that
+        if self.machine.quirks.shift_vx {
+            let vx = self
+                .machine
+                .memory
+                .get_rw_slice(self.machine.memory.var_addr + self.machine.vx, 1)?;
+            let lsb = vx[0] & 0x1;
+            vx[0] >>= 1;
+            self.machine
+                .memory
+                .write(&[lsb], self.machine.memory.var_addr + 0xf, 1)?; // vf
+            return Ok(44);
+        }
+        let vy = self
+            .machine
+            .memory
+            .get_ro_slice(self.machine.memory.var_addr + self.machine.vy, 1)?[0];
+        let res = vy >> 1;
+        self.machine
+            .memory
+            .write(&[res], self.machine.memory.var_addr + self.machine.vx, 1)?;
+        self.machine
+            .memory
+            .write(&[res], self.machine.memory.var_addr + self.machine.vy, 1)?;
+        self.machine
+            .memory
+            .write(&[vy & 0x1], self.machine.memory.var_addr + 0xf, 1)?; // vf
+        Ok(44)
+    }
+
+    /// 8xy7
+    fn inst_y_minus_x(&mut self) -> Result<usize, Chip8Error> {
+        let vy = self
+            .machine
+            .memory
+            .get_ro_slice(self.machine.memory.var_addr + self.machine.vy, 1)?[0]
+            as u16;
+        let vx = self
+            .machine
+            .memory
+            .get_rw_slice(self.machine.memory.var_addr + self.machine.vx, 1)?;
+        let res: u16 = 0x100 + vy - (vx[0] as u16);
+        vx[0] = res as u8;
+        self.machine.memory.write(
+            &[if res < 0x100 { 0x00 } else { 0x01 }],
+            self.machine.memory.var_addr + 0xf,
+            1,
+        )?;
+        Ok(44)
+    }
+
+    /// 8xye
+    fn inst_lshift_y_load_x(&mut self) -> Result<usize, Chip8Error> {
+        // (see discussion here: https://laurencescotford.com/chip-8-on-the-cosmac-vip-arithmetic-and-logic-instructions/)
+        if self.machine.quirks.shift_vx {
+            let vx = self
+                .machine
+                .memory
+                .get_rw_slice(self.machine.memory.var_addr + self.machine.vx, 1)?;
+            let msb = (vx[0] & 0x80) >> 7;
+            vx[0] <<= 1;
+            self.machine
+                .memory
+                .write(&[msb], self.machine.memory.var_addr + 0xf, 1)?; // vf
+            return Ok(44);
+        }
+        let vy = self
+            .machine
+            .memory
+            .get_ro_slice(self.machine.memory.var_addr + self.machine.vy, 1)?[0];
+        let res: u8 = vy << 1;
+        self.machine
+            .memory
+            .write(&[res], self.machine.memory.var_addr + self.machine.vx, 1)?;
+        self.machine
+            .memory
+            .write(&[res], self.machine.memory.var_addr + self.machine.vy, 1)?;
+        self.machine
+            .memory
+            .write(&[(vy & 0x80) >> 7], self.machine.memory.var_addr + 0xf, 1)?; // vf
+        Ok(44)
+    }
+
+    /// 9xy0
+    fn inst_x_ne_y(&mut self) -> Result<usize, Chip8Error> {
+        let lhs = self
+            .machine
+            .memory
+            .get_ro_slice(self.machine.memory.var_addr + self.machine.vx, 1)?[0];
+        let rhs = self
+            .machine
+            .memory
+            .get_ro_slice(self.machine.memory.var_addr + self.machine.vy, 1)?[0];
+        if lhs != rhs {
+            self.machine.program_counter += 2;
+            Ok(18)
+        } else {
+            Ok(14)
+        }
+    }
+
+    /// annn
+    fn inst_set_i(&mut self) -> Result<usize, Chip8Error> {
+        self.machine.i = self.machine.instruction_data & 0xfff;
+        Ok(12)
+    }
+
+    /// bnnn
+    fn inst_jump_with_offset(&mut self) -> Result<usize, Chip8Error> {
+        // CHIP-48 and SUPER-CHIP read the x as well as the nnn, as bxnn
+        let vx = if self.machine.quirks.jump_adds_vx {
+            self.machine.vx
+        } else {
+            0
+        };
+        let offset = self
+            .machine
+            .memory
+            .get_ro_slice(self.machine.memory.var_addr + vx, 1)?[0] as u16;
+        self.machine.program_counter = (self.machine.instruction_data & 0xfff) + offset;
+        if self.machine.instruction_data & 0xf00 != self.machine.program_counter & 0xf00 {
+            // crosses a page boundary
+            Ok(24)
+        } else {
+            Ok(22)
+        }
+    }
+
+    /// cxnn
+    fn inst_random(&mut self) -> Result<usize, Chip8Error> {
+        // increment seed
+        self.machine.random = self.machine.random.wrapping_add(1);
+
+        // address for random number
+        let rand_addr = 0x100 + (0xff & self.machine.random);
+
+        // fetch byte at rand address
+        let rand_val = self.machine.memory.get_ro_slice(rand_addr, 1)?[0];
+
+        // add to high-order byte of seed
+        let rand_val = ((self.machine.random >> 8) as u8).wrapping_add(rand_val);
+
+        // div by 2 and add to itself
+        let rand_val = (rand_val / 2).wrapping_add(rand_val);
+
+        // save in top byte of seed
+        self.machine.random = (self.machine.random & 0xff) + ((rand_val as u16) << 8);
+
+        // mask with nn and store in vx
+        self.machine.memory.write(
+            &[rand_val & (self.machine.instruction_data & 0xff) as u8],
+            self.machine.memory.var_addr + self.machine.vx,
+            1,
+        )?;
+
+        Ok(36)
+    }
+
+    /// dxyn
+    fn inst_draw_sprite(&mut self) -> Result<usize, Chip8Error> {
+        //
+        //  x_bit_offset
+        // -->|                       (work ram contents)
+        //    .xxxxx...  |            ....xxxx x.......
+        //    x.....x..  |            ...x.... .x......
+        //    x.x.x.x..  | rows  ==>  ...x.x.x .x......
+        //    x.....x..  v            ...x.... .x......
+        //    .x.x.x...  -            ....x.x. x.......
+        //
+        // bit offset from byte margin
+        let x_bit_offset = self
+            .machine
+            .memory
+            .get_ro_slice(self.machine.memory.var_addr + self.machine.vx, 1)?[0]
+            & 0x7;
+
+        // number of rows in the sprite. DXY0 has none, but still takes its
+        // time, waits for the interrupt and clears VF, as on the VIP
+        let rows = (self.machine.instruction_data & 0xf) as usize;
+        let parts = self.parts(rows);
+        let (first, last) = (parts.start == 0, parts.end == rows);
+
+        // data to draw (copied to a vec to avoid shenanigans with borrowing)
+        let sprite = self
+            .read_at_i(0, rows)
+            .map_err(|e| self.access_fault(rows, e))?;
+
+        // writable work area
+        let work = self
+            .machine
+            .memory
+            .get_rw_slice(self.machine.memory.work_addr, 32)?;
+
+        // write a correctly left-shifted version of the sprite into the work area
+        for idx in parts.clone() {
+            let byte = sprite[idx];
+            work[idx * 2] = byte >> x_bit_offset;
+            work[idx * 2 + 1] = if x_bit_offset == 0 {
+                0x0
+            } else {
+                byte << (8 - x_bit_offset)
+            };
+        }
+
+        // wait for the next display interrupt, unless showing what happens
+        // when sprites don't
+        if last {
+            self.machine.state = if self.machine.shear {
+                CycleState::Execute
+            } else {
+                CycleState::WaitInterrupt
+            };
+            self.instruction = Some(Chip8Interpreter::inst_draw_sprite_pt2);
+            self.machine.second_half = true;
+        }
+
+        // duration is the VIP's routine at 0070, up to its IDL:
+        //     50 to work out where the sprite's going
+        //   + 36 * rows to copy each row to the work area
+        //   + 20 * (rows * x_bit_offset) to shift each row into place
+        //   + 10 * rows to put I back where it was
+        //   + 18 to get there and wait for the interrupt
+        Ok(50 * first as usize
+            + (36 + 20 * x_bit_offset as usize) * parts.len()
+            + (18 + 10 * rows) * last as usize)
+    }
+
+    /// dxyn (after the interrupt)
+    fn inst_draw_sprite_pt2(&mut self) -> Result<usize, Chip8Error> {
+        // number of rows in the sprite
+        let rows = 0xf & self.machine.instruction_data as usize;
+        let parts = self.parts(rows);
+        let (first, last) = (parts.start == 0, parts.end == rows);
+        let mut dur = if first { 10 } else { 0 };
+
+        // display x and y coords (in bits) (again), wrapped to the screen
+        let (page_addr, width, height) = self.display_geometry();
+        let vx_val = (width - 1)
+            & self
+                .machine
+                .memory
+                .get_ro_slice(self.machine.memory.var_addr + self.machine.vx, 1)?[0]
+                as usize;
+        let vy_val = (height - 1)
+            & self
+                .machine
+                .memory
+                .get_ro_slice(self.machine.memory.var_addr + self.machine.vy, 1)?[0]
+                as usize;
+
+        // address to start drawing sprite in memory
+        let stride = width / 8;
+        let draw_addr = vx_val / 8 // x byte offset
+                      + vy_val * stride; // y byte offset
+
+        // readable work area
+        let work = self
+            .machine
+            .memory
+            .get_ro_slice(self.machine.memory.work_addr, rows * 2)?
+            .to_vec();
+
+        // writable vram
+        let vram = self
+            .machine
+            .memory
+            .get_rw_slice(page_addr, width * height / 8)?;
+
+        // collision flag (gets written to VF when done)
+        if first {
+            self.machine.collided = false;
+        }
+
+        // iterate thru pairs of bytes, looking for collisions and whether (for
+        // the right-hand byte) they can be displayed or not.
+        let bytes = work
+            .iter()
+            .enumerate()
+            .skip(parts.start * 2)
+            .take(parts.len() * 2);
+        for (idx, byte) in bytes {
+            let this_addr = draw_addr + (idx / 2) * stride + idx % 2;
+            if this_addr >= vram.len() {
+                // drawing off the bottom of the screen
+                continue;
+            }
+            if idx % 2 == 1 && this_addr.is_multiple_of(stride) {
+                // right-hand byte hangs off the edge of the screen
+                continue;
+            }
+            if (vram[this_addr] & *byte) != 0x0 {
+                self.machine.collided = true;
+                dur += 4;
+            }
+            vram[this_addr] ^= byte;
+            if let Some(h) = &mut self.heat {
+                h.xored(width, height, this_addr, *byte);
+            }
+            dur += if idx % 2 == 0 { 34 } else { 16 }
+        }
+
+        // the VIP stops as soon as a row reaches the bottom, without
+        // counting down the rest
+        if last {
+            dur += if rows > 0 && vy_val + rows >= height {
+                12
+            } else {
+                16
+            };
+        }
+
+        // save the collision flag in VF
+        if last {
+            let collided = self.machine.collided as u8;
+            self.machine
+                .memory
+                .write(&[collided], self.machine.memory.var_addr + 0xf, 1)?;
+            if self.machine.collided {
+                self.collided();
+            }
+        }
+
+        // duration is the rest of the VIP's routine, after its IDL:
+        //    10 to set up
+        //  + 34 * rows on the screen for the left byte, and seeing if
+        //    there's a right one
+        //  + 16 * rows for the right byte, if it's not off the edge
+        //  + 4 for each byte that collides
+        //  + 16 to save VF and go (12 if the sprite reached the bottom)
+        Ok(dur)
+    }
+
+    /// ex9e
+    fn inst_skip_key_eq(&mut self) -> Result<usize, Chip8Error> {
+        let vx = self
+            .machine
+            .memory
+            .get_ro_slice(self.machine.memory.var_addr + self.machine.vx, 1)?[0];
+
+        if self.input.read_key()? == Some(vx) {
+            self.input.flush_keys()?;
+            self.machine.program_counter += 2;
+            Ok(18)
+        } else {
+            Ok(14)
+        }
+    }
+
+    /// exa1
+    fn inst_skip_key_ne(&mut self) -> Result<usize, Chip8Error> {
+        let vx = self
+            .machine
+            .memory
+            .get_ro_slice(self.machine.memory.var_addr + self.machine.vx, 1)?[0];
+
+        if self.input.read_key()? != Some(vx) {
+            self.machine.program_counter += 2;
+            Ok(18)
+        } else {
+            self.input.flush_keys()?;
+            Ok(14)
+        }
+    }
+
+    /// fx07
+    fn inst_get_timer(&mut self) -> Result<usize, Chip8Error> {
+        self.machine.memory.write(
+            &[self.machine.timers.general],
+            self.machine.memory.var_addr + self.machine.vx,
+            1,
+        )?;
+        Ok(10)
+    }
+
+    /// fx0a
+    fn inst_wait_key(&mut self) -> Result<usize, Chip8Error> {
+        // the plan is to poll for a key after each interrupt, so that wait_key
+        // is interruptable. theoretical timings can therefore be much shorter
+        // than the COSMAC, although the user is likely slower anyway
+        self.machine.state = CycleState::WaitInterrupt;
+
+        if let Some(key) = self.input.read_key()? {
+            match self.machine.timers.tone {
+                1 => {
+                    self.machine.memory.write(
+                        &[key],
+                        self.machine.memory.var_addr + self.machine.vx,
+                        1,
+                    )?;
+                    self.input.flush_keys()?;
+                    self.machine.state = CycleState::FetchDecode;
+                }
+                2..=3 => {
+                    self.machine.timers.tone -= 1;
+                }
+                _ => {
+                    self.machine.timers.tone = 4;
+                }
+            }
+        }
+        Ok(1000) // dummy value
+    }
+
+    /// fx15
+    fn inst_set_timer(&mut self) -> Result<usize, Chip8Error> {
+        self.machine.timers.general = self
+            .machine
+            .memory
+            .get_ro_slice(self.machine.memory.var_addr + self.machine.vx, 1)?[0];
+        Ok(10)
+    }
+
+    /// fx18
+    fn inst_set_sound(&mut self) -> Result<usize, Chip8Error> {
+        self.machine.timers.tone = self
+            .machine
+            .memory
+            .get_ro_slice(self.machine.memory.var_addr + self.machine.vx, 1)?[0];
+        // started or stopped there and then, unless it's to wait for the
+        // frame. topping it up is still the same tone
+        let audible = self
+            .machine
+            .timers
+            .tone_audible(self.machine.quirks.short_tone);
+        if !self.machine.quirks.frame_tone && audible != self.buzzing {
+            self.buzz(audible)?;
+        }
+        Ok(10)
+    }
+
+    /// fx1e
+    fn inst_add_x_to_i(&mut self) -> Result<usize, Chip8Error> {
+        let vx = self
+            .machine
+            .memory
+            .get_ro_slice(self.machine.memory.var_addr + self.machine.vx, 1)?[0]
+            as u16;
+        let old_i = self.machine.i;
+        let i = old_i as usize + vx as usize;
+        if self.machine.quirks.add_i_sets_vf {
+            self.machine.memory.write(
+                &[(i > 0xfff) as u8],
+                self.machine.memory.var_addr + 0xf,
+                1,
+            )?;
+        }
+        match self.machine.quirks.mask_i {
+            true => self.machine.i = (i & 0xfff) as u16,
+            false => self.move_i(i),
+        }
+        // 12+4 or 18+4; from https://laurencescotford.com/chip-8-on-the-cosmac-vip-indexing-the-memory/
+        if (old_i & 0xff00) == (self.machine.i & 0xff00) {
+            Ok(16)
+        } else {
+            Ok(22)
+        }
+    }
+
+    /// fx29
+    fn inst_load_char(&mut self) -> Result<usize, Chip8Error> {
+        let ch = self
+            .machine
+            .memory
+            .get_ro_slice(self.machine.memory.var_addr + self.machine.vx, 1)?[0];
+        self.machine.i = self.font.small(&self.machine.memory, ch)?;
+        Ok(20)
+    }
+
+    /// fx30 (SCHIP). the VIP has no big font, so it's illegal there
+    fn inst_load_big_char(&mut self) -> Result<usize, Chip8Error> {
+        let ch = self
+            .machine
+            .memory
+            .get_ro_slice(self.machine.memory.var_addr + self.machine.vx, 1)?[0];
+        match self.font.big(&self.machine.memory, ch)? {
+            Some(addr) => {
+                self.machine.i = addr;
+                Ok(20)
+            }
+            None => Err(Chip8Error::IllegalInstruction {
+                addr: self.machine.program_counter - 2,
+                inst: self.machine.instruction_data,
+            }),
+        }
+    }
+
+    /// fx33
+    fn inst_x_to_bcd(&mut self) -> Result<usize, Chip8Error> {
+        let input = self
+            .machine
+            .memory
+            .get_ro_slice(self.machine.memory.var_addr + self.machine.vx, 1)?[0];
+        let output = [input / 100, (input % 100) / 10, (input % 100) % 10];
+        self.write_at_i(0, &output)
+            .map_err(|e| self.access_fault(output.len(), e))?;
+        Ok(84 + 16 * ((output[0] + output[1] + output[2]) as usize))
+    }
+
+    /// fx55
+    fn inst_save_v_at_i(&mut self) -> Result<usize, Chip8Error> {
+        let count = 1 + self.machine.vx as usize;
+        let parts = self.parts(count);
+        let v = self
+            .machine
+            .memory
+            .get_ro_slice(
+                self.machine.memory.var_addr + parts.start as u16,
+                parts.len(),
+            )?
+            .to_vec();
+        self.write_at_i(parts.start, &v)
+            .map_err(|e| self.access_fault(count, e))?;
+        Ok(self.finish_load_store(parts, count))
+    }
+
+    /// fx65
+    fn inst_load_v_at_i(&mut self) -> Result<usize, Chip8Error> {
+        let count = 1 + self.machine.vx as usize;
+        let parts = self.parts(count);
+        let v = self
+            .read_at_i(parts.start, parts.len())
+            .map_err(|e| self.access_fault(count, e))?;
+        self.machine.memory.write(
+            &v,
+            self.machine.memory.var_addr + parts.start as u16,
+            v.len(),
+        )?;
+        Ok(self.finish_load_store(parts, count))
+    }
+
+    /// the end of FX55 and FX65, once parts of their count registers are
+    /// done, returning how long those parts took
+    fn finish_load_store(&mut self, parts: Range<usize>, count: usize) -> usize {
+        // i points at address after i+vx, unless it's meant to stay put,
+        // or stop one short
+        let last = parts.end == count;
+        let quirks = self.machine.quirks;
+        if last && !quirks.load_store_leaves_i {
+            let count = count - quirks.load_store_adds_x as usize;
+            self.move_i(self.machine.i as usize + count);
+        }
+        // 14 + 14 * x + 4
+        14 * (parts.start == 0) as usize + 14 * parts.len() + 4 * last as usize
+    }
+}
+
+/// state machine for fetch-decode-execute-interrupt. it's in the state before
+/// and during it's doing the thing. so think "fetch-ing", "ready to fetch", ...
+///
+/// |                  .-----------------------.
+/// |                  v                       |
+/// | .-------.    .----------------.     .---------.
+/// | | start |--->| fetch + decode |---->| execute |
+/// | `-------'    `----------------'     `---------'
+/// |                  ^                       |
+/// |                  |   .---------------.   |
+/// |                  `---| interruptable |<--'
+/// |                      `---------------'
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "full", derive(Serialize, Deserialize))]
+enum CycleState {
+    FetchDecode,
+    Execute,
+    WaitInterrupt, // waiting for an interrupt
+    Exited,        // the program's stopped for good
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::costs::Costs;
+    use crate::display::fixtures::Frames;
+    use crate::heat::Heat;
+    use std::cell::Cell;
+    use std::error::Error;
+
+    fn test_with(
+        f: fn(i: &mut Chip8Interpreter) -> Result<(), Box<dyn Error>>,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut display = display::DummyDisplay::new()?;
+        let mut input = input::DummyInput::new(&[0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f]);
+        let mut sound = sound::Mute::new();
+        let mut i = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+        let mut prog: &[u8] = &[0x00, 0xe0, 0xa2, 0x2a, 0x60, 0x0c];
+        i.load_program(&mut prog)?;
+        f(&mut i)
+    }
+
+    #[test]
+    fn test_program_load_ok() -> Result<(), Box<dyn Error>> {
+        test_with(|_i| Ok(()))
+    }
+
+    #[test]
+    fn test_fetch_and_decode_moves_pc() -> Result<(), Box<dyn Error>> {
+        test_with(|i| {
+            let _ = i.fetch_and_decode()?;
+            assert_eq!(i.machine.program_counter, 0x202);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_fetch_and_decode_sets_state() -> Result<(), Box<dyn Error>> {
+        test_with(|i| {
+            let _ = i.fetch_and_decode()?;
+            assert!(i.machine.state == CycleState::Execute);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_fetch_and_decode_zero_inst_duration() -> Result<(), Box<dyn Error>> {
+        // 0xxx instructions take 40 machine cycles on the original chip-8
+        // the first test fixture instruction is 00e0
+        test_with(|i| {
+            assert_eq!(i.fetch_and_decode()?, 40);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_fetch_and_decode_other_inst_duration() -> Result<(), Box<dyn Error>> {
+        // other instructions take 68 machine cycles
+        // the second test fixture instruction is axxx
+        test_with(|i| {
+            let _ = i.fetch_and_decode()?;
+            assert_eq!(i.fetch_and_decode()?, 68);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_fetch_and_decode_sets_vx() -> Result<(), Box<dyn Error>> {
+        test_with(|i| {
+            // second test fixture instruction is a22a
+            let _ = i.fetch_and_decode()?;
+            let _ = i.fetch_and_decode()?;
+            assert_eq!(i.machine.vx, 0x02);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_fetch_and_decode_sets_vy() -> Result<(), Box<dyn Error>> {
+        test_with(|i| {
+            // first test fixture instruction is 0e00
+            let _ = i.fetch_and_decode()?;
+            assert_eq!(i.machine.vy, 0x0e);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_fetch_and_decode_illegal() -> Result<(), Box<dyn Error>> {
+        test_with(|i| {
+            let mut m: &[u8] = &[0x80, 0x08];
+            i.load_program(&mut m)?;
+            assert!(matches!(
+                i.fetch_and_decode(),
+                Err(Chip8Error::IllegalInstruction {
+                    addr: 0x200,
+                    inst: 0x8008
+                })
+            ));
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_run_off_the_end() -> Result<(), Box<dyn Error>> {
+        test_with(|i| {
+            // the last byte gets padded to 6000, then there's nothing
+            let mut m: &[u8] = &[0x61, 0x01, 0x62, 0x02, 0x63, 0x03, 0x60];
+            i.load_program(&mut m)?;
+            for _ in 0..4 {
+                i.step()?;
+            }
+            assert!(matches!(
+                i.step(),
+                Err(Chip8Error::IllegalInstruction {
+                    addr: 0x208,
+                    inst: 0x0000
+                })
+            ));
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_machine_code() -> Result<(), Box<dyn Error>> {
+        test_with(|i| {
+            // 0300; loop forever
+            // 0300: LDI 42; SEX R6; STXD (v3 = 42); GLO RA; ADI 1; PLO RA
+            //       (i += 1); SEP R4
+            let mut m: &[u8] = &[0x03, 0x00, 0x12, 0x02];
+            i.load_program(&mut m)?;
+            #[rustfmt::skip]
+            let code = [0xf8, 0x42, 0xe6, 0x73, 0x8a, 0xfc, 0x01, 0xaa, 0xd4];
+            i.machine.memory.write(&code, 0x300, code.len())?;
+            i.machine.i = 0x2fe;
+
+            let _ = i.fetch_and_decode()?;
+            assert_eq!(i.machine.vx, 3);
+            let t = i.call()?;
+            assert_eq!(i.v(3), 0x42);
+            assert_eq!(i.machine.i, 0x2ff);
+            assert_eq!(i.machine.program_counter, 0x202);
+            assert_eq!(t, 7 * 2);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_call_ok() -> Result<(), Box<dyn Error>> {
+        test_with(|i| {
+            let _ = i.fetch_and_decode()?;
+            assert_eq!(i.call()?, 24); // cycles for 0e00
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_clear_screen() -> Result<(), Box<dyn Error>> {
+        // 0e00
+        test_with(|i| {
+            // fill display memory with 1s
+            let m: &[u8] = &[1; 256];
+            i.machine.memory.write(m, 0xf00, 0x100)?;
+
+            // call 0e00
+            let _ = i.fetch_and_decode()?;
+            let t = i.inst_clear_screen()?;
+
+            assert_eq!(i.machine.memory.get_ro_slice(0xf00, 0x100)?, &[0; 256]);
+            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-machine-code-integration/
+            // takes 24 cycles
+            assert_eq!(t, 24);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_branch() -> Result<(), Box<dyn Error>> {
+        test_with(|i| {
+            let mut m: &[u8] = &[0x12, 0x34];
+            i.load_program(&mut m)?;
+
+            // call 1234
+            let _ = i.fetch_and_decode()?;
+            let t = i.inst_branch()?;
+
+            assert_eq!(i.machine.program_counter, 0x234);
+            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-branch-and-call-instructions/
+            // takes 12 cycles
+            assert_eq!(t, 12);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_subroutine() -> Result<(), Box<dyn Error>> {
+        test_with(|i| {
+            let mut m: &[u8] = &[0x23, 0x45];
+            i.load_program(&mut m)?;
+
+            // call 2345
+            let _ = i.fetch_and_decode()?;
+            let t = i.inst_subroutine()?;
+
+            assert_eq!(i.machine.memory.get_ro_slice(0xece, 2)?, &[0x02, 0x02]);
+            assert_eq!(i.machine.stack_pointer, 0xecc);
+            assert_eq!(i.machine.program_counter, 0x345);
+            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-branch-and-call-instructions/
+            // takes 26 cycles
+            assert_eq!(t, 26);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_ret() -> Result<(), Box<dyn Error>> {
+        test_with(|i| {
+            let mut m: &[u8] = &[0x22, 0x04, 0x00, 0xe0, 0x00, 0xee];
+            i.load_program(&mut m)?;
+
+            // call 2345
+            let _ = i.fetch_and_decode()?;
+            let _ = i.call()?;
+            let _ = i.fetch_and_decode()?;
+            let t = i.inst_ret()?;
+
+            assert_eq!(i.machine.memory.get_ro_slice(0xece, 2)?, &[0x02, 0x02]);
+            assert_eq!(i.machine.stack_pointer, 0xece);
+            assert_eq!(i.machine.program_counter, 0x202);
+            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-branch-and-call-instructions/
+            // takes 10 cycles
+            assert_eq!(t, 10);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_skip_vx_eq_ok() -> Result<(), Box<dyn Error>> {
+        test_with(|i| {
+            let mut m: &[u8] = &[0x34, 0x56];
+            i.load_program(&mut m)?;
+            i.machine.memory.write(&[0x56], 0xef4, 1)?;
+
+            // call 3456
+            let _ = i.fetch_and_decode()?;
+            let t = i.inst_skip_vx_eq()?;
+
+            assert_eq!(i.machine.program_counter, 0x204);
+            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-skip-instructions/
+            // takes 14 cycles
+            assert_eq!(t, 14);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_skip_vx_eq_not() -> Result<(), Box<dyn Error>> {
+        test_with(|i| {
+            let mut m: &[u8] = &[0x34, 0x56];
+            i.load_program(&mut m)?;
+            i.machine.memory.write(&[0x57], 0xef4, 1)?;
+
+            // call 3456
+            let _ = i.fetch_and_decode()?;
+            let t = i.inst_skip_vx_eq()?;
+
+            assert_eq!(i.machine.program_counter, 0x202);
+            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-skip-instructions/
+            // takes 10 cycles
+            assert_eq!(t, 10);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_skip_vx_ne_ok() -> Result<(), Box<dyn Error>> {
+        test_with(|i| {
+            let mut m: &[u8] = &[0x44, 0x67];
+            i.load_program(&mut m)?;
+            i.machine.memory.write(&[0x56], 0xef4, 1)?;
+
+            // call 4467
+            let _ = i.fetch_and_decode()?;
+            let t = i.inst_skip_vx_ne()?;
+
+            assert_eq!(i.machine.program_counter, 0x204);
+            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-skip-instructions/
+            // takes 14 cycles
+            assert_eq!(t, 14);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_skip_vx_ne_not() -> Result<(), Box<dyn Error>> {
+        test_with(|i| {
+            let mut m: &[u8] = &[0x44, 0x67];
+            i.load_program(&mut m)?;
+            i.machine.memory.write(&[0x67], 0xef4, 1)?;
+
+            // call 4467
+            let _ = i.fetch_and_decode()?;
+            let t = i.inst_skip_vx_ne()?;
+
+            assert_eq!(i.machine.program_counter, 0x202);
+            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-skip-instructions/
+            // takes 10 cycles
+            assert_eq!(t, 10);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_skip_x_eq_y_ok() -> Result<(), Box<dyn Error>> {
+        test_with(|i| {
+            let mut m: &[u8] = &[0x54, 0x50];
+            i.load_program(&mut m)?;
+            i.machine.memory.write(&[0x56, 0x56], 0xef4, 2)?;
+
+            // call 5450
+            let _ = i.fetch_and_decode()?;
+            let t = i.inst_x_eq_y()?;
+
+            assert_eq!(i.machine.program_counter, 0x204);
+            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-skip-instructions/
+            // takes 18 cycles
+            assert_eq!(t, 18);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_skip_x_eq_y_not() -> Result<(), Box<dyn Error>> {
+        test_with(|i| {
+            let mut m: &[u8] = &[0x54, 0x50];
+            i.load_program(&mut m)?;
+            i.machine.memory.write(&[0x57, 0x56], 0xef4, 2)?;
+
+            // call 5450
+            let _ = i.fetch_and_decode()?;
+            let t = i.inst_x_eq_y()?;
+
+            assert_eq!(i.machine.program_counter, 0x202);
+            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-skip-instructions/
+            // takes 14 cycles
+            assert_eq!(t, 14);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_skip_x_ne_y_ok() -> Result<(), Box<dyn Error>> {
+        test_with(|i| {
+            let mut m: &[u8] = &[0x94, 0x50];
+            i.load_program(&mut m)?;
+            i.machine.memory.write(&[0x56, 0x57], 0xef4, 2)?;
+
+            // call 9450
+            let _ = i.fetch_and_decode()?;
+            let t = i.inst_x_ne_y()?;
+
+            assert_eq!(i.machine.program_counter, 0x204);
+            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-skip-instructions/
+            // takes 18 cycles
+            assert_eq!(t, 18);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_skip_x_ne_y_not() -> Result<(), Box<dyn Error>> {
+        test_with(|i| {
+            let mut m: &[u8] = &[0x94, 0x50];
+            i.load_program(&mut m)?;
+            i.machine.memory.write(&[0x67, 0x67], 0xef4, 2)?;
+
+            // call 9450
+            let _ = i.fetch_and_decode()?;
+            let t = i.inst_x_ne_y()?;
+
+            assert_eq!(i.machine.program_counter, 0x202);
+            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-skip-instructions/
+            // takes 14 cycles
+            assert_eq!(t, 14);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_load_vx() -> Result<(), Box<dyn Error>> {
+        test_with(|i| {
+            let mut m: &[u8] = &[0x61, 0x23];
+            i.load_program(&mut m)?;
+
+            // call 6123
+            let _ = i.fetch_and_decode()?;
+            let t = i.inst_load_vx()?;
+
+            assert_eq!(i.machine.vx, 1);
+            // 0xef0 is where vx variables are on 4k layout
+            assert_eq!(
+                i.machine.memory.get_ro_slice(0xef0, 16)?,
+                &[0, 0x23, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]
+            );
+
+            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-loading-and-saving-variables/
+            // takes 6 cycles
+            assert_eq!(t, 6);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_add_to_vx() -> Result<(), Box<dyn Error>> {
+        test_with(|i| {
+            let mut m: &[u8] = &[0x71, 0x99];
+            i.load_program(&mut m)?;
+
+            // call 7123
+            let _ = i.fetch_and_decode()?;
+            let t = i.inst_add_to_vx()?;
+
+            assert_eq!(i.machine.vx, 1);
+            // 0xef0 is where vx variables are on 4k layout
+            assert_eq!(
+                i.machine.memory.get_ro_slice(0xef0, 16)?,
+                &[0, 0x99, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]
+            );
+
+            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-arithmetic-and-logic-instructions/
+            // takes 10 cycles
+            assert_eq!(t, 10);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_add_to_vx_overrun() -> Result<(), Box<dyn Error>> {
+        test_with(|i| {
+            let mut m: &[u8] = &[0x61, 0x81, 0x71, 0x82];
+            i.load_program(&mut m)?;
+
+            // call 7123
+            let _ = i.fetch_and_decode()?;
+            let _ = i.inst_load_vx()?;
+            let _ = i.fetch_and_decode()?;
+            let _ = i.inst_add_to_vx()?;
+
+            // 0xef0 is where vx variables are on 4k layout
+            assert_eq!(
+                i.machine.memory.get_ro_slice(0xef0, 16)?,
+                &[0, 0x03, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]
+            );
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_load_x_with_y() -> Result<(), Box<dyn Error>> {
+        // 8xy0
+        test_with(|i| {
+            let mut m: &[u8] = &[0x81, 0x20];
+            i.load_program(&mut m)?;
+            i.machine.memory.write(&[0x11, 0x22], 0xef1, 2)?;
+
+            // call 8120
+            let _ = i.fetch_and_decode()?;
+            let t = i.inst_load_x_with_y()?;
+
+            assert_eq!(i.machine.memory.get_ro_slice(0xef1, 2)?, &[0x22, 0x22]);
+            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-loading-and-saving-variables/
+            // takes 12 cycles
+            assert_eq!(t, 12);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_x_or_with_y() -> Result<(), Box<dyn Error>> {
+        // 8xy1
+        test_with(|i| {
+            let mut m: &[u8] = &[0x81, 0x21];
+            i.load_program(&mut m)?;
+            i.machine.memory.write(&[0x2d, 0x4b], 0xef1, 2)?;
+
+            // call 8121
+            let _ = i.fetch_and_decode()?;
+            let t = i.inst_x_or_with_y()?;
+
+            assert_eq!(i.machine.memory.get_ro_slice(0xef1, 2)?, &[0x6f, 0x4b]);
+            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-loading-and-saving-variables/
+            // takes 44 cycles
+            assert_eq!(t, 44);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_x_and_with_y() -> Result<(), Box<dyn Error>> {
+        // 8xy2
+        test_with(|i| {
+            let mut m: &[u8] = &[0x81, 0x22];
+            i.load_program(&mut m)?;
+            i.machine.memory.write(&[0x2d, 0x4b], 0xef1, 2)?;
+
+            // call 8122
+            let _ = i.fetch_and_decode()?;
+            let t = i.inst_x_and_with_y()?;
+
+            assert_eq!(i.machine.memory.get_ro_slice(0xef1, 2)?, &[0x09, 0x4b]);
+            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-loading-and-saving-variables/
+            // takes 44 cycles
+            assert_eq!(t, 44);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_x_xor_with_y() -> Result<(), Box<dyn Error>> {
+        // 8xy3
+        test_with(|i| {
+            let mut m: &[u8] = &[0x81, 0x23];
+            i.load_program(&mut m)?;
+            i.machine.memory.write(&[0x2d, 0x4b], 0xef1, 2)?;
+
+            // call 8123
+            let _ = i.fetch_and_decode()?;
+            let t = i.inst_x_xor_with_y()?;
+
+            assert_eq!(i.machine.memory.get_ro_slice(0xef1, 2)?, &[0x66, 0x4b]);
+            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-loading-and-saving-variables/
+            // takes 44 cycles
+            assert_eq!(t, 44);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_logic_leaves_vf_quirk() -> Result<(), Box<dyn Error>> {
+        // 8121, 8122 then 8123, with vf set before each
+        test_with(|i| {
+            let mut m: &[u8] = &[0x81, 0x21, 0x81, 0x22, 0x81, 0x23];
+            i.load_program(&mut m)?;
+            for (quirks, vf) in [(Quirks::VIP, 0x00), (Quirks::MODERN, 0x7f)] {
+                i.set_quirks(quirks);
+                i.set_pc(0x200);
+                for f in [
+                    Chip8Interpreter::inst_x_or_with_y,
+                    Chip8Interpreter::inst_x_and_with_y,
+                    Chip8Interpreter::inst_x_xor_with_y,
+                ] {
+                    i.machine.memory.write(&[0x7f], 0xeff, 1)?;
+                    let _ = i.fetch_and_decode()?;
+                    f(i)?;
+                    assert_eq!(i.machine.memory.get_ro_slice(0xeff, 1)?, &[vf]);
+                }
+            }
+
+            // and as vx, vf's reset after the result's in it
+            let mut m: &[u8] = &[0x8f, 0x21];
+            i.load_program(&mut m)?;
+            i.set_quirks(Quirks::VIP);
+            i.set_pc(0x200);
+            let _ = i.fetch_and_decode()?;
+            i.inst_x_or_with_y()?;
+            assert_eq!(i.machine.memory.get_ro_slice(0xeff, 1)?, &[0x00]);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_x_add_y() -> Result<(), Box<dyn Error>> {
+        // 8xy4
+        test_with(|i| {
+            let mut m: &[u8] = &[0x81, 0x24];
+            i.load_program(&mut m)?;
+            i.machine.memory.write(&[0x2d, 0x4b], 0xef1, 2)?;
+
+            // call 8124
+            let _ = i.fetch_and_decode()?;
+            let t = i.inst_x_add_y()?;
+
+            assert_eq!(i.machine.memory.get_ro_slice(0xef1, 2)?, &[0x78, 0x4b]);
+            assert_eq!(i.machine.memory.get_ro_slice(0xeff, 1)?, &[0x00]); // vf
+
+            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-loading-and-saving-variables/
+            // takes 44 cycles
+            assert_eq!(t, 44);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_x_add_y_carry() -> Result<(), Box<dyn Error>> {
+        // 8xy4
+        test_with(|i| {
+            let mut m: &[u8] = &[0x81, 0x24];
+            i.load_program(&mut m)?;
+            i.machine.memory.write(&[0xed, 0x4b], 0xef1, 2)?;
+
+            // call 8124
+            let _ = i.fetch_and_decode()?;
+            let t = i.inst_x_add_y()?;
+
+            assert_eq!(i.machine.memory.get_ro_slice(0xef1, 2)?, &[0x38, 0x4b]);
+            assert_eq!(i.machine.memory.get_ro_slice(0xeff, 1)?, &[0x01]); // vf
+
+            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-loading-and-saving-variables/
+            // takes 44 cycles
+            assert_eq!(t, 44);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_x_minus_y() -> Result<(), Box<dyn Error>> {
+        // 8xy5
+        test_with(|i| {
+            let mut m: &[u8] = &[0x81, 0x25];
+            i.load_program(&mut m)?;
+            i.machine.memory.write(&[0x4b, 0x2d], 0xef1, 2)?;
+
+            // call 8125
+            let _ = i.fetch_and_decode()?;
+            let t = i.inst_x_minus_y()?;
+
+            assert_eq!(i.machine.memory.get_ro_slice(0xef1, 2)?, &[0x1e, 0x2d]);
+            assert_eq!(i.machine.memory.get_ro_slice(0xeff, 1)?, &[0x01]); // vf
+
+            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-loading-and-saving-variables/
+            // takes 44 cycles
+            assert_eq!(t, 44);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_x_minus_y_borrow() -> Result<(), Box<dyn Error>> {
+        // 8xy5
+        test_with(|i| {
+            let mut m: &[u8] = &[0x81, 0x25];
+            i.load_program(&mut m)?;
+            i.machine.memory.write(&[0x2d, 0x4b], 0xef1, 2)?;
+
+            // call 8125
+            let _ = i.fetch_and_decode()?;
+            let t = i.inst_x_minus_y()?;
+
+            assert_eq!(i.machine.memory.get_ro_slice(0xef1, 2)?, &[0xe2, 0x4b]);
+            assert_eq!(i.machine.memory.get_ro_slice(0xeff, 1)?, &[0x00]); // vf
+
+            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-loading-and-saving-variables/
+            // takes 44 cycles
+            assert_eq!(t, 44);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_rshift_y_load_x_0lsb() -> Result<(), Box<dyn Error>> {
+        // 8xy6
+        test_with(|i| {
+            let mut m: &[u8] = &[0x81, 0x26];
+            i.load_program(&mut m)?;
+            i.machine.memory.write(&[0xff, 0x2c], 0xef1, 2)?;
+
+            // call 8126
+            let _ = i.fetch_and_decode()?;
+            let t = i.inst_rshift_y_load_x()?;
+
+            assert_eq!(i.machine.memory.get_ro_slice(0xef1, 2)?, &[0x16, 0x16]);
+            assert_eq!(i.machine.memory.get_ro_slice(0xeff, 1)?, &[0x00]); // vf
+
+            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-loading-and-saving-variables/
+            // takes 44 cycles
+            assert_eq!(t, 44);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_rshift_y_load_x_1lsb() -> Result<(), Box<dyn Error>> {
+        // 8xy6
+        test_with(|i| {
+            let mut m: &[u8] = &[0x81, 0x26];
+            i.load_program(&mut m)?;
+            i.machine.memory.write(&[0xff, 0x2d], 0xef1, 2)?;
+
+            // call 8126
+            let _ = i.fetch_and_decode()?;
+            let t = i.inst_rshift_y_load_x()?;
+
+            assert_eq!(i.machine.memory.get_ro_slice(0xef1, 2)?, &[0x16, 0x16]);
+            assert_eq!(i.machine.memory.get_ro_slice(0xeff, 1)?, &[0x01]); // vf
+
+            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-loading-and-saving-variables/
+            // takes 44 cycles
+            assert_eq!(t, 44);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_shift_vx_quirk() -> Result<(), Box<dyn Error>> {
+        // 8126 then 812e, shifting v1 in place and leaving v2 alone
+        test_with(|i| {
+            let mut m: &[u8] = &[0x81, 0x26, 0x81, 0x2e];
+            i.load_program(&mut m)?;
+            i.set_quirks(Quirks::MODERN);
+            i.machine.memory.write(&[0x2d, 0xff], 0xef1, 2)?;
+
+            let _ = i.fetch_and_decode()?;
+            i.inst_rshift_y_load_x()?;
+            assert_eq!(i.machine.memory.get_ro_slice(0xef1, 2)?, &[0x16, 0xff]);
+            assert_eq!(i.machine.memory.get_ro_slice(0xeff, 1)?, &[0x01]); // vf
+
+            let _ = i.fetch_and_decode()?;
+            i.inst_lshift_y_load_x()?;
+            assert_eq!(i.machine.memory.get_ro_slice(0xef1, 2)?, &[0x2c, 0xff]);
+            assert_eq!(i.machine.memory.get_ro_slice(0xeff, 1)?, &[0x00]); // vf
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_shift_into_vf() -> Result<(), Box<dyn Error>> {
+        // 8f16 then 8f1e: whichever way the shift's done, the flag's
+        // written last, so it's all that's left in vf
+        test_with(|i| {
+            let mut m: &[u8] = &[0x8f, 0x16, 0x8f, 0x1e];
+            i.load_program(&mut m)?;
+            for quirks in [Quirks::VIP, Quirks::MODERN] {
+                i.set_quirks(quirks);
+                i.set_pc(0x200);
+                i.machine.memory.write(&[0x81], 0xef1, 1)?; // v1
+                i.machine.memory.write(&[0x81], 0xeff, 1)?; // vf
+
+                let _ = i.fetch_and_decode()?;
+                i.inst_rshift_y_load_x()?;
+                assert_eq!(i.machine.memory.get_ro_slice(0xeff, 1)?, &[0x01]);
+
+                let _ = i.fetch_and_decode()?;
+                i.inst_lshift_y_load_x()?;
+                assert_eq!(i.machine.memory.get_ro_slice(0xeff, 1)?, &[0x00]);
+
+                // and only the VIP takes its input from vy, and writes it back
+                let v1 = i.machine.memory.get_ro_slice(0xef1, 1)?[0];
+                assert_eq!(v1, if quirks.shift_vx { 0x81 } else { 0x80 });
+            }
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_y_minus_x() -> Result<(), Box<dyn Error>> {
+        // 8xy7
+        test_with(|i| {
+            let mut m: &[u8] = &[0x81, 0x27];
+            i.load_program(&mut m)?;
+            i.machine.memory.write(&[0x2d, 0x4b], 0xef1, 2)?;
+
+            // call 8127
+            let _ = i.fetch_and_decode()?;
+            let t = i.inst_y_minus_x()?;
+
+            assert_eq!(i.machine.memory.get_ro_slice(0xef1, 2)?, &[0x1e, 0x4b]);
+            assert_eq!(i.machine.memory.get_ro_slice(0xeff, 1)?, &[0x01]); // vf
+
+            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-loading-and-saving-variables/
+            // takes 44 cycles
+            assert_eq!(t, 44);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_y_minus_x_borrow() -> Result<(), Box<dyn Error>> {
+        // 8xy7
+        test_with(|i| {
+            let mut m: &[u8] = &[0x81, 0x27];
+            i.load_program(&mut m)?;
+            i.machine.memory.write(&[0x4b, 0x2d], 0xef1, 2)?;
+
+            // call 8127
+            let _ = i.fetch_and_decode()?;
+            let t = i.inst_y_minus_x()?;
+
+            assert_eq!(i.machine.memory.get_ro_slice(0xef1, 2)?, &[0xe2, 0x2d]);
+            assert_eq!(i.machine.memory.get_ro_slice(0xeff, 1)?, &[0x00]); // vf
+
+            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-loading-and-saving-variables/
+            // takes 44 cycles
+            assert_eq!(t, 44);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_lshift_y_load_x_0msb() -> Result<(), Box<dyn Error>> {
+        // 8xye
+        test_with(|i| {
+            let mut m: &[u8] = &[0x81, 0x2e];
+            i.load_program(&mut m)?;
+            i.machine.memory.write(&[0xff, 0x2d], 0xef1, 2)?;
+
+            // call 812e
+            let _ = i.fetch_and_decode()?;
+            let t = i.inst_lshift_y_load_x()?;
+
+            assert_eq!(i.machine.memory.get_ro_slice(0xef1, 2)?, &[0x5a, 0x5a]);
+            assert_eq!(i.machine.memory.get_ro_slice(0xeff, 1)?, &[0x00]); // vf
+
+            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-loading-and-saving-variables/
+            // takes 44 cycles
+            assert_eq!(t, 44);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_lshift_y_load_x_1msb() -> Result<(), Box<dyn Error>> {
+        // 8xye
+        test_with(|i| {
+            let mut m: &[u8] = &[0x81, 0x2e];
+            i.load_program(&mut m)?;
+            i.machine.memory.write(&[0xff, 0xad], 0xef1, 2)?;
+
+            // call 812e
+            let _ = i.fetch_and_decode()?;
+            let t = i.inst_lshift_y_load_x()?;
+
+            assert_eq!(i.machine.memory.get_ro_slice(0xef1, 2)?, &[0x5a, 0x5a]);
+            assert_eq!(i.machine.memory.get_ro_slice(0xeff, 1)?, &[0x01]); // vf
+
+            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-loading-and-saving-variables/
+            // takes 44 cycles
+            assert_eq!(t, 44);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_set_i() -> Result<(), Box<dyn Error>> {
+        // annn
         test_with(|i| {
-            // fill display memory with 1s
-            let m: &[u8] = &[1; 256];
-            i.memory.write(&m, 0xf00, 0x100)?;
+            let mut m: &[u8] = &[0xa1, 0x23];
+            i.load_program(&mut m)?;
 
-            // call 0e00
+            // call a123
             let _ = i.fetch_and_decode()?;
-            let t = i.inst_clear_screen()?;
+            let t = i.inst_set_i()?;
 
-            assert_eq!(i.memory.get_ro_slice(0xf00, 0x100), &[0; 256]);
-            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-machine-code-integration/
-            // takes 24 cycles
+            assert_eq!(i.machine.i, 0x123);
+            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-loading-and-saving-variables/
+            // takes 12 cycles
+            assert_eq!(t, 12);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_jump_offset() -> Result<(), Box<dyn Error>> {
+        // bnnn
+        test_with(|i| {
+            let mut m: &[u8] = &[0xb1, 0x23];
+            i.load_program(&mut m)?;
+            i.machine.memory.write(&[0x40], 0xef0, 1)?;
+
+            // call b123
+            let _ = i.fetch_and_decode()?;
+            let t = i.inst_jump_with_offset()?;
+
+            assert_eq!(i.machine.program_counter, 0x163);
+            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-branch-and-call-instructions/
+            // takes 22 cycles within a page
+            assert_eq!(t, 22);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_jump_offset_across_pages() -> Result<(), Box<dyn Error>> {
+        // bnnn
+        test_with(|i| {
+            let mut m: &[u8] = &[0xb1, 0x23];
+            i.load_program(&mut m)?;
+            i.machine.memory.write(&[0xdd], 0xef0, 1)?;
+
+            // call b123
+            let _ = i.fetch_and_decode()?;
+            let t = i.inst_jump_with_offset()?;
+
+            assert_eq!(i.machine.program_counter, 0x200);
+            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-branch-and-call-instructions/
+            // takes 24 cycles across pages
             assert_eq!(t, 24);
             Ok(())
         })
     }
 
     #[test]
-    fn test_branch() -> Result<(), Box<dyn Error>> {
+    fn test_jump_adds_vx_quirk() -> Result<(), Box<dyn Error>> {
+        // b123, with v0 = 0x40 and v1 = 0x10
         test_with(|i| {
-            let mut m: &[u8] = &[0x12, 0x34];
+            let mut m: &[u8] = &[0xb1, 0x23];
             i.load_program(&mut m)?;
+            i.machine.memory.write(&[0x40, 0x10], 0xef0, 2)?;
+            for (quirks, pc) in [(Quirks::VIP, 0x163), (Quirks::CHIP48, 0x133)] {
+                i.set_quirks(quirks);
+                i.set_pc(0x200);
+                let _ = i.fetch_and_decode()?;
+                i.inst_jump_with_offset()?;
+                assert_eq!(i.machine.program_counter, pc);
+            }
+            Ok(())
+        })
+    }
 
-            // call 1234
+    #[test]
+    fn test_random_seed_inc_by_interrupt() -> Result<(), Box<dyn Error>> {
+        test_with(|i| {
+            i.machine.random = 0x1234;
+            i.interrupt(Interrupt::DisplayRefresh)?;
+            assert_eq!(i.machine.random, 0x1235);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_random_logic() -> Result<(), Box<dyn Error>> {
+        // cxnn
+        test_with(|i| {
+            let mut m: &[u8] = &[0xc2, 0x03];
+            i.load_program(&mut m)?;
+            i.machine.random = 0x0107;
+
+            // call c203
             let _ = i.fetch_and_decode()?;
-            let t = i.inst_branch()?;
+            let t = i.inst_random()?;
+
+            // mem[1 + 0x0107 & 0xff] == 0x56
+            // 56 + 01 == 57
+            // 57/2+57 == 82
+
+            assert_eq!(i.machine.random, 0x8208);
+            assert_eq!(i.machine.memory.get_ro_slice(0xef2, 1)?, &[0x02]);
+            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-generating-random-numbers/
+            // takes 36 cycles
+            assert_eq!(t, 36);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_dxyn_waits() -> Result<(), Box<dyn Error>> {
+        // dxyn
+        test_with(|i| {
+            let mut m: &[u8] = &[
+                0xa2, 0x06, 0x60, 0x04, 0xd0, 0x05, 0xf0, 0x78, 0x3c, 0x1e, 0x0f,
+            ];
+            i.load_program(&mut m)?;
+
+            // call d008
+            for _ in 0..6 {
+                i.cycle()?;
+            }
+            let t = i.inst_draw_sprite()?;
+
+            assert!(i.machine.state == CycleState::WaitInterrupt);
+            assert_eq!(i.machine.instruction_data, 0xd005);
+            //assert_eq!(i.instruction, Some(Chip8Interpreter::inst_draw_sprite_pt2));
+            //
+            // xxxx....      ....xxxx ........
+            // .xxxx...      .....xxx x.......
+            // ..xxxx..  ==> ......xx xx......
+            // ...xxxx.      .......x xxx.....
+            // ....xxxx      ........ xxxx....
+            assert_eq!(
+                i.machine.memory.get_ro_slice(0xed0, 32)?,
+                &[
+                    0x0f, 0x00, 0x07, 0x80, 0x03, 0xc0, 0x01, 0xe0, 0x00, 0xf0, 0x00, 0x00, 0x00,
+                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00
+                ]
+            );
+
+            // shifting each row 4 bits takes its time
+            assert_eq!(t, 698);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_dxyn_pt2() -> Result<(), Box<dyn Error>> {
+        // dxyn
+        test_with(|i| {
+            let mut m: &[u8] = &[
+                0xa2, 0x06, 0x60, 0x04, 0xd0, 0x05, 0xf0, 0x78, 0x3c, 0x1e, 0x0f,
+            ];
+            i.load_program(&mut m)?;
+
+            // write a colliding px into vram to test collision bit
+            i.machine.memory.write(&[0x08], 0xf20, 1)?;
+
+            // call d008
+            for _ in 0..7 {
+                i.cycle()?;
+            }
+            let t = i.inst_draw_sprite_pt2()?;
+
+            assert_eq!(
+                // 5 rows of vram across where the sprite should be
+                i.machine.memory.get_ro_slice(0xf20, 0x28)?,
+                &[
+                    0x07, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x07, 0x80, 0x00, 0x00, 0x00,
+                    0x00, 0x00, 0x00, 0x03, 0xc0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0xe0,
+                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xf0, 0x00, 0x00, 0x00, 0x00, 0x00,
+                    0x00
+                ]
+            );
+
+            // vf == 1
+            assert_eq!(i.machine.memory.get_ro_slice(0xeff, 1)?[0], 1);
+
+            assert_eq!(t, 280);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_dxy0() -> Result<(), Box<dyn Error>> {
+        // vf = 1; i = 0x208; d000; loop
+        test_with(|i| {
+            let mut m: &[u8] = &[0x6f, 0x01, 0xa2, 0x08, 0xd0, 0x00, 0x12, 0x06, 0xff, 0xff];
+            i.load_program(&mut m)?;
+            i.step()?;
+            i.step()?;
+            i.cycle()?;
+            // no rows, but the VIP still goes through the motions
+            let t = i.inst_draw_sprite()?;
+            assert!(i.machine.state == CycleState::WaitInterrupt);
+            assert_eq!(t, 68);
+
+            i.run_frames(1)?;
+            assert_eq!(i.v(0xf), 0);
+            assert!(i
+                .machine
+                .memory
+                .get_ro_slice(0xf00, 0x100)?
+                .iter()
+                .all(|b| *b == 0));
+            Ok(())
+        })
+    }
+
+    /// DXYN (v0, v1 and n rows of ff at 0300) run by the VIP's own
+    /// routine on the 1802, over a screen full of under: the cycles up to
+    /// its IDL, and after
+    fn vip_dxyn(x: u8, y: u8, n: u8, under: u8) -> Result<(usize, usize), Box<dyn Error>> {
+        let mut m = memory::Chip8MemoryMap::new()?;
+        m.write(&[0xd0, 0x10 | n], 0x200, 2)?;
+        m.write(&[x, y], 0xef0, 2)?;
+        m.write(&[0xff; 15], 0x300, 15)?;
+        m.write(&[under; 0x100], 0xf00, 0x100)?;
+        // as the interpreter leaves things for the routine: R5 on the
+        // instruction's second byte, R6 and R7 on VX and VY, I in RA and
+        // the display page in RB.1
+        let mut cpu = Cdp1802::new();
+        (cpu.p, cpu.x) = (3, 2);
+        cpu.r[2] = 0xecf;
+        cpu.r[3] = 0x070;
+        cpu.r[4] = 0x01b;
+        cpu.r[5] = 0x201;
+        cpu.r[6] = 0xef0;
+        cpu.r[7] = 0xef1;
+        cpu.r[0xa] = 0x300;
+        cpu.r[0xb] = 0xf00;
+        let mut before = 0;
+        while !cpu.idle {
+            before += cpu.step(&mut m, &mut NoIo)?;
+        }
+        // the interrupt comes, and goes
+        cpu.idle = false;
+        let mut after = 0;
+        while cpu.p != 4 {
+            after += cpu.step(&mut m, &mut NoIo)?;
+        }
+        Ok((before, after))
+    }
+
+    #[test]
+    fn test_dxyn_timing() -> Result<(), Box<dyn Error>> {
+        // x, y, rows, what's on the screen under the sprite, and the cycles
+        // before and after the interrupt
+        #[rustfmt::skip]
+        let cases = [
+            // lined up with a byte, so no shifting
+            (8, 4, 5, 0x00, 298, 276),
+            // shifted half a byte, as in test_dxyn_waits
+            (4, 0, 5, 0x00, 698, 276),
+            // as far as it shifts, and as tall as it gets
+            (15, 8, 15, 0x00, 2858, 776),
+            // colliding, both halves
+            (3, 2, 4, 0xff, 492, 258),
+            // the right byte's off the right-hand edge
+            (58, 6, 5, 0x00, 498, 196),
+            // the last row's the bottom one
+            (8, 27, 5, 0x00, 298, 272),
+            // and off the bottom, with the rest left out
+            (8, 30, 5, 0x00, 298, 122),
+            // DXY0 still goes through the motions
+            (0, 0, 0, 0x00, 68, 26),
+        ];
+        for (x, y, n, under, before, after) in cases {
+            let mut display = display::DummyDisplay::new()?;
+            let mut input = input::DummyInput::new(&[]);
+            let mut sound = sound::Mute::new();
+            let mut i = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+            i.load_program(&mut &[0xd0, 0x10 | n][..])?;
+            i.machine.memory.write(&[x, y], 0xef0, 2)?;
+            i.machine.memory.write(&[0xff; 15], 0x300, 15)?;
+            i.machine.memory.write(&[under; 0x100], 0xf00, 0x100)?;
+            i.machine.i = 0x300;
+            i.fetch_and_decode()?;
+            let ours = (i.inst_draw_sprite()?, i.inst_draw_sprite_pt2()?);
+            let case = format!("d01{:x} at ({}, {})", n, x, y);
+            assert_eq!(ours, (before, after), "{}", case);
+            assert_eq!(vip_dxyn(x, y, n, under)?, ours, "{} on the VIP", case);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_key_skip_eq_none() -> Result<(), Box<dyn Error>> {
+        // ex9e
+        test_with(|i| {
+            let mut m: &[u8] = &[0xe2, 0x9e];
+            i.load_program(&mut m)?;
+            i.machine.memory.write(&[0x0a], 0xef2, 1)?;
+            i.input.flush_keys()?;
+
+            // call e29e
+            let _ = i.fetch_and_decode()?;
+            let t = i.inst_skip_key_eq()?;
 
-            assert_eq!(i.program_counter, 0x234);
+            assert_eq!(i.machine.program_counter, 0x202);
             // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-branch-and-call-instructions/
-            // takes 12 cycles
-            assert_eq!(t, 12);
+            // takes 14 cycles
+            assert_eq!(t, 14);
             Ok(())
         })
     }
 
     #[test]
-    fn test_subroutine() -> Result<(), Box<dyn Error>> {
+    fn test_key_skip_eq_match() -> Result<(), Box<dyn Error>> {
+        // ex9e
         test_with(|i| {
-            let mut m: &[u8] = &[0x23, 0x45];
+            let mut m: &[u8] = &[0xe2, 0x9e];
             i.load_program(&mut m)?;
+            i.machine.memory.write(&[0x0f], 0xef2, 1)?;
 
-            // call 2345
+            // call e29e
             let _ = i.fetch_and_decode()?;
-            let t = i.inst_subroutine()?;
+            let t = i.inst_skip_key_eq()?;
 
-            assert_eq!(i.memory.get_ro_slice(0xece, 2), &[0x02, 0x02]);
-            assert_eq!(i.stack_pointer, 0xecc);
-            assert_eq!(i.program_counter, 0x345);
+            assert_eq!(i.machine.program_counter, 0x204);
+            assert_eq!(i.input.read_key()?, None);
             // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-branch-and-call-instructions/
-            // takes 26 cycles
-            assert_eq!(t, 26);
+            // takes 18 cycles
+            assert_eq!(t, 18);
             Ok(())
         })
     }
 
     #[test]
-    fn test_ret() -> Result<(), Box<dyn Error>> {
+    fn test_key_skip_eq_nomatch() -> Result<(), Box<dyn Error>> {
+        // ex9e
         test_with(|i| {
-            let mut m: &[u8] = &[0x22, 0x04, 0x00, 0xe0, 0x00, 0xee];
+            let mut m: &[u8] = &[0xe2, 0x9e];
             i.load_program(&mut m)?;
+            i.machine.memory.write(&[0x01], 0xef2, 1)?;
 
-            // call 2345
+            // call e29e
             let _ = i.fetch_and_decode()?;
-            let _ = i.call()?;
+            let t = i.inst_skip_key_eq()?;
+
+            assert_eq!(i.machine.program_counter, 0x202);
+            assert_ne!(i.input.read_key()?, None);
+            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-branch-and-call-instructions/
+            // takes 14 cycles
+            assert_eq!(t, 14);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_key_skip_ne_none() -> Result<(), Box<dyn Error>> {
+        // exa1
+        test_with(|i| {
+            let mut m: &[u8] = &[0xe2, 0xa1];
+            i.load_program(&mut m)?;
+            i.machine.memory.write(&[0x0a], 0xef2, 1)?;
+            i.input.flush_keys()?;
+
+            // call e2a1
             let _ = i.fetch_and_decode()?;
-            let t = i.inst_ret()?;
+            let t = i.inst_skip_key_ne()?;
 
-            assert_eq!(i.memory.get_ro_slice(0xece, 2), &[0x02, 0x02]);
-            assert_eq!(i.stack_pointer, 0xece);
-            assert_eq!(i.program_counter, 0x202);
+            assert_eq!(i.machine.program_counter, 0x204);
             // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-branch-and-call-instructions/
-            // takes 10 cycles
-            assert_eq!(t, 10);
+            // takes 18 cycles
+            assert_eq!(t, 18);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_key_skip_ne_match() -> Result<(), Box<dyn Error>> {
+        // exa1
+        test_with(|i| {
+            let mut m: &[u8] = &[0xe2, 0xa1];
+            i.load_program(&mut m)?;
+            i.machine.memory.write(&[0x0f], 0xef2, 1)?;
+
+            // call e2a1
+            let _ = i.fetch_and_decode()?;
+            let t = i.inst_skip_key_ne()?;
+
+            assert_eq!(i.machine.program_counter, 0x202);
+            assert_eq!(i.input.read_key()?, None);
+            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-branch-and-call-instructions/
+            // takes 14 cycles
+            assert_eq!(t, 14);
             Ok(())
         })
     }
 
     #[test]
-    fn test_skip_vx_eq_ok() -> Result<(), Box<dyn Error>> {
+    fn test_key_skip_ne_nomatch() -> Result<(), Box<dyn Error>> {
+        // exa1
         test_with(|i| {
-            let mut m: &[u8] = &[0x34, 0x56];
+            let mut m: &[u8] = &[0xe2, 0xa1];
             i.load_program(&mut m)?;
-            i.memory.write(&[0x56], 0xef4, 1)?;
+            i.machine.memory.write(&[0x01], 0xef2, 1)?;
 
-            // call 3456
+            // call e2a1
             let _ = i.fetch_and_decode()?;
-            let t = i.inst_skip_vx_eq()?;
+            let t = i.inst_skip_key_ne()?;
 
-            assert_eq!(i.program_counter, 0x204);
-            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-skip-instructions/
-            // takes 14 cycles
-            assert_eq!(t, 14);
+            assert_eq!(i.machine.program_counter, 0x204);
+            assert_ne!(i.input.read_key()?, None);
+            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-branch-and-call-instructions/
+            // takes 18 cycles
+            assert_eq!(t, 18);
             Ok(())
         })
     }
 
     #[test]
-    fn test_skip_vx_eq_not() -> Result<(), Box<dyn Error>> {
+    fn test_get_timer() -> Result<(), Box<dyn Error>> {
+        // fx07
         test_with(|i| {
-            let mut m: &[u8] = &[0x34, 0x56];
+            let mut m: &[u8] = &[0xf0, 0x07];
             i.load_program(&mut m)?;
-            i.memory.write(&[0x57], 0xef4, 1)?;
+            i.machine.memory.write(&[0x80], 0xef0, 1)?;
+            i.machine.timers.general = 0x08;
 
-            // call 3456
+            // call fx07
             let _ = i.fetch_and_decode()?;
-            let t = i.inst_skip_vx_eq()?;
+            let t = i.inst_get_timer()?;
 
-            assert_eq!(i.program_counter, 0x202);
-            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-skip-instructions/
+            assert_eq!(i.machine.memory.get_ro_slice(0xef0, 1)?, &[0x08]);
+            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-branch-and-call-instructions/
             // takes 10 cycles
             assert_eq!(t, 10);
             Ok(())
@@ -1022,37 +3925,38 @@ mod tests {
     }
 
     #[test]
-    fn test_skip_vx_ne_ok() -> Result<(), Box<dyn Error>> {
+    fn test_wait_key() -> Result<(), Box<dyn Error>> {
+        // fx0a
         test_with(|i| {
-            let mut m: &[u8] = &[0x44, 0x67];
+            let mut m: &[u8] = &[0xf0, 0x0a];
             i.load_program(&mut m)?;
-            i.memory.write(&[0x56], 0xef4, 1)?;
-
-            // call 4467
+            i.machine.memory.write(&[0x80], 0xef0, 1)?;
+            i.machine.timers.tone = 1;
+            // call fx0a
             let _ = i.fetch_and_decode()?;
-            let t = i.inst_skip_vx_ne()?;
+            let _t = i.inst_wait_key()?;
 
-            assert_eq!(i.program_counter, 0x204);
-            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-skip-instructions/
-            // takes 14 cycles
-            assert_eq!(t, 14);
+            assert_eq!(i.machine.memory.get_ro_slice(0xef0, 1)?, &[0x0f]);
+            // see https://laurencescotford.com/chip-8-on-the-cosmac-vip-keyboard-input/
             Ok(())
         })
     }
 
     #[test]
-    fn test_skip_vx_ne_not() -> Result<(), Box<dyn Error>> {
+    fn test_set_tone() -> Result<(), Box<dyn Error>> {
+        // fx18
         test_with(|i| {
-            let mut m: &[u8] = &[0x44, 0x67];
+            let mut m: &[u8] = &[0xf0, 0x18];
             i.load_program(&mut m)?;
-            i.memory.write(&[0x67], 0xef4, 1)?;
+            i.machine.memory.write(&[0x80], 0xef0, 1)?;
+            i.machine.timers.tone = 0x08;
 
-            // call 4467
+            // call fx18
             let _ = i.fetch_and_decode()?;
-            let t = i.inst_skip_vx_ne()?;
+            let t = i.inst_set_sound()?;
 
-            assert_eq!(i.program_counter, 0x202);
-            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-skip-instructions/
+            assert_eq!(i.machine.timers.tone, 0x80);
+            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-sound/
             // takes 10 cycles
             assert_eq!(t, 10);
             Ok(())
@@ -1060,1007 +3964,1856 @@ mod tests {
     }
 
     #[test]
-    fn test_skip_x_eq_y_ok() -> Result<(), Box<dyn Error>> {
+    fn test_interrupt_decrements_tone_timer() -> Result<(), Box<dyn Error>> {
         test_with(|i| {
-            let mut m: &[u8] = &[0x54, 0x50];
-            i.load_program(&mut m)?;
-            i.memory.write(&[0x56, 0x56], 0xef4, 2)?;
+            i.machine.timers.tone = 0x08;
+            let t = i.interrupt(Interrupt::DisplayRefresh)?;
 
-            // call 5450
-            let _ = i.fetch_and_decode()?;
-            let t = i.inst_x_eq_y()?;
-
-            assert_eq!(i.program_counter, 0x204);
-            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-skip-instructions/
-            // takes 18 cycles
-            assert_eq!(t, 18);
+            assert_eq!(i.machine.timers.tone, 0x07);
+            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-branch-and-call-instructions/
+            // takes 811 + 1024 cycles
+            assert_eq!(t, 1835);
             Ok(())
         })
     }
 
     #[test]
-    fn test_skip_x_eq_y_not() -> Result<(), Box<dyn Error>> {
+    fn test_timing() -> Result<(), Box<dyn Error>> {
         test_with(|i| {
-            let mut m: &[u8] = &[0x54, 0x50];
-            i.load_program(&mut m)?;
-            i.memory.write(&[0x57, 0x56], 0xef4, 2)?;
-
-            // call 5450
-            let _ = i.fetch_and_decode()?;
-            let t = i.inst_x_eq_y()?;
-
-            assert_eq!(i.program_counter, 0x202);
-            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-skip-instructions/
-            // takes 14 cycles
-            assert_eq!(t, 14);
+            i.set_timing(Timing::preset("free+fetch-0nnn=30+fetch=60")?);
+            i.machine.timers.tone = 0x08;
+            // only the tone timer's 4 cycles are left
+            assert_eq!(i.interrupt(Interrupt::DisplayRefresh)?, 4);
+            // the test fixture's 00e0 then axxx
+            assert_eq!(i.fetch_and_decode()?, 30);
+            assert_eq!(i.fetch_and_decode()?, 60);
             Ok(())
         })
     }
 
     #[test]
-    fn test_skip_x_ne_y_ok() -> Result<(), Box<dyn Error>> {
+    fn test_cost_model() -> Result<(), Box<dyn Error>> {
+        let costs = Costs::preset("flat=10+DXYN=200")?;
+        let mut display = display::DummyDisplay::new()?;
+        let mut input = input::DummyInput::new(&[]);
+        let mut sound = sound::Mute::new();
+        let mut i = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+        i.set_cost_model(&costs);
+        i.load_program(&mut &[0x60, 0x12, 0xd0, 0x05, 0x70, 0x01][..])?;
+        // 6xnn's flat, whatever the VIP took
+        i.fetch_and_decode()?;
+        assert_eq!(i.cycle()?, 10);
+        // dxyn's charged as it finishes, after the interrupt
+        i.fetch_and_decode()?;
+        assert_eq!(i.cycle()?, 0);
+        assert_eq!(i.machine.state, CycleState::WaitInterrupt);
+        i.interrupt(Interrupt::DisplayRefresh)?;
+        assert_eq!(i.cycle()?, 200);
+        drop(i);
+
+        // a frame of counting in a loop gets further without the VIP's
+        // costs on top of the fetches
+        let count = |costs: &dyn CostModel| -> Result<u8, Chip8Error> {
+            let mut display = display::DummyDisplay::new()?;
+            let mut input = input::DummyInput::new(&[]);
+            let mut sound = sound::Mute::new();
+            let mut i = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+            i.set_cost_model(costs);
+            i.load_program(&mut &[0x70, 0x01, 0x12, 0x00][..])?;
+            i.run_frames(3)?;
+            Ok(i.v(0))
+        };
+        let (vip, flat) = (count(&VipCosts)?, count(&Costs::flat(0))?);
+        assert!(flat > vip, "{} vs {}", flat, vip);
+        // (68 + 10) + (68 + 12) a time, to 68 + 68, give or take the
+        // time left over
+        assert!((vip as usize * 158).abs_diff(flat as usize * 136) < 158);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cycle_history() -> Result<(), Box<dyn Error>> {
         test_with(|i| {
-            let mut m: &[u8] = &[0x94, 0x50];
+            // loop forever: all fetching and executing, besides the interrupt
+            let mut m: &[u8] = &[0x12, 0x00];
             i.load_program(&mut m)?;
-            i.memory.write(&[0x56, 0x57], 0xef4, 2)?;
+            i.run_frames(3)?;
+            let last = i.cycle_history()[display::HUD_SCOPE_FRAMES - 1];
+            assert!(last.interrupt >= 807 + 1024);
+            assert!(last.fetch > 0 && last.execute > 0);
+            assert_eq!(last.idle, 0);
+            // a frame's a frame's worth, give or take the instruction the
+            // interrupt landed after
+            assert!(last.total().abs_diff(i.frame_cycles() as u32) < 100);
+            Ok(())
+        })?;
 
-            // call 9450
-            let _ = i.fetch_and_decode()?;
-            let t = i.inst_x_ne_y()?;
+        // waiting for a key nobody presses is all idling
+        let mut display = display::DummyDisplay::new()?;
+        let mut input = input::DummyInput::new(&[]);
+        let mut sound = sound::Mute::new();
+        let mut i = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+        i.load_program(&mut &[0xf0, 0x0a][..])?;
+        i.run_frames(3)?;
+        let last = i.cycle_history()[display::HUD_SCOPE_FRAMES - 1];
+        assert_eq!((last.fetch, last.execute), (0, 0));
+        assert!(last.idle > last.interrupt);
+        Ok(())
+    }
 
-            assert_eq!(i.program_counter, 0x204);
-            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-skip-instructions/
-            // takes 18 cycles
-            assert_eq!(t, 18);
+    #[test]
+    fn test_timers_follow_emulated_time() -> Result<(), Box<dyn Error>> {
+        test_with(|i| {
+            // v0 = 60; general timer = v0; loop forever
+            let mut m: &[u8] = &[0x60, 0x3c, 0xf0, 0x15, 0x12, 0x04];
+            i.load_program(&mut m)?;
+            i.run_cycles(30 * CHIP8_FRAME_CYCLES)?;
+            // the timer gets set just after the first interrupt, so should
+            // have seen 29 or so since
+            assert!((30..=31).contains(&i.machine.timers.general));
             Ok(())
         })
     }
 
     #[test]
-    fn test_skip_x_ne_y_not() -> Result<(), Box<dyn Error>> {
+    fn test_tone_timer_source() -> Result<(), Box<dyn Error>> {
         test_with(|i| {
-            let mut m: &[u8] = &[0x94, 0x50];
+            // the timers at 50Hz, while the picture's still 60
+            let mut m: &[u8] = &[0x60, 0x3c, 0xf0, 0x15, 0x12, 0x04];
             i.load_program(&mut m)?;
-            i.memory.write(&[0x67, 0x67], 0xef4, 2)?;
-
-            // call 9450
-            let _ = i.fetch_and_decode()?;
-            let t = i.inst_x_ne_y()?;
-
-            assert_eq!(i.program_counter, 0x202);
-            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-skip-instructions/
-            // takes 14 cycles
-            assert_eq!(t, 14);
+            i.add_interrupt_source(&interrupt::ToneTimer {
+                rate: RefreshRate::PAL,
+            });
+            i.run_cycles(30 * CHIP8_FRAME_CYCLES)?;
+            assert!((35..=36).contains(&i.machine.timers.general));
+            assert!((30..=31).contains(&i.machine.frames));
+            // and the display's ISR leaves them alone
+            let general = i.machine.timers.general;
+            assert_eq!(i.interrupt(Interrupt::DisplayRefresh)?, 807 + 1024);
+            assert_eq!(i.machine.timers.general, general);
+
+            i.remove_interrupt_source(Interrupt::ToneTimer);
+            i.interrupt(Interrupt::DisplayRefresh)?;
+            assert_eq!(i.machine.timers.general, general - 1);
             Ok(())
         })
     }
 
     #[test]
-    fn test_load_vx() -> Result<(), Box<dyn Error>> {
+    fn test_raise_interrupt() -> Result<(), Box<dyn Error>> {
         test_with(|i| {
-            let mut m: &[u8] = &[0x61, 0x23];
+            // v0 = 60; general timer = v0; loop forever
+            let mut m: &[u8] = &[0x60, 0x3c, 0xf0, 0x15, 0x12, 0x04];
             i.load_program(&mut m)?;
-
-            // call 6123
-            let _ = i.fetch_and_decode()?;
-            let t = i.inst_load_vx()?;
-
-            assert_eq!(i.vx, 1);
-            // 0xef0 is where vx variables are on 4k layout
-            assert_eq!(
-                i.memory.get_ro_slice(0xef0, 16),
-                &[0, 0x23, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]
-            );
-
-            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-loading-and-saving-variables/
-            // takes 6 cycles
-            assert_eq!(t, 6);
+            i.remove_interrupt_source(Interrupt::DisplayRefresh);
+            i.run_cycles(10 * CHIP8_FRAME_CYCLES)?;
+            // no frames go by on their own
+            assert_eq!((i.machine.frames, i.machine.timers.general), (0, 60));
+            let cycles = i.machine.cycles;
+            i.raise_interrupt(Interrupt::DisplayRefresh)?;
+            assert_eq!((i.machine.frames, i.machine.timers.general), (1, 59));
+            assert!(i.machine.cycles >= cycles + 807 + 1024);
+            // and it's not going to happen again
+            assert_eq!(i.machine.interrupts.next_due(), None);
             Ok(())
         })
     }
 
     #[test]
-    fn test_add_to_vx() -> Result<(), Box<dyn Error>> {
+    fn test_host_vsync() -> Result<(), Box<dyn Error>> {
         test_with(|i| {
-            let mut m: &[u8] = &[0x71, 0x99];
+            let mut m: &[u8] = &[0x60, 0x3c, 0xf0, 0x15, 0x12, 0x04];
             i.load_program(&mut m)?;
-
-            // call 7123
-            let _ = i.fetch_and_decode()?;
-            let t = i.inst_add_to_vx()?;
-
-            assert_eq!(i.vx, 1);
-            // 0xef0 is where vx variables are on 4k layout
-            assert_eq!(
-                i.memory.get_ro_slice(0xef0, 16),
-                &[0, 0x99, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]
-            );
-
-            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-arithmetic-and-logic-instructions/
-            // takes 10 cycles
-            assert_eq!(t, 10);
+            i.set_host_vsync(true);
+            // the refresh rate only says how long a frame is now
+            i.set_refresh_rate(RefreshRate::PAL);
+            for _ in 0..3 {
+                i.on_vblank()?;
+            }
+            assert_eq!(i.machine.frames, 3);
+            // give or take the instruction each frame ran over by
+            assert!(i.machine.cycles.abs_diff(3 * 4402) < 3 * 100);
+            i.run_cycles(10 * CHIP8_FRAME_CYCLES)?;
+            assert_eq!(i.machine.frames, 3);
+
+            // and back to keeping time itself
+            i.set_host_vsync(false);
+            i.run_frames(3)?;
+            assert!((5..=7).contains(&i.machine.frames));
             Ok(())
         })
     }
 
     #[test]
-    fn test_add_to_vx_overrun() -> Result<(), Box<dyn Error>> {
+    fn test_cold_boot() -> Result<(), Box<dyn Error>> {
+        let mut display = display::DummyDisplay::new()?;
+        let mut input = input::DummyInput::new(&[]);
+        let mut sound = sound::Mute::new();
+        let mut i = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+        i.set_seed(0x1234);
+        i.power_on(Boot::Cold)?;
+        // v0 += 1; loop forever
+        i.load_program(&mut &[0x70, 0x01, 0x12, 0x02][..])?;
+        // the registers came up as something, and the rest of RAM too
+        assert!((0..16).any(|r| i.v(r) != 0));
+        let junk = i.memory().get_ro_slice(0x204, 0x100)?;
+        assert!(junk.iter().any(|b| *b != 0));
+        assert_eq!(i.machine.interrupts.next_due(), Some(1516));
+        // and it's the same from the same seed
+        let v = i
+            .machine
+            .memory
+            .get_ro_slice(i.machine.memory.var_addr, 16)?
+            .to_vec();
+        drop(i);
+        let mut i = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+        i.set_seed(0x1234);
+        i.power_on(Boot::Cold)?;
+        assert_eq!(
+            i.machine
+                .memory
+                .get_ro_slice(i.machine.memory.var_addr, 16)?,
+            v
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_pal_refresh_rate() -> Result<(), Box<dyn Error>> {
         test_with(|i| {
-            let mut m: &[u8] = &[0x61, 0x81, 0x71, 0x82];
+            // the same as above, but at 50Hz the same time's only 25 frames
+            let mut m: &[u8] = &[0x60, 0x3c, 0xf0, 0x15, 0x12, 0x04];
             i.load_program(&mut m)?;
+            i.set_refresh_rate(RefreshRate::PAL);
+            assert_eq!(i.frame_cycles(), 4402);
+            i.run_cycles(30 * CHIP8_FRAME_CYCLES)?;
+            assert!((35..=36).contains(&i.machine.timers.general));
+            // and run_frames goes by the frame, however long it is (give or
+            // take one landing right at the end)
+            let frames = i.machine.frames;
+            i.run_frames(50)?;
+            assert!((50..=51).contains(&(i.machine.frames - frames)));
+            Ok(())
+        })
+    }
 
-            // call 7123
-            let _ = i.fetch_and_decode()?;
-            let _ = i.inst_load_vx()?;
-            let _ = i.fetch_and_decode()?;
-            let _ = i.inst_add_to_vx()?;
-
-            // 0xef0 is where vx variables are on 4k layout
-            assert_eq!(
-                i.memory.get_ro_slice(0xef0, 16),
-                &[0, 0x03, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]
-            );
+    #[test]
+    fn test_clock_rate() -> Result<(), Box<dyn Error>> {
+        let clock = ManualClock::new();
+        let mut display = display::DummyDisplay::new()?;
+        let mut input = input::DummyInput::new(&[]);
+        let mut sound = sound::Mute::new();
+        let mut i = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+        i.set_clock(&clock);
+        // v0 += 1 for ever
+        i.load_program(&mut &[0x70, 0x01, 0x12, 0x00][..])?;
+        assert_eq!(i.frame_cycles(), 3668);
+        i.main_loop(10)?;
+        let (vip, vip_cycles) = (clock.now(), i.machine.cycles);
+
+        // twice as fast a clock gets twice as much done a frame, in the
+        // same time
+        i.set_clock_rate(ClockRate::parse("3.52128")?);
+        assert_eq!(i.clock_rate().mhz(), 3.52128);
+        i.main_loop(1)?;
+        assert_eq!(i.frame_cycles(), 7338);
+        let (start, cycles) = (clock.now(), i.machine.cycles);
+        i.main_loop(10)?;
+        let took = clock.now() - start;
+        assert!(took.abs_diff(vip) < vip / 50, "{:?} vs {:?}", took, vip);
+        let ran = i.machine.cycles - cycles;
+        assert!(
+            ran.abs_diff(2 * vip_cycles) < 3668,
+            "{} vs {}",
+            ran,
+            vip_cycles
+        );
+        Ok(())
+    }
 
+    #[test]
+    fn test_state() -> Result<(), Box<dyn Error>> {
+        let run = |rom: &[u8], f: fn(&mut Chip8Interpreter) -> Result<(), Chip8Error>| {
+            let mut display = display::DummyDisplay::new()?;
+            let mut input = input::DummyInput::new(&[]);
+            let mut sound = sound::Mute::new();
+            let mut i = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+            i.load_program(&mut &rom[..])?;
+            // errors are states too
+            let _ = f(&mut i);
+            Ok::<_, Chip8Error>(i.state())
+        };
+        // drawing, and half way through
+        let draw_wait = [0xd0, 0x05, 0xf0, 0x0a, 0x12, 0x04];
+        let state = run(&draw_wait, |_| Ok(()))?;
+        assert_eq!(state, InterpreterState::Running);
+        let state = run(&draw_wait, |i| {
+            i.cycle()?;
+            i.cycle()?;
             Ok(())
-        })
+        })?;
+        assert_eq!(state, InterpreterState::WaitingForVblank);
+        // then waiting for a key that never comes
+        let state = run(&draw_wait, |i| i.run_frames(3))?;
+        assert_eq!(state, InterpreterState::WaitingForKey);
+        // jumping to itself
+        let state = run(&[0x12, 0x00], |i| i.run_frames(1))?;
+        assert_eq!(state, InterpreterState::Halted);
+        // and something it doesn't know, which stays wrong
+        let state = run(&[0xff, 0xff], |i| i.run_frames(1))?;
+        assert!(matches!(state, InterpreterState::Faulted { .. }));
+        Ok(())
     }
 
     #[test]
-    fn test_load_x_with_y() -> Result<(), Box<dyn Error>> {
-        // 8xy0
-        test_with(|i| {
-            let mut m: &[u8] = &[0x81, 0x20];
-            i.load_program(&mut m)?;
-            i.memory.write(&[0x11, 0x22], 0xef1, 2)?;
+    fn test_checkpoint() -> Result<(), Box<dyn Error>> {
+        // count frames in V0, going wrong on the fifth
+        #[rustfmt::skip]
+        let rom = [
+            0x61, 0x01, 0xf1, 0x15, // delay := 1
+            0xf1, 0x07, 0x31, 0x00, 0x12, 0x04, // wait for it
+            0x70, 0x01, 0x30, 0x05, 0x12, 0x00, // v0 += 1, again unless it's 5
+            0xff, 0xff,
+        ];
+        let mut display = display::DummyDisplay::new()?;
+        let mut input = input::DummyInput::new(&[]);
+        let mut sound = sound::Mute::new();
+        let mut i = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+        i.load_program(&mut &rom[..])?;
+        assert!(i.run_frames(20).is_err());
+        assert!(i.checkpoint().is_none());
+        drop(i);
 
-            // call 8120
-            let _ = i.fetch_and_decode()?;
-            let t = i.inst_load_x_with_y()?;
+        let mut i = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+        i.set_checkpoints(true);
+        i.load_program(&mut &rom[..])?;
+        let e = i.run_frames(20).unwrap_err().to_string();
+        let faulted_at = i.frames();
+        let checkpoint = i.checkpoint().expect("a checkpoint").clone();
+        assert_eq!(checkpoint.frames, faulted_at - 2);
+        // and from there, it goes wrong the same way within a couple of frames
+        i.restore(checkpoint)?;
+        assert_eq!(i.v(0), 3);
+        assert_eq!(i.run_frames(3).unwrap_err().to_string(), e);
+        assert_eq!(i.frames(), faulted_at);
+        Ok(())
+    }
 
-            assert_eq!(i.memory.get_ro_slice(0xef1, 2), &[0x22, 0x22]);
-            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-loading-and-saving-variables/
-            // takes 12 cycles
-            assert_eq!(t, 12);
+    #[test]
+    fn test_key_watchdog() -> Result<(), Box<dyn Error>> {
+        let mut display = display::DummyDisplay::new()?;
+        let mut input = input::DummyInput::new(&[]);
+        let mut sound = sound::Mute::new();
+        let mut i = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+        i.load_program(&mut &[0x00, 0xe0, 0xf0, 0x0a, 0x12, 0x00][..])?;
+        // waiting's fine without the watchdog
+        i.run_frames(20)?;
+        i.set_key_watchdog(Some(10));
+        i.run_frames(10)?;
+        match i.run_frames(10) {
+            Err(Chip8Error::KeyDeadlock { addr, frames }) => {
+                assert_eq!((addr, frames), (0x202, 11));
+            }
+            r => panic!("{:?}", r),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_cancel() -> Result<(), Box<dyn Error>> {
+        let mut display = display::DummyDisplay::new()?;
+        let mut input = input::DummyInput::new(&[]);
+        let mut sound = sound::Mute::new();
+        let mut i = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+        // count up in V0 forever
+        i.load_program(&mut &[0x70, 0x01, 0x12, 0x00][..])?;
+        let token = CancelToken::new();
+        i.set_cancel_token(token.clone());
+        i.run_frames(1)?;
+
+        // a run that'd take days is stopped from elsewhere
+        let canceller = token.clone();
+        let handle = std::thread::spawn(move || {
+            std::thread::sleep(time::Duration::from_millis(50));
+            canceller.cancel();
+        });
+        let start = time::Instant::now();
+        assert!(matches!(
+            i.run_frames(u32::MAX as u64),
+            Err(Chip8Error::Cancelled)
+        ));
+        assert!(start.elapsed() < time::Duration::from_secs(5));
+        handle.join().expect("it cancels");
+        let (frames, v0) = (i.frames(), i.v(0));
+        assert!(matches!(i.step(), Err(Chip8Error::Cancelled)));
+        assert!(matches!(i.main_loop(1), Err(Chip8Error::Cancelled)));
+        assert_eq!((i.frames(), i.v(0)), (frames, v0));
+
+        // and carries on where it was once it's reset
+        token.reset();
+        i.run_frames(1)?;
+        assert_eq!(i.frames(), frames + 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_register_accessors() -> Result<(), Box<dyn Error>> {
+        test_with(|i| {
+            i.set_v(0x3, 0x42);
+            assert_eq!(i.v(0x3), 0x42);
+            assert_eq!(i.machine.memory.get_ro_slice(0xef3, 1)?, &[0x42]);
+            // only the bottom nybble picks the register
+            assert_eq!(i.v(0x13), 0x42);
+            i.set_i(0x300);
+            assert_eq!(i.i(), 0x300);
+            assert_eq!(i.pc(), 0x200);
+            assert!(i.stack()?.is_empty());
+            i.machine.timers.general = 7;
+            i.machine.timers.tone = 8;
+            assert_eq!((i.delay_timer(), i.sound_timer()), (7, 8));
             Ok(())
         })
     }
 
     #[test]
-    fn test_x_or_with_y() -> Result<(), Box<dyn Error>> {
-        // 8xy1
-        test_with(|i| {
-            let mut m: &[u8] = &[0x81, 0x21];
-            i.load_program(&mut m)?;
-            i.memory.write(&[0x2d, 0x4b], 0xef1, 2)?;
+    fn test_step_waiting_for_key() -> Result<(), Box<dyn Error>> {
+        // v0 = key; loop forever
+        let program: &[u8] = &[0xf0, 0x0a, 0x12, 0x02];
+        let mut display = display::DummyDisplay::new()?;
+        let mut input = input::DummyInput::new(&[]);
+        let mut sound = sound::Mute::new();
+        let mut i = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+        i.load_program(&mut &program[..])?;
+        // with no key coming, each step looks once and comes back
+        for _ in 0..3 {
+            i.step()?;
+            assert_eq!(i.state(), InterpreterState::WaitingForKey);
+        }
+        drop(i);
 
-            // call 8121
-            let _ = i.fetch_and_decode()?;
-            let t = i.inst_x_or_with_y()?;
+        // and with one, it's done after it's been let go
+        let mut input = input::DummyInput::new(&[7; 8]);
+        let mut i = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+        i.load_program(&mut &program[..])?;
+        i.step()?;
+        let mut steps = 1;
+        while i.state() == InterpreterState::WaitingForKey {
+            i.step()?;
+            steps += 1;
+            assert!(steps < 10, "still waiting after {} steps", steps);
+        }
+        assert_eq!(i.v(0), 7);
+        Ok(())
+    }
 
-            assert_eq!(i.memory.get_ro_slice(0xef1, 2), &[0x6f, 0x4b]);
-            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-loading-and-saving-variables/
-            // takes 44 cycles
-            assert_eq!(t, 44);
+    #[test]
+    fn test_step_waiting_for_vsync() -> Result<(), Box<dyn Error>> {
+        test_with(|i| {
+            // draw; loop forever, with the VIP waiting for the frame to draw
+            i.load_program(&mut &[0xd0, 0x01, 0x12, 0x02][..])?;
+            i.set_host_vsync(true);
+            // there's no frame till the host says, so the step gives up
+            i.step()?;
+            assert_eq!(i.state(), InterpreterState::WaitingForVblank);
+            // and once there is, it's drawn
+            i.on_vblank()?;
+            i.step()?;
+            assert_ne!(i.state(), InterpreterState::WaitingForVblank);
             Ok(())
         })
     }
 
     #[test]
-    fn test_x_and_with_y() -> Result<(), Box<dyn Error>> {
-        // 8xy2
+    fn test_stack_accessor() -> Result<(), Box<dyn Error>> {
         test_with(|i| {
-            let mut m: &[u8] = &[0x81, 0x22];
+            // call 0x204; call 0x208; loop forever
+            let mut m: &[u8] = &[0x22, 0x04, 0x00, 0x00, 0x22, 0x08, 0x00, 0x00, 0x12, 0x08];
             i.load_program(&mut m)?;
-            i.memory.write(&[0x2d, 0x4b], 0xef1, 2)?;
-
-            // call 8122
-            let _ = i.fetch_and_decode()?;
-            let t = i.inst_x_and_with_y()?;
-
-            assert_eq!(i.memory.get_ro_slice(0xef1, 2), &[0x09, 0x4b]);
-            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-loading-and-saving-variables/
-            // takes 44 cycles
-            assert_eq!(t, 44);
+            i.run_frames(1)?;
+            assert_eq!(i.stack()?, vec![0x202, 0x206]);
+            // and one that's gone wrong is an error, not a panic
+            i.machine.stack_pointer = i.machine.memory.stack_addr + 2;
+            assert!(i.stack().is_err());
             Ok(())
         })
     }
 
     #[test]
-    fn test_x_xor_with_y() -> Result<(), Box<dyn Error>> {
-        // 8xy3
+    fn test_watches_see_every_frame() -> Result<(), Box<dyn Error>> {
+        struct CountFrames(usize);
+        impl MemoryWatch for CountFrames {
+            fn frame(&mut self, _: &memory::Chip8MemoryMap) -> Result<Vec<String>, Chip8Error> {
+                self.0 += 1;
+                Ok(vec![])
+            }
+        }
+
+        let mut display = display::DummyDisplay::new()?;
+        let mut input = input::DummyInput::new(&[]);
+        let mut sound = sound::Mute::new();
+        let mut count = CountFrames(0);
+        let mut i = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+        i.add_watch(&mut count);
+        let mut m: &[u8] = &[0x12, 0x00];
+        i.load_program(&mut m)?;
+        i.run_frames(5)?;
+        drop(i);
+        assert_eq!(count.0, 5);
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_timer() -> Result<(), Box<dyn Error>> {
+        // fx15
         test_with(|i| {
-            let mut m: &[u8] = &[0x81, 0x23];
+            let mut m: &[u8] = &[0xf0, 0x15];
             i.load_program(&mut m)?;
-            i.memory.write(&[0x2d, 0x4b], 0xef1, 2)?;
+            i.machine.memory.write(&[0x80], 0xef0, 1)?;
+            i.machine.timers.general = 0x08;
 
-            // call 8123
+            // call fx15
             let _ = i.fetch_and_decode()?;
-            let t = i.inst_x_xor_with_y()?;
+            let t = i.inst_set_timer()?;
 
-            assert_eq!(i.memory.get_ro_slice(0xef1, 2), &[0x66, 0x4b]);
-            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-loading-and-saving-variables/
-            // takes 44 cycles
-            assert_eq!(t, 44);
+            assert_eq!(i.machine.timers.general, 0x80);
+            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-branch-and-call-instructions/
+            // takes 10 cycles
+            assert_eq!(t, 10);
             Ok(())
         })
     }
 
     #[test]
-    fn test_x_add_y() -> Result<(), Box<dyn Error>> {
-        // 8xy4
+    fn test_interrupt_decrements_timer() -> Result<(), Box<dyn Error>> {
         test_with(|i| {
-            let mut m: &[u8] = &[0x81, 0x24];
-            i.load_program(&mut m)?;
-            i.memory.write(&[0x2d, 0x4b], 0xef1, 2)?;
-
-            // call 8124
-            let _ = i.fetch_and_decode()?;
-            let t = i.inst_x_add_y()?;
-
-            assert_eq!(i.memory.get_ro_slice(0xef1, 2), &[0x78, 0x4b]);
-            assert_eq!(i.memory.get_ro_slice(0xeff, 1), &[0x00]); // vf
+            i.machine.timers.general = 0x08;
+            let t = i.interrupt(Interrupt::DisplayRefresh)?;
 
-            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-loading-and-saving-variables/
-            // takes 44 cycles
-            assert_eq!(t, 44);
+            assert_eq!(i.machine.timers.general, 0x07);
+            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-branch-and-call-instructions/
+            // takes 815 + 1024 cycles
+            assert_eq!(t, 1839);
             Ok(())
         })
     }
 
     #[test]
-    fn test_x_add_y_carry() -> Result<(), Box<dyn Error>> {
-        // 8xy4
+    fn test_add_x_to_i() -> Result<(), Box<dyn Error>> {
+        // fx1e
         test_with(|i| {
-            let mut m: &[u8] = &[0x81, 0x24];
+            let mut m: &[u8] = &[0xf0, 0x1e];
             i.load_program(&mut m)?;
-            i.memory.write(&[0xed, 0x4b], 0xef1, 2)?;
+            i.machine.memory.write(&[0x84], 0xef0, 1)?;
+            i.machine.i = 0x42;
 
-            // call 8124
+            // call fx1e
             let _ = i.fetch_and_decode()?;
-            let t = i.inst_x_add_y()?;
-
-            assert_eq!(i.memory.get_ro_slice(0xef1, 2), &[0x38, 0x4b]);
-            assert_eq!(i.memory.get_ro_slice(0xeff, 1), &[0x01]); // vf
+            let t = i.inst_add_x_to_i()?;
 
-            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-loading-and-saving-variables/
-            // takes 44 cycles
-            assert_eq!(t, 44);
+            assert_eq!(i.machine.i, 0xc6);
+            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-indexing-the-memory/
+            // takes 12+4 cycles
+            assert_eq!(t, 16);
             Ok(())
         })
     }
 
     #[test]
-    fn test_x_minus_y() -> Result<(), Box<dyn Error>> {
-        // 8xy5
+    fn test_add_x_to_i_with_carry() -> Result<(), Box<dyn Error>> {
+        // fx1e
         test_with(|i| {
-            let mut m: &[u8] = &[0x81, 0x25];
+            let mut m: &[u8] = &[0xf0, 0x1e];
             i.load_program(&mut m)?;
-            i.memory.write(&[0x4b, 0x2d], 0xef1, 2)?;
+            i.machine.memory.write(&[0x84], 0xef0, 1)?;
+            i.machine.i = 0x82;
 
-            // call 8125
+            // call fx1e
             let _ = i.fetch_and_decode()?;
-            let t = i.inst_x_minus_y()?;
-
-            assert_eq!(i.memory.get_ro_slice(0xef1, 2), &[0x1e, 0x2d]);
-            assert_eq!(i.memory.get_ro_slice(0xeff, 1), &[0x01]); // vf
+            let t = i.inst_add_x_to_i()?;
 
-            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-loading-and-saving-variables/
-            // takes 44 cycles
-            assert_eq!(t, 44);
+            assert_eq!(i.machine.i, 0x106);
+            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-indexing-the-memory/
+            // takes 18+4 cycles
+            assert_eq!(t, 22);
             Ok(())
         })
     }
 
-    #[test]
-    fn test_x_minus_y_borrow() -> Result<(), Box<dyn Error>> {
-        // 8xy5
-        test_with(|i| {
-            let mut m: &[u8] = &[0x81, 0x25];
-            i.load_program(&mut m)?;
-            i.memory.write(&[0x2d, 0x4b], 0xef1, 2)?;
-
-            // call 8125
-            let _ = i.fetch_and_decode()?;
-            let t = i.inst_x_minus_y()?;
-
-            assert_eq!(i.memory.get_ro_slice(0xef1, 2), &[0xe2, 0x4b]);
-            assert_eq!(i.memory.get_ro_slice(0xeff, 1), &[0x00]); // vf
+    /// v0-v3 = 1 2 3 4, then I pointed 2 bytes short of the end of memory,
+    /// for f to run these from there: save them; load them back; bcd of v3;
+    /// draw 3 rows; i += v3 twice
+    fn test_out_of_range_with(
+        out_of_range: OutOfRange,
+        f: fn(i: &mut Chip8Interpreter) -> Result<(), Box<dyn Error>>,
+    ) -> Result<(), Box<dyn Error>> {
+        #[rustfmt::skip]
+        let program = [
+            0x60, 0x01, 0x61, 0x02, 0x62, 0x03, 0x63, 0x04,
+            0xf3, 0x55, 0xf3, 0x65, 0xf3, 0x33, 0xd0, 0x03,
+            0xf3, 0x1e, 0xf3, 0x1e,
+        ];
+        let mut display = display::DummyDisplay::new()?;
+        let mut input = input::DummyInput::new(&[]);
+        let mut sound = sound::Mute::new();
+        let mut i = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+        i.set_quirks(Quirks {
+            load_store_leaves_i: true,
+            out_of_range,
+            ..Quirks::VIP
+        });
+        i.load_program(&mut &program[..])?;
+        for _ in 0..4 {
+            i.step()?;
+        }
+        i.machine.i = 0x81fe;
+        f(&mut i)
+    }
 
-            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-loading-and-saving-variables/
-            // takes 44 cycles
-            assert_eq!(t, 44);
+    #[test]
+    fn test_out_of_range_faults() -> Result<(), Box<dyn Error>> {
+        // nothing's there, so each of them faults rather than panicking
+        test_out_of_range_with(OutOfRange::Fault, |i| {
+            match i.step() {
+                Err(Chip8Error::AccessFault { reason, .. }) => match *reason {
+                    Chip8Error::MemoryFault { addr, len } => assert_eq!((addr, len), (0x81fe, 4)),
+                    r => panic!("{:?}", r),
+                },
+                r => panic!("{:?}", r),
+            }
+            for (pc, inst) in [(0x20a, "fx65"), (0x20c, "fx33"), (0x20e, "dxyn")] {
+                i.set_pc(pc);
+                assert!(
+                    matches!(i.step(), Err(Chip8Error::AccessFault { .. })),
+                    "{}",
+                    inst
+                );
+            }
+            // I can point off the end, so long as nothing's read from there
+            i.set_pc(0x210);
+            i.step()?;
+            i.step()?;
+            assert_eq!(i.i(), 0x8206);
+            // and it says where, even past the top of I
+            i.machine.i = 0xffff;
+            assert!(matches!(
+                i.read_at_i(2, 1),
+                Err(Chip8Error::MemoryFault {
+                    addr: 0x10001,
+                    len: 1
+                })
+            ));
             Ok(())
         })
     }
 
     #[test]
-    fn test_rshift_y_load_x_0lsb() -> Result<(), Box<dyn Error>> {
-        // 8xy6
-        test_with(|i| {
-            let mut m: &[u8] = &[0x81, 0x26];
-            i.load_program(&mut m)?;
-            i.memory.write(&[0xff, 0x2c], 0xef1, 2)?;
-
-            // call 8126
-            let _ = i.fetch_and_decode()?;
-            let t = i.inst_rshift_y_load_x()?;
+    fn test_out_of_range_wraps() -> Result<(), Box<dyn Error>> {
+        // round to the start of memory
+        test_out_of_range_with(OutOfRange::Wrap, |i| {
+            // the last of memory's the VIP's ROM, so it's read across the
+            // end, but only written past it
+            let rom = i.machine.memory.get_ro_slice(0x81fe, 2)?.to_vec();
+            i.set_pc(0x20a);
+            i.step()?;
+            assert_eq!(
+                [i.v(0), i.v(1), i.v(2), i.v(3)],
+                [rom[0], rom[1], 0x60, 0x01]
+            );
+            i.machine.i = 0x8201;
+            i.set_pc(0x208);
+            i.step()?;
+            assert_eq!(
+                i.machine.memory.get_ro_slice(0x201, 4)?,
+                &[rom[0], rom[1], 0x60, 0x01]
+            );
+            i.machine.i = 0x8200;
+            i.set_pc(0x20c);
+            i.step()?;
+            assert_eq!(i.machine.memory.get_ro_slice(0x200, 3)?, &[0, 0, 1]);
+            i.machine.i = 0x81ff;
+            i.set_pc(0x210);
+            i.step()?;
+            assert_eq!(i.i(), 0x200);
+            // I off the end by some other way wraps as soon as it's used
+            i.machine.i = 0x9201;
+            i.set_pc(0x20a);
+            i.step()?;
+            assert_eq!(i.i(), 0x201);
+            assert_eq!([i.v(0), i.v(1)], [0, 1]);
+            Ok(())
+        })
+    }
 
-            assert_eq!(i.memory.get_ro_slice(0xef1, 2), &[0x16, 0x16]);
-            assert_eq!(i.memory.get_ro_slice(0xeff, 1), &[0x00]); // vf
+    #[test]
+    fn test_out_of_range_grows() -> Result<(), Box<dyn Error>> {
+        // XO-CHIP's 64k, which wraps round at 16 bits
+        test_out_of_range_with(OutOfRange::Grow, |i| {
+            for _ in 0..4 {
+                i.step()?;
+            }
+            assert_eq!(i.machine.memory.size(), 0x10000);
+            assert_eq!(i.machine.memory.get_ro_slice(0x81fe, 4)?, &[0, 0, 4, 4]);
+            i.machine.i = 0xfffe;
+            i.step()?;
+            i.step()?;
+            assert_eq!(i.i(), 0x0006);
+            Ok(())
+        })
+    }
 
-            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-loading-and-saving-variables/
-            // takes 44 cycles
-            assert_eq!(t, 44);
+    #[test]
+    fn test_load_store_protected() -> Result<(), Box<dyn Error>> {
+        test_out_of_range_with(OutOfRange::Fault, |i| {
+            // running into the VIP's ROM: nothing's written, and it says why
+            i.machine.i = 0x7ffe;
+            let e = i.step().unwrap_err();
+            assert_eq!(
+                e.to_string(),
+                "f355 at 0208, with I = 7ffe, for 4 byte(s): 8000 is write-protected"
+            );
+            assert_eq!(i.machine.memory.get_ro_slice(0x7ffe, 2)?, &[0, 0]);
+            // but it can be read
+            i.machine.i = 0x8000;
+            i.step()?;
+            assert_eq!(i.v(0), i.machine.memory.get_ro_slice(0x8000, 1)?[0]);
+            // and so can anything protected on top
+            i.machine.memory.protect(0x300..0x303);
+            i.machine.i = 0x300;
+            let e = i.step().unwrap_err();
+            assert!(e.to_string().starts_with("f333 at 020c"), "{}", e);
+            assert!(e.to_string().ends_with("0300 is write-protected"), "{}", e);
             Ok(())
         })
     }
 
     #[test]
-    fn test_rshift_y_load_x_1lsb() -> Result<(), Box<dyn Error>> {
-        // 8xy6
+    fn test_add_x_to_i_overflow_quirks() -> Result<(), Box<dyn Error>> {
+        // fx1e, three times
         test_with(|i| {
-            let mut m: &[u8] = &[0x81, 0x26];
+            let mut m: &[u8] = &[0xf0, 0x1e, 0xf0, 0x1e, 0xf0, 0x1e];
             i.load_program(&mut m)?;
-            i.memory.write(&[0xff, 0x2d], 0xef1, 2)?;
-
-            // call 8126
-            let _ = i.fetch_and_decode()?;
-            let t = i.inst_rshift_y_load_x()?;
-
-            assert_eq!(i.memory.get_ro_slice(0xef1, 2), &[0x16, 0x16]);
-            assert_eq!(i.memory.get_ro_slice(0xeff, 1), &[0x01]); // vf
-
-            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-loading-and-saving-variables/
-            // takes 44 cycles
-            assert_eq!(t, 44);
+            i.set_v(0x0, 0x20);
+            i.set_v(0xf, 0xaa);
+            // the VIP's I goes past 0xfff, and leaves VF alone
+            i.machine.i = 0xff0;
+            i.step()?;
+            assert_eq!((i.machine.i, i.v(0xf)), (0x1010, 0xaa));
+
+            i.set_quirks(Quirks {
+                add_i_sets_vf: true,
+                mask_i: true,
+                ..Quirks::VIP
+            });
+            i.machine.i = 0xff0;
+            i.step()?;
+            assert_eq!((i.machine.i, i.v(0xf)), (0x010, 1));
+            i.step()?;
+            assert_eq!((i.machine.i, i.v(0xf)), (0x030, 0));
             Ok(())
         })
     }
 
     #[test]
-    fn test_y_minus_x() -> Result<(), Box<dyn Error>> {
-        // 8xy7
+    fn test_load_char() -> Result<(), Box<dyn Error>> {
+        // fx29
         test_with(|i| {
-            let mut m: &[u8] = &[0x81, 0x27];
+            let mut m: &[u8] = &[0xf2, 0x29];
             i.load_program(&mut m)?;
-            i.memory.write(&[0x2d, 0x4b], 0xef1, 2)?;
+            i.machine.memory.write(&[0x0e], 0xef2, 1)?;
 
-            // call 8127
+            // call f229
             let _ = i.fetch_and_decode()?;
-            let t = i.inst_y_minus_x()?;
+            let t = i.inst_load_char()?;
 
-            assert_eq!(i.memory.get_ro_slice(0xef1, 2), &[0x1e, 0x4b]);
-            assert_eq!(i.memory.get_ro_slice(0xeff, 1), &[0x01]); // vf
+            assert_eq!(i.machine.i, 0x8110);
 
-            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-loading-and-saving-variables/
-            // takes 44 cycles
-            assert_eq!(t, 44);
+            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-the-character-set/
+            // takes 18+4 cycles
+            assert_eq!(t, 20);
             Ok(())
         })
     }
 
     #[test]
-    fn test_y_minus_x_borrow() -> Result<(), Box<dyn Error>> {
-        // 8xy7
+    fn test_load_big_char() -> Result<(), Box<dyn Error>> {
+        // fx30
         test_with(|i| {
-            let mut m: &[u8] = &[0x81, 0x27];
+            let mut m: &[u8] = &[0xf2, 0x30, 0xf2, 0x30];
             i.load_program(&mut m)?;
-            i.memory.write(&[0x4b, 0x2d], 0xef1, 2)?;
+            i.machine.memory.write(&[0x0e], 0xef2, 1)?;
 
-            // call 8127
+            // the VIP has no big font
             let _ = i.fetch_and_decode()?;
-            let t = i.inst_y_minus_x()?;
-
-            assert_eq!(i.memory.get_ro_slice(0xef1, 2), &[0xe2, 0x2d]);
-            assert_eq!(i.memory.get_ro_slice(0xeff, 1), &[0x00]); // vf
-
-            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-loading-and-saving-variables/
-            // takes 44 cycles
-            assert_eq!(t, 44);
+            assert!(matches!(
+                i.inst_load_big_char(),
+                Err(Chip8Error::IllegalInstruction {
+                    addr: 0x200,
+                    inst: 0xf230
+                })
+            ));
+
+            i.set_font(&crate::font::SchipFont)?;
+            let _ = i.fetch_and_decode()?;
+            i.inst_load_big_char()?;
+            assert_eq!(i.machine.i, 0x1050 + 14 * 10);
             Ok(())
         })
     }
 
     #[test]
-    fn test_lshift_y_load_x_0msb() -> Result<(), Box<dyn Error>> {
-        // 8xye
+    fn test_x_to_bcd() -> Result<(), Box<dyn Error>> {
+        // fx33
         test_with(|i| {
-            let mut m: &[u8] = &[0x81, 0x2e];
+            let mut m: &[u8] = &[0xf2, 0x33];
             i.load_program(&mut m)?;
-            i.memory.write(&[0xff, 0x2d], 0xef1, 2)?;
+            i.machine.memory.write(&[0x7b], 0xef2, 1)?;
+            i.machine.i = 0x300;
 
-            // call 812e
+            // call f233
             let _ = i.fetch_and_decode()?;
-            let t = i.inst_lshift_y_load_x()?;
-
-            assert_eq!(i.memory.get_ro_slice(0xef1, 2), &[0x5a, 0x5a]);
-            assert_eq!(i.memory.get_ro_slice(0xeff, 1), &[0x00]); // vf
+            let t = i.inst_x_to_bcd()?;
 
-            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-loading-and-saving-variables/
-            // takes 44 cycles
-            assert_eq!(t, 44);
+            assert_eq!(i.machine.i, 0x300);
+            assert_eq!(i.machine.memory.get_ro_slice(i.machine.i, 3)?, &[1, 2, 3]);
+            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-binary-coded-decimal/
+            // takes 4 + 80 + (16 for each 1, 10, 100) cycles
+            assert_eq!(t, 180);
             Ok(())
         })
     }
 
     #[test]
-    fn test_lshift_y_load_x_1msb() -> Result<(), Box<dyn Error>> {
-        // 8xye
+    fn test_save_v_at_i() -> Result<(), Box<dyn Error>> {
+        // fx55
         test_with(|i| {
-            let mut m: &[u8] = &[0x81, 0x2e];
+            let mut m: &[u8] = &[0xff, 0x55];
             i.load_program(&mut m)?;
-            i.memory.write(&[0xff, 0xad], 0xef1, 2)?;
+            i.machine.memory.write(
+                &[
+                    0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b, 0x1c,
+                    0x1d, 0x1e, 0x1f,
+                ],
+                0xef0,
+                16,
+            )?;
+            i.machine.i = 0x300;
 
-            // call 812e
+            // call fx55
             let _ = i.fetch_and_decode()?;
-            let t = i.inst_lshift_y_load_x()?;
-
-            assert_eq!(i.memory.get_ro_slice(0xef1, 2), &[0x5a, 0x5a]);
-            assert_eq!(i.memory.get_ro_slice(0xeff, 1), &[0x01]); // vf
+            let t = i.inst_save_v_at_i()?;
 
+            assert_eq!(i.machine.i, 0x310);
+            assert_eq!(
+                i.machine.memory.get_ro_slice(0x300, 16)?,
+                &[
+                    0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b, 0x1c,
+                    0x1d, 0x1e, 0x1f
+                ]
+            );
             // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-loading-and-saving-variables/
-            // takes 44 cycles
-            assert_eq!(t, 44);
+            // takes 238 + 4 cycles for 16 registers
+            assert_eq!(t, 242);
             Ok(())
         })
     }
 
     #[test]
-    fn test_set_i() -> Result<(), Box<dyn Error>> {
-        // annn
+    fn test_load_store_leaves_i_quirk() -> Result<(), Box<dyn Error>> {
+        // f155 then f165
         test_with(|i| {
-            let mut m: &[u8] = &[0xa1, 0x23];
+            let mut m: &[u8] = &[0xf1, 0x55, 0xf1, 0x65];
             i.load_program(&mut m)?;
-
-            // call a123
+            i.set_quirks(Quirks::MODERN);
+            i.machine.i = 0x300;
             let _ = i.fetch_and_decode()?;
-            let t = i.inst_set_i()?;
-
-            assert_eq!(i.i, 0x123);
-            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-loading-and-saving-variables/
-            // takes 12 cycles
-            assert_eq!(t, 12);
+            i.inst_save_v_at_i()?;
+            assert_eq!(i.machine.i, 0x300);
+            let _ = i.fetch_and_decode()?;
+            i.inst_load_v_at_i()?;
+            assert_eq!(i.machine.i, 0x300);
             Ok(())
         })
     }
 
     #[test]
-    fn test_jump_offset() -> Result<(), Box<dyn Error>> {
-        // bnnn
+    fn test_load_store_adds_x_quirk() -> Result<(), Box<dyn Error>> {
+        // f155 then f165: CHIP-48's I ends up on v1's byte each time
         test_with(|i| {
-            let mut m: &[u8] = &[0xb1, 0x23];
+            let mut m: &[u8] = &[0xf1, 0x55, 0xf1, 0x65];
             i.load_program(&mut m)?;
-            i.memory.write(&[0x40], 0xef0, 1)?;
-
-            // call b123
+            i.set_quirks(Quirks::CHIP48);
+            i.machine.i = 0x300;
             let _ = i.fetch_and_decode()?;
-            let t = i.inst_jump_with_offset()?;
-
-            assert_eq!(i.program_counter, 0x163);
-            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-branch-and-call-instructions/
-            // takes 22 cycles within a page
-            assert_eq!(t, 22);
+            i.inst_save_v_at_i()?;
+            assert_eq!(i.machine.i, 0x301);
+            let _ = i.fetch_and_decode()?;
+            i.inst_load_v_at_i()?;
+            assert_eq!(i.machine.i, 0x302);
+
+            // leaving I alone wins, if both are asked for
+            i.set_quirks(Quirks {
+                load_store_leaves_i: true,
+                ..Quirks::CHIP48
+            });
+            i.set_pc(0x200);
+            let _ = i.fetch_and_decode()?;
+            i.inst_save_v_at_i()?;
+            assert_eq!(i.machine.i, 0x302);
             Ok(())
         })
     }
 
-    #[test]
-    fn test_jump_offset_across_pages() -> Result<(), Box<dyn Error>> {
-        // bnnn
-        test_with(|i| {
-            let mut m: &[u8] = &[0xb1, 0x23];
-            i.load_program(&mut m)?;
-            i.memory.write(&[0xdd], 0xef0, 1)?;
+    /// remembers when it was told to beep (true) and stop (false), by
+    /// how many frames had gone by
+    #[derive(Default)]
+    struct Buzzes {
+        frames: u32,
+        heard: Vec<(bool, u32)>,
+    }
 
-            // call b123
-            let _ = i.fetch_and_decode()?;
-            let t = i.inst_jump_with_offset()?;
+    impl sound::Sound for Buzzes {
+        fn beep(&mut self) -> Result<(), Chip8Error> {
+            self.heard.push((true, self.frames));
+            Ok(())
+        }
 
-            assert_eq!(i.program_counter, 0x200);
-            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-branch-and-call-instructions/
-            // takes 24 cycles across pages
-            assert_eq!(t, 24);
+        fn stop(&mut self) -> Result<(), Chip8Error> {
+            self.heard.push((false, self.frames));
             Ok(())
-        })
+        }
+
+        fn tick(&mut self) -> Result<(), Chip8Error> {
+            self.frames += 1;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_tone_timing() -> Result<(), Box<dyn Error>> {
+        // v0 = 5; tone = v0; stop
+        let five = [0x60, 0x05, 0xf0, 0x18, 0x12, 0x04];
+        // v0 = 5; tone = v0; v1 = 0; tone = v1; stop
+        let none = [0x60, 0x05, 0xf0, 0x18, 0x61, 0x00, 0xf1, 0x18, 0x12, 0x08];
+        // v0 = 5; tone = v0; tone = v0; stop
+        let topped_up = [0x60, 0x05, 0xf0, 0x18, 0xf0, 0x18, 0x12, 0x06];
+        let heard = |rom: &[u8], quirks: &str| -> Result<Vec<(bool, u32)>, Box<dyn Error>> {
+            let mut display = display::DummyDisplay::new()?;
+            let mut input = input::DummyInput::new(&[]);
+            let mut buzzes = Buzzes::default();
+            let mut i = Chip8Interpreter::new(&mut display, &mut input, &mut buzzes)?;
+            i.set_quirks(Quirks::profile(quirks)?);
+            i.load_program(&mut &rom[..])?;
+            i.run_frames(10)?;
+            drop(i);
+            Ok(buzzes.heard)
+        };
+        // straight away in the first frame (after its interrupt), and as
+        // soon as the timer runs out in the fifth interrupt after that
+        assert_eq!(heard(&five, "vip")?, [(true, 1), (false, 5)]);
+        // or for the five whole frames after the one it was set in
+        assert_eq!(heard(&five, "vip+frame-tone")?, [(true, 1), (false, 6)]);
+        // setting it to 0 stops it there and then, or it's never heard
+        assert_eq!(heard(&none, "vip")?, [(true, 1), (false, 1)]);
+        assert_eq!(heard(&none, "vip+frame-tone")?, []);
+        // topping it up is the one tone, not another
+        assert_eq!(heard(&topped_up, "vip")?, [(true, 1), (false, 5)]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_muted_tone() -> Result<(), Box<dyn Error>> {
+        // v0 = 5; tone = v0; stop
+        let rom = [0x60, 0x05, 0xf0, 0x18, 0x12, 0x04];
+        let mut display = display::DummyDisplay::new()?;
+        let mut input = input::DummyInput::new(&[]);
+        let mut buzzes = Buzzes::default();
+        let mut i = Chip8Interpreter::new(&mut display, &mut input, &mut buzzes)?;
+        i.load_program(&mut &rom[..])?;
+        // a device with no volume of its own isn't beeped while muted, and
+        // starts and stops as the mute does
+        i.change_volume(input::VolumeRequest::ToggleMute)?;
+        i.run_frames(2)?;
+        i.change_volume(input::VolumeRequest::ToggleMute)?;
+        i.run_frames(1)?;
+        i.change_volume(input::VolumeRequest::ToggleMute)?;
+        i.run_frames(7)?;
+        drop(i);
+        assert_eq!(buzzes.heard, [(true, 2), (false, 3), (false, 5)]);
+        Ok(())
     }
 
     #[test]
-    fn test_random_seed_inc_by_interrupt() -> Result<(), Box<dyn Error>> {
+    fn test_short_tone_quirk() -> Result<(), Box<dyn Error>> {
+        // v0 = 1; tone = v0; stop
+        let rom = [0x60, 0x01, 0xf0, 0x18, 0x12, 0x04];
+        for (quirks, heard) in [(Quirks::VIP, false), (Quirks::MODERN, true)] {
+            let mut display = display::DummyDisplay::new()?;
+            let mut input = input::DummyInput::new(&[]);
+            let mut sound = sound::ToneRecorder::new();
+            let mut i = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+            i.set_quirks(quirks);
+            i.load_program(&mut &rom[..])?;
+            i.run_frames(3)?;
+            drop(i);
+            assert_eq!(sound.samples().iter().any(|s| *s != 0), heard);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_step() -> Result<(), Box<dyn Error>> {
         test_with(|i| {
-            i.random = 0x1234;
-            i.interrupt()?;
-            assert_eq!(i.random, 0x1235);
+            i.step()?;
+            assert_eq!(i.pc(), 0x202);
+            i.step()?;
+            assert_eq!(i.i(), 0x22a);
             Ok(())
         })
     }
 
     #[test]
-    fn test_random_logic() -> Result<(), Box<dyn Error>> {
-        // cxnn
+    fn test_restore() -> Result<(), Box<dyn Error>> {
+        // i = glyph v1; draw it at (v1, v1); v1 += 1; again
+        #[rustfmt::skip]
+        let program: &[u8] = &[0xf1, 0x29, 0xd1, 0x15, 0x71, 0x01, 0x12, 0x00];
+        let (mut d1, mut d2) = (display::DummyDisplay, display::DummyDisplay);
+        let (mut i1, mut i2) = (input::DummyInput::new(&[]), input::DummyInput::new(&[]));
+        let (mut s1, mut s2) = (sound::Mute::new(), sound::Mute::new());
+        let mut a = Chip8Interpreter::new(&mut d1, &mut i1, &mut s1)?;
+        let mut b = Chip8Interpreter::new(&mut d2, &mut i2, &mut s2)?;
+        a.load_program(&mut &program[..])?;
+
+        // save part way through a draw
+        a.run_frames(3)?;
+        while !a.machine.second_half {
+            a.run_cycles(1)?;
+        }
+        b.restore(a.machine_state().clone())?;
+
+        a.run_frames(5)?;
+        b.run_frames(5)?;
+        assert_eq!(a.pc(), b.pc());
+        assert_eq!(a.v(1), b.v(1));
+        assert_eq!(a.frames(), b.frames());
+        assert_eq!(
+            a.memory().get_ro_slice(0xf00, 0x100)?,
+            b.memory().get_ro_slice(0xf00, 0x100)?
+        );
+
+        // and one that makes no sense is turned away, leaving b as it was
+        let pc = b.pc();
+        let mut bad = a.machine_state().clone();
+        bad.program_counter = 0xffff;
+        assert!(b.restore(bad).is_err());
+        let mut bad = a.machine_state().clone();
+        (bad.state, bad.second_half, bad.program_counter) = (CycleState::Execute, false, 0);
+        assert!(b.restore(bad).is_err());
+        let mut bad = a.machine_state().clone();
+        bad.stack_pointer = bad.memory.stack_addr + 2;
+        assert!(b.restore(bad).is_err());
+        let mut bad = a.machine_state().clone();
+        bad.memory.var_addr = 0xfffe;
+        assert!(b.restore(bad).is_err());
+        assert_eq!(b.pc(), pc);
+        b.run_frames(1)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_save_v_at_i() -> Result<(), Box<dyn Error>> {
+        // fx55, a register at a time
         test_with(|i| {
-            let mut m: &[u8] = &[0xc2, 0x03];
+            let mut m: &[u8] = &[0xf2, 0x55];
             i.load_program(&mut m)?;
-            i.random = 0x0107;
-
-            // call c203
-            let _ = i.fetch_and_decode()?;
-            let t = i.inst_random()?;
-
-            // mem[1 + 0x0107 & 0xff] == 0x56
-            // 56 + 01 == 57
-            // 57/2+57 == 82
-
-            assert_eq!(i.random, 0x8208);
-            assert_eq!(i.memory.get_ro_slice(0xef2, 1), &[0x02]);
-            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-generating-random-numbers/
-            // takes 36 cycles
-            assert_eq!(t, 36);
+            i.machine.memory.write(&[1, 2, 3], 0xef0, 3)?;
+            i.machine.i = 0x300;
+            i.set_split_instructions(true);
+
+            let mut t = i.cycle()?;
+            t += i.cycle()?;
+            assert_eq!(i.machine.memory.get_ro_slice(0x300, 3)?, [1, 0, 0]);
+            assert_eq!(i.machine.state, CycleState::Execute);
+            assert_eq!(i.machine.i, 0x300);
+
+            t += i.cycle()? + i.cycle()?;
+            assert_eq!(i.machine.memory.get_ro_slice(0x300, 3)?, [1, 2, 3]);
+            assert_eq!(i.machine.state, CycleState::FetchDecode);
+            assert_eq!(i.machine.i, 0x303);
+            // same as all in one go, plus the fetch
+            assert_eq!(t, 68 + 14 + 14 * 3 + 4);
             Ok(())
         })
     }
 
     #[test]
-    fn test_dxyn_waits() -> Result<(), Box<dyn Error>> {
-        // dxyn
+    fn test_split_instructions_draw_the_same() -> Result<(), Box<dyn Error>> {
+        // i = glyph v1; draw it at (v1 * 3, v1); save v0-vf; v1 += 1; again
+        #[rustfmt::skip]
+        let program: &[u8] = &[
+            0xf1, 0x29, 0xd1, 0x25, 0xa3, 0x00, 0xff, 0x55,
+            0x71, 0x01, 0x82, 0x14, 0x82, 0x14, 0x82, 0x14,
+            0x12, 0x00,
+        ];
+        let (mut d1, mut d2) = (display::DummyDisplay, display::DummyDisplay);
+        let (mut i1, mut i2) = (input::DummyInput::new(&[]), input::DummyInput::new(&[]));
+        let (mut s1, mut s2) = (sound::Mute::new(), sound::Mute::new());
+        let mut a = Chip8Interpreter::new(&mut d1, &mut i1, &mut s1)?;
+        let mut b = Chip8Interpreter::new(&mut d2, &mut i2, &mut s2)?;
+        b.set_split_instructions(true);
+        for m in [&mut a, &mut b] {
+            m.set_seed(0);
+            m.load_program(&mut &program[..])?;
+        }
+        // the same sprites get drawn either way, but a DXYN takes a good
+        // part of a frame on the VIP, so one that's split can have the
+        // interrupt come while it's still shifting its rows, and wait for
+        // the next. it's never ahead
+        let mut screens = [Vec::new(), Vec::new()];
+        for sprites in 1..=8 {
+            for (m, screen) in [&mut a, &mut b].into_iter().zip(&mut screens) {
+                while m.v(1) < sprites {
+                    m.run_frames(1)?;
+                }
+                *screen = m.memory().get_ro_slice(0xf00, 0x100)?.to_vec();
+            }
+            assert_eq!(screens[0], screens[1], "{} sprites", sprites);
+            assert!(b.frames() >= a.frames());
+        }
+        assert!(b.frames() < a.frames() + 8);
+        Ok(())
+    }
+
+    #[test]
+    fn test_shear() -> Result<(), Box<dyn Error>> {
+        let frames = Frames::default();
+        let mut display = frames.clone();
+        let mut input = input::DummyInput::new(&[]);
+        let mut sound = sound::Mute::new();
+        let mut i = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+        i.set_shear(true);
+        i.load_program(&mut &[0x12, 0x00][..])?;
+
+        // just after the ISR, the top of the frame's gone out, but not the
+        // bottom, so only a change at the bottom shows in this frame
+        i.run_cycles(1000)?;
+        i.machine.memory.write(&[0xff], 0xf00, 1)?;
+        i.machine.memory.write(&[0xff], 0xff8, 1)?;
+        i.run_frames(2)?;
+        let drawn = frames.drawn();
+        let [torn, whole] = &drawn[..] else {
+            panic!("drew {} frames", drawn.len());
+        };
+        assert_eq!((torn[0], torn[0xf8]), (0x00, 0xff));
+        assert_eq!((whole[0], whole[0xf8]), (0xff, 0xff));
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_v_at_i() -> Result<(), Box<dyn Error>> {
+        // fx65
         test_with(|i| {
-            let mut m: &[u8] = &[
-                0xa2, 0x06, 0x60, 0x04, 0xd0, 0x05, 0xf0, 0x78, 0x3c, 0x1e, 0x0f,
-            ];
+            let mut m: &[u8] = &[0xff, 0x65];
             i.load_program(&mut m)?;
+            i.machine.memory.write(
+                &[
+                    0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b, 0x1c,
+                    0x1d, 0x1e, 0x1f,
+                ],
+                0x300,
+                16,
+            )?;
+            i.machine.i = 0x300;
 
-            // call d008
-            for _ in 0..6 {
-                i.cycle()?;
-            }
-            let t = i.inst_draw_sprite()?;
+            // call fx65
+            let _ = i.fetch_and_decode()?;
+            let t = i.inst_load_v_at_i()?;
 
-            assert!(i.state == InterpreterState::WaitInterrupt);
-            assert_eq!(i.instruction_data, 0xd005);
-            //assert_eq!(i.instruction, Some(Chip8Interpreter::inst_draw_sprite_pt2));
-            //
-            // xxxx....      ....xxxx ........
-            // .xxxx...      .....xxx x.......
-            // ..xxxx..  ==> ......xx xx......
-            // ...xxxx.      .......x xxx.....
-            // ....xxxx      ........ xxxx....
+            assert_eq!(i.machine.i, 0x310);
             assert_eq!(
-                i.memory.get_ro_slice(0xed0, 32),
+                i.machine.memory.get_ro_slice(0xef0, 16)?,
                 &[
-                    0x0f, 0x00, 0x07, 0x80, 0x03, 0xc0, 0x01, 0xe0, 0x00, 0xf0, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00
+                    0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b, 0x1c,
+                    0x1d, 0x1e, 0x1f
                 ]
             );
-
-            assert_eq!(t, 261);
+            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-loading-and-saving-variables/
+            // takes 238 + 4 cycles for 16 registers
+            assert_eq!(t, 242);
             Ok(())
         })
     }
 
     #[test]
-    fn test_dxyn_pt2() -> Result<(), Box<dyn Error>> {
-        // dxyn
-        test_with(|i| {
-            let mut m: &[u8] = &[
-                0xa2, 0x06, 0x60, 0x04, 0xd0, 0x05, 0xf0, 0x78, 0x3c, 0x1e, 0x0f,
-            ];
-            i.load_program(&mut m)?;
-
-            // write a colliding px into vram to test collision bit
-            i.memory.write(&[0x08], 0xf20, 1)?;
-
-            // call d008
-            for _ in 0..7 {
-                i.cycle()?;
-            }
-            let t = i.inst_draw_sprite_pt2()?;
-
+    fn test_isa_table_matches_decoder() -> Result<(), Box<dyn Error>> {
+        let mut display = display::DummyDisplay::new()?;
+        let mut input = input::DummyInput::new(&[]);
+        let mut sound = sound::Mute::new();
+        let mut schip = crate::schip::Schip::new();
+        let mut i = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+        i.add_extension(&mut schip);
+        for inst in 0..=0xffff {
             assert_eq!(
-                // 5 rows of vram across where the sprite should be
-                i.memory.get_ro_slice(0xf20, 0x28),
-                &[
-                    0x07, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x07, 0x80, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x03, 0xc0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0xe0,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xf0, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00
-                ]
+                i.decode(0x200, inst).is_ok(),
+                crate::isa::lookup(inst).is_some(),
+                "{:04x}",
+                inst
             );
+        }
+        Ok(())
+    }
 
-            // vf == 1
-            assert_eq!(i.memory.get_ro_slice(0xeff, 1)[0], 1);
+    #[test]
+    fn test_manual_clock() -> Result<(), Box<dyn Error>> {
+        let clock = ManualClock::new();
+        let mut display = display::DummyDisplay::new()?;
+        let mut input = input::DummyInput::new(&[]);
+        let mut sound = sound::Mute::new();
+        let mut i = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+        i.set_clock(&clock);
+        i.load_program(&mut &[0x12, 0x00][..])?;
+        // no waiting, but the clock says it's been a second
+        assert_eq!(i.main_loop(60)?, RunOutcome::Finished);
+        let frame = time::Duration::from_nanos(CHIP8_CYCLE_NS * CHIP8_FRAME_CYCLES);
+        assert!(clock.now() >= 60 * frame);
+        assert!(clock.now() < 61 * frame);
+        Ok(())
+    }
 
-            assert_eq!(t, 139);
-            Ok(())
-        })
+    /// a ManualClock, counting how often it's looked at
+    struct CountingClock(ManualClock, Cell<usize>);
+
+    impl Clock for CountingClock {
+        fn now(&self) -> time::Duration {
+            self.1.set(self.1.get() + 1);
+            self.0.now()
+        }
+
+        fn sleep(&self, duration: time::Duration) {
+            self.0.sleep(duration)
+        }
     }
 
     #[test]
-    fn test_key_skip_eq_none() -> Result<(), Box<dyn Error>> {
-        // ex9e
-        test_with(|i| {
-            let mut m: &[u8] = &[0xe2, 0x9e];
-            i.load_program(&mut m)?;
-            i.memory.write(&[0x0a], 0xef2, 1)?;
-            i.input.flush_keys()?;
+    fn test_clock_batches() -> Result<(), Box<dyn Error>> {
+        let clock = CountingClock(ManualClock::new(), Cell::new(0));
+        let mut display = display::DummyDisplay::new()?;
+        let mut input = input::DummyInput::new(&[]);
+        let mut sound = sound::Mute::new();
+        let mut i = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+        i.set_clock(&clock);
+        // a tight loop of short instructions
+        i.load_program(&mut &[0x70, 0x01, 0x12, 0x00][..])?;
+        i.main_loop(60)?;
+        // a couple of looks at the clock every half a millisecond or so
+        // rather than every instruction, and still a second gone by
+        let looks = clock.1.get() / 60;
+        assert!(looks < 40, "{} looks a frame", looks);
+        let frame = time::Duration::from_nanos(CHIP8_CYCLE_NS * CHIP8_FRAME_CYCLES);
+        assert!(clock.0.now() >= 60 * frame);
+        assert!(clock.0.now() < 61 * frame);
+        Ok(())
+    }
 
-            // call e29e
-            let _ = i.fetch_and_decode()?;
-            let t = i.inst_skip_key_eq()?;
+    /// a host so slow that every instruction's late
+    struct SlowClock(ManualClock);
 
-            assert_eq!(i.program_counter, 0x202);
-            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-branch-and-call-instructions/
-            // takes 14 cycles
-            assert_eq!(t, 14);
-            Ok(())
-        })
+    impl Clock for SlowClock {
+        fn now(&self) -> time::Duration {
+            self.0.advance(time::Duration::from_millis(10));
+            self.0.now()
+        }
+
+        fn sleep(&self, _duration: time::Duration) {}
     }
 
     #[test]
-    fn test_key_skip_eq_match() -> Result<(), Box<dyn Error>> {
-        // ex9e
-        test_with(|i| {
-            let mut m: &[u8] = &[0xe2, 0x9e];
-            i.load_program(&mut m)?;
-            i.memory.write(&[0x0f], 0xef2, 1)?;
+    fn test_quiet_overruns() -> Result<(), Box<dyn Error>> {
+        let clock = SlowClock(ManualClock::new());
+        let mut display = display::DummyDisplay::new()?;
+        let mut input = input::DummyInput::new(&[]);
+        let mut sound = sound::Mute::new();
+        let mut i = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+        i.set_clock(&clock);
+        i.set_verbosity(Verbosity::Quiet);
+        i.load_program(&mut &[0x12, 0x00][..])?;
+        i.main_loop(2)?;
+        // counted, even if nothing was said
+        let overruns = i.overruns();
+        assert!(overruns.instructions > 10);
+        assert_eq!(overruns.interrupts, 2);
+        assert!(!i.warn_overrun());
+        // once a frame, unless every time's wanted
+        i.set_verbosity(Verbosity::Normal);
+        assert!(i.warn_overrun());
+        assert!(!i.warn_overrun());
+        i.main_loop(1)?;
+        assert!(!i.warn_overrun());
+        i.set_verbosity(Verbosity::Verbose);
+        assert!(i.warn_overrun());
+        assert!(i.warn_overrun());
+        Ok(())
+    }
 
-            // call e29e
-            let _ = i.fetch_and_decode()?;
-            let t = i.inst_skip_key_eq()?;
+    #[test]
+    fn test_verbosity() -> Result<(), Chip8Error> {
+        assert_eq!(Verbosity::parse("0")?, Verbosity::Silent);
+        assert_eq!(Verbosity::parse("3")?, Verbosity::Verbose);
+        assert!(Verbosity::parse("4").is_err());
+        assert!(Verbosity::parse("loud").is_err());
+        assert_eq!(Verbosity::Normal.quieter().quieter(), Verbosity::Silent);
+        assert_eq!(Verbosity::Silent.quieter(), Verbosity::Silent);
+        assert_eq!(Verbosity::Normal.louder().louder(), Verbosity::Verbose);
+        assert!(Verbosity::Quiet < Verbosity::Normal);
+        Ok(())
+    }
 
-            assert_eq!(i.program_counter, 0x204);
-            assert_eq!(i.input.read_key()?, None);
-            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-branch-and-call-instructions/
-            // takes 18 cycles
-            assert_eq!(t, 18);
-            Ok(())
-        })
+    /// nobody pressing anything, however long it's waited on
+    struct IdleKeys<'c> {
+        clock: &'c ManualClock,
+        waits: &'c Cell<usize>,
     }
 
-    #[test]
-    fn test_key_skip_eq_nomatch() -> Result<(), Box<dyn Error>> {
-        // ex9e
-        test_with(|i| {
-            let mut m: &[u8] = &[0xe2, 0x9e];
-            i.load_program(&mut m)?;
-            i.memory.write(&[0x01], 0xef2, 1)?;
+    impl input::Input for IdleKeys<'_> {
+        fn flush_keys(&mut self) -> Result<(), Chip8Error> {
+            Ok(())
+        }
 
-            // call e29e
-            let _ = i.fetch_and_decode()?;
-            let t = i.inst_skip_key_eq()?;
+        fn read_key(&mut self) -> Result<Option<u8>, Chip8Error> {
+            Ok(None)
+        }
 
-            assert_eq!(i.program_counter, 0x202);
-            assert_ne!(i.input.read_key()?, None);
-            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-branch-and-call-instructions/
-            // takes 14 cycles
-            assert_eq!(t, 14);
+        fn tick(&mut self) -> Result<(), Chip8Error> {
             Ok(())
-        })
+        }
+
+        fn wait_for_event(&mut self, timeout: time::Duration) -> Result<bool, Chip8Error> {
+            self.waits.set(self.waits.get() + 1);
+            self.clock.advance(timeout);
+            Ok(true)
+        }
     }
 
     #[test]
-    fn test_key_skip_ne_none() -> Result<(), Box<dyn Error>> {
-        // exa1
-        test_with(|i| {
-            let mut m: &[u8] = &[0xe2, 0xa1];
-            i.load_program(&mut m)?;
-            i.memory.write(&[0x0a], 0xef2, 1)?;
-            i.input.flush_keys()?;
+    fn test_idle_wait() -> Result<(), Box<dyn Error>> {
+        let clock = ManualClock::new();
+        let waits = Cell::new(0);
+        let mut display = display::DummyDisplay::new()?;
+        let mut input = IdleKeys {
+            clock: &clock,
+            waits: &waits,
+        };
+        let mut sound = sound::Mute::new();
+        let mut i = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+        i.set_clock(&clock);
+        i.set_idle_wait(true);
+        // two seconds on the delay timer, then wait for a key
+        i.load_program(&mut &[0x60, 0x78, 0xf0, 0x15, 0xf0, 0x0a][..])?;
+        let frame = time::Duration::from_nanos(CHIP8_CYCLE_NS * CHIP8_FRAME_CYCLES);
+        // no resting while the timer's going
+        i.main_loop(60)?;
+        assert_eq!(waits.get(), 0);
+        assert!(clock.now() < 61 * frame);
+        // it runs out at about 120 frames, then it's 15 frames a wait
+        i.main_loop(120)?;
+        assert!((3..=5).contains(&waits.get()), "{} waits", waits.get());
+        assert_eq!(i.machine_state().frames, 180);
+        // and the frames keep up with the clock, give or take the last wait
+        assert!(clock.now() >= 180 * frame - IDLE_WAIT);
+        assert!(clock.now() < 181 * frame + IDLE_WAIT);
+        Ok(())
+    }
 
-            // call e2a1
-            let _ = i.fetch_and_decode()?;
-            let t = i.inst_skip_key_ne()?;
+    /// asks for a speed change every frame
+    struct SpeedKeys(Vec<input::SpeedRequest>);
 
-            assert_eq!(i.program_counter, 0x204);
-            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-branch-and-call-instructions/
-            // takes 18 cycles
-            assert_eq!(t, 18);
+    impl input::Input for SpeedKeys {
+        fn flush_keys(&mut self) -> Result<(), Chip8Error> {
             Ok(())
-        })
+        }
+
+        fn read_key(&mut self) -> Result<Option<u8>, Chip8Error> {
+            Ok(None)
+        }
+
+        fn tick(&mut self) -> Result<(), Chip8Error> {
+            Ok(())
+        }
+
+        fn take_speed_request(&mut self) -> Result<Option<input::SpeedRequest>, Chip8Error> {
+            Ok(self.0.pop())
+        }
     }
 
     #[test]
-    fn test_key_skip_ne_match() -> Result<(), Box<dyn Error>> {
-        // exa1
-        test_with(|i| {
-            let mut m: &[u8] = &[0xe2, 0xa1];
-            i.load_program(&mut m)?;
-            i.memory.write(&[0x0f], 0xef2, 1)?;
-
-            // call e2a1
-            let _ = i.fetch_and_decode()?;
-            let t = i.inst_skip_key_ne()?;
+    fn test_speed() -> Result<(), Box<dyn Error>> {
+        use input::SpeedRequest::*;
+        let clock = ManualClock::new();
+        let mut display = display::DummyDisplay::new()?;
+        // taken from the end: faster twice, slower, then lots of faster
+        let mut input = SpeedKeys(vec![Faster, Faster, Faster, Faster, Slower, Faster, Faster]);
+        let mut sound = sound::Mute::new();
+        let mut i = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+        i.set_clock(&clock);
+        i.load_program(&mut &[0x12, 0x00][..])?;
+        i.main_loop(2)?;
+        assert_eq!(i.speed(), 4.0);
+        i.main_loop(3)?;
+        assert_eq!(i.speed(), 8.0);
+
+        // 4 frames at 8x take as long as half a frame at 1x
+        let before = clock.now();
+        i.main_loop(4)?;
+        let frame = time::Duration::from_nanos(CHIP8_CYCLE_NS * CHIP8_FRAME_CYCLES);
+        let taken = clock.now() - before;
+        assert!(taken > frame * 4 / 10 && taken < frame * 6 / 10);
+        Ok(())
+    }
 
-            assert_eq!(i.program_counter, 0x202);
-            assert_eq!(i.input.read_key()?, None);
-            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-branch-and-call-instructions/
-            // takes 14 cycles
-            assert_eq!(t, 14);
-            Ok(())
-        })
+    #[test]
+    fn test_set_speed() -> Result<(), Box<dyn Error>> {
+        let clock = ManualClock::new();
+        let mut display = display::DummyDisplay::new()?;
+        let mut input = input::DummyInput::new(&[]);
+        let mut sound = sound::Mute::new();
+        let mut i = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+        i.set_clock(&clock);
+        i.load_program(&mut &[0x12, 0x00][..])?;
+        for (asked, got) in [
+            (0.0, 1.0),
+            (-2.0, 1.0),
+            (f64::NAN, 1.0),
+            (f64::INFINITY, 8.0),
+            (0.001, 0.25),
+            (3.0, 3.0),
+        ] {
+            i.set_speed(asked);
+            assert_eq!(i.speed(), got, "{}", asked);
+            i.main_loop(1)?;
+        }
+        Ok(())
     }
 
     #[test]
-    fn test_key_skip_ne_nomatch() -> Result<(), Box<dyn Error>> {
-        // exa1
-        test_with(|i| {
-            let mut m: &[u8] = &[0xe2, 0xa1];
-            i.load_program(&mut m)?;
-            i.memory.write(&[0x01], 0xef2, 1)?;
+    fn test_slow_motion() -> Result<(), Box<dyn Error>> {
+        let clock = ManualClock::new();
+        let mut display = display::DummyDisplay::new()?;
+        let mut input = SpeedKeys(vec![input::SpeedRequest::SlowMotion(true)]);
+        let mut sound = sound::Mute::new();
+        let mut i = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+        i.set_clock(&clock);
+        i.load_program(&mut &[0x12, 0x00][..])?;
+        i.main_loop(2)?;
+
+        // 4 frames in slow motion take as long as 8, and the speed the
+        // player picked hasn't changed
+        let before = clock.now();
+        i.main_loop(4)?;
+        let frame = time::Duration::from_nanos(CHIP8_CYCLE_NS * CHIP8_FRAME_CYCLES);
+        let taken = clock.now() - before;
+        assert!(taken > frame * 7 && taken < frame * 9);
+        assert_eq!(i.speed(), 1.0);
+        Ok(())
+    }
 
-            // call e2a1
-            let _ = i.fetch_and_decode()?;
-            let t = i.inst_skip_key_ne()?;
+    /// asks for a volume change every frame
+    struct VolumeKeys(Vec<input::VolumeRequest>);
+
+    impl input::Input for VolumeKeys {
+        fn flush_keys(&mut self) -> Result<(), Chip8Error> {
+            Ok(())
+        }
+
+        fn read_key(&mut self) -> Result<Option<u8>, Chip8Error> {
+            Ok(None)
+        }
 
-            assert_eq!(i.program_counter, 0x204);
-            assert_ne!(i.input.read_key()?, None);
-            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-branch-and-call-instructions/
-            // takes 18 cycles
-            assert_eq!(t, 18);
+        fn tick(&mut self) -> Result<(), Chip8Error> {
             Ok(())
-        })
+        }
+
+        fn take_volume_request(&mut self) -> Result<Option<input::VolumeRequest>, Chip8Error> {
+            Ok(self.0.pop())
+        }
     }
 
     #[test]
-    fn test_get_timer() -> Result<(), Box<dyn Error>> {
-        // fx07
-        test_with(|i| {
-            let mut m: &[u8] = &[0xf0, 0x07];
-            i.load_program(&mut m)?;
-            i.memory.write(&[0x80], 0xef0, 1)?;
-            i.general_timer = 0x08;
+    fn test_volume() -> Result<(), Box<dyn Error>> {
+        use input::VolumeRequest::*;
+        let clock = ManualClock::new();
+        let mut display = display::DummyDisplay::new()?;
+        // taken from the end: quieter twice, mute, louder
+        let mut input = VolumeKeys(vec![Louder, ToggleMute, Quieter, Quieter]);
+        let mut sound = sound::ToneRecorder::new();
+        let mut i = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+        i.set_clock(&clock);
+        i.load_program(&mut &[0x12, 0x00][..])?;
+        i.main_loop(2)?;
+        assert_eq!(i.volume().audible(), 80);
+        i.main_loop(2)?;
+        // louder while muted is still muted, but remembered for later
+        assert_eq!(i.volume().audible(), 0);
+        assert_eq!(i.volume().level, 90);
+        Ok(())
+    }
 
-            // call fx07
-            let _ = i.fetch_and_decode()?;
-            let t = i.inst_get_timer()?;
+    /// presses the help hotkey on the frames given
+    struct HelpKeys(Vec<bool>);
 
-            assert_eq!(i.memory.get_ro_slice(0xef0, 1), &[0x08]);
-            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-branch-and-call-instructions/
-            // takes 10 cycles
-            assert_eq!(t, 10);
+    impl input::Input for HelpKeys {
+        fn flush_keys(&mut self) -> Result<(), Chip8Error> {
             Ok(())
-        })
-    }
+        }
 
-    #[test]
-    fn test_wait_key() -> Result<(), Box<dyn Error>> {
-        // fx0a
-        test_with(|i| {
-            let mut m: &[u8] = &[0xf0, 0x0a];
-            i.load_program(&mut m)?;
-            i.memory.write(&[0x80], 0xef0, 1)?;
-            i.tone_timer = 1;
-            // call fx0a
-            let _ = i.fetch_and_decode()?;
-            let _t = i.inst_wait_key()?;
+        fn read_key(&mut self) -> Result<Option<u8>, Chip8Error> {
+            Ok(None)
+        }
 
-            assert_eq!(i.memory.get_ro_slice(0xef0, 1), &[0x0f]);
-            // see https://laurencescotford.com/chip-8-on-the-cosmac-vip-keyboard-input/
+        fn tick(&mut self) -> Result<(), Chip8Error> {
             Ok(())
-        })
-    }
+        }
 
-    #[test]
-    fn test_set_tone() -> Result<(), Box<dyn Error>> {
-        // fx18
-        test_with(|i| {
-            let mut m: &[u8] = &[0xf0, 0x18];
-            i.load_program(&mut m)?;
-            i.memory.write(&[0x80], 0xef0, 1)?;
-            i.tone_timer = 0x08;
+        fn take_help_toggle(&mut self) -> Result<bool, Chip8Error> {
+            Ok(self.0.pop().unwrap_or(false))
+        }
+    }
 
-            // call fx18
-            let _ = i.fetch_and_decode()?;
-            let t = i.inst_set_sound()?;
+    /// remembers the help it was given to show
+    struct HelpShown(Vec<Option<Vec<String>>>);
 
-            assert_eq!(i.tone_timer, 0x80);
-            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-sound/
-            // takes 10 cycles
-            assert_eq!(t, 10);
+    impl display::Display for HelpShown {
+        fn draw(&mut self, _data: &[u8]) -> Result<(), Chip8Error> {
             Ok(())
-        })
-    }
+        }
 
-    #[test]
-    fn test_interrupt_decrements_tone_timer() -> Result<(), Box<dyn Error>> {
-        test_with(|i| {
-            i.tone_timer = 0x08;
-            let t = i.interrupt()?;
+        fn get_display_size_bytes(&mut self) -> usize {
+            0x100
+        }
 
-            assert_eq!(i.tone_timer, 0x07);
-            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-branch-and-call-instructions/
-            // takes 811 + 1024 cycles
-            assert_eq!(t, 1835);
+        fn set_mode(&mut self, _width: usize, _height: usize) -> Result<(), Chip8Error> {
             Ok(())
-        })
+        }
+
+        fn set_help(&mut self, help: Option<Vec<String>>) {
+            self.0.push(help);
+        }
     }
 
     #[test]
-    fn test_set_timer() -> Result<(), Box<dyn Error>> {
-        // fx15
-        test_with(|i| {
-            let mut m: &[u8] = &[0xf0, 0x15];
-            i.load_program(&mut m)?;
-            i.memory.write(&[0x80], 0xef0, 1)?;
-            i.general_timer = 0x08;
+    fn test_help() -> Result<(), Box<dyn Error>> {
+        let clock = ManualClock::new();
+        let mut shown = HelpShown(vec![]);
+        // taken from the end: shown on the second frame, hidden on the fourth
+        let mut input = HelpKeys(vec![true, false, true, false]);
+        let mut sound = sound::Mute::new();
+        let mut i = Chip8Interpreter::new(&mut shown, &mut input, &mut sound)?;
+        i.set_clock(&clock);
+        i.set_help(vec!["? show or hide these keys".to_string()]);
+        i.load_program(&mut &[0x12, 0x00][..])?;
+        i.main_loop(5)?;
+        drop(i);
+        assert_eq!(
+            shown.0,
+            [Some(vec!["? show or hide these keys".to_string()]), None]
+        );
+        Ok(())
+    }
 
-            // call fx15
-            let _ = i.fetch_and_decode()?;
-            let t = i.inst_set_timer()?;
+    /// the keys go to the emulator between the frames given
+    struct FocusKeys {
+        frames: u32,
+        emulator: Range<u32>,
+    }
 
-            assert_eq!(i.general_timer, 0x80);
-            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-branch-and-call-instructions/
-            // takes 10 cycles
-            assert_eq!(t, 10);
+    impl input::Input for FocusKeys {
+        fn flush_keys(&mut self) -> Result<(), Chip8Error> {
             Ok(())
-        })
-    }
+        }
 
-    #[test]
-    fn test_interrupt_decrements_timer() -> Result<(), Box<dyn Error>> {
-        test_with(|i| {
-            i.general_timer = 0x08;
-            let t = i.interrupt()?;
+        fn read_key(&mut self) -> Result<Option<u8>, Chip8Error> {
+            Ok(None)
+        }
 
-            assert_eq!(i.general_timer, 0x07);
-            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-branch-and-call-instructions/
-            // takes 815 + 1024 cycles
-            assert_eq!(t, 1839);
+        fn tick(&mut self) -> Result<(), Chip8Error> {
+            self.frames += 1;
             Ok(())
-        })
+        }
+
+        fn focus(&self) -> input::Focus {
+            match self.emulator.contains(&self.frames) {
+                true => input::Focus::Emulator,
+                false => input::Focus::Game,
+            }
+        }
     }
 
-    #[test]
-    fn test_add_x_to_i() -> Result<(), Box<dyn Error>> {
-        // fx1e
-        test_with(|i| {
-            let mut m: &[u8] = &[0xf0, 0x1e];
-            i.load_program(&mut m)?;
-            i.memory.write(&[0x84], 0xef0, 1)?;
-            i.i = 0x42;
+    /// remembers what it was told about the focus
+    struct FocusShown(Vec<input::Focus>);
 
-            // call fx1e
-            let _ = i.fetch_and_decode()?;
-            let t = i.inst_add_x_to_i()?;
+    impl display::Display for FocusShown {
+        fn draw(&mut self, _data: &[u8]) -> Result<(), Chip8Error> {
+            Ok(())
+        }
 
-            assert_eq!(i.i, 0xc6);
-            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-indexing-the-memory/
-            // takes 12+4 cycles
-            assert_eq!(t, 16);
+        fn get_display_size_bytes(&mut self) -> usize {
+            0x100
+        }
+
+        fn set_mode(&mut self, _width: usize, _height: usize) -> Result<(), Chip8Error> {
             Ok(())
-        })
+        }
+
+        fn set_focus(&mut self, focus: input::Focus) {
+            self.0.push(focus);
+        }
     }
 
     #[test]
-    fn test_add_x_to_i_with_carry() -> Result<(), Box<dyn Error>> {
-        // fx1e
-        test_with(|i| {
-            let mut m: &[u8] = &[0xf0, 0x1e];
-            i.load_program(&mut m)?;
-            i.memory.write(&[0x84], 0xef0, 1)?;
-            i.i = 0x82;
+    fn test_focus() -> Result<(), Box<dyn Error>> {
+        let clock = ManualClock::new();
+        let mut shown = FocusShown(vec![]);
+        let mut input = FocusKeys {
+            frames: 0,
+            emulator: 2..4,
+        };
+        let mut sound = sound::Mute::new();
+        let mut i = Chip8Interpreter::new(&mut shown, &mut input, &mut sound)?;
+        i.set_clock(&clock);
+        i.load_program(&mut &[0x12, 0x00][..])?;
+        i.main_loop(6)?;
+        drop(i);
+        // told once each way, not every frame
+        assert_eq!(shown.0, [input::Focus::Emulator, input::Focus::Game]);
+        Ok(())
+    }
 
-            // call fx1e
-            let _ = i.fetch_and_decode()?;
-            let t = i.inst_add_x_to_i()?;
+    /// asks for a savestate slot on the frames given
+    struct SlotKeys(Vec<Option<input::SlotRequest>>);
 
-            assert_eq!(i.i, 0x106);
-            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-indexing-the-memory/
-            // takes 18+4 cycles
-            assert_eq!(t, 22);
+    impl input::Input for SlotKeys {
+        fn flush_keys(&mut self) -> Result<(), Chip8Error> {
             Ok(())
-        })
+        }
+
+        fn read_key(&mut self) -> Result<Option<u8>, Chip8Error> {
+            Ok(None)
+        }
+
+        fn tick(&mut self) -> Result<(), Chip8Error> {
+            Ok(())
+        }
+
+        fn take_slot_request(&mut self) -> Result<Option<input::SlotRequest>, Chip8Error> {
+            Ok(self.0.pop().flatten())
+        }
     }
 
     #[test]
-    fn test_load_char() -> Result<(), Box<dyn Error>> {
-        // fx29
-        test_with(|i| {
-            let mut m: &[u8] = &[0xf2, 0x29];
-            i.load_program(&mut m)?;
-            i.memory.write(&[0x0e], 0xef2, 1)?;
+    fn test_slot_request() -> Result<(), Box<dyn Error>> {
+        use input::SlotRequest::*;
+        let clock = ManualClock::new();
+        let mut display = display::DummyDisplay::new()?;
+        // taken from the end: save on the third frame
+        let mut input = SlotKeys(vec![None, Some(Save(3)), None, None]);
+        let mut sound = sound::Mute::new();
+        let mut i = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+        i.set_clock(&clock);
+        i.load_program(&mut &[0x12, 0x00][..])?;
+        assert_eq!(i.main_loop(10)?, RunOutcome::SlotRequested(Save(3)));
+        assert_eq!(i.frames(), 3);
+        // and it carries on from there
+        assert_eq!(i.main_loop(2)?, RunOutcome::Finished);
+        assert_eq!(i.frames(), 5);
+        Ok(())
+    }
 
-            // call f229
-            let _ = i.fetch_and_decode()?;
-            let t = i.inst_load_char()?;
+    /// remembers what it was told had changed in each frame
+    struct Changes(Vec<Option<Vec<Range<usize>>>>);
 
-            assert_eq!(i.i, 0x8110);
+    impl display::Display for Changes {
+        fn draw(&mut self, data: &[u8]) -> Result<(), Chip8Error> {
+            self.draw_changes(data, None)
+        }
 
-            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-the-character-set/
-            // takes 18+4 cycles
-            assert_eq!(t, 20);
+        fn draw_changes(
+            &mut self,
+            _data: &[u8],
+            changed: Option<&[Range<usize>]>,
+        ) -> Result<(), Chip8Error> {
+            self.0.push(changed.map(|c| c.to_vec()));
             Ok(())
-        })
+        }
+
+        fn get_display_size_bytes(&mut self) -> usize {
+            0x100
+        }
+
+        fn set_mode(&mut self, _width: usize, _height: usize) -> Result<(), Chip8Error> {
+            Ok(())
+        }
     }
 
     #[test]
-    fn test_x_to_bcd() -> Result<(), Box<dyn Error>> {
-        // fx33
-        test_with(|i| {
-            let mut m: &[u8] = &[0xf2, 0x33];
-            i.load_program(&mut m)?;
-            i.memory.write(&[0x7b], 0xef2, 1)?;
-            i.i = 0x300;
+    fn test_draw_changes() -> Result<(), Box<dyn Error>> {
+        let mut changes = Changes(vec![]);
+        let mut input = input::DummyInput::new(&[]);
+        let mut sound = sound::Mute::new();
+        let mut i = Chip8Interpreter::new(&mut changes, &mut input, &mut sound)?;
+        // draw 0 in the top left corner, then stop
+        i.load_program(&mut &[0x60, 0x00, 0xf0, 0x29, 0xd0, 0x05, 0x12, 0x06][..])?;
+        i.run_frames(3)?;
+        i.set_hires(false)?;
+        i.run_frames(1)?;
+        drop(i);
+        assert_eq!(
+            changes.0,
+            [
+                // nothing to compare the first frame with
+                None,
+                // DXYN waits for the interrupt, so the glyph's in frame 3
+                Some(vec![]),
+                Some(vec![0..1, 8..9, 16..17, 24..25, 32..33]),
+                // nothing to compare the first after a mode change with either
+                None,
+            ]
+        );
+        Ok(())
+    }
 
-            // call f233
-            let _ = i.fetch_and_decode()?;
-            let t = i.inst_x_to_bcd()?;
+    /// remembers the heat it's been given
+    struct Heated(Vec<Option<Heat>>);
 
-            assert_eq!(i.i, 0x300);
-            assert_eq!(i.memory.get_ro_slice(i.i, 3), &[1, 2, 3]);
-            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-binary-coded-decimal/
-            // takes 4 + 80 + (16 for each 1, 10, 100) cycles
-            assert_eq!(t, 180);
+    impl display::Display for Heated {
+        fn draw(&mut self, _data: &[u8]) -> Result<(), Chip8Error> {
             Ok(())
-        })
+        }
+
+        fn get_display_size_bytes(&mut self) -> usize {
+            0x100
+        }
+
+        fn set_mode(&mut self, _width: usize, _height: usize) -> Result<(), Chip8Error> {
+            Ok(())
+        }
+
+        fn set_heat(&mut self, heat: Option<Heat>) {
+            self.0.push(heat);
+        }
     }
 
     #[test]
-    fn test_save_v_at_i() -> Result<(), Box<dyn Error>> {
-        // fx55
-        test_with(|i| {
-            let mut m: &[u8] = &[0xff, 0x55];
-            i.load_program(&mut m)?;
-            i.memory.write(
-                &[
-                    0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b, 0x1c,
-                    0x1d, 0x1e, 0x1f,
-                ],
-                0xef0,
-                16,
-            )?;
-            i.i = 0x300;
+    fn test_heat_map() -> Result<(), Box<dyn Error>> {
+        let mut heated = Heated(vec![]);
+        let mut input = input::DummyInput::new(&[]);
+        let mut sound = sound::Mute::new();
+        let mut i = Chip8Interpreter::new(&mut heated, &mut input, &mut sound)?;
+        // draw 0 in the top left corner, then stop
+        i.load_program(&mut &[0x60, 0x00, 0xf0, 0x29, 0xd0, 0x05, 0x12, 0x06][..])?;
+        i.run_frames(1)?;
+        i.set_heat_map(true);
+        assert!(i.heat_map());
+        i.run_frames(2)?;
+        i.set_heat_map(false);
+        i.run_frames(1)?;
+        drop(i);
+        assert_eq!(heated.0.len(), 3);
+        // nothing drawn yet, then the glyph's first row
+        assert!(heated.0[0]
+            .as_ref()
+            .is_some_and(|h| h.levels.iter().all(|l| *l == 0)));
+        let heat = heated.0[1].clone().unwrap_or_else(|| panic!("no heat"));
+        assert_eq!((heat.width, heat.height), (64, 32));
+        assert!((0..4).all(|x| heat.level(x, 0) > 0));
+        assert_eq!(heat.level(4, 0), 0);
+        // and gone when it's switched off
+        assert_eq!(heated.0[2], None);
+        Ok(())
+    }
 
-            // call fx55
-            let _ = i.fetch_and_decode()?;
-            let t = i.inst_save_v_at_i()?;
+    /// remembers what the program's given it to feel
+    struct Felt(Vec<Feedback>);
 
-            assert_eq!(i.i, 0x310);
-            assert_eq!(
-                i.memory.get_ro_slice(0x300, 16),
-                &[
-                    0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b, 0x1c,
-                    0x1d, 0x1e, 0x1f
-                ]
-            );
-            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-loading-and-saving-variables/
-            // takes 238 + 4 cycles for 16 registers
-            assert_eq!(t, 242);
+    impl input::Input for Felt {
+        fn flush_keys(&mut self) -> Result<(), Chip8Error> {
             Ok(())
-        })
+        }
+
+        fn read_key(&mut self) -> Result<Option<u8>, Chip8Error> {
+            Ok(None)
+        }
+
+        fn tick(&mut self) -> Result<(), Chip8Error> {
+            Ok(())
+        }
+
+        fn feedback(&mut self, feedback: Feedback) {
+            self.0.push(feedback);
+        }
     }
 
     #[test]
-    fn test_load_v_at_i() -> Result<(), Box<dyn Error>> {
-        // fx65
-        test_with(|i| {
-            let mut m: &[u8] = &[0xff, 0x65];
-            i.load_program(&mut m)?;
-            i.memory.write(
-                &[
-                    0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b, 0x1c,
-                    0x1d, 0x1e, 0x1f,
-                ],
-                0x300,
-                16,
-            )?;
-            i.i = 0x300;
+    fn test_feedback() -> Result<(), Box<dyn Error>> {
+        let mut display = display::DummyDisplay::new()?;
+        let mut felt = Felt(vec![]);
+        let mut sound = sound::Mute::new();
+        let mut i = Chip8Interpreter::new(&mut display, &mut felt, &mut sound)?;
+        // draw 0, then draw it again over itself, then sound the buzzer
+        i.load_program(
+            &mut &[
+                0x60, 0x00, 0xf0, 0x29, 0xd0, 0x05, 0xd0, 0x05, 0x61, 0x10, 0xf1, 0x18, 0x12, 0x0c,
+            ][..],
+        )?;
+        i.run_frames(6)?;
+        drop(i);
+        assert_eq!(felt.0, [Feedback::Collision, Feedback::Buzzer]);
+
+        // and SUPER-CHIP's 16x16 sprites, drawn over themselves the same way
+        let mut felt = Felt(vec![]);
+        let mut schip = crate::schip::Schip::new();
+        let mut i = Chip8Interpreter::new(&mut display, &mut felt, &mut sound)?;
+        i.add_extension(&mut schip);
+        // i = the sprite; d000 twice; stop; then a 16x16 sprite, all lit
+        let mut program = vec![0xa2, 0x0a, 0xd0, 0x00, 0xd0, 0x00, 0x00, 0xfd, 0x00, 0x00];
+        program.extend([0xff; 32]);
+        i.load_program(&mut &program[..])?;
+        i.run_frames(6)?;
+        drop(i);
+        assert_eq!(felt.0, [Feedback::Collision]);
+        Ok(())
+    }
 
-            // call fx65
-            let _ = i.fetch_and_decode()?;
-            let t = i.inst_load_v_at_i()?;
+    #[cfg(feature = "full")]
+    mod full {
+        use super::*;
+
+        #[test]
+        fn test_restore_from_text() -> Result<(), Box<dyn Error>> {
+            // i = glyph v1; draw it at (v1, v1); v1 += 1; again
+            #[rustfmt::skip]
+            let program: &[u8] = &[0xf1, 0x29, 0xd1, 0x15, 0x71, 0x01, 0x12, 0x00];
+            let (mut d1, mut d2) = (display::DummyDisplay, display::DummyDisplay);
+            let (mut i1, mut i2) = (input::DummyInput::new(&[]), input::DummyInput::new(&[]));
+            let (mut s1, mut s2) = (sound::Mute::new(), sound::Mute::new());
+            let mut a = Chip8Interpreter::new(&mut d1, &mut i1, &mut s1)?;
+            let mut b = Chip8Interpreter::new(&mut d2, &mut i2, &mut s2)?;
+            a.load_program(&mut &program[..])?;
+
+            // part way through a draw, via a file's worth of text
+            a.run_frames(3)?;
+            while !a.machine.second_half {
+                a.run_cycles(1)?;
+            }
+            let saved = toml::to_string(&toml::Value::try_from(a.machine_state())?)?;
+            b.restore(toml::from_str(&saved)?)?;
 
-            assert_eq!(i.i, 0x310);
+            a.run_frames(5)?;
+            b.run_frames(5)?;
+            assert_eq!(a.pc(), b.pc());
+            assert_eq!(a.frames(), b.frames());
             assert_eq!(
-                i.memory.get_ro_slice(0xef0, 16),
-                &[
-                    0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b, 0x1c,
-                    0x1d, 0x1e, 0x1f
-                ]
+                a.memory().get_ro_slice(0xf00, 0x100)?,
+                b.memory().get_ro_slice(0xf00, 0x100)?
             );
-            // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-loading-and-saving-variables/
-            // takes 238 + 4 cycles for 16 registers
-            assert_eq!(t, 242);
             Ok(())
-        })
+        }
     }
 }
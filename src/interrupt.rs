@@ -0,0 +1,398 @@
+use crate::error::Chip8Error;
+#[cfg(feature = "full")]
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fmt;
+
+/// the slowest and fastest refresh rates that make any sense, in Hz
+const REFRESH_MIN_HZ: f64 = 1.0;
+const REFRESH_MAX_HZ: f64 = 1000.0;
+
+/// why the interpreter is being interrupted
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "full", derive(Serialize, Deserialize))]
+pub enum Interrupt {
+    /// the 1861 is about to start a frame. on the VIP this runs the ISR that
+    /// updates the timers and DMAs the display page out to the screen
+    DisplayRefresh,
+    /// the timers count down, on a machine where they're not counted down
+    /// by the display's ISR
+    ToneTimer,
+    /// something else, e.g. an extension's own hardware, with a number to
+    /// say what. extensions hear about it; the interpreter doesn't
+    External(u8),
+}
+
+/// something that interrupts the interpreter, on its own cadence. it's
+/// asked when once it's added to the interpreter, and the interrupt queue
+/// takes it from there
+pub trait InterruptSource {
+    /// which interrupt it raises
+    fn interrupt(&self) -> Interrupt;
+
+    /// the machine cycle it first fires at, given its cycle now, and how
+    /// many cycles go by before each time after that (0 to fire just the
+    /// once), for a machine cycle of cycle_ns
+    fn schedule(&self, now: u64, cycle_ns: u64) -> (u64, u64);
+}
+
+/// the 1861 interrupting at the start of each frame, from now on
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DisplayRefresh {
+    pub rate: RefreshRate,
+}
+
+impl InterruptSource for DisplayRefresh {
+    fn interrupt(&self) -> Interrupt {
+        Interrupt::DisplayRefresh
+    }
+
+    fn schedule(&self, now: u64, cycle_ns: u64) -> (u64, u64) {
+        (now, self.rate.frame_cycles(cycle_ns))
+    }
+}
+
+/// the timers counting down at a rate of their own, rather than in the
+/// display's ISR as on the VIP, e.g. to keep them at 60Hz while the
+/// picture goes out at 50. the first count's a whole count away
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ToneTimer {
+    pub rate: RefreshRate,
+}
+
+impl InterruptSource for ToneTimer {
+    fn interrupt(&self) -> Interrupt {
+        Interrupt::ToneTimer
+    }
+
+    fn schedule(&self, now: u64, cycle_ns: u64) -> (u64, u64) {
+        let period = self.rate.frame_cycles(cycle_ns);
+        (now + period, period)
+    }
+}
+
+/// an External(reason) interrupt at machine cycle at, then every every
+/// cycles (or just the once, if every's 0): for tests to interrupt
+/// exactly when they like, or hardware that keeps its own time
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct External {
+    pub reason: u8,
+    pub at: u64,
+    pub every: u64,
+}
+
+impl InterruptSource for External {
+    fn interrupt(&self) -> Interrupt {
+        Interrupt::External(self.reason)
+    }
+
+    fn schedule(&self, now: u64, _cycle_ns: u64) -> (u64, u64) {
+        (self.at.max(now), self.every)
+    }
+}
+
+/// how often the 1861 interrupts: 60Hz on the VIP, as it's an NTSC
+/// machine, but 50Hz on PAL ones like the ETI 660, and some ports picked
+/// their own. the timers count down and the picture goes out once an
+/// interrupt, so this is how fast everything a ROM times happens. kept in
+/// millihertz, so 59.94 is exact
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "full",
+    derive(Serialize, Deserialize),
+    serde(try_from = "Millihertz")
+)]
+pub struct RefreshRate {
+    millihertz: u32,
+}
+
+/// a refresh rate as it's saved, which could say anything, to be checked
+/// before it's one
+#[cfg(feature = "full")]
+#[derive(Deserialize)]
+struct Millihertz {
+    millihertz: u32,
+}
+
+#[cfg(feature = "full")]
+impl TryFrom<Millihertz> for RefreshRate {
+    type Error = Chip8Error;
+
+    fn try_from(saved: Millihertz) -> Result<Self, Chip8Error> {
+        let hz = saved.millihertz as f64 / 1000.0;
+        match (REFRESH_MIN_HZ..=REFRESH_MAX_HZ).contains(&hz) {
+            true => Ok(RefreshRate {
+                millihertz: saved.millihertz,
+            }),
+            false => Err(Chip8Error::ConfigError(format!(
+                "a refresh rate is between {} and {}Hz, not {}Hz",
+                REFRESH_MIN_HZ, REFRESH_MAX_HZ, hz
+            ))),
+        }
+    }
+}
+
+impl RefreshRate {
+    pub const NTSC: RefreshRate = RefreshRate { millihertz: 60_000 };
+    pub const PAL: RefreshRate = RefreshRate { millihertz: 50_000 };
+
+    /// e.g. "50", "59.94", or "pal" or "ntsc"
+    pub fn parse(s: &str) -> Result<Self, Chip8Error> {
+        match s {
+            "ntsc" => return Ok(Self::NTSC),
+            "pal" => return Ok(Self::PAL),
+            _ => {}
+        }
+        match s.trim_end_matches("Hz").parse::<f64>() {
+            Ok(hz) if (REFRESH_MIN_HZ..=REFRESH_MAX_HZ).contains(&hz) => Ok(RefreshRate {
+                millihertz: (hz * 1000.0).round() as u32,
+            }),
+            _ => Err(Chip8Error::ConfigError(format!(
+                "a refresh rate is pal, ntsc or between {} and {}Hz, not {}",
+                REFRESH_MIN_HZ, REFRESH_MAX_HZ, s
+            ))),
+        }
+    }
+
+    pub fn hz(&self) -> f64 {
+        self.millihertz as f64 / 1000.0
+    }
+
+    /// machine cycles from one interrupt to the next, for a machine cycle
+    /// of cycle_ns
+    pub fn frame_cycles(&self, cycle_ns: u64) -> u64 {
+        1_000_000_000_000 / self.millihertz as u64 / cycle_ns
+    }
+
+    /// how many samples at sample_rate go by before frame n starts. a frame
+    /// needn't be a whole number of samples, so the difference between one
+    /// frame's and the next's is how many to make for it
+    pub fn samples_before(&self, frame: u64, sample_rate: u32) -> u64 {
+        frame * sample_rate as u64 * 1000 / self.millihertz as u64
+    }
+
+    /// frames a second as a fraction, (numerator, denominator), for formats
+    /// that want one
+    pub fn ratio(&self) -> (u32, u32) {
+        let (mut a, mut b) = (self.millihertz, 1000);
+        while b != 0 {
+            (a, b) = (b, a % b);
+        }
+        (self.millihertz / a, 1000 / a)
+    }
+}
+
+impl Default for RefreshRate {
+    fn default() -> Self {
+        Self::NTSC
+    }
+}
+
+impl fmt::Display for RefreshRate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}Hz", self.hz())
+    }
+}
+
+// NB. field order matters: the derived Ord sorts on `at` first
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "full", derive(Serialize, Deserialize))]
+struct Scheduled {
+    at: u64,
+    interrupt: Interrupt,
+    period: u64,
+}
+
+/// queue of interrupts, ordered by the machine cycle at which they next fire
+#[derive(Clone)]
+#[cfg_attr(feature = "full", derive(Serialize, Deserialize))]
+pub struct InterruptQueue {
+    queue: BinaryHeap<Reverse<Scheduled>>,
+}
+
+impl InterruptQueue {
+    pub fn new() -> Self {
+        InterruptQueue {
+            queue: BinaryHeap::new(),
+        }
+    }
+
+    /// fire interrupt every `period` machine cycles, starting at cycle `at`.
+    /// a period of 0 fires just the once
+    pub fn register(&mut self, interrupt: Interrupt, at: u64, period: u64) {
+        self.queue.push(Reverse(Scheduled {
+            at,
+            interrupt,
+            period,
+        }));
+    }
+
+    /// schedule source's interrupt, it being machine cycle now
+    pub fn add_source(&mut self, source: &dyn InterruptSource, now: u64, cycle_ns: u64) {
+        let (at, period) = source.schedule(now, cycle_ns);
+        self.register(source.interrupt(), at, period);
+    }
+
+    /// whether interrupt's going to fire again
+    pub fn contains(&self, interrupt: Interrupt) -> bool {
+        self.queue.iter().any(|Reverse(s)| s.interrupt == interrupt)
+    }
+
+    /// forget about all occurrences of an interrupt
+    pub fn unregister(&mut self, interrupt: Interrupt) {
+        self.queue.retain(|Reverse(s)| s.interrupt != interrupt);
+    }
+
+    /// the machine cycle at which the next interrupt fires
+    pub fn next_due(&self) -> Option<u64> {
+        self.queue.peek().map(|Reverse(s)| s.at)
+    }
+
+    /// the next interrupt, if it is due by machine cycle `cycles`
+    pub fn peek_due(&self, cycles: u64) -> Option<Interrupt> {
+        match self.queue.peek() {
+            Some(Reverse(s)) if s.at <= cycles => Some(s.interrupt),
+            _ => None,
+        }
+    }
+
+    /// take the next interrupt if it is due by machine cycle `cycles`,
+    /// rescheduling it if it's periodic
+    pub fn pop_due(&mut self, cycles: u64) -> Option<Interrupt> {
+        if self.next_due()? > cycles {
+            return None;
+        }
+        let Reverse(s) = self.queue.pop()?;
+        if s.period > 0 {
+            self.register(s.interrupt, s.at + s.period, s.period);
+        }
+        Some(s.interrupt)
+    }
+}
+
+impl Default for InterruptQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_empty_queue() {
+        let mut q = InterruptQueue::new();
+        assert_eq!(q.next_due(), None);
+        assert_eq!(q.pop_due(u64::MAX), None);
+    }
+
+    #[test]
+    fn test_not_due_yet() {
+        let mut q = InterruptQueue::new();
+        q.register(Interrupt::DisplayRefresh, 100, 0);
+        assert_eq!(q.peek_due(99), None);
+        assert_eq!(q.pop_due(99), None);
+        assert_eq!(q.peek_due(100), Some(Interrupt::DisplayRefresh));
+        assert_eq!(q.pop_due(100), Some(Interrupt::DisplayRefresh));
+        assert_eq!(q.next_due(), None);
+    }
+
+    #[test]
+    fn test_periodic_reschedules() {
+        let mut q = InterruptQueue::new();
+        q.register(Interrupt::DisplayRefresh, 0, 3668);
+        assert_eq!(q.pop_due(0), Some(Interrupt::DisplayRefresh));
+        assert_eq!(q.next_due(), Some(3668));
+        // catches up if we're a long way behind
+        assert_eq!(q.pop_due(10_000), Some(Interrupt::DisplayRefresh));
+        assert_eq!(q.pop_due(10_000), Some(Interrupt::DisplayRefresh));
+        assert_eq!(q.pop_due(10_000), None);
+        assert_eq!(q.next_due(), Some(3668 * 3));
+    }
+
+    #[test]
+    fn test_ordering() {
+        let mut q = InterruptQueue::new();
+        q.register(Interrupt::DisplayRefresh, 50, 0);
+        q.register(Interrupt::DisplayRefresh, 10, 0);
+        assert_eq!(q.next_due(), Some(10));
+        q.pop_due(10);
+        assert_eq!(q.next_due(), Some(50));
+    }
+
+    #[test]
+    fn test_unregister() {
+        let mut q = InterruptQueue::new();
+        q.register(Interrupt::DisplayRefresh, 0, 100);
+        q.unregister(Interrupt::DisplayRefresh);
+        assert_eq!(q.next_due(), None);
+    }
+
+    #[test]
+    fn test_sources() {
+        let mut q = InterruptQueue::new();
+        q.add_source(&DisplayRefresh::default(), 100, 4543);
+        q.add_source(
+            &ToneTimer {
+                rate: RefreshRate::PAL,
+            },
+            100,
+            4543,
+        );
+        q.add_source(
+            &External {
+                reason: 7,
+                at: 50,
+                every: 0,
+            },
+            100,
+            4543,
+        );
+        assert!(q.contains(Interrupt::ToneTimer));
+        assert!(!q.contains(Interrupt::External(8)));
+        // one that's due already fires straight away
+        assert_eq!(q.pop_due(100), Some(Interrupt::DisplayRefresh));
+        assert_eq!(q.pop_due(100), Some(Interrupt::External(7)));
+        assert_eq!(q.pop_due(100), None);
+        assert!(!q.contains(Interrupt::External(7)));
+        assert_eq!(q.next_due(), Some(100 + 3668));
+        q.pop_due(100 + 3668);
+        assert_eq!(q.next_due(), Some(100 + 4402));
+    }
+
+    #[test]
+    fn test_refresh_rate() -> Result<(), Chip8Error> {
+        assert_eq!(RefreshRate::parse("pal")?, RefreshRate::PAL);
+        assert_eq!(RefreshRate::parse("50")?, RefreshRate::PAL);
+        assert_eq!(RefreshRate::parse("60Hz")?, RefreshRate::NTSC);
+        assert!(RefreshRate::parse("0").is_err());
+        assert!(RefreshRate::parse("fast").is_err());
+        let odd = RefreshRate::parse("59.94")?;
+        assert_eq!(odd.to_string(), "59.94Hz");
+        assert_eq!(odd.ratio(), (2997, 50));
+        assert_eq!(RefreshRate::PAL.ratio(), (50, 1));
+        // the VIP's 4.543us machine cycles
+        assert_eq!(RefreshRate::NTSC.frame_cycles(4543), 3668);
+        assert_eq!(RefreshRate::PAL.frame_cycles(4543), 4402);
+        // 44.1kHz is a whole number of samples a frame at 50 and 60Hz, but
+        // not at 59.94, so some frames get a sample more than others
+        assert_eq!(RefreshRate::PAL.samples_before(1, 44_100), 882);
+        let sizes: Vec<u64> = (0..4)
+            .map(|n| odd.samples_before(n + 1, 44_100) - odd.samples_before(n, 44_100))
+            .collect();
+        assert_eq!(sizes, [735, 736, 736, 735]);
+        Ok(())
+    }
+
+    #[cfg(feature = "full")]
+    #[test]
+    fn test_saved_refresh_rate() {
+        let saved = toml::to_string(&RefreshRate::PAL).unwrap();
+        assert_eq!(toml::from_str::<RefreshRate>(&saved), Ok(RefreshRate::PAL));
+        // one that was never parsed doesn't get in either
+        assert!(toml::from_str::<RefreshRate>("millihertz = 0").is_err());
+        assert!(toml::from_str::<RefreshRate>("millihertz = 4000000000").is_err());
+    }
+}
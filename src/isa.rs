@@ -0,0 +1,426 @@
+//! # the instruction set
+//!
+//! every instruction this build knows, which variant it comes from and what
+//! it does, in one table. the decoder's a match for speed, so the table is
+//! what frontends (and the tests that keep the two in step) look at. plus a
+//! check that runs a ROM and says which of the instructions it used this
+//! build can't do, so a frontend can warn before launching it. and execute,
+//! which runs one instruction on nothing but the registers, so tests can
+//! check what instructions do against a model of them
+use crate::display::DummyDisplay;
+use crate::error::Chip8Error;
+use crate::font::SchipFont;
+use crate::input::DummyInput;
+use crate::interpreter::{Chip8Interpreter, InterpreterState};
+use crate::memory::MemoryMap;
+use crate::quirks::Quirks;
+use crate::schip::Schip;
+use crate::sound::Mute;
+use std::fmt;
+
+/// which interpreter an instruction first turned up in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Variant {
+    /// the VIP's own
+    Chip8,
+    /// the HP48's SUPER-CHIP 1.1 (--schip)
+    Schip,
+}
+
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Variant::Chip8 => "chip-8",
+            Variant::Schip => "schip",
+        })
+    }
+}
+
+/// one row of the table
+#[derive(Debug, PartialEq)]
+pub struct Opcode {
+    /// how it's usually written, e.g. 8XY6
+    pub pattern: &'static str,
+    /// an instruction is this one if inst & mask == bits
+    pub mask: u16,
+    pub bits: u16,
+    pub variant: Variant,
+    pub description: &'static str,
+}
+
+impl Opcode {
+    pub fn matches(&self, inst: u16) -> bool {
+        inst & self.mask == self.bits
+    }
+
+    /// what it does under quirks, for the instructions they change the
+    /// workings of, and the description for the rest
+    pub fn describe(&self, quirks: &Quirks) -> &'static str {
+        match (self.pattern, quirks.logic_leaves_vf) {
+            ("8XY1", false) => "VX |= VY, VF = 0",
+            ("8XY2", false) => "VX &= VY, VF = 0",
+            ("8XY3", false) => "VX ^= VY, VF = 0",
+            ("8XY1", true) => "VX |= VY",
+            ("8XY2", true) => "VX &= VY",
+            ("8XY3", true) => "VX ^= VY",
+            _ => self.description,
+        }
+    }
+}
+
+/// tab-separated, one per line, for anything that wants to read the table
+impl fmt::Display for Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}\t{}\t{}",
+            self.pattern, self.variant, self.description
+        )
+    }
+}
+
+macro_rules! opcode {
+    ($pattern:literal, $mask:literal, $bits:literal, $variant:ident, $description:literal) => {
+        Opcode {
+            pattern: $pattern,
+            mask: $mask,
+            bits: $bits,
+            variant: Variant::$variant,
+            description: $description,
+        }
+    };
+}
+
+/// everything implemented, most specific first: the first match is the one.
+/// like the VIP, 5XY0 and 9XY0 don't look at their last digit
+#[rustfmt::skip]
+pub const ISA: [Opcode; 42] = [
+    opcode!("00E0", 0xffff, 0x00e0, Chip8, "clear the screen"),
+    opcode!("00EE", 0xffff, 0x00ee, Chip8, "return from subroutine"),
+    opcode!("00CN", 0xfff0, 0x00c0, Schip, "scroll down N lines"),
+    opcode!("00FB", 0xffff, 0x00fb, Schip, "scroll right 4 pixels"),
+    opcode!("00FC", 0xffff, 0x00fc, Schip, "scroll left 4 pixels"),
+    opcode!("00FD", 0xffff, 0x00fd, Schip, "exit the interpreter"),
+    opcode!("00FE", 0xffff, 0x00fe, Schip, "low resolution"),
+    opcode!("00FF", 0xffff, 0x00ff, Schip, "high resolution"),
+    opcode!("0NNN", 0xf000, 0x0000, Chip8, "call 1802 machine code at NNN (not 000)"),
+    opcode!("1NNN", 0xf000, 0x1000, Chip8, "jump to NNN"),
+    opcode!("2NNN", 0xf000, 0x2000, Chip8, "call subroutine at NNN"),
+    opcode!("3XNN", 0xf000, 0x3000, Chip8, "skip if VX == NN"),
+    opcode!("4XNN", 0xf000, 0x4000, Chip8, "skip if VX != NN"),
+    opcode!("5XY0", 0xf000, 0x5000, Chip8, "skip if VX == VY"),
+    opcode!("6XNN", 0xf000, 0x6000, Chip8, "VX = NN"),
+    opcode!("7XNN", 0xf000, 0x7000, Chip8, "VX += NN"),
+    opcode!("8XY0", 0xf00f, 0x8000, Chip8, "VX = VY"),
+    opcode!("8XY1", 0xf00f, 0x8001, Chip8, "VX |= VY, VF = 0 (unless logic-leaves-vf)"),
+    opcode!("8XY2", 0xf00f, 0x8002, Chip8, "VX &= VY, VF = 0 (unless logic-leaves-vf)"),
+    opcode!("8XY3", 0xf00f, 0x8003, Chip8, "VX ^= VY, VF = 0 (unless logic-leaves-vf)"),
+    opcode!("8XY4", 0xf00f, 0x8004, Chip8, "VX += VY, VF = carry"),
+    opcode!("8XY5", 0xf00f, 0x8005, Chip8, "VX -= VY, VF = not borrow"),
+    opcode!("8XY6", 0xf00f, 0x8006, Chip8, "VX = VY >> 1, VF = bit shifted out"),
+    opcode!("8XY7", 0xf00f, 0x8007, Chip8, "VX = VY - VX, VF = not borrow"),
+    opcode!("8XYE", 0xf00f, 0x800e, Chip8, "VX = VY << 1, VF = bit shifted out"),
+    opcode!("9XY0", 0xf000, 0x9000, Chip8, "skip if VX != VY"),
+    opcode!("ANNN", 0xf000, 0xa000, Chip8, "I = NNN"),
+    opcode!("BNNN", 0xf000, 0xb000, Chip8, "jump to NNN + V0"),
+    opcode!("CXNN", 0xf000, 0xc000, Chip8, "VX = random & NN"),
+    opcode!("DXYN", 0xf000, 0xd000, Chip8, "draw N rows of sprite at I at (VX, VY), VF = collision"),
+    opcode!("EX9E", 0xf0ff, 0xe09e, Chip8, "skip if key VX is down"),
+    opcode!("EXA1", 0xf0ff, 0xe0a1, Chip8, "skip if key VX is up"),
+    opcode!("FX07", 0xf0ff, 0xf007, Chip8, "VX = delay timer"),
+    opcode!("FX0A", 0xf0ff, 0xf00a, Chip8, "wait for a key, VX = key"),
+    opcode!("FX15", 0xf0ff, 0xf015, Chip8, "delay timer = VX"),
+    opcode!("FX18", 0xf0ff, 0xf018, Chip8, "sound timer = VX"),
+    opcode!("FX1E", 0xf0ff, 0xf01e, Chip8, "I += VX"),
+    opcode!("FX29", 0xf0ff, 0xf029, Chip8, "I = small glyph for VX"),
+    opcode!("FX30", 0xf0ff, 0xf030, Schip, "I = big glyph for VX"),
+    opcode!("FX33", 0xf0ff, 0xf033, Chip8, "store VX as BCD at I"),
+    opcode!("FX55", 0xf0ff, 0xf055, Chip8, "store V0-VX at I"),
+    opcode!("FX65", 0xf0ff, 0xf065, Chip8, "load V0-VX from I"),
+];
+
+/// the row for inst, if it's an instruction at all
+pub fn lookup(inst: u16) -> Option<&'static Opcode> {
+    // 0000's where programs that run off their end get to, so the
+    // interpreter stops there rather than calling it
+    match inst {
+        0x0000 => None,
+        _ => ISA.iter().find(|o| o.matches(inst)),
+    }
+}
+
+/// what inst does, in words, with its registers and numbers filled in:
+/// 331f is "skip if V3 == 0x1F". as it does under quirks, if they're known,
+/// or with where they make a difference said. None if it isn't an
+/// instruction
+pub fn explain(inst: u16, quirks: Option<&Quirks>) -> Option<String> {
+    let opcode = lookup(inst)?;
+    let description = quirks.map_or(opcode.description, |q| opcode.describe(q));
+    let fill = |word: &str| match word {
+        "VX" => format!("V{:X}", (inst >> 8) & 0xf),
+        "VY" => format!("V{:X}", (inst >> 4) & 0xf),
+        "NNN" => format!("0x{:03X}", inst & 0xfff),
+        "NN" => format!("0x{:02X}", inst & 0xff),
+        "N" => (inst & 0xf).to_string(),
+        _ => word.to_string(),
+    };
+    let mut explained = String::new();
+    let mut word = String::new();
+    for c in description.chars() {
+        if c.is_ascii_alphanumeric() {
+            word.push(c);
+        } else {
+            explained.push_str(&fill(&word));
+            explained.push(c);
+            word.clear();
+        }
+    }
+    explained.push_str(&fill(&word));
+    Some(explained)
+}
+
+/// an instruction a ROM ran that this build couldn't
+#[derive(Debug, PartialEq)]
+pub struct Unsupported {
+    pub addr: u16,
+    pub inst: u16,
+    /// the variant that has it, if any does
+    pub needs: Option<Variant>,
+}
+
+impl fmt::Display for Unsupported {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.needs {
+            Some(v) => write!(f, "{:04x}: {:04x} needs {}", self.addr, self.inst, v),
+            None => write!(
+                f,
+                "{:04x}: {:04x} isn't an instruction",
+                self.addr, self.inst
+            ),
+        }
+    }
+}
+
+/// however many instructions unsupported is given, it stops after this
+/// many frames: a minute, for a ROM that spends its time drawing
+const UNSUPPORTED_MAX_FRAMES: u64 = 3600;
+
+/// run rom for up to max_instructions with no keys pressed and only the
+/// given variants' instructions, and list any others it runs into. each one
+/// is skipped to see what else turns up, so anything after the first is a
+/// best guess. it stops early once the ROM's waiting for a key, which with
+/// none pressed is for ever, or has stopped
+pub fn unsupported(
+    rom: &[u8],
+    variants: &[Variant],
+    max_instructions: u64,
+) -> Result<Vec<Unsupported>, Chip8Error> {
+    let mut display = DummyDisplay;
+    let mut input = DummyInput::new(&[]);
+    let mut sound = Mute::new();
+    let mut schip = Schip::new();
+    let mut machine = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+    machine.set_seed(0);
+    if variants.contains(&Variant::Schip) {
+        machine.add_extension(&mut schip);
+        machine.set_font(&SchipFont)?;
+    }
+    machine.load_program(&mut &rom[..])?;
+
+    let mut found: Vec<Unsupported> = Vec::new();
+    for _ in 0..max_instructions {
+        let stuck = matches!(
+            machine.state(),
+            InterpreterState::WaitingForKey | InterpreterState::Halted
+        );
+        if stuck || machine.exited() || machine.frames() >= UNSUPPORTED_MAX_FRAMES {
+            break;
+        }
+        let addr = machine.pc();
+        let word = machine.memory().get_ro_slice(addr, 2)?;
+        let inst = u16::from_be_bytes([word[0], word[1]]);
+        let needs = lookup(inst).map(|o| o.variant);
+        if needs.is_some_and(|v| variants.contains(&v)) {
+            // anything else going wrong is the ROM's problem, not ours
+            if machine.step().is_err() {
+                break;
+            }
+            continue;
+        }
+        if !found.iter().any(|u| u.addr == addr) {
+            found.push(Unsupported { addr, inst, needs });
+        }
+        machine.set_pc(addr + 2);
+    }
+    Ok(found)
+}
+
+/// what an instruction sees of the machine, apart from memory and devices
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Registers {
+    pub v: [u8; 16],
+    pub i: u16,
+    pub pc: u16,
+}
+
+impl Default for Registers {
+    fn default() -> Self {
+        Registers {
+            v: [0; 16],
+            i: 0,
+            pc: 0x200,
+        }
+    }
+}
+
+/// run inst at regs.pc on a fresh machine with regs loaded, and hand back
+/// the registers after it: e.g. for property tests that 8XY4 sets VF when
+/// the sum goes over 255, without building a machine for each. the random
+/// number generator always starts from the same seed, and no keys are
+/// pressed, so FX0A (which would wait forever) isn't allowed
+pub fn execute(regs: Registers, inst: u16, quirks: Quirks) -> Result<Registers, Chip8Error> {
+    if inst & 0xf0ff == 0xf00a {
+        return Err(Chip8Error::ConfigError(
+            "FX0A waits for a key, and nobody's pressing one".to_string(),
+        ));
+    }
+    let mut display = DummyDisplay;
+    let mut input = DummyInput::new(&[]);
+    let mut sound = Mute::new();
+    let mut machine = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+    machine.set_seed(0);
+    machine.set_quirks(quirks);
+    for (reg, value) in regs.v.iter().enumerate() {
+        machine.set_v(reg as u8, *value);
+    }
+    machine.set_i(regs.i);
+    machine.set_pc(regs.pc);
+    machine
+        .memory_mut()
+        .get_rw_slice(regs.pc, 2)?
+        .copy_from_slice(&inst.to_be_bytes());
+    machine.step()?;
+    let mut after = Registers {
+        v: [0; 16],
+        i: machine.i(),
+        pc: machine.pc(),
+    };
+    for (reg, value) in after.v.iter_mut().enumerate() {
+        *value = machine.v(reg as u8);
+    }
+    Ok(after)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_lookup() {
+        assert_eq!(lookup(0x8126).unwrap().pattern, "8XY6");
+        assert_eq!(lookup(0x00ff).unwrap().variant, Variant::Schip);
+        // machine code, unless it's something more specific
+        assert_eq!(lookup(0x0123).unwrap().pattern, "0NNN");
+        assert_eq!(lookup(0x0000), None);
+        assert_eq!(lookup(0x8128), None);
+        assert_eq!(lookup(0xf0ff), None);
+    }
+
+    /// two different registers, neither of them VF, and what's in them all
+    fn registers() -> impl Strategy<Value = (u16, u16, Registers)> {
+        (0..0xfu16, 1..0xfu16, any::<[u8; 16]>()).prop_map(|(x, d, v)| {
+            let regs = Registers {
+                v,
+                ..Registers::default()
+            };
+            (x, (x + d) % 0xf, regs)
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn test_execute_arithmetic((x, y, regs) in registers()) {
+            let (vx, vy) = (regs.v[x as usize], regs.v[y as usize]);
+            let xy = (x << 8) | (y << 4);
+            // 8XY4: VF is the carry
+            let after = execute(regs, 0x8004 | xy, Quirks::VIP).unwrap();
+            prop_assert_eq!(after.v[x as usize], vx.wrapping_add(vy));
+            prop_assert_eq!(after.v[0xf], (vx as u16 + vy as u16 > 0xff) as u8);
+            // 8XY5: VF is not borrow
+            let after = execute(regs, 0x8005 | xy, Quirks::VIP).unwrap();
+            prop_assert_eq!(after.v[x as usize], vx.wrapping_sub(vy));
+            prop_assert_eq!(after.v[0xf], (vx >= vy) as u8);
+            // 7XNN leaves VF alone
+            let after = execute(regs, 0x7000 | xy, Quirks::VIP).unwrap();
+            prop_assert_eq!(after.v[x as usize], vx.wrapping_add(xy as u8));
+            prop_assert_eq!(after.v[0xf], regs.v[0xf]);
+            // 3XNN skips if they're equal
+            let after = execute(regs, 0x3000 | (x << 8) | vy as u16, Quirks::VIP).unwrap();
+            prop_assert_eq!(after.pc, if vx == vy { 0x204 } else { 0x202 });
+        }
+
+        #[test]
+        fn test_execute_shifts((x, y, regs) in registers()) {
+            let (vx, vy) = (regs.v[x as usize], regs.v[y as usize]);
+            let xy = (x << 8) | (y << 4);
+            let after = execute(regs, 0x8006 | xy, Quirks::VIP).unwrap();
+            prop_assert_eq!(after.v[x as usize], vy >> 1);
+            prop_assert_eq!(after.v[0xf], vy & 1);
+            let after = execute(regs, 0x800e | xy, Quirks::MODERN).unwrap();
+            prop_assert_eq!(after.v[x as usize], vx << 1);
+            prop_assert_eq!(after.v[0xf], vx >> 7);
+        }
+    }
+
+    #[test]
+    fn test_execute_wait_key() {
+        assert!(execute(Registers::default(), 0xf30a, Quirks::VIP).is_err());
+    }
+
+    #[test]
+    fn test_explain() {
+        assert_eq!(explain(0x331f, None).unwrap(), "skip if V3 == 0x1F");
+        assert_eq!(
+            explain(0xd125, None).unwrap(),
+            "draw 5 rows of sprite at I at (V1, V2), VF = collision"
+        );
+        assert_eq!(explain(0xfa55, None).unwrap(), "store V0-VA at I");
+        assert_eq!(explain(0x2345, None).unwrap(), "call subroutine at 0x345");
+        assert_eq!(explain(0x8128, None), None);
+        // VF's only cleared by the logic instructions on some interpreters
+        assert_eq!(
+            explain(0x8122, None).unwrap(),
+            "V1 &= V2, VF = 0 (unless logic-leaves-vf)"
+        );
+        assert_eq!(
+            explain(0x8122, Some(&Quirks::VIP)).unwrap(),
+            "V1 &= V2, VF = 0"
+        );
+        let leaves = Quirks {
+            logic_leaves_vf: true,
+            ..Quirks::VIP
+        };
+        assert_eq!(explain(0x8122, Some(&leaves)).unwrap(), "V1 &= V2");
+        assert_eq!(explain(0x6012, Some(&leaves)).unwrap(), "V0 = 0x12");
+    }
+
+    #[test]
+    fn test_unsupported() -> Result<(), Chip8Error> {
+        // hires; 8128; v0 = 1; loop forever
+        let rom = [0x00, 0xff, 0x81, 0x28, 0x60, 0x01, 0x12, 0x06];
+        let found = unsupported(&rom, &[Variant::Chip8], 100)?;
+        assert_eq!(
+            found.iter().map(|u| u.to_string()).collect::<Vec<String>>(),
+            ["0200: 00ff needs schip", "0202: 8128 isn't an instruction"]
+        );
+        let found = unsupported(&rom, &[Variant::Chip8, Variant::Schip], 100)?;
+        assert_eq!(found.len(), 1);
+        // waiting for a key, or drawing, for as long as it's let
+        let rom = [0xf0, 0x0a, 0x00, 0xff];
+        assert!(unsupported(&rom, &[Variant::Chip8], u64::MAX)?.is_empty());
+        let rom = [0xd0, 0x01, 0x12, 0x00];
+        assert!(unsupported(&rom, &[Variant::Chip8], u64::MAX)?.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,467 @@
+//! # keypad transitions
+//!
+//! hosts tell us about keys in all sorts of ways. a terminal sends an event
+//! when a key goes down, then nothing for its repeat delay, then a stream of
+//! repeats (as often as it likes), and nothing at all when the key comes
+//! up. buttons and sticks are read as they are at the time, and bounce, or
+//! jitter round the deadzone, so a key can seem to go up and straight back
+//! down again: a ghost press. a KeyFilter takes whatever the host says, as
+//! it says it, and turns it into a clean press, a hold for as long as the
+//! key's down, and a release, a frame at a time. every Input backend that
+//! reads a host's keys goes through one, so the program sees the same keypad
+//! whichever it is
+//!
+//! it's also where the keys get some help, for players who find holding
+//! them (or holding them in time) hard going: sticky keys, where a tap holds
+//! a key down until it's tapped again, and slow motion, where the game runs
+//! slower for as long as a key's down
+use crate::error::Chip8Error;
+
+/// what happened to a key over a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyTransition {
+    Press(u8),
+    Hold(u8),
+    Release(u8),
+}
+
+/// how the host repeats keys, and what to make of it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyRepeat {
+    /// frames a key's still taken as down after its first event, waiting for
+    /// the host's repeats to start
+    pub delay: usize,
+    /// frames it's still taken as down between repeats, once they have
+    pub interval: usize,
+    /// frames a key has to stay up before it counts as let go. anything
+    /// shorter is a ghost, and the key's held throughout
+    pub ghost: usize,
+    /// the host's repeats only keep a key held: a key the program's taken
+    /// stays taken until it's let go and pressed again
+    pub suppress: bool,
+}
+
+impl KeyRepeat {
+    /// a terminal's keyboard: X's repeat delay is 660ms, and the slowest
+    /// repeats (macOS's) come about every 90ms
+    pub const TERMINAL: KeyRepeat = KeyRepeat {
+        delay: 40,
+        interval: 6,
+        ghost: 0,
+        suppress: false,
+    };
+
+    /// buttons (or a stick) read as they are every frame, which bounce
+    pub const BUTTONS: KeyRepeat = KeyRepeat {
+        delay: 0,
+        interval: 0,
+        ghost: 2,
+        suppress: false,
+    };
+
+    /// the delay and interval in frames, e.g. "40,6"
+    pub fn parse(s: &str) -> Result<Self, Chip8Error> {
+        let bad = || {
+            Chip8Error::ConfigError(format!(
+                "can't read \"{}\" as a repeat delay and interval, in frames (e.g. 40,6)",
+                s
+            ))
+        };
+        let (delay, interval) = s.split_once(',').ok_or_else(bad)?;
+        Ok(KeyRepeat {
+            delay: delay.trim().parse().map_err(|_| bad())?,
+            interval: interval.trim().parse().map_err(|_| bad())?,
+            ..KeyRepeat::TERMINAL
+        })
+    }
+}
+
+impl Default for KeyRepeat {
+    fn default() -> Self {
+        KeyRepeat::TERMINAL
+    }
+}
+
+/// help with the keys
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Assist {
+    /// a tap holds a key down until it's tapped again (or another key is)
+    pub sticky: bool,
+    /// the game runs slower while a key's down
+    pub slow_motion: bool,
+}
+
+/// the key that's down
+#[derive(Debug)]
+struct Held {
+    key: u8,
+    /// its Press has gone out
+    pressed: bool,
+    /// seen since the last frame
+    fresh: bool,
+    /// frames since it was last seen (or let go)
+    quiet: usize,
+    /// the host's started repeating it
+    repeating: bool,
+    /// let go, but not for long enough to be sure
+    up: bool,
+    /// the program's taken it
+    taken: bool,
+}
+
+/// raw host key events in, keypad transitions out
+#[derive(Debug, Default)]
+pub struct KeyFilter {
+    repeat: KeyRepeat,
+    assist: Assist,
+    held: Option<Held>,
+    /// releases of keys that were replaced mid-frame
+    pending: Vec<KeyTransition>,
+    /// the key a tap's left down, with sticky keys
+    stuck: Option<u8>,
+    /// and one that's been let go of without a tap
+    unstuck: Option<u8>,
+    /// whether slow motion was last asked for, or let off
+    slowed: bool,
+}
+
+impl KeyFilter {
+    pub fn new(repeat: KeyRepeat) -> Self {
+        KeyFilter {
+            repeat,
+            ..Default::default()
+        }
+    }
+
+    pub fn set_repeat(&mut self, repeat: KeyRepeat) {
+        self.repeat = repeat;
+    }
+
+    pub fn set_assist(&mut self, assist: Assist) {
+        // a stuck key's let go, with nothing to unstick it, and the tap
+        // that stuck it's done with
+        if !assist.sticky {
+            if let Some(k) = self.stuck.take() {
+                self.pending.push(KeyTransition::Release(k));
+                self.held = None;
+            }
+        }
+        self.assist = assist;
+    }
+
+    /// the host says key's down: pressed, or repeated. the keypad only has
+    /// the one key down at a time, so any other is let go
+    pub fn seen(&mut self, key: u8) {
+        match &mut self.held {
+            Some(h) if h.key == key => {
+                h.repeating |= h.pressed && !h.up;
+                h.fresh = true;
+                h.up = false;
+                if !self.repeat.suppress {
+                    h.taken = false;
+                }
+            }
+            _ => {
+                self.drop_held();
+                self.held = Some(Held {
+                    key,
+                    pressed: false,
+                    fresh: true,
+                    quiet: 0,
+                    repeating: false,
+                    up: false,
+                    taken: false,
+                });
+            }
+        }
+    }
+
+    /// the host says key's up, for hosts that say so
+    pub fn let_go(&mut self, key: u8) {
+        if let Some(h) = self.held.as_mut().filter(|h| h.key == key && !h.up) {
+            h.up = true;
+            h.fresh = false;
+            h.quiet = 0;
+        }
+    }
+
+    /// for hosts that are read rather than told: key's the one down now,
+    /// if any
+    pub fn sample(&mut self, key: Option<u8>) {
+        match (key, self.held.as_ref().map(|h| h.key)) {
+            (Some(k), _) => self.seen(k),
+            (None, Some(k)) => self.let_go(k),
+            (None, None) => {}
+        }
+    }
+
+    /// let go of whatever's down, now, ghost, stuck or not
+    pub fn clear(&mut self) {
+        self.drop_held();
+        self.unstuck = self.stuck.take().or(self.unstuck);
+    }
+
+    /// let go of the host's key, now. with sticky keys, its press was a tap
+    /// that's already been taken
+    fn drop_held(&mut self) {
+        if let Some(h) = self
+            .held
+            .take()
+            .filter(|h| h.pressed && !self.assist.sticky)
+        {
+            self.pending.push(KeyTransition::Release(h.key));
+        }
+    }
+
+    /// the key that's down, unless the program's taken it
+    pub fn read(&self) -> Option<u8> {
+        match self.assist.sticky {
+            true => self.stuck,
+            false => self.held.as_ref().filter(|h| !h.taken).map(|h| h.key),
+        }
+    }
+
+    /// the program's taken the key that's down: it's not read again until
+    /// the host repeats it (or, suppressing repeats, presses it again). a
+    /// stuck key stays down until it's tapped again, whatever the program
+    /// does
+    pub fn flush(&mut self) {
+        if let Some(h) = &mut self.held {
+            h.taken = true;
+        }
+    }
+
+    /// Some(true) when a key's gone down with slow motion on, and
+    /// Some(false) once it's come up (or slow motion's off), if that's
+    /// changed since we last looked
+    pub fn take_slow_motion(&mut self) -> Option<bool> {
+        let down = match self.assist.sticky {
+            true => self.stuck.is_some(),
+            false => self.held.is_some(),
+        };
+        let slowed = self.assist.slow_motion && down;
+        (slowed != self.slowed).then(|| {
+            self.slowed = slowed;
+            slowed
+        })
+    }
+
+    /// a frame's passed: what happened over it
+    pub fn tick(&mut self) -> Vec<KeyTransition> {
+        let transitions = self.tick_host();
+        match self.assist.sticky {
+            true => self.stick(transitions),
+            false => transitions,
+        }
+    }
+
+    /// what the host's keys did over the frame
+    fn tick_host(&mut self) -> Vec<KeyTransition> {
+        let mut transitions = std::mem::take(&mut self.pending);
+        let Some(h) = &mut self.held else {
+            return transitions;
+        };
+        match h.fresh {
+            true => h.quiet = 0,
+            false => h.quiet += 1,
+        }
+        h.fresh = false;
+        let limit = match (h.up, h.repeating) {
+            (true, _) => self.repeat.ghost,
+            (false, false) => self.repeat.delay,
+            (false, true) => self.repeat.interval,
+        };
+        // a press lasts at least a frame, however quickly it's let go
+        if !h.pressed {
+            h.pressed = true;
+            transitions.push(KeyTransition::Press(h.key));
+        } else if h.quiet > limit {
+            transitions.push(KeyTransition::Release(h.key));
+            self.held = None;
+        } else {
+            transitions.push(KeyTransition::Hold(h.key));
+        }
+        transitions
+    }
+
+    /// what the keys did over the frame, with each press a tap that holds
+    /// a key down or lets it go
+    fn stick(&mut self, host: Vec<KeyTransition>) -> Vec<KeyTransition> {
+        let mut transitions: Vec<KeyTransition> = self
+            .unstuck
+            .take()
+            .map(KeyTransition::Release)
+            .into_iter()
+            .collect();
+        for transition in host {
+            match (transition, self.stuck) {
+                (KeyTransition::Press(k), Some(s)) if k == s => {
+                    self.stuck = None;
+                    transitions.push(KeyTransition::Release(k));
+                }
+                (KeyTransition::Press(k), stuck) => {
+                    if let Some(s) = stuck {
+                        transitions.push(KeyTransition::Release(s));
+                    }
+                    self.stuck = Some(k);
+                    transitions.push(KeyTransition::Press(k));
+                }
+                // the host's holds and releases are all part of the tap
+                _ => {}
+            }
+        }
+        if let (Some(k), true) = (self.stuck, transitions.is_empty()) {
+            transitions.push(KeyTransition::Hold(k));
+        }
+        transitions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use KeyTransition::*;
+
+    fn ticks(filter: &mut KeyFilter, n: usize) -> Vec<KeyTransition> {
+        (0..n).flat_map(|_| filter.tick()).collect()
+    }
+
+    #[test]
+    fn test_terminal_repeats() {
+        let mut filter = KeyFilter::new(KeyRepeat {
+            delay: 3,
+            interval: 1,
+            ..KeyRepeat::TERMINAL
+        });
+        // pressed, then nothing until the repeats start
+        filter.seen(0x5);
+        assert_eq!(filter.read(), Some(0x5));
+        assert_eq!(ticks(&mut filter, 4), [Press(5), Hold(5), Hold(5), Hold(5)]);
+        // then a repeat every other frame, which keeps it held
+        for _ in 0..3 {
+            filter.seen(0x5);
+            assert_eq!(ticks(&mut filter, 2), [Hold(5), Hold(5)]);
+        }
+        // and once they stop, it's let go
+        assert_eq!(filter.tick(), [Release(5)]);
+        assert_eq!(filter.read(), None);
+        assert!(filter.tick().is_empty());
+    }
+
+    #[test]
+    fn test_another_key() {
+        let mut filter = KeyFilter::new(KeyRepeat::TERMINAL);
+        filter.seen(0x5);
+        filter.tick();
+        // a tap on another key, let go of before the frame's out
+        filter.seen(0x6);
+        filter.let_go(0x6);
+        assert_eq!(filter.tick(), [Release(5), Press(6)]);
+        assert_eq!(filter.tick(), [Release(6)]);
+    }
+
+    #[test]
+    fn test_ghost_press() {
+        let mut filter = KeyFilter::new(KeyRepeat::BUTTONS);
+        let mut frames = |key, n| -> Vec<KeyTransition> {
+            (0..n)
+                .flat_map(|_| {
+                    filter.sample(key);
+                    filter.tick()
+                })
+                .collect()
+        };
+        assert_eq!(frames(Some(0xa), 1), [Press(0xa)]);
+        // a bounce: up for a frame, then down again
+        assert_eq!(frames(None, 1), [Hold(0xa)]);
+        assert_eq!(frames(Some(0xa), 2), [Hold(0xa), Hold(0xa)]);
+        // really let go
+        assert_eq!(frames(None, 3), [Hold(0xa), Hold(0xa), Release(0xa)]);
+    }
+
+    #[test]
+    fn test_taken() {
+        let mut filter = KeyFilter::new(KeyRepeat::TERMINAL);
+        filter.seen(0x1);
+        filter.flush();
+        assert_eq!(filter.read(), None);
+        // a repeat brings it back
+        filter.seen(0x1);
+        assert_eq!(filter.read(), Some(0x1));
+
+        // unless repeats are suppressed, when it takes a fresh press
+        filter.set_repeat(KeyRepeat {
+            suppress: true,
+            ..KeyRepeat::TERMINAL
+        });
+        filter.flush();
+        filter.seen(0x1);
+        assert_eq!(filter.read(), None);
+        filter.clear();
+        filter.seen(0x1);
+        assert_eq!(filter.read(), Some(0x1));
+    }
+
+    #[test]
+    fn test_sticky() {
+        let mut filter = KeyFilter::new(KeyRepeat::TERMINAL);
+        filter.set_assist(Assist {
+            sticky: true,
+            ..Default::default()
+        });
+        // a tap holds it down long after the terminal's let go
+        filter.seen(0x5);
+        assert_eq!(filter.tick(), [Press(5)]);
+        assert_eq!(ticks(&mut filter, 100).last(), Some(&Hold(5)));
+        filter.flush();
+        assert_eq!(filter.read(), Some(0x5));
+        // tapped again, it's let go
+        filter.seen(0x5);
+        assert_eq!(filter.tick(), [Release(5)]);
+        assert_eq!(filter.read(), None);
+        assert_eq!(ticks(&mut filter, 100), []);
+
+        // another key takes over
+        filter.seen(0x5);
+        filter.tick();
+        filter.seen(0x6);
+        assert_eq!(filter.tick(), [Release(5), Press(6)]);
+        // and turning it off lets go
+        filter.set_assist(Assist::default());
+        assert_eq!(filter.tick(), [Release(6)]);
+        assert_eq!(filter.read(), None);
+    }
+
+    #[test]
+    fn test_slow_motion() {
+        let mut filter = KeyFilter::new(KeyRepeat::BUTTONS);
+        filter.set_assist(Assist {
+            slow_motion: true,
+            ..Default::default()
+        });
+        assert_eq!(filter.take_slow_motion(), None);
+        filter.sample(Some(0x2));
+        assert_eq!(filter.take_slow_motion(), Some(true));
+        filter.tick();
+        assert_eq!(filter.take_slow_motion(), None);
+        for _ in 0..4 {
+            filter.sample(None);
+            filter.tick();
+        }
+        assert_eq!(filter.take_slow_motion(), Some(false));
+        // and it's let off if it's turned off mid-press
+        filter.sample(Some(0x2));
+        assert_eq!(filter.take_slow_motion(), Some(true));
+        filter.set_assist(Assist::default());
+        assert_eq!(filter.take_slow_motion(), Some(false));
+    }
+
+    #[test]
+    fn test_parse() -> Result<(), Chip8Error> {
+        let repeat = KeyRepeat::parse("30, 4")?;
+        assert_eq!((repeat.delay, repeat.interval), (30, 4));
+        assert!(KeyRepeat::parse("30").is_err());
+        assert!(KeyRepeat::parse("a,b").is_err());
+        Ok(())
+    }
+}
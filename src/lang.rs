@@ -0,0 +1,443 @@
+//! # languages
+//!
+//! the text the player reads -- the pause menu, the help overlay and the
+//! warnings -- looked up by a key, in whichever language they've asked for
+//! (--lang, the config's language, or failing those $LANG). a table per
+//! language is all it takes: the messages are short, and only ever need
+//! their {}s filling in, in order. anything a translation hasn't got to yet
+//! comes out in English. what's typed (the menu's commands, the hotkeys'
+//! names) stays as it is, so the same commands work whatever the language
+use crate::error::Chip8Error;
+use std::fmt;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// the languages there are tables for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[repr(u8)]
+pub enum Language {
+    #[default]
+    English,
+    French,
+}
+
+impl Language {
+    const ALL: [(&'static str, Language); 2] =
+        [("en", Language::English), ("fr", Language::French)];
+
+    /// by its code, e.g. "fr", or a locale, e.g. "fr_FR.UTF-8"
+    pub fn parse(s: &str) -> Result<Self, Chip8Error> {
+        let code = s.split(['_', '.', '-']).next().unwrap_or_default();
+        Self::ALL
+            .iter()
+            .find(|(c, _)| c.eq_ignore_ascii_case(code))
+            .map(|(_, l)| *l)
+            .ok_or_else(|| {
+                Chip8Error::ConfigError(format!(
+                    "no language called \"{}\" (try {})",
+                    s,
+                    Self::ALL.map(|(c, _)| c).join(" or ")
+                ))
+            })
+    }
+
+    /// whatever the environment's locale says, if it's one we've got
+    pub fn from_env() -> Self {
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|v| std::env::var(v).ok())
+            .find(|v| !v.is_empty())
+            .and_then(|v| Self::parse(&v).ok())
+            .unwrap_or_default()
+    }
+
+    fn table(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Language::English => ENGLISH,
+            Language::French => FRENCH,
+        }
+    }
+}
+
+/// picked once, at start-up, and read from everywhere after
+static LANGUAGE: AtomicU8 = AtomicU8::new(Language::English as u8);
+
+pub fn set_language(language: Language) {
+    LANGUAGE.store(language as u8, Ordering::Relaxed);
+}
+
+pub fn language() -> Language {
+    match LANGUAGE.load(Ordering::Relaxed) {
+        l if l == Language::French as u8 => Language::French,
+        _ => Language::English,
+    }
+}
+
+/// the text for key, in the language that's been picked
+pub fn text(key: &str) -> &str {
+    text_in(language(), key)
+}
+
+/// the text for key in language, or in English if it's not been
+/// translated. a key nobody's written any text for comes out as itself,
+/// rather than taking the emulator down
+pub fn text_in(language: Language, key: &str) -> &str {
+    let find = |l: Language| l.table().iter().find(|(k, _)| *k == key).map(|(_, t)| *t);
+    find(language)
+        .or_else(|| find(Language::English))
+        .unwrap_or(key)
+}
+
+/// the text for key, with its {}s filled in by args, in order
+pub fn format(key: &str, args: &[&dyn fmt::Display]) -> String {
+    fill(text(key), args)
+}
+
+fn fill(template: &str, args: &[&dyn fmt::Display]) -> String {
+    let mut parts = template.split("{}");
+    let mut filled = parts.next().unwrap_or_default().to_string();
+    for (n, part) in parts.enumerate() {
+        if let Some(arg) = args.get(n) {
+            filled.push_str(&arg.to_string());
+        }
+        filled.push_str(part);
+    }
+    filled
+}
+
+const ENGLISH: &[(&str, &str)] = &[
+    (
+        "menu.help",
+        "\
+continue (or just enter)     carry on playing
+quit                         stop the emulator
+step [<n>]                   run one (or n) instructions, explaining each
+search <filter>              narrow down RAM, e.g. search 3, search > 2,
+                             search changed/unchanged/up/down
+search reset                 start a new search
+poke <addr>=<value>          write to memory, e.g. poke 0x3a0=3
+freeze <name>=<addr>[:<val>] add a cheat holding addr at val (or its current value)
+toggle <name>                switch a cheat on or off
+cheats                       list this ROM's cheats
+watch [<addr>[:<len>]]       sample memory every frame, e.g. watch 0x3a0:2;
+                             on its own, plot the last second of each
+unwatch <addr>               stop watching
+achievement <rule>           add an achievement, e.g.
+                             achievement 0x3a0 >= 100 -> Century!
+copy                         copy the machine's state, to share as text
+paste [<state>]              carry on from a state copied earlier
+report                       save a zip of what's needed to report a bug
+undo, redo                   undo (or redo) a change to the speed, quirks or theme
+sprites <sheet.png>          put an edited sprite sheet into memory",
+    ),
+    ("menu.paused", "paused> "),
+    (
+        "menu.tutorial",
+        "step (or s) runs the next instruction and says what it did",
+    ),
+    (
+        "menu.resume-crash",
+        "this is a frame or so before it went wrong: step (or s) to go an instruction at a time",
+    ),
+    ("menu.cant-step", "can't step \"{}\" instructions"),
+    ("menu.search-reset", "search reset"),
+    ("menu.matches", "{} address(es) match"),
+    (
+        "menu.freeze-needs-address",
+        "freeze needs an address, e.g. freeze {}=0x3a0",
+    ),
+    ("menu.holds", "{} holds {} at {}"),
+    ("menu.is", "{} is {}"),
+    ("menu.on", "on"),
+    ("menu.off", "off"),
+    ("menu.not-watched", "{} isn't being watched"),
+    ("menu.copied", "copied"),
+    ("menu.sprites-changed", "{} sprite(s) changed"),
+    ("menu.report-saved", "saved {}: attach it to the issue"),
+    ("hotkey.menu", "pause and open the menu"),
+    ("hotkey.help", "show or hide these keys"),
+    ("hotkey.hud", "show or hide the HUD"),
+    ("hotkey.slower", "run slower"),
+    ("hotkey.faster", "run faster"),
+    ("hotkey.normal-speed", "back to the VIP's speed"),
+    ("hotkey.quieter", "quieter"),
+    ("hotkey.louder", "louder"),
+    ("hotkey.mute", "mute or unmute"),
+    (
+        "hotkey.focus",
+        "keys to the emulator only, or back to the game",
+    ),
+    ("hotkey.slots", "show the savestate slots, to pick one"),
+    ("hotkey.save-slot", "save to a savestate slot"),
+    ("hotkey.load-slot", "load from a savestate slot"),
+    ("hotkey.palette", "search every command"),
+    ("palette.speed", "run at {}x speed"),
+    ("palette.quirk", "turn quirk {} on or off"),
+    ("palette.theme", "use the {} theme"),
+    ("palette.heat-map", "turn the heat map on or off"),
+    ("heat.map", "the heat map"),
+    (
+        "palette.undo",
+        "undo the last change to the speed, quirks or theme",
+    ),
+    ("palette.redo", "redo the last change undone"),
+    ("palette.nothing", "  nothing matches"),
+    ("settings.undone", "undid: {}"),
+    ("stats.never", "never played"),
+    ("stats.played-once", "played once, for {}"),
+    ("stats.played", "played {} times, for {}"),
+    ("stats.best", "best score {}"),
+    ("stats.new-best", "a new best score: {}"),
+    ("settings.redone", "redid: {}"),
+    ("settings.nothing", "nothing to undo or redo"),
+    (
+        "settings.keep",
+        "keep the speed, quirks and theme as they are now, for next time? [y/N] ",
+    ),
+    (
+        "palette.help",
+        "type to search, up and down to pick, enter to do it",
+    ),
+    (
+        "warning.unknown-key-event",
+        "Warning: unknown key event received",
+    ),
+    ("warning.unknown-event", "Warning: unknown event received"),
+    (
+        "warning.cant-map",
+        "Warning: can't map {} to a COSMAC key or a hotkey",
+    ),
+    (
+        "warning.backup-missing",
+        "Warning: {} is missing, so using {}",
+    ),
+    (
+        "warning.checksum",
+        "Warning: {} doesn't match its checksum (edited by hand?), but using it anyway",
+    ),
+    (
+        "warning.backup-damaged",
+        "Warning: {} is damaged ({}), so using {}",
+    ),
+    (
+        "warning.hotkey-on-keypad",
+        "Warning: {} is on the keypad, so it's not the {} hotkey",
+    ),
+    (
+        "warning.odd-size",
+        "Warning: {} bytes is an odd size for a ROM, so it might have been cut short",
+    ),
+    (
+        "warning.attract-fault",
+        "Warning: {} faulted, so it was skipped ({})",
+    ),
+    (
+        "warning.bad-language",
+        "Warning: the config's language won't do ({}), so using English",
+    ),
+    ("warning.skipping", "Warning: skipping {}: {}"),
+    (
+        "error.panicked",
+        "{}\n\nthat's a bug in the emulator, not the ROM: please report it (the pause menu's report saves what's needed)",
+    ),
+    (
+        "error.resume-crash",
+        "chip8 --resume-crash goes back to a frame or so before, to step into it",
+    ),
+    (
+        "warning.display-stalled",
+        "Warning: the display's been stuck drawing for {}ms, so frames are being skipped",
+    ),
+    (
+        "warning.display-recovered",
+        "the display's drawing again, after {} frame(s) skipped",
+    ),
+];
+
+const FRENCH: &[(&str, &str)] = &[
+    (
+        "menu.help",
+        "\
+continue (ou juste entrée)   reprendre la partie
+quit                         quitter l'émulateur
+step [<n>]                   exécuter une (ou n) instructions, en expliquant chacune
+search <filtre>              restreindre la RAM, p. ex. search 3, search > 2,
+                             search changed/unchanged/up/down
+search reset                 recommencer une recherche
+poke <adr>=<valeur>          écrire en mémoire, p. ex. poke 0x3a0=3
+freeze <nom>=<adr>[:<val>]   ajouter une triche qui bloque adr à val (ou à sa valeur actuelle)
+toggle <nom>                 activer ou désactiver une triche
+cheats                       lister les triches de cette ROM
+watch [<adr>[:<long>]]       relever la mémoire à chaque image, p. ex. watch 0x3a0:2 ;
+                             seul, tracer la dernière seconde de chacune
+unwatch <adr>                ne plus surveiller
+achievement <règle>          ajouter un succès, p. ex.
+                             achievement 0x3a0 >= 100 -> Century!
+copy                         copier l'état de la machine, à partager en texte
+paste [<état>]               reprendre à partir d'un état copié plus tôt
+report                       enregistrer un zip de quoi signaler un bogue
+undo, redo                   annuler (ou rétablir) une modification de la vitesse,
+                             des bizarreries ou du thème
+sprites <planche.png>        mettre en mémoire une planche de sprites retouchée",
+    ),
+    ("menu.paused", "en pause> "),
+    (
+        "menu.tutorial",
+        "step (ou s) exécute l'instruction suivante et explique ce qu'elle a fait",
+    ),
+    (
+        "menu.resume-crash",
+        "on est environ une image avant que ça tourne mal : step (ou s) pour avancer d'une instruction à la fois",
+    ),
+    ("menu.cant-step", "impossible d'exécuter « {} » instructions"),
+    ("menu.search-reset", "recherche remise à zéro"),
+    ("menu.matches", "{} adresse(s) correspondent"),
+    ("menu.freeze-needs-address", "freeze a besoin d'une adresse, p. ex. freeze {}=0x3a0"),
+    ("menu.holds", "{} bloque {} à {}"),
+    ("menu.is", "{} est {}"),
+    ("menu.on", "activée"),
+    ("menu.off", "désactivée"),
+    ("menu.not-watched", "{} n'est pas surveillée"),
+    ("menu.copied", "copié"),
+    ("menu.sprites-changed", "{} sprite(s) modifié(s)"),
+    ("menu.report-saved", "{} enregistré : joignez-le au ticket"),
+    ("hotkey.menu", "mettre en pause et ouvrir le menu"),
+    ("hotkey.help", "afficher ou masquer ces touches"),
+    ("hotkey.hud", "afficher ou masquer le HUD"),
+    ("hotkey.slower", "ralentir"),
+    ("hotkey.faster", "accélérer"),
+    ("hotkey.normal-speed", "revenir à la vitesse du VIP"),
+    ("hotkey.quieter", "moins fort"),
+    ("hotkey.louder", "plus fort"),
+    ("hotkey.mute", "couper ou remettre le son"),
+    ("hotkey.focus", "touches pour l'émulateur seul, ou de nouveau pour le jeu"),
+    ("hotkey.slots", "afficher les emplacements de sauvegarde, pour en choisir un"),
+    ("hotkey.save-slot", "sauvegarder dans un emplacement"),
+    ("hotkey.load-slot", "charger depuis un emplacement"),
+    ("hotkey.palette", "chercher parmi toutes les commandes"),
+    ("palette.speed", "jouer à la vitesse {}x"),
+    ("palette.quirk", "activer ou désactiver la bizarrerie {}"),
+    ("palette.theme", "utiliser le thème {}"),
+    ("palette.heat-map", "activer ou désactiver la carte de chaleur"),
+    ("heat.map", "la carte de chaleur"),
+    ("palette.undo", "annuler la dernière modification de la vitesse, des bizarreries ou du thème"),
+    ("palette.redo", "rétablir la dernière modification annulée"),
+    ("palette.nothing", "  aucune commande ne correspond"),
+    ("settings.undone", "annulé : {}"),
+    ("stats.never", "jamais joué"),
+    ("stats.played-once", "joué une fois, pendant {}"),
+    ("stats.played", "joué {} fois, pendant {}"),
+    ("stats.best", "meilleur score {}"),
+    ("stats.new-best", "un nouveau meilleur score : {}"),
+    ("settings.redone", "rétabli : {}"),
+    ("settings.nothing", "rien à annuler ni à rétablir"),
+    (
+        "settings.keep",
+        "garder la vitesse, les bizarreries et le thème actuels pour la prochaine fois ? [o/N] ",
+    ),
+    (
+        "palette.help",
+        "tapez pour chercher, haut et bas pour choisir, entrée pour valider",
+    ),
+    ("warning.unknown-key-event", "Attention : événement de touche inconnu reçu"),
+    ("warning.unknown-event", "Attention : événement inconnu reçu"),
+    (
+        "warning.cant-map",
+        "Attention : impossible d'associer {} à une touche COSMAC ou à un raccourci",
+    ),
+    ("warning.backup-missing", "Attention : {} est introuvable, donc {} est utilisé"),
+    (
+        "warning.checksum",
+        "Attention : {} ne correspond pas à sa somme de contrôle (modifié à la main ?), mais il est utilisé quand même",
+    ),
+    ("warning.backup-damaged", "Attention : {} est abîmé ({}), donc {} est utilisé"),
+    (
+        "warning.hotkey-on-keypad",
+        "Attention : {} est sur le clavier CHIP-8, ce n'est donc pas le raccourci {}",
+    ),
+    (
+        "warning.odd-size",
+        "Attention : {} octets, c'est une taille étrange pour une ROM, elle a peut-être été tronquée",
+    ),
+    (
+        "warning.attract-fault",
+        "Attention : {} a planté, elle a donc été sautée ({})",
+    ),
+    (
+        "warning.bad-language",
+        "Attention : la langue de la configuration ne convient pas ({}), donc en anglais",
+    ),
+    ("warning.skipping", "Attention : {} est ignoré : {}"),
+    (
+        "error.panicked",
+        "{}\n\nc'est un bogue de l'émulateur, pas de la ROM : merci de le signaler (report, dans le menu de pause, enregistre ce qu'il faut)",
+    ),
+    (
+        "error.resume-crash",
+        "chip8 --resume-crash revient environ une image avant, pour l'exécuter pas à pas",
+    ),
+    (
+        "warning.display-stalled",
+        "Attention : l'affichage est bloqué depuis {} ms, donc des images sont sautées",
+    ),
+    (
+        "warning.display-recovered",
+        "l'affichage reprend, après {} image(s) sautée(s)",
+    ),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() -> Result<(), Chip8Error> {
+        assert_eq!(Language::parse("fr")?, Language::French);
+        assert_eq!(Language::parse("fr_FR.UTF-8")?, Language::French);
+        assert_eq!(Language::parse("EN")?, Language::English);
+        let e = Language::parse("tlh")
+            .map(|_| ())
+            .map_err(|e| e.to_string());
+        assert!(e.is_err_and(|e| e.contains("en or fr")));
+        Ok(())
+    }
+
+    #[test]
+    fn test_translations_complete() {
+        // every key's translated, with the same {}s to fill in, and nothing
+        // translated that English hasn't got
+        for (_, language) in Language::ALL {
+            for (key, english) in ENGLISH {
+                let text = language
+                    .table()
+                    .iter()
+                    .find(|(k, _)| k == key)
+                    .map(|(_, t)| *t);
+                let text = text.unwrap_or_else(|| panic!("{:?} hasn't got {}", language, key));
+                assert_eq!(
+                    text.matches("{}").count(),
+                    english.matches("{}").count(),
+                    "{:?} {}",
+                    language,
+                    key
+                );
+            }
+            assert_eq!(language.table().len(), ENGLISH.len(), "{:?}", language);
+        }
+    }
+
+    #[test]
+    fn test_fill() {
+        assert_eq!(
+            fill(text_in(Language::English, "menu.is"), &[&"lives", &"on"]),
+            "lives is on"
+        );
+        assert_eq!(
+            fill(
+                text_in(Language::French, "menu.holds"),
+                &[&"vies", &"0x300", &3]
+            ),
+            "vies bloque 0x300 à 3"
+        );
+        assert_eq!(text_in(Language::French, "nonsense"), "nonsense");
+    }
+}
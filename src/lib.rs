@@ -1,64 +1,217 @@
-///
-/// ## Design
-///
-/// * authentic timing to COSMAC VIP as much as possible
-/// * map to machine cycle (not 1.76064 MHz clock)
-/// * abstract display so can plug alternatives; starting with TUI in-console
-/// * anticipate emulation of RCA CDP 1802 itself
-/// * CHIP-8 instructions will run as fast as possible then sleep, to match
-///   timings; so not quite authentic
-///
-/// Enums to represent:
-///
-/// * memory map
-///    - should allow for RAM, ROM and DMA in the future
-///    - need a means of initialising from an external file or whatever
-/// * instruction set
-/// * the interpreter itself
-///    - pub .cycle() -> n -- move on n machine cycle(s)
-///    - pub .interrupt(reason) -- interrupt for reason
-///    - need to maintain simple state machine such that we can .cycle() and
-///      keep proper timings for fetch/decode/execute
-///    - state machine also needs to wait for interrupts. whilst it's doing
-///      this .cycle() does nothing and returns 1
-/// * some config (e.g. CHIP-8 vs. SUPER-CHIP)
-/// * the environment
-///    - sets everything up; runs the main loop
-///    - maintains a queue of interrupt handlers, ordered by next to fire
-/// * display, with trait for rendering
-///    - provide an interface such that the interpreter doesn't need to know
-///      how the display works
-/// * input device, with trait for reading key-presses
-/// * audio device, with trait for making beeps
-///
-/// Model
-///
-/// Environment
-///  |-- display, input, audio, config, memory(config)
-///  |-- interpreter(display, input, audio, memory, config)
-///  |    |-- instruction set(config)
-///  |    `-- set up machine state(config)
-///  `-- main loop
-///       |   // this logic gets the timing mostly right; altho the interrupt always
-///       |   // happens after the CHIP-8 instruction is processed. i.e. wallclock
-///       |   // timing will look good, but some things might happen too quickly anyway
-///       |-- new_cycles = interpreter.cycle();
-///       |-- while interrupt_queue.top().would_interrupt(cycles + new_cycles) {
-///       |     sleep(some_proportion_of(new_cycles)); new_cycles -= that proportion;
-///       |     interpreter.interrupt(REASON);
-///       |     interrupt_queue.insert(interrupt_queue.pop())
-///       |   }
-///       `-- sleep(new_cycles * 4.54us)
-///
-/// # Useful links
-///
-/// * ROMs: <https://github.com/dmatlack/chip8/tree/master/roms>
-///         <https://github.com/mir3z/chip8-emu/tree/master/roms>
-/// * COSMAC details: <https://laurencescotford.com/chip-8-on-the-cosmac-vip-index/>
-///         <http://www.bitsavers.org/components/rca/cosmac/COSMAC_VIP_Instruction_Manual_1978.pdf>
-/// * variations: <https://chip-8.github.io/extensions/>
+//!
+//! ## Design
+//!
+//! * authentic timing to COSMAC VIP as much as possible
+//! * map to machine cycle (not 1.76064 MHz clock)
+//! * abstract display so can plug alternatives; starting with TUI in-console
+//! * anticipate emulation of RCA CDP 1802 itself
+//! * CHIP-8 instructions will run as fast as possible then sleep, to match
+//!   timings; so not quite authentic
+//!
+//! Enums to represent:
+//!
+//! * memory map
+//!    - should allow for RAM, ROM and DMA in the future
+//!    - need a means of initialising from an external file or whatever
+//! * instruction set
+//! * the interpreter itself
+//!    - pub .cycle() -> n -- move on n machine cycle(s)
+//!    - pub .interrupt(reason) -- interrupt for reason
+//!    - need to maintain simple state machine such that we can .cycle() and
+//!      keep proper timings for fetch/decode/execute
+//!    - state machine also needs to wait for interrupts. whilst it's doing
+//!      this .cycle() does nothing and returns 1
+//! * some config (e.g. CHIP-8 vs. SUPER-CHIP)
+//! * the environment
+//!    - sets everything up; runs the main loop
+//!    - maintains a queue of interrupt handlers, ordered by next to fire
+//! * display, with trait for rendering
+//!    - provide an interface such that the interpreter doesn't need to know
+//!      how the display works
+//! * input device, with trait for reading key-presses
+//! * audio device, with trait for making beeps
+//!
+//! Model
+//!
+//! Environment
+//!  |-- display, input, audio, config, memory(config)
+//!  |-- interpreter(display, input, audio, memory, config)
+//!  |    |-- instruction set(config)
+//!  |    `-- set up machine state(config)
+//!  `-- main loop
+//!       |   // this logic gets the timing mostly right; altho the interrupt always
+//!       |   // happens after the CHIP-8 instruction is processed. i.e. wallclock
+//!       |   // timing will look good, but some things might happen too quickly anyway
+//!       |-- new_cycles = interpreter.cycle();
+//!       |-- while interrupt_queue.top().would_interrupt(cycles + new_cycles) {
+//!       |     sleep(some_proportion_of(new_cycles)); new_cycles -= that proportion;
+//!       |     interpreter.interrupt(REASON);
+//!       |     interrupt_queue.insert(interrupt_queue.pop())
+//!       |   }
+//!       `-- sleep(new_cycles * 4.54us)
+//!
+//! # Useful links
+//!
+//! * ROMs: <https://github.com/dmatlack/chip8/tree/master/roms>
+//!   <https://github.com/mir3z/chip8-emu/tree/master/roms>
+//! * COSMAC details: <https://laurencescotford.com/chip-8-on-the-cosmac-vip-index/>
+//!   <http://www.bitsavers.org/components/rca/cosmac/COSMAC_VIP_Instruction_Manual_1978.pdf>
+//! * variations: <https://chip-8.github.io/extensions/>
+
+// the core, which is all that's built with --no-default-features --features
+// core: enough to run a program, for anybody's frontend
+pub mod boot;
+pub mod cancel;
+pub mod cdp1802;
+pub mod clock;
+pub mod costs;
+pub mod debug;
 pub mod display;
+pub mod error;
+pub mod extension;
+pub mod font;
+pub mod heat;
 pub mod input;
 pub mod interpreter;
+pub mod interrupt;
+pub mod isa;
+pub mod keypad;
 pub mod memory;
+pub mod quirks;
+pub mod schip;
 pub mod sound;
+pub mod stable;
+pub mod timer;
+pub mod timing;
+pub mod trace;
+pub mod watch;
+pub mod window;
+
+// and everything else the emulator is. storage, and what it needs, are in
+// the web feature's builds too, to keep things in a browser
+#[cfg(feature = "full")]
+pub mod achievement;
+#[cfg(feature = "full")]
+pub mod analyse;
+#[cfg(feature = "full")]
+pub mod art;
+#[cfg(feature = "full")]
+pub mod asm;
+#[cfg(feature = "full")]
+pub mod attract;
+#[cfg(feature = "full")]
+pub mod audio;
+#[cfg(feature = "full")]
+pub mod blend;
+#[cfg(feature = "full")]
+pub mod bridge;
+#[cfg(feature = "full")]
+pub mod bundle;
+#[cfg(feature = "full")]
+pub mod calibrate;
+#[cfg(feature = "full")]
+pub mod chat;
+#[cfg(feature = "full")]
+pub mod cheat;
+#[cfg(any(feature = "full", feature = "web"))]
+pub mod clipboard;
+#[cfg(feature = "full")]
+pub mod config;
+#[cfg(feature = "full")]
+pub mod corpus;
+#[cfg(feature = "full")]
+pub mod decompile;
+#[cfg(feature = "full")]
+pub mod detect;
+#[cfg(feature = "full")]
+pub mod diag;
+#[cfg(feature = "full")]
+pub mod differential;
+#[cfg(feature = "full")]
+pub mod dual;
+#[cfg(feature = "full")]
+pub mod frameskip;
+#[cfg(feature = "full")]
+pub mod gallery;
+#[cfg(feature = "full")]
+pub mod gamepad;
+#[cfg(feature = "gpio")]
+pub mod gpio;
+#[cfg(feature = "gui")]
+pub mod gui;
+#[cfg(feature = "full")]
+pub mod hotkey;
+#[cfg(any(feature = "full", feature = "web"))]
+pub mod lang;
+#[cfg(feature = "full")]
+pub mod menu;
+#[cfg(feature = "full")]
+pub mod metrics;
+#[cfg(feature = "full")]
+pub mod netplay;
+#[cfg(feature = "full")]
+pub mod ocr;
+#[cfg(feature = "full")]
+pub mod optimise;
+#[cfg(feature = "full")]
+pub mod palette;
+#[cfg(feature = "full")]
+pub mod patch;
+#[cfg(feature = "full")]
+pub mod paths;
+#[cfg(any(feature = "full", feature = "web"))]
+pub mod persist;
+#[cfg(feature = "full")]
+pub mod platform;
+#[cfg(feature = "full")]
+pub mod png;
+#[cfg(feature = "full")]
+pub mod profile;
+#[cfg(feature = "full")]
+pub mod record;
+#[cfg(feature = "full")]
+pub mod recover;
+#[cfg(feature = "full")]
+pub mod render;
+#[cfg(feature = "full")]
+pub mod replay;
+#[cfg(feature = "full")]
+pub mod report;
+#[cfg(feature = "full")]
+pub mod rominfo;
+#[cfg(feature = "full")]
+pub mod romtest;
+#[cfg(all(feature = "sdl", feature = "full"))]
+pub mod sdl;
+#[cfg(feature = "full")]
+pub mod search;
+#[cfg(feature = "full")]
+pub mod selftest;
+#[cfg(feature = "full")]
+pub mod service;
+#[cfg(feature = "full")]
+pub mod session;
+#[cfg(feature = "full")]
+pub mod settings;
+#[cfg(feature = "full")]
+pub mod slots;
+#[cfg(feature = "full")]
+pub mod spectate;
+#[cfg(feature = "full")]
+pub mod sprite;
+#[cfg(feature = "full")]
+pub mod stats;
+#[cfg(any(feature = "full", feature = "web"))]
+pub mod storage;
+#[cfg(feature = "full")]
+pub mod stress;
+#[cfg(feature = "full")]
+pub mod tape;
+#[cfg(feature = "full")]
+pub mod termcaps;
+#[cfg(feature = "full")]
+pub mod thumbnail;
+#[cfg(feature = "full")]
+pub mod touch;
+#[cfg(feature = "full")]
+pub mod vip;
@@ -1,38 +1,2689 @@
+use std::cell::{Cell, RefCell};
 use std::env;
 use std::error::Error;
-use std::fs::File;
+use std::fs::{self, File};
+use std::io::{self as stdio, BufRead, BufReader, BufWriter, IsTerminal, Write};
+use std::iter;
+use std::net::{TcpListener, UdpSocket};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use chip8::display::MonoTermDisplay;
-use chip8::input::StdinInput;
-use chip8::interpreter::Chip8Interpreter;
-use chip8::sound::Mute;
+use chip8::achievement::AchievementSet;
+use chip8::analyse::{self, Severity};
+use chip8::art::{self, Art, ArtFormat};
+use chip8::asm::{self, LineTable};
+use chip8::attract::{self, Playlist};
+use chip8::audio::AUDIO_PLAYER_BUFFER;
+use chip8::blend::{self, Blend};
+use chip8::boot::Boot;
+use chip8::bridge::HostBridge;
+use chip8::bundle::Bundle;
+use chip8::calibrate::Calibration;
+use chip8::cdp1802::ClockRate;
+use chip8::chat::{self, ChatInput, CHAT_DEFAULT_WINDOW};
+use chip8::cheat::{self, CheatEngine};
+use chip8::clipboard;
+use chip8::clock::{Clock, SpinClock, SystemClock};
+use chip8::config::{self, Config};
+use chip8::corpus::Case;
+use chip8::costs::Costs;
+use chip8::decompile;
+use chip8::detect;
+use chip8::diag;
+use chip8::differential;
+use chip8::display::{
+    Cells, Display, DummyDisplay, PlaneColours, Scale, SplitHalf, SplitTermDisplay, Theme,
+};
+use chip8::dual;
+use chip8::error::Chip8Error;
+use chip8::font::SchipFont;
+use chip8::frameskip::{FrameSkip, SkipPolicy};
+use chip8::gallery::{self, Choice};
+use chip8::gamepad::{ForceFeedback, Genre, Joystick, PadInput, PadMap, RumbleOn};
+use chip8::hotkey::{self, Action, Hotkeys};
+use chip8::input::{self, DummyInput, Input, SlotRequest, SpeedRequest, StdinInput, VolumeRequest};
+use chip8::interpreter::{
+    Chip8Interpreter, InterpreterState, Overruns, RunOutcome, Verbosity, CHIP8_SPEEDS,
+};
+use chip8::interrupt::RefreshRate;
+use chip8::isa::{self, Variant};
+use chip8::keypad::{Assist, KeyRepeat};
+use chip8::lang::{self, Language};
+use chip8::memory::{Chip8MemoryMap, MemoryMap};
+use chip8::menu::{MenuAction, PauseMenu};
+use chip8::metrics::{self, Metrics, MetricsCollector};
+use chip8::netplay::{self, HashTap, Lockstep, LockstepInput};
+use chip8::optimise;
+use chip8::palette::{self, Command, Palette};
+use chip8::patch;
+use chip8::paths;
+use chip8::platform::{Frontend, TerminalOptions};
+use chip8::png;
+use chip8::profile::Profile;
+use chip8::quirks::Quirks;
+use chip8::record::{DisplayRecording, RecordingDisplay, VideoRecorder};
+use chip8::recover::PanicPolicy;
+use chip8::replay::{self, Demo, FrameHasher, PlaybackInput, RecordingInput, Replay};
+use chip8::report::{BugReport, REPORT_TRACE_LINES};
+use chip8::rominfo;
+use chip8::schip::Schip;
+use chip8::selftest;
+use chip8::service::{self, Limits, Service};
+use chip8::session::Session;
+use chip8::settings::{Settings, SettingsHistory};
+use chip8::slots::SaveSlots;
+use chip8::sound::{self, Mute, Sound, ToneRecorder, UiCue};
+use chip8::spectate::{BroadcastInput, Broadcaster, SpectatorInput};
+use chip8::sprite;
+use chip8::stats::{ScoreWatch, Stats};
+use chip8::storage::FileStorage;
+use chip8::stress::{self, StressOptions, STRESS_RATE_MAX};
+use chip8::tape;
+use chip8::termcaps::{Capabilities, Colours};
+use chip8::thumbnail::{self, ThumbnailCache};
+use chip8::timing::Timing;
+use chip8::trace::{self, TraceEntry, TraceFormat, TraceReader, TraceRing, TraceWriter};
+use chip8::vip::VipMachine;
+use chip8::watch::{Watch, WatchSet};
+use chip8::window::Scaling;
+use crossterm::style::ResetColor;
+use crossterm::terminal::{self, ClearType};
+use crossterm::{cursor, execute};
+
+/// how far --diff-quirks looks: about a minute of a typical ROM
+const DIFF_MAX_INSTRUCTIONS: u64 = 500_000;
+/// or a minute of one that spends its time waiting, e.g. drawing
+const DIFF_MAX_FRAMES: u64 = 3600;
+/// and --check-opcodes, likewise
+const CHECK_MAX_INSTRUCTIONS: u64 = 500_000;
+/// frames --render-thread lets pile up before dropping them
+const RENDER_QUEUE_FRAMES: usize = 2;
+/// how long an optimised ROM has to behave like the original, at least
+const OPTIMISE_VERIFY_FRAMES: u64 = 600;
+/// how many frames a headless or uncapped run lets FX0A wait with no keys
+/// to come before giving up: ten seconds, at 60Hz
+const KEY_WATCHDOG_FRAMES: u64 = 600;
+/// the exit status when it does, so a sweep through a pile of ROMs can
+/// tell them from ones that went wrong (1)
+const KEY_DEADLOCK_EXIT_STATUS: i32 = 3;
+/// source lines shown either side of the one being stepped through
+const STEP_SOURCE_CONTEXT: usize = 2;
+/// what runs when no ROM's given, from a roms directory (see paths.rs);
+/// without it, the gallery comes up instead
+const DEFAULT_ROM: &str = "trip8_demo.ch8";
+/// how long the slot picker waits for a key at a time
+const SLOT_PICKER_WAIT: Duration = Duration::from_millis(250);
 
 fn main() -> Result<(), Box<dyn Error>> {
+    // anything kept where it went in older versions, to where it goes now
+    paths::migrate();
+
     // read cli args
-    let rom_path = match env::args().nth(1) {
+    let mut rom_path = None;
+    let mut remaps = Vec::new();
+    let mut rebindings = Vec::new();
+    let mut gamepad_path = None;
+    let mut chat_from = None;
+    let mut chat_window = CHAT_DEFAULT_WINDOW;
+    let mut touch = false;
+    let mut key_repeat = KeyRepeat::TERMINAL;
+    let mut assist = Assist::default();
+    let mut language = None;
+    let mut pad_profile = None;
+    let mut rumble_on = None;
+    let mut cheat_changes = Vec::new();
+    let mut pokes = Vec::new();
+    let mut uncapped = false;
+    let mut key_watchdog = None;
+    let mut host_bridge = false;
+    let mut calibrate = false;
+    let mut verbosity = Verbosity::Normal;
+    let mut audio_path = None;
+    let mut video_path = None;
+    let mut record_replay_path = None;
+    let mut replay_path = None;
+    let mut record_demo = false;
+    let mut netplay = None;
+    let mut input_delay = netplay::NET_DEFAULT_DELAY;
+    let mut broadcast_addr = None;
+    let mut metrics_addr = None;
+    let mut watches = WatchSet::default();
+    let mut spectate_addr = None;
+    let mut monitor = false;
+    let mut load_tape_path = None;
+    let mut patch_path = None;
+    let mut save_tape_path = None;
+    let mut compare_path = None;
+    let mut compare_quirks = None;
+    let mut quirks = Quirks::default();
+    let mut quirks_given = false;
+    let mut diff_quirks = None;
+    let mut auto_quirks = false;
+    let mut detect_quirks = false;
+    let mut lint = false;
+    let mut check_opcodes = false;
+    let mut info = false;
+    let mut frame_skip = None;
+    let mut blend = None;
+    let mut render_thread = false;
+    let mut idle_wait = true;
+    let mut audio_buffer = None;
+    let mut hud = false;
+    let mut heat_map = false;
+    let mut volume = None;
+    let mut tutorial = false;
+    let mut schip = false;
+    let mut split_instructions = false;
+    let mut shear = false;
+    let mut refresh_rate = None;
+    let mut clock_rate = None;
+    let mut timing = None;
+    let mut costs = None;
+    let mut boot = Boot::default();
+    let mut speed = None;
+    let mut profile_name = None;
+    let mut profiles = None;
+    let mut bundle = None;
+    let mut gallery = false;
+    let mut resume = false;
+    let mut save_session = false;
+    let mut resume_crash = false;
+    let mut frontend = Frontend::Terminal;
+    let mut theme = Theme::default();
+    let mut theme_given = false;
+    let mut invert = false;
+    let mut cells = None;
+    let mut plane_colours = None;
+    let mut scale = None;
+    let mut terminal_caps = None;
+    let mut scaling = None;
+    let mut on_panic = None;
+    let mut diag = None;
+    let mut self_test = false;
+    let mut serve = None;
+    let mut stress = None;
+    let mut attract = None;
+    let mut play_display = None;
+    let mut display_recording_path = None;
+    let mut emulated = false;
+    let mut trace_path = None;
+    let mut trace_format = None;
+    let mut trace_convert = None;
+    let mut export_art = None;
+    let mut art_format = None;
+    let mut trace_diff = None;
+    let mut assemble = None;
+    let mut optimise_paths = None;
+    let mut decompile_paths = None;
+    let mut sprite_paths = None;
+    let mut inject_paths = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            // remap a key for this ROM, and remember it for next time
+            "--map" => match args.next() {
+                Some(m) => remaps.push(config::parse_remap(&m)?),
+                None => return Err("--map needs an argument, e.g. --map j=4".into()),
+            },
+            // move a hotkey, for every ROM, and remember it for next time
+            "--hotkey" => match args.next() {
+                Some(b) => rebindings.push(hotkey::parse_binding(&b)?),
+                None => return Err("--hotkey needs an argument, e.g. --hotkey help=f1".into()),
+            },
+            // play with a gamepad too, e.g. /dev/input/js0
+            "--gamepad" => match args.next() {
+                Some(p) => gamepad_path = Some(p),
+                None => return Err("--gamepad needs a device, e.g. /dev/input/js0".into()),
+            },
+            // let a stream's chat vote for keys, from bots connecting to
+            // an address, or piped in with - (see chat.rs)
+            "--chat" => match args.next() {
+                Some(c) => chat_from = Some(c),
+                None => return Err("--chat needs an address, or - for stdin".into()),
+            },
+            // how many frames chat's votes are counted over
+            "--chat-window" => match args.next().map(|n| n.parse()) {
+                Some(Ok(n)) if n > 0 => chat_window = n,
+                _ => return Err("--chat-window needs a number of frames".into()),
+            },
+            // draw the keypad, and take taps (or clicks) on it as presses
+            "--touch" => touch = true,
+            // how long the terminal takes to start repeating a held key, and
+            // then between repeats, in frames, e.g. --key-repeat 40,6
+            "--key-repeat" => match args.next() {
+                Some(r) => {
+                    key_repeat = KeyRepeat {
+                        suppress: key_repeat.suppress,
+                        ..KeyRepeat::parse(&r)?
+                    }
+                }
+                None => return Err("--key-repeat needs a delay and interval, e.g. 40,6".into()),
+            },
+            // a held key only counts once, however often the terminal
+            // repeats it
+            "--suppress-repeats" => key_repeat.suppress = true,
+            // a tap holds a key down until it's tapped again
+            "--sticky-keys" => assist.sticky = true,
+            // run at half speed while a key's down, to give time to react
+            "--slow-motion" => assist.slow_motion = true,
+            // which keys the gamepad presses: paddle, maze or shooter, if
+            // the ROM's not one we know the genre of (or it's wrong)
+            "--pad-profile" => match args.next() {
+                Some(g) => pad_profile = Some(Genre::parse(&g)?),
+                None => return Err("--pad-profile needs paddle, maze or shooter".into()),
+            },
+            // shake the gamepad when the buzzer sounds, or sprites collide,
+            // or both, e.g. --rumble buzzer,collision
+            "--rumble" => match args.next() {
+                Some(r) => rumble_on = Some(RumbleOn::parse(&r)?),
+                None => return Err("--rumble needs buzzer, collision or both".into()),
+            },
+            // which language to talk in, e.g. --lang fr, over the config's
+            // and the locale's
+            "--lang" => match args.next() {
+                Some(l) => language = Some(Language::parse(&l)?),
+                None => return Err("--lang needs a language, e.g. --lang fr".into()),
+            },
+            // sample some memory every frame, to plot from the pause menu's
+            // watch command, e.g. --watch 0x3a0:2
+            "--watch" => match args.next() {
+                Some(w) => watches.add(Watch::parse(&w)?),
+                None => return Err("--watch needs an address, e.g. --watch 0x3a0:2".into()),
+            },
+            // run as fast as possible rather than at COSMAC speed
+            "--uncapped" => uncapped = true,
+            // how long FX0A can wait for a key with nothing left to press
+            // one, headless or uncapped: --key-watchdog 60, or 0 for ever
+            "--key-watchdog" => match args.next().map(|f| f.parse()) {
+                Some(Ok(f)) => key_watchdog = Some(f),
+                _ => return Err("--key-watchdog needs a number of frames".into()),
+            },
+            // let the ROM talk to the emulator through memory at 0x0e80, for
+            // testing ROMs (see bridge.rs)
+            "--host-bridge" => host_bridge = true,
+            // say less about falling behind the VIP: -q only how often it
+            // did at the end, -qq (or -q twice) not even that. -v warns
+            // every time, rather than once a frame, and --verbosity picks a
+            // level from 0 (silent) to 3 (verbose)
+            "--quiet" | "-q" => verbosity = verbosity.quieter(),
+            "-qq" => verbosity = verbosity.quieter().quieter(),
+            "--verbose" | "-v" => verbosity = verbosity.louder(),
+            "--verbosity" => match args.next() {
+                Some(v) => verbosity = Verbosity::parse(&v)?,
+                None => return Err("--verbosity needs a level, 0 (silent) to 3 (verbose)".into()),
+            },
+            // render the buzzer to a WAV file instead of the speaker
+            "--record-audio" => match args.next() {
+                Some(p) => audio_path = Some(p),
+                None => return Err("--record-audio needs a file name".into()),
+            },
+            // record what's on the screen to a y4m video
+            "--record-video" => match args.next() {
+                Some(p) => video_path = Some(p),
+                None => return Err("--record-video needs a file name".into()),
+            },
+            // record every frame the display's given, to play into another
+            // display later with play-display
+            "--record-display" => match args.next() {
+                Some(p) => display_recording_path = Some(p),
+                None => return Err("--record-display needs a file name".into()),
+            },
+            // keep the keys pressed with the ROM's config, for attract mode
+            // to play
+            "--record-demo" => record_demo = true,
+            // record keypresses and frame hashes so the run can be replayed
+            "--record-replay" => match args.next() {
+                Some(p) => record_replay_path = Some(p),
+                None => return Err("--record-replay needs a file name".into()),
+            },
+            // play back a recorded run, checking it comes out the same
+            "--replay" => match args.next() {
+                Some(p) => replay_path = Some(p),
+                None => return Err("--replay needs a file name".into()),
+            },
+            // two-player netplay: wait for a peer on this address...
+            "--host" => match args.next() {
+                Some(a) => netplay = Some((true, a)),
+                None => return Err("--host needs an address, e.g. --host 0.0.0.0:8008".into()),
+            },
+            // ...or connect to one waiting there
+            "--join" => match args.next() {
+                Some(a) => netplay = Some((false, a)),
+                None => return Err("--join needs an address, e.g. --join 10.0.0.1:8008".into()),
+            },
+            // frames between pressing a key and seeing it during netplay
+            "--input-delay" => match args.next().map(|d| d.parse()) {
+                Some(Ok(d)) => input_delay = d,
+                _ => return Err("--input-delay needs a number of frames".into()),
+            },
+            // serve frames, instructions, timing and who's connected for
+            // Prometheus to scrape, at /metrics
+            "--metrics" => match args.next() {
+                Some(a) => metrics_addr = Some(a),
+                None => {
+                    return Err("--metrics needs an address, e.g. --metrics 0.0.0.0:9108".into())
+                }
+            },
+            // let spectators watch this run live
+            "--broadcast" => match args.next() {
+                Some(a) => broadcast_addr = Some(a),
+                None => {
+                    return Err(
+                        "--broadcast needs an address, e.g. --broadcast 0.0.0.0:8009".into(),
+                    )
+                }
+            },
+            // watch someone else's run
+            "--spectate" => match args.next() {
+                Some(a) => spectate_addr = Some(a),
+                None => {
+                    return Err("--spectate needs an address, e.g. --spectate 10.0.0.1:8009".into())
+                }
+            },
+            // switch on a cheat for this ROM, defining it if need be, e.g.
+            // --cheat lives or --cheat lives=0x3a0:3
+            "--cheat" => match args.next() {
+                Some(c) => cheat_changes.push((cheat::parse_cheat(&c)?, true)),
+                None => return Err("--cheat needs a name, e.g. --cheat lives=0x3a0:3".into()),
+            },
+            "--no-cheat" => match args.next() {
+                Some(c) => cheat_changes.push(((c, None), false)),
+                None => return Err("--no-cheat needs a name".into()),
+            },
+            // write to memory once, as soon as the program starts
+            "--poke" => match args.next() {
+                Some(p) => pokes.push(cheat::parse_poke(&p)?),
+                None => return Err("--poke needs an argument, e.g. --poke 0x3a0=3".into()),
+            },
+            // run the whole VIP, starting in its hex monitor
+            "--monitor" => monitor = true,
+            // put an IPS or BPS patch on the ROM as it's loaded
+            "--patch" => match args.next() {
+                Some(p) => patch_path = Some(p),
+                None => return Err("--patch needs an IPS or BPS file".into()),
+            },
+            // load the program from a cassette recording rather than a ROM file
+            "--load-tape" => match args.next() {
+                Some(p) => load_tape_path = Some(p),
+                None => return Err("--load-tape needs a WAV file name".into()),
+            },
+            // and save the program to one on the way out
+            "--save-tape" => match args.next() {
+                Some(p) => save_tape_path = Some(p),
+                None => return Err("--save-tape needs a WAV file name".into()),
+            },
+            // run the ROM under two quirks profiles side by side, on the
+            // same keys, to see which it wants, e.g. --compare vip,modern;
+            // or another ROM alongside it, to compare them
+            "--compare" => {
+                match args.next() {
+                    Some(p) if p.contains(',') && !Path::new(&p).exists() => {
+                        let (a, b) = p.split_once(',').unwrap_or_default();
+                        compare_quirks = Some([Quirks::profile(a)?, Quirks::profile(b)?]);
+                    }
+                    Some(p) => compare_path = Some(p),
+                    None => return Err(
+                        "--compare needs two quirks profiles or a ROM, e.g. --compare vip,modern"
+                            .into(),
+                    ),
+                }
+            }
+            // behave like a later interpreter, e.g. --quirks chip48, with
+            // any odd quirks on top, e.g. --quirks vip+add-i-sets-vf, or
+            // --quirks auto to guess
+            "--quirks" => match args.next() {
+                Some(q) if q == "auto" => (auto_quirks, quirks_given) = (true, true),
+                Some(q) => (quirks, quirks_given) = (Quirks::profile(&q)?, true),
+                None => return Err("--quirks needs a profile, e.g. --quirks modern".into()),
+            },
+            // say where the ROM first behaves differently under two profiles
+            "--diff-quirks" => match args.next().as_deref().map(|q| q.split_once(',')) {
+                Some(Some((a, b))) => {
+                    diff_quirks = Some([Quirks::profile(a)?, Quirks::profile(b)?])
+                }
+                _ => {
+                    return Err(
+                        "--diff-quirks needs two profiles, e.g. --diff-quirks vip,modern".into(),
+                    )
+                }
+            },
+            // understand the SUPER-CHIP's extra instructions
+            "--schip" => schip = true,
+            // let interrupts land part way through long instructions, as on
+            // the VIP
+            "--split-instructions" => split_instructions = true,
+            // show sprites tearing as if drawn while the frame goes out
+            "--shear" => shear = true,
+            // interrupt (and so count down the timers) at 50Hz, as on a PAL
+            // machine, or whatever a ROM expects: --refresh-rate pal
+            "--refresh-rate" => match args.next() {
+                Some(r) => refresh_rate = Some(RefreshRate::parse(&r)?),
+                None => return Err("--refresh-rate needs pal, ntsc or a number of Hz".into()),
+            },
+            // run the 1802 at another clock, as an overclocked VIP does,
+            // getting more done each frame: --clock-rate 3.52
+            "--clock-rate" => match args.next() {
+                Some(r) => clock_rate = Some(ClockRate::parse(&r)?),
+                None => return Err("--clock-rate needs vip or a number of MHz".into()),
+            },
+            // charge the interrupt, DMA and fetching at another interpreter's
+            // rates, e.g. --timing free, or --timing vip+interrupt=850
+            "--timing" => match args.next() {
+                Some(t) => timing = Some(Timing::preset(&t)?),
+                None => return Err("--timing needs a preset, e.g. --timing vip".into()),
+            },
+            // charge the program's instructions another way, e.g. --costs
+            // flat for a modern interpreter's, or --costs vip+DXYN=200
+            "--costs" => match args.next() {
+                Some(c) => costs = Some(Costs::preset(&c)?),
+                None => return Err("--costs needs a preset, e.g. --costs flat".into()),
+            },
+            // come up as a VIP does from being switched on, with junk in
+            // memory, or (fast, as usual) straight into the program
+            "--boot" => match args.next() {
+                Some(b) => boot = Boot::parse(&b)?,
+                None => return Err("--boot needs fast or cold".into()),
+            },
+            // start at one of the speeds the hotkeys go through, e.g.
+            // --speed 2 for twice the VIP's
+            "--speed" => match args.next().map(|s| s.parse()) {
+                Some(Ok(s)) if CHIP8_SPEEDS.contains(&s) => speed = Some(s),
+                _ => return Err(format!("--speed needs one of {:?}", CHIP8_SPEEDS).into()),
+            },
+            // play with a shared profile's keys, quirks and speed, by name
+            // or file (see profile.rs)
+            "--profile" => match args.next() {
+                Some(p) => profile_name = Some(p),
+                None => return Err("--profile needs a name, e.g. --profile schip-modern".into()),
+            },
+            // list, share and use profiles: chip8 profiles list, chip8
+            // profiles import FILE, chip8 profiles export NAME OUT (with
+            // --quirks, --schip, --map and --speed), or chip8 profiles
+            // apply NAME ROM to play the ROM with it from now on
+            "profiles" if rom_path.is_none() && profiles.is_none() => {
+                let command = args.next().unwrap_or_default();
+                let wanted =
+                    match command.as_str() {
+                        "list" => 0,
+                        "import" => 1,
+                        "export" | "apply" => 2,
+                        _ => return Err(
+                            "profiles needs list, import FILE, export NAME OUT or apply NAME ROM"
+                                .into(),
+                        ),
+                    };
+                let operands: Vec<String> = args.by_ref().take(wanted).collect();
+                if operands.len() < wanted {
+                    return Err(
+                        format!("profiles {} needs {} more argument(s)", command, wanted).into(),
+                    );
+                }
+                profiles = Some((command, operands));
+            }
+            // write an executable that plays the ROM as soon as it's run,
+            // with --quirks, --schip, --map and --speed (or --profile), to
+            // give to anyone: chip8 bundle game.ch8 --out mygame
+            "bundle" if rom_path.is_none() && bundle.is_none() => match args.next() {
+                Some(p) => bundle = Some((p, None)),
+                None => return Err("bundle needs a ROM, e.g. bundle game.ch8 --out mygame".into()),
+            },
+            "--out" => match (&mut bundle, args.next()) {
+                (Some((_, out)), Some(p)) => *out = Some(p),
+                _ => return Err("--out needs bundle, and where to write it".into()),
+            },
+            // time the host's sleeping and drawing at startup, to spin
+            // rather than sleep and skip frames if it needs to. it's off
+            // unless asked for (--no-calibrate is what it always was, for
+            // scripts that said so)
+            "--calibrate" => calibrate = true,
+            "--no-calibrate" => calibrate = false,
+            // keep time frame by frame even while the program's only
+            // waiting for a key, rather than resting until one comes
+            "--no-idle-wait" => idle_wait = false,
+            // draw fewer frames when the terminal can't keep up
+            "--frame-skip" => match args.next() {
+                Some(s) => frame_skip = Some(SkipPolicy::parse(&s)?),
+                None => return Err("--frame-skip needs auto or a number".into()),
+            },
+            // light what was lit in the last few frames too, to hide flicker
+            "--blend" => match args.next() {
+                Some(s) => blend = Some(blend::parse_frames(&s)?),
+                None => return Err("--blend needs 2 or 3 frames".into()),
+            },
+            // draw on a thread of its own, so the terminal never holds us up
+            "--render-thread" => render_thread = true,
+            // how many milliseconds of sound the sound card's player holds:
+            // less is closer to the picture, but more likely to stutter.
+            // chip8 diag av says how far apart they are
+            "--audio-buffer" => match args.next().map(|m| m.parse()) {
+                Some(Ok(m)) if m > 0 => audio_buffer = Some(Duration::from_millis(m)),
+                _ => return Err("--audio-buffer needs a number of milliseconds".into()),
+            },
+            // classic or high-contrast
+            "--theme" => match args.next() {
+                Some(t) => (theme, theme_given) = (Theme::parse(&t)?, true),
+                None => return Err("--theme needs classic or high-contrast".into()),
+            },
+            // XO-CHIP's four colours, as #rrggbb separated by commas, in
+            // place of Octo's
+            "--plane-colours" => {
+                match args.next() {
+                    Some(c) => plane_colours = Some(PlaneColours::parse(&c)?),
+                    None => return Err(
+                        "--plane-colours needs four colours, e.g. #000000,#ffffff,#ff6600,#662200"
+                            .into(),
+                    ),
+                }
+            }
+            // dark pixels on a light background
+            "--invert" => invert = true,
+            // lit pixels as blocks and unlit ones blank, so they don't
+            // depend on telling colours apart
+            "--glyphs" => theme.glyphs = true,
+            // pixels per character: block (1), quadrant (2x2) or sextant (2x3)
+            "--cells" => match args.next() {
+                Some(c) => cells = Some(Cells::parse(&c)?),
+                None => return Err("--cells needs block, quadrant, sextant or ascii".into()),
+            },
+            // draw each pixel bigger; 2x1 makes them square in most terminals
+            "--scale" => match args.next() {
+                Some(s) => scale = Some(Scale::parse(&s)?),
+                None => return Err("--scale needs a size, e.g. --scale 2x1".into()),
+            },
+            // say what the terminal can do, where it's been taken for
+            // something it isn't, e.g. --terminal-caps 256,unicode
+            "--terminal-caps" => match args.next() {
+                Some(c) => terminal_caps = Some(c),
+                None => return Err("--terminal-caps needs e.g. 256,unicode or 80x25".into()),
+            },
+            // how a window fits the picture in: integer, aspect or stretch,
+            // with +smooth to blend the pixels, e.g. --scaling aspect+smooth
+            "--scaling" => match args.next() {
+                Some(s) => scaling = Some(Scaling::parse(&s)?),
+                None => return Err("--scaling needs integer, aspect or stretch".into()),
+            },
+            // what to do if the emulator panics: recover (stop tidily, with
+            // the terminal put back and the machine kept for --resume-crash)
+            // or unwind (let it, backtrace and all)
+            "--on-panic" => match args.next() {
+                Some(p) => on_panic = Some(PanicPolicy::parse(&p)?),
+                None => return Err("--on-panic needs recover or unwind".into()),
+            },
+            // where to draw and read keys: terminal, text (the screen
+            // described in words, for screen readers) or headless, or gui
+            // (a window with a debugger round it) if it's built with gui,
+            // or sdl (a window of SDL2's) if it's built with sdl
+            "--frontend" => match args.next() {
+                Some(f) => frontend = Frontend::parse(&f)?,
+                None => return Err(format!("--frontend needs {}", Frontend::names()).into()),
+            },
+            // start paused, to step through the program an instruction at a
+            // time with explanations
+            "--tutorial" => tutorial = true,
+            // start with the HUD showing (tab toggles it)
+            "--hud" => hud = true,
+            // start with the picture coloured by where it's being drawn
+            // (the palette's "heat map" toggles it)
+            "--heat-map" => heat_map = true,
+            // how loud to beep, as a percentage, from now on ([ and ] change
+            // it while playing, and m mutes the ROM)
+            "--volume" => match args.next().and_then(|v| v.parse::<u8>().ok()) {
+                Some(v) if v <= 100 => volume = Some(v),
+                _ => return Err("--volume needs a percentage, e.g. --volume 50".into()),
+            },
+            // guess which profile the ROM wants, and why
+            "--detect-quirks" => detect_quirks = true,
+            // every instruction this build knows, tab-separated
+            "--list-opcodes" => {
+                for opcode in isa::ISA.iter() {
+                    println!("{}", opcode);
+                }
+                return Ok(());
+            }
+            // run the ROM for a bit and say what it wanted that we haven't got
+            "--check-opcodes" => check_opcodes = true,
+            // what we know about the ROM, and what it looks like
+            "--info" => info = true,
+            // pick one of the built-in demos to play
+            "--gallery" => gallery = true,
+            // carry on from the session saved last time, and save it again
+            // on the way out
+            "--resume" => resume = true,
+            // save the session on the way out, for --resume
+            "--save-session" => save_session = true,
+            // go back to a frame or so before the last fault, paused, to
+            // step into it
+            "--resume-crash" => resume_crash = true,
+            // check the display, input or audio works: chip8 diag display
+            "diag" if rom_path.is_none() && diag.is_none() => diag = args.next(),
+            // check the emulator itself, e.g. that it runs the same way
+            // every time: chip8 selftest
+            "selftest" if rom_path.is_none() => self_test = true,
+            // run machines for anyone who asks over HTTP, e.g. a web
+            // playground: chip8 serve 127.0.0.1:8008 (see service.rs)
+            "serve" if rom_path.is_none() && serve.is_none() => match args.next() {
+                Some(a) => serve = Some((a, Limits::default(), Vec::new())),
+                None => return Err("serve needs an address, e.g. 127.0.0.1:8008".into()),
+            },
+            // how many machines serve runs at once
+            "--serve-sessions" => match (&mut serve, args.next().map(|n| n.parse())) {
+                (Some((_, l, _)), Some(Ok(n))) => l.sessions = n,
+                _ => return Err("--serve-sessions needs serve, and a number of machines".into()),
+            },
+            // how many frames a second each of serve's machines can run
+            "--serve-fps" => match (&mut serve, args.next().map(|n| n.parse())) {
+                (Some((_, l, _)), Some(Ok(n))) => l.frames_per_second = n,
+                _ => return Err("--serve-fps needs serve, and frames a second".into()),
+            },
+            // how much host time each of serve's machines can take, in
+            // milliseconds a second
+            "--serve-cpu" => match (&mut serve, args.next().map(|n| n.parse())) {
+                (Some((_, l, _)), Some(Ok(ms))) => l.cpu_per_second = Duration::from_millis(ms),
+                _ => return Err("--serve-cpu needs serve, and milliseconds a second".into()),
+            },
+            // how many seconds one of serve's machines can sit idle before
+            // it's got rid of
+            "--serve-idle" => match (&mut serve, args.next().map(|n| n.parse())) {
+                (Some((_, l, _)), Some(Ok(s))) => l.idle = Duration::from_secs(s),
+                _ => return Err("--serve-idle needs serve, and a number of seconds".into()),
+            },
+            // a page that can call serve from a browser, e.g.
+            // https://example.com (as many times over as there are)
+            "--serve-origin" => {
+                match (&mut serve, args.next()) {
+                    (Some((_, _, o)), Some(origin)) => o.push(origin),
+                    _ => return Err(
+                        "--serve-origin needs serve, and a page's origin, e.g. https://example.com"
+                            .into(),
+                    ),
+                }
+            }
+            // run the ROM headless with the keypad hammered, checking the
+            // input path holds up: chip8 stress game.ch8 (see stress.rs)
+            "stress" if rom_path.is_none() && stress.is_none() => {
+                stress = Some(StressOptions::default())
+            }
+            "--stress-rate" => match (&mut stress, args.next().map(|r| r.parse::<f64>())) {
+                (Some(s), Some(Ok(r))) if r.is_finite() && r > 0.0 && r <= STRESS_RATE_MAX => {
+                    s.rate = r
+                }
+                _ => {
+                    return Err(format!(
+                        "--stress-rate needs stress, and presses a second (up to {})",
+                        STRESS_RATE_MAX
+                    )
+                    .into())
+                }
+            },
+            "--stress-frames" => match (&mut stress, args.next().map(|f| f.parse())) {
+                (Some(s), Some(Ok(f))) => s.frames = f,
+                _ => return Err("--stress-frames needs stress, and a number of frames".into()),
+            },
+            "--stress-seed" => match (&mut stress, args.next().map(|n| n.parse())) {
+                (Some(s), Some(Ok(n))) => s.seed = n,
+                _ => return Err("--stress-seed needs stress, and a number".into()),
+            },
+            // play what --record-display recorded into whichever frontend,
+            // without running anything: chip8 play-display run.txt
+            "play-display" if rom_path.is_none() && play_display.is_none() => match args.next() {
+                Some(p) => play_display = Some(p),
+                None => return Err("play-display needs a display recording".into()),
+            },
+            // run the ROMs on a playlist in turn, for a kiosk: chip8
+            // attract playlist.toml (see attract.rs)
+            "attract" if rom_path.is_none() && attract.is_none() => match args.next() {
+                Some(p) => attract = Some(p),
+                None => return Err("attract needs a playlist".into()),
+            },
+            // write every instruction run to a file, in a format going by
+            // its extension (.jsonl, .csv, .bin or text) unless told
+            "--trace" => match args.next() {
+                Some(p) => trace_path = Some(p),
+                None => return Err("--trace needs a file name".into()),
+            },
+            "--trace-format" => match args.next() {
+                Some(f) => trace_format = Some(TraceFormat::parse(&f)?),
+                None => return Err("--trace-format needs text, jsonl, csv or bin".into()),
+            },
+            // from one trace format to another: chip8 trace-convert a.bin b.jsonl
+            "trace-convert" if rom_path.is_none() && trace_convert.is_none() => {
+                match (args.next(), args.next()) {
+                    (Some(from), Some(to)) => trace_convert = Some((from, to)),
+                    _ => return Err("trace-convert needs a trace and where to put it".into()),
+                }
+            }
+            // a savestate's screen as ANSI art, text or an SVG, e.g. for a
+            // blog post: chip8 export-art 3.toml pong.svg (see art.rs)
+            "export-art" if rom_path.is_none() && export_art.is_none() => {
+                match (args.next(), args.next()) {
+                    (Some(from), Some(to)) => export_art = Some((from, to)),
+                    _ => return Err("export-art needs a savestate and where to put it".into()),
+                }
+            }
+            // what export-art makes, if not what its extension says
+            "--art-format" => match args.next() {
+                Some(f) => art_format = Some(ArtFormat::parse(&f)?),
+                None => return Err("--art-format needs ansi, text, ascii or svg".into()),
+            },
+            // where two traces part ways, e.g. ours and another emulator's:
+            // chip8 trace-diff mine.jsonl theirs.jsonl
+            "trace-diff" if rom_path.is_none() && trace_diff.is_none() => {
+                match (args.next(), args.next()) {
+                    (Some(mine), Some(theirs)) => trace_diff = Some((mine, theirs)),
+                    _ => return Err("trace-diff needs two traces to compare".into()),
+                }
+            }
+            // look over a ROM (or source) for mistakes without running it:
+            // chip8 lint game.ch8
+            "lint" if rom_path.is_none() => lint = true,
+            // a program's source to a ROM: chip8 asm game.8o game.ch8
+            "asm" if rom_path.is_none() && assemble.is_none() => match (args.next(), args.next()) {
+                (Some(from), Some(to)) => assemble = Some((from, to)),
+                _ => return Err("asm needs a source file and where to put the ROM".into()),
+            },
+            // a ROM back to source: chip8 decompile game.ch8 game.8o
+            "decompile" if rom_path.is_none() && decompile_paths.is_none() => {
+                match (args.next(), args.next()) {
+                    (Some(from), Some(to)) => decompile_paths = Some((from, to)),
+                    _ => return Err("decompile needs a ROM and where to put the source".into()),
+                }
+            }
+            // a ROM's sprites, on a sheet to edit: chip8 sprites game.ch8 sheet.png
+            "sprites" if rom_path.is_none() && sprite_paths.is_none() => {
+                match (args.next(), args.next()) {
+                    (Some(rom), Some(sheet)) => sprite_paths = Some((rom, sheet)),
+                    _ => return Err("sprites needs a ROM and where to put the sheet".into()),
+                }
+            }
+            // and back again, into a copy of the ROM:
+            // chip8 inject-sprites game.ch8 sheet.png modded.ch8
+            "inject-sprites" if rom_path.is_none() && inject_paths.is_none() => {
+                match (args.next(), args.next(), args.next()) {
+                    (Some(rom), Some(sheet), Some(to)) => inject_paths = Some((rom, sheet, to)),
+                    _ => {
+                        return Err(
+                            "inject-sprites needs a ROM, its sheet and where to put the result"
+                                .into(),
+                        )
+                    }
+                }
+            }
+            // an experimental peephole pass, checked against the original by
+            // running both (on --replay's keys, if there's one):
+            // chip8 optimise game.ch8 smaller.ch8
+            "optimise" if rom_path.is_none() && optimise_paths.is_none() => {
+                match (args.next(), args.next()) {
+                    (Some(from), Some(to)) => optimise_paths = Some((from, to)),
+                    _ => return Err("optimise needs a ROM and where to put the result".into()),
+                }
+            }
+            // diag input through a ROM that reads the keys, not just the
+            // keyboard
+            "--emulated" => emulated = true,
+            _ => rom_path = Some(arg),
+        }
+    }
+
+    if invert {
+        theme = theme.inverted();
+    }
+    if diag.is_some() || attract.is_some() || play_display.is_some() {
+        let colours = match frontend {
+            Frontend::Terminal => {
+                fit_terminal(terminal_caps.as_deref(), &mut cells, &mut scale, false)?
+            }
+            _ => Colours::TrueColour,
+        };
+        let options = TerminalOptions {
+            status: None,
+            render_queue: render_thread.then_some(RENDER_QUEUE_FRAMES),
+            theme,
+            plane_colours,
+            cells,
+            scale,
+            colours,
+            hotkeys: Hotkeys::default(),
+            touch,
+            key_repeat,
+            assist,
+            audio_buffer,
+            scaling: scaling.unwrap_or_default(),
+            window: None,
+        };
+        // the keys as they'd be playing: --profile's, with --map's over them
+        let mut keymap = input::conventional_keymap();
+        if let Some(p) = &profile_name {
+            keymap.extend(
+                Profile::find(&FileStorage, &Profile::default_dir(), p)?.keymap_overrides()?,
+            );
+        }
+        keymap.extend(remaps.iter().copied());
+        if let Some(what) = diag {
+            return run_diag(&what, emulated, frontend, keymap, options);
+        }
+        if let Some(p) = attract {
+            return run_attract(&p, frontend, keymap, options);
+        }
+        if let Some(p) = play_display {
+            return run_play_display(&p, frontend, refresh_rate, options);
+        }
+    }
+    if self_test {
+        let mut corpus = Case::builtin();
+        corpus.extend(Case::list(&FileStorage, &Case::default_dir())?);
+        let checks = selftest::selftest(&corpus)?;
+        for check in &checks {
+            println!("{}", check);
+        }
+        let failed = checks.iter().filter(|c| c.result.is_err()).count();
+        if failed > 0 {
+            return Err(format!("{} of {} checks failed", failed, checks.len()).into());
+        }
+        return Ok(());
+    }
+    // everything kept between runs is kept in files
+    let storage = FileStorage;
+    if let Some((from, to)) = export_art {
+        let session = match Session::load(&storage, Path::new(&from))? {
+            Some(s) => s,
+            None => return Err(format!("there's no savestate at {}", from).into()),
+        };
+        let (data, width, height) = art::screen(&session)?;
+        let format = art_format.unwrap_or_else(|| ArtFormat::from_path(Path::new(&to)));
+        let art = Art {
+            format,
+            cells: cells.unwrap_or(Cells::Block),
+            scale: scale.unwrap_or(format.default_scale()),
+            theme,
+        };
+        let text = art.render(&data, width, height);
+        match to.as_str() {
+            "-" => print!("{}", text),
+            _ => fs::write(&to, text)?,
+        }
+        return Ok(());
+    }
+    if let Some((addr, limits, origins)) = serve {
+        let listener = TcpListener::bind(&addr)?;
+        println!(
+            "serving machines on http://{}/machines",
+            listener.local_addr()?
+        );
+        service::serve(listener, Service::new(limits).with_origins(origins));
+        return Ok(());
+    }
+    if let Some((from, to)) = trace_convert {
+        let n = trace::convert(
+            BufReader::new(File::open(&from)?),
+            TraceFormat::from_path(Path::new(&from)),
+            BufWriter::new(File::create(&to)?),
+            trace_format.unwrap_or_else(|| TraceFormat::from_path(Path::new(&to))),
+        )?;
+        println!("{} instructions", n);
+        return Ok(());
+    }
+    if let Some((from, to)) = assemble {
+        let program = asm::assemble_file(Path::new(&from))?;
+        fs::write(&to, &program.rom)?;
+        println!("{} bytes", program.rom.len());
+        return Ok(());
+    }
+    if let Some((from, to)) = decompile_paths {
+        let rom = fs::read(&from)?;
+        let name = Path::new(&from).file_name().unwrap_or_default();
+        let source = decompile::decompile(&name.to_string_lossy(), &rom);
+        fs::write(&to, &source)?;
+        println!("{} lines", source.lines().count());
+        return Ok(());
+    }
+    if let Some((from, to)) = sprite_paths {
+        let rom = fs::read(&from)?;
+        let sprites = sprite::find(&rom);
+        if sprites.is_empty() {
+            return Err(format!("couldn't find anything {} draws", from).into());
+        }
+        for s in &sprites {
+            println!("{}", s);
+        }
+        fs::write(&to, png::write(&sprite::sheet(&rom, &sprites)))?;
+        println!("{} sprite(s)", sprites.len());
+        return Ok(());
+    }
+    if let Some((from, sheet, to)) = inject_paths {
+        let mut rom = fs::read(&from)?;
+        let n = inject_sprites(&sheet, &rom.clone(), |addr, bytes| {
+            let start = addr as usize - 0x200;
+            rom[start..start + bytes.len()].copy_from_slice(bytes);
+            Ok(())
+        })?;
+        fs::write(&to, &rom)?;
+        println!("{}", lang::format("menu.sprites-changed", &[&n]));
+        return Ok(());
+    }
+    if let Some((from, to)) = optimise_paths {
+        let rom = match asm::is_source(Path::new(&from)) {
+            true => asm::assemble_file(Path::new(&from))?.rom,
+            false => fs::read(&from)?,
+        };
+        let optimised = optimise::optimise(&rom)?;
+        for change in &optimised.changes {
+            println!("{}", change);
+        }
+        let replay = match &replay_path {
+            Some(p) => Some(Replay::read(BufReader::new(File::open(p)?))?),
+            None => None,
+        };
+        let frames = replay
+            .as_ref()
+            .map_or(0, |r| r.frames.len() as u64)
+            .max(OPTIMISE_VERIFY_FRAMES);
+        optimise::verify(&rom, &optimised, replay.as_ref(), frames)?;
+        fs::write(&to, &optimised.rom)?;
+        println!(
+            "{} bytes, down from {}, and the same for {} frames",
+            optimised.rom.len(),
+            rom.len(),
+            frames
+        );
+        return Ok(());
+    }
+    if let Some((mine, theirs)) = trace_diff {
+        let read = |path: &str| -> Result<Vec<TraceEntry>, Chip8Error> {
+            let input = BufReader::new(File::open(path)?);
+            TraceReader::new(input, TraceFormat::from_path(Path::new(path)))?.collect()
+        };
+        let (mine, theirs) = (read(&mine)?, read(&theirs)?);
+        let (mine, theirs) = differential::align(&mine, &theirs);
+        match differential::trace_divergence(mine, theirs) {
+            Some((n, d)) => {
+                print!("{}", d);
+                // what went into it, and what each got out
+                for (label, trace) in [("mine:  ", mine), ("theirs:", theirs)] {
+                    for entry in &trace[n.saturating_sub(1)..=n] {
+                        println!("  {} {}", label, entry);
+                    }
+                }
+            }
+            None => println!(
+                "no difference in the {} instructions both traces have",
+                mine.len().min(theirs.len())
+            ),
+        }
+        return Ok(());
+    }
+
+    // which ROM, and what it's called
+    let config_path = Config::default_path();
+    lang::set_language(language.unwrap_or_else(Language::from_env));
+    let mut config = Config::load(&storage, &config_path)?;
+    if let (None, Some(l)) = (language, &config.language) {
+        // a language the config's been given by hand is no reason not to
+        // start
+        match Language::parse(l) {
+            Ok(l) => lang::set_language(l),
+            Err(e) => {
+                lang::set_language(Language::English);
+                eprintln!("{}", lang::format("warning.bad-language", &[&e]));
+            }
+        }
+    }
+    if let (None, Some(t)) = (timing, &config.timing) {
+        timing = Some(Timing::preset(t)?);
+    }
+    if let (None, Some(c)) = (&costs, &config.costs) {
+        costs = Some(Costs::preset(c)?);
+    }
+    if let (None, Some(r)) = (clock_rate, &config.clock_rate) {
+        clock_rate = Some(ClockRate::parse(r)?);
+    }
+    if let (None, Some(s)) = (scaling, &config.scaling) {
+        scaling = Some(Scaling::parse(s)?);
+    }
+    if let (None, Some(p)) = (on_panic, &config.on_panic) {
+        on_panic = Some(PanicPolicy::parse(p)?);
+    }
+    let on_panic = on_panic.unwrap_or_default();
+    if let Some((command, operands)) = profiles {
+        let dir = Profile::default_dir();
+        match (command.as_str(), operands.as_slice()) {
+            ("import", [from]) => {
+                let profile = Profile::load(&storage, Path::new(from))?;
+                println!("installed {}", profile.install(&storage, &dir)?.display());
+            }
+            ("export", [name, to]) => {
+                let profile = profile_of(name, schip, quirks, speed, &remaps)?;
+                match to.as_str() {
+                    "-" => print!("{}", profile.to_toml()?),
+                    _ => profile.save(&storage, Path::new(to))?,
+                }
+            }
+            ("apply", [name, rom]) => {
+                let profile = Profile::find(&storage, &dir, name)?;
+                profile.check_rom(&fs::read(rom)?, CHECK_MAX_INSTRUCTIONS)?;
+                // one from a file is installed, to be found again by name
+                if Profile::find(&storage, &dir, &profile.name).ok().as_ref() != Some(&profile) {
+                    profile.install(&storage, &dir)?;
+                }
+                let rom_name = rominfo::rom_name(Path::new(rom));
+                config.rom_mut(&rom_name).profile = Some(profile.name.clone());
+                config.save(&storage, &config_path)?;
+                println!("{} plays with {} from now on", rom_name, profile.name);
+            }
+            _ => {
+                for p in Profile::list(&storage, &dir)? {
+                    let variant = if p.schip { "SUPER-CHIP" } else { "CHIP-8" };
+                    println!(
+                        "{:16} {:10} {:12} x{:<4} {}",
+                        p.name, variant, p.quirks, p.speed, p.description
+                    );
+                }
+            }
+        }
+        return Ok(());
+    }
+    if let Some((from, out)) = bundle {
+        let rom = fs::read(&from)?;
+        let profile = match &profile_name {
+            Some(p) => Profile::find(&storage, &Profile::default_dir(), p)?,
+            None => profile_of("bundle", schip, quirks, speed, &remaps)?,
+        };
+        profile.check_rom(&rom, CHECK_MAX_INSTRUCTIONS)?;
+        let rom_name = rominfo::rom_name(Path::new(&from));
+        // beside the ROM, called after it, unless it's been told
+        let out = out.map_or_else(|| Path::new(&from).with_extension(""), PathBuf::from);
+        let written = Bundle {
+            rom_name,
+            rom,
+            profile,
+        }
+        .save(&out)?;
+        println!(
+            "wrote {}, which plays {} when it's run",
+            written.display(),
+            from
+        );
+        return Ok(());
+    }
+    let session_path = Session::default_path();
+    let mut session = match (resume, resume_crash) {
+        (true, _) => match Session::load(&storage, &session_path)? {
+            Some(s) => Some(s),
+            None => return Err("there's no session to resume".into()),
+        },
+        (_, true) => match Session::load(&storage, &paths::crash_file())? {
+            Some(s) => Some(s),
+            None => return Err("there's been no crash to go back to".into()),
+        },
+        _ => None,
+    };
+    if let Some(s) = &session {
+        hud = s.hud;
+        schip = s.schip;
+    }
+    // a bundle plays the ROM it was made with, unless it's given another
+    let mut bundled = match (&rom_path, &load_tape_path, &session) {
+        (None, None, None) => Bundle::current()?,
+        _ => None,
+    };
+    let nothing_to_run = rom_path.is_none()
+        && load_tape_path.is_none()
+        && session.is_none()
+        && bundled.is_none()
+        && paths::find_rom(DEFAULT_ROM).is_none();
+    let mut rom_path = match rom_path {
         Some(p) => p,
-        None => "roms/trip8_demo.ch8".to_string(),
+        None => paths::find_rom(DEFAULT_ROM).map_or(DEFAULT_ROM.to_string(), |p| {
+            p.to_string_lossy().into_owned()
+        }),
+    };
+    let thumbnails = ThumbnailCache::new(&ThumbnailCache::default_dir());
+    let stats_path = Stats::default_path();
+    let mut stats = Stats::load(&storage, &stats_path)?;
+    let mut gallery_rom = None;
+    if gallery || nothing_to_run {
+        let choice = gallery::choose(&thumbnails, &mut config, &stats)?;
+        // for the favourites
+        config.save(&storage, &config_path)?;
+        match choice {
+            Some(Choice::File(p)) => rom_path = p,
+            Some(c) => gallery_rom = Some(c),
+            None => return Ok(()),
+        }
+    }
+    if gallery_rom.is_some() {
+        bundled = None;
+    }
+    let rom_name = match (&session, &gallery_rom, &bundled) {
+        (Some(s), _, _) => s.rom_name.clone(),
+        (_, Some(c), _) => c.name(),
+        (_, _, Some(b)) => b.rom_name.clone(),
+        _ => rominfo::rom_name(Path::new(&rom_path)),
     };
 
+    if volume.is_some() {
+        config.volume = volume;
+        config.save(&storage, &config_path)?;
+    }
+    let volume = config.volume(&rom_name);
+    // what was kept from changing it while playing comes before a profile's
+    let kept = config.roms.get(&rom_name);
+    speed = speed.or(kept.and_then(|r| r.speed));
+    if let (false, Some(q)) = (quirks_given, kept.and_then(|r| r.quirks)) {
+        (quirks, quirks_given) = (q, true);
+    }
+    if let (false, Some(t)) = (theme_given, &config.theme) {
+        theme = Theme {
+            glyphs: theme.glyphs,
+            ..Theme::parse(t)?
+        };
+        if invert {
+            theme = theme.inverted();
+        }
+    }
+    if let (None, Some(c)) = (plane_colours, &config.plane_colours) {
+        plane_colours = Some(PlaneColours::parse(c)?);
+    }
+    // a profile's settings, where nothing more particular's been asked for
+    let profile = match profile_name.as_deref().or_else(|| {
+        config
+            .roms
+            .get(&rom_name)
+            .and_then(|r| r.profile.as_deref())
+    }) {
+        Some(p) => Some(Profile::find(&storage, &Profile::default_dir(), p)?),
+        None => bundled.as_ref().map(|b| b.profile.clone()),
+    };
+    if let Some(p) = &profile {
+        p.check_variant(schip)?;
+        schip |= p.schip;
+        if !quirks_given {
+            quirks = p.quirks()?;
+        }
+        speed = speed.or(Some(p.speed));
+    }
+    // --hotkey's bindings, kept once they're known to work
+    let mut bindings = config.hotkeys.clone();
+    bindings.extend(rebindings);
+
+    // figure out the keymap for this ROM
+    if !remaps.is_empty() || !cheat_changes.is_empty() {
+        let rom_config = config.rom_mut(&rom_name);
+        for (host_key, key) in remaps {
+            rom_config.remap(host_key, key)?;
+        }
+        for ((name, cheat), enabled) in cheat_changes {
+            if let Some(cheat) = cheat {
+                rom_config.cheats.insert(name.clone(), cheat);
+            }
+            match rom_config.cheats.get_mut(&name) {
+                Some(c) => c.enabled = enabled,
+                None => return Err(format!("no cheat called \"{}\"", name).into()),
+            }
+        }
+        config.save(&storage, &config_path)?;
+    }
+    let mut cheats = CheatEngine::new(
+        config
+            .roms
+            .get(&rom_name)
+            .map(|r| r.cheats.clone())
+            .unwrap_or_default(),
+    );
+    for (addr, value) in pokes {
+        cheats.poke(addr, value);
+    }
+    let mut keymap = input::conventional_keymap();
+    if let Some(p) = &profile {
+        keymap.extend(p.keymap_overrides()?);
+    }
+    if let Some(rom_config) = config.roms.get(&rom_name) {
+        keymap.extend(rom_config.keymap_overrides()?);
+    }
+    let (hotkeys, shadowed) = Hotkeys::from_config(&bindings)?.beside(&keymap)?;
+    if bindings != config.hotkeys {
+        config.hotkeys = bindings;
+        config.save(&storage, &config_path)?;
+    }
+    // the number keys are often the keypad's, and the slot picker loads
+    // slots without them
+    let shadowed = shadowed
+        .into_iter()
+        .filter(|(_, a)| !matches!(a, Action::LoadSlot(_)));
+    for (key, action) in shadowed {
+        let name = action.name();
+        eprintln!(
+            "{}",
+            lang::format("warning.hotkey-on-keypad", &[&key, &name])
+        );
+    }
+
+    // source is assembled first, and which line each instruction came from
+    // kept for stepping through it
+    let mut source_lines = None;
+    let rom = match &load_tape_path {
+        _ if session.is_some() => session.as_ref().map(|s| s.rom.clone()).unwrap_or_default(),
+        Some(p) => tape::read_tape(&mut BufReader::new(File::open(p)?))?,
+        None => match &gallery_rom {
+            Some(c) => c.rom()?,
+            None if bundled.is_some() => {
+                bundled.as_ref().map(|b| b.rom.clone()).unwrap_or_default()
+            }
+            None if asm::is_source(Path::new(&rom_path)) => {
+                let program = asm::assemble_file(Path::new(&rom_path))?;
+                source_lines = Some(program.lines);
+                program.rom
+            }
+            None => fs::read(&rom_path)?,
+        },
+    };
+    // a resumed session's ROM had its patch put on already
+    let rom = match &patch_path {
+        Some(p) if session.is_none() => patch::apply(&rom, &fs::read(p)?)?,
+        _ => rom,
+    };
+    // (an assembled program's data can be any length it likes)
+    if rom.len() % 2 == 1 && source_lines.is_none() {
+        eprintln!("{}", lang::format("warning.odd-size", &[&rom.len()]));
+    }
+    if let Some(profiles) = diff_quirks {
+        match differential::first_divergence(
+            &rom,
+            profiles,
+            DIFF_MAX_INSTRUCTIONS,
+            DIFF_MAX_FRAMES,
+        )? {
+            Some(d) => print!("{}", d),
+            None => println!(
+                "no divergence within {} instructions or {} frames",
+                DIFF_MAX_INSTRUCTIONS, DIFF_MAX_FRAMES
+            ),
+        }
+        return Ok(());
+    }
+    if detect_quirks {
+        print!("{}", detect::detect(&rom)?);
+        return Ok(());
+    }
+    if lint {
+        let lints = analyse::lint(&rom);
+        for l in &lints {
+            println!("{}", l);
+        }
+        let count = |s: Severity| lints.iter().filter(|l| l.severity == s).count();
+        let errors = count(Severity::Error);
+        println!(
+            "{} error(s), {} warning(s), {} note(s)",
+            errors,
+            count(Severity::Warning),
+            count(Severity::Note)
+        );
+        if errors > 0 {
+            return Err(format!("{} has errors", rom_name).into());
+        }
+        return Ok(());
+    }
+    if check_opcodes {
+        let variants = match schip {
+            true => vec![Variant::Chip8, Variant::Schip],
+            false => vec![Variant::Chip8],
+        };
+        let found = isa::unsupported(&rom, &variants, CHECK_MAX_INSTRUCTIONS)?;
+        if found.is_empty() {
+            println!(
+                "nothing unsupported in the first {} instructions",
+                CHECK_MAX_INSTRUCTIONS
+            );
+        }
+        for u in found {
+            println!("{}", u);
+        }
+        return Ok(());
+    }
+    if info {
+        match rominfo::lookup(&rom_name) {
+            Some(i) => println!("{}", i.status_line(&keymap)),
+            None => println!("{}", rom_name),
+        }
+        println!(
+            "{} bytes, hash {:016x}",
+            rom.len(),
+            replay::frame_hash(&rom)
+        );
+        println!("{}", stats.rom(&rom_name).describe());
+        for line in thumbnail::braille(&thumbnails.get(&rom)?) {
+            println!("{}", line);
+        }
+        return Ok(());
+    }
+    if load_tape_path.is_none() && gallery_rom.is_none() && session.is_none() && bundled.is_none() {
+        if let Ok(p) = fs::canonicalize(&rom_path) {
+            config.add_recent(&p.to_string_lossy());
+            config.save(&storage, &config_path)?;
+        }
+    }
+    if auto_quirks {
+        quirks = detect::detect(&rom)?.quirks;
+    }
+    if let Some(mut options) = stress {
+        options.quirks = quirks;
+        options.schip = schip;
+        let report = stress::stress(&rom, &options)?;
+        println!("{}", report);
+        // the emulator's fault, not the ROM's: cut down, and kept for the
+        // self-test to check on
+        if let (true, Some(found)) = (report.panicked, &report.failure) {
+            let case = Case {
+                name: format!("{}-{:04x}", rom_name, options.seed as u16),
+                found: found.clone(),
+                rom: rom.clone(),
+                quirks: options.quirks.name().unwrap_or_default(),
+                schip,
+                demo: Demo::new(options.seed as u16, &report.keys),
+            };
+            match case.panics() {
+                true => {
+                    let path = case.minimise().save(&storage, &Case::default_dir())?;
+                    println!("kept in {}, for chip8 selftest", path.display());
+                }
+                false => println!("it didn't panic again through the stable API, so it's not kept"),
+            }
+        }
+        if report.failure.is_some() {
+            return Err(format!("{} didn't hold up", rom_name).into());
+        }
+        return Ok(());
+    }
+    // what the terminal can draw with, and so how, unless that's been said
+    let caps = terminal_caps.as_deref().or(config.terminal_caps.as_deref());
+    let colours = match frontend {
+        Frontend::Terminal => fit_terminal(caps, &mut cells, &mut scale, schip)?,
+        _ => Colours::TrueColour,
+    };
+    let compare = match (compare_quirks, compare_path) {
+        (Some([a, b]), _) => Some([(rom.clone(), a), (rom.clone(), b)].map(|(rom, q)| {
+            let name = format!("{} ({})", rom_name, q.name().unwrap_or_default());
+            (rom, name, q)
+        })),
+        (None, Some(p)) => Some([
+            (rom.clone(), rom_name.clone(), quirks),
+            (fs::read(&p)?, rominfo::rom_name(Path::new(&p)), quirks),
+        ]),
+        (None, None) => None,
+    };
+    if let Some(machines) = compare {
+        let theme = colours.theme(theme);
+        return run_compare(machines, keymap, hotkeys, key_repeat, theme);
+    }
+
     // initialise
     // TODO: decouple internal and external resolution; make interpreter responsible for former
-    let mut display = MonoTermDisplay::new(64, 32)?;
-    let mut input = StdinInput::new();
-    let mut sound = Mute::new();
-    let mut interpreter = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+    let status = rominfo::lookup(&rom_name).map(|info| info.status_line(&keymap));
+    let replay = match &replay_path {
+        Some(p) => Some(Replay::read(BufReader::new(File::open(p)?))?),
+        None => None,
+    };
+
+    // find our netplay peer and agree on a seed with it
+    let (mut lockstep, netplay_seed) = match netplay {
+        Some(_) if replay.is_some() || spectate_addr.is_some() => {
+            return Err("can't play back a replay or spectate over netplay".into());
+        }
+        Some((host, addr)) => {
+            let socket = if host {
+                UdpSocket::bind(&addr)?
+            } else {
+                let socket = UdpSocket::bind("0.0.0.0:0")?;
+                socket.connect(&addr)?;
+                socket
+            };
+            let rom_hash = replay::frame_hash(&rom);
+            let seed = netplay::handshake(&socket, host, rand::random(), rom_hash)?;
+            let lockstep = Lockstep::new(socket, input_delay, seed, rom_hash)?;
+            (Some(lockstep), Some(seed))
+        }
+        None => (None, None),
+    };
+    let mut spectator = match spectate_addr {
+        Some(_) if replay.is_some() => return Err("can't play back a replay and spectate".into()),
+        Some(a) => Some(SpectatorInput::connect(a)?),
+        None => None,
+    };
+
+    // everyone playing along has to use the same seed
+    let seed = match (&replay, netplay_seed, &spectator) {
+        (Some(r), _, _) => r.seed,
+        (_, Some(s), _) => s,
+        (_, _, Some(s)) => s.seed(),
+        _ => rand::random(),
+    };
+    let mut broadcaster = match broadcast_addr {
+        Some(a) => Some(Broadcaster::new(TcpListener::bind(a)?, seed)?),
+        None => None,
+    };
+    let metrics = match metrics_addr {
+        Some(a) => {
+            let metrics = Arc::new(Metrics::default());
+            metrics::serve(TcpListener::bind(a)?, metrics.clone());
+            if let Some(l) = &mut lockstep {
+                l.set_metrics(metrics.clone());
+            }
+            if let Some(b) = &mut broadcaster {
+                b.set_metrics(metrics.clone());
+            }
+            Some(metrics)
+        }
+        None => None,
+    };
+
+    // the keyboard's only ours if we're the ones playing
+    let keymap = match (&replay, &spectator) {
+        (None, None) => Some(keymap),
+        _ => None,
+    };
+    let options = TerminalOptions {
+        status,
+        render_queue: render_thread.then_some(RENDER_QUEUE_FRAMES),
+        theme,
+        plane_colours,
+        cells,
+        scale,
+        colours,
+        hotkeys: hotkeys.clone(),
+        touch,
+        key_repeat,
+        assist,
+        audio_buffer,
+        scaling: scaling.unwrap_or_default(),
+        window: config.window,
+    };
+    let mut platform = frontend.platform(keymap, options)?;
+    // the gui's, which sees the machine, and pauses and steps it, in place
+    // of the terminal's prompt
+    let debugger = platform.debugger();
+    let whereabouts = platform.whereabouts();
+    let (display, platform_input, platform_sound) = platform.devices();
+    let mut recorder = ToneRecorder::new();
+    let sound: &mut dyn Sound = match audio_path {
+        Some(_) => &mut recorder,
+        None => platform_sound,
+    };
+    // see how well the host sleeps and draws, if asked to, to know how long
+    // to spin for and whether to skip frames (if not told to). it's all
+    // sleeping, so there's no point when uncapped
+    let calibration = match calibrate && !uncapped {
+        true => Some(Calibration::measure(&SystemClock::new(), display)?),
+        false => None,
+    };
+    if let Some(c) = &calibration {
+        display.notify(&format!("timing: {}", c));
+        frame_skip = frame_skip.or(c.skip_policy());
+    }
+    // only the terminal misses out on skipped frames; recordings get them all
+    let draw_clock = SystemClock::new();
+    let mut skipper;
+    let display: &mut dyn Display = match frame_skip {
+        Some(policy) => {
+            skipper = FrameSkip::new(display, policy, &draw_clock);
+            &mut skipper
+        }
+        None => display,
+    };
+    // blended ahead of any skipping, so it's blended from every frame, but
+    // behind the recordings, which want them as they were
+    let mut blender;
+    let display: &mut dyn Display = match blend.or(config.blend.get(frontend.name()).copied()) {
+        Some(frames) if frames > 1 => {
+            blender = Blend::new(display, frames);
+            &mut blender
+        }
+        _ => display,
+    };
+    let mut video;
+    let display: &mut dyn Display = match video_path {
+        Some(p) => {
+            let out = BufWriter::new(File::create(p)?);
+            video = VideoRecorder::new(out, 64, 32, 4, Some(display));
+            // a resumed session carries on at the rate it was going at
+            video.set_refresh_rate(
+                refresh_rate
+                    .or(session.as_ref().map(|s| s.state.refresh_rate()))
+                    .unwrap_or_default(),
+            );
+            &mut video
+        }
+        None => display,
+    };
+    let mut display_recorder;
+    let display: &mut dyn Display = match display_recording_path {
+        Some(p) => {
+            let out = BufWriter::new(File::create(p)?);
+            display_recorder = RecordingDisplay::new(out, 64, 32, Some(display));
+            &mut display_recorder
+        }
+        None => display,
+    };
+    let mut hasher = None;
+    let display: &mut dyn Display =
+        if record_replay_path.is_some() || replay.is_some() || spectator.is_some() {
+            hasher.insert(FrameHasher::new(Some(display)))
+        } else {
+            display
+        };
+    let latest_hash = Cell::new(None);
+    let mut tap;
+    let display: &mut dyn Display = if lockstep.is_some() || broadcaster.is_some() {
+        tap = HashTap::new(display, &latest_hash);
+        &mut tap
+    } else {
+        display
+    };
+
+    // keys come from the keyboard (and the gamepad, and chat), possibly
+    // shared with a netplay peer, unless we're playing back a replay or
+    // watching someone else
+    let mut pad_input;
+    let mut chat_input;
+    let mut lockstep_input;
+    let mut broadcast_input;
+    let mut playback;
+    let mut recording = None;
+    let input: &mut dyn Input = match (&replay, &mut spectator) {
+        (Some(r), _) => {
+            playback = PlaybackInput::new(r.keys());
+            &mut playback
+        }
+        (_, Some(s)) => s,
+        _ => {
+            let input: &mut dyn Input = match &gamepad_path {
+                Some(p) => {
+                    let info = rominfo::lookup(&rom_name);
+                    // a maze's diamond suits most games that don't say
+                    let genre = pad_profile
+                        .or(info.and_then(|i| i.genre))
+                        .unwrap_or(Genre::Maze);
+                    let map = PadMap::for_rom(genre, info);
+                    pad_input = PadInput::new(platform_input, Joystick::open(p)?, map);
+                    pad_input.set_assist(assist);
+                    if let Some(on) = rumble_on {
+                        pad_input.set_rumble(Box::new(ForceFeedback::open(p)?), on);
+                    }
+                    &mut pad_input
+                }
+                None => platform_input,
+            };
+            let input: &mut dyn Input = match chat_from.as_deref() {
+                Some("-") => {
+                    chat_input = ChatInput::new(input, chat::stdin(), chat_window);
+                    &mut chat_input
+                }
+                Some(addr) => {
+                    let lines = chat::listen(TcpListener::bind(addr)?);
+                    chat_input = ChatInput::new(input, lines, chat_window);
+                    &mut chat_input
+                }
+                None => input,
+            };
+            let input: &mut dyn Input = match lockstep {
+                Some(l) => {
+                    lockstep_input = LockstepInput::new(input, l, &latest_hash);
+                    &mut lockstep_input
+                }
+                None => input,
+            };
+            let input: &mut dyn Input = match broadcaster {
+                Some(b) => {
+                    broadcast_input = BroadcastInput::new(input, b, &latest_hash);
+                    &mut broadcast_input
+                }
+                None => input,
+            };
+            if record_replay_path.is_some() || record_demo {
+                // the whole run for a replay, but only as much as attract
+                // mode plays of a demo
+                recording.insert(match record_replay_path {
+                    Some(_) => RecordingInput::new(input),
+                    None => RecordingInput::new(input).up_to(attract::demo_frames()),
+                })
+            } else {
+                input
+            }
+        }
+    };
+    if monitor {
+        sound.set_volume(volume)?;
+        return run_vip(&rom, display, input, sound, save_tape_path);
+    }
+
+    // the pause menu needs to get at these while the interpreter's using them
+    let achievements = RefCell::new(AchievementSet::load(
+        &storage,
+        &AchievementSet::default_path(&rom_name),
+    )?);
+    let cheats = RefCell::new(cheats);
+    let mut achievements_watch = &achievements;
+    let watches = RefCell::new(watches);
+    let mut watches_watch = &watches;
+    let mut cheats_patch = &cheats;
+    let bridge = RefCell::new(HostBridge::new(seed));
+    let mut bridge_watch = &bridge;
+    let mut bridge_patch = &bridge;
+    let mut schip_extension = Schip::new();
+    // the last few instructions, in case there's a bug to report
+    let trace = RefCell::new(TraceRing::new(REPORT_TRACE_LINES));
+    let mut tracer = &trace;
+    let collector = metrics.map(|m| RefCell::new(MetricsCollector::new(m)));
+    let (mut collector_watch, mut collector_tracer) = (collector.as_ref(), collector.as_ref());
+    let mut trace_writer = match &trace_path {
+        Some(p) => Some(TraceWriter::new(
+            BufWriter::new(File::create(p)?),
+            trace_format.unwrap_or_else(|| TraceFormat::from_path(Path::new(p))),
+        )),
+        None => None,
+    };
+    let mut debug_tracer = debugger.as_ref().map(|d| d.tracer());
+    // the best score, where it's known where it's kept
+    let score = config
+        .roms
+        .get(&rom_name)
+        .and_then(|r| r.score)
+        .or(rominfo::lookup(&rom_name).and_then(|i| i.score));
+    let mut score_watch = score.map(|s| ScoreWatch::new(s, stats.rom(&rom_name).best));
+    let spin = calibration.map(|c| SpinClock::new(c.spin().as_nanos() as u32));
+    let mut interpreter = Chip8Interpreter::new(display, input, sound)?;
+    if let Some(clock) = &spin {
+        interpreter.set_clock(clock);
+    }
+    interpreter.set_seed(seed);
+    interpreter.set_quirks(quirks);
+    interpreter.set_split_instructions(split_instructions);
+    interpreter.set_shear(shear);
+    interpreter.set_hud(hud);
+    interpreter.set_heat_map(heat_map);
+    interpreter.set_help(hotkeys.help());
+    interpreter.set_volume(volume)?;
+    interpreter.set_verbosity(verbosity);
+    interpreter.add_watch(&mut achievements_watch);
+    interpreter.add_watch(&mut watches_watch);
+    interpreter.add_patch(&mut cheats_patch);
+    interpreter.add_tracer(&mut tracer);
+    if let Some(w) = &mut trace_writer {
+        interpreter.add_tracer(w);
+    }
+    if let Some(t) = &mut debug_tracer {
+        interpreter.add_tracer(t);
+    }
+    if let Some(w) = &mut score_watch {
+        interpreter.add_watch(w);
+    }
+    if let (Some(w), Some(t)) = (&mut collector_watch, &mut collector_tracer) {
+        interpreter.add_watch(w);
+        interpreter.add_tracer(t);
+    }
+    if host_bridge {
+        interpreter.add_watch(&mut bridge_watch);
+        interpreter.add_patch(&mut bridge_patch);
+    }
+    if schip {
+        interpreter.add_extension(&mut schip_extension);
+        interpreter.set_font(&SchipFont)?;
+    }
+    let frame_count = replay.as_ref().map_or(18_000, |r| r.frames.len());
 
     // load a program
-    let mut f = File::open(rom_path)?;
+    interpreter.power_on(boot)?;
+    interpreter.load_program(&mut rom.as_slice())?;
+    match session.take() {
+        Some(s) => {
+            interpreter.restore(s.state)?;
+            interpreter.set_speed(s.speed);
+        }
+        None => {
+            if let Some(s) = speed {
+                interpreter.set_speed(s);
+            }
+        }
+    }
+    if let Some(r) = refresh_rate {
+        interpreter.set_refresh_rate(r);
+    }
+    if let Some(r) = clock_rate {
+        interpreter.set_clock_rate(r);
+    }
+    if let Some(t) = timing {
+        interpreter.set_timing(t);
+    }
+    if let Some(c) = &costs {
+        interpreter.set_cost_model(c);
+    }
+    interpreter.set_idle_wait(idle_wait);
+    interpreter.set_checkpoints(true);
+    if uncapped || frontend == Frontend::Headless {
+        match key_watchdog.unwrap_or(KEY_WATCHDOG_FRAMES) {
+            0 => {}
+            f => interpreter.set_key_watchdog(Some(f)),
+        }
+    }
+    // changes to the speed, quirks and theme while playing, to undo
+    let start_theme = theme;
+    let mut history = SettingsHistory::new(Settings {
+        speed: interpreter.speed(),
+        quirks: interpreter.quirks(),
+        theme: None,
+    });
+    let mut menu = PauseMenu::new();
+    let slots = SaveSlots::new(&storage, &SaveSlots::default_dir(&rom_name));
+    let started = Instant::now();
+    let result = if uncapped {
+        on_panic.run(|| interpreter.run_frames(frame_count as u64))
+    } else {
+        let mut paused = tutorial || resume_crash;
+        loop {
+            if !std::mem::take(&mut paused) {
+                let remaining = frame_count - interpreter.frames() as usize;
+                // a frame at a time, for the debugger to see each one
+                let run = match &debugger {
+                    Some(_) => remaining.min(1),
+                    None => remaining,
+                };
+                let mut outcome = on_panic.run(|| interpreter.main_loop(run));
+                if let Ok(RunOutcome::PaletteRequested) = outcome {
+                    let command = choose_command(&mut interpreter, &hotkeys)?;
+                    // the menu and the slots are the main loop's to look after
+                    match command
+                        .map(|c| {
+                            run_command(&mut interpreter, c, &mut history, start_theme, invert)
+                        })
+                        .transpose()?
+                    {
+                        Some(Some(o)) => outcome = Ok(o),
+                        _ => continue,
+                    }
+                }
+                match outcome {
+                    Ok(RunOutcome::MenuRequested) => {}
+                    Ok(RunOutcome::SlotRequested(request)) => {
+                        use_slot(&mut interpreter, &slots, request, &rom_name, &rom, schip)?;
+                        continue;
+                    }
+                    Ok(RunOutcome::Finished) if run < remaining => {
+                        if let Some(d) = &debugger {
+                            d.publish(&interpreter)?;
+                            if !d.take_pause_request() {
+                                continue;
+                            }
+                        }
+                    }
+                    // out of frames, or the program exited itself (00FD): both
+                    // are a clean stop, so a zero exit status
+                    r => break r.map(|_| ()),
+                }
+            }
+            if let Some(d) = &debugger {
+                match d.pause(&mut interpreter)? {
+                    true => {
+                        interpreter.input_mut().flush_keys()?;
+                        continue;
+                    }
+                    false => break Ok(()),
+                }
+            }
+            // escape pauses the game and drops to a prompt
+            interpreter.cue(UiCue::Click)?;
+            terminal::disable_raw_mode()?;
+            let mut stdout = stdio::stdout();
+            // chat's bot has stdin if it's piping its votes in, so the
+            // player's typing is read from the terminal a key at a time
+            let mut lines: Box<dyn Iterator<Item = Result<String, Chip8Error>>> =
+                match chat_from.as_deref() {
+                    Some("-") => Box::new(iter::from_fn(|| input::read_line().transpose())),
+                    _ => Box::new(stdio::stdin().lock().lines().map(|l| Ok(l?))),
+                };
+            if tutorial {
+                print!("\n{}", lang::text("menu.tutorial"));
+            }
+            if std::mem::take(&mut resume_crash) {
+                print!("\n{}", lang::text("menu.resume-crash"));
+            }
+            let action = loop {
+                print!("\n{}", lang::text("menu.paused"));
+                stdout.flush()?;
+                let line = match lines.next() {
+                    Some(line) => line?,
+                    None => break MenuAction::Quit,
+                };
+                // not matched on directly, so the borrows are over before
+                // stepping lets the watches have them
+                let action = menu.command(
+                    &line,
+                    interpreter.memory_mut(),
+                    &mut cheats.borrow_mut(),
+                    &mut achievements.borrow_mut(),
+                    &mut watches.borrow_mut(),
+                    &mut stdout,
+                )?;
+                match action {
+                    MenuAction::Stay => {}
+                    MenuAction::Step(n) => {
+                        for _ in 0..n {
+                            match explain_step(&mut interpreter, source_lines.as_ref(), &mut stdout)
+                            {
+                                // stepping into a fault's often the point
+                                Err(e)
+                                    if matches!(
+                                        interpreter.state(),
+                                        InterpreterState::Faulted { .. }
+                                    ) =>
+                                {
+                                    writeln!(stdout, "{}", e)?;
+                                    break;
+                                }
+                                r => r?,
+                            }
+                        }
+                    }
+                    MenuAction::CopyState => {
+                        let shared = session_of(&interpreter, &rom_name, &rom, schip).to_share()?;
+                        match clipboard::copy(&shared) {
+                            Ok(()) => writeln!(stdout, "{}", lang::text("menu.copied"))?,
+                            // there for the copying by hand instead
+                            Err(_) => writeln!(stdout, "{}", shared)?,
+                        }
+                    }
+                    MenuAction::PasteState(text) => {
+                        match paste_state(&mut interpreter, text, schip, &mut stdout) {
+                            Err(e @ Chip8Error::ConfigError(_)) => writeln!(stdout, "{}", e)?,
+                            r => r?,
+                        }
+                    }
+                    MenuAction::LoadSprites(sheet) => {
+                        let memory = interpreter.memory_mut();
+                        let loaded = inject_sprites(&sheet, &rom, |addr, bytes| {
+                            memory.write(bytes, addr, bytes.len())
+                        });
+                        match loaded {
+                            Ok(n) => {
+                                writeln!(stdout, "{}", lang::format("menu.sprites-changed", &[&n]))?
+                            }
+                            Err(e @ (Chip8Error::Io(_) | Chip8Error::BadImage(_))) => {
+                                writeln!(stdout, "{}", e)?
+                            }
+                            Err(e) => return Err(e.into()),
+                        }
+                    }
+                    MenuAction::Undo | MenuAction::Redo => {
+                        let redo = action == MenuAction::Redo;
+                        let said = undo_settings(
+                            &mut interpreter,
+                            &mut history,
+                            start_theme,
+                            invert,
+                            redo,
+                        );
+                        writeln!(stdout, "{}", said)?;
+                    }
+                    MenuAction::Report => {
+                        let report = BugReport {
+                            session: &session_of(&interpreter, &rom_name, &rom, schip),
+                            config: &config,
+                            trace: trace.borrow().entries().cloned().collect(),
+                            frames: interpreter.frames(),
+                            calibration,
+                            overruns: interpreter.overruns(),
+                        };
+                        let dir = paths::reports_dir();
+                        fs::create_dir_all(&dir)?;
+                        let path = dir.join(report.file_name());
+                        report.write(&mut BufWriter::new(File::create(&path)?))?;
+                        writeln!(
+                            stdout,
+                            "{}",
+                            lang::format("menu.report-saved", &[&path.display()])
+                        )?;
+                    }
+                    action => break action,
+                }
+            };
+            terminal::enable_raw_mode()?;
+            if action == MenuAction::Quit {
+                break Ok(());
+            }
+            // the key that opened the menu, or one latched while stepping,
+            // isn't the program's to see when it carries on
+            interpreter.input_mut().flush_keys()?;
+            interpreter.display_mut().refresh()?;
+        }
+    };
+    if let Some(p) = save_tape_path {
+        save_tape(&p, interpreter.memory(), rom.len())?;
+    }
+    if save_session || resume {
+        session_of(&interpreter, &rom_name, &rom, schip).save(&storage, &session_path)?;
+    }
+    // an instruction that went wrong (or the emulator, with a panic) leaves
+    // the machine as it was a frame or so before, to go back to and step into
+    let panicked = matches!(result, Err(Chip8Error::Panicked(_)));
+    let crashed = match (interpreter.state(), interpreter.checkpoint()) {
+        (state, Some(state_before))
+            if panicked || matches!(state, InterpreterState::Faulted { .. }) =>
+        {
+            let checkpoint = Session {
+                state: state_before.clone(),
+                ..session_of(&interpreter, &rom_name, &rom, schip)
+            };
+            checkpoint.save(&storage, &paths::crash_file())?;
+            true
+        }
+        _ => false,
+    };
+    let overruns = interpreter.overruns();
+    let final_volume = interpreter.volume();
+    catch_up(&interpreter, &mut history);
+    drop(interpreter);
+    if let Some(w) = &mut trace_writer {
+        w.flush()?;
+    }
+    // however it ended, it was played, if someone was at the keys: a replay,
+    // a spectator's view or a headless run aren't anyone's go at it
+    let played = !uncapped && frontend != Frontend::Headless;
+    if played && replay.is_none() && spectator.is_none() {
+        let best = score_watch.and_then(|w| w.highest());
+        stats.rom_mut(&rom_name).record(started.elapsed(), best);
+        stats.save(&storage, &stats_path)?;
+    }
+    let mut deadlock = None;
+    match result {
+        // a spectator keeps going until the broadcast stops
+        Err(Chip8Error::Io(e))
+            if spectator.is_some() && e.kind() == stdio::ErrorKind::UnexpectedEof => {}
+        // recordings and the like are still worth saving
+        Err(e @ Chip8Error::KeyDeadlock { .. }) => deadlock = Some(e),
+        // our fault, not the program's: rather than leave it on top of half
+        // a frame, start the screen afresh to say so
+        Err(e @ Chip8Error::Panicked(_)) => {
+            if frontend == Frontend::Terminal {
+                execute!(
+                    stdio::stdout(),
+                    ResetColor,
+                    terminal::Clear(ClearType::All),
+                    cursor::MoveTo(0, 0),
+                    cursor::Show
+                )?;
+            }
+            let said = lang::format("error.panicked", &[&e]);
+            return Err(match crashed {
+                true => format!("{}\n{}", said, lang::text("error.resume-crash")),
+                false => said,
+            }
+            .into());
+        }
+        Err(e) if crashed => {
+            return Err(format!(
+                "{} (chip8 --resume-crash goes back to just before, to step into it)",
+                e
+            )
+            .into())
+        }
+        r => r?,
+    }
 
-    interpreter.load_program(&mut f)?;
-    interpreter.main_loop(18_000)?;
+    // and where the window was left, if there was one
+    if let Some(g) = whereabouts
+        .and_then(|w| w.get())
+        .filter(|g| config.window != Some(*g))
+    {
+        config.window = Some(g);
+        config.save(&storage, &config_path)?;
+    }
+    // and the volume, if it was changed while playing
+    if final_volume != volume {
+        config.set_volume(&rom_name, final_volume);
+        config.save(&storage, &config_path)?;
+    }
+    // and the speed, quirks and theme, if they were changed and the player
+    // wants them that way from now on
+    if history.changed() && keep_settings()? {
+        let kept = history.now();
+        let rom_config = config.rom_mut(&rom_name);
+        (rom_config.speed, rom_config.quirks) = (Some(kept.speed), Some(kept.quirks));
+        if let Some(t) = kept.theme {
+            config.theme = Some(t.to_string());
+        }
+        config.save(&storage, &config_path)?;
+    }
+    if let (true, Some(r)) = (record_demo, &recording) {
+        let keys = r.keys();
+        let demo = &keys[..keys.len().min(attract::demo_frames())];
+        config.rom_mut(&rom_name).demo = Some(Demo::new(seed, demo));
+        config.save(&storage, &config_path)?;
+    }
+    // keep anything found from the pause menu for next time
+    if menu.cheats_changed() {
+        config.rom_mut(&rom_name).cheats = cheats.borrow().cheats().clone();
+        config.save(&storage, &config_path)?;
+    }
+    for line in menu.new_achievements() {
+        AchievementSet::append_line(&storage, &AchievementSet::default_path(&rom_name), line)?;
+    }
+
+    let hashes = hasher.as_ref().map_or(&[][..], |h| h.hashes());
+    if let (Some(p), Some(r)) = (record_replay_path, &recording) {
+        let replay = Replay::from_recording(seed, r.keys(), hashes);
+        replay.write(&mut BufWriter::new(File::create(p)?))?;
+    }
+    let replay = replay.or_else(|| spectator.map(|s| s.replay()));
+    let divergence = replay.as_ref().and_then(|r| r.first_divergence(hashes));
 
-    // test card for the display
-    //display.test_card()?;
+    if let Some(p) = audio_path {
+        recorder.write_wav(&mut File::create(p)?)?;
+    }
 
     // shove some junk on stdout to stop the cli messing up the last frame
     for _ in 0..12 {
         println!();
     }
+    if overruns != Overruns::default() && verbosity > Verbosity::Silent {
+        eprintln!(
+            "{} instructions and {} interrupts took longer than on the COSMAC",
+            overruns.instructions, overruns.interrupts
+        );
+    }
+    // and whatever the ROM said over the bridge, for whoever's testing it
+    for event in bridge.borrow_mut().take_events() {
+        eprintln!("{}", event);
+    }
+    if let Some(frame) = divergence {
+        return Err(format!("replay diverged at frame {}", frame).into());
+    }
+    if let Some(e) = deadlock {
+        eprintln!("{}", e);
+        process::exit(KEY_DEADLOCK_EXIT_STATUS);
+    }
+    Ok(())
+}
+
+/// chip8 diag display|input|audio|av, with input --emulated going through a
+/// ROM, and the keys as keymap has them. av can be headless, to time the
+/// sound without the terminal
+fn run_diag(
+    what: &str,
+    emulated: bool,
+    frontend: Frontend,
+    keymap: input::Keymap,
+    options: TerminalOptions,
+) -> Result<(), Box<dyn Error>> {
+    match what {
+        "audio" => return Ok(diag::audio(&mut stdio::stdout(), &SystemClock::new())?),
+        "display" | "input" | "av" => (),
+        _ => {
+            return Err(format!(
+                "can't diagnose \"{}\" (try display, input, audio or av)",
+                what
+            )
+            .into())
+        }
+    }
+    let clock = SystemClock::new();
+    let said = |report: diag::AvReport| {
+        println!("{}", report);
+        println!(
+            "--audio-buffer sets how many milliseconds the sound card's player holds \
+             (it's {} unless told)",
+            AUDIO_PLAYER_BUFFER.as_millis()
+        );
+    };
+    if frontend == Frontend::Headless {
+        if what != "av" {
+            return Err("diag needs something to show it on and keys to stop it with".into());
+        }
+        // the flashes are timed as they'd be drawn, against the sound card
+        // as the terminal would play it, with nothing on the terminal
+        let mut sound = sound::best_available(options.audio_buffer);
+        let mut input = DummyInput::new(&[]);
+        said(diag::av(
+            &mut DummyDisplay,
+            &mut input,
+            sound.as_mut(),
+            &clock,
+        )?);
+        return Ok(());
+    }
+    let mut platform = frontend.platform(Some(keymap.clone()), options)?;
+    let (display, input, sound) = platform.devices();
+    match what {
+        "display" => diag::display(display, input, &clock)?,
+        "av" => {
+            let report = diag::av(display, input, sound, &clock)?;
+            // put the terminal back before saying how it went
+            drop(platform);
+            said(report);
+        }
+        _ if emulated => diag::emulated_input(display, input, sound)?,
+        _ => diag::input(display, input, &keymap, &clock)?,
+    }
+    Ok(())
+}
+
+/// what --record-display recorded at path, played into frontend's display
+/// at rate (flat out, headless), without running anything
+fn run_play_display(
+    path: &str,
+    frontend: Frontend,
+    rate: Option<RefreshRate>,
+    options: TerminalOptions,
+) -> Result<(), Box<dyn Error>> {
+    let recording = DisplayRecording::read(BufReader::new(File::open(path)?))?;
+    let mut platform = frontend.platform(None, options)?;
+    let (display, _, _) = platform.devices();
+    let clock = SystemClock::new();
+    let clock: Option<&dyn Clock> = match frontend {
+        Frontend::Headless => None,
+        _ => Some(&clock),
+    };
+    let drawn = recording.play_paced(display, clock, rate.unwrap_or_default())?;
+    drop(platform);
+    println!("played {} frames", drawn);
+    Ok(())
+}
+
+/// the ROMs on the playlist at path, round and round until escape
+fn run_attract(
+    path: &str,
+    frontend: Frontend,
+    keymap: input::Keymap,
+    options: TerminalOptions,
+) -> Result<(), Box<dyn Error>> {
+    let shows = Playlist::load(
+        Path::new(path),
+        &Config::load(&FileStorage, &Config::default_path())?,
+    )?;
+    if frontend == Frontend::Headless {
+        return Err("attract mode needs something to show it on".into());
+    }
+    let mut platform = frontend.platform(Some(keymap), options)?;
+    let (display, input, sound) = platform.devices();
+    let faults = attract::run(&shows, display, input, sound, None)?;
+    drop(platform);
+    for (title, e) in faults {
+        eprintln!("{}", lang::format("warning.attract-fault", &[&title, &e]));
+    }
+    Ok(())
+}
+
+/// a profile called name, from what --schip, --quirks, --speed and --map
+/// said
+fn profile_of(
+    name: &str,
+    schip: bool,
+    quirks: Quirks,
+    speed: Option<f64>,
+    remaps: &[(char, u8)],
+) -> Result<Profile, Box<dyn Error>> {
+    let mut profile = Profile::new(name);
+    profile.schip = schip;
+    profile.quirks = match quirks.name() {
+        Some(q) => q,
+        None => return Err("can't put these quirks in a profile".into()),
+    };
+    profile.speed = speed.unwrap_or(1.0);
+    for (host_key, key) in remaps {
+        profile.keymap.insert(host_key.to_string(), *key);
+    }
+    profile.validate()?;
+    Ok(profile)
+}
+
+/// boot a whole VIP into its monitor, with rom loaded as if typed in. escape
+/// flips the RUN switch, to run whatever's in memory as CHIP-8
+fn run_vip(
+    rom: &[u8],
+    display: &mut dyn Display,
+    input: &mut dyn Input,
+    sound: &mut dyn Sound,
+    save_tape_path: Option<String>,
+) -> Result<(), Box<dyn Error>> {
+    let mut memory = Chip8MemoryMap::new()?;
+    memory.load_program(&mut &rom[..])?;
+    let mut vip = VipMachine::new(memory);
+    vip.reset(true);
+    let mut monitor = true;
+    while vip.main_loop(usize::MAX, display, input, sound)? == RunOutcome::MenuRequested {
+        // escape from CHIP-8 quits rather than going round again
+        if !monitor {
+            break;
+        }
+        vip.reset(false);
+        monitor = false;
+    }
+    if let Some(p) = save_tape_path {
+        save_tape(&p, vip.memory(), rom.len())?;
+    }
+    for _ in 0..12 {
+        println!();
+    }
+    Ok(())
+}
+
+/// what the terminal can do, with what caps says put right, and so the
+/// cells and scale to draw with, unless they were given: the best that fit
+/// SCHIP's hires if it might be wanted, or the VIP's picture. an error if
+/// the terminal can't draw it at all
+fn fit_terminal(
+    caps: Option<&str>,
+    cells: &mut Option<Cells>,
+    scale: &mut Option<Scale>,
+    hires: bool,
+) -> Result<Colours, Chip8Error> {
+    let mut detected = Capabilities::detect();
+    if let Some(c) = caps {
+        detected = detected.overridden(c)?;
+    }
+    let (width, height) = if hires { (128, 64) } else { (64, 32) };
+    let (c, s) = detected.fit(width, height)?;
+    if (*cells, *scale) == (None, None) {
+        (*cells, *scale) = (Some(c), Some(s));
+    }
+    Ok(detected.colours)
+}
+
+/// run two machines, each a ROM, its name and its quirks, side by side
+/// until escape
+fn run_compare(
+    machines: [(Vec<u8>, String, Quirks); 2],
+    keymap: input::Keymap,
+    hotkeys: Hotkeys,
+    key_repeat: KeyRepeat,
+    theme: Theme,
+) -> Result<(), Box<dyn Error>> {
+    let [(left, left_name, left_quirks), (right, right_name, right_quirks)] = machines;
+    let mut split = SplitTermDisplay::new(64, 32, [&left_name, &right_name])?;
+    split.set_theme(theme);
+    let screen = RefCell::new(split);
+    let mut input = StdinInput::with_keys(keymap, hotkeys)?;
+    input.set_key_repeat(key_repeat);
+    dual::run_side_by_side(
+        [(&left, left_quirks), (&right, right_quirks)],
+        [
+            &mut SplitHalf::left(&screen),
+            &mut SplitHalf::right(&screen),
+        ],
+        &mut input,
+        &mut Mute::new(),
+        rand::random(),
+        18_000,
+    )?;
+    for _ in 0..12 {
+        println!();
+    }
+    Ok(())
+}
+
+/// hand each sprite on sheet that isn't what's in rom any more to put,
+/// with its address, and say how many there were
+fn inject_sprites(
+    sheet: &str,
+    rom: &[u8],
+    mut put: impl FnMut(u16, &[u8]) -> Result<(), Chip8Error>,
+) -> Result<usize, Chip8Error> {
+    let picture = png::read(&fs::read(sheet)?)?;
+    let changes = sprite::changes(&picture, rom, &sprite::find(rom))?;
+    for (s, bytes) in &changes {
+        put(s.addr, bytes)?;
+    }
+    Ok(changes.len())
+}
+
+/// run the next instruction, and say what it was and what it does (and,
+/// for an assembled program, where it is in the source)
+fn explain_step(
+    interpreter: &mut Chip8Interpreter,
+    source_lines: Option<&LineTable>,
+    out: &mut impl Write,
+) -> Result<(), Chip8Error> {
+    let addr = interpreter.pc();
+    let word = interpreter.memory().get_ro_slice(addr, 2)?;
+    let inst = u16::from_be_bytes([word[0], word[1]]);
+    let explanation = isa::explain(inst, Some(&interpreter.quirks()))
+        .unwrap_or_else(|| "not an instruction".to_string());
+    writeln!(out, "{:04x}: {:04x}  {}", addr, inst, explanation)?;
+    if let Some(excerpt) = source_lines.and_then(|l| l.excerpt(addr, STEP_SOURCE_CONTEXT)) {
+        for line in excerpt {
+            writeln!(out, "    {}", line)?;
+        }
+    }
+    interpreter.step()
+}
+
+/// everything needed to carry on from here later, or somewhere else
+fn session_of(interpreter: &Chip8Interpreter, rom_name: &str, rom: &[u8], schip: bool) -> Session {
+    Session {
+        rom_name: rom_name.to_string(),
+        rom: rom.to_vec(),
+        speed: interpreter.speed(),
+        hud: interpreter.hud(),
+        schip,
+        state: interpreter.machine_state().clone(),
+    }
+}
+
+/// carry on from a shared state, given or from the clipboard. the ROM comes
+/// with it, as part of memory, but the keymap and cheats stay as they are
+fn paste_state(
+    interpreter: &mut Chip8Interpreter,
+    text: Option<String>,
+    schip: bool,
+    out: &mut impl Write,
+) -> Result<(), Chip8Error> {
+    let text = match text {
+        Some(t) => t,
+        None => clipboard::paste()?,
+    };
+    let session = Session::from_share(&text)?;
+    let rom_name = session.rom_name.clone();
+    restore_session(interpreter, session, schip)?;
+    writeln!(out, "pasted a state from {}", rom_name)?;
+    Ok(())
+}
+
+/// carry on from session, if it was saved the way we're running
+fn restore_session(
+    interpreter: &mut Chip8Interpreter,
+    session: Session,
+    schip: bool,
+) -> Result<(), Chip8Error> {
+    if session.schip != schip {
+        return Err(Chip8Error::ConfigError(format!(
+            "that state needs running {} --schip",
+            if session.schip { "with" } else { "without" }
+        )));
+    }
+    interpreter.restore(session.state)?;
+    interpreter.set_speed(session.speed);
+    Ok(())
+}
+
+/// show the command palette over the game, paused, until the player picks
+/// something to do, or goes back with escape (None)
+fn choose_command(
+    interpreter: &mut Chip8Interpreter,
+    hotkeys: &Hotkeys,
+) -> Result<Option<Command>, Chip8Error> {
+    let frame = screen(interpreter)?;
+    let mut palette = Palette::new(palette::commands(hotkeys));
+    interpreter.input_mut().set_typing(true);
+    let command = 'typing: loop {
+        interpreter.display_mut().set_palette(Some(palette.lines()));
+        interpreter.display_mut().draw(&frame)?;
+        let input = interpreter.input_mut();
+        let typed = loop {
+            if !input.wait_for_event(SLOT_PICKER_WAIT)? {
+                std::thread::sleep(SLOT_PICKER_WAIT);
+            }
+            let typed = input.take_typed()?;
+            if !typed.is_empty() {
+                break typed;
+            }
+        };
+        for t in typed {
+            match palette.key(t) {
+                palette::Outcome::Open => {}
+                palette::Outcome::Chosen(c) => break 'typing Some(c),
+                palette::Outcome::Closed => break 'typing None,
+            }
+        }
+    };
+    interpreter.input_mut().set_typing(false);
+    interpreter.display_mut().set_palette(None);
+    Ok(command)
+}
+
+/// do what was chosen from the palette, as its hotkey would have, keeping
+/// any change to the settings in history. the menu and the slots need the
+/// main loop, so come back as what main_loop would have returned for their
+/// hotkeys
+fn run_command(
+    interpreter: &mut Chip8Interpreter,
+    command: Command,
+    history: &mut SettingsHistory,
+    start_theme: Theme,
+    invert: bool,
+) -> Result<Option<RunOutcome>, Chip8Error> {
+    catch_up(interpreter, history);
+    match command {
+        Command::Hotkey(action) => match action {
+            Action::Menu => return Ok(Some(RunOutcome::MenuRequested)),
+            Action::Slots => return Ok(Some(RunOutcome::SlotRequested(SlotRequest::Pick))),
+            Action::SaveSlot(n) => {
+                return Ok(Some(RunOutcome::SlotRequested(SlotRequest::Save(n))))
+            }
+            Action::LoadSlot(n) => {
+                return Ok(Some(RunOutcome::SlotRequested(SlotRequest::Load(n))))
+            }
+            Action::Help => interpreter.show_help(!interpreter.showing_help()),
+            Action::Hud => interpreter.set_hud(!interpreter.hud()),
+            Action::Slower => interpreter.change_speed(SpeedRequest::Slower),
+            Action::Faster => interpreter.change_speed(SpeedRequest::Faster),
+            Action::NormalSpeed => interpreter.change_speed(SpeedRequest::Normal),
+            Action::Quieter => interpreter.change_volume(VolumeRequest::Quieter)?,
+            Action::Louder => interpreter.change_volume(VolumeRequest::Louder)?,
+            Action::Mute => interpreter.change_volume(VolumeRequest::ToggleMute)?,
+            // main_loop tells the display next frame
+            Action::Focus => {
+                let input = interpreter.input_mut();
+                input.set_focus(input.focus().toggled());
+            }
+            Action::Palette => {}
+        },
+        Command::Speed(speed) => interpreter.set_speed(speed),
+        Command::Quirk(name) => {
+            let mut quirks = interpreter.quirks();
+            let on = match quirks.toggle(name)? {
+                true => lang::text("menu.on"),
+                false => lang::text("menu.off"),
+            };
+            interpreter.set_quirks(quirks);
+            interpreter
+                .display_mut()
+                .notify(&lang::format("menu.is", &[&name, &on]));
+            let what = lang::format("palette.quirk", &[&name]);
+            history.change(
+                &what,
+                Settings {
+                    quirks,
+                    ..history.now()
+                },
+            );
+        }
+        Command::Theme(name) => {
+            let settings = Settings {
+                theme: Some(name),
+                ..history.now()
+            };
+            interpreter
+                .display_mut()
+                .set_theme(settings.theme(start_theme, invert));
+            history.change(&lang::format("palette.theme", &[&name]), settings);
+        }
+        Command::HeatMap => {
+            let on = !interpreter.heat_map();
+            interpreter.set_heat_map(on);
+            let said = match on {
+                true => lang::text("menu.on"),
+                false => lang::text("menu.off"),
+            };
+            interpreter
+                .display_mut()
+                .notify(&lang::format("menu.is", &[&lang::text("heat.map"), &said]));
+        }
+        Command::Undo | Command::Redo => {
+            let said = undo_settings(
+                interpreter,
+                history,
+                start_theme,
+                invert,
+                command == Command::Redo,
+            );
+            interpreter.display_mut().notify(&said);
+        }
+    }
+    // the speed's changed like its hotkeys change it
+    catch_up(interpreter, history);
+    Ok(None)
+}
+
+/// take in a change to the speed made some other way than through history,
+/// e.g. with its hotkeys, so it can be undone too
+fn catch_up(interpreter: &Chip8Interpreter, history: &mut SettingsHistory) {
+    let speed = interpreter.speed();
+    let what = lang::format("palette.speed", &[&speed]);
+    history.change(
+        &what,
+        Settings {
+            speed,
+            ..history.now()
+        },
+    );
+}
+
+/// put the speed, quirks and theme back as they were before the last
+/// change (or after the last one undone, if redoing), saying what it was
+fn undo_settings(
+    interpreter: &mut Chip8Interpreter,
+    history: &mut SettingsHistory,
+    start_theme: Theme,
+    invert: bool,
+    redo: bool,
+) -> String {
+    catch_up(interpreter, history);
+    let done = match redo {
+        true => history.redo(),
+        false => history.undo(),
+    };
+    match done {
+        Some((what, settings)) => {
+            interpreter.set_speed(settings.speed);
+            interpreter.set_quirks(settings.quirks);
+            interpreter
+                .display_mut()
+                .set_theme(settings.theme(start_theme, invert));
+            let key = if redo {
+                "settings.redone"
+            } else {
+                "settings.undone"
+            };
+            lang::format(key, &[&what])
+        }
+        None => lang::text("settings.nothing").to_string(),
+    }
+}
+
+/// ask whether to keep the settings changed while playing. nobody's there
+/// to say so unless stdin's a terminal
+fn keep_settings() -> Result<bool, Chip8Error> {
+    if !stdio::stdin().is_terminal() {
+        return Ok(false);
+    }
+    let raw = terminal::is_raw_mode_enabled()?;
+    terminal::disable_raw_mode()?;
+    print!("\n{}", lang::text("settings.keep"));
+    stdio::stdout().flush()?;
+    let mut answer = String::new();
+    stdio::stdin().lock().read_line(&mut answer)?;
+    if raw {
+        terminal::enable_raw_mode()?;
+    }
+    // yes, or oui
+    Ok(matches!(
+        answer.trim().to_lowercase().as_str(),
+        "y" | "yes" | "o" | "oui"
+    ))
+}
+
+/// what's on the screen, in whichever resolution it's in
+fn screen(interpreter: &Chip8Interpreter) -> Result<Vec<u8>, Chip8Error> {
+    let (addr, width, height) = interpreter.display_geometry();
+    Ok(interpreter
+        .memory()
+        .get_ro_slice(addr, width * height / 8)?
+        .to_vec())
+}
+
+/// save to or load from a savestate slot, or show the picker for the player
+/// to choose one, and say how it went on the display. a slot that can't be
+/// used isn't worth stopping the game for
+fn use_slot(
+    interpreter: &mut Chip8Interpreter,
+    slots: &SaveSlots,
+    request: SlotRequest,
+    rom_name: &str,
+    rom: &[u8],
+    schip: bool,
+) -> Result<(), Chip8Error> {
+    let request = match request {
+        SlotRequest::Pick => pick_slot(interpreter, slots)?,
+        r => r,
+    };
+    let done = match request {
+        SlotRequest::Save(n) => screen(interpreter)
+            .and_then(|frame| slots.save(n, &session_of(interpreter, rom_name, rom, schip), &frame))
+            .map(|_| format!("saved to slot {}", n)),
+        SlotRequest::Load(n) => match slots.load(n) {
+            Ok(Some(session)) => {
+                restore_session(interpreter, session, schip).map(|_| format!("loaded slot {}", n))
+            }
+            Ok(None) => Ok(format!("slot {} is empty", n)),
+            Err(e) => Err(e),
+        },
+        SlotRequest::Pick => return Ok(()),
+    };
+    // a chime for a save or load that worked, so it's heard as well as seen
+    if done.is_ok() && matches!(request, SlotRequest::Save(_) | SlotRequest::Load(_)) {
+        interpreter.cue(UiCue::Confirm)?;
+    }
+    let notice = done.unwrap_or_else(|e| e.to_string());
+    interpreter.display_mut().notify(&notice);
+    Ok(())
+}
+
+/// show the slots over the game, paused, until the player saves or loads
+/// one, or goes back (Pick) with escape or the picker's key again. the
+/// keypad's 0 to 9 load slots here too, as the number keys are often the
+/// keypad's
+fn pick_slot(
+    interpreter: &mut Chip8Interpreter,
+    slots: &SaveSlots,
+) -> Result<SlotRequest, Chip8Error> {
+    let frame = screen(interpreter)?;
+    let mut lines = slots.picker();
+    lines.push(String::new());
+    lines.push("0 to 9 on the keypad load that slot, and the save keys save to it".to_string());
+    interpreter.display_mut().set_slots(Some(lines));
+    interpreter.display_mut().draw(&frame)?;
+    let input = interpreter.input_mut();
+    let request = loop {
+        if !input.wait_for_event(SLOT_PICKER_WAIT)? {
+            std::thread::sleep(SLOT_PICKER_WAIT);
+        }
+        input.flush_keys()?;
+        if let Some(n @ 0..=9) = input.read_key()? {
+            break SlotRequest::Load(n);
+        }
+        if input.take_menu_request()? {
+            break SlotRequest::Pick;
+        }
+        if let Some(request) = input.take_slot_request()? {
+            break request;
+        }
+    };
+    interpreter.display_mut().set_slots(None);
+    Ok(request)
+}
+
+/// save the pages of memory holding the program, as the VIP's monitor would
+fn save_tape(path: &str, memory: &Chip8MemoryMap, len: usize) -> Result<(), Box<dyn Error>> {
+    let pages = len.div_ceil(0x100).max(1);
+    let data = memory.get_ro_slice(0x200, pages * 0x100)?;
+    tape::write_tape(&mut BufWriter::new(File::create(path)?), data)?;
     Ok(())
 }
@@ -1,12 +1,15 @@
+use crate::error::Chip8Error;
+#[cfg(feature = "full")]
+use serde::{Deserialize, Serialize};
 use std::io;
-use std::io::Read;
+use std::ops::Range;
 
 // NB. addresses are u16 as per the chip-8; lengths are usize to stop endless casting
 
 /// Represents memory map, ROM, RAM etc.
 pub trait MemoryMap {
     /// write unknown len of data into memory at a particular address
-    fn write_any(&mut self, reader: &mut impl io::Read, addr: u16) -> Result<(), io::Error> {
+    fn write_any(&mut self, reader: &mut impl io::Read, addr: u16) -> Result<(), Chip8Error> {
         // there's probably a considerably slicker way of figuring out the
         // length of what we're reading
         let mut buf = Vec::new();
@@ -15,24 +18,31 @@ pub trait MemoryMap {
     }
 
     /// write a chunk of bytes into "RAM"
-    fn write(&mut self, data: &[u8], addr: u16, len: usize) -> Result<(), io::Error> {
-        let bytes = self.get_rw_slice(addr, len);
-        let mut d: &[u8] = data;
-        d.read(bytes)?;
-        Ok(())
+    fn write(&mut self, data: &[u8], addr: u16, len: usize) -> Result<(), Chip8Error> {
+        let bytes = self.get_rw_slice(addr, len)?;
+        match data.get(..len) {
+            Some(d) => {
+                bytes.copy_from_slice(d);
+                Ok(())
+            }
+            None => Err(Chip8Error::MemoryFault {
+                addr: addr.into(),
+                len,
+            }),
+        }
     }
 
     /// get a two-byte word (stack)
-    fn get_word(&mut self, addr: u16) -> u16 {
-        let word = self.get_ro_slice(addr, 2);
-        ((word[0] as u16) << 8) + (word[1] as u16)
+    fn get_word(&mut self, addr: u16) -> Result<u16, Chip8Error> {
+        let word = self.get_ro_slice(addr, 2)?;
+        Ok(((word[0] as u16) << 8) + (word[1] as u16))
     }
 
     /// get a r/w slice of the underlying memory (heap)
-    fn get_rw_slice(&mut self, addr: u16, len: usize) -> &mut [u8];
+    fn get_rw_slice(&mut self, addr: u16, len: usize) -> Result<&mut [u8], Chip8Error>;
 
     /// get a r/o slice of the underlying memory (heap)
-    fn get_ro_slice(&self, addr: u16, len: usize) -> &[u8];
+    fn get_ro_slice(&self, addr: u16, len: usize) -> Result<&[u8], Chip8Error>;
 }
 
 /// Defines the CHIP-8 standard memory map
@@ -55,23 +65,34 @@ pub trait MemoryMap {
 ///   0x8000-0xb1ff  ROM
 ///
 /// chip-8 programs *should* not access these directly
+#[derive(Clone)]
+#[cfg_attr(feature = "full", derive(Serialize, Deserialize))]
 pub struct Chip8MemoryMap {
+    #[cfg_attr(feature = "full", serde(with = "crate::clipboard::as_base64"))]
     bytes: Box<[u8]>,
     pub program_addr: u16,
     pub stack_addr: u16,
     pub work_addr: u16,
     pub var_addr: u16,
     pub display_addr: u16,
+    pub hires_display_addr: u16,
+    /// what programs can't write to, besides the VIP's ROM
+    #[cfg_attr(feature = "full", serde(default))]
+    protected: Vec<Range<u16>>,
 }
 
 impl MemoryMap for Chip8MemoryMap {
-    fn get_rw_slice(&mut self, addr: u16, len: usize) -> &mut [u8] {
+    fn get_rw_slice(&mut self, addr: u16, len: usize) -> Result<&mut [u8], Chip8Error> {
         let a = addr as usize;
-        &mut self.bytes[a..(a + len)]
+        self.bytes
+            .get_mut(a..(a + len))
+            .ok_or(Chip8Error::MemoryFault { addr: a, len })
     }
-    fn get_ro_slice(&self, addr: u16, len: usize) -> &[u8] {
+    fn get_ro_slice(&self, addr: u16, len: usize) -> Result<&[u8], Chip8Error> {
         let a = addr as usize;
-        &self.bytes[a..(a + len)]
+        self.bytes
+            .get(a..(a + len))
+            .ok_or(Chip8Error::MemoryFault { addr: a, len })
     }
 }
 
@@ -81,18 +102,28 @@ const CHIP8_RAM_SIZE_BYTES: u16 = 4096;
 /// how much addressable space the COSMAC VIP has
 const COSMAC_MAX_RAM_BYTES: u16 = 0x8200;
 
+/// where the VIP's ROM is, which programs can read but not write
+const COSMAC_ROM: Range<u16> = 0x8000..0x8200;
+
+/// how much XO-CHIP has: all that 16 bits can reach
+pub(crate) const XO_CHIP_RAM_BYTES: usize = 0x10000;
+
 /// offsets from the top of RAM
 const CHIP8_STACK_OFFSET: u16 = 0x0132; // not! 0x0160; stack grows downward into real memory
 const CHIP8_WORK_OFFSET: u16 = 0x0130;
 const CHIP8_VAR_OFFSET: u16 = 0x0110;
 const CHIP8_DISPLAY_OFFSET: u16 = 0x100;
 
+/// SCHIP's 128x64 screen needs 1k, which the VIP's RAM hasn't room for, so
+/// it goes above it (after the SCHIP fonts)
+const CHIP8_HIRES_DISPLAY_ADDR: u16 = 0x1100;
+
 /// where the program is loaded
 const CHIP8_PROGRAM_ADDR: u16 = 0x0200;
 
 impl Chip8MemoryMap {
     /// initialises CHIP-8 with contemporary memory contents
-    pub fn new() -> Result<Self, io::Error> {
+    pub fn new() -> Result<Self, Chip8Error> {
         // rather than being clever about paging RAM/ROM, since the whole thing
         // is ~32.5kib, let's just malloc the whole address space
         let mut mm = Chip8MemoryMap {
@@ -102,26 +133,97 @@ impl Chip8MemoryMap {
             work_addr: CHIP8_RAM_SIZE_BYTES - CHIP8_WORK_OFFSET,
             var_addr: CHIP8_RAM_SIZE_BYTES - CHIP8_VAR_OFFSET,
             display_addr: CHIP8_RAM_SIZE_BYTES - CHIP8_DISPLAY_OFFSET,
+            hires_display_addr: CHIP8_HIRES_DISPLAY_ADDR,
+            protected: Vec::new(),
         };
         // write the original chip-8 interpreter at 0x000
         mm.write(&CHIP8_INTERPRETER_SOURCE, 0x0, 0x200)?;
 
         // write the COSMAC VIP ROM at 0x8000
-        mm.write(&COSMAC_VIP_ROM, 0x8000, 0x200)?;
+        mm.write(&COSMAC_VIP_ROM, COSMAC_ROM.start, COSMAC_ROM.len())?;
 
         Ok(mm)
     }
 
-    /// load a CHIP-8 program at 0x200
-    pub fn load_program(&mut self, reader: &mut impl io::Read) -> Result<(), io::Error> {
-        self.write_any(reader, self.program_addr)
+    /// how many bytes there are, from 0x0000
+    pub fn size(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// is this a memory map the interpreter can work with? one that's
+    /// been saved and read back in, say, may not be: the wrong size, or
+    /// with the stack or the display off the end
+    pub fn check(&self) -> Result<(), Chip8Error> {
+        if !(COSMAC_MAX_RAM_BYTES as usize..=XO_CHIP_RAM_BYTES).contains(&self.bytes.len()) {
+            return Err(Chip8Error::MemoryFault {
+                addr: 0,
+                len: self.bytes.len(),
+            });
+        }
+        for (addr, len) in [
+            (self.program_addr, 2),
+            (self.stack_addr, 2),
+            (self.work_addr, 1),
+            (self.var_addr, 16),
+            (self.display_addr, 0x100),
+            (self.hires_display_addr, 0x400),
+        ] {
+            self.get_ro_slice(addr, len)?;
+        }
+        Ok(())
+    }
+
+    /// stop programs writing to range, e.g. to catch one scribbling over a
+    /// font. the emulator itself can still write there
+    pub fn protect(&mut self, range: Range<u16>) {
+        self.protected.push(range);
+    }
+
+    /// can programs write to addr? not to the VIP's ROM, unless memory's
+    /// grown over it, nor anything that's been protected
+    pub fn is_protected(&self, addr: u16) -> bool {
+        (self.bytes.len() < XO_CHIP_RAM_BYTES && COSMAC_ROM.contains(&addr))
+            || self.protected.iter().any(|r| r.contains(&addr))
+    }
+
+    /// write byte at addr as a program would, which can't be to protected
+    /// memory (nor off the end)
+    pub fn store(&mut self, byte: u8, addr: u16) -> Result<(), Chip8Error> {
+        if self.is_protected(addr) {
+            return Err(Chip8Error::WriteProtected { addr });
+        }
+        self.write(&[byte], addr, 1)
+    }
+
+    /// fill out the rest of the 64k 16 bits can reach, as XO-CHIP does, all
+    /// of it RAM
+    pub fn grow(&mut self) {
+        if self.bytes.len() < XO_CHIP_RAM_BYTES {
+            let mut bytes = self.bytes.to_vec();
+            bytes.resize(XO_CHIP_RAM_BYTES, 0);
+            self.bytes = bytes.into_boxed_slice();
+        }
+    }
+
+    /// load a CHIP-8 program at 0x200. an empty one's an error, rather than
+    /// a machine running whatever's in RAM; an odd-sized one (cut short,
+    /// say) gets a zero byte to finish its last instruction off
+    pub fn load_program(&mut self, reader: &mut impl io::Read) -> Result<(), Chip8Error> {
+        let mut rom = Vec::new();
+        reader.read_to_end(&mut rom)?;
+        if rom.is_empty() {
+            return Err(Chip8Error::BadRom("there's nothing in it".to_string()));
+        }
+        if rom.len() % 2 == 1 {
+            rom.push(0);
+        }
+        self.write(&rom, self.program_addr, rom.len())
     }
 }
 
 #[allow(dead_code)]
 const CHIP8_CONTEMPORARY_FONT_ADDR: u16 = 0x050;
-#[allow(dead_code)]
-const CHIP8_CONTEMPORARY_FONT: [u8; 80] = [
+pub(crate) const CHIP8_CONTEMPORARY_FONT: [u8; 80] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
     0x20, 0x60, 0x20, 0x20, 0x70, // 1
     0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
@@ -161,14 +263,14 @@ const CHIP8_ORIGINAL_FONT: [u8; 51] = [
 // https://www.old-computers.com/download/rca/RCA_COSMAC_VIP-Instruction_Manual_for_VP-111.pdf
 #[rustfmt::skip]
 const CHIP8_INTERPRETER_SOURCE: [u8; 0x200] = [
-    0x91, 0xbb, 0xff, 0x01, 0xb2, 0xb6, 0xf6, 0xcf, // 0000
+    0x91, 0xbb, 0xff, 0x01, 0xb2, 0xb6, 0xf8, 0xcf, // 0000
     0xa2, 0xf8, 0x81, 0xb1, 0xf8, 0x46, 0xa1, 0x90,
     0xb4, 0xf8, 0x1b, 0xa4, 0xf8, 0x01, 0xb5, 0xf8,
     0xfc, 0xa5, 0xd4, 0x96, 0xb7, 0xe2, 0x94, 0xbc,
     0x45, 0xaf, 0xf6, 0xf6, 0xf6, 0xf6, 0x32, 0x44,
     0xf9, 0x50, 0xac, 0x8f, 0xfa, 0x0f, 0xf9, 0xf0,
     0xa6, 0x05, 0xf6, 0xf6, 0xf6, 0xf6, 0xf9, 0xf0, // 0030
-    0xa7, 0x4c, 0xb3, 0xbc, 0xfc, 0x0f, 0xac, 0x0c,
+    0xa7, 0x4c, 0xb3, 0x8c, 0xfc, 0x0f, 0xac, 0x0c,
     0xa3, 0xd3, 0x30, 0x1b, 0x8f, 0xfa, 0x0f, 0xb3,
     0x45, 0x30, 0x40, 0x22, 0x69, 0x12, 0xd4, 0x00,
     0x00, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01,
@@ -198,8 +300,8 @@ const CHIP8_INTERPRETER_SOURCE: [u8; 0x200] = [
     0x22, 0xdc, 0x12, 0x56, 0xd4, 0x06, 0xb8, 0xd4,
     0x06, 0xa8, 0xd4, 0x64, 0x0a, 0x01, 0xe6, 0x8a,
     0xf4, 0xaa, 0x3b, 0x28, 0x9a, 0xfc, 0x01, 0xba, // 0120
-    0xd4, 0xf8, 0x91, 0xba, 0x06, 0xfa, 0x0f, 0xaa,
-    0x0a, 0xaa, 0xd5, 0xe6, 0x06, 0xbf, 0x93, 0xbe,
+    0xd4, 0xf8, 0x81, 0xba, 0x06, 0xfa, 0x0f, 0xaa,
+    0x0a, 0xaa, 0xd4, 0xe6, 0x06, 0xbf, 0x93, 0xbe,
     0xf8, 0x1b, 0xae, 0x2a, 0x1a, 0xf8, 0x00, 0x5a,
     0x0e, 0xf5, 0x3b, 0x4b, 0x56, 0x0a, 0xfc, 0x01,
     0x5a, 0x30, 0x40, 0x4e, 0xf6, 0x3b, 0x3c, 0x9f,
@@ -301,7 +403,7 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_memory_zeroed() -> Result<(), io::Error> {
+    fn test_memory_zeroed() -> Result<(), Chip8Error> {
         let m = Chip8MemoryMap::new()?;
         // NB. memory is zeroed from 0x200 because before that we bake in the
         //     font and other interpreter details
@@ -310,7 +412,7 @@ mod tests {
     }
 
     #[test]
-    fn test_write_any_data_ok() -> Result<(), io::Error> {
+    fn test_write_any_data_ok() -> Result<(), Chip8Error> {
         let mut dst = Chip8MemoryMap::new()?;
         let mut src: &[u8] = &[0, 1, 2, 3, 4, 5, 6, 7];
         dst.write_any(&mut src, 0x208)?;
@@ -321,11 +423,25 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_load_program() -> Result<(), Chip8Error> {
+        let mut m = Chip8MemoryMap::new()?;
+        assert!(matches!(
+            m.load_program(&mut &[][..]),
+            Err(Chip8Error::BadRom(_))
+        ));
+        // an odd byte out gets a zero to go with it, whatever was there
+        m.write(&[0xff; 4], 0x200, 4)?;
+        m.load_program(&mut &[0x12, 0x00, 0x60][..])?;
+        assert_eq!(m.bytes[0x200..0x204], [0x12, 0x00, 0x60, 0x00]);
+        Ok(())
+    }
+
     #[test]
     fn test_write_slice_ok() {
         let mut dst = Chip8MemoryMap::new().unwrap();
         let src: &[u8] = &[0, 1, 2, 3, 4, 5, 6, 7];
-        dst.write(&src, 0x208, 8).unwrap();
+        dst.write(src, 0x208, 8).unwrap();
         assert_eq!(
             dst.bytes[0x200..0x210],
             [0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 2, 3, 4, 5, 6, 7]
@@ -335,32 +451,83 @@ mod tests {
     #[test]
     fn test_read_ro() {
         let m = Chip8MemoryMap::new().unwrap();
-        let s = m.get_ro_slice(0x200, 8);
+        let s = m.get_ro_slice(0x200, 8).unwrap();
         assert_eq!(s, &[0, 0, 0, 0, 0, 0, 0, 0]);
     }
 
     #[test]
     fn test_read_word() {
         let mut m = Chip8MemoryMap::new().unwrap();
-        let mut src: &[u8] = &[0, 1, 2, 3, 4, 5, 6, 7];
-        m.write(&mut src, 0, 8).unwrap();
-        assert_eq!(m.get_word(0x4), 0x0405);
+        let src: &[u8] = &[0, 1, 2, 3, 4, 5, 6, 7];
+        m.write(src, 0, 8).unwrap();
+        assert_eq!(m.get_word(0x4).unwrap(), 0x0405);
     }
 
     #[test]
-    #[should_panic]
-    fn test_read_too_much_panic() {
+    fn test_read_too_much_faults() {
         let mut dst = Chip8MemoryMap::new().unwrap();
         let mut src: &[u8] = &[0; 8];
-        let _ = dst.write_any(&mut src, 0x9000);
+        assert!(matches!(
+            dst.write_any(&mut src, 0x9000),
+            Err(Chip8Error::MemoryFault {
+                addr: 0x9000,
+                len: 8
+            })
+        ));
+    }
+
+    #[test]
+    fn test_grow() -> Result<(), Chip8Error> {
+        let mut mm = Chip8MemoryMap::new()?;
+        assert_eq!(mm.size(), 0x8200);
+        assert!(mm.get_ro_slice(0xfffe, 2).is_err());
+        mm.write(&[1, 2], 0x200, 2)?;
+        mm.grow();
+        assert_eq!(mm.size(), 0x10000);
+        assert_eq!(mm.get_ro_slice(0xfffe, 2)?, &[0, 0]);
+        // what was there's still there
+        assert_eq!(mm.get_ro_slice(0x200, 2)?, &[1, 2]);
+        mm.grow();
+        assert_eq!(mm.size(), 0x10000);
+        Ok(())
+    }
+
+    #[test]
+    fn test_protect() -> Result<(), Chip8Error> {
+        let mut mm = Chip8MemoryMap::new()?;
+        // the VIP's ROM is
+        assert!(matches!(
+            mm.store(0xff, 0x8000),
+            Err(Chip8Error::WriteProtected { addr: 0x8000 })
+        ));
+        assert!(mm.is_protected(0x81ff) && !mm.is_protected(0x8200));
+        // other memory isn't, until it's asked to be
+        mm.store(0xff, 0x1000)?;
+        mm.protect(0x1000..0x1050);
+        assert!(mm.store(0xff, 0x104f).is_err());
+        mm.store(0xff, 0x1050)?;
+        // the emulator can still write anywhere
+        mm.write(&[0xff], 0x1000, 1)?;
+        assert!(mm.store(0xff, 0x8200).is_err());
+        // and XO-CHIP's memory is all RAM, apart from what's protected
+        mm.grow();
+        mm.store(0xff, 0x8000)?;
+        assert!(mm.store(0xff, 0x1000).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_short_data_faults() {
+        let mut dst = Chip8MemoryMap::new().unwrap();
+        assert!(dst.write(&[0; 2], 0x200, 4).is_err());
     }
 
     #[test]
-    fn test_program_load_ok() -> Result<(), io::Error> {
+    fn test_program_load_ok() -> Result<(), Chip8Error> {
         let mut dst = Chip8MemoryMap::new()?;
         let mut prog: &[u8] = &[0x00, 0xe0]; // clear screen
         dst.load_program(&mut prog)?;
-        assert_eq!(dst.get_ro_slice(0x200, 2), &[0x00, 0xe0]);
+        assert_eq!(dst.get_ro_slice(0x200, 2)?, &[0x00, 0xe0]);
         Ok(())
     }
 
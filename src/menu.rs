@@ -0,0 +1,348 @@
+use crate::achievement::{Achievement, AchievementSet};
+use crate::cheat::{self, Cheat, CheatEngine};
+use crate::error::Chip8Error;
+use crate::lang;
+use crate::memory::{Chip8MemoryMap, MemoryMap};
+use crate::search::{MemorySearch, SearchFilter};
+use crate::watch::{Watch, WatchSet};
+use std::io;
+
+/// how many search results are worth printing
+const MENU_MAX_RESULTS: usize = 8;
+
+/// how many frames back a watch's plot goes: a second
+const MENU_PLOT_FRAMES: usize = 60;
+
+fn on_off(on: bool) -> &'static str {
+    lang::text(if on { "menu.on" } else { "menu.off" })
+}
+
+/// what to do after a menu command
+#[derive(Debug, PartialEq)]
+pub enum MenuAction {
+    /// wait for another command
+    Stay,
+    /// back to the game
+    Continue,
+    Quit,
+    /// run this many instructions, then come back to the menu
+    Step(u32),
+    /// share the machine's state as text
+    CopyState,
+    /// carry on from a shared state: this one, or the clipboard's
+    PasteState(Option<String>),
+    /// bundle up a bug report
+    Report,
+    /// put the sprites on this sheet into memory
+    LoadSprites(String),
+    /// undo the last change to the speed, quirks or theme
+    Undo,
+    /// and redo the last one undone
+    Redo,
+}
+
+/// the emulator's pause menu. it's just text commands; whoever's showing it
+/// is responsible for getting them from the player
+#[derive(Default)]
+pub struct PauseMenu {
+    search: Option<MemorySearch>,
+    cheats_changed: bool,
+    new_achievements: Vec<String>,
+}
+
+impl PauseMenu {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// have any cheats been added or switched on or off?
+    pub fn cheats_changed(&self) -> bool {
+        self.cheats_changed
+    }
+
+    /// achievements added from the menu, as lines for the ROM's
+    /// achievements file
+    pub fn new_achievements(&self) -> &[String] {
+        &self.new_achievements
+    }
+
+    /// run a command, writing anything the player needs to see to out.
+    /// mistakes get explained rather than returned as errors
+    pub fn command(
+        &mut self,
+        line: &str,
+        memory: &mut Chip8MemoryMap,
+        cheats: &mut CheatEngine,
+        achievements: &mut AchievementSet,
+        watches: &mut WatchSet,
+        out: &mut impl io::Write,
+    ) -> Result<MenuAction, Chip8Error> {
+        match self.run(line.trim(), memory, cheats, achievements, watches, out) {
+            Err(e @ Chip8Error::ConfigError(_)) | Err(e @ Chip8Error::MemoryFault { .. }) => {
+                writeln!(out, "{}", e)?;
+                Ok(MenuAction::Stay)
+            }
+            r => r,
+        }
+    }
+
+    fn run(
+        &mut self,
+        line: &str,
+        memory: &mut Chip8MemoryMap,
+        cheats: &mut CheatEngine,
+        achievements: &mut AchievementSet,
+        watches: &mut WatchSet,
+        out: &mut impl io::Write,
+    ) -> Result<MenuAction, Chip8Error> {
+        let (command, args) = line.split_once(' ').unwrap_or((line, ""));
+        let args = args.trim();
+        match command {
+            "" | "c" | "continue" => return Ok(MenuAction::Continue),
+            "q" | "quit" => return Ok(MenuAction::Quit),
+            "s" | "step" if args.is_empty() => return Ok(MenuAction::Step(1)),
+            "s" | "step" => match args.parse() {
+                Ok(n) => return Ok(MenuAction::Step(n)),
+                Err(_) => {
+                    return Err(Chip8Error::ConfigError(lang::format(
+                        "menu.cant-step",
+                        &[&args],
+                    )))
+                }
+            },
+            "copy" => return Ok(MenuAction::CopyState),
+            "report" => return Ok(MenuAction::Report),
+            "undo" => return Ok(MenuAction::Undo),
+            "redo" => return Ok(MenuAction::Redo),
+            "sprites" if !args.is_empty() => return Ok(MenuAction::LoadSprites(args.to_string())),
+            "paste" if args.is_empty() => return Ok(MenuAction::PasteState(None)),
+            "paste" => return Ok(MenuAction::PasteState(Some(args.to_string()))),
+            "search" if args == "reset" => {
+                self.search = None;
+                writeln!(out, "{}", lang::text("menu.search-reset"))?;
+            }
+            "search" => {
+                let filter = SearchFilter::parse(args)?;
+                let search = match &mut self.search {
+                    Some(s) => s,
+                    None => self.search.insert(MemorySearch::new(memory)?),
+                };
+                let n = search.refine(memory, filter)?;
+                writeln!(out, "{}", lang::format("menu.matches", &[&n]))?;
+                for a in search.candidates().iter().take(MENU_MAX_RESULTS) {
+                    writeln!(out, "  {:#05x} = {}", a, memory.get_ro_slice(*a, 1)?[0])?;
+                }
+            }
+            "poke" => {
+                let (addr, value) = cheat::parse_poke(args)?;
+                memory.get_rw_slice(addr, 1)?[0] = value;
+            }
+            "freeze" => {
+                // without a value, hold the address at whatever it is now
+                let (name, cheat) = match args.split_once('=') {
+                    Some((name, addr)) if !addr.contains(':') => {
+                        let addr = cheat::parse_addr(addr)?;
+                        let value = memory.get_ro_slice(addr, 1)?[0];
+                        let cheat = Cheat {
+                            addr,
+                            value,
+                            enabled: true,
+                        };
+                        (name.to_string(), cheat)
+                    }
+                    _ => match cheat::parse_cheat(args)? {
+                        (name, Some(cheat)) => (name, cheat),
+                        (name, None) => {
+                            return Err(Chip8Error::ConfigError(lang::format(
+                                "menu.freeze-needs-address",
+                                &[&name],
+                            )))
+                        }
+                    },
+                };
+                let addr = format!("{:#05x}", cheat.addr);
+                let holds = lang::format("menu.holds", &[&name, &addr, &cheat.value]);
+                writeln!(out, "{}", holds)?;
+                cheats.insert(&name, cheat);
+                self.cheats_changed = true;
+            }
+            "toggle" => {
+                let on = cheats.toggle(args)?;
+                writeln!(out, "{}", lang::format("menu.is", &[&args, &on_off(on)]))?;
+                self.cheats_changed = true;
+            }
+            "cheats" => {
+                for (name, c) in cheats.cheats() {
+                    let state = on_off(c.enabled);
+                    writeln!(out, "  {} ({}): {:#05x} = {}", name, state, c.addr, c.value)?;
+                }
+            }
+            "watch" if args.is_empty() => {
+                for w in watches.watches() {
+                    for line in w.plot(MENU_PLOT_FRAMES) {
+                        writeln!(out, "  {}", line)?;
+                    }
+                }
+            }
+            "watch" => {
+                let watch = Watch::parse(args)?;
+                // one that's out of range is better found now than next frame
+                memory.get_ro_slice(watch.addr, watch.len)?;
+                watches.add(watch);
+            }
+            "unwatch" => {
+                if !watches.remove(cheat::parse_addr(args)?) {
+                    writeln!(out, "{}", lang::format("menu.not-watched", &[&args]))?;
+                }
+            }
+            "achievement" => {
+                achievements.push(Achievement::parse(args)?);
+                self.new_achievements.push(args.to_string());
+            }
+            _ => writeln!(out, "{}", lang::text("menu.help"))?,
+        }
+        Ok(MenuAction::Stay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::watch::MemoryPatch;
+
+    struct Fixture {
+        menu: PauseMenu,
+        memory: Chip8MemoryMap,
+        cheats: CheatEngine,
+        achievements: AchievementSet,
+        watches: WatchSet,
+        out: Vec<u8>,
+    }
+
+    impl Fixture {
+        fn new() -> Result<Self, Chip8Error> {
+            Ok(Fixture {
+                menu: PauseMenu::new(),
+                memory: Chip8MemoryMap::new()?,
+                cheats: CheatEngine::default(),
+                achievements: AchievementSet::default(),
+                watches: WatchSet::default(),
+                out: Vec::new(),
+            })
+        }
+
+        fn command(&mut self, line: &str) -> Result<MenuAction, Chip8Error> {
+            self.out.clear();
+            self.menu.command(
+                line,
+                &mut self.memory,
+                &mut self.cheats,
+                &mut self.achievements,
+                &mut self.watches,
+                &mut self.out,
+            )
+        }
+
+        fn output(&self) -> String {
+            String::from_utf8_lossy(&self.out).to_string()
+        }
+    }
+
+    #[test]
+    fn test_continue_and_quit() -> Result<(), Chip8Error> {
+        let mut f = Fixture::new()?;
+        assert_eq!(f.command("")?, MenuAction::Continue);
+        assert_eq!(f.command("quit")?, MenuAction::Quit);
+        assert_eq!(f.command("help")?, MenuAction::Stay);
+        assert!(f.output().contains("search <filter>"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_step() -> Result<(), Chip8Error> {
+        let mut f = Fixture::new()?;
+        assert_eq!(f.command("step")?, MenuAction::Step(1));
+        assert_eq!(f.command("s 10")?, MenuAction::Step(10));
+        assert_eq!(f.command("step lots")?, MenuAction::Stay);
+        assert!(f.output().contains("can't step \"lots\""));
+        assert_eq!(f.command("copy")?, MenuAction::CopyState);
+        assert_eq!(f.command("paste")?, MenuAction::PasteState(None));
+        assert_eq!(
+            f.command("paste chip8-state:abc")?,
+            MenuAction::PasteState(Some("chip8-state:abc".to_string()))
+        );
+        assert_eq!(f.command("report")?, MenuAction::Report);
+        assert_eq!(f.command("undo")?, MenuAction::Undo);
+        assert_eq!(f.command("redo")?, MenuAction::Redo);
+        assert_eq!(
+            f.command("sprites edited.png")?,
+            MenuAction::LoadSprites("edited.png".to_string())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_then_freeze() -> Result<(), Chip8Error> {
+        let mut f = Fixture::new()?;
+        f.command("poke 0x300=3")?;
+        f.command("search 3")?;
+        f.command("poke 0x300=2")?;
+        f.command("search down")?;
+        assert!(f.output().starts_with("1 address(es) match"));
+        assert!(f.output().contains("0x300 = 2"));
+
+        f.command("freeze lives=0x300")?;
+        assert!(f.menu.cheats_changed());
+        f.memory.get_rw_slice(0x300, 1)?[0] = 0;
+        f.cheats.after_instruction(&mut f.memory)?;
+        assert_eq!(f.memory.get_ro_slice(0x300, 1)?[0], 2);
+
+        f.command("toggle lives")?;
+        assert_eq!(f.output(), "lives is off\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_mistakes_are_explained() -> Result<(), Chip8Error> {
+        let mut f = Fixture::new()?;
+        assert_eq!(f.command("poke 0xffff=1")?, MenuAction::Stay);
+        assert!(f.output().contains("memory fault"));
+        assert_eq!(f.command("toggle nothing")?, MenuAction::Stay);
+        assert!(f.output().contains("no cheat"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_achievement() -> Result<(), Chip8Error> {
+        let mut f = Fixture::new()?;
+        f.command("achievement 0x300 >= 1 -> One")?;
+        assert_eq!(f.menu.new_achievements(), &["0x300 >= 1 -> One"]);
+        assert!(!f.achievements.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_watch() -> Result<(), Chip8Error> {
+        use crate::watch::MemoryWatch;
+        let mut f = Fixture::new()?;
+        f.command("watch 0x300:2")?;
+        for lives in [3, 2] {
+            f.memory.get_rw_slice(0x300, 1)?[0] = lives;
+            f.watches.frame(&f.memory)?;
+        }
+        f.command("watch")?;
+        assert_eq!(
+            f.output(),
+            "  0x300 =   2  █▁  (2..3)\n  0x301 =   0  ▁▁  (0..0)\n"
+        );
+        f.command("unwatch 0x300")?;
+        f.command("watch")?;
+        assert_eq!(f.output(), "");
+        f.command("watch 0xffff:4")?;
+        assert!(f.output().contains("isn't in memory"));
+        // and past the end of what this machine has
+        f.command("watch 0xfff0:4")?;
+        assert!(f.output().contains("memory fault"));
+        Ok(())
+    }
+}
@@ -0,0 +1,211 @@
+//! # metrics
+//!
+//! for instances left running on a server (netplay, or broadcasting to
+//! spectators): --metrics ADDR serves how it's getting on over HTTP, in
+//! Prometheus' text format, for Prometheus (or anything else) to scrape
+//! from /metrics. there's frames and instructions run, how long frames
+//! take on the host (the average should be a sixtieth of a second at 1x,
+//! and anything over is drift), and who's connected. the numbers are
+//! atomics, so the server thread can read them while the interpreter's
+//! busy
+use crate::error::Chip8Error;
+use crate::memory::Chip8MemoryMap;
+use crate::trace::{TraceEntry, Tracer};
+use crate::watch::MemoryWatch;
+use std::fmt::Display;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// how long a scraper gets to send its request. they're answered one at a
+/// time, so one that connects and says nothing would hold up the rest
+const METRICS_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// what's being counted
+#[derive(Default)]
+pub struct Metrics {
+    pub frames: AtomicU64,
+    pub instructions: AtomicU64,
+    /// the host's time between frames, added up, in nanoseconds
+    pub frame_nanos: AtomicU64,
+    pub spectators: AtomicU64,
+    pub netplay_peers: AtomicU64,
+}
+
+impl Metrics {
+    /// everything, in Prometheus' text format
+    pub fn render(&self) -> String {
+        let get = |n: &AtomicU64| n.load(Ordering::Relaxed);
+        let mut out = String::new();
+        let mut metric =
+            |name, kind, help, value: String| metric(&mut out, name, kind, help, value);
+        metric(
+            "frames_total",
+            "counter",
+            "Display refreshes emulated.",
+            get(&self.frames).to_string(),
+        );
+        metric(
+            "instructions_total",
+            "counter",
+            "CHIP-8 instructions run.",
+            get(&self.instructions).to_string(),
+        );
+        metric(
+            "frame_seconds_total",
+            "counter",
+            "Host time taken by frames; over frames_total/60 at 1x is drift.",
+            format!("{:.6}", get(&self.frame_nanos) as f64 / 1e9),
+        );
+        metric(
+            "spectators",
+            "gauge",
+            "Spectators connected.",
+            get(&self.spectators).to_string(),
+        );
+        metric(
+            "netplay_peers",
+            "gauge",
+            "Netplay peers connected.",
+            get(&self.netplay_peers).to_string(),
+        );
+        out
+    }
+}
+
+/// one metric, with its help and type, onto the end of out
+pub(crate) fn metric(out: &mut String, name: &str, kind: &str, help: &str, value: impl Display) {
+    *out += &format!(
+        "# HELP chip8_{0} {1}\n# TYPE chip8_{0} {2}\nchip8_{0} {3}\n",
+        name, help, kind, value
+    );
+}
+
+/// counts frames (as a watch) and instructions (as a tracer) into some
+/// Metrics. it's both, so it goes in a RefCell
+pub struct MetricsCollector {
+    metrics: Arc<Metrics>,
+    last_frame: Option<Instant>,
+}
+
+impl MetricsCollector {
+    pub fn new(metrics: Arc<Metrics>) -> Self {
+        MetricsCollector {
+            metrics,
+            last_frame: None,
+        }
+    }
+}
+
+impl MemoryWatch for MetricsCollector {
+    fn frame(&mut self, _memory: &Chip8MemoryMap) -> Result<Vec<String>, Chip8Error> {
+        let now = Instant::now();
+        self.metrics.frames.fetch_add(1, Ordering::Relaxed);
+        // the first frame's got nothing to be timed from
+        if let Some(last) = self.last_frame.replace(now) {
+            let nanos = (now - last).as_nanos() as u64;
+            self.metrics.frame_nanos.fetch_add(nanos, Ordering::Relaxed);
+        }
+        Ok(Vec::new())
+    }
+}
+
+impl Tracer for MetricsCollector {
+    fn trace(&mut self, _entry: &TraceEntry) -> Result<(), Chip8Error> {
+        self.metrics.instructions.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+/// serve metrics on listener from a thread of its own, for as long as the
+/// program runs
+pub fn serve(listener: TcpListener, metrics: Arc<Metrics>) {
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            // a scraper that goes away half way isn't our problem
+            let _ = respond(stream, &metrics);
+        }
+    });
+}
+
+fn respond(mut stream: TcpStream, metrics: &Metrics) -> Result<(), Chip8Error> {
+    stream.set_read_timeout(Some(METRICS_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    // the headers don't matter, but they need reading before answering
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+    let (status, body) = match request.split_whitespace().take(2).collect::<Vec<_>>()[..] {
+        ["GET", "/metrics"] => ("200 OK", metrics.render()),
+        ["GET", _] => ("404 Not Found", "try /metrics\n".to_string()),
+        _ => ("405 Method Not Allowed", String::new()),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_render() -> Result<(), Chip8Error> {
+        let metrics = Arc::new(Metrics::default());
+        let mut collector = MetricsCollector::new(metrics.clone());
+        let memory = Chip8MemoryMap::new()?;
+        collector.frame(&memory)?;
+        collector.frame(&memory)?;
+        collector.trace(&TraceEntry {
+            frame: 1,
+            pc: 0x200,
+            opcode: 0x1200,
+            v: [0; 16],
+            i: 0,
+        })?;
+        metrics.spectators.store(3, Ordering::Relaxed);
+        let text = metrics.render();
+        assert!(text.contains("# TYPE chip8_frames_total counter\nchip8_frames_total 2\n"));
+        assert!(text.contains("\nchip8_instructions_total 1\n"));
+        assert!(text.contains("\nchip8_spectators 3\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_serve() -> Result<(), Chip8Error> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        let metrics = Arc::new(Metrics::default());
+        metrics.frames.store(60, Ordering::Relaxed);
+        serve(listener, metrics);
+        let get = |path: &str| -> Result<String, Chip8Error> {
+            let mut s = TcpStream::connect(addr)?;
+            write!(s, "GET {} HTTP/1.1\r\nHost: chip8\r\n\r\n", path)?;
+            let mut response = String::new();
+            s.read_to_string(&mut response)?;
+            Ok(response)
+        };
+        let response = get("/metrics")?;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("chip8_netplay_peers 0\n"));
+        assert!(response.contains("\nchip8_frames_total 60\n"));
+        assert!(get("/")?.starts_with("HTTP/1.1 404"));
+        // someone who never gets round to asking is given up on
+        let _silent = TcpStream::connect(addr)?;
+        assert!(get("/metrics")?.starts_with("HTTP/1.1 200 OK\r\n"));
+        Ok(())
+    }
+}
@@ -0,0 +1,637 @@
+use crate::display::{Display, Hud, Theme};
+use crate::error::Chip8Error;
+use crate::heat::Heat;
+use crate::input::{Feedback, Focus, Input};
+use crate::keypad::KeyTransition;
+use crate::metrics::Metrics;
+use crate::replay::frame_hash;
+use crate::sound::Volume;
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::io;
+use std::net::UdpSocket;
+use std::ops::Range;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// first bytes of every packet, so we can ignore strays
+const NET_MAGIC: &[u8; 2] = b"C8";
+const PACKET_HELLO: u8 = 0;
+const PACKET_FRAME: u8 = 1;
+/// how long to wait for a packet before sending ours again
+const NET_RESEND: Duration = Duration::from_millis(10);
+/// how long to wait for a peer before giving up
+pub const NET_TIMEOUT: Duration = Duration::from_secs(10);
+/// frames of input delay; enough to hide a typical internet round trip
+pub const NET_DEFAULT_DELAY: u32 = 3;
+
+/// one bit per keypad key
+pub fn key_mask(key: Option<u8>) -> u16 {
+    key.map_or(0, |k| 1 << (k & 0xf))
+}
+
+/// the interpreter can only see one key at a time, so if both players are
+/// pressing something, the lower key wins
+pub fn mask_key(mask: u16) -> Option<u8> {
+    if mask == 0 {
+        None
+    } else {
+        Some(mask.trailing_zeros() as u8)
+    }
+}
+
+fn hello_packet(seed: u16, rom_hash: u64) -> Vec<u8> {
+    let mut p = NET_MAGIC.to_vec();
+    p.push(PACKET_HELLO);
+    p.extend_from_slice(&seed.to_be_bytes());
+    p.extend_from_slice(&rom_hash.to_be_bytes());
+    p
+}
+
+fn parse_hello(p: &[u8]) -> Option<(u16, u64)> {
+    if p.len() != 13 || &p[..2] != NET_MAGIC || p[2] != PACKET_HELLO {
+        return None;
+    }
+    let seed = u16::from_be_bytes(p[3..5].try_into().ok()?);
+    let rom_hash = u64::from_be_bytes(p[5..13].try_into().ok()?);
+    Some((seed, rom_hash))
+}
+
+/// say hello to the peer and agree on a seed. the host picks the seed and
+/// waits for someone to connect to it; the joiner keeps knocking until the
+/// host answers, and uses the host's seed. both sides must be running the
+/// same ROM
+pub fn handshake(
+    socket: &UdpSocket,
+    host: bool,
+    seed: u16,
+    rom_hash: u64,
+) -> Result<u16, Chip8Error> {
+    let start = Instant::now();
+    let mut buf = [0u8; 512];
+    socket.set_read_timeout(Some(NET_RESEND * 10))?;
+    loop {
+        if start.elapsed() > NET_TIMEOUT && !host {
+            return Err(
+                io::Error::new(io::ErrorKind::TimedOut, "no answer from netplay host").into(),
+            );
+        }
+        if !host {
+            send(socket, &hello_packet(seed, rom_hash))?;
+        }
+        let (n, from) = match socket.recv_from(&mut buf) {
+            Ok(r) => r,
+            Err(e) if is_transient(&e) => continue,
+            Err(e) => return Err(e.into()),
+        };
+        if let Some((peer_seed, peer_rom)) = parse_hello(&buf[..n]) {
+            if peer_rom != rom_hash {
+                return Err(Chip8Error::ConfigError(
+                    "netplay peer is running a different ROM".to_string(),
+                ));
+            }
+            if host {
+                socket.connect(from)?;
+                send(socket, &hello_packet(seed, rom_hash))?;
+                return Ok(seed);
+            }
+            return Ok(peer_seed);
+        }
+    }
+}
+
+/// errors that just mean nothing's turned up (yet). a connected UDP socket
+/// reports the peer's port being closed, which we treat the same as the peer
+/// being slow; if it never comes back, we time out
+fn is_transient(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut | io::ErrorKind::ConnectionRefused
+    )
+}
+
+/// send, not minding if the peer isn't listening right now
+fn send(socket: &UdpSocket, p: &[u8]) -> Result<(), Chip8Error> {
+    match socket.send(p) {
+        Err(e) if !is_transient(&e) => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// deterministic lockstep over UDP. each frame both peers send their keypad
+/// mask for `delay` frames in the future, then wait for the other's mask for
+/// this frame; as both emulators see exactly the same keys on exactly the
+/// same frames, they stay in step without sending any state. frame hashes
+/// ride along with the inputs so a desync is caught straight away
+pub struct Lockstep {
+    socket: UdpSocket,
+    delay: u32,
+    // next frame to play
+    frame: u32,
+    local: BTreeMap<u32, u16>,
+    remote: BTreeMap<u32, u16>,
+    local_hashes: BTreeMap<u32, u64>,
+    remote_hashes: BTreeMap<u32, u64>,
+    // what we said in the handshake, in case the host's answer went missing
+    hello: Vec<u8>,
+    metrics: Option<Arc<Metrics>>,
+}
+
+impl Lockstep {
+    /// socket must be connected to the peer, e.g. after a handshake
+    pub fn new(
+        socket: UdpSocket,
+        delay: u32,
+        seed: u16,
+        rom_hash: u64,
+    ) -> Result<Self, Chip8Error> {
+        socket.set_read_timeout(Some(NET_RESEND))?;
+        Ok(Lockstep {
+            socket,
+            // keeps a frame's worth of inputs inside one packet
+            delay: delay.min(100),
+            frame: 0,
+            local: BTreeMap::new(),
+            remote: BTreeMap::new(),
+            local_hashes: BTreeMap::new(),
+            remote_hashes: BTreeMap::new(),
+            hello: hello_packet(seed, rom_hash),
+            metrics: None,
+        })
+    }
+
+    /// count the peer in metrics for as long as it's there
+    pub fn set_metrics(&mut self, metrics: Arc<Metrics>) {
+        metrics.netplay_peers.store(1, Ordering::Relaxed);
+        self.metrics = Some(metrics);
+    }
+
+    /// the peer's gone, or we have
+    fn disconnected(&self) {
+        if let Some(m) = &self.metrics {
+            m.netplay_peers.store(0, Ordering::Relaxed);
+        }
+    }
+
+    /// the frame we're about to play
+    pub fn frame(&self) -> u32 {
+        self.frame
+    }
+
+    /// play a frame: queue our keys for later, and get both players' keys
+    /// for now, waiting for the peer if it's behind. `prev_hash` is the hash
+    /// of the last frame drawn, if any
+    pub fn advance(&mut self, local_mask: u16, prev_hash: Option<u64>) -> Result<u16, Chip8Error> {
+        if let (Some(h), Some(prev)) = (prev_hash, self.frame.checked_sub(1)) {
+            self.local_hashes.insert(prev, h);
+        }
+        self.local
+            .insert(self.frame.saturating_add(self.delay), local_mask);
+        // send before checking, so that the peer finds out about any desync
+        // too
+        self.send_frame()?;
+        self.check_hashes()?;
+
+        // nobody could press anything during the first `delay` frames
+        let start = Instant::now();
+        while self.frame >= self.delay && !self.remote.contains_key(&self.frame) {
+            if start.elapsed() > NET_TIMEOUT {
+                self.disconnected();
+                return Err(
+                    io::Error::new(io::ErrorKind::TimedOut, "netplay peer went away").into(),
+                );
+            }
+            if !self.receive()? {
+                self.send_frame()?;
+            }
+            self.check_hashes()?;
+        }
+
+        let mask = self.local.get(&self.frame).copied().unwrap_or(0)
+            | self.remote.get(&self.frame).copied().unwrap_or(0);
+
+        // forget anything the peer can't still be waiting for
+        let keep = self.frame.saturating_sub(self.delay + 1);
+        self.local = self.local.split_off(&keep);
+        self.remote = self.remote.split_off(&keep);
+        self.frame = self.frame.saturating_add(1);
+        Ok(mask)
+    }
+
+    /// compare whatever frame hashes we have from both sides
+    fn check_hashes(&mut self) -> Result<(), Chip8Error> {
+        for (frame, theirs) in &self.remote_hashes {
+            if let Some(ours) = self.local_hashes.get(frame) {
+                if ours != theirs {
+                    return Err(Chip8Error::Desync { frame: *frame });
+                }
+            }
+        }
+        let local = &self.local_hashes;
+        self.remote_hashes.retain(|f, _| !local.contains_key(f));
+
+        // the peer is never more than a few frames away, so only the last
+        // few hashes can still be of interest to either of us
+        let keep = self.frame.saturating_sub(2 * self.delay + 4);
+        self.local_hashes = self.local_hashes.split_off(&keep);
+        self.remote_hashes = self.remote_hashes.split_off(&keep);
+        Ok(())
+    }
+
+    /// send every input the peer might still need, plus our recent hashes
+    fn send_frame(&self) -> Result<(), Chip8Error> {
+        let mut p = NET_MAGIC.to_vec();
+        p.push(PACKET_FRAME);
+        let first = self.local.keys().next().copied().unwrap_or(self.frame);
+        p.extend_from_slice(&first.to_be_bytes());
+        let masks: Vec<u16> = self.local.range(first..).map(|(_, m)| *m).collect();
+        p.push(masks.len() as u8);
+        for m in masks {
+            p.extend_from_slice(&m.to_be_bytes());
+        }
+        p.push(self.local_hashes.len() as u8);
+        for (frame, hash) in &self.local_hashes {
+            p.extend_from_slice(&frame.to_be_bytes());
+            p.extend_from_slice(&hash.to_be_bytes());
+        }
+        send(&self.socket, &p)
+    }
+
+    /// take a packet from the peer, if one turns up in time
+    fn receive(&mut self) -> Result<bool, Chip8Error> {
+        let mut buf = [0u8; 1024];
+        let n = loop {
+            match self.socket.recv(&mut buf) {
+                Ok(n) => break n,
+                // reporting a refused send uses up the error, and there may
+                // still be packets queued behind it
+                Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => continue,
+                Err(e) if is_transient(&e) => return Ok(false),
+                Err(e) => return Err(e.into()),
+            }
+        };
+        let p = &buf[..n];
+        if parse_hello(p).is_some() {
+            // the joiner didn't hear the host's hello, so say it again
+            send(&self.socket, &self.hello)?;
+            return Ok(true);
+        }
+        if n < 8 || &p[..2] != NET_MAGIC || p[2] != PACKET_FRAME {
+            return Ok(true);
+        }
+        let first = u32::from_be_bytes([p[3], p[4], p[5], p[6]]);
+        let count = p[7] as usize;
+        let hashes_at = 8 + count * 2;
+        if n <= hashes_at || n != hashes_at + 1 + p[hashes_at] as usize * 12 {
+            return Ok(true);
+        }
+        // the frames are the peer's say-so, so anything that's not near
+        // ours is nonsense, and kept out of the maps
+        for (i, m) in p[8..8 + count * 2].chunks(2).enumerate() {
+            match first.checked_add(i as u32) {
+                Some(frame) if self.near(frame) => {
+                    self.remote.insert(frame, u16::from_be_bytes([m[0], m[1]]));
+                }
+                _ => {}
+            }
+        }
+        for h in p[hashes_at + 1..].chunks(12) {
+            let frame = u32::from_be_bytes(h[..4].try_into().unwrap());
+            let hash = u64::from_be_bytes(h[4..].try_into().unwrap());
+            if self.near(frame) {
+                self.remote_hashes.insert(frame, hash);
+            }
+        }
+        Ok(true)
+    }
+
+    /// could the peer, a few frames either side of us at most, be telling
+    /// us about frame?
+    fn near(&self, frame: u32) -> bool {
+        let window = 2 * self.delay + 4;
+        frame >= self.frame.saturating_sub(window) && frame <= self.frame.saturating_add(window)
+    }
+}
+
+impl Drop for Lockstep {
+    fn drop(&mut self) {
+        self.disconnected();
+    }
+}
+
+/// passes frames on to another display, and remembers the hash of the latest
+/// one for LockstepInput to send to the peer
+pub struct HashTap<'a> {
+    inner: &'a mut dyn Display,
+    latest: &'a Cell<Option<u64>>,
+}
+
+impl<'a> HashTap<'a> {
+    pub fn new(inner: &'a mut dyn Display, latest: &'a Cell<Option<u64>>) -> Self {
+        HashTap { inner, latest }
+    }
+}
+
+impl<'a> Display for HashTap<'a> {
+    fn draw(&mut self, data: &[u8]) -> Result<(), Chip8Error> {
+        self.draw_changes(data, None)
+    }
+
+    fn draw_changes(
+        &mut self,
+        data: &[u8],
+        changed: Option<&[Range<usize>]>,
+    ) -> Result<(), Chip8Error> {
+        self.latest.set(Some(frame_hash(data)));
+        self.inner.draw_changes(data, changed)
+    }
+
+    fn get_display_size_bytes(&mut self) -> usize {
+        self.inner.get_display_size_bytes()
+    }
+
+    fn set_planes(&mut self, planes: usize) -> Result<(), Chip8Error> {
+        self.inner.set_planes(planes)
+    }
+
+    fn set_mode(&mut self, width: usize, height: usize) -> Result<(), Chip8Error> {
+        self.inner.set_mode(width, height)
+    }
+
+    fn set_status(&mut self, status: &str) {
+        self.inner.set_status(status);
+    }
+
+    fn notify(&mut self, notice: &str) {
+        self.inner.notify(notice);
+    }
+
+    fn set_speed(&mut self, speed: f64) {
+        self.inner.set_speed(speed);
+    }
+
+    fn set_volume(&mut self, volume: Volume) {
+        self.inner.set_volume(volume);
+    }
+
+    fn set_hud(&mut self, hud: Option<Hud>) {
+        self.inner.set_hud(hud);
+    }
+
+    fn set_heat(&mut self, heat: Option<Heat>) {
+        self.inner.set_heat(heat);
+    }
+
+    fn set_help(&mut self, help: Option<Vec<String>>) {
+        self.inner.set_help(help);
+    }
+
+    fn set_slots(&mut self, slots: Option<Vec<String>>) {
+        self.inner.set_slots(slots);
+    }
+
+    fn set_palette(&mut self, palette: Option<Vec<String>>) {
+        self.inner.set_palette(palette);
+    }
+
+    fn set_theme(&mut self, theme: Theme) {
+        self.inner.set_theme(theme);
+    }
+
+    fn set_focus(&mut self, focus: Focus) {
+        self.inner.set_focus(focus);
+    }
+
+    fn refresh(&mut self) -> Result<(), Chip8Error> {
+        self.inner.refresh()
+    }
+}
+
+/// feeds the interpreter both players' keys, sampling the local player once
+/// a frame. savestate slots and the command palette don't get through, as
+/// the peer wouldn't jump to the same state or change the same quirk
+pub struct LockstepInput<'a> {
+    inner: &'a mut dyn Input,
+    lockstep: Lockstep,
+    latest_hash: &'a Cell<Option<u64>>,
+    latched_key: Option<u8>,
+}
+
+impl<'a> LockstepInput<'a> {
+    pub fn new(
+        inner: &'a mut dyn Input,
+        lockstep: Lockstep,
+        latest_hash: &'a Cell<Option<u64>>,
+    ) -> Self {
+        LockstepInput {
+            inner,
+            lockstep,
+            latest_hash,
+            latched_key: None,
+        }
+    }
+}
+
+impl<'a> Input for LockstepInput<'a> {
+    fn flush_keys(&mut self) -> Result<(), Chip8Error> {
+        self.latched_key = None;
+        self.inner.flush_keys()
+    }
+
+    fn read_key(&mut self) -> Result<Option<u8>, Chip8Error> {
+        Ok(self.latched_key)
+    }
+
+    fn tick(&mut self) -> Result<(), Chip8Error> {
+        self.inner.tick()?;
+        let local = key_mask(self.inner.read_key()?);
+        let mask = self.lockstep.advance(local, self.latest_hash.get())?;
+        self.latched_key = mask_key(mask);
+        Ok(())
+    }
+
+    fn wait_for_event(&mut self, timeout: Duration) -> Result<bool, Chip8Error> {
+        self.inner.wait_for_event(timeout)
+    }
+
+    fn transitions(&self) -> &[KeyTransition] {
+        self.inner.transitions()
+    }
+
+    fn focus(&self) -> Focus {
+        self.inner.focus()
+    }
+
+    fn set_focus(&mut self, focus: Focus) {
+        self.inner.set_focus(focus)
+    }
+
+    fn feedback(&mut self, feedback: Feedback) {
+        self.inner.feedback(feedback)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::Typed;
+    use std::thread;
+
+    fn socket_pair() -> Result<(UdpSocket, UdpSocket), Chip8Error> {
+        let a = UdpSocket::bind("127.0.0.1:0")?;
+        let b = UdpSocket::bind("127.0.0.1:0")?;
+        a.connect(b.local_addr()?)?;
+        b.connect(a.local_addr()?)?;
+        Ok((a, b))
+    }
+
+    #[test]
+    fn test_masks() {
+        assert_eq!(key_mask(None), 0);
+        assert_eq!(key_mask(Some(0xa)), 0x0400);
+        assert_eq!(mask_key(0), None);
+        assert_eq!(mask_key(0x0410), Some(0x4));
+    }
+
+    #[test]
+    fn test_handshake() -> Result<(), Chip8Error> {
+        let host = UdpSocket::bind("127.0.0.1:0")?;
+        let join = UdpSocket::bind("127.0.0.1:0")?;
+        join.connect(host.local_addr()?)?;
+        let t = thread::spawn(move || handshake(&host, true, 0x1234, 42));
+        assert_eq!(handshake(&join, false, 0x9999, 42)?, 0x1234);
+        assert_eq!(t.join().unwrap()?, 0x1234);
+        Ok(())
+    }
+
+    #[test]
+    fn test_handshake_rejects_other_rom() -> Result<(), Chip8Error> {
+        let host = UdpSocket::bind("127.0.0.1:0")?;
+        let join = UdpSocket::bind("127.0.0.1:0")?;
+        join.connect(host.local_addr()?)?;
+        join.send(&hello_packet(0, 1))?;
+        assert!(handshake(&host, true, 0, 2).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_lockstep_exchanges_keys() -> Result<(), Chip8Error> {
+        let (a, b) = socket_pair()?;
+        let t = thread::spawn(move || -> Result<Vec<u16>, Chip8Error> {
+            let mut l = Lockstep::new(b, 2, 0, 0)?;
+            (0..6).map(|f| l.advance(1 << f, Some(f as u64))).collect()
+        });
+        let mut l = Lockstep::new(a, 2, 0, 0)?;
+        let metrics = Arc::new(Metrics::default());
+        l.set_metrics(metrics.clone());
+        let ours = (0..6)
+            .map(|f| l.advance(0x8000, Some(f as u64)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let theirs = t.join().unwrap()?;
+        // nothing for the first two frames, then both players' keys, late
+        assert_eq!(ours, vec![0, 0, 0x8001, 0x8002, 0x8004, 0x8008]);
+        assert_eq!(ours, theirs);
+        // and the peer's counted until we're done with it
+        assert_eq!(metrics.netplay_peers.load(Ordering::Relaxed), 1);
+        drop(l);
+        assert_eq!(metrics.netplay_peers.load(Ordering::Relaxed), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_lockstep_ignores_far_frames() -> Result<(), Chip8Error> {
+        let (a, b) = socket_pair()?;
+        let mut l = Lockstep::new(a, 2, 0, 0)?;
+        // masks running off the end of the frame numbers, and a hash for a
+        // frame nowhere near ours
+        let mut p = NET_MAGIC.to_vec();
+        p.push(PACKET_FRAME);
+        p.extend_from_slice(&(u32::MAX - 1).to_be_bytes());
+        p.push(4);
+        p.extend_from_slice(&[0xff; 8]);
+        p.push(1);
+        p.extend_from_slice(&u32::MAX.to_be_bytes());
+        p.extend_from_slice(&42u64.to_be_bytes());
+        b.send(&p)?;
+        assert!(l.receive()?);
+        assert!(l.remote.is_empty());
+        assert!(l.remote_hashes.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_lockstep_detects_desync() -> Result<(), Chip8Error> {
+        let (a, b) = socket_pair()?;
+        let t = thread::spawn(move || -> Result<(), Chip8Error> {
+            let mut l = Lockstep::new(b, 1, 0, 0)?;
+            for f in 0..10 {
+                l.advance(0, Some(f))?;
+            }
+            Ok(())
+        });
+        let mut l = Lockstep::new(a, 1, 0, 0)?;
+        let mut result = Ok(0);
+        for f in 0..10 {
+            result = l.advance(0, Some(if f == 3 { 99 } else { f }));
+            if result.is_err() {
+                break;
+            }
+        }
+        // both sides notice
+        assert!(matches!(result, Err(Chip8Error::Desync { frame: 2 })));
+        assert!(matches!(
+            t.join().unwrap(),
+            Err(Chip8Error::Desync { frame: 2 })
+        ));
+        Ok(())
+    }
+
+    /// a player at the command palette, choosing to load slot 3
+    struct AtThePalette;
+
+    impl Input for AtThePalette {
+        fn flush_keys(&mut self) -> Result<(), Chip8Error> {
+            Ok(())
+        }
+
+        fn read_key(&mut self) -> Result<Option<u8>, Chip8Error> {
+            Ok(None)
+        }
+
+        fn tick(&mut self) -> Result<(), Chip8Error> {
+            Ok(())
+        }
+
+        fn take_palette_request(&mut self) -> Result<bool, Chip8Error> {
+            Ok(true)
+        }
+
+        fn take_typed(&mut self) -> Result<Vec<Typed>, Chip8Error> {
+            Ok("load 3\n"
+                .chars()
+                .map(|c| match c {
+                    '\n' => Typed::Enter,
+                    c => Typed::Char(c),
+                })
+                .collect())
+        }
+    }
+
+    /// neither the palette, nor what's typed into it, gets through input
+    fn palette_held_back(input: &mut dyn Input) -> Result<(), Chip8Error> {
+        assert!(!input.take_palette_request()?);
+        input.set_typing(true);
+        assert_eq!(input.take_typed()?, vec![]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_lockstep_holds_back_the_palette() -> Result<(), Chip8Error> {
+        let (a, _b) = socket_pair()?;
+        let mut inner = AtThePalette;
+        let latest = Cell::new(None);
+        let lockstep = Lockstep::new(a, 1, 0, 0)?;
+        palette_held_back(&mut LockstepInput::new(&mut inner, lockstep, &latest))
+    }
+}
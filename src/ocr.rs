@@ -0,0 +1,161 @@
+use crate::error::Chip8Error;
+use crate::font::{FontLocator, VipFont};
+use crate::memory::{Chip8MemoryMap, MemoryMap};
+
+/// the framebuffer is 64x32, one bit per pixel, leftmost pixel in the top bit
+const OCR_WIDTH: usize = 64;
+const OCR_HEIGHT: usize = 32;
+/// font glyphs are 4 pixels wide and 5 tall
+const GLYPH_WIDTH: usize = 4;
+const GLYPH_HEIGHT: usize = 5;
+
+/// read a number stored as a decimal digit per byte, most significant first, as
+/// FX33 leaves it. None if any of the bytes isn't a digit
+pub fn read_bcd(
+    memory: &Chip8MemoryMap,
+    addr: u16,
+    digits: usize,
+) -> Result<Option<u32>, Chip8Error> {
+    Ok(memory
+        .get_ro_slice(addr, digits)?
+        .iter()
+        .try_fold(0, |n, d| (*d < 10).then(|| n * 10 + *d as u32)))
+}
+
+/// what's on screen, in the format DigitReader wants
+pub fn framebuffer(memory: &Chip8MemoryMap) -> Result<&[u8], Chip8Error> {
+    memory.get_ro_slice(memory.display_addr, OCR_WIDTH * OCR_HEIGHT / 8)
+}
+
+/// is the pixel at x, y lit? anything off screen isn't
+pub fn pixel(frame: &[u8], x: usize, y: usize) -> bool {
+    x < OCR_WIDTH
+        && y < OCR_HEIGHT
+        && frame
+            .get(y * OCR_WIDTH / 8 + x / 8)
+            .is_some_and(|b| b & (0x80 >> (x % 8)) != 0)
+}
+
+/// reads digits drawn on screen with the built-in font (i.e. via FX29), so
+/// bots and batch runs can read scores without knowing where a ROM keeps them
+pub struct DigitReader {
+    // each glyph's rows, in the top nibble
+    glyphs: [[u8; GLYPH_HEIGHT]; 10],
+}
+
+impl DigitReader {
+    /// use the font the interpreter would, by looking the glyphs up the same
+    /// way FX29 does
+    pub fn from_memory(memory: &Chip8MemoryMap) -> Result<Self, Chip8Error> {
+        let mut glyphs = [[0; GLYPH_HEIGHT]; 10];
+        for (d, glyph) in glyphs.iter_mut().enumerate() {
+            let addr = VipFont.small(memory, d as u8)?;
+            for (row, byte) in glyph
+                .iter_mut()
+                .zip(memory.get_ro_slice(addr, GLYPH_HEIGHT)?)
+            {
+                *row = byte & 0xf0;
+            }
+        }
+        Ok(DigitReader { glyphs })
+    }
+
+    /// the digit whose glyph's top left corner is at x, y, if there is one
+    pub fn read_digit(&self, frame: &[u8], x: usize, y: usize) -> Option<u8> {
+        let mut rows = [0u8; GLYPH_HEIGHT];
+        for (dy, row) in rows.iter_mut().enumerate() {
+            for dx in 0..GLYPH_WIDTH {
+                if pixel(frame, x + dx, y + dy) {
+                    *row |= 0x80 >> dx;
+                }
+            }
+        }
+        self.glyphs.iter().position(|g| *g == rows).map(|d| d as u8)
+    }
+
+    /// read a number of digits drawn left to right, spacing pixels apart
+    /// (typically 5), e.g. a score drawn straight after FX33
+    pub fn read_number(
+        &self,
+        frame: &[u8],
+        x: usize,
+        y: usize,
+        digits: usize,
+        spacing: usize,
+    ) -> Option<u32> {
+        (0..digits).try_fold(0, |n, i| {
+            self.read_digit(frame, x + i * spacing, y)
+                .map(|d| n * 10 + d as u32)
+        })
+    }
+
+    /// every digit on screen with a blank column either side of it, as
+    /// (x, y, digit), top to bottom then left to right
+    pub fn find_digits(&self, frame: &[u8]) -> Vec<(usize, usize, u8)> {
+        let blank_column = |x: Option<usize>, y: usize| {
+            x.is_none_or(|x| (0..GLYPH_HEIGHT).all(|dy| !pixel(frame, x, y + dy)))
+        };
+        let mut found = Vec::new();
+        for y in 0..=(OCR_HEIGHT - GLYPH_HEIGHT) {
+            for x in 0..=(OCR_WIDTH - GLYPH_WIDTH) {
+                if let Some(d) = self.read_digit(frame, x, y) {
+                    if blank_column(x.checked_sub(1), y) && blank_column(Some(x + GLYPH_WIDTH), y) {
+                        found.push((x, y, d));
+                    }
+                }
+            }
+        }
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::Chip8Interpreter;
+    use crate::{display, input, sound};
+
+    #[test]
+    fn test_read_bcd() -> Result<(), Chip8Error> {
+        let mut m = Chip8MemoryMap::new()?;
+        m.write(&[1, 2, 3, 0xa], 0x300, 4)?;
+        assert_eq!(read_bcd(&m, 0x300, 3)?, Some(123));
+        assert_eq!(read_bcd(&m, 0x301, 3)?, None);
+        assert!(read_bcd(&m, 0xffff, 3).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_drawn_score() -> Result<(), Chip8Error> {
+        // v0 = 207; i = 0x300; bcd v0; load v0-v2
+        // then draw each digit 5 pixels apart at (10, 12)
+        #[rustfmt::skip]
+        let mut program: &[u8] = &[
+            0x60, 0xcf, 0xa3, 0x00, 0xf0, 0x33, 0xf2, 0x65,
+            0x63, 0x0a, 0x64, 0x0c,
+            0xf0, 0x29, 0xd3, 0x45, 0x73, 0x05,
+            0xf1, 0x29, 0xd3, 0x45, 0x73, 0x05,
+            0xf2, 0x29, 0xd3, 0x45,
+            0x12, 0x1c,
+        ];
+        let mut display = display::DummyDisplay::new()?;
+        let mut input = input::DummyInput::new(&[]);
+        let mut sound = sound::Mute::new();
+        let mut i = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+        i.load_program(&mut program)?;
+        i.run_frames(10)?;
+
+        let m = i.memory();
+        assert_eq!(read_bcd(m, 0x300, 3)?, Some(207));
+        let reader = DigitReader::from_memory(m)?;
+        let frame = framebuffer(m)?;
+        assert_eq!(reader.read_digit(frame, 10, 12), Some(2));
+        assert_eq!(reader.read_number(frame, 10, 12, 3, 5), Some(207));
+        assert_eq!(reader.read_number(frame, 11, 12, 3, 5), None);
+        assert_eq!(
+            reader.find_digits(frame),
+            vec![(10, 12, 2), (15, 12, 0), (20, 12, 7)]
+        );
+        Ok(())
+    }
+}
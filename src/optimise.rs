@@ -0,0 +1,529 @@
+//! # peephole optimiser
+//!
+//! an experiment: rewrite a ROM into one that does the same with fewer
+//! instructions. it looks through each straight run of code (as the
+//! analyser found it) for instructions that can't do anything: loading I
+//! or a register with what it already holds, adding 0, copying a register
+//! to itself and jumping to the very next instruction. it also points jumps
+//! to jumps straight at where they end up.
+//!
+//! taking an instruction out moves everything after it, so every address
+//! in a jump, call or ANNN is moved to match. that's only safe if the
+//! analyser can find them all, so a ROM with BNNN, machine code or code
+//! that writes over itself is left alone. even then, it's only as good as
+//! the check afterwards: both ROMs are run side by side, on recorded keys
+//! if there are some, and the new one has to match the old one's screen,
+//! registers, timers and memory every frame
+use crate::analyse::{self, Analysis};
+use crate::display::DummyDisplay;
+use crate::error::Chip8Error;
+use crate::interpreter::Chip8Interpreter;
+use crate::isa;
+use crate::memory::MemoryMap;
+use crate::ocr;
+use crate::replay::{PlaybackInput, Replay};
+use crate::sound::Mute;
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// where programs start
+const OPTIMISE_ORIGIN: u16 = 0x200;
+
+/// how many jumps in a row to follow, before giving up on it as a loop
+const OPTIMISE_MAX_HOPS: usize = 16;
+
+/// the stack's room below the top of it, as the memory map has it (0x0ea0
+/// to 0x0ecf on a 4K VIP). what's there is return addresses, which move
+const OPTIMISE_STACK_BYTES: u16 = 0x30;
+
+/// the things that get taken out, or changed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Peephole {
+    /// ANNN when I's already NNN
+    ReloadI,
+    /// 6XNN when VX's already NN
+    ReloadRegister,
+    /// 7X00
+    AddZero,
+    /// 8XX0
+    CopyToSelf,
+    /// 1NNN to the next instruction
+    JumpToNext,
+    /// 1NNN to another 1NNN, which now goes straight there
+    JumpToJump,
+}
+
+impl fmt::Display for Peephole {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Peephole::ReloadI => "I already holds this",
+            Peephole::ReloadRegister => "the register already holds this",
+            Peephole::AddZero => "adds nothing",
+            Peephole::CopyToSelf => "copies a register to itself",
+            Peephole::JumpToNext => "jumps to the next instruction",
+            Peephole::JumpToJump => "jumps to a jump",
+        })
+    }
+}
+
+/// what was done, and where (in the original)
+#[derive(Debug, PartialEq)]
+pub struct Change {
+    pub addr: u16,
+    pub inst: u16,
+    pub peephole: Peephole,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let done = match self.peephole {
+            Peephole::JumpToJump => "shortened",
+            _ => "removed",
+        };
+        write!(
+            f,
+            "{:04x}: {:04x} {} ({})",
+            self.addr, self.inst, done, self.peephole
+        )
+    }
+}
+
+/// the new ROM, and how it got there
+#[derive(Debug, PartialEq)]
+pub struct Optimised {
+    pub rom: Vec<u8>,
+    pub changes: Vec<Change>,
+}
+
+/// what's known about the registers part way along a run of code
+#[derive(Clone, Copy, Default, PartialEq)]
+struct Known {
+    v: [Option<u8>; 16],
+    i: Option<u16>,
+}
+
+impl Known {
+    /// what's still known if an instruction that would make this from
+    /// before might not have run
+    fn maybe(self, before: Known) -> Known {
+        let keep = |a: Option<u8>, b: Option<u8>| a.filter(|_| a == b);
+        let mut v = [None; 16];
+        for (n, r) in v.iter_mut().enumerate() {
+            *r = keep(self.v[n], before.v[n]);
+        }
+        Known {
+            v,
+            i: self.i.filter(|_| self.i == before.i),
+        }
+    }
+
+    /// what's known after inst
+    fn after(mut self, inst: u16) -> Known {
+        let (x, y) = (((inst >> 8) & 0xf) as usize, ((inst >> 4) & 0xf) as usize);
+        let nn = inst as u8;
+        match inst & 0xf000 {
+            0x6000 => self.v[x] = Some(nn),
+            0x7000 => self.v[x] = self.v[x].map(|v| v.wrapping_add(nn)),
+            0x8000 if inst & 0xf == 0 => self.v[x] = self.v[y],
+            // the rest of the arithmetic changes VF too (even the logic,
+            // on the VIP)
+            0x8000 => {
+                self.v[x] = None;
+                self.v[0xf] = None;
+            }
+            0xa000 => self.i = Some(inst & 0xfff),
+            0xc000 => self.v[x] = None,
+            0xd000 => self.v[0xf] = None,
+            0xf000 => match inst & 0xff {
+                0x07 | 0x0a => self.v[x] = None,
+                0x1e | 0x29 | 0x30 | 0x55 => self.i = None,
+                0x65 => {
+                    self.i = None;
+                    self.v[..=x].fill(None);
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+        self
+    }
+}
+
+/// the optimised rom, or why it can't be
+pub fn optimise(rom: &[u8]) -> Result<Optimised, Chip8Error> {
+    let a = analyse::analyse(rom);
+    let cant = |why: &str| Err(Chip8Error::BadRom(format!("can't optimise it: {}", why)));
+    if !a.computed_jumps.is_empty() {
+        return cant("BNNN jumps somewhere that can't be worked out");
+    }
+    if !a.code_writes.is_empty() {
+        return cant("it writes over its own code");
+    }
+    if code(rom, &a).any(|(_, inst)| isa::lookup(inst).is_some_and(|o| o.pattern == "0NNN")) {
+        return cant("it calls machine code");
+    }
+
+    // where the flow of control joins from somewhere else, so nothing's
+    // known; and the instructions a skip skips, which can't be taken out
+    let mut joins = BTreeSet::from([OPTIMISE_ORIGIN]);
+    let mut skipped = BTreeSet::new();
+    for (addr, inst) in code(rom, &a) {
+        match inst & 0xf000 {
+            0x1000 | 0x2000 => {
+                joins.insert(inst & 0xfff);
+                joins.extend(addr.checked_add(2));
+            }
+            // the instruction after the one skipped isn't a join: what's
+            // known there is worked out from both ways of getting there
+            _ if analyse::is_skip(inst) => {
+                skipped.extend(addr.checked_add(2));
+            }
+            _ if inst == 0x00ee || inst == 0x00fd => {
+                joins.extend(addr.checked_add(2));
+            }
+            _ => {}
+        }
+    }
+
+    let mut changes = Vec::new();
+    let mut known = Known::default();
+    let mut last = None;
+    for (addr, inst) in code(rom, &a) {
+        if joins.contains(&addr) || last != addr.checked_sub(2) {
+            known = Known::default();
+        }
+        last = Some(addr);
+        let (x, y) = ((inst >> 8) & 0xf, (inst >> 4) & 0xf);
+        let peephole = match inst & 0xf000 {
+            0xa000 if known.i == Some(inst & 0xfff) => Some(Peephole::ReloadI),
+            0x6000 if known.v[x as usize] == Some(inst as u8) => Some(Peephole::ReloadRegister),
+            0x7000 if inst & 0xff == 0 => Some(Peephole::AddZero),
+            0x8000 if inst & 0xf == 0 && x == y => Some(Peephole::CopyToSelf),
+            0x1000 if addr.checked_add(2) == Some(inst & 0xfff) => Some(Peephole::JumpToNext),
+            0x1000 if jump_target(rom, inst & 0xfff) != inst & 0xfff => Some(Peephole::JumpToJump),
+            _ => None,
+        };
+        let removable = !skipped.contains(&addr) && !a.data.contains(&addr);
+        match peephole {
+            Some(Peephole::JumpToJump) => changes.push(Change {
+                addr,
+                inst,
+                peephole: Peephole::JumpToJump,
+            }),
+            Some(peephole) if removable => {
+                changes.push(Change {
+                    addr,
+                    inst,
+                    peephole,
+                });
+                continue;
+            }
+            _ => {}
+        }
+        let after = known.after(inst);
+        known = match skipped.contains(&addr) {
+            true => after.maybe(known),
+            false => after,
+        };
+    }
+
+    Ok(Optimised {
+        rom: relocate(rom, &a, &changes),
+        changes,
+    })
+}
+
+/// each instruction the analyser found, in order
+fn code<'r>(rom: &'r [u8], a: &'r Analysis) -> impl Iterator<Item = (u16, u16)> + 'r {
+    a.code
+        .iter()
+        .filter_map(|addr| Some((*addr, analyse::word_at(rom, *addr)?)))
+}
+
+/// where a jump to addr ends up, following any jumps there
+fn jump_target(rom: &[u8], mut addr: u16) -> u16 {
+    let start = addr;
+    for _ in 0..OPTIMISE_MAX_HOPS {
+        match analyse::word_at(rom, addr) {
+            Some(inst) if inst & 0xf000 == 0x1000 && inst & 0xfff != addr => addr = inst & 0xfff,
+            _ => return addr,
+        }
+    }
+    // round in circles
+    start
+}
+
+/// the bytes changes takes out
+fn removed(changes: &[Change]) -> BTreeSet<u16> {
+    changes
+        .iter()
+        .filter(|c| c.peephole != Peephole::JumpToJump)
+        .flat_map(|c| [c.addr, c.addr.saturating_add(1)])
+        .collect()
+}
+
+/// where what was at an address in a ROM len long is, once removed's been
+/// taken out. anything outside the ROM (scratch memory, the font) stays put
+fn mover(len: usize, removed: &BTreeSet<u16>) -> impl Fn(u16) -> u16 + '_ {
+    let rom = OPTIMISE_ORIGIN as usize..OPTIMISE_ORIGIN as usize + len;
+    move |addr| match addr {
+        a if rom.contains(&(a as usize)) => a - removed.range(..a).count() as u16,
+        a => a,
+    }
+}
+
+/// rom without what changes takes out, and with every address moved to
+/// match
+fn relocate(rom: &[u8], a: &Analysis, changes: &[Change]) -> Vec<u8> {
+    let removed = removed(changes);
+    let moved = mover(rom.len(), &removed);
+    let mut out = Vec::with_capacity(rom.len());
+    for (n, byte) in rom.iter().enumerate() {
+        // anything past the end of memory can't have been taken out
+        let kept = u16::try_from(OPTIMISE_ORIGIN as usize + n)
+            .map_or(true, |addr| !removed.contains(&addr));
+        if kept {
+            out.push(*byte);
+        }
+    }
+    for (addr, inst) in code(rom, a) {
+        if removed.contains(&addr) {
+            continue;
+        }
+        let target = match inst & 0xf000 {
+            0x1000 => jump_target(rom, inst & 0xfff),
+            0x2000 | 0xa000 => inst & 0xfff,
+            _ => continue,
+        };
+        let at = (moved(addr) - OPTIMISE_ORIGIN) as usize;
+        let word = (inst & 0xf000) | moved(target);
+        out[at..at + 2].copy_from_slice(&word.to_be_bytes());
+    }
+    out
+}
+
+/// run original and optimised side by side for frames, playing back
+/// replay's keys if there is one, and check they're in the same state
+/// every frame: the same screen, registers, I, timers, stack and memory,
+/// allowing for the addresses that have moved
+pub fn verify(
+    original: &[u8],
+    optimised: &Optimised,
+    replay: Option<&Replay>,
+    frames: u64,
+) -> Result<(), Chip8Error> {
+    let removed = removed(&optimised.changes);
+    let moved = mover(original.len(), &removed);
+    // the jumps, calls and loads relocate rewrote, which won't match
+    let analysis = analyse::analyse(original);
+    let rewritten: BTreeSet<u16> = code(original, &analysis)
+        .filter(|(_, inst)| matches!(inst & 0xf000, 0x1000 | 0x2000 | 0xa000))
+        .flat_map(|(addr, _)| [addr, addr.saturating_add(1)])
+        .collect();
+
+    let keys = replay.map(|r| r.keys()).unwrap_or_default();
+    let mut displays = [DummyDisplay, DummyDisplay];
+    let mut inputs = [PlaybackInput::new(keys.clone()), PlaybackInput::new(keys)];
+    let mut sounds = [Mute::new(), Mute::new()];
+    let [da, db] = &mut displays;
+    let [ia, ib] = &mut inputs;
+    let [sa, sb] = &mut sounds;
+    let mut a = Chip8Interpreter::new(da, ia, sa)?;
+    let mut b = Chip8Interpreter::new(db, ib, sb)?;
+    for (machine, rom) in [(&mut a, original), (&mut b, &optimised.rom[..])] {
+        machine.set_seed(replay.map_or(0, |r| r.seed));
+        machine.load_program(&mut &rom[..])?;
+    }
+    for frame in 0..frames {
+        let differs = |what: String| {
+            Err(Chip8Error::TestFailure(format!(
+                "the optimised ROM {} at frame {}",
+                what, frame
+            )))
+        };
+        match (a.run_frames(1), b.run_frames(1)) {
+            (Ok(()), Ok(())) => {}
+            // both stopping the same way is as good as both carrying on
+            (Err(e), Err(f)) if e.to_string() == f.to_string() => return Ok(()),
+            (Err(_), Err(_)) => return differs("stopped differently".to_string()),
+            (Err(e), Ok(())) => {
+                return differs(format!("carried on where the original stopped ({})", e))
+            }
+            (Ok(()), Err(e)) => return differs(format!("stopped ({})", e)),
+        }
+        if let Some(r) = (0..16).find(|r| a.v(*r) != b.v(*r)) {
+            return differs(format!("has a different V{:X}", r));
+        }
+        if moved(a.i()) != b.i() {
+            return differs("has a different I".to_string());
+        }
+        if (a.delay_timer(), a.sound_timer()) != (b.delay_timer(), b.sound_timer()) {
+            return differs("has different timers".to_string());
+        }
+        if a.stack()?.into_iter().map(&moved).ne(b.stack()?) {
+            return differs("has a different stack".to_string());
+        }
+        if ocr::framebuffer(a.memory())? != ocr::framebuffer(b.memory())? {
+            return differs("drew something different".to_string());
+        }
+        let (ma, mb) = (a.memory(), b.memory());
+        let stack = ma.stack_addr.saturating_sub(OPTIMISE_STACK_BYTES - 2)..=ma.stack_addr + 1;
+        let (bytes, theirs) = (
+            ma.get_ro_slice(0, ma.size())?,
+            mb.get_ro_slice(0, mb.size())?,
+        );
+        let different = (0..bytes.len())
+            .map(|n| n as u16)
+            .filter(|addr| {
+                !stack.contains(addr) && !removed.contains(addr) && !rewritten.contains(addr)
+            })
+            .find(|addr| theirs.get(moved(*addr) as usize) != Some(&bytes[*addr as usize]));
+        if let Some(addr) = different {
+            return differs(format!(
+                "has something different in memory at {:#05x}",
+                addr
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(rom: &[u8]) -> Vec<u16> {
+        rom.chunks(2)
+            .map(|w| u16::from_be_bytes([w[0], w[1]]))
+            .collect()
+    }
+
+    fn rom(words: &[u16]) -> Vec<u8> {
+        words.iter().flat_map(|w| w.to_be_bytes()).collect()
+    }
+
+    #[test]
+    fn test_optimise() -> Result<(), Chip8Error> {
+        #[rustfmt::skip]
+        let original = rom(&[
+            0xa214, // 200: I = sprite
+            0x6005, // 202: V0 = 5
+            0xa214, // 204: I = sprite, again
+            0x6005, // 206: V0 = 5, again
+            0x7100, // 208: V1 += 0
+            0x8220, // 20a: V2 = V2
+            0x1210, // 20c: jump to the next-but-one...
+            0x0000, // 20e: (never run)
+            0xd015, // 210: draw
+            0x1212, // 212: stop here
+            0xf090, // 214: the sprite
+        ]);
+        let optimised = optimise(&original)?;
+        let removed: Vec<Peephole> = optimised.changes.iter().map(|c| c.peephole).collect();
+        assert_eq!(
+            removed,
+            [
+                Peephole::ReloadI,
+                Peephole::ReloadRegister,
+                Peephole::AddZero,
+                Peephole::CopyToSelf
+            ]
+        );
+        assert_eq!(
+            words(&optimised.rom),
+            [0xa20c, 0x6005, 0x1208, 0x0000, 0xd015, 0x120a, 0xf090]
+        );
+        verify(&original, &optimised, None, 10)
+    }
+
+    #[test]
+    fn test_jumps() -> Result<(), Chip8Error> {
+        // jump to the next instruction; jump to a jump, which jumps to the
+        // next instruction
+        let original = rom(&[0x1202, 0x1206, 0x0000, 0x1208, 0x1208]);
+        let optimised = optimise(&original)?;
+        assert_eq!(
+            optimised
+                .changes
+                .iter()
+                .map(|c| c.to_string())
+                .collect::<Vec<_>>(),
+            [
+                "0200: 1202 removed (jumps to the next instruction)",
+                "0202: 1206 shortened (jumps to a jump)",
+                "0206: 1208 removed (jumps to the next instruction)"
+            ]
+        );
+        assert_eq!(words(&optimised.rom), [0x1204, 0x0000, 0x1204]);
+        verify(&original, &optimised, None, 10)
+    }
+
+    #[test]
+    fn test_skips_are_left_alone() -> Result<(), Chip8Error> {
+        // the load after the skip might not run, so it can't go, and what it
+        // loads can't be relied on after either
+        let original = rom(&[0x6005, 0x3101, 0x6005, 0x6005, 0x6006, 0x1208]);
+        let optimised = optimise(&original)?;
+        assert_eq!(
+            optimised.changes.iter().map(|c| c.addr).collect::<Vec<_>>(),
+            [0x206]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_end_of_memory() -> Result<(), Chip8Error> {
+        // a ROM that fills memory, with a skip at the very end
+        let mut original = rom(&[0x7001]).repeat((0x10000 - 0x200) / 2);
+        original.splice(original.len() - 2.., [0x30, 0x01]);
+        original.splice(..4, [0x60, 0x01, 0x60, 0x01]);
+        let optimised = optimise(&original)?;
+        assert_eq!(optimised.changes.len(), 1);
+        assert_eq!(optimised.rom.len(), original.len() - 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_refusals() {
+        assert!(optimise(&rom(&[0xb200])).is_err());
+        assert!(optimise(&rom(&[0xa200, 0xf055, 0x1204])).is_err());
+        assert!(optimise(&rom(&[0x0300, 0x1202])).is_err());
+    }
+
+    #[test]
+    fn test_verify() -> Result<(), Chip8Error> {
+        let differs = |original: &[u16], different: &[u16]| {
+            let different = Optimised {
+                rom: rom(different),
+                changes: Vec::new(),
+            };
+            verify(&rom(original), &different, None, 10)
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(
+            differs(&[0x6005, 0x1202], &[0x6006, 0x1202]),
+            "test failed: the optimised ROM has a different V0 at frame 0"
+        );
+        // not just the registers and the screen
+        assert_eq!(
+            differs(&[0xa300, 0x1202], &[0xa301, 0x1202]),
+            "test failed: the optimised ROM has a different I at frame 0"
+        );
+        assert_eq!(
+            differs(&[0x6005, 0xf015, 0x1204], &[0x6005, 0xf018, 0x1204]),
+            "test failed: the optimised ROM has different timers at frame 0"
+        );
+        assert_eq!(
+            differs(&[0x1200, 0x0012], &[0x1200, 0x0013]),
+            "test failed: the optimised ROM has something different in memory at 0x203 at frame 0"
+        );
+        // which, taken out, leaves everything after where it was
+        let original = rom(&[
+            0x6005, 0x6005, 0xa20c, 0xf055, 0x2210, 0x120a, 0x0001, 0x00ee,
+        ]);
+        let optimised = optimise(&original)?;
+        assert_eq!(optimised.changes.len(), 1);
+        verify(&original, &optimised, None, 10)
+    }
+}
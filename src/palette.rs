@@ -0,0 +1,311 @@
+//! # the command palette
+//!
+//! ctrl+p over the game lists everything the emulator can be asked to do
+//! -- each hotkey's action, each savestate slot on its own, each speed,
+//! turning each quirk on or off, each theme, and undoing or redoing any of
+//! those (see the settings module) -- to find by typing any of its name,
+//! e.g. "sav3" or "quirk shift", and do with enter. so nobody needs to
+//! remember a key they use once a month, or what a quirk's called.
+//!
+//! the hotkeys' actions come from the same registry the keys do, so
+//! they're listed with whatever keys they're on now, and an action added
+//! there turns up here too
+use crate::display::Theme;
+use crate::hotkey::{Action, Hotkeys};
+use crate::input::Typed;
+use crate::interpreter::CHIP8_SPEEDS;
+use crate::lang;
+use crate::quirks::Quirks;
+use crate::slots::SLOT_COUNT;
+use std::cmp::Reverse;
+
+/// how many matches are listed at once
+pub const PALETTE_LINES: usize = 10;
+
+/// how wide the list is, so it doesn't jump about while typing
+const PALETTE_WIDTH: usize = 56;
+
+/// something the palette can do
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    /// whatever the hotkey does
+    Hotkey(Action),
+    /// run at this speed
+    Speed(f64),
+    /// turn this quirk on or off
+    Quirk(&'static str),
+    /// colour things in with this theme
+    Theme(&'static str),
+    /// colour the picture by where it's being drawn, or stop
+    HeatMap,
+    /// undo the last change to the settings
+    Undo,
+    /// and redo the last one undone
+    Redo,
+}
+
+/// a command, as the palette lists it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub command: Command,
+    pub title: String,
+    /// its hotkeys, if it has any
+    pub keys: String,
+}
+
+/// everything there is to do, with hotkeys' keys as they are in hotkeys
+pub fn commands(hotkeys: &Hotkeys) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    for action in Action::ALL {
+        let slot = |n| format!("{} {}", action.description(), n);
+        let actions: Vec<(Action, String)> = match action {
+            Action::SaveSlot(_) => (0..SLOT_COUNT)
+                .map(|n| (Action::SaveSlot(n), slot(n)))
+                .collect(),
+            Action::LoadSlot(_) => (0..SLOT_COUNT)
+                .map(|n| (Action::LoadSlot(n), slot(n)))
+                .collect(),
+            // it's open already
+            Action::Palette => Vec::new(),
+            a => vec![(a, a.description())],
+        };
+        for (action, title) in actions {
+            let keys: Vec<String> = hotkeys.keys(action).iter().map(|k| k.to_string()).collect();
+            entries.push(Entry {
+                command: Command::Hotkey(action),
+                title,
+                keys: keys.join(" "),
+            });
+        }
+    }
+    entries.extend(CHIP8_SPEEDS.iter().map(|s| Entry {
+        command: Command::Speed(*s),
+        title: lang::format("palette.speed", &[s]),
+        keys: String::new(),
+    }));
+    entries.extend(Quirks::FLAGS.iter().map(|q| Entry {
+        command: Command::Quirk(q),
+        title: lang::format("palette.quirk", &[q]),
+        keys: String::new(),
+    }));
+    entries.extend(Theme::PRESETS.iter().map(|(t, _)| Entry {
+        command: Command::Theme(t),
+        title: lang::format("palette.theme", &[t]),
+        keys: String::new(),
+    }));
+    for (command, title) in [
+        (Command::HeatMap, "palette.heat-map"),
+        (Command::Undo, "palette.undo"),
+        (Command::Redo, "palette.redo"),
+    ] {
+        entries.push(Entry {
+            command,
+            title: lang::text(title).to_string(),
+            keys: String::new(),
+        });
+    }
+    entries
+}
+
+/// how well query matches title, if it does at all: its characters have
+/// to come in the same order, and the more of them that start a word or
+/// follow on from the last, the better. spaces in query don't count
+pub fn score(query: &str, title: &str) -> Option<u32> {
+    let title: Vec<char> = title.to_lowercase().chars().collect();
+    let mut score = 0;
+    // where the last character matched, and where to look from next
+    let mut last: Option<usize> = None;
+    let mut from = 0;
+    for q in query.to_lowercase().chars().filter(|c| !c.is_whitespace()) {
+        let found = from + title[from..].iter().position(|c| *c == q)?;
+        score += 1;
+        if found == 0 || !title[found - 1].is_alphanumeric() {
+            score += 2;
+        }
+        if last.is_some_and(|l| l + 1 == found) {
+            score += 2;
+        }
+        last = Some(found);
+        from = found + 1;
+    }
+    Some(score)
+}
+
+/// what the palette's doing, after a key
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    /// still looking
+    Open,
+    Chosen(Command),
+    /// gone back to the game without doing anything
+    Closed,
+}
+
+/// the commands, narrowed down by what's been typed
+pub struct Palette {
+    entries: Vec<Entry>,
+    query: String,
+    // which of the matches enter would choose
+    selected: usize,
+}
+
+impl Palette {
+    pub fn new(entries: Vec<Entry>) -> Self {
+        Palette {
+            entries,
+            query: String::new(),
+            selected: 0,
+        }
+    }
+
+    /// the commands that match what's been typed, best first, and in the
+    /// order they were listed among equals
+    pub fn matches(&self) -> Vec<&Entry> {
+        let mut scored: Vec<(u32, &Entry)> = self
+            .entries
+            .iter()
+            .filter_map(|e| score(&self.query, &e.title).map(|s| (s, e)))
+            .collect();
+        scored.sort_by_key(|(s, _)| Reverse(*s));
+        scored.into_iter().map(|(_, e)| e).collect()
+    }
+
+    pub fn key(&mut self, typed: Typed) -> Outcome {
+        match typed {
+            Typed::Char(c) => {
+                self.query.push(c);
+                self.selected = 0;
+            }
+            Typed::Backspace => {
+                self.query.pop();
+                self.selected = 0;
+            }
+            Typed::Up => self.selected = self.selected.saturating_sub(1),
+            Typed::Down => {
+                let last = self.matches().len().saturating_sub(1);
+                self.selected = (self.selected + 1).min(last);
+            }
+            Typed::Enter => {
+                if let Some(e) = self.matches().get(self.selected) {
+                    return Outcome::Chosen(e.command);
+                }
+            }
+            Typed::Escape => return Outcome::Closed,
+        }
+        Outcome::Open
+    }
+
+    /// for the display to show: what's been typed, and a screenful of
+    /// matches, keeping the selected one on it
+    pub fn lines(&self) -> Vec<String> {
+        let matches = self.matches();
+        let first = self.selected.saturating_sub(PALETTE_LINES - 1);
+        let mut lines = vec![format!("> {}", self.query), String::new()];
+        for (n, e) in matches.iter().enumerate().skip(first).take(PALETTE_LINES) {
+            let marker = if n == self.selected { '>' } else { ' ' };
+            let width = PALETTE_WIDTH.saturating_sub(e.keys.chars().count() + 2);
+            lines.push(format!("{} {:width$}{}", marker, e.title, e.keys));
+        }
+        if matches.is_empty() {
+            lines.push(lang::text("palette.nothing").to_string());
+        }
+        // the same height, however many match
+        lines.resize(PALETTE_LINES + 2, String::new());
+        lines.push(lang::text("palette.help").to_string());
+        lines
+            .into_iter()
+            .map(|l| format!("{:PALETTE_WIDTH$}", l))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hotkey::HostKey;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_commands() -> Result<(), crate::error::Chip8Error> {
+        let config = BTreeMap::from([("hud".to_string(), "h f4".to_string())]);
+        let entries = commands(&Hotkeys::from_config(&config)?);
+        let find = |c: Command| entries.iter().find(|e| e.command == c);
+        assert_eq!(find(Command::Hotkey(Action::Menu)).unwrap().keys, "esc");
+        assert_eq!(find(Command::Hotkey(Action::Hud)).unwrap().keys, "h f4");
+        assert_eq!(
+            find(Command::Hotkey(Action::SaveSlot(3))).unwrap().title,
+            "save to a savestate slot 3"
+        );
+        assert_eq!(find(Command::Hotkey(Action::LoadSlot(0))).unwrap().keys, "");
+        assert!(find(Command::Hotkey(Action::Palette)).is_none());
+        assert_eq!(find(Command::Speed(2.0)).unwrap().title, "run at 2x speed");
+        assert!(find(Command::Quirk("shift-vx")).is_some());
+        assert_eq!(
+            find(Command::Theme("high-contrast")).unwrap().title,
+            "use the high-contrast theme"
+        );
+        assert!(find(Command::Undo).is_some());
+        assert_eq!(
+            find(Command::HeatMap).unwrap().title,
+            "turn the heat map on or off"
+        );
+        assert_eq!(
+            Hotkeys::default().action(HostKey::Ctrl('p')),
+            Some(Action::Palette)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_score() {
+        assert_eq!(score("", "anything"), Some(0));
+        assert_eq!(score("xyz", "run faster"), None);
+        // in order, or not at all
+        assert_eq!(score("fr", "run faster"), Some(4));
+        assert_eq!(score("rf", "run faster"), Some(6));
+        assert!(score("ss", "save state") > score("ss", "toss"));
+        assert!(score("mute", "mute or unmute") > score("mte", "mute or unmute"));
+        assert_eq!(score("S V", "save"), score("sv", "save"));
+    }
+
+    #[test]
+    fn test_palette() {
+        let mut palette = Palette::new(commands(&Hotkeys::default()));
+        assert_eq!(palette.matches().len(), palette.entries.len());
+        for c in "sav3".chars() {
+            assert_eq!(palette.key(Typed::Char(c)), Outcome::Open);
+        }
+        assert_eq!(
+            palette.key(Typed::Enter),
+            Outcome::Chosen(Command::Hotkey(Action::SaveSlot(3)))
+        );
+        let lines = palette.lines();
+        assert_eq!(lines.len(), PALETTE_LINES + 3);
+        assert!(lines.iter().all(|l| l.chars().count() == PALETTE_WIDTH));
+        assert!(lines[2].starts_with("> save to a savestate slot 3"));
+        assert!(lines[2].trim_end().ends_with(" #"));
+
+        // only the two slot 3s match, so there's no going further down
+        palette.key(Typed::Down);
+        palette.key(Typed::Down);
+        assert!(palette.lines()[3].starts_with("> load from a savestate slot 3"));
+        palette.key(Typed::Up);
+        assert!(palette.lines()[2].starts_with('>'));
+        for _ in 0..4 {
+            palette.key(Typed::Backspace);
+        }
+        for c in "quirk shift".chars() {
+            palette.key(Typed::Char(c));
+        }
+        assert_eq!(
+            palette.key(Typed::Enter),
+            Outcome::Chosen(Command::Quirk("shift-vx"))
+        );
+
+        // nothing to choose
+        palette.key(Typed::Char('#'));
+        assert!(palette.matches().is_empty());
+        assert_eq!(palette.key(Typed::Enter), Outcome::Open);
+        assert_eq!(palette.key(Typed::Escape), Outcome::Closed);
+    }
+}
@@ -0,0 +1,315 @@
+//! # patches
+//!
+//! ROM hacks mostly get passed round as patches rather than ROMs, so
+//! --patch puts one on the ROM as it's loaded, before anything else sees
+//! it. there's IPS, the old format everything can make: bytes to put at
+//! each offset, and no way of telling it's being put on the right ROM. and
+//! there's BPS, which copies from the original as well and checks the
+//! original, the result and itself with CRC32s, so a patch for another
+//! version of a game gets caught rather than making garbage
+use crate::error::Chip8Error;
+use crate::persist::crc32;
+
+const IPS_MAGIC: &[u8] = b"PATCH";
+/// "EOF", where an offset would be
+const IPS_END: usize = 0x45_4f46;
+const BPS_MAGIC: &[u8] = b"BPS1";
+
+/// the biggest ROM a patch can make: all the memory there is. anything
+/// bigger's a damaged patch rather than a plan
+const PATCH_MAX_SIZE: usize = 0x10000;
+
+fn bad(message: String) -> Chip8Error {
+    Chip8Error::BadPatch(message)
+}
+
+/// rom, with the IPS or BPS patch put on it
+pub fn apply(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, Chip8Error> {
+    if let Some(records) = patch.strip_prefix(IPS_MAGIC) {
+        apply_ips(rom, records)
+    } else if patch.starts_with(BPS_MAGIC) {
+        apply_bps(rom, patch)
+    } else {
+        Err(bad("it's not an IPS or a BPS patch".to_string()))
+    }
+}
+
+fn apply_ips(rom: &[u8], mut records: &[u8]) -> Result<Vec<u8>, Chip8Error> {
+    let mut take = |n: usize| -> Result<usize, Chip8Error> {
+        let (number, rest) = records
+            .split_at_checked(n)
+            .ok_or_else(|| bad("the patch is cut short".to_string()))?;
+        records = rest;
+        Ok(number.iter().fold(0, |n, b| n << 8 | *b as usize))
+    };
+    let mut out = rom.to_vec();
+    loop {
+        let offset = take(3)?;
+        if offset == IPS_END {
+            break;
+        }
+        // a length of nothing means a run of one byte, and it's a run's
+        // length and the byte next instead
+        let (len, run) = match take(2)? {
+            0 => (take(2)?, Some(take(1)? as u8)),
+            len => (len, None),
+        };
+        if offset + len > PATCH_MAX_SIZE {
+            return Err(bad(format!(
+                "it writes past {:#x}, which is more than there's memory for",
+                offset + len
+            )));
+        }
+        if out.len() < offset + len {
+            out.resize(offset + len, 0);
+        }
+        for byte in &mut out[offset..offset + len] {
+            *byte = match run {
+                Some(b) => b,
+                None => take(1)? as u8,
+            };
+        }
+    }
+    // some IPS patches cut the ROM down to size after
+    if let Ok(size) = take(3) {
+        out.truncate(size);
+    }
+    Ok(out)
+}
+
+/// BPS's numbers, 7 bits a byte, with the top bit set on the last
+fn bps_number(patch: &[u8], at: &mut usize) -> Result<usize, Chip8Error> {
+    let (mut n, mut shift) = (0usize, 1usize);
+    loop {
+        let b = *patch
+            .get(*at)
+            .ok_or_else(|| bad("the patch is cut short".to_string()))?;
+        *at += 1;
+        n = n
+            .checked_add((b & 0x7f) as usize * shift)
+            .ok_or_else(|| bad("the patch is damaged".to_string()))?;
+        if b & 0x80 != 0 {
+            return Ok(n);
+        }
+        shift = shift
+            .checked_mul(0x80)
+            .ok_or_else(|| bad("the patch is damaged".to_string()))?;
+        n = n
+            .checked_add(shift)
+            .ok_or_else(|| bad("the patch is damaged".to_string()))?;
+    }
+}
+
+fn apply_bps(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, Chip8Error> {
+    // the last twelve bytes are the original's, the result's and the
+    // patch's own CRC32s
+    if patch.len() < BPS_MAGIC.len() + 12 {
+        return Err(bad("the patch is cut short".to_string()));
+    }
+    let (body, sums) = patch.split_at(patch.len() - 12);
+    let sum = |n: usize| u32::from_le_bytes([sums[n], sums[n + 1], sums[n + 2], sums[n + 3]]);
+    if crc32(&patch[..patch.len() - 4]) != sum(8) {
+        return Err(bad("the patch is damaged".to_string()));
+    }
+    if crc32(rom) != sum(0) {
+        return Err(bad(format!(
+            "it's for a different ROM (one with a CRC32 of {:08x}, and this one's {:08x})",
+            sum(0),
+            crc32(rom)
+        )));
+    }
+
+    let mut at = BPS_MAGIC.len();
+    let source_size = bps_number(body, &mut at)?;
+    let target_size = bps_number(body, &mut at)?;
+    let metadata = bps_number(body, &mut at)?;
+    // the metadata's skipped, but it has to be there
+    at = at
+        .checked_add(metadata)
+        .filter(|at| *at <= body.len())
+        .ok_or_else(|| bad("the patch is cut short".to_string()))?;
+    if source_size != rom.len() || target_size > PATCH_MAX_SIZE {
+        return Err(bad(format!(
+            "it's for a {} byte ROM, making a {} byte one, and this one's {} bytes",
+            source_size,
+            target_size,
+            rom.len()
+        )));
+    }
+    let damaged = || bad("the patch is damaged".to_string());
+    let mut out: Vec<u8> = Vec::with_capacity(target_size);
+    let (mut source_at, mut target_at) = (0usize, 0usize);
+    // copies move on from where the last one of their kind left off, by a
+    // signed offset with its sign in the bottom bit
+    let relative = |from: usize, n: usize| match n & 1 {
+        0 => from.checked_add(n >> 1),
+        _ => from.checked_sub(n >> 1),
+    };
+    while at < body.len() {
+        let action = bps_number(body, &mut at)?;
+        let len = (action >> 2) + 1;
+        if out
+            .len()
+            .checked_add(len)
+            .is_none_or(|end| end > target_size)
+        {
+            return Err(damaged());
+        }
+        // a range of len from start, if there's no overflowing to get there
+        let span = |start: usize| start.checked_add(len).map(|end| start..end);
+        match action & 3 {
+            // the original's bytes, where they are
+            0 => out.extend(rom.get(out.len()..out.len() + len).ok_or_else(damaged)?),
+            // new bytes, from the patch
+            1 => {
+                let new = span(at).and_then(|r| body.get(r)).ok_or_else(damaged)?;
+                out.extend(new);
+                at += len;
+            }
+            // the original's, from somewhere else
+            2 => {
+                source_at = relative(source_at, bps_number(body, &mut at)?).ok_or_else(damaged)?;
+                let from = span(source_at)
+                    .and_then(|r| rom.get(r))
+                    .ok_or_else(damaged)?;
+                out.extend(from);
+                source_at += len;
+            }
+            // some of the result so far, which can overlap what it's making
+            _ => {
+                target_at = relative(target_at, bps_number(body, &mut at)?).ok_or_else(damaged)?;
+                for _ in 0..len {
+                    let b = *out.get(target_at).ok_or_else(damaged)?;
+                    out.push(b);
+                    target_at += 1;
+                }
+            }
+        }
+    }
+    if crc32(&out) != sum(4) {
+        return Err(bad(format!(
+            "it didn't come out as it should (a CRC32 of {:08x}, not {:08x})",
+            crc32(&out),
+            sum(4)
+        )));
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROM: [u8; 8] = [0x60, 0x01, 0x70, 0x01, 0x12, 0x02, 0xf0, 0x90];
+
+    #[test]
+    fn test_ips() -> Result<(), Chip8Error> {
+        #[rustfmt::skip]
+        let patch = [
+            b"PATCH".as_slice(),
+            // 6005 at 0000
+            &[0x00, 0x00, 0x00, 0x00, 0x02, 0x60, 0x05],
+            // four 0xaa at 0006, off the end
+            &[0x00, 0x00, 0x06, 0x00, 0x00, 0x00, 0x04, 0xaa],
+            b"EOF",
+        ]
+        .concat();
+        assert_eq!(
+            apply(&ROM, &patch)?,
+            [0x60, 0x05, 0x70, 0x01, 0x12, 0x02, 0xaa, 0xaa, 0xaa, 0xaa]
+        );
+        // and cut down to size
+        let truncated = [patch.as_slice(), &[0x00, 0x00, 0x07]].concat();
+        assert_eq!(apply(&ROM, &truncated)?.len(), 7);
+        let e = apply(&ROM, &patch[..12]).unwrap_err();
+        assert_eq!(e.to_string(), "bad patch: the patch is cut short");
+        Ok(())
+    }
+
+    /// a BPS number
+    fn number(mut n: usize) -> Vec<u8> {
+        let mut out = Vec::new();
+        loop {
+            let x = (n & 0x7f) as u8;
+            n >>= 7;
+            if n == 0 {
+                out.push(0x80 | x);
+                return out;
+            }
+            out.push(x);
+            n -= 1;
+        }
+    }
+
+    /// a BPS patch of actions, from source to target
+    fn bps(source: &[u8], target: &[u8], actions: &[u8]) -> Vec<u8> {
+        let mut patch = BPS_MAGIC.to_vec();
+        patch.extend(number(source.len()));
+        patch.extend(number(target.len()));
+        patch.extend(number(0));
+        patch.extend(actions);
+        patch.extend(crc32(source).to_le_bytes());
+        patch.extend(crc32(target).to_le_bytes());
+        patch.extend(crc32(&patch).to_le_bytes());
+        patch
+    }
+
+    #[test]
+    fn test_bps() -> Result<(), Chip8Error> {
+        assert_eq!(number(0x80), [0x00, 0x80]);
+        let target = [0x60, 0x01, 0x70, 0x02, 0x70, 0x02, 0x70, 0x02, 0xf0, 0x90];
+        let actions = [
+            // two of the original's
+            number(1 << 2).as_slice(),
+            // two new ones
+            &number(1 << 2 | 1),
+            &[0x70, 0x02],
+            // the new ones again, twice, overlapping what it's copying
+            &number(3 << 2 | 3),
+            &number(2 << 1),
+            // and the original's last two
+            &number(1 << 2 | 2),
+            &number(6 << 1),
+        ]
+        .concat();
+        let patch = bps(&ROM, &target, &actions);
+        assert_eq!(apply(&ROM, &patch)?, target);
+
+        // the wrong ROM, a damaged patch, and one that doesn't add up
+        let e = apply(&target, &patch).unwrap_err();
+        assert!(e.to_string().contains("it's for a different ROM"));
+        let mut damaged = patch.clone();
+        damaged[8] ^= 1;
+        assert!(apply(&ROM, &damaged)
+            .unwrap_err()
+            .to_string()
+            .contains("damaged"));
+        let mut other = target;
+        other[0] = 0x61;
+        let wrong = bps(&ROM, &other, &actions);
+        assert!(apply(&ROM, &wrong)
+            .unwrap_err()
+            .to_string()
+            .contains("didn't come out"));
+        // numbers that would take it off the end of anything
+        let mut huge = bps(&ROM, &target, &actions);
+        let metadata = BPS_MAGIC.len() + 2;
+        huge.splice(metadata..metadata + 1, number(usize::MAX - 8));
+        let n = huge.len() - 4;
+        let sum = crc32(&huge[..n]).to_le_bytes();
+        huge[n..].copy_from_slice(&sum);
+        assert!(apply(&ROM, &huge).is_err());
+        assert!(apply(&ROM, &bps(&ROM, &target, &[0x7f; 12])).is_err());
+        let far = [number(1 << 2 | 2), number(usize::MAX - 1)].concat();
+        assert!(apply(&ROM, &bps(&ROM, &target, &far)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_not_a_patch() {
+        assert!(matches!(
+            apply(&ROM, b"hello"),
+            Err(Chip8Error::BadPatch(_))
+        ));
+    }
+}
@@ -0,0 +1,295 @@
+//! # where things go
+//!
+//! each platform has its own idea of where a program's files live: the XDG
+//! directories on Linux and the like, Application Support (and Caches) on
+//! macOS, and AppData on Windows. everything the emulator keeps between
+//! runs is found from here rather than wherever it happens to be run from:
+//!
+//! * config: config.toml and profiles/
+//! * data: session.toml, crash.toml, stats.toml, achievements/, savestates/,
+//!   reports/, roms/ and corpus/
+//! * cache: thumbnails/, which can always be made again
+//!
+//! the XDG variables win on any platform if they're set, for anyone who
+//! wants everything in one place (and for tests). before there was a data
+//! directory everything went next to the config, and migrate moves it
+//! across when the emulator starts
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// what's under each platform directory
+const APP_DIR: &str = "chip8";
+
+/// the three places things go
+#[derive(Debug, PartialEq)]
+struct Dirs {
+    config: PathBuf,
+    data: PathBuf,
+    cache: PathBuf,
+}
+
+/// where they are, given a way to look up environment variables
+fn dirs(var: impl Fn(&str) -> Option<OsString>) -> Dirs {
+    let home = PathBuf::from(
+        var("HOME")
+            .or_else(|| var("USERPROFILE"))
+            .unwrap_or_default(),
+    );
+    let xdg = |name: &str| var(name).filter(|v| !v.is_empty()).map(PathBuf::from);
+    let (config, data, cache) = platform_dirs(&home, &var);
+    Dirs {
+        config: xdg("XDG_CONFIG_HOME").unwrap_or(config).join(APP_DIR),
+        data: xdg("XDG_DATA_HOME").unwrap_or(data).join(APP_DIR),
+        cache: xdg("XDG_CACHE_HOME").unwrap_or(cache).join(APP_DIR),
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn platform_dirs(
+    home: &Path,
+    _var: &impl Fn(&str) -> Option<OsString>,
+) -> (PathBuf, PathBuf, PathBuf) {
+    let support = home.join("Library").join("Application Support");
+    (
+        support.clone(),
+        support,
+        home.join("Library").join("Caches"),
+    )
+}
+
+#[cfg(windows)]
+fn platform_dirs(
+    home: &Path,
+    var: &impl Fn(&str) -> Option<OsString>,
+) -> (PathBuf, PathBuf, PathBuf) {
+    let roaming = var("APPDATA").map_or(home.join("AppData").join("Roaming"), PathBuf::from);
+    let local = var("LOCALAPPDATA").map_or(home.join("AppData").join("Local"), PathBuf::from);
+    (roaming.clone(), roaming, local)
+}
+
+#[cfg(not(any(target_os = "macos", windows)))]
+fn platform_dirs(
+    home: &Path,
+    _var: &impl Fn(&str) -> Option<OsString>,
+) -> (PathBuf, PathBuf, PathBuf) {
+    (
+        home.join(".config"),
+        home.join(".local").join("share"),
+        home.join(".cache"),
+    )
+}
+
+fn here() -> Dirs {
+    dirs(|name| env::var_os(name))
+}
+
+/// where everything went before: ~/.config/chip8, or under $XDG_CONFIG_HOME
+fn old_dir() -> PathBuf {
+    match env::var_os("XDG_CONFIG_HOME") {
+        Some(p) => PathBuf::from(p),
+        None => PathBuf::from(env::var_os("HOME").unwrap_or_default()).join(".config"),
+    }
+    .join(APP_DIR)
+}
+
+/// what's moved from old to path by migrate
+fn moves() -> [(PathBuf, PathBuf); 3] {
+    let (here, old) = (here(), old_dir());
+    [
+        (here.config.join("config.toml"), old.join("config.toml")),
+        (here.data.join("session.toml"), old.join("session.toml")),
+        (here.data.join("achievements"), old.join("achievements")),
+    ]
+}
+
+/// move whatever's still where it went before there was a data directory
+/// to where it goes now, once, at startup. anything that can't be moved
+/// is left, and found, where it is
+pub fn migrate() {
+    for (path, old) in moves() {
+        settle(&path, &old);
+    }
+}
+
+/// move old to path if there's nothing at path yet
+fn settle(path: &Path, old: &Path) {
+    if path == old || path.exists() || !old.exists() {
+        return;
+    }
+    // if it fails, found still finds it at old
+    let _ = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::rename(old, path));
+}
+
+/// path, or old if migrate couldn't move it there
+fn found(path: PathBuf, old: PathBuf) -> PathBuf {
+    match !path.exists() && old.exists() {
+        true => old,
+        false => path,
+    }
+}
+
+pub fn config_file() -> PathBuf {
+    found(
+        here().config.join("config.toml"),
+        old_dir().join("config.toml"),
+    )
+}
+
+/// where shared profiles are installed (see the profile module)
+pub fn profiles_dir() -> PathBuf {
+    here().config.join("profiles")
+}
+
+/// what --save-session writes and --resume reads
+pub fn session_file() -> PathBuf {
+    found(
+        here().data.join("session.toml"),
+        old_dir().join("session.toml"),
+    )
+}
+
+/// the machine from just before the last fault, for --resume-crash
+pub fn crash_file() -> PathBuf {
+    here().data.join("crash.toml")
+}
+
+/// how much each ROM's been played, and the best scores at it
+pub fn stats_file() -> PathBuf {
+    here().data.join("stats.toml")
+}
+
+/// a file of rules for each ROM
+pub fn achievements_dir() -> PathBuf {
+    found(
+        here().data.join("achievements"),
+        old_dir().join("achievements"),
+    )
+}
+
+/// a directory of savestate slots for each ROM
+pub fn savestates_dir() -> PathBuf {
+    here().data.join("savestates")
+}
+
+/// the pause menu's bug reports
+pub fn reports_dir() -> PathBuf {
+    here().data.join("reports")
+}
+
+pub fn thumbnails_dir() -> PathBuf {
+    here().cache.join("thumbnails")
+}
+
+/// what's made the emulator panic, for the self-test to play back (see
+/// the corpus module)
+pub fn corpus_dir() -> PathBuf {
+    here().data.join("corpus")
+}
+
+/// ROMs installed for everyone to find, e.g. the demo
+pub fn roms_dir() -> PathBuf {
+    here().data.join("roms")
+}
+
+/// name (a ROM's, say, which could have come from a session file or a
+/// bundle) made safe to be one file name in one of the directories above:
+/// separators (any system's) and control characters are '_', so there's no
+/// climbing out, and "", "." and ".." aren't names at all. anything else
+/// is left as it was, so the names files already have still match
+pub fn file_name(name: &str) -> String {
+    let safe: String = name
+        .chars()
+        .map(|c| match c.is_control() || "/\\:".contains(c) {
+            true => '_',
+            false => c,
+        })
+        .collect();
+    match safe.as_str() {
+        "" | "." | ".." => safe.replace('.', "_") + "_",
+        _ => safe,
+    }
+}
+
+/// name in a roms/ directory: the one we've been run next to, as in a
+/// checkout of the source, or failing that the installed ones
+pub fn find_rom(name: &str) -> Option<PathBuf> {
+    [Path::new("roms").join(name), roms_dir().join(name)]
+        .into_iter()
+        .find(|p| p.exists())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_name() {
+        assert_eq!(file_name("brix"), "brix");
+        assert_eq!(file_name("space invaders.v2"), "space invaders.v2");
+        assert_eq!(file_name("../../.bashrc"), ".._.._.bashrc");
+        assert_eq!(file_name("a/b\\c:\0"), "a_b_c__");
+        assert_eq!(file_name(".."), "___");
+        assert_eq!(file_name("."), "__");
+        assert_eq!(file_name(""), "_");
+        assert_eq!(
+            Path::new("d").join(file_name("../x")).parent(),
+            Some(Path::new("d"))
+        );
+    }
+
+    #[test]
+    fn test_xdg_wins() {
+        let d = dirs(|name| match name {
+            "HOME" => Some("/home/vip".into()),
+            "XDG_CONFIG_HOME" => Some("/xdg/config".into()),
+            "XDG_DATA_HOME" => Some("/xdg/data".into()),
+            // set but empty counts as not set
+            "XDG_CACHE_HOME" => Some("".into()),
+            _ => None,
+        });
+        assert_eq!(d.config, Path::new("/xdg/config/chip8"));
+        assert_eq!(d.data, Path::new("/xdg/data/chip8"));
+        assert!(d.cache.starts_with("/home/vip"));
+    }
+
+    #[cfg(not(any(target_os = "macos", windows)))]
+    #[test]
+    fn test_linux_defaults() {
+        let d = dirs(|name| (name == "HOME").then(|| "/home/vip".into()));
+        assert_eq!(
+            d,
+            Dirs {
+                config: "/home/vip/.config/chip8".into(),
+                data: "/home/vip/.local/share/chip8".into(),
+                cache: "/home/vip/.cache/chip8".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_settle() -> std::io::Result<()> {
+        let dir = env::temp_dir().join(format!("chip8-paths-{}", std::process::id()));
+        let (old, new) = (dir.join("old.toml"), dir.join("new").join("new.toml"));
+        fs::create_dir_all(&dir)?;
+        fs::write(&old, "v = 1")?;
+        // looking doesn't move anything
+        assert_eq!(found(new.clone(), old.clone()), old);
+        assert!(old.exists());
+        settle(&new, &old);
+        assert_eq!(found(new.clone(), old.clone()), new);
+        assert_eq!(fs::read_to_string(&new)?, "v = 1");
+        assert!(!old.exists());
+        // once it's moved, anything left behind is ignored
+        fs::write(&old, "v = 2")?;
+        settle(&new, &old);
+        assert_eq!(found(new.clone(), old.clone()), new);
+        assert_eq!(fs::read_to_string(&new)?, "v = 1");
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,248 @@
+//! # saving safely
+//!
+//! a crash (or a full disk) part way through saving shouldn't cost anyone
+//! their config or their session. everything's written to a temporary file
+//! next to the real one, flushed to disk, and renamed over it, which
+//! happens all at once or not at all; the copy it replaces is kept as
+//! .bak. text files also start with a checksum, so one that's been damaged
+//! anyway is noticed when it's loaded, and the backup used instead, with a
+//! warning. they're read and written through a Storage (see the storage
+//! module), which is files unless someone says otherwise
+use crate::error::Chip8Error;
+use crate::lang;
+use crate::storage::Storage;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+const CHECKSUM_PREFIX: &str = "# checksum ";
+const CHECKSUM_NOTE: &str = " (delete this line if editing by hand)";
+
+/// the CRC zip (and PNG, and BPS) wants, and the checksums here, bit by
+/// bit: there's not enough to checksum to be worth a table
+pub fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0, |crc, b| {
+        (0..8).fold(crc ^ *b as u32, |crc, _| match crc & 1 {
+            1 => (crc >> 1) ^ 0xedb8_8320,
+            _ => crc >> 1,
+        })
+    })
+}
+
+/// path with another extension on the end, e.g. config.toml.bak
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().map_or(OsString::new(), |n| n.to_owned());
+    name.push(".");
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// where the copy before the last save is kept
+pub fn backup_path(path: &Path) -> PathBuf {
+    with_suffix(path, "bak")
+}
+
+/// replace path with data all at once, creating its directory if needed
+pub fn write_atomic(path: &Path, data: &[u8]) -> Result<(), Chip8Error> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let temp = with_suffix(path, "tmp");
+    let written = (|| {
+        {
+            let mut f = File::create(&temp)?;
+            f.write_all(data)?;
+            f.sync_all()?;
+        }
+        // a crash between these two leaves just the backup, which load_text
+        // knows to look for
+        if path.exists() {
+            fs::rename(path, backup_path(path))?;
+        }
+        fs::rename(&temp, path)
+    })();
+    if written.is_err() {
+        // not to be found next to it forever after. the save's failed
+        // anyway, so whether this does too is neither here nor there
+        let _ = fs::remove_file(&temp);
+    }
+    Ok(written?)
+}
+
+/// what's kept at path, as text, if there's anything
+pub fn read_text(storage: &dyn Storage, path: &Path) -> Result<Option<String>, Chip8Error> {
+    match storage.read(path)? {
+        Some(data) => String::from_utf8(data)
+            .map(Some)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e).into()),
+        None => Ok(None),
+    }
+}
+
+/// keep text at path with a checksum line at the top, for load_text
+pub fn save_text(storage: &dyn Storage, path: &Path, text: &str) -> Result<(), Chip8Error> {
+    let checked = format!(
+        "{}{:08x}{}\n{}",
+        CHECKSUM_PREFIX,
+        crc32(text.as_bytes()),
+        CHECKSUM_NOTE,
+        text
+    );
+    storage.write(path, checked.as_bytes())
+}
+
+/// what the checksum line says about the rest
+#[derive(Debug, PartialEq)]
+enum Checked<'t> {
+    Good(&'t str),
+    Bad(&'t str),
+    /// written by hand, or before there were checksums
+    Unchecked(&'t str),
+}
+
+fn check(text: &str) -> Checked<'_> {
+    let checksum = text
+        .strip_prefix(CHECKSUM_PREFIX)
+        .and_then(|t| t.split_once('\n'))
+        .and_then(|(line, rest)| Some((u32::from_str_radix(line.get(..8)?, 16).ok()?, rest)));
+    match checksum {
+        Some((sum, rest)) if sum == crc32(rest.as_bytes()) => Checked::Good(rest),
+        Some((_, rest)) => Checked::Bad(rest),
+        None => Checked::Unchecked(text),
+    }
+}
+
+/// read something saved with save_text, or None if it's never been saved.
+/// if it's damaged, the backup's used instead, and if it doesn't match
+/// its checksum but still makes sense it's used anyway (it was probably
+/// edited by hand); either way, with a warning
+pub fn load_text<T>(
+    storage: &dyn Storage,
+    path: &Path,
+    parse: impl Fn(&str) -> Result<T, Chip8Error>,
+) -> Result<Option<T>, Chip8Error> {
+    let backup = backup_path(path);
+    let text = match read_text(storage, path)? {
+        Some(t) => t,
+        None => {
+            return match load_backup(storage, &backup, &parse) {
+                Some(t) => {
+                    eprintln!(
+                        "{}",
+                        lang::format(
+                            "warning.backup-missing",
+                            &[&path.display(), &backup.display()]
+                        )
+                    );
+                    Ok(Some(t))
+                }
+                None => Ok(None),
+            };
+        }
+    };
+    match check(&text) {
+        Checked::Good(text) | Checked::Unchecked(text) => parse(text).map(Some),
+        Checked::Bad(text) => match parse(text) {
+            Ok(t) => {
+                eprintln!("{}", lang::format("warning.checksum", &[&path.display()]));
+                Ok(Some(t))
+            }
+            Err(e) => match load_backup(storage, &backup, &parse) {
+                Some(t) => {
+                    eprintln!(
+                        "{}",
+                        lang::format(
+                            "warning.backup-damaged",
+                            &[&path.display(), &e, &backup.display()]
+                        )
+                    );
+                    Ok(Some(t))
+                }
+                None => Err(e),
+            },
+        },
+    }
+}
+
+/// the backup, if there is one and it's intact
+fn load_backup<T>(
+    storage: &dyn Storage,
+    backup: &Path,
+    parse: impl Fn(&str) -> Result<T, Chip8Error>,
+) -> Option<T> {
+    let text = read_text(storage, backup).ok()??;
+    match check(&text) {
+        Checked::Good(text) | Checked::Unchecked(text) => parse(text).ok(),
+        Checked::Bad(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::FileStorage;
+    use std::env;
+
+    fn parse_number(s: &str) -> Result<u32, Chip8Error> {
+        s.trim()
+            .parse()
+            .map_err(|_| Chip8Error::ConfigError(format!("not a number: {}", s)))
+    }
+
+    #[test]
+    fn test_check() {
+        let sum = crc32(b"1\n");
+        let good = format!("# checksum {:08x} (whatever)\n1\n", sum);
+        assert_eq!(check(&good), Checked::Good("1\n"));
+        let bad = format!("# checksum {:08x}\n2\n", sum);
+        assert_eq!(check(&bad), Checked::Bad("2\n"));
+        assert_eq!(
+            check("# just a comment\n1\n"),
+            Checked::Unchecked("# just a comment\n1\n")
+        );
+    }
+
+    #[test]
+    fn test_save_and_recover() -> Result<(), Chip8Error> {
+        let dir = env::temp_dir().join(format!("chip8-persist-{}", std::process::id()));
+        let path = dir.join("number.txt");
+        // files, for the renames and the backup to be real
+        let files = FileStorage;
+        assert_eq!(load_text(&files, &path, parse_number)?, None);
+        save_text(&files, &path, "1\n")?;
+        save_text(&files, &path, "2\n")?;
+        assert_eq!(load_text(&files, &path, parse_number)?, Some(2));
+        assert_eq!(
+            load_text(&files, &backup_path(&path), parse_number)?,
+            Some(1)
+        );
+        assert!(!with_suffix(&path, "tmp").exists());
+
+        // edited by hand, with or without taking the checksum out
+        fs::write(&path, "3\n")?;
+        assert_eq!(load_text(&files, &path, parse_number)?, Some(3));
+        let saved = fs::read_to_string(backup_path(&path))?;
+        fs::write(&path, saved.replace("1\n", "4\n"))?;
+        assert_eq!(load_text(&files, &path, parse_number)?, Some(4));
+
+        // damaged, or gone between the renames: back to the backup
+        fs::write(&path, saved.replace("1\n", "1\0\0"))?;
+        assert_eq!(load_text(&files, &path, parse_number)?, Some(1));
+        fs::remove_file(&path)?;
+        assert_eq!(load_text(&files, &path, parse_number)?, Some(1));
+
+        // but a hand-written mistake is still a mistake
+        fs::write(&path, "one\n")?;
+        assert!(load_text(&files, &path, parse_number).is_err());
+
+        // a save that fails part way leaves nothing behind
+        fs::remove_file(backup_path(&path))?;
+        fs::create_dir_all(backup_path(&path).join("in the way"))?;
+        assert!(save_text(&files, &path, "5\n").is_err());
+        assert!(!with_suffix(&path, "tmp").exists());
+        assert!(load_text(&files, &path, parse_number).is_err());
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
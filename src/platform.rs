@@ -0,0 +1,393 @@
+//! # platforms
+//!
+//! a display, an input and a sound device that belong together: the
+//! terminal's picture goes with its keyboard, and a headless run wants
+//! neither. a Platform builds all three at once, so a frontend is one type
+//! to write, and picking one is a Frontend away
+//!
+//! there's no platform for the browser: a page mustn't block, and a
+//! Platform's devices are driven by main_loop, which sleeps between
+//! instructions. a page drives the stable module's Emulator a frame at a
+//! time from requestAnimationFrame instead, drawing each Frame it gets
+//! back, the way the SDL example does from its own loop
+use crate::debug::Debugger;
+use crate::display::{
+    Cells, Display, DummyDisplay, MonoTermDisplay, PlaneColours, Scale, TextDisplay, Theme,
+};
+use crate::error::Chip8Error;
+#[cfg(feature = "gui")]
+use crate::gui::GuiPlatform;
+use crate::hotkey::Hotkeys;
+use crate::input::{DummyInput, Input, Keymap, StdinInput};
+use crate::keypad::{Assist, KeyRepeat};
+use crate::render::ThreadedDisplay;
+#[cfg(feature = "sdl")]
+use crate::sdl::SdlPlatform;
+use crate::sound::{self, Mute, Sound};
+use crate::termcaps::Colours;
+use crate::window::{Geometry, Scaling, Whereabouts};
+use std::io::{self, Stdout};
+use std::time::Duration;
+
+pub trait Platform {
+    /// the display, input and sound, together: the interpreter borrows all
+    /// three at once
+    fn devices(&mut self) -> (&mut dyn Display, &mut dyn Input, &mut dyn Sound);
+
+    /// the emulator's end of the platform's own debugger, if it has one.
+    /// there's only the one, so it's handed over the first time
+    fn debugger(&mut self) -> Option<Debugger> {
+        None
+    }
+
+    /// where the platform's window is, if it has one, to open it where it
+    /// was left next time
+    fn whereabouts(&self) -> Option<Whereabouts> {
+        None
+    }
+}
+
+/// how the terminal platforms should look, and which keys drive them
+#[derive(Default)]
+pub struct TerminalOptions {
+    /// the controls, say, under the picture
+    pub status: Option<String>,
+    /// draw on a thread of its own, with room for this many frames
+    pub render_queue: Option<usize>,
+    pub theme: Theme,
+    /// what XO-CHIP's four colours come out as, if not Octo's
+    pub plane_colours: Option<PlaneColours>,
+    pub cells: Option<Cells>,
+    pub scale: Option<Scale>,
+    /// as many as the terminal has, for the theme's to come out as
+    pub colours: Colours,
+    pub hotkeys: Hotkeys,
+    /// a keypad on the screen, for touchscreens
+    pub touch: bool,
+    /// how the keyboard repeats keys
+    pub key_repeat: KeyRepeat,
+    /// sticky keys, or slow motion
+    pub assist: Assist,
+    /// how much the sound card's player buffers, if not the default
+    pub audio_buffer: Option<Duration>,
+    /// how a window fits the picture in, for frontends with one
+    pub scaling: Scaling,
+    /// and where it opens, if it's been somewhere before
+    pub window: Option<Geometry>,
+}
+
+/// which platform to run on
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Frontend {
+    /// the terminal we were started from
+    Terminal,
+    /// nothing to see or hear, and nobody pressing keys
+    Headless,
+    /// the screen described in text, for screen readers and logs
+    Text,
+    /// a Raspberry Pi's LED matrix and buttons
+    #[cfg(feature = "gpio")]
+    Matrix,
+    /// a window, with a debugger round the screen
+    #[cfg(feature = "gui")]
+    Gui,
+    /// a window of SDL2's, with nothing round the screen
+    #[cfg(feature = "sdl")]
+    Sdl,
+}
+
+impl Frontend {
+    /// the frontends this build has
+    pub const ALL: &'static [Frontend] = &[
+        Frontend::Terminal,
+        Frontend::Headless,
+        Frontend::Text,
+        #[cfg(feature = "gpio")]
+        Frontend::Matrix,
+        #[cfg(feature = "gui")]
+        Frontend::Gui,
+        #[cfg(feature = "sdl")]
+        Frontend::Sdl,
+    ];
+
+    pub fn parse(s: &str) -> Result<Self, Chip8Error> {
+        Self::ALL
+            .iter()
+            .find(|f| f.name() == s)
+            .copied()
+            .ok_or_else(|| {
+                Chip8Error::ConfigError(format!(
+                    "no frontend called \"{}\" (try {})",
+                    s,
+                    Self::names()
+                ))
+            })
+    }
+
+    /// what --frontend calls it
+    pub fn name(&self) -> &'static str {
+        match self {
+            Frontend::Terminal => "terminal",
+            Frontend::Headless => "headless",
+            Frontend::Text => "text",
+            #[cfg(feature = "gpio")]
+            Frontend::Matrix => "matrix",
+            #[cfg(feature = "gui")]
+            Frontend::Gui => "gui",
+            #[cfg(feature = "sdl")]
+            Frontend::Sdl => "sdl",
+        }
+    }
+
+    /// the names of the frontends this build has, e.g. "terminal,
+    /// headless or text"
+    pub fn names() -> String {
+        let names: Vec<&str> = Self::ALL.iter().map(Frontend::name).collect();
+        match names.split_last() {
+            Some((last, [])) => last.to_string(),
+            Some((last, rest)) => format!("{} or {}", rest.join(", "), last),
+            None => String::new(),
+        }
+    }
+
+    /// build the platform. keymap's None when keys come from somewhere
+    /// other than the keyboard (a replay, say)
+    pub fn platform(
+        self,
+        keymap: Option<Keymap>,
+        options: TerminalOptions,
+    ) -> Result<Box<dyn Platform>, Chip8Error> {
+        Ok(match self {
+            Frontend::Terminal => Box::new(TerminalPlatform::new(keymap, options)?),
+            Frontend::Headless => Box::new(HeadlessPlatform::new()),
+            Frontend::Text => Box::new(TextPlatform::new(keymap, options)?),
+            #[cfg(feature = "gpio")]
+            Frontend::Matrix => Box::new(MatrixPlatform::new(keymap.is_some())?),
+            #[cfg(feature = "gui")]
+            Frontend::Gui => Box::new(GuiPlatform::new(keymap, options)?),
+            #[cfg(feature = "sdl")]
+            Frontend::Sdl => Box::new(SdlPlatform::new(keymap, options)?),
+        })
+    }
+}
+
+/// draws in the terminal, reads its keyboard, and beeps however it can
+pub struct TerminalPlatform {
+    display: Box<dyn Display>,
+    input: Box<dyn Input>,
+    sound: Box<dyn Sound>,
+}
+
+impl TerminalPlatform {
+    pub fn new(keymap: Option<Keymap>, options: TerminalOptions) -> Result<Self, Chip8Error> {
+        let render_queue = options.render_queue;
+        let hotkeys = options.hotkeys;
+        let touch = options.touch;
+        let key_repeat = options.key_repeat;
+        let assist = options.assist;
+        let audio_buffer = options.audio_buffer;
+        let make = move || {
+            let mut display = MonoTermDisplay::new(64, 32)?;
+            display.set_theme(options.theme);
+            if let Some(colours) = options.plane_colours {
+                display.set_plane_colours(colours);
+            }
+            if let Some(cells) = options.cells {
+                display.set_cells(cells)?;
+            }
+            if let Some(scale) = options.scale {
+                display.set_scale(scale)?;
+            }
+            display.set_colours(options.colours);
+            if let Some(s) = options.status {
+                display.set_status(&s);
+            }
+            display.set_keypad(options.touch);
+            Ok(display)
+        };
+        let display: Box<dyn Display> = match render_queue {
+            Some(queue) => Box::new(ThreadedDisplay::spawn(make, queue)?),
+            None => Box::new(make()?),
+        };
+        // leave the keyboard alone if we're not listening to it, so ^C
+        // still works
+        let input: Box<dyn Input> = match keymap {
+            Some(k) => {
+                let mut input = StdinInput::with_keys(k, hotkeys)?;
+                input.set_touch(touch)?;
+                input.set_key_repeat(key_repeat);
+                input.set_assist(assist);
+                Box::new(input)
+            }
+            None => Box::new(DummyInput::new(&[])),
+        };
+        Ok(TerminalPlatform {
+            display,
+            input,
+            sound: sound::best_available(audio_buffer),
+        })
+    }
+}
+
+impl Platform for TerminalPlatform {
+    fn devices(&mut self) -> (&mut dyn Display, &mut dyn Input, &mut dyn Sound) {
+        (
+            self.display.as_mut(),
+            self.input.as_mut(),
+            self.sound.as_mut(),
+        )
+    }
+}
+
+/// runs without a screen, a keyboard or a speaker: for servers, and for
+/// checking ROMs in batches
+pub struct HeadlessPlatform {
+    display: DummyDisplay,
+    input: DummyInput,
+    sound: Mute,
+}
+
+impl HeadlessPlatform {
+    pub fn new() -> Self {
+        HeadlessPlatform {
+            display: DummyDisplay,
+            input: DummyInput::new(&[]),
+            sound: Mute::new(),
+        }
+    }
+}
+
+impl Default for HeadlessPlatform {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Platform for HeadlessPlatform {
+    fn devices(&mut self) -> (&mut dyn Display, &mut dyn Input, &mut dyn Sound) {
+        (&mut self.display, &mut self.input, &mut self.sound)
+    }
+}
+
+/// describes the screen on stdout, and reads the keyboard
+pub struct TextPlatform {
+    display: TextDisplay<Stdout>,
+    input: Box<dyn Input>,
+    sound: Mute,
+}
+
+impl TextPlatform {
+    /// only the keys in options matter: there's nothing to draw
+    pub fn new(keymap: Option<Keymap>, options: TerminalOptions) -> Result<Self, Chip8Error> {
+        // reading the keyboard puts the terminal in raw mode, where a line
+        // feed doesn't go back to the start of the line by itself
+        let (input, line_end): (Box<dyn Input>, _) = match keymap {
+            Some(k) => {
+                let mut input = StdinInput::with_keys(k, options.hotkeys)?;
+                input.set_key_repeat(options.key_repeat);
+                input.set_assist(options.assist);
+                (Box::new(input), "\r\n")
+            }
+            None => (Box::new(DummyInput::new(&[])), "\n"),
+        };
+        Ok(TextPlatform {
+            display: TextDisplay::new(io::stdout(), line_end),
+            input,
+            sound: Mute::new(),
+        })
+    }
+}
+
+impl Platform for TextPlatform {
+    fn devices(&mut self) -> (&mut dyn Display, &mut dyn Input, &mut dyn Sound) {
+        (&mut self.display, self.input.as_mut(), &mut self.sound)
+    }
+}
+
+/// an LED matrix and buttons on a Raspberry Pi's GPIO pins, wired up as
+/// the gpio module describes
+#[cfg(feature = "gpio")]
+pub struct MatrixPlatform {
+    display: crate::gpio::Hub75Display,
+    input: Box<dyn Input>,
+    sound: Mute,
+}
+
+#[cfg(feature = "gpio")]
+impl MatrixPlatform {
+    /// buttons is false when keys come from somewhere else
+    pub fn new(buttons: bool) -> Result<Self, Chip8Error> {
+        use crate::gpio::{Gpio, GpioInput, Hub75Display, Hub75Pins};
+        use crate::gpio::{CABINET_BUTTONS, CABINET_MENU_BUTTON};
+        use std::sync::Arc;
+        let gpio = Arc::new(Gpio::open()?);
+        let input: Box<dyn Input> = match buttons {
+            true => Box::new(GpioInput::new(
+                gpio.clone(),
+                &CABINET_BUTTONS,
+                CABINET_MENU_BUTTON,
+            )),
+            false => Box::new(DummyInput::new(&[])),
+        };
+        Ok(MatrixPlatform {
+            display: Hub75Display::new(gpio, Hub75Pins::REGULAR),
+            input,
+            sound: Mute::new(),
+        })
+    }
+}
+
+#[cfg(feature = "gpio")]
+impl Platform for MatrixPlatform {
+    fn devices(&mut self) -> (&mut dyn Display, &mut dyn Input, &mut dyn Sound) {
+        (&mut self.display, self.input.as_mut(), &mut self.sound)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::Chip8Interpreter;
+
+    #[test]
+    fn test_parse() -> Result<(), Chip8Error> {
+        assert_eq!(Frontend::parse("terminal")?, Frontend::Terminal);
+        assert_eq!(Frontend::parse("headless")?, Frontend::Headless);
+        assert_eq!(Frontend::parse("text")?, Frontend::Text);
+        assert!(Frontend::parse("wasm").is_err());
+        assert_eq!(Frontend::parse(Frontend::Text.name())?, Frontend::Text);
+        #[cfg(feature = "gui")]
+        assert_eq!(Frontend::parse("gui")?, Frontend::Gui);
+        #[cfg(feature = "sdl")]
+        assert_eq!(Frontend::parse("sdl")?, Frontend::Sdl);
+        // every frontend there is goes by its name
+        for f in Frontend::ALL {
+            assert_eq!(Frontend::parse(f.name())?, *f);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_names() {
+        assert!(Frontend::names().starts_with("terminal, headless"));
+        let said = Frontend::parse("wasm").unwrap_err().to_string();
+        assert!(said.contains(&Frontend::names()), "{}", said);
+        #[cfg(feature = "sdl")]
+        assert!(said.contains("sdl"), "{}", said);
+        #[cfg(feature = "gui")]
+        assert!(said.contains("gui"), "{}", said);
+        #[cfg(not(any(feature = "gpio", feature = "gui", feature = "sdl")))]
+        assert_eq!(Frontend::names(), "terminal, headless or text");
+    }
+
+    #[test]
+    fn test_headless() -> Result<(), Chip8Error> {
+        let mut platform = Frontend::Headless.platform(None, TerminalOptions::default())?;
+        let (display, input, sound) = platform.devices();
+        let mut machine = Chip8Interpreter::new(display, input, sound)?;
+        machine.load_program(&mut &[0x70, 0x01, 0x12, 0x00][..])?;
+        machine.run_frames(1)?;
+        assert!(machine.v(0) > 0);
+        Ok(())
+    }
+}
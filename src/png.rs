@@ -0,0 +1,547 @@
+//! # png
+//!
+//! just enough PNG for sprite sheets. writing is the easy half: greyscale,
+//! and stored rather than compressed, like bug reports' zips. reading has
+//! to cope with whatever a paint program saves once a sheet's been edited,
+//! so it takes any colour type at any depth (as long as it isn't
+//! interlaced) and inflates, which is the bulk of this
+use crate::error::Chip8Error;
+use crate::persist::crc32;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+
+/// the most a stored deflate block can hold
+const PNG_STORED_BLOCK: usize = 0xffff;
+
+/// the biggest picture read, in pixels: no sprite sheet's anywhere near,
+/// and a PNG can say it's any size it likes
+const PNG_MAX_PIXELS: usize = 1 << 24;
+
+/// a picture, in shades of grey
+#[derive(Debug, Clone, PartialEq)]
+pub struct Picture {
+    pub width: usize,
+    pub height: usize,
+    /// a byte a pixel, black (0) to white (255), a row at a time from the top
+    pub grey: Vec<u8>,
+}
+
+impl Picture {
+    pub fn new(width: usize, height: usize, shade: u8) -> Self {
+        Picture {
+            width,
+            height,
+            grey: vec![shade; width * height],
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> u8 {
+        self.grey[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, shade: u8) {
+        self.grey[y * self.width + x] = shade;
+    }
+}
+
+fn bad(message: &str) -> Chip8Error {
+    Chip8Error::BadImage(message.to_string())
+}
+
+/// what zlib wraps round deflate to check it
+fn adler32(data: &[u8]) -> u32 {
+    let (a, b) = data.iter().fold((1u32, 0u32), |(a, b), byte| {
+        let a = (a + *byte as u32) % 65521;
+        (a, (b + a) % 65521)
+    });
+    b << 16 | a
+}
+
+/// picture as a PNG file
+pub fn write(picture: &Picture) -> Vec<u8> {
+    // every row starts with its filter, which is none
+    let mut raw = Vec::new();
+    for row in picture.grey.chunks(picture.width.max(1)) {
+        raw.push(0);
+        raw.extend(row);
+    }
+    // zlib: deflate with no compression, no dictionary
+    let mut zlib = vec![0x78, 0x01];
+    let blocks: Vec<&[u8]> = raw.chunks(PNG_STORED_BLOCK).collect();
+    for (n, block) in blocks.iter().enumerate() {
+        zlib.push((n + 1 == blocks.len()) as u8);
+        zlib.extend((block.len() as u16).to_le_bytes());
+        zlib.extend((!(block.len() as u16)).to_le_bytes());
+        zlib.extend(*block);
+    }
+    zlib.extend(adler32(&raw).to_be_bytes());
+
+    let mut header = Vec::new();
+    header.extend((picture.width as u32).to_be_bytes());
+    header.extend((picture.height as u32).to_be_bytes());
+    // 8 bits a pixel, grey, deflate, adaptive filtering, not interlaced
+    header.extend([8, 0, 0, 0, 0]);
+
+    let mut png = PNG_SIGNATURE.to_vec();
+    for (kind, data) in [(b"IHDR", header), (b"IDAT", zlib), (b"IEND", Vec::new())] {
+        png.extend((data.len() as u32).to_be_bytes());
+        let mut checked = kind.to_vec();
+        checked.extend(&data);
+        png.extend(&checked);
+        png.extend(crc32(&checked).to_be_bytes());
+    }
+    png
+}
+
+/// a PNG file as a picture in grey: colours by how bright they are, and
+/// anything see-through as if it were on black
+pub fn read(png: &[u8]) -> Result<Picture, Chip8Error> {
+    if !png.starts_with(&PNG_SIGNATURE) {
+        return Err(bad("not a PNG"));
+    }
+    let mut header = None;
+    let mut palette: Vec<[u8; 4]> = Vec::new();
+    let mut zlib = Vec::new();
+    let mut rest = &png[PNG_SIGNATURE.len()..];
+    loop {
+        if rest.len() < 12 {
+            return Err(bad("the PNG's cut short"));
+        }
+        let len = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+        let end = len
+            .checked_add(12)
+            .filter(|end| *end <= rest.len())
+            .ok_or_else(|| bad("the PNG's cut short"))?;
+        let (checked, sum) = (&rest[4..end - 4], &rest[end - 4..end]);
+        if crc32(checked).to_be_bytes() != sum {
+            return Err(bad("the PNG's damaged"));
+        }
+        let (kind, data) = checked.split_at(4);
+        match kind {
+            b"IHDR" if data.len() == 13 => header = Some(data.to_vec()),
+            b"PLTE" => {
+                palette = data
+                    .chunks_exact(3)
+                    .map(|c| [c[0], c[1], c[2], 0xff])
+                    .collect()
+            }
+            // a palette's transparency
+            b"tRNS" => {
+                for (entry, alpha) in palette.iter_mut().zip(data) {
+                    entry[3] = *alpha;
+                }
+            }
+            b"IDAT" => zlib.extend(data),
+            b"IEND" => break,
+            _ => {}
+        }
+        rest = &rest[end..];
+    }
+
+    let header = header.ok_or_else(|| bad("the PNG has no header"))?;
+    let number = |at: usize| {
+        u32::from_be_bytes([header[at], header[at + 1], header[at + 2], header[at + 3]]) as usize
+    };
+    let (width, height, depth, colour) = (number(0), number(4), header[8] as usize, header[9]);
+    if header[12] != 0 {
+        return Err(bad("the PNG's interlaced: save it without"));
+    }
+    // how many samples a pixel has
+    let samples = match (colour, depth) {
+        (0, 1 | 2 | 4 | 8 | 16) | (3, 1 | 2 | 4 | 8) => 1,
+        (4, 8 | 16) => 2,
+        (2, 8 | 16) => 3,
+        (6, 8 | 16) => 4,
+        _ => return Err(bad("the PNG's colours aren't any kind there is")),
+    };
+    let too_big = || bad("the PNG's too big to be a sprite sheet");
+    if width.checked_mul(height).is_none_or(|n| n > PNG_MAX_PIXELS) {
+        return Err(too_big());
+    }
+    let stride = width
+        .checked_mul(samples * depth)
+        .ok_or_else(too_big)?
+        .div_ceil(8);
+    // how far back the filters look: a whole pixel, or a byte if it's less
+    let step = (samples * depth).div_ceil(8);
+    // each row, and its filter
+    let size = (stride + 1).checked_mul(height).ok_or_else(too_big)?;
+
+    let raw = inflate(&zlib, size)?;
+    if raw.len() < size {
+        return Err(bad("the PNG has less picture than it says"));
+    }
+    let mut rows: Vec<Vec<u8>> = Vec::with_capacity(height);
+    for y in 0..height {
+        let line = &raw[y * (stride + 1)..(y + 1) * (stride + 1)];
+        let above = match y {
+            0 => vec![0; stride],
+            _ => rows[y - 1].clone(),
+        };
+        let mut row = line[1..].to_vec();
+        for x in 0..stride {
+            let left = if x >= step { row[x - step] } else { 0 };
+            let corner = if x >= step { above[x - step] } else { 0 };
+            let predicted = match line[0] {
+                0 => 0,
+                1 => left,
+                2 => above[x],
+                3 => ((left as u16 + above[x] as u16) / 2) as u8,
+                4 => paeth(left, above[x], corner),
+                _ => return Err(bad("the PNG has a filter there isn't")),
+            };
+            row[x] = row[x].wrapping_add(predicted);
+        }
+        rows.push(row);
+    }
+
+    let mut picture = Picture::new(width, height, 0);
+    for (y, row) in rows.iter().enumerate() {
+        for x in 0..width {
+            // each sample as 0-255: the top byte of 16, or spread out from
+            // fewer than 8
+            let sample = |n: usize| -> u8 {
+                let bit = (x * samples + n) * depth;
+                match depth {
+                    8 | 16 => row[bit / 8],
+                    _ => {
+                        let max = (1 << depth) - 1;
+                        let v = (row[bit / 8] >> (8 - depth - bit % 8)) & max;
+                        (v as usize * 255 / max as usize) as u8
+                    }
+                }
+            };
+            let index = |n: usize| -> usize {
+                let bit = (x * samples + n) * depth;
+                match depth {
+                    8 => row[bit / 8] as usize,
+                    _ => ((row[bit / 8] >> (8 - depth - bit % 8)) & ((1 << depth) - 1)) as usize,
+                }
+            };
+            let (shade, alpha) = match colour {
+                0 => (sample(0), 255),
+                3 => {
+                    let [r, g, b, a] = *palette
+                        .get(index(0))
+                        .ok_or_else(|| bad("the PNG's palette is too short"))?;
+                    (luma(r, g, b), a)
+                }
+                4 => (sample(0), sample(1)),
+                2 => (luma(sample(0), sample(1), sample(2)), 255),
+                _ => (luma(sample(0), sample(1), sample(2)), sample(3)),
+            };
+            picture.set(x, y, (shade as u16 * alpha as u16 / 255) as u8);
+        }
+    }
+    Ok(picture)
+}
+
+/// how bright a colour looks
+fn luma(r: u8, g: u8, b: u8) -> u8 {
+    ((r as u32 * 299 + g as u32 * 587 + b as u32 * 114) / 1000) as u8
+}
+
+/// whichever of the neighbours is nearest what they'd suggest together
+fn paeth(left: u8, above: u8, corner: u8) -> u8 {
+    let p = left as i16 + above as i16 - corner as i16;
+    let (pa, pb, pc) = (
+        (p - left as i16).abs(),
+        (p - above as i16).abs(),
+        (p - corner as i16).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        left
+    } else if pb <= pc {
+        above
+    } else {
+        corner
+    }
+}
+
+/// deflate's bits, least significant first
+struct Bits<'a> {
+    data: &'a [u8],
+    at: usize,
+}
+
+impl Bits<'_> {
+    fn bits(&mut self, n: usize) -> Result<usize, Chip8Error> {
+        let mut value = 0;
+        for i in 0..n {
+            let byte = self
+                .data
+                .get(self.at / 8)
+                .ok_or_else(|| bad("the PNG's picture is cut short"))?;
+            value |= ((*byte as usize >> (self.at % 8)) & 1) << i;
+            self.at += 1;
+        }
+        Ok(value)
+    }
+}
+
+/// a canonical Huffman code: how many codes there are of each length, and
+/// the symbols in order
+struct Huffman {
+    counts: [usize; 16],
+    symbols: Vec<usize>,
+}
+
+impl Huffman {
+    fn new(lengths: &[usize]) -> Self {
+        let mut counts = [0; 16];
+        for len in lengths {
+            counts[*len] += 1;
+        }
+        counts[0] = 0;
+        let mut symbols: Vec<usize> = (0..lengths.len()).filter(|s| lengths[*s] > 0).collect();
+        symbols.sort_by_key(|s| lengths[*s]);
+        Huffman { counts, symbols }
+    }
+
+    fn decode(&self, bits: &mut Bits) -> Result<usize, Chip8Error> {
+        // codes of each length follow on from the shorter ones
+        let (mut code, mut first, mut index) = (0, 0, 0);
+        for count in &self.counts[1..] {
+            code |= bits.bits(1)?;
+            if code < first + count {
+                return Ok(self.symbols[index + code - first]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(bad("the PNG's picture is damaged"))
+    }
+}
+
+/// lengths 257 to 285 start at, and how many extra bits they have
+#[rustfmt::skip]
+const INFLATE_LENGTHS: [(usize, usize); 29] = [
+    (3, 0), (4, 0), (5, 0), (6, 0), (7, 0), (8, 0), (9, 0), (10, 0),
+    (11, 1), (13, 1), (15, 1), (17, 1), (19, 2), (23, 2), (27, 2), (31, 2),
+    (35, 3), (43, 3), (51, 3), (59, 3), (67, 4), (83, 4), (99, 4), (115, 4),
+    (131, 5), (163, 5), (195, 5), (227, 5), (258, 0),
+];
+
+/// and distances 0 to 29
+#[rustfmt::skip]
+const INFLATE_DISTANCES: [(usize, usize); 30] = [
+    (1, 0), (2, 0), (3, 0), (4, 0), (5, 1), (7, 1), (9, 2), (13, 2),
+    (17, 3), (25, 3), (33, 4), (49, 4), (65, 5), (97, 5), (129, 6), (193, 6),
+    (257, 7), (385, 7), (513, 8), (769, 8), (1025, 9), (1537, 9), (2049, 10), (3073, 10),
+    (4097, 11), (6145, 11), (8193, 12), (12289, 12), (16385, 13), (24577, 13),
+];
+
+/// the order a dynamic block's code length code lengths come in
+const INFLATE_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// undo zlib's deflate, which should come to no more than limit bytes
+fn inflate(zlib: &[u8], limit: usize) -> Result<Vec<u8>, Chip8Error> {
+    if zlib.len() < 2 || zlib[0] & 0x0f != 8 || zlib[1] & 0x20 != 0 {
+        return Err(bad("the PNG's picture isn't deflated"));
+    }
+    let mut bits = Bits {
+        data: &zlib[2..],
+        at: 0,
+    };
+    let mut out = Vec::new();
+    loop {
+        let last = bits.bits(1)? == 1;
+        match bits.bits(2)? {
+            0 => {
+                // stored, from the next whole byte
+                bits.at = bits.at.div_ceil(8) * 8;
+                let len = bits.bits(16)?;
+                if bits.bits(16)? != !len & 0xffff {
+                    return Err(bad("the PNG's picture is damaged"));
+                }
+                let start = bits.at / 8;
+                let block = bits
+                    .data
+                    .get(start..start + len)
+                    .ok_or_else(|| bad("the PNG's picture is cut short"))?;
+                if out.len() + len > limit {
+                    return Err(too_much());
+                }
+                out.extend(block);
+                bits.at += len * 8;
+            }
+            1 => {
+                let mut lengths = [8; 288];
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                let literals = Huffman::new(&lengths);
+                let distances = Huffman::new(&[5; 30]);
+                inflate_block(&mut bits, &literals, &distances, &mut out, limit)?;
+            }
+            2 => {
+                let literal_count = bits.bits(5)? + 257;
+                let distance_count = bits.bits(5)? + 1;
+                let length_count = bits.bits(4)? + 4;
+                let mut length_lengths = [0; 19];
+                for n in INFLATE_ORDER.iter().take(length_count) {
+                    length_lengths[*n] = bits.bits(3)?;
+                }
+                let lengths_code = Huffman::new(&length_lengths);
+                let mut lengths = Vec::new();
+                while lengths.len() < literal_count + distance_count {
+                    let (repeat, times) = match lengths_code.decode(&mut bits)? {
+                        16 => {
+                            let previous = *lengths
+                                .last()
+                                .ok_or_else(|| bad("the PNG's picture is damaged"))?;
+                            (previous, 3 + bits.bits(2)?)
+                        }
+                        17 => (0, 3 + bits.bits(3)?),
+                        18 => (0, 11 + bits.bits(7)?),
+                        len => (len, 1),
+                    };
+                    lengths.extend(std::iter::repeat_n(repeat, times));
+                }
+                let (literals, distances) = lengths.split_at(literal_count);
+                inflate_block(
+                    &mut bits,
+                    &Huffman::new(literals),
+                    &Huffman::new(distances),
+                    &mut out,
+                    limit,
+                )?;
+            }
+            _ => return Err(bad("the PNG's picture is damaged")),
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+/// more picture than the PNG said there'd be
+fn too_much() -> Chip8Error {
+    bad("the PNG has more picture than it says")
+}
+
+/// a compressed block, up to its end, or limit bytes
+fn inflate_block(
+    bits: &mut Bits,
+    literals: &Huffman,
+    distances: &Huffman,
+    out: &mut Vec<u8>,
+    limit: usize,
+) -> Result<(), Chip8Error> {
+    loop {
+        let symbol = literals.decode(bits)?;
+        match symbol {
+            0..=255 if out.len() >= limit => return Err(too_much()),
+            0..=255 => out.push(symbol as u8),
+            256 => return Ok(()),
+            _ => {
+                let (base, extra) = *INFLATE_LENGTHS
+                    .get(symbol - 257)
+                    .ok_or_else(|| bad("the PNG's picture is damaged"))?;
+                let len = base + bits.bits(extra)?;
+                let (base, extra) = *INFLATE_DISTANCES
+                    .get(distances.decode(bits)?)
+                    .ok_or_else(|| bad("the PNG's picture is damaged"))?;
+                let distance = base + bits.bits(extra)?;
+                let from = out
+                    .len()
+                    .checked_sub(distance)
+                    .ok_or_else(|| bad("the PNG's picture is damaged"))?;
+                if out.len() + len > limit {
+                    return Err(too_much());
+                }
+                // it can overlap what it's adding, so a byte at a time
+                for n in 0..len {
+                    out.push(out[from + n]);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() -> Result<(), Chip8Error> {
+        let mut picture = Picture::new(3, 2, 0);
+        picture.set(1, 0, 255);
+        picture.set(2, 1, 128);
+        let png = write(&picture);
+        assert!(png.starts_with(&PNG_SIGNATURE));
+        assert_eq!(read(&png)?, picture);
+        // one big enough to need more than one stored block
+        let big = Picture::new(300, 300, 7);
+        assert_eq!(read(&write(&big))?, big);
+        Ok(())
+    }
+
+    #[test]
+    fn test_compressed() -> Result<(), Chip8Error> {
+        // a 4x2 picture as a paint program might save it: RGBA, filtered
+        // (sub, then up) and compressed with fixed codes, by zlib
+        #[rustfmt::skip]
+        let png = [
+            0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d,
+            0x49, 0x48, 0x44, 0x52, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x02,
+            0x08, 0x06, 0x00, 0x00, 0x00, 0x7f, 0xa8, 0x7d, 0x63, 0x00, 0x00, 0x00,
+            0x1a, 0x49, 0x44, 0x41, 0x54, 0x78, 0xda, 0x63, 0x64, 0x60, 0x60, 0xf8,
+            0x0f, 0x04, 0x0c, 0x8c, 0x8c, 0x8c, 0x20, 0x06, 0x03, 0x13, 0x32, 0x07,
+            0x44, 0x03, 0x00, 0xef, 0xe8, 0x0d, 0x00, 0x38, 0x83, 0xc2, 0x1c, 0x00,
+            0x00, 0x00, 0x00, 0x49, 0x45, 0x4e, 0x44, 0xae, 0x42, 0x60, 0x82,
+        ];
+        let picture = read(&png)?;
+        assert_eq!((picture.width, picture.height), (4, 2));
+        assert_eq!(picture.grey, [0, 255, 0, 255, 255, 0, 255, 0]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_inflate() -> Result<(), Chip8Error> {
+        let hex = |h: &str| -> Vec<u8> {
+            (0..h.len())
+                .step_by(2)
+                .map(|n| u8::from_str_radix(&h[n..n + 2], 16).unwrap_or_default())
+                .collect()
+        };
+        // zlib's own, with fixed codes and then with codes of its own
+        let fixed = hex("78da4b4c2408924805c9080000c7f12447");
+        let expected = [[b'a'; 35].as_slice(), &[b'b'; 49], &[b'c'; 11]].concat();
+        assert_eq!(inflate(&fixed, 95)?, expected);
+        let dynamic =
+            hex("78da258ac11100000cc16645f79fa1d27a90cb9193a869594703569a690fd22ce65f462dbb3516e7");
+        assert_eq!(
+            inflate(&dynamic, 60)?,
+            b"abcccaaaacaabacaaaadcaabccabaabcabadaaaabbadabaababacaabaaab"
+        );
+        // but no more than there's meant to be
+        assert!(inflate(&fixed, 94).is_err());
+        assert!(inflate(&dynamic, 59).is_err());
+        // and a stored block has to agree with itself about its length
+        let stored = [0x78, 0x01, 0x01, 0x02, 0x00, 0xfd, 0xff, b'h', b'i'];
+        assert_eq!(inflate(&stored, 2)?, b"hi");
+        assert!(inflate(&[0x78, 0x01, 0x01, 0x02, 0x00, 0xfd, 0xfe, b'h', b'i'], 2).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_not_a_png() {
+        assert!(matches!(read(b"GIF89a"), Err(Chip8Error::BadImage(_))));
+        let mut png = write(&Picture::new(2, 2, 0));
+        png[20] ^= 1;
+        assert!(read(&png).unwrap_err().to_string().contains("damaged"));
+        // or one that says it's bigger than anything could be
+        let mut png = write(&Picture::new(2, 2, 0));
+        png[16..24].copy_from_slice(&[0xff; 8]);
+        let sum = crc32(&png[12..29]).to_be_bytes();
+        png[29..33].copy_from_slice(&sum);
+        assert!(read(&png).unwrap_err().to_string().contains("too big"));
+        // or a chunk longer than the file
+        let mut png = write(&Picture::new(2, 2, 0));
+        png[8..12].copy_from_slice(&[0xff; 4]);
+        assert!(read(&png).unwrap_err().to_string().contains("cut short"));
+    }
+}
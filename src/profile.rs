@@ -0,0 +1,295 @@
+//! # profiles
+//!
+//! the keys, quirks and speed a ROM (or a kind of ROM) plays best with,
+//! as a small file to pass round, e.g. schip-modern.toml:
+//!
+//! ```toml
+//! name = "schip-modern"
+//! description = "SUPER-CHIP games from the 90s on"
+//! schip = true
+//! quirks = "modern"
+//! speed = 2.0
+//!
+//! [keymap]
+//! j = 4
+//! l = 6
+//! ```
+//!
+//! chip8 profiles export NAME OUT writes one from whatever --quirks,
+//! --schip, --map and --speed say; chip8 profiles import FILE checks one
+//! over and installs it beside the config, where chip8 profiles list finds
+//! it; and chip8 profiles apply NAME ROM has a ROM played with it from
+//! then on (or --profile NAME, for just the once). a profile's for either
+//! CHIP-8 or SUPER-CHIP, and one for CHIP-8 isn't applied to a ROM that
+//! needs the SUPER-CHIP, nor run with --schip
+use crate::config;
+use crate::error::Chip8Error;
+use crate::interpreter::CHIP8_SPEEDS;
+use crate::isa::{self, Variant};
+use crate::lang;
+use crate::paths;
+use crate::persist;
+use crate::quirks::Quirks;
+use crate::storage::Storage;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// a named set of settings, to share
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    /// what it's called, which is what it's installed as
+    pub name: String,
+    /// what it's for, in a line
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub description: String,
+    /// it's for the SUPER-CHIP, rather than the VIP's CHIP-8
+    #[serde(default)]
+    pub schip: bool,
+    /// the quirks profile, as --quirks takes it, e.g. "vip+mask-i"
+    #[serde(default = "default_quirks")]
+    pub quirks: String,
+    /// how much faster than the VIP to run, one of the speeds the hotkeys
+    /// go through
+    #[serde(default = "default_speed")]
+    pub speed: f64,
+    /// host key -> COSMAC key, over the top of the default keymap (and
+    /// under a ROM's own)
+    #[serde(default)]
+    pub keymap: BTreeMap<String, u8>,
+}
+
+fn default_quirks() -> String {
+    "vip".to_string()
+}
+
+fn default_speed() -> f64 {
+    1.0
+}
+
+impl Profile {
+    /// called name, with the VIP's settings
+    pub fn new(name: &str) -> Self {
+        Profile {
+            name: name.to_string(),
+            description: String::new(),
+            schip: false,
+            quirks: default_quirks(),
+            speed: default_speed(),
+            keymap: BTreeMap::new(),
+        }
+    }
+
+    /// a profile from its TOML, having checked it over
+    pub fn from_toml(s: &str) -> Result<Self, Chip8Error> {
+        let profile: Profile =
+            toml::from_str(s).map_err(|e| Chip8Error::ConfigError(e.to_string()))?;
+        profile.validate()?;
+        Ok(profile)
+    }
+
+    pub fn to_toml(&self) -> Result<String, Chip8Error> {
+        toml::to_string(self).map_err(|e| Chip8Error::ConfigError(e.to_string()))
+    }
+
+    /// is everything in it something we can use? (it may well have come
+    /// from someone else)
+    pub fn validate(&self) -> Result<(), Chip8Error> {
+        let bad = |what: String| {
+            Err(Chip8Error::ConfigError(format!(
+                "profile \"{}\": {}",
+                self.name, what
+            )))
+        };
+        let named = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+        if self.name.is_empty() || !self.name.chars().all(named) {
+            return bad("a name's letters, numbers, - and _".to_string());
+        }
+        if !CHIP8_SPEEDS.contains(&self.speed) {
+            return bad(format!(
+                "speed {} isn't one of {:?}",
+                self.speed, CHIP8_SPEEDS
+            ));
+        }
+        if let Err(e) = self.quirks().and(self.keymap_overrides()) {
+            return bad(e.to_string());
+        }
+        Ok(())
+    }
+
+    pub fn quirks(&self) -> Result<Quirks, Chip8Error> {
+        Quirks::profile(&self.quirks)
+    }
+
+    /// the keymap, as the input takes it
+    pub fn keymap_overrides(&self) -> Result<Vec<(char, u8)>, Chip8Error> {
+        config::keymap_overrides(&self.keymap)
+    }
+
+    /// can it be used with the SUPER-CHIP on (schip) or off?
+    pub fn check_variant(&self, schip: bool) -> Result<(), Chip8Error> {
+        match (self.schip, schip) {
+            (false, true) => Err(Chip8Error::ConfigError(format!(
+                "profile \"{}\" is for CHIP-8, not the SUPER-CHIP",
+                self.name
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// can rom be played with it? a CHIP-8 profile can't be used with a ROM
+    /// that needs the SUPER-CHIP (as far as the first max_instructions it
+    /// could run show)
+    pub fn check_rom(&self, rom: &[u8], max_instructions: u64) -> Result<(), Chip8Error> {
+        if self.schip {
+            return Ok(());
+        }
+        let found = isa::unsupported(rom, &[Variant::Chip8], max_instructions)?;
+        match found.iter().find(|u| u.needs == Some(Variant::Schip)) {
+            Some(u) => Err(Chip8Error::ConfigError(format!(
+                "profile \"{}\" is for CHIP-8, but {}",
+                self.name, u
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// read one from a file, e.g. one that's been shared
+    pub fn load(storage: &dyn Storage, path: &Path) -> Result<Self, Chip8Error> {
+        match persist::read_text(storage, path)? {
+            Some(text) => Self::from_toml(&text),
+            None => Err(io::Error::from(io::ErrorKind::NotFound).into()),
+        }
+    }
+
+    /// write it to a file, e.g. to share
+    pub fn save(&self, storage: &dyn Storage, path: &Path) -> Result<(), Chip8Error> {
+        storage.write(path, self.to_toml()?.as_bytes())
+    }
+
+    /// where it's installed, in dir
+    pub fn path_in(dir: &Path, name: &str) -> PathBuf {
+        dir.join(format!("{}.toml", paths::file_name(name)))
+    }
+
+    /// install it in dir, for list and find to find, over any with the
+    /// same name
+    pub fn install(&self, storage: &dyn Storage, dir: &Path) -> Result<PathBuf, Chip8Error> {
+        let path = Self::path_in(dir, &self.name);
+        self.save(storage, &path)?;
+        Ok(path)
+    }
+
+    /// the one installed in dir called name, or if name's a file, the one
+    /// in it
+    pub fn find(storage: &dyn Storage, dir: &Path, name: &str) -> Result<Self, Chip8Error> {
+        let file = Path::new(name);
+        if file.extension().is_some() && storage.exists(file)? {
+            return Self::load(storage, file);
+        }
+        match Self::load(storage, &Self::path_in(dir, name)) {
+            Err(Chip8Error::Io(e)) if e.kind() == io::ErrorKind::NotFound => {
+                Err(Chip8Error::ConfigError(format!(
+                    "no profile called \"{}\" (chip8 profiles list shows them)",
+                    name
+                )))
+            }
+            p => p,
+        }
+    }
+
+    /// everything installed in dir, by name. any that can't be read are
+    /// left out, with a warning
+    pub fn list(storage: &dyn Storage, dir: &Path) -> Result<Vec<Self>, Chip8Error> {
+        let mut profiles = Vec::new();
+        for path in storage.list(dir)? {
+            if path.extension().is_some_and(|e| e == "toml") {
+                match Self::load(storage, &path) {
+                    Ok(p) => profiles.push(p),
+                    Err(e) => eprintln!(
+                        "{}",
+                        lang::format("warning.skipping", &[&path.display(), &e])
+                    ),
+                }
+            }
+        }
+        profiles.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(profiles)
+    }
+
+    /// where profiles are installed if nobody says otherwise
+    pub fn default_dir() -> PathBuf {
+        paths::profiles_dir()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    const SCHIP_MODERN: &str = "name = \"schip-modern\"\n\
+        schip = true\n\
+        quirks = \"modern\"\n\
+        speed = 2.0\n\
+        [keymap]\n\
+        j = 4\n";
+
+    #[test]
+    fn test_parse() -> Result<(), Chip8Error> {
+        let p = Profile::from_toml(SCHIP_MODERN)?;
+        assert_eq!(p.quirks()?, Quirks::MODERN);
+        assert_eq!(p.keymap_overrides()?, [('j', 4)]);
+        assert_eq!(Profile::from_toml(&p.to_toml()?)?, p);
+        // the defaults are the VIP's
+        let p = Profile::from_toml("name = \"plain\"")?;
+        assert_eq!((p.quirks()?, p.speed, p.schip), (Quirks::VIP, 1.0, false));
+
+        let bad = |s: &str| Profile::from_toml(s).is_err();
+        assert!(bad("name = \"../etc\""));
+        assert!(bad("name = \"fast\"\nspeed = 3.0"));
+        assert!(bad("name = \"odd\"\nquirks = \"amiga\""));
+        assert!(bad("name = \"keys\"\n[keymap]\nj = 16"));
+        assert!(bad("name = \"typo\"\nspeeed = 2.0"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_variant() -> Result<(), Chip8Error> {
+        let schip = Profile::from_toml(SCHIP_MODERN)?;
+        assert!(schip.check_variant(true).is_ok());
+        assert!(Profile::new("vip").check_variant(true).is_err());
+        assert!(Profile::new("vip").check_variant(false).is_ok());
+
+        // 00FF (high resolution) then loop
+        let rom = [0x00, 0xff, 0x12, 0x02];
+        assert!(Profile::new("vip").check_rom(&rom, 100).is_err());
+        assert!(schip.check_rom(&rom, 100).is_ok());
+        assert!(Profile::new("vip").check_rom(&[0x12, 0x00], 100).is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_install() -> Result<(), Chip8Error> {
+        let storage = MemoryStorage::new();
+        let dir = Profile::default_dir();
+        assert_eq!(Profile::list(&storage, &dir)?, []);
+        let mut p = Profile::from_toml(SCHIP_MODERN)?;
+        p.install(&storage, &dir)?;
+        p.name = "another".to_string();
+        p.install(&storage, &dir)?;
+        storage.write(&dir.join("broken.toml"), b"name = 4")?;
+        let names: Vec<String> = Profile::list(&storage, &dir)?
+            .into_iter()
+            .map(|p| p.name)
+            .collect();
+        assert_eq!(names, ["another", "schip-modern"]);
+        assert_eq!(Profile::find(&storage, &dir, "another")?, p);
+        assert!(Profile::find(&storage, &dir, "missing").is_err());
+        let file = dir.join("another.toml");
+        assert_eq!(Profile::find(&storage, &dir, &file.to_string_lossy())?, p);
+        Ok(())
+    }
+}
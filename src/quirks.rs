@@ -0,0 +1,284 @@
+use crate::error::Chip8Error;
+#[cfg(feature = "full")]
+use serde::{Deserialize, Serialize};
+
+/// the ways later CHIP-8 interpreters behave differently from the VIP's,
+/// which ROMs written for them can come to rely on
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "full", derive(Serialize, Deserialize))]
+pub struct Quirks {
+    /// 8XY6 and 8XYE shift VX in place, rather than shifting VY into VX
+    pub shift_vx: bool,
+    /// FX55 and FX65 leave I alone, rather than pointing it past the last
+    /// register saved or loaded
+    pub load_store_leaves_i: bool,
+    /// FX55 and FX65 point I at the last register saved or loaded, one
+    /// short of the VIP, as CHIP-48 did. leaving I alone beats it
+    #[cfg_attr(feature = "full", serde(default))]
+    pub load_store_adds_x: bool,
+    /// 8XY1, 8XY2 and 8XY3 leave VF alone, rather than clearing it, which
+    /// the VIP did as a side effect of how it ran them
+    #[cfg_attr(feature = "full", serde(default))]
+    pub logic_leaves_vf: bool,
+    /// BNNN jumps to XNN plus VX, rather than NNN plus V0, as CHIP-48 and
+    /// SUPER-CHIP read it (as BXNN)
+    #[cfg_attr(feature = "full", serde(default))]
+    pub jump_adds_vx: bool,
+    /// FX18 with VX = 1 sounds for a frame, rather than being too short
+    /// to hear as on the VIP (see timer::TONE_MIN_FRAMES)
+    #[cfg_attr(feature = "full", serde(default))]
+    pub short_tone: bool,
+    /// the buzzer only starts or stops at the start of a frame, when the
+    /// timers are looked at, rather than as soon as FX18 sets the tone
+    /// timer or it runs out, as emulators that only sound a frame at a
+    /// time have it
+    #[cfg_attr(feature = "full", serde(default))]
+    pub frame_tone: bool,
+    /// FX1E sets VF when I goes past 0xFFF, and clears it when it doesn't,
+    /// as the Amiga's interpreter did (Spacefight 2091! counts on it)
+    #[cfg_attr(feature = "full", serde(default))]
+    pub add_i_sets_vf: bool,
+    /// I is kept to 12 bits, so FX1E wraps it round from 0xFFF to 0x000
+    /// rather than pointing it past the end of memory
+    #[cfg_attr(feature = "full", serde(default))]
+    pub mask_i: bool,
+    /// what happens once I, or what an instruction reads or writes past
+    /// it, goes off the end of memory
+    #[cfg_attr(feature = "full", serde(default))]
+    pub out_of_range: OutOfRange,
+}
+
+/// how to find memory past the end of memory
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "full", derive(Serialize, Deserialize))]
+pub enum OutOfRange {
+    /// stop with a MemoryFault, as there's nothing there
+    #[default]
+    Fault,
+    /// wrap round to 12 bits, as if the address lines above them weren't
+    /// connected
+    Wrap,
+    /// grow memory to 64k, as XO-CHIP's is, wrapping round at 16 bits
+    Grow,
+}
+
+impl Quirks {
+    /// how the VIP's own interpreter behaves
+    pub const VIP: Quirks = Quirks {
+        shift_vx: false,
+        load_store_leaves_i: false,
+        load_store_adds_x: false,
+        logic_leaves_vf: false,
+        jump_adds_vx: false,
+        short_tone: false,
+        frame_tone: false,
+        add_i_sets_vf: false,
+        mask_i: false,
+        out_of_range: OutOfRange::Fault,
+    };
+
+    /// how most interpreters since CHIP-48 and SUPER-CHIP behave, which is
+    /// what most ROMs written since the 90s expect
+    pub const MODERN: Quirks = Quirks {
+        shift_vx: true,
+        load_store_leaves_i: true,
+        load_store_adds_x: false,
+        logic_leaves_vf: true,
+        jump_adds_vx: false,
+        short_tone: true,
+        frame_tone: false,
+        add_i_sets_vf: false,
+        mask_i: false,
+        out_of_range: OutOfRange::Fault,
+    };
+
+    /// how the HP48's CHIP-48 behaved, which the SUPER-CHIP mostly took on
+    pub const CHIP48: Quirks = Quirks {
+        shift_vx: true,
+        load_store_leaves_i: false,
+        load_store_adds_x: true,
+        logic_leaves_vf: true,
+        jump_adds_vx: true,
+        short_tone: true,
+        frame_tone: false,
+        add_i_sets_vf: false,
+        mask_i: false,
+        out_of_range: OutOfRange::Fault,
+    };
+
+    /// the profiles there are, by name
+    pub const PROFILES: [(&'static str, Quirks); 3] = [
+        ("vip", Quirks::VIP),
+        ("modern", Quirks::MODERN),
+        ("chip48", Quirks::CHIP48),
+    ];
+
+    /// the quirks that can be turned on one at a time, by name
+    pub const FLAGS: [&'static str; 11] = [
+        "shift-vx",
+        "load-store-leaves-i",
+        "load-store-adds-x",
+        "logic-leaves-vf",
+        "jump-adds-vx",
+        "short-tone",
+        "frame-tone",
+        "add-i-sets-vf",
+        "mask-i",
+        "wrap-memory",
+        "grow-memory",
+    ];
+
+    /// look up a profile by name, e.g. "vip", with any quirks to turn on as
+    /// well after it, e.g. "vip+add-i-sets-vf+mask-i"
+    pub fn profile(name: &str) -> Result<Quirks, Chip8Error> {
+        let mut parts = name.split('+');
+        let profile = parts.next().unwrap_or_default();
+        let mut quirks = Self::PROFILES
+            .iter()
+            .find(|(n, _)| *n == profile)
+            .map(|(_, q)| *q)
+            .ok_or_else(|| {
+                Chip8Error::ConfigError(format!(
+                    "no quirks profile called \"{}\" (try {})",
+                    profile,
+                    Self::PROFILES.map(|(n, _)| n).join(" or ")
+                ))
+            })?;
+        for flag in parts {
+            quirks.turn_on(flag)?;
+        }
+        Ok(quirks)
+    }
+
+    /// the shortest thing Quirks::profile would take to give these, if
+    /// anything would, e.g. "vip+mask-i"
+    pub fn name(&self) -> Option<String> {
+        let names = Self::PROFILES.iter().filter_map(|(name, profile)| {
+            let mut name = name.to_string();
+            let mut quirks = *profile;
+            for flag in Self::FLAGS {
+                let (mut ours, mut theirs) = (quirks, *self);
+                ours.turn_on(flag).ok()?;
+                theirs.turn_on(flag).ok()?;
+                // on in these, but not yet in the profile
+                if theirs == *self && ours != quirks {
+                    quirks = ours;
+                    name = name + "+" + flag;
+                }
+            }
+            (quirks == *self).then_some(name)
+        });
+        names.min_by_key(|n| n.len())
+    }
+
+    /// is the quirk called name on?
+    pub fn is_on(&self, name: &str) -> Result<bool, Chip8Error> {
+        let mut on = *self;
+        on.turn_on(name)?;
+        Ok(on == *self)
+    }
+
+    /// turn the quirk called name on if it's off, or off if it's on, e.g.
+    /// from the command palette. true if it's on now
+    pub fn toggle(&mut self, name: &str) -> Result<bool, Chip8Error> {
+        let on = !self.is_on(name)?;
+        self.set(name, on)?;
+        Ok(on)
+    }
+
+    fn turn_on(&mut self, name: &str) -> Result<(), Chip8Error> {
+        self.set(name, true)
+    }
+
+    fn set(&mut self, name: &str, on: bool) -> Result<(), Chip8Error> {
+        // off, memory's back to faulting, whichever way it went before
+        let range = |r| if on { r } else { OutOfRange::Fault };
+        match name {
+            "shift-vx" => self.shift_vx = on,
+            "load-store-leaves-i" => self.load_store_leaves_i = on,
+            "load-store-adds-x" => self.load_store_adds_x = on,
+            "logic-leaves-vf" => self.logic_leaves_vf = on,
+            "jump-adds-vx" => self.jump_adds_vx = on,
+            "short-tone" => self.short_tone = on,
+            "frame-tone" => self.frame_tone = on,
+            "add-i-sets-vf" => self.add_i_sets_vf = on,
+            "mask-i" => self.mask_i = on,
+            "wrap-memory" => self.out_of_range = range(OutOfRange::Wrap),
+            "grow-memory" => self.out_of_range = range(OutOfRange::Grow),
+            _ => {
+                return Err(Chip8Error::ConfigError(format!(
+                    "no quirk called \"{}\" (try {})",
+                    name,
+                    Self::FLAGS.join(", ")
+                )))
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks::VIP
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile() -> Result<(), Chip8Error> {
+        assert_eq!(Quirks::profile("vip")?, Quirks::default());
+        assert!(Quirks::profile("modern")?.shift_vx);
+        assert!(Quirks::profile("chip48")?.load_store_adds_x);
+        assert!(Quirks::profile("amiga").is_err());
+
+        // with extra quirks on top
+        let quirks = Quirks::profile("vip+add-i-sets-vf+mask-i")?;
+        assert!(quirks.add_i_sets_vf && quirks.mask_i && !quirks.shift_vx);
+        let mut flags = Quirks::VIP;
+        for flag in Quirks::FLAGS {
+            flags.turn_on(flag)?;
+        }
+        assert_eq!(
+            flags,
+            Quirks::profile(&format!("vip+{}", Quirks::FLAGS.join("+")))?
+        );
+        assert!(Quirks::profile("modern+amiga").is_err());
+        assert_eq!(
+            Quirks::profile("modern+wrap-memory")?.out_of_range,
+            OutOfRange::Wrap
+        );
+
+        // and back again
+        assert_eq!(Quirks::VIP.name().as_deref(), Some("vip"));
+        assert_eq!(Quirks::MODERN.name().as_deref(), Some("modern"));
+        assert_eq!(quirks.name().as_deref(), Some("vip+add-i-sets-vf+mask-i"));
+        let grown = Quirks::profile("chip48+grow-memory")?;
+        assert_eq!(Quirks::profile(&grown.name().unwrap())?, grown);
+        let mut odd = Quirks::MODERN;
+        odd.shift_vx = false;
+        assert_eq!(Quirks::profile(&odd.name().unwrap())?, odd);
+        Ok(())
+    }
+
+    #[test]
+    fn test_toggle() -> Result<(), Chip8Error> {
+        let mut quirks = Quirks::MODERN;
+        assert!(quirks.is_on("shift-vx")?);
+        assert!(!quirks.toggle("shift-vx")?);
+        assert!(!quirks.shift_vx);
+        assert!(quirks.toggle("shift-vx")?);
+        assert_eq!(quirks, Quirks::MODERN);
+
+        // growing memory takes over from wrapping it, and going back faults
+        assert!(quirks.toggle("wrap-memory")?);
+        assert!(quirks.toggle("grow-memory")?);
+        assert!(!quirks.is_on("wrap-memory")?);
+        assert!(!quirks.toggle("grow-memory")?);
+        assert_eq!(quirks.out_of_range, OutOfRange::Fault);
+        assert!(quirks.toggle("amiga").is_err());
+        Ok(())
+    }
+}
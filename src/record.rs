@@ -0,0 +1,653 @@
+use crate::clock::Clock;
+use crate::display::{Display, Hud, Theme};
+use crate::error::Chip8Error;
+use crate::heat::Heat;
+use crate::input::Focus;
+use crate::interrupt::RefreshRate;
+use crate::sound::Volume;
+use std::io;
+use std::ops::Range;
+use std::time::Duration;
+
+/// luma for lit and unlit pixels (video range)
+const Y4M_WHITE: u8 = 235;
+const Y4M_BLACK: u8 = 16;
+/// neutral chroma
+const Y4M_GREY: u8 = 128;
+
+/// records every frame the interpreter draws as a YUV4MPEG2 (.y4m) video,
+/// optionally passing it on to another display as well so a live run can be
+/// recorded. the interpreter draws once per emulated frame, so the video runs
+/// at exactly the refresh rate (60fps, unless it's been set otherwise) of
+/// emulated time and lines up with ToneRecorder's audio,
+/// e.g. `ffmpeg -i run.y4m -i run.wav run.mp4`. a video can't change size
+/// part way through, so if the resolution changes, the picture's scaled to
+/// fit the size it started at
+pub struct VideoRecorder<'a, W: io::Write> {
+    out: W,
+    width: usize,
+    height: usize,
+    // the video's size, in video pixels
+    video_width: usize,
+    video_height: usize,
+    // bitplanes, one after another in what's drawn
+    planes: usize,
+    inner: Option<&'a mut dyn Display>,
+    header_written: bool,
+    refresh_rate: RefreshRate,
+}
+
+impl<'a, W: io::Write> VideoRecorder<'a, W> {
+    /// width and height are the CHIP-8 resolution; scale is how many video
+    /// pixels to use for each CHIP-8 pixel
+    pub fn new(
+        out: W,
+        width: usize,
+        height: usize,
+        scale: usize,
+        inner: Option<&'a mut dyn Display>,
+    ) -> Self {
+        VideoRecorder {
+            out,
+            width,
+            height,
+            video_width: width * scale,
+            video_height: height * scale,
+            planes: 1,
+            inner,
+            header_written: false,
+            refresh_rate: RefreshRate::default(),
+        }
+    }
+
+    /// frames go by this often, e.g. 50Hz for a ROM written for a PAL
+    /// machine. only before the first frame's recorded
+    pub fn set_refresh_rate(&mut self, rate: RefreshRate) {
+        self.refresh_rate = rate;
+    }
+
+    fn write_frame(&mut self, data: &[u8]) -> Result<(), io::Error> {
+        let (w, h) = (self.video_width, self.video_height);
+        if !self.header_written {
+            // 4:2:0 chroma is the most widely supported, even though we don't
+            // have any colour to put in it
+            let (frames, seconds) = self.refresh_rate.ratio();
+            writeln!(
+                self.out,
+                "YUV4MPEG2 W{} H{} F{}:{} Ip A1:1 C420jpeg",
+                w, h, frames, seconds
+            )?;
+            self.header_written = true;
+        }
+        writeln!(self.out, "FRAME")?;
+
+        // luma plane, one row at a time: with more than one bitplane, the
+        // colours are greys, in order
+        let plane_size = self.width * self.height / 8;
+        let brightest = (1 << self.planes) - 1;
+        let mut row = vec![0u8; w];
+        for y in 0..h {
+            for (x, px) in row.iter_mut().enumerate() {
+                let n = (y * self.height / h) * self.width + x * self.width / w;
+                let colour = (0..self.planes)
+                    .map(|p| (1 & (data[p * plane_size + n / 8] >> (7 - n % 8))) << p)
+                    .sum::<u8>() as u32;
+                *px = Y4M_BLACK + ((Y4M_WHITE - Y4M_BLACK) as u32 * colour / brightest) as u8;
+            }
+            self.out.write_all(&row)?;
+        }
+
+        // both chroma planes
+        let chroma = vec![Y4M_GREY; w.div_ceil(2) * h.div_ceil(2)];
+        self.out.write_all(&chroma)?;
+        self.out.write_all(&chroma)?;
+        Ok(())
+    }
+}
+
+impl<'a, W: io::Write> Display for VideoRecorder<'a, W> {
+    fn draw(&mut self, data: &[u8]) -> Result<(), Chip8Error> {
+        self.draw_changes(data, None)
+    }
+
+    /// every frame gets recorded; the inner display can skip what's unchanged
+    fn draw_changes(
+        &mut self,
+        data: &[u8],
+        changed: Option<&[Range<usize>]>,
+    ) -> Result<(), Chip8Error> {
+        let size = self.width * self.height / 8 * self.planes;
+        if data.len() != size {
+            return Err(Chip8Error::DisplayError(format!(
+                "VideoRecorder must have correct-sized data to draw (got {} bytes, not {})",
+                data.len(),
+                size
+            )));
+        }
+        self.write_frame(data)?;
+        match &mut self.inner {
+            Some(d) => d.draw_changes(data, changed),
+            None => Ok(()),
+        }
+    }
+
+    fn get_display_size_bytes(&mut self) -> usize {
+        self.width * self.height / 8 * self.planes
+    }
+
+    fn set_planes(&mut self, planes: usize) -> Result<(), Chip8Error> {
+        if !(1..=4).contains(&planes) {
+            return Err(Chip8Error::DisplayError(format!(
+                "VideoRecorder can't record {} planes",
+                planes
+            )));
+        }
+        self.planes = planes;
+        match &mut self.inner {
+            Some(d) => d.set_planes(planes),
+            None => Ok(()),
+        }
+    }
+
+    fn set_mode(&mut self, width: usize, height: usize) -> Result<(), Chip8Error> {
+        self.width = width;
+        self.height = height;
+        match &mut self.inner {
+            Some(d) => d.set_mode(width, height),
+            None => Ok(()),
+        }
+    }
+
+    fn set_status(&mut self, status: &str) {
+        if let Some(d) = &mut self.inner {
+            d.set_status(status);
+        }
+    }
+
+    fn notify(&mut self, notice: &str) {
+        if let Some(d) = &mut self.inner {
+            d.notify(notice);
+        }
+    }
+
+    fn set_speed(&mut self, speed: f64) {
+        if let Some(d) = &mut self.inner {
+            d.set_speed(speed);
+        }
+    }
+
+    fn set_volume(&mut self, volume: Volume) {
+        if let Some(d) = &mut self.inner {
+            d.set_volume(volume);
+        }
+    }
+
+    fn set_hud(&mut self, hud: Option<Hud>) {
+        if let Some(d) = &mut self.inner {
+            d.set_hud(hud);
+        }
+    }
+
+    fn set_heat(&mut self, heat: Option<Heat>) {
+        if let Some(d) = &mut self.inner {
+            d.set_heat(heat);
+        }
+    }
+
+    fn set_help(&mut self, help: Option<Vec<String>>) {
+        if let Some(d) = &mut self.inner {
+            d.set_help(help);
+        }
+    }
+
+    fn set_slots(&mut self, slots: Option<Vec<String>>) {
+        if let Some(d) = &mut self.inner {
+            d.set_slots(slots);
+        }
+    }
+
+    fn set_palette(&mut self, palette: Option<Vec<String>>) {
+        if let Some(d) = &mut self.inner {
+            d.set_palette(palette);
+        }
+    }
+
+    fn set_theme(&mut self, theme: Theme) {
+        if let Some(d) = &mut self.inner {
+            d.set_theme(theme);
+        }
+    }
+
+    fn set_focus(&mut self, focus: Focus) {
+        if let Some(d) = &mut self.inner {
+            d.set_focus(focus);
+        }
+    }
+
+    fn refresh(&mut self) -> Result<(), Chip8Error> {
+        match &mut self.inner {
+            Some(d) => d.refresh(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// the first line of a display recording, to know one by
+const DISPLAY_RECORDING_HEADER: &str = "chip8 display recording";
+
+/// records what the interpreter gives the display to a file, as text: the
+/// mode and planes whenever they change, and every frame drawn, with how
+/// many were drawn before it. unlike a video, nothing's been drawn yet, so
+/// a DisplayRecording can play it into any other display (a new backend, to
+/// see it draws what the old one did) without running the interpreter.
+/// like VideoRecorder, it can pass everything on to another display as
+/// well, so a live run can be recorded
+pub struct RecordingDisplay<'a, W: io::Write> {
+    out: W,
+    width: usize,
+    height: usize,
+    planes: usize,
+    inner: Option<&'a mut dyn Display>,
+    header_written: bool,
+    // frames drawn so far
+    frames: u64,
+}
+
+impl<'a, W: io::Write> RecordingDisplay<'a, W> {
+    /// width and height are the resolution it starts in
+    pub fn new(out: W, width: usize, height: usize, inner: Option<&'a mut dyn Display>) -> Self {
+        RecordingDisplay {
+            out,
+            width,
+            height,
+            planes: 1,
+            inner,
+            header_written: false,
+            frames: 0,
+        }
+    }
+
+    /// the header, with the mode it's in to start with
+    fn header(&mut self) -> Result<(), io::Error> {
+        writeln!(self.out, "{}", DISPLAY_RECORDING_HEADER)?;
+        writeln!(self.out, "mode {} {}", self.width, self.height)?;
+        self.header_written = true;
+        Ok(())
+    }
+
+    /// one line of the recording, after the header if it's not there yet
+    fn record(&mut self, line: &str) -> Result<(), io::Error> {
+        if !self.header_written {
+            self.header()?;
+        }
+        writeln!(self.out, "{}", line)
+    }
+}
+
+impl<'a, W: io::Write> Display for RecordingDisplay<'a, W> {
+    fn draw(&mut self, data: &[u8]) -> Result<(), Chip8Error> {
+        self.draw_changes(data, None)
+    }
+
+    /// every frame gets recorded; the inner display can skip what's unchanged
+    fn draw_changes(
+        &mut self,
+        data: &[u8],
+        changed: Option<&[Range<usize>]>,
+    ) -> Result<(), Chip8Error> {
+        let size = self.width * self.height / 8 * self.planes;
+        if data.len() != size {
+            return Err(Chip8Error::DisplayError(format!(
+                "RecordingDisplay must have correct-sized data to draw (got {} bytes, not {})",
+                data.len(),
+                size
+            )));
+        }
+        let hex: String = data.iter().map(|b| format!("{:02x}", b)).collect();
+        self.record(&format!("frame {} {}", self.frames, hex))?;
+        self.out.flush()?;
+        self.frames += 1;
+        match &mut self.inner {
+            Some(d) => d.draw_changes(data, changed),
+            None => Ok(()),
+        }
+    }
+
+    fn get_display_size_bytes(&mut self) -> usize {
+        self.width * self.height / 8 * self.planes
+    }
+
+    fn set_planes(&mut self, planes: usize) -> Result<(), Chip8Error> {
+        self.planes = planes;
+        self.record(&format!("planes {}", planes))?;
+        match &mut self.inner {
+            Some(d) => d.set_planes(planes),
+            None => Ok(()),
+        }
+    }
+
+    fn set_mode(&mut self, width: usize, height: usize) -> Result<(), Chip8Error> {
+        self.width = width;
+        self.height = height;
+        // a mode before anything else is the one it starts in
+        match self.header_written {
+            true => self.record(&format!("mode {} {}", width, height))?,
+            false => self.header()?,
+        }
+        match &mut self.inner {
+            Some(d) => d.set_mode(width, height),
+            None => Ok(()),
+        }
+    }
+
+    fn set_status(&mut self, status: &str) {
+        if let Some(d) = &mut self.inner {
+            d.set_status(status);
+        }
+    }
+
+    fn notify(&mut self, notice: &str) {
+        if let Some(d) = &mut self.inner {
+            d.notify(notice);
+        }
+    }
+
+    fn set_speed(&mut self, speed: f64) {
+        if let Some(d) = &mut self.inner {
+            d.set_speed(speed);
+        }
+    }
+
+    fn set_volume(&mut self, volume: Volume) {
+        if let Some(d) = &mut self.inner {
+            d.set_volume(volume);
+        }
+    }
+
+    fn set_hud(&mut self, hud: Option<Hud>) {
+        if let Some(d) = &mut self.inner {
+            d.set_hud(hud);
+        }
+    }
+
+    fn set_heat(&mut self, heat: Option<Heat>) {
+        if let Some(d) = &mut self.inner {
+            d.set_heat(heat);
+        }
+    }
+
+    fn set_help(&mut self, help: Option<Vec<String>>) {
+        if let Some(d) = &mut self.inner {
+            d.set_help(help);
+        }
+    }
+
+    fn set_slots(&mut self, slots: Option<Vec<String>>) {
+        if let Some(d) = &mut self.inner {
+            d.set_slots(slots);
+        }
+    }
+
+    fn set_palette(&mut self, palette: Option<Vec<String>>) {
+        if let Some(d) = &mut self.inner {
+            d.set_palette(palette);
+        }
+    }
+
+    fn set_theme(&mut self, theme: Theme) {
+        if let Some(d) = &mut self.inner {
+            d.set_theme(theme);
+        }
+    }
+
+    fn set_focus(&mut self, focus: Focus) {
+        if let Some(d) = &mut self.inner {
+            d.set_focus(focus);
+        }
+    }
+
+    fn refresh(&mut self) -> Result<(), Chip8Error> {
+        match &mut self.inner {
+            Some(d) => d.refresh(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// one thing a RecordingDisplay recorded
+#[derive(Debug, Clone, PartialEq)]
+pub enum DisplayEvent {
+    /// width and height
+    Mode(usize, usize),
+    Planes(usize),
+    /// how many frames were drawn before it, and what was drawn
+    Frame(u64, Vec<u8>),
+}
+
+/// what a RecordingDisplay recorded, to play into another display
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DisplayRecording {
+    pub events: Vec<DisplayEvent>,
+}
+
+impl DisplayRecording {
+    pub fn read(r: impl io::BufRead) -> Result<Self, Chip8Error> {
+        let mut lines = r.lines();
+        if lines.next().transpose()?.as_deref() != Some(DISPLAY_RECORDING_HEADER) {
+            return Err(Chip8Error::ConfigError(
+                "that's not a display recording (try one from --record-display)".to_string(),
+            ));
+        }
+        let mut events = Vec::new();
+        for (n, line) in lines.enumerate() {
+            let line = line?;
+            let bad = || {
+                Chip8Error::ConfigError(format!(
+                    "line {} of the display recording doesn't make sense: \"{}\"",
+                    n + 2,
+                    line.chars().take(40).collect::<String>()
+                ))
+            };
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let number = |w: &str| w.parse::<usize>().map_err(|_| bad());
+            events.push(match words[..] {
+                [] => continue,
+                ["mode", w, h] => DisplayEvent::Mode(number(w)?, number(h)?),
+                ["planes", p] => DisplayEvent::Planes(number(p)?),
+                ["frame", i, hex] if hex.is_ascii() && hex.len() % 2 == 0 => DisplayEvent::Frame(
+                    number(i)? as u64,
+                    (0..hex.len())
+                        .step_by(2)
+                        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| bad()))
+                        .collect::<Result<_, _>>()?,
+                ),
+                _ => return Err(bad()),
+            });
+        }
+        Ok(DisplayRecording { events })
+    }
+
+    /// the frames drawn, and how many were before each
+    pub fn frames(&self) -> impl Iterator<Item = (u64, &[u8])> {
+        self.events.iter().filter_map(|e| match e {
+            DisplayEvent::Frame(i, data) => Some((*i, data.as_slice())),
+            _ => None,
+        })
+    }
+
+    /// put it all into display, as fast as it'll go, returning how many
+    /// frames it drew
+    pub fn play(&self, display: &mut dyn Display) -> Result<u64, Chip8Error> {
+        self.play_paced(display, None, RefreshRate::default())
+    }
+
+    /// put it all into display, each frame when it came (by how many came
+    /// before it) at rate, keeping time with clock, if there is one
+    pub fn play_paced(
+        &self,
+        display: &mut dyn Display,
+        clock: Option<&dyn Clock>,
+        rate: RefreshRate,
+    ) -> Result<u64, Chip8Error> {
+        let start = clock.map(|c| c.now());
+        let mut drawn = 0;
+        for event in &self.events {
+            match event {
+                DisplayEvent::Mode(w, h) => display.set_mode(*w, *h)?,
+                DisplayEvent::Planes(p) => display.set_planes(*p)?,
+                DisplayEvent::Frame(i, data) => {
+                    if let (Some(clock), Some(start)) = (clock, start) {
+                        let due = start + Duration::from_secs_f64(*i as f64 / rate.hz());
+                        clock.sleep(due.saturating_sub(clock.now()));
+                    }
+                    display.draw(data)?;
+                    drawn += 1;
+                }
+            }
+        }
+        Ok(drawn)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    #[test]
+    fn test_header_and_frame_size() -> Result<(), Chip8Error> {
+        let mut out = Vec::new();
+        let mut r = VideoRecorder::new(&mut out, 64, 32, 2, None);
+        r.draw(&[0; 256])?;
+        r.draw(&[0xff; 256])?;
+
+        let header = b"YUV4MPEG2 W128 H64 F60:1 Ip A1:1 C420jpeg\n";
+        assert_eq!(&out[..header.len()], header);
+        let frame = 6 + 128 * 64 + 2 * 64 * 32;
+        assert_eq!(out.len(), header.len() + 2 * frame);
+
+        let mut out = Vec::new();
+        let mut r = VideoRecorder::new(&mut out, 64, 32, 1, None);
+        r.set_refresh_rate(RefreshRate::PAL);
+        r.draw(&[0; 256])?;
+        assert!(out.starts_with(b"YUV4MPEG2 W64 H32 F50:1 "));
+        Ok(())
+    }
+
+    #[test]
+    fn test_pixels() -> Result<(), Chip8Error> {
+        let mut out = Vec::new();
+        let mut data = [0u8; 256];
+        data[0] = 0x80; // top-left pixel
+        let mut r = VideoRecorder::new(&mut out, 64, 32, 1, None);
+        r.draw(&data)?;
+
+        let luma = out.iter().position(|b| *b == b'\n').unwrap() + 1 + 6;
+        assert_eq!(out[luma], Y4M_WHITE);
+        assert_eq!(out[luma + 1], Y4M_BLACK);
+        Ok(())
+    }
+
+    #[test]
+    fn test_planes() -> Result<(), Chip8Error> {
+        let mut out = Vec::new();
+        let mut r = VideoRecorder::new(&mut out, 64, 32, 1, None);
+        r.set_planes(2)?;
+        assert_eq!(r.get_display_size_bytes(), 512);
+        assert!(r.draw(&[0; 256]).is_err());
+        // in the first plane, the second, and both
+        let mut data = [0u8; 512];
+        data[0] = 0xa0;
+        data[256] = 0x60;
+        r.draw(&data)?;
+        assert!(r.set_planes(0).is_err());
+
+        let luma = out.iter().position(|b| *b == b'\n').unwrap() + 1 + 6;
+        let grey = |c: u32| Y4M_BLACK + ((Y4M_WHITE - Y4M_BLACK) as u32 * c / 3) as u8;
+        assert_eq!(out[luma..luma + 4], [grey(1), grey(2), grey(3), Y4M_BLACK]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_change_mode() -> Result<(), Chip8Error> {
+        let mut out = Vec::new();
+        let mut r = VideoRecorder::new(&mut out, 64, 32, 2, None);
+        r.draw(&[0; 256])?;
+        // 128x64 frames fit the same 128x64 video, a pixel each
+        r.set_mode(128, 64)?;
+        let mut data = [0u8; 1024];
+        data[0] = 0x40; // second pixel along
+        r.draw(&data)?;
+        assert!(r.draw(&[0; 256]).is_err());
+
+        let frame = 6 + 128 * 64 + 2 * 64 * 32;
+        let luma = out.len() - frame + 6;
+        assert_eq!(out[luma..luma + 3], [Y4M_BLACK, Y4M_WHITE, Y4M_BLACK]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_wrong_size() {
+        let mut out = Vec::new();
+        let mut r = VideoRecorder::new(&mut out, 64, 32, 1, None);
+        assert!(r.draw(&[0; 10]).is_err());
+    }
+    #[test]
+    fn test_display_recording() -> Result<(), Chip8Error> {
+        let mut out = Vec::new();
+        let mut r = RecordingDisplay::new(&mut out, 64, 32, None);
+        let mut frame = [0u8; 256];
+        frame[0] = 0x80;
+        r.draw(&frame)?;
+        r.set_mode(128, 64)?;
+        assert!(r.draw(&frame).is_err());
+        r.draw(&[0xff; 1024])?;
+        let text = String::from_utf8_lossy(&out).to_string();
+        assert!(text.starts_with("chip8 display recording\nmode 64 32\nframe 0 8000"));
+
+        // played into another display, it records the same again
+        let recording = DisplayRecording::read(text.as_bytes())?;
+        assert_eq!(
+            recording.frames().map(|(i, _)| i).collect::<Vec<_>>(),
+            [0, 1]
+        );
+        let mut again = Vec::new();
+        let drawn = recording.play(&mut RecordingDisplay::new(&mut again, 64, 32, None))?;
+        assert_eq!(drawn, 2);
+        assert_eq!(String::from_utf8_lossy(&again), text);
+        Ok(())
+    }
+
+    #[test]
+    fn test_play_paced() -> Result<(), Chip8Error> {
+        let recording = DisplayRecording::read(
+            "chip8 display recording\nmode 8 1\nframe 0 00\nframe 30 ff\n".as_bytes(),
+        )?;
+        let mut out = Vec::new();
+        let clock = ManualClock::new();
+        let mut r = RecordingDisplay::new(&mut out, 64, 32, None);
+        assert_eq!(
+            recording.play_paced(&mut r, Some(&clock), RefreshRate::NTSC)?,
+            2
+        );
+        // the second came 30 frames, half a second, after the first
+        assert_eq!(clock.now(), Duration::from_millis(500));
+        Ok(())
+    }
+
+    #[test]
+    fn test_bad_recording() {
+        assert!(DisplayRecording::read("YUV4MPEG2".as_bytes()).is_err());
+        let bad = |line: &str| {
+            let text = format!("chip8 display recording\n{}\n", line);
+            DisplayRecording::read(text.as_bytes()).is_err()
+        };
+        assert!(bad("frame 0 0"));
+        assert!(bad("frame 0 zz"));
+        // not split in the middle of a character
+        assert!(bad("frame 0 aé0"));
+        assert!(bad("mode 64"));
+        assert!(bad("volume 11"));
+        assert!(!bad(""));
+    }
+}
@@ -0,0 +1,135 @@
+//! # getting over a panic
+//!
+//! nothing in the emulator should panic: anything a program can do wrong is
+//! a Chip8Error. but until the last of the unwraps has gone, one that does
+//! would leave the terminal in raw mode with half a frame on it, and the
+//! panic's message staircased across it. so the frontends run their frames
+//! through catch, which turns a panic into Chip8Error::Panicked: the
+//! terminal's put back as it goes, the machine from just before is kept for
+//! --resume-crash, and the message comes out readably at the end.
+//!
+//! --on-panic unwind leaves panics alone, backtrace and all, for whoever's
+//! debugging the emulator itself
+use crate::error::Chip8Error;
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::panic::{self, AssertUnwindSafe, PanicHookInfo};
+use std::sync::Once;
+
+/// what to do about a panic while running frames
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum PanicPolicy {
+    /// stop tidily, with an error
+    #[default]
+    Recover,
+    /// let it go, as if nothing had caught it
+    Unwind,
+}
+
+impl PanicPolicy {
+    pub fn parse(s: &str) -> Result<Self, Chip8Error> {
+        match s {
+            "recover" => Ok(PanicPolicy::Recover),
+            "unwind" => Ok(PanicPolicy::Unwind),
+            _ => Err(Chip8Error::ConfigError(format!(
+                "can't \"{}\" on a panic (try recover or unwind)",
+                s
+            ))),
+        }
+    }
+
+    /// f, with a panic in it dealt with as this says
+    pub fn run<T>(self, f: impl FnOnce() -> Result<T, Chip8Error>) -> Result<T, Chip8Error> {
+        match self {
+            PanicPolicy::Recover => catch(f),
+            PanicPolicy::Unwind => f(),
+        }
+    }
+}
+
+thread_local! {
+    /// whether this thread's inside catch, so its panics are ours to report
+    static CATCHING: Cell<bool> = const { Cell::new(false) };
+    /// what the last panic caught on this thread said, and where, from the
+    /// hook
+    static CAUGHT: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+static HOOKED: Once = Once::new();
+
+/// f, with a panic in it (on this thread) coming back as
+/// Chip8Error::Panicked rather than unwinding any further. the panic's
+/// message isn't printed as it happens, only kept for the error
+pub fn catch<T>(f: impl FnOnce() -> Result<T, Chip8Error>) -> Result<T, Chip8Error> {
+    HOOKED.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| match CATCHING.with(Cell::get) {
+            true => CAUGHT.with(|c| *c.borrow_mut() = Some(describe(info))),
+            // anyone else's panics are printed as they always were
+            false => previous(info),
+        }))
+    });
+    let was = CATCHING.with(|c| c.replace(true));
+    let run = panic::catch_unwind(AssertUnwindSafe(f));
+    CATCHING.with(|c| c.set(was));
+    run.unwrap_or_else(|payload| {
+        let caught = CAUGHT.with(|c| c.borrow_mut().take());
+        Err(Chip8Error::Panicked(
+            caught.unwrap_or_else(|| message(payload.as_ref())),
+        ))
+    })
+}
+
+/// what a panic said, as far as can be told: panic! with a literal gives a
+/// &str, and with arguments a String
+pub fn message(payload: &(dyn Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_default()
+}
+
+fn describe(info: &PanicHookInfo) -> String {
+    let said = message(info.payload());
+    match info.location() {
+        Some(l) => format!("{} at {}:{}", said, l.file(), l.line()),
+        None => said,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_catch() {
+        assert_eq!(catch(|| Ok(3)).ok(), Some(3));
+        assert!(matches!(
+            catch::<()>(|| Err(Chip8Error::Cancelled)),
+            Err(Chip8Error::Cancelled)
+        ));
+        let v: Vec<u8> = Vec::new();
+        match catch(|| Ok(v[1])) {
+            Err(Chip8Error::Panicked(s)) => {
+                assert!(s.starts_with("index out of bounds"), "{}", s);
+                assert!(s.contains("recover.rs"), "{}", s);
+            }
+            r => panic!("{:?}", r),
+        }
+        // and it's no longer catching afterwards
+        assert!(!CATCHING.with(Cell::get));
+    }
+
+    #[test]
+    fn test_parse() -> Result<(), Chip8Error> {
+        assert_eq!(PanicPolicy::parse("recover")?, PanicPolicy::default());
+        assert_eq!(PanicPolicy::parse("unwind")?, PanicPolicy::Unwind);
+        assert!(PanicPolicy::parse("ignore").is_err());
+        assert!(matches!(
+            PanicPolicy::Recover.run::<()>(|| panic!("oops")),
+            Err(Chip8Error::Panicked(_))
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,473 @@
+//! # rendering on another thread
+//!
+//! a slow terminal (or encoder) holds up whatever calls draw, and in the
+//! interpreter that's the ISR, so the emulation falls behind. ThreadedDisplay
+//! hands copies of each frame to a thread of its own instead, through a
+//! short queue. when the queue's full the frame is dropped: the interpreter
+//! never waits for the picture. the real display is built on the render
+//! thread, so it doesn't have to be Send
+//!
+//! nor does it wait for anything else for long. a display can get stuck
+//! part way through drawing (a terminal that's stopped reading, or a pipe
+//! to a remote one that's full), and everything but frames waits for room
+//! in the queue. so the render thread says when it started on what it's
+//! doing, and once that's been too long ago, it's taken to be stuck: with
+//! a warning, frames are skipped rather than queued, and everything else
+//! is kept back until it's drawing again
+use crate::display::{Display, Hud, Theme};
+use crate::error::Chip8Error;
+use crate::heat::Heat;
+use crate::input::Focus;
+use crate::lang;
+use crate::sound::Volume;
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// how long the render thread can spend on one thing before it's stuck
+const RENDER_STALL: Duration = Duration::from_millis(250);
+
+/// how often to look for room in the queue, while waiting for it
+const RENDER_POLL: Duration = Duration::from_millis(1);
+
+/// when the render thread started on what it's doing, or None if it's
+/// waiting for something to do
+type Busy = Arc<Mutex<Option<Instant>>>;
+
+fn lock(busy: &Busy) -> MutexGuard<'_, Option<Instant>> {
+    busy.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// what's been held back while the render thread's stuck
+#[derive(Default)]
+struct Stalled {
+    backlog: Vec<Command>,
+    skipped: u64,
+}
+
+impl Stalled {
+    /// keep command back. only the last of each setting's worth sending
+    /// (or it'd pile up for as long as it's stuck, e.g. the HUD's every
+    /// frame), but every notification's something to say
+    fn hold(&mut self, command: Command) {
+        if !matches!(command, Command::Notify(_)) {
+            let kind = std::mem::discriminant(&command);
+            self.backlog.retain(|c| std::mem::discriminant(c) != kind);
+        }
+        self.backlog.push(command);
+    }
+}
+
+/// what the render thread gets asked to do
+enum Command {
+    Draw(Vec<u8>),
+    SetMode(usize, usize),
+    SetPlanes(usize),
+    SetStatus(String),
+    Notify(String),
+    SetSpeed(f64),
+    SetVolume(Volume),
+    // boxed, as it's a lot bigger than anything else here
+    SetHud(Option<Box<Hud>>),
+    SetHeat(Option<Box<Heat>>),
+    SetHelp(Option<Vec<String>>),
+    SetSlots(Option<Vec<String>>),
+    SetPalette(Option<Vec<String>>),
+    SetTheme(Theme),
+    SetFocus(Focus),
+    Refresh,
+}
+
+/// passes everything on to a display running on its own thread
+pub struct ThreadedDisplay {
+    commands: Option<SyncSender<Command>>,
+    thread: Option<JoinHandle<Result<(), Chip8Error>>>,
+    size_bytes: usize,
+    planes: usize,
+    busy: Busy,
+    stall: Duration,
+    stalled: Option<Stalled>,
+}
+
+impl ThreadedDisplay {
+    /// start a render thread, build a display on it with make, and queue up
+    /// to queue frames for it
+    pub fn spawn<D, F>(make: F, queue: usize) -> Result<Self, Chip8Error>
+    where
+        D: Display,
+        F: FnOnce() -> Result<D, Chip8Error> + Send + 'static,
+    {
+        let (commands, received) = mpsc::sync_channel(queue);
+        let (made, size) = mpsc::channel();
+        let busy = Busy::default();
+        let working = busy.clone();
+        let thread = thread::spawn(move || {
+            let mut display = match make() {
+                Ok(mut d) => {
+                    // nothing to do if we've already been given up on
+                    let _ = made.send(Ok(d.get_display_size_bytes()));
+                    d
+                }
+                Err(e) => {
+                    let _ = made.send(Err(e));
+                    return Ok(());
+                }
+            };
+            render(&mut display, received, &working)
+        });
+        let size_bytes = size.recv().map_err(|_| {
+            Chip8Error::DisplayError("the render thread died starting up".to_string())
+        })??;
+        Ok(ThreadedDisplay {
+            commands: Some(commands),
+            thread: Some(thread),
+            size_bytes,
+            planes: 1,
+            busy,
+            stall: RENDER_STALL,
+            stalled: None,
+        })
+    }
+
+    /// take the render thread to be stuck after this long on one thing
+    pub fn stall_after(mut self, stall: Duration) -> Self {
+        self.stall = stall;
+        self
+    }
+
+    /// how long the render thread's been stuck, if it has
+    fn stuck_for(&self) -> Option<Duration> {
+        lock(&self.busy)
+            .map(|since| since.elapsed())
+            .filter(|t| *t >= self.stall)
+    }
+
+    /// send command, waiting for room if wait, otherwise dropping it. while
+    /// the render thread's stuck, frames are dropped, and the rest kept
+    /// back for when it's not
+    fn send(&mut self, command: Command, wait: bool) -> Result<(), Chip8Error> {
+        match (&self.stalled, self.stuck_for()) {
+            (None, Some(t)) => self.stall(t),
+            (Some(_), None) => self.recover()?,
+            _ => {}
+        }
+        if let Some(stalled) = &mut self.stalled {
+            match command {
+                Command::Draw(_) => stalled.skipped += 1,
+                c if wait => stalled.hold(c),
+                _ => {}
+            }
+            return Ok(());
+        }
+        let mut command = command;
+        loop {
+            let Some(c) = &self.commands else {
+                return Err(self.stopped());
+            };
+            match c.try_send(command) {
+                Ok(()) => return Ok(()),
+                Err(TrySendError::Disconnected(_)) => return Err(self.stopped()),
+                Err(TrySendError::Full(_)) if !wait => return Ok(()),
+                Err(TrySendError::Full(unsent)) => match self.stuck_for() {
+                    Some(t) => {
+                        self.stall(t);
+                        self.stalled.get_or_insert_default().hold(unsent);
+                        return Ok(());
+                    }
+                    None => {
+                        command = unsent;
+                        thread::sleep(RENDER_POLL);
+                    }
+                },
+            }
+        }
+    }
+
+    /// the render thread's stuck
+    fn stall(&mut self, stuck_for: Duration) {
+        eprintln!(
+            "{}",
+            lang::format("warning.display-stalled", &[&stuck_for.as_millis()])
+        );
+        self.stalled = Some(Stalled::default());
+    }
+
+    /// the render thread's come unstuck, so catch it up on what it's missed
+    fn recover(&mut self) -> Result<(), Chip8Error> {
+        let Some(stalled) = self.stalled.take() else {
+            return Ok(());
+        };
+        eprintln!(
+            "{}",
+            lang::format("warning.display-recovered", &[&stalled.skipped])
+        );
+        for command in stalled.backlog {
+            // which could get it stuck again, and keep the rest back again
+            self.send(command, true)?;
+        }
+        Ok(())
+    }
+
+    /// why the render thread's stopped
+    fn stopped(&mut self) -> Chip8Error {
+        self.stop().err().unwrap_or_else(|| {
+            Chip8Error::DisplayError("the render thread has stopped".to_string())
+        })
+    }
+
+    /// finish drawing what's queued, then stop the render thread
+    pub fn stop(&mut self) -> Result<(), Chip8Error> {
+        if self.stalled.is_some() && self.stuck_for().is_none() {
+            self.recover()?;
+        }
+        self.commands = None;
+        // it might never come unstuck, and waiting for it would get us
+        // stuck too, so it's left to it
+        if self.stuck_for().is_some() {
+            self.thread = None;
+            return Ok(());
+        }
+        match self.thread.take().map(|t| t.join()) {
+            Some(Ok(result)) => result,
+            Some(Err(_)) => Err(Chip8Error::DisplayError(
+                "the render thread panicked".to_string(),
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+/// the render thread's loop: carry out commands until there are no more,
+/// saying when it started on each
+fn render(
+    display: &mut dyn Display,
+    commands: Receiver<Command>,
+    busy: &Busy,
+) -> Result<(), Chip8Error> {
+    for command in commands {
+        *lock(busy) = Some(Instant::now());
+        match command {
+            Command::Draw(frame) => display.draw(&frame)?,
+            Command::SetMode(width, height) => display.set_mode(width, height)?,
+            Command::SetPlanes(planes) => display.set_planes(planes)?,
+            Command::SetStatus(status) => display.set_status(&status),
+            Command::Notify(notice) => display.notify(&notice),
+            Command::SetSpeed(speed) => display.set_speed(speed),
+            Command::SetVolume(volume) => display.set_volume(volume),
+            Command::SetHud(hud) => display.set_hud(hud.map(|h| *h)),
+            Command::SetHeat(heat) => display.set_heat(heat.map(|h| *h)),
+            Command::SetHelp(help) => display.set_help(help),
+            Command::SetSlots(slots) => display.set_slots(slots),
+            Command::SetPalette(palette) => display.set_palette(palette),
+            Command::SetTheme(theme) => display.set_theme(theme),
+            Command::SetFocus(focus) => display.set_focus(focus),
+            Command::Refresh => display.refresh()?,
+        }
+        *lock(busy) = None;
+    }
+    Ok(())
+}
+
+impl Display for ThreadedDisplay {
+    fn draw(&mut self, data: &[u8]) -> Result<(), Chip8Error> {
+        self.send(Command::Draw(data.to_vec()), false)
+    }
+
+    fn get_display_size_bytes(&mut self) -> usize {
+        self.size_bytes
+    }
+
+    fn set_mode(&mut self, width: usize, height: usize) -> Result<(), Chip8Error> {
+        self.size_bytes = width * height / 8 * self.planes;
+        self.send(Command::SetMode(width, height), true)
+    }
+
+    fn set_planes(&mut self, planes: usize) -> Result<(), Chip8Error> {
+        self.size_bytes = self.size_bytes / self.planes * planes;
+        self.planes = planes;
+        self.send(Command::SetPlanes(planes), true)
+    }
+
+    fn set_status(&mut self, status: &str) {
+        // errors turn up on the next draw
+        let _ = self.send(Command::SetStatus(status.to_string()), true);
+    }
+
+    fn notify(&mut self, notice: &str) {
+        let _ = self.send(Command::Notify(notice.to_string()), true);
+    }
+
+    fn set_speed(&mut self, speed: f64) {
+        let _ = self.send(Command::SetSpeed(speed), true);
+    }
+
+    fn set_volume(&mut self, volume: Volume) {
+        let _ = self.send(Command::SetVolume(volume), true);
+    }
+
+    fn set_hud(&mut self, hud: Option<Hud>) {
+        // it's sent every frame, so like a frame it can be dropped, but
+        // taking it away mustn't be
+        let wait = hud.is_none();
+        let _ = self.send(Command::SetHud(hud.map(Box::new)), wait);
+    }
+
+    fn set_heat(&mut self, heat: Option<Heat>) {
+        // the same goes for the heat
+        let wait = heat.is_none();
+        let _ = self.send(Command::SetHeat(heat.map(Box::new)), wait);
+    }
+
+    fn set_help(&mut self, help: Option<Vec<String>>) {
+        let _ = self.send(Command::SetHelp(help), true);
+    }
+
+    fn set_slots(&mut self, slots: Option<Vec<String>>) {
+        let _ = self.send(Command::SetSlots(slots), true);
+    }
+
+    fn set_palette(&mut self, palette: Option<Vec<String>>) {
+        let _ = self.send(Command::SetPalette(palette), true);
+    }
+
+    fn set_theme(&mut self, theme: Theme) {
+        let _ = self.send(Command::SetTheme(theme), true);
+    }
+
+    fn set_focus(&mut self, focus: Focus) {
+        let _ = self.send(Command::SetFocus(focus), true);
+    }
+
+    fn refresh(&mut self) -> Result<(), Chip8Error> {
+        self.send(Command::Refresh, true)
+    }
+}
+
+impl Drop for ThreadedDisplay {
+    fn drop(&mut self) {
+        // nothing useful to do with an error now
+        let _ = self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::fixtures::Frames;
+    use std::sync::{Arc, Mutex};
+
+    /// can't draw anything
+    struct Broken;
+
+    impl Display for Broken {
+        fn draw(&mut self, _data: &[u8]) -> Result<(), Chip8Error> {
+            Err(Chip8Error::DisplayError("broken".to_string()))
+        }
+
+        fn get_display_size_bytes(&mut self) -> usize {
+            0x100
+        }
+
+        fn set_mode(&mut self, _width: usize, _height: usize) -> Result<(), Chip8Error> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_draws_on_its_own_thread() -> Result<(), Chip8Error> {
+        let frames = Frames::default();
+        let theirs = frames.clone();
+        let mut d = ThreadedDisplay::spawn(move || Ok(theirs), 100)?;
+        assert_eq!(d.get_display_size_bytes(), 0x100);
+        for n in 0..10 {
+            d.draw(&[n])?;
+        }
+        d.set_mode(128, 64)?;
+        assert_eq!(d.get_display_size_bytes(), 0x400);
+        d.stop()?;
+        let firsts: Vec<u8> = frames.drawn().iter().map(|f| f[0]).collect();
+        assert_eq!(firsts, (0..10).collect::<Vec<u8>>());
+        Ok(())
+    }
+
+    /// draws whatever it's given, but not a 0x01 until it's told to
+    struct Stuck(Arc<Mutex<Vec<u8>>>, Receiver<()>);
+
+    impl Display for Stuck {
+        fn draw(&mut self, data: &[u8]) -> Result<(), Chip8Error> {
+            if data[0] == 0x01 {
+                let _ = self.1.recv();
+            }
+            self.0.lock().unwrap().push(data[0]);
+            Ok(())
+        }
+
+        fn get_display_size_bytes(&mut self) -> usize {
+            0x100
+        }
+
+        fn set_mode(&mut self, _width: usize, _height: usize) -> Result<(), Chip8Error> {
+            Ok(())
+        }
+
+        fn set_status(&mut self, status: &str) {
+            self.0.lock().unwrap().push(status.len() as u8);
+        }
+    }
+
+    #[test]
+    fn test_stuck() -> Result<(), Chip8Error> {
+        let frames = Arc::new(Mutex::new(vec![]));
+        let theirs = frames.clone();
+        let (unstick, stuck) = mpsc::channel();
+        let mut d = ThreadedDisplay::spawn(move || Ok(Stuck(theirs, stuck)), 1)?
+            .stall_after(Duration::from_millis(20));
+        d.draw(&[0x01])?;
+        // frames are dropped, and a status waits for room, but not for ever
+        let started = Instant::now();
+        for n in 1..=20 {
+            d.draw(&[0x02])?;
+            d.set_status(&"s".repeat(n));
+        }
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(d.stalled.as_ref().is_some_and(|s| s.skipped > 0));
+        // and only the last status is kept back, however many there were
+        assert_eq!(d.stalled.as_ref().map(|s| s.backlog.len()), Some(1));
+
+        unstick.send(()).unwrap();
+        while d.stuck_for().is_some() || lock(&d.busy).is_some() {
+            thread::sleep(RENDER_POLL);
+        }
+        d.draw(&[0x03])?;
+        assert!(d.stalled.is_none());
+        d.stop()?;
+        let frames = frames.lock().unwrap();
+        assert_eq!(frames.first(), Some(&0x01));
+        // the last status wasn't lost
+        assert!(frames.contains(&20));
+        Ok(())
+    }
+
+    #[test]
+    fn test_errors_come_back() -> Result<(), Chip8Error> {
+        let mut d = ThreadedDisplay::spawn(|| Ok(Broken), 1)?;
+        d.draw(&[0xff])?;
+        // sooner or later, the render thread's failure shows up
+        let mut result = Ok(());
+        for _ in 0..1000 {
+            result = d.refresh();
+            if result.is_err() {
+                break;
+            }
+        }
+        assert!(matches!(result, Err(Chip8Error::DisplayError(e)) if e == "broken"));
+
+        let failed = ThreadedDisplay::spawn(
+            || -> Result<Broken, Chip8Error> { Err(Chip8Error::DisplayError("nope".into())) },
+            1,
+        );
+        assert!(failed.is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,614 @@
+use crate::display::{Display, Hud, Theme};
+use crate::error::Chip8Error;
+use crate::heat::Heat;
+use crate::input::{Feedback, Focus, Input, SpeedRequest, VolumeRequest};
+use crate::keypad::KeyTransition;
+use crate::sound::Volume;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::ops::Range;
+use std::time::Duration;
+
+/// first line of every replay file
+const REPLAY_MAGIC: &str = "chip8-replay 1";
+
+/// FNV-1a hash of a frame's display data; cheap, and good enough to spot two
+/// runs drifting apart
+pub fn frame_hash(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |h, b| {
+        (h ^ *b as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// what happened in one frame of a recorded run
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ReplayFrame {
+    /// key latched at the start of the frame
+    pub key: Option<u8>,
+    /// frame_hash of what got drawn
+    pub hash: u64,
+}
+
+/// everything needed to play a run back deterministically, plus frame
+/// hashes to check that it really did play back the same
+#[derive(Debug, PartialEq)]
+pub struct Replay {
+    pub seed: u16,
+    pub frames: Vec<ReplayFrame>,
+}
+
+impl Replay {
+    /// stitch together what RecordingInput and FrameHasher captured
+    pub fn from_recording(seed: u16, keys: &[Option<u8>], hashes: &[u64]) -> Self {
+        Replay {
+            seed,
+            frames: keys
+                .iter()
+                .zip(hashes)
+                .map(|(key, hash)| ReplayFrame {
+                    key: *key,
+                    hash: *hash,
+                })
+                .collect(),
+        }
+    }
+
+    /// the keys to feed to a PlaybackInput
+    pub fn keys(&self) -> Vec<Option<u8>> {
+        self.frames.iter().map(|f| f.key).collect()
+    }
+
+    /// the first frame at which a playback's hashes differ from the
+    /// recording's, if any. running out of frames early counts as diverging
+    pub fn first_divergence(&self, hashes: &[u64]) -> Option<usize> {
+        self.frames
+            .iter()
+            .zip(hashes)
+            .position(|(f, h)| f.hash != *h)
+            .or(if hashes.len() < self.frames.len() {
+                Some(hashes.len())
+            } else {
+                None
+            })
+    }
+
+    /// write as text: a header, the seed, then one "key hash" line per frame
+    pub fn write(&self, w: &mut impl io::Write) -> Result<(), Chip8Error> {
+        write_header(w, self.seed)?;
+        for f in &self.frames {
+            write_frame(w, f)?;
+        }
+        Ok(())
+    }
+
+    pub fn read(r: impl io::BufRead) -> Result<Self, Chip8Error> {
+        let mut lines = r.lines();
+        let seed = read_header(&mut lines)?;
+        let mut frames = Vec::new();
+        for (n, line) in lines.enumerate() {
+            frames.push(parse_frame(&line?, n + 3)?);
+        }
+        Ok(Replay { seed, frames })
+    }
+}
+
+/// just the keys of a recording, and the seed they go with: enough to play
+/// a run back without checking it as it goes, and small enough to keep in
+/// the config for attract mode to play. it has to be played back with the
+/// settings it was recorded with, as ever
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Demo {
+    pub seed: u16,
+    /// the key latched each frame as a hex digit, or - for none
+    pub keys: String,
+}
+
+impl Demo {
+    pub fn new(seed: u16, keys: &[Option<u8>]) -> Self {
+        Demo {
+            seed,
+            keys: keys
+                .iter()
+                .map(|k| match k {
+                    Some(k) => char::from_digit(*k as u32, 16).unwrap_or('-'),
+                    None => '-',
+                })
+                .collect(),
+        }
+    }
+
+    /// the keys to feed to a PlaybackInput
+    pub fn keys(&self) -> Result<Vec<Option<u8>>, Chip8Error> {
+        self.keys
+            .chars()
+            .map(|c| match c {
+                '-' => Ok(None),
+                c => c.to_digit(16).map(|k| Some(k as u8)).ok_or_else(|| {
+                    Chip8Error::ConfigError(format!("a demo can't have {:?} for a key", c))
+                }),
+            })
+            .collect()
+    }
+}
+
+impl From<&Replay> for Demo {
+    fn from(replay: &Replay) -> Self {
+        Demo::new(replay.seed, &replay.keys())
+    }
+}
+
+fn bad_line(line: usize) -> Chip8Error {
+    Chip8Error::ConfigError(format!("bad replay file at line {}", line))
+}
+
+pub(crate) fn write_header(w: &mut impl io::Write, seed: u16) -> Result<(), Chip8Error> {
+    writeln!(w, "{}", REPLAY_MAGIC)?;
+    writeln!(w, "seed {:04x}", seed)?;
+    Ok(())
+}
+
+pub(crate) fn write_frame(w: &mut impl io::Write, f: &ReplayFrame) -> Result<(), Chip8Error> {
+    match f.key {
+        Some(k) => write!(w, "{:x}", k)?,
+        None => write!(w, "-")?,
+    }
+    writeln!(w, " {:016x}", f.hash)?;
+    Ok(())
+}
+
+/// check the magic and get the seed
+pub(crate) fn read_header(
+    lines: &mut impl Iterator<Item = io::Result<String>>,
+) -> Result<u16, Chip8Error> {
+    if lines.next().transpose()?.as_deref() != Some(REPLAY_MAGIC) {
+        return Err(bad_line(1));
+    }
+    match lines.next().transpose()? {
+        Some(l) => l
+            .strip_prefix("seed ")
+            .and_then(|s| u16::from_str_radix(s, 16).ok())
+            .ok_or_else(|| bad_line(2)),
+        None => Err(bad_line(2)),
+    }
+}
+
+/// `line` is the line number, for error messages
+pub(crate) fn parse_frame(s: &str, line: usize) -> Result<ReplayFrame, Chip8Error> {
+    let (key, hash) = s.split_once(' ').ok_or_else(|| bad_line(line))?;
+    let key = match key {
+        "-" => None,
+        k => Some(u8::from_str_radix(k, 16).map_err(|_| bad_line(line))?),
+    };
+    let hash = u64::from_str_radix(hash, 16).map_err(|_| bad_line(line))?;
+    Ok(ReplayFrame { key, hash })
+}
+
+/// wraps another Input, sampling it once a frame so that what the
+/// interpreter sees can be recorded and played back exactly. savestate
+/// slots don't get through: a replay can't follow a jump to another state.
+/// nor does the command palette, which can do that too, or change a quirk
+pub struct RecordingInput<'a> {
+    inner: &'a mut dyn Input,
+    latched_key: Option<u8>,
+    keys: Vec<Option<u8>>,
+    limit: usize,
+}
+
+impl<'a> RecordingInput<'a> {
+    pub fn new(inner: &'a mut dyn Input) -> Self {
+        RecordingInput {
+            inner,
+            latched_key: None,
+            keys: Vec::new(),
+            limit: usize::MAX,
+        }
+    }
+
+    /// only keep the first frames' keys, for a demo that won't be played
+    /// for any longer than that. the keys still get through after
+    pub fn up_to(mut self, frames: usize) -> Self {
+        self.limit = frames;
+        self
+    }
+
+    /// the key latched at the start of each frame so far
+    pub fn keys(&self) -> &[Option<u8>] {
+        &self.keys
+    }
+}
+
+impl<'a> Input for RecordingInput<'a> {
+    fn flush_keys(&mut self) -> Result<(), Chip8Error> {
+        self.latched_key = None;
+        self.inner.flush_keys()
+    }
+
+    fn read_key(&mut self) -> Result<Option<u8>, Chip8Error> {
+        Ok(self.latched_key)
+    }
+
+    fn tick(&mut self) -> Result<(), Chip8Error> {
+        self.inner.tick()?;
+        self.latched_key = self.inner.read_key()?;
+        if self.keys.len() < self.limit {
+            self.keys.push(self.latched_key);
+        }
+        Ok(())
+    }
+
+    fn wait_for_event(&mut self, timeout: Duration) -> Result<bool, Chip8Error> {
+        self.inner.wait_for_event(timeout)
+    }
+
+    fn transitions(&self) -> &[KeyTransition] {
+        self.inner.transitions()
+    }
+
+    fn take_menu_request(&mut self) -> Result<bool, Chip8Error> {
+        self.inner.take_menu_request()
+    }
+
+    fn take_speed_request(&mut self) -> Result<Option<SpeedRequest>, Chip8Error> {
+        self.inner.take_speed_request()
+    }
+
+    fn take_hud_toggle(&mut self) -> Result<bool, Chip8Error> {
+        self.inner.take_hud_toggle()
+    }
+
+    fn take_help_toggle(&mut self) -> Result<bool, Chip8Error> {
+        self.inner.take_help_toggle()
+    }
+
+    fn take_volume_request(&mut self) -> Result<Option<VolumeRequest>, Chip8Error> {
+        self.inner.take_volume_request()
+    }
+
+    fn focus(&self) -> Focus {
+        self.inner.focus()
+    }
+
+    fn set_focus(&mut self, focus: Focus) {
+        self.inner.set_focus(focus)
+    }
+
+    fn feedback(&mut self, feedback: Feedback) {
+        self.inner.feedback(feedback)
+    }
+
+    fn out_of_keys(&self) -> bool {
+        self.latched_key.is_none() && self.inner.out_of_keys()
+    }
+}
+
+/// plays back keys captured by a RecordingInput, one per frame
+pub struct PlaybackInput {
+    keys: Vec<Option<u8>>,
+    frame: usize,
+    latched_key: Option<u8>,
+}
+
+impl PlaybackInput {
+    pub fn new(keys: Vec<Option<u8>>) -> Self {
+        PlaybackInput {
+            keys,
+            frame: 0,
+            latched_key: None,
+        }
+    }
+}
+
+impl Input for PlaybackInput {
+    fn flush_keys(&mut self) -> Result<(), Chip8Error> {
+        self.latched_key = None;
+        Ok(())
+    }
+
+    fn read_key(&mut self) -> Result<Option<u8>, Chip8Error> {
+        Ok(self.latched_key)
+    }
+
+    fn tick(&mut self) -> Result<(), Chip8Error> {
+        self.latched_key = self.keys.get(self.frame).copied().flatten();
+        self.frame += 1;
+        Ok(())
+    }
+
+    fn out_of_keys(&self) -> bool {
+        // (a key held to the end counts as one still to come)
+        self.latched_key.is_none() && self.keys.iter().skip(self.frame).all(Option::is_none)
+    }
+}
+
+/// hashes every frame that gets drawn, optionally passing it on to another
+/// display
+pub struct FrameHasher<'a> {
+    inner: Option<&'a mut dyn Display>,
+    hashes: Vec<u64>,
+}
+
+impl<'a> FrameHasher<'a> {
+    pub fn new(inner: Option<&'a mut dyn Display>) -> Self {
+        FrameHasher {
+            inner,
+            hashes: Vec::new(),
+        }
+    }
+
+    pub fn hashes(&self) -> &[u64] {
+        &self.hashes
+    }
+}
+
+impl<'a> Display for FrameHasher<'a> {
+    fn draw(&mut self, data: &[u8]) -> Result<(), Chip8Error> {
+        self.draw_changes(data, None)
+    }
+
+    fn draw_changes(
+        &mut self,
+        data: &[u8],
+        changed: Option<&[Range<usize>]>,
+    ) -> Result<(), Chip8Error> {
+        self.hashes.push(frame_hash(data));
+        match &mut self.inner {
+            Some(d) => d.draw_changes(data, changed),
+            None => Ok(()),
+        }
+    }
+
+    fn get_display_size_bytes(&mut self) -> usize {
+        match &mut self.inner {
+            Some(d) => d.get_display_size_bytes(),
+            None => 0x100,
+        }
+    }
+
+    fn set_mode(&mut self, width: usize, height: usize) -> Result<(), Chip8Error> {
+        match &mut self.inner {
+            Some(d) => d.set_mode(width, height),
+            None => Ok(()),
+        }
+    }
+
+    fn set_planes(&mut self, planes: usize) -> Result<(), Chip8Error> {
+        match &mut self.inner {
+            Some(d) => d.set_planes(planes),
+            None => Ok(()),
+        }
+    }
+
+    fn set_status(&mut self, status: &str) {
+        if let Some(d) = &mut self.inner {
+            d.set_status(status);
+        }
+    }
+
+    fn notify(&mut self, notice: &str) {
+        if let Some(d) = &mut self.inner {
+            d.notify(notice);
+        }
+    }
+
+    fn set_speed(&mut self, speed: f64) {
+        if let Some(d) = &mut self.inner {
+            d.set_speed(speed);
+        }
+    }
+
+    fn set_volume(&mut self, volume: Volume) {
+        if let Some(d) = &mut self.inner {
+            d.set_volume(volume);
+        }
+    }
+
+    fn set_hud(&mut self, hud: Option<Hud>) {
+        if let Some(d) = &mut self.inner {
+            d.set_hud(hud);
+        }
+    }
+
+    fn set_heat(&mut self, heat: Option<Heat>) {
+        if let Some(d) = &mut self.inner {
+            d.set_heat(heat);
+        }
+    }
+
+    fn set_help(&mut self, help: Option<Vec<String>>) {
+        if let Some(d) = &mut self.inner {
+            d.set_help(help);
+        }
+    }
+
+    fn set_slots(&mut self, slots: Option<Vec<String>>) {
+        if let Some(d) = &mut self.inner {
+            d.set_slots(slots);
+        }
+    }
+
+    fn set_palette(&mut self, palette: Option<Vec<String>>) {
+        if let Some(d) = &mut self.inner {
+            d.set_palette(palette);
+        }
+    }
+
+    fn set_theme(&mut self, theme: Theme) {
+        if let Some(d) = &mut self.inner {
+            d.set_theme(theme);
+        }
+    }
+
+    fn set_focus(&mut self, focus: Focus) {
+        if let Some(d) = &mut self.inner {
+            d.set_focus(focus);
+        }
+    }
+
+    fn refresh(&mut self) -> Result<(), Chip8Error> {
+        match &mut self.inner {
+            Some(d) => d.refresh(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::{DummyInput, Typed};
+    use crate::interpreter::Chip8Interpreter;
+    use crate::sound::Mute;
+
+    /// run a program that draws random junk, returning its frame hashes
+    fn run_random(seed: u16, keys: Vec<Option<u8>>) -> Result<Vec<u64>, Chip8Error> {
+        let mut hasher = FrameHasher::new(None);
+        let mut input = PlaybackInput::new(keys);
+        let mut sound = Mute::new();
+        let mut i = Chip8Interpreter::new(&mut hasher, &mut input, &mut sound)?;
+        i.set_seed(seed);
+        // 0200: c0ff  V0 = rand
+        // 0202: 6100  V1 = 0
+        // 0204: f029  I = sprite for V0
+        // 0206: d115  draw
+        // 0208: 1200  loop
+        let program = [0xc0, 0xff, 0x61, 0x00, 0xf0, 0x29, 0xd1, 0x15, 0x12, 0x00];
+        i.load_program(&mut program.as_slice())?;
+        i.run_frames(10)?;
+        drop(i);
+        Ok(hasher.hashes().to_vec())
+    }
+
+    #[test]
+    fn test_playback_is_deterministic() -> Result<(), Chip8Error> {
+        let hashes = run_random(0x1234, vec![])?;
+        let replay = Replay::from_recording(0x1234, &[None; 10], &hashes);
+        assert_eq!(
+            replay.first_divergence(&run_random(0x1234, replay.keys())?),
+            None
+        );
+        assert!(replay
+            .first_divergence(&run_random(0x4321, replay.keys())?)
+            .is_some());
+        Ok(())
+    }
+
+    fn test_replay() -> Replay {
+        Replay::from_recording(
+            0xbeef,
+            &[None, Some(0xa), None],
+            &[1, 2, 0xffff_ffff_ffff_ffff],
+        )
+    }
+
+    #[test]
+    fn test_frame_hash() {
+        assert_eq!(frame_hash(&[]), 0xcbf2_9ce4_8422_2325);
+        assert_ne!(frame_hash(&[0; 256]), frame_hash(&[1; 256]));
+    }
+
+    #[test]
+    fn test_round_trip() -> Result<(), Chip8Error> {
+        let r = test_replay();
+        let mut out = Vec::new();
+        r.write(&mut out)?;
+        assert_eq!(Replay::read(out.as_slice())?, r);
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_rejects_junk() {
+        assert!(Replay::read("not a replay\n".as_bytes()).is_err());
+        assert!(Replay::read("chip8-replay 1\nseed xyz\n".as_bytes()).is_err());
+        assert!(Replay::read("chip8-replay 1\nseed 0000\n- zz\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_divergence() {
+        let r = test_replay();
+        assert_eq!(r.first_divergence(&[1, 2, 0xffff_ffff_ffff_ffff]), None);
+        assert_eq!(r.first_divergence(&[1, 3, 0xffff_ffff_ffff_ffff]), Some(1));
+        assert_eq!(r.first_divergence(&[1, 2]), Some(2));
+    }
+
+    #[test]
+    fn test_record_then_playback() -> Result<(), Chip8Error> {
+        let mut inner = DummyInput::new(&[0x5, 0x4]);
+        let mut rec = RecordingInput::new(&mut inner);
+        rec.tick()?;
+        // stays latched for the whole frame
+        assert_eq!(rec.read_key()?, Some(0x4));
+        assert_eq!(rec.read_key()?, Some(0x4));
+        rec.tick()?;
+        assert!(!rec.out_of_keys());
+        rec.tick()?;
+        assert!(rec.out_of_keys());
+        assert_eq!(rec.keys(), &[Some(0x4), Some(0x5), None]);
+
+        let mut play = PlaybackInput::new(rec.keys().to_vec());
+        play.tick()?;
+        assert_eq!(play.read_key()?, Some(0x4));
+        play.flush_keys()?;
+        assert_eq!(play.read_key()?, None);
+        play.tick()?;
+        assert_eq!(play.read_key()?, Some(0x5));
+        assert!(!play.out_of_keys());
+        play.tick()?;
+        assert!(play.out_of_keys());
+        play.tick()?;
+        assert_eq!(play.read_key()?, None);
+
+        // a demo's only as long as it's needed, but the keys keep coming
+        let mut inner = DummyInput::new(&[0x5, 0x4]);
+        let mut rec = RecordingInput::new(&mut inner).up_to(1);
+        rec.tick()?;
+        rec.tick()?;
+        assert_eq!(rec.read_key()?, Some(0x5));
+        assert_eq!(rec.keys(), &[Some(0x4)]);
+        Ok(())
+    }
+
+    /// a player at the command palette, choosing to load slot 3
+    struct AtThePalette;
+
+    impl Input for AtThePalette {
+        fn flush_keys(&mut self) -> Result<(), Chip8Error> {
+            Ok(())
+        }
+
+        fn read_key(&mut self) -> Result<Option<u8>, Chip8Error> {
+            Ok(None)
+        }
+
+        fn tick(&mut self) -> Result<(), Chip8Error> {
+            Ok(())
+        }
+
+        fn take_palette_request(&mut self) -> Result<bool, Chip8Error> {
+            Ok(true)
+        }
+
+        fn take_typed(&mut self) -> Result<Vec<Typed>, Chip8Error> {
+            Ok("load 3\n"
+                .chars()
+                .map(|c| match c {
+                    '\n' => Typed::Enter,
+                    c => Typed::Char(c),
+                })
+                .collect())
+        }
+    }
+
+    /// neither the palette, nor what's typed into it, gets through input
+    fn palette_held_back(input: &mut dyn Input) -> Result<(), Chip8Error> {
+        assert!(!input.take_palette_request()?);
+        input.set_typing(true);
+        assert_eq!(input.take_typed()?, vec![]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_recording_holds_back_the_palette() -> Result<(), Chip8Error> {
+        let mut inner = AtThePalette;
+        palette_held_back(&mut RecordingInput::new(&mut inner))
+    }
+}
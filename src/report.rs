@@ -0,0 +1,163 @@
+//! # bug reports
+//!
+//! "submarine.ch8 renders wrong" is hard to do anything with. the pause
+//! menu's report command bundles up what's needed to see it happen: the
+//! machine's state (so it can be --resume'd straight into the problem), the
+//! last instructions run, the config, a hash of the ROM to be sure it's the
+//! same one, and how well the host was keeping up. it's a zip so it can be
+//! attached to an issue as it is; the files in it are only stored, not
+//! compressed, so there's no need for a deflate library
+use crate::calibrate::Calibration;
+use crate::config::Config;
+use crate::error::Chip8Error;
+use crate::interpreter::Overruns;
+use crate::paths;
+use crate::persist::crc32;
+use crate::replay::frame_hash;
+use crate::session::Session;
+use crate::trace::TraceEntry;
+use std::io::Write;
+
+/// how many instructions back a report goes
+pub const REPORT_TRACE_LINES: usize = 1000;
+
+/// everything going into a report
+pub struct BugReport<'r> {
+    pub session: &'r Session,
+    pub config: &'r Config,
+    /// oldest first
+    pub trace: Vec<TraceEntry>,
+    pub frames: u64,
+    pub calibration: Option<Calibration>,
+    pub overruns: Overruns,
+}
+
+impl<'r> BugReport<'r> {
+    /// where to put it, so reports on different ROMs or from different
+    /// points don't overwrite each other
+    pub fn file_name(&self) -> String {
+        let rom_name = paths::file_name(&self.session.rom_name);
+        format!("chip8-report-{}-{}.zip", rom_name, self.frames)
+    }
+
+    /// the files in the zip, by name
+    pub fn files(&self) -> Result<Vec<(&'static str, Vec<u8>)>, Chip8Error> {
+        let summary = format!(
+            "rom: {}\nrom hash: {:016x} ({} bytes)\nframe: {}\nspeed: {}\nschip: {}\n\
+             emulator: chip8 {} on {}/{}\n\n\
+             state.toml carries on from here: copy it to the session file and --resume\n",
+            self.session.rom_name,
+            frame_hash(&self.session.rom),
+            self.session.rom.len(),
+            self.frames,
+            self.session.speed,
+            self.session.schip,
+            env!("CARGO_PKG_VERSION"),
+            std::env::consts::OS,
+            std::env::consts::ARCH,
+        );
+        let trace: String = self.trace.iter().map(|e| format!("{}\n", e)).collect();
+        let timing = format!(
+            "calibration: {}\nslow instructions: {}\nslow interrupts: {}\n",
+            self.calibration
+                .map_or("not measured".to_string(), |c| c.to_string()),
+            self.overruns.instructions,
+            self.overruns.interrupts,
+        );
+        Ok(vec![
+            ("README.txt", summary.into_bytes()),
+            ("state.toml", self.session.to_toml()?.into_bytes()),
+            ("trace.txt", trace.into_bytes()),
+            ("config.toml", self.config.to_toml()?.into_bytes()),
+            ("timing.txt", timing.into_bytes()),
+        ])
+    }
+
+    pub fn write(&self, out: &mut impl Write) -> Result<(), Chip8Error> {
+        write_zip(out, &self.files()?)
+    }
+}
+
+// every file's dated 1980-01-01, zip's zero
+const ZIP_DATE: u16 = 0x0021;
+
+/// a zip of files, stored as they are
+pub fn write_zip(out: &mut impl Write, files: &[(&str, Vec<u8>)]) -> Result<(), Chip8Error> {
+    let mut offset = 0u32;
+    let mut directory = Vec::new();
+    for (name, data) in files {
+        // the same for the file's own header and its directory entry:
+        // version 2.0 needed, no flags, stored, time, date, crc and sizes
+        let mut common = Vec::new();
+        for field in [20u16, 0, 0, 0, ZIP_DATE] {
+            common.extend(field.to_le_bytes());
+        }
+        for field in [crc32(data), data.len() as u32, data.len() as u32] {
+            common.extend(field.to_le_bytes());
+        }
+        common.extend((name.len() as u16).to_le_bytes());
+
+        let mut local = 0x0403_4b50u32.to_le_bytes().to_vec();
+        local.extend(&common);
+        local.extend(0u16.to_le_bytes()); // no extra
+        local.extend(name.as_bytes());
+        out.write_all(&local)?;
+        out.write_all(data)?;
+
+        directory.extend(0x0201_4b50u32.to_le_bytes());
+        directory.extend(20u16.to_le_bytes()); // made by 2.0
+        directory.extend(&common);
+        // no extra, comment, disk number or attributes
+        directory.extend([0; 12]);
+        directory.extend(offset.to_le_bytes());
+        directory.extend(name.as_bytes());
+        offset += (local.len() + data.len()) as u32;
+    }
+    out.write_all(&directory)?;
+
+    let mut end = 0x0605_4b50u32.to_le_bytes().to_vec();
+    end.extend([0; 4]); // one disk
+    end.extend((files.len() as u16).to_le_bytes());
+    end.extend((files.len() as u16).to_le_bytes());
+    end.extend((directory.len() as u32).to_le_bytes());
+    end.extend(offset.to_le_bytes());
+    end.extend([0; 2]); // no comment
+    out.write_all(&end)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn test_write_zip() -> Result<(), Chip8Error> {
+        let mut zip = Vec::new();
+        write_zip(
+            &mut zip,
+            &[("a.txt", b"chip".to_vec()), ("b.txt", b"8".to_vec())],
+        )?;
+        // two local headers of 30 bytes plus name, then the data
+        assert_eq!(&zip[..4], b"PK\x03\x04");
+        assert_eq!(&zip[30..39], b"a.txtchip");
+        assert_eq!(&zip[39..43], b"PK\x03\x04");
+        assert_eq!(&zip[14..18], crc32(b"chip").to_le_bytes());
+        // the directory starts where the end record says it does, and
+        // points back at the second file
+        let end = &zip[zip.len() - 22..];
+        assert_eq!(&end[..4], b"PK\x05\x06");
+        assert_eq!(u16::from_le_bytes([end[10], end[11]]), 2);
+        let start = u32::from_le_bytes(end[16..20].try_into().unwrap()) as usize;
+        assert_eq!(start, 75);
+        assert_eq!(&zip[start..start + 4], b"PK\x01\x02");
+        let second = start + 46 + 5;
+        assert_eq!(&zip[second + 42..second + 46], 39u32.to_le_bytes());
+        Ok(())
+    }
+}
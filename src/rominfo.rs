@@ -0,0 +1,261 @@
+use crate::gamepad::Genre;
+use crate::input::Keymap;
+use crate::stats::Score;
+use std::path::Path;
+
+/// what we know about a particular ROM, so the user doesn't have to guess at
+/// the controls
+pub struct RomInfo {
+    pub title: &'static str,
+    /// what sort of game it is, to pick a gamepad profile
+    pub genre: Option<Genre>,
+    /// COSMAC key -> what it does in this game
+    pub controls: &'static [(u8, &'static str)],
+    /// where it keeps its score, for the stats, if anyone's found it. none
+    /// of these have been checked yet, so config has to say for now
+    pub score: Option<Score>,
+}
+
+/// known ROMs, keyed on their (lower-case) file name without extension
+// controls from https://github.com/dmatlack/chip8/tree/master/roms and
+// https://chip-8.github.io/database/
+const ROM_DATABASE: [(&str, RomInfo); 11] = [
+    (
+        "blinky",
+        RomInfo {
+            title: "Blinky",
+            genre: Some(Genre::Maze),
+            controls: &[(0x3, "up"), (0x6, "down"), (0x7, "left"), (0x8, "right")],
+            score: None,
+        },
+    ),
+    (
+        "brix",
+        RomInfo {
+            title: "Brix",
+            genre: Some(Genre::Paddle),
+            controls: &[(0x4, "left"), (0x6, "right")],
+            score: None,
+        },
+    ),
+    (
+        "invaders",
+        RomInfo {
+            title: "Space Invaders",
+            genre: Some(Genre::Shooter),
+            controls: &[(0x4, "left"), (0x6, "right"), (0x5, "fire")],
+            score: None,
+        },
+    ),
+    (
+        "missile",
+        RomInfo {
+            title: "Missile Command",
+            genre: Some(Genre::Shooter),
+            controls: &[(0x8, "fire")],
+            score: None,
+        },
+    ),
+    (
+        "pong",
+        RomInfo {
+            title: "Pong",
+            genre: Some(Genre::Paddle),
+            controls: &[
+                (0x1, "p1 up"),
+                (0x4, "p1 down"),
+                (0xc, "p2 up"),
+                (0xd, "p2 down"),
+            ],
+            score: None,
+        },
+    ),
+    (
+        "pong2",
+        RomInfo {
+            title: "Pong 2",
+            genre: Some(Genre::Paddle),
+            controls: &[
+                (0x1, "p1 up"),
+                (0x4, "p1 down"),
+                (0xc, "p2 up"),
+                (0xd, "p2 down"),
+            ],
+            score: None,
+        },
+    ),
+    (
+        "tank",
+        RomInfo {
+            title: "Tank",
+            genre: Some(Genre::Shooter),
+            controls: &[
+                (0x2, "up"),
+                (0x8, "down"),
+                (0x4, "left"),
+                (0x6, "right"),
+                (0x5, "fire"),
+            ],
+            score: None,
+        },
+    ),
+    (
+        "tetris",
+        RomInfo {
+            title: "Tetris",
+            genre: None,
+            controls: &[
+                (0x4, "rotate"),
+                (0x5, "left"),
+                (0x6, "right"),
+                (0x7, "drop"),
+            ],
+            score: None,
+        },
+    ),
+    (
+        "trip8_demo",
+        RomInfo {
+            title: "Trip8 Demo",
+            genre: None,
+            controls: &[],
+            score: None,
+        },
+    ),
+    (
+        "ufo",
+        RomInfo {
+            title: "UFO",
+            genre: Some(Genre::Shooter),
+            controls: &[(0x4, "fire left"), (0x5, "fire up"), (0x6, "fire right")],
+            score: None,
+        },
+    ),
+    (
+        "wipeoff",
+        RomInfo {
+            title: "Wipe Off",
+            genre: Some(Genre::Paddle),
+            controls: &[(0x4, "left"), (0x6, "right")],
+            score: None,
+        },
+    ),
+];
+
+/// the name we use to look a ROM up, both in the database and in the config
+pub fn rom_name(path: &Path) -> String {
+    path.file_stem()
+        .map(|s| s.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+/// find out what we know about a ROM
+pub fn lookup(name: &str) -> Option<&'static RomInfo> {
+    ROM_DATABASE
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, info)| info)
+}
+
+impl RomInfo {
+    /// describe the controls in terms of the keys the user actually presses,
+    /// e.g. "Q/E = rotate, W = drop"
+    pub fn describe_controls(&self, keymap: &Keymap) -> String {
+        let mut actions: Vec<(&str, Vec<char>)> = Vec::new();
+        for (key, action) in self.controls {
+            let mut host_keys: Vec<char> = keymap
+                .iter()
+                .filter(|(_, k)| *k == key)
+                .map(|(c, _)| c.to_ascii_uppercase())
+                .collect();
+            host_keys.sort_unstable();
+            match actions.iter_mut().find(|(a, _)| a == action) {
+                Some((_, keys)) => keys.extend(host_keys),
+                None => actions.push((action, host_keys)),
+            }
+        }
+        actions
+            .iter()
+            .map(|(action, keys)| match keys.len() {
+                0 => format!("(unmapped) = {}", action),
+                _ => format!(
+                    "{} = {}",
+                    keys.iter()
+                        .map(|c| c.to_string())
+                        .collect::<Vec<_>>()
+                        .join("/"),
+                    action
+                ),
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// one-liner suitable for a status bar
+    pub fn status_line(&self, keymap: &Keymap) -> String {
+        match self.controls.len() {
+            0 => self.title.to_string(),
+            _ => format!("{}: {}", self.title, self.describe_controls(keymap)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::conventional_keymap;
+
+    #[test]
+    fn test_rom_name() {
+        assert_eq!(rom_name(Path::new("roms/TETRIS.ch8")), "tetris");
+        assert_eq!(rom_name(Path::new("pong")), "pong");
+    }
+
+    #[test]
+    fn test_lookup() {
+        assert_eq!(lookup("tetris").unwrap().title, "Tetris");
+        assert!(lookup("not_a_rom").is_none());
+    }
+
+    #[test]
+    fn test_describe_controls() {
+        let info = lookup("tetris").unwrap();
+        assert_eq!(
+            info.describe_controls(&conventional_keymap()),
+            "Q = rotate, W = left, E = right, A = drop"
+        );
+    }
+
+    #[test]
+    fn test_describe_controls_groups_actions() {
+        let info = RomInfo {
+            title: "Test",
+            genre: None,
+            controls: &[(0x4, "rotate"), (0x6, "rotate"), (0x5, "drop")],
+            score: None,
+        };
+        assert_eq!(
+            info.describe_controls(&conventional_keymap()),
+            "Q/E = rotate, W = drop"
+        );
+    }
+
+    #[test]
+    fn test_describe_controls_remapped() {
+        let info = lookup("brix").unwrap();
+        let mut keymap = conventional_keymap();
+        keymap.insert('j', 0x4);
+        assert_eq!(info.describe_controls(&keymap), "J/Q = left, E = right");
+    }
+
+    #[test]
+    fn test_describe_controls_unmapped() {
+        let info = lookup("brix").unwrap();
+        let mut keymap = conventional_keymap();
+        keymap.remove(&'q');
+        assert_eq!(
+            info.describe_controls(&keymap),
+            "(unmapped) = left, E = right"
+        );
+    }
+}
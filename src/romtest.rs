@@ -0,0 +1,232 @@
+use crate::display::{self, DummyDisplay};
+use crate::error::Chip8Error;
+use crate::input::HeldKey;
+use crate::interpreter::Chip8Interpreter;
+use crate::memory::MemoryMap;
+use crate::sound::Mute;
+use crate::stable::Frame;
+use std::cell::Cell;
+
+/// how long run_until waits before giving up: a minute of emulated time
+const ROMTEST_MAX_FRAMES: u64 = 3600;
+
+/// drives a ROM from a Rust unit test, e.g.
+/// ```
+/// # use chip8::romtest::RomTest;
+/// # let rom = [0x12, 0x00];
+/// RomTest::run(&rom, |t| {
+///     t.press('a').for_frames(3)?;
+///     t.run_until(|m| m.v(0) == 0)?;
+///     t.expect_pixel(10, 12, false)
+/// })
+/// # .unwrap();
+/// ```
+/// runs are deterministic: there's no sleeping, nothing to see or hear and
+/// the random number generator always starts from the same seed
+pub struct RomTest<'t, 'a> {
+    interpreter: &'t mut Chip8Interpreter<'a>,
+    held: &'t Cell<Option<u8>>,
+}
+
+impl<'t, 'a> RomTest<'t, 'a> {
+    /// load rom and hand it to test
+    pub fn run(
+        rom: &[u8],
+        test: impl FnOnce(&mut RomTest) -> Result<(), Chip8Error>,
+    ) -> Result<(), Chip8Error> {
+        let held = Cell::new(None);
+        let mut input = HeldKey(&held);
+        let mut display = DummyDisplay::new()?;
+        let mut sound = Mute::new();
+        let mut interpreter = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+        interpreter.set_seed(0);
+        interpreter.load_program(&mut &rom[..])?;
+        test(&mut RomTest {
+            interpreter: &mut interpreter,
+            held: &held,
+        })
+    }
+
+    /// the machine, e.g. to look at its registers or memory
+    pub fn interpreter(&mut self) -> &mut Chip8Interpreter<'a> {
+        self.interpreter
+    }
+
+    pub fn run_frames(&mut self, frames: u64) -> Result<&mut Self, Chip8Error> {
+        self.interpreter.run_frames(frames)?;
+        Ok(self)
+    }
+
+    /// run until done says so, checking at the end of every frame. gives up
+    /// after a minute of emulated time. returns the frames it took
+    pub fn run_until(
+        &mut self,
+        done: impl Fn(&Chip8Interpreter) -> bool,
+    ) -> Result<u64, Chip8Error> {
+        for frame in 0..ROMTEST_MAX_FRAMES {
+            if done(self.interpreter) {
+                return Ok(frame);
+            }
+            self.interpreter.run_frames(1)?;
+        }
+        Err(Chip8Error::TestFailure(format!(
+            "still waiting after {} frames",
+            ROMTEST_MAX_FRAMES
+        )))
+    }
+
+    /// run until the screen's stayed the same for `frames` frames in a row
+    /// (a title screen's finished drawing, say) and return it. gives up
+    /// after a minute of emulated time, like run_until
+    pub fn run_until_stable(&mut self, frames: u64) -> Result<Vec<u8>, Chip8Error> {
+        let mut last = self.frame()?;
+        let mut still = 0;
+        for _ in 0..ROMTEST_MAX_FRAMES {
+            if still >= frames {
+                return Ok(last);
+            }
+            self.interpreter.run_frames(1)?;
+            let frame = self.frame()?;
+            if frame == last {
+                still += 1;
+            } else {
+                still = 0;
+                last = frame;
+            }
+        }
+        Err(Chip8Error::TestFailure(format!(
+            "screen still changing after {} frames",
+            ROMTEST_MAX_FRAMES
+        )))
+    }
+
+    /// what's on the screen, in whichever resolution it's in
+    pub fn frame(&self) -> Result<Vec<u8>, Chip8Error> {
+        let (addr, width, height) = self.interpreter.display_geometry();
+        Ok(self
+            .interpreter
+            .memory()
+            .get_ro_slice(addr, width * height / 8)?
+            .to_vec())
+    }
+
+    /// what's on the screen, as the stable API has it, to ask where
+    /// what's lit is
+    pub fn screen(&self) -> Result<Frame, Chip8Error> {
+        let (_, width, height) = self.interpreter.display_geometry();
+        Ok(Frame::new(width, height, &self.frame()?))
+    }
+
+    /// the screen as '#'s and '.'s, to compare with a snapshot
+    pub fn ascii(&self) -> Result<String, Chip8Error> {
+        let (_, width, _) = self.interpreter.display_geometry();
+        Ok(display::to_ascii(&self.frame()?, width))
+    }
+
+    /// hold down a key on the hex keypad, '0' to 'f'
+    pub fn press(&mut self, key: char) -> Press<'_, 't, 'a> {
+        Press { test: self, key }
+    }
+
+    pub fn expect_pixel(&mut self, x: usize, y: usize, on: bool) -> Result<(), Chip8Error> {
+        if self.screen()?.pixel(x, y) == on {
+            Ok(())
+        } else {
+            Err(Chip8Error::TestFailure(format!(
+                "expected pixel ({}, {}) to be {}",
+                x,
+                y,
+                if on { "on" } else { "off" }
+            )))
+        }
+    }
+}
+
+/// run rom, with nobody pressing anything, until its screen settles for
+/// `frames` frames, and return what's on it: for golden-image tests of
+/// menus and test ROMs that draw their results and stop
+pub fn stable_frame(rom: &[u8], frames: u64) -> Result<Vec<u8>, Chip8Error> {
+    let mut frame = Vec::new();
+    RomTest::run(rom, |t| {
+        frame = t.run_until_stable(frames)?;
+        Ok(())
+    })?;
+    Ok(frame)
+}
+
+/// a key being pressed, until it's let go
+pub struct Press<'p, 't, 'a> {
+    test: &'p mut RomTest<'t, 'a>,
+    key: char,
+}
+
+impl<'p, 't, 'a> Press<'p, 't, 'a> {
+    pub fn for_frames(self, frames: u64) -> Result<&'p mut RomTest<'t, 'a>, Chip8Error> {
+        let key = self.key.to_digit(16).ok_or_else(|| {
+            Chip8Error::ConfigError(format!("no key '{}' on the keypad", self.key))
+        })?;
+        self.test.held.set(Some(key as u8));
+        let result = self.test.interpreter.run_frames(frames);
+        self.test.held.set(None);
+        result?;
+        Ok(self.test)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // wait for a key; v0 += key; draw the glyph for v0 at (v1, v1); stop
+    #[rustfmt::skip]
+    const ADD_KEY: [u8; 10] = [
+        0xf2, 0x0a, 0x80, 0x24, 0xf0, 0x29, 0xd1, 0x15, 0x12, 0x08,
+    ];
+
+    #[test]
+    fn test_press_and_run_until() -> Result<(), Chip8Error> {
+        RomTest::run(&ADD_KEY, |t| {
+            t.expect_pixel(0, 0, false)?;
+            t.press('3').for_frames(3)?;
+            let frames = t.run_until(|m| m.v(0) == 3)?;
+            assert_eq!(frames, 0);
+            t.run_frames(1)?;
+            t.expect_pixel(0, 0, true)?;
+            // the VIP's 3
+            let ascii = t.ascii()?;
+            let top: Vec<&str> = ascii.lines().take(6).map(|l| &l[..5]).collect();
+            assert_eq!(top, ["####.", "...#.", "####.", "...#.", "####.", "....."]);
+            let screen = t.screen()?;
+            assert_eq!(screen.bounds(), Some((0, 0, 4, 5)));
+            assert_eq!(screen.lit_count(), 14);
+            assert!(t.run_until(|m| m.v(0) == 4).is_err());
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_stable_frame() -> Result<(), Chip8Error> {
+        // draw f0 at (0, 0), wait a second, draw 0f next to it, stop
+        #[rustfmt::skip]
+        let rom = [
+            0xa2, 0x14, 0xd0, 0x01, 0x61, 0x3c, 0xf1, 0x15, 0xf1, 0x07,
+            0x31, 0x00, 0x12, 0x08, 0xa2, 0x15, 0xd0, 0x01, 0x12, 0x12,
+            0xf0, 0x0f,
+        ];
+        let mut expect = vec![0; 0x100];
+        expect[0] = 0xff;
+        assert_eq!(stable_frame(&rom, 90)?, expect);
+        // whereas the ball never stops bouncing
+        assert!(stable_frame(crate::gallery::GALLERY[0].rom, 90).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_bad_key() -> Result<(), Chip8Error> {
+        RomTest::run(&[0x12, 0x00], |t| {
+            assert!(t.press('g').for_frames(1).is_err());
+            assert!(t.expect_pixel(0, 0, true).is_err());
+            Ok(())
+        })
+    }
+}
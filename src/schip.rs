@@ -0,0 +1,378 @@
+//! # SUPER-CHIP
+//!
+//! the HP48's CHIP-8 added instructions of its own. they all sit in 0NNN
+//! space, which the VIP uses for machine code, so they're an extension
+//! rather than part of the decoder, and only ROMs that want them get them.
+//!
+//! SCHIP 1.1 draws the low-resolution screen as 2x2 blocks on its 128x64
+//! one, so its scrolls always count high-resolution pixels: in low
+//! resolution, 00C1 moves the picture down half a pixel. a 64x32 page can't
+//! show half a pixel, so the odd line is kept owing until the next scroll
+//! down makes it a whole one.
+//!
+//! DXY0 draws nothing on the VIP, so the SUPER-CHIP's 16x16 sprites (32
+//! bytes at I, two to a row) come here too, in either resolution, as SCHIP
+//! 1.1 draws them. in high resolution VF is how many rows collided, or
+//! went off the bottom; in low resolution it's just whether any did
+use crate::error::Chip8Error;
+use crate::extension::OpcodeExtension;
+use crate::interpreter::Chip8Interpreter;
+use crate::memory::MemoryMap;
+
+/// how far 00FB and 00FC move things, in high-resolution pixels
+const SCHIP_SCROLL_PIXELS: u32 = 4;
+/// about as long as clearing the screen takes (as does switching modes,
+/// which clears it)
+const SCHIP_SCROLL_CYCLES: usize = 24;
+/// about as long as a return takes
+const SCHIP_EXIT_CYCLES: usize = 10;
+/// roughly what the VIP would take over 16 rows of a sprite twice as wide
+const SCHIP_SPRITE_CYCLES: usize = 400;
+/// a 16x16 sprite's rows
+const SCHIP_SPRITE_ROWS: usize = 16;
+
+/// the SUPER-CHIP instructions
+#[derive(Default)]
+pub struct Schip {
+    /// a high-resolution line scrolled down in low resolution but not shown
+    half_row: bool,
+}
+
+impl Schip {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl OpcodeExtension for Schip {
+    fn handles(&self, inst: u16) -> bool {
+        matches!(inst, 0x00c0..=0x00cf | 0x00fb..=0x00ff) || inst & 0xf00f == 0xd000
+    }
+
+    fn execute(
+        &mut self,
+        inst: u16,
+        interpreter: &mut Chip8Interpreter,
+    ) -> Result<usize, Chip8Error> {
+        match inst {
+            // dxy0: a 16x16 sprite
+            0xd000..=0xdfff => {
+                return draw_big_sprite(
+                    interpreter,
+                    (inst >> 8 & 0xf) as u8,
+                    (inst >> 4 & 0xf) as u8,
+                )
+            }
+            // 00fd: exit the interpreter
+            0x00fd => {
+                interpreter.exit();
+                return Ok(SCHIP_EXIT_CYCLES);
+            }
+            // 00fe/00ff: low/high resolution
+            0x00fe | 0x00ff => {
+                self.half_row = false;
+                interpreter.set_hires(inst == 0x00ff)?;
+                return Ok(SCHIP_SCROLL_CYCLES);
+            }
+            _ => {}
+        }
+
+        // scrolls count high-resolution pixels, so low resolution goes half
+        // as far
+        let hires = interpreter.hires();
+        let (addr, width, height) = interpreter.display_geometry();
+        let page = interpreter
+            .memory_mut()
+            .get_rw_slice(addr, width * height / 8)?;
+        let pixels = if hires {
+            SCHIP_SCROLL_PIXELS
+        } else {
+            SCHIP_SCROLL_PIXELS / 2
+        };
+        match inst {
+            // 00cn: down n lines
+            0x00c0..=0x00cf if hires => scroll_down(page, width / 8, (inst & 0xf) as usize),
+            0x00c0..=0x00cf => {
+                let lines = (inst & 0xf) as usize + self.half_row as usize;
+                self.half_row = lines & 1 == 1;
+                scroll_down(page, width / 8, lines / 2);
+            }
+            // 00fb: right 4 pixels
+            0x00fb => scroll_right(page, width / 8, pixels),
+            // 00fc: left 4 pixels
+            _ => scroll_left(page, width / 8, pixels),
+        }
+        Ok(SCHIP_SCROLL_CYCLES)
+    }
+}
+
+/// dxy0: the 16x16 sprite at I, XORed onto the screen at VX, VY (wrapped
+/// onto it, then clipped at its edges)
+fn draw_big_sprite(interpreter: &mut Chip8Interpreter, x: u8, y: u8) -> Result<usize, Chip8Error> {
+    let hires = interpreter.hires();
+    let (addr, width, height) = interpreter.display_geometry();
+    let x = interpreter.v(x) as usize & (width - 1);
+    let y = interpreter.v(y) as usize & (height - 1);
+    let sprite = interpreter.read_at_i(0, SCHIP_SPRITE_ROWS * 2)?;
+    let stride = width / 8;
+    // what's drawn where, for the heat map once the page is let go
+    let mut drawn = Vec::new();
+    let heat = interpreter.heat_map();
+    let page = interpreter
+        .memory_mut()
+        .get_rw_slice(addr, width * height / 8)?;
+    let mut rows_hit = 0;
+    for (row, pair) in sprite.chunks_exact(2).enumerate() {
+        if y + row >= height {
+            // off the bottom, which counts as a collision in high resolution
+            rows_hit += hires as u8;
+            continue;
+        }
+        // the row's 16 pixels, shifted across the three bytes they can touch
+        let bits = (u16::from_be_bytes([pair[0], pair[1]]) as u32) << (8 - x % 8);
+        let mut hit = false;
+        for b in 0..3 {
+            let column = x / 8 + b;
+            if column >= stride {
+                // off the right-hand edge
+                break;
+            }
+            let byte = (bits >> (16 - 8 * b)) as u8;
+            let index = (y + row) * stride + column;
+            let pixels = &mut page[index];
+            hit |= *pixels & byte != 0;
+            *pixels ^= byte;
+            if heat {
+                drawn.push((index, byte));
+            }
+        }
+        rows_hit += hit as u8;
+    }
+    for (index, byte) in drawn {
+        interpreter.heat_xored(index, byte);
+    }
+    let vf = match hires {
+        true => rows_hit,
+        false => (rows_hit > 0) as u8,
+    };
+    interpreter.set_v(0xf, vf);
+    if rows_hit > 0 {
+        interpreter.collided();
+    }
+    Ok(SCHIP_SPRITE_CYCLES)
+}
+
+/// move a page of row_bytes-wide rows down, blanking the rows at the top
+pub fn scroll_down(page: &mut [u8], row_bytes: usize, rows: usize) {
+    let shift = (rows * row_bytes).min(page.len());
+    page.copy_within(..page.len() - shift, shift);
+    page[..shift].fill(0);
+}
+
+/// move each row right by bits (less than 8), blanking the left edge.
+/// scrolling by nothing does nothing
+pub fn scroll_right(page: &mut [u8], row_bytes: usize, bits: u32) {
+    if bits == 0 || row_bytes == 0 {
+        return;
+    }
+    for row in page.chunks_exact_mut(row_bytes) {
+        for b in (0..row.len()).rev() {
+            let carry = if b > 0 { row[b - 1] << (8 - bits) } else { 0 };
+            row[b] = (row[b] >> bits) | carry;
+        }
+    }
+}
+
+/// move each row left by bits (less than 8), blanking the right edge.
+/// scrolling by nothing does nothing
+pub fn scroll_left(page: &mut [u8], row_bytes: usize, bits: u32) {
+    if bits == 0 || row_bytes == 0 {
+        return;
+    }
+    for row in page.chunks_exact_mut(row_bytes) {
+        for b in 0..row.len() {
+            let carry = row.get(b + 1).map_or(0, |next| next >> (8 - bits));
+            row[b] = (row[b] << bits) | carry;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::RunOutcome;
+    use crate::{display, input, sound};
+
+    #[test]
+    fn test_scroll_down() {
+        // 2 bytes wide, 3 rows
+        let mut page = [1, 2, 3, 4, 5, 6];
+        scroll_down(&mut page, 2, 1);
+        assert_eq!(page, [0, 0, 1, 2, 3, 4]);
+        scroll_down(&mut page, 2, 0);
+        assert_eq!(page, [0, 0, 1, 2, 3, 4]);
+        // right off the bottom
+        scroll_down(&mut page, 2, 5);
+        assert_eq!(page, [0; 6]);
+    }
+
+    #[test]
+    fn test_scroll_sideways() {
+        let mut page = [0x81, 0x81, 0xff, 0x00];
+        scroll_right(&mut page, 2, 4);
+        // bits carry across bytes, but not rows; the right edge drops off
+        assert_eq!(page, [0x08, 0x18, 0x0f, 0xf0]);
+        scroll_left(&mut page, 2, 4);
+        assert_eq!(page, [0x81, 0x80, 0xff, 0x00]);
+        scroll_left(&mut page, 2, 2);
+        assert_eq!(page, [0x06, 0x00, 0xfc, 0x00]);
+        // and by nothing, nothing happens
+        scroll_right(&mut page, 2, 0);
+        scroll_left(&mut page, 2, 0);
+        scroll_left(&mut page, 0, 4);
+        assert_eq!(page, [0x06, 0x00, 0xfc, 0x00]);
+    }
+
+    fn run(program: &[u8]) -> Result<Vec<u8>, Chip8Error> {
+        let mut display = display::DummyDisplay::new()?;
+        let mut input = input::DummyInput::new(&[]);
+        let mut sound = sound::Mute::new();
+        let mut schip = Schip::new();
+        let mut i = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+        i.add_extension(&mut schip);
+        i.load_program(&mut &program[..])?;
+        let addr = i.memory().display_addr;
+        i.memory_mut().write(&[0xff; 8], addr, 8)?;
+        i.run_frames(1)?;
+        Ok(i.memory().get_ro_slice(addr, 0x100)?.to_vec())
+    }
+
+    #[test]
+    fn test_exit() -> Result<(), Chip8Error> {
+        let mut display = display::DummyDisplay::new()?;
+        let mut input = input::DummyInput::new(&[]);
+        let mut sound = sound::Mute::new();
+        let mut schip = Schip::new();
+        let mut i = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+        i.add_extension(&mut schip);
+        // v0 = 1; exit; v0 = 2
+        i.load_program(&mut &[0x60, 0x01, 0x00, 0xfd, 0x60, 0x02][..])?;
+        assert_eq!(i.main_loop(60)?, RunOutcome::Exited);
+        assert!(i.exited());
+        assert_eq!(i.v(0), 1);
+        // and stays stopped
+        i.run_frames(1)?;
+        assert_eq!(i.main_loop(60)?, RunOutcome::Exited);
+        assert_eq!(i.pc(), 0x204);
+        Ok(())
+    }
+
+    #[test]
+    fn test_lores_scroll_down_by_halves() -> Result<(), Chip8Error> {
+        // 00c1 is half a row, so nothing shows until the second
+        let page = run(&[0x00, 0xc1, 0x12, 0x02])?;
+        assert_eq!(page[..8], [0xff; 8]);
+        let page = run(&[0x00, 0xc1, 0x00, 0xc1, 0x00, 0xc4, 0x12, 0x06])?;
+        assert_eq!(page[..24], [0; 24]);
+        assert_eq!(page[24..32], [0xff; 8]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_hires() -> Result<(), Chip8Error> {
+        let mut display = display::DummyDisplay::new()?;
+        let mut input = input::DummyInput::new(&[]);
+        let mut sound = sound::Mute::new();
+        let mut schip = Schip::new();
+        let mut i = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+        i.add_extension(&mut schip);
+        // hires; v0 = 120; v1 = 60; i = glyph 8; draw it; down 1 line;
+        // left 4 pixels; lores; stop
+        #[rustfmt::skip]
+        i.load_program(&mut &[
+            0x00, 0xff, 0x60, 0x78, 0x61, 0x3c, 0xf0, 0x29,
+            0xd0, 0x15, 0x00, 0xc1, 0x00, 0xfc, 0x00, 0xfd,
+        ][..])?;
+        i.run_frames(10)?;
+        assert!(i.hires());
+        let (addr, width, height) = i.display_geometry();
+        assert_eq!((width, height), (128, 64));
+        let page = i.memory().get_ro_slice(addr, 0x400)?.to_vec();
+        // 8 is 0xf0 across the top, so at (116, 61) after the scrolls, and
+        // clipped at the bottom
+        assert_eq!(page[61 * 16 + 14..61 * 16 + 16], [0x0f, 0x00]);
+        assert_eq!(page[63 * 16 + 14..63 * 16 + 16], [0x0f, 0x00]);
+        assert!(page[..61 * 16].iter().all(|b| *b == 0));
+
+        // and back to lores, blank
+        i.set_hires(false)?;
+        assert_eq!(i.display_geometry().1, 64);
+        let addr = i.display_geometry().0;
+        assert!(i
+            .memory()
+            .get_ro_slice(addr, 0x100)?
+            .iter()
+            .all(|b| *b == 0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_big_sprite() -> Result<(), Chip8Error> {
+        let mut display = display::DummyDisplay::new()?;
+        let mut input = input::DummyInput::new(&[]);
+        let mut sound = sound::Mute::new();
+        let mut schip = Schip::new();
+        let mut i = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+        i.add_extension(&mut schip);
+        // v0 = 4; i = the sprite; d000 twice; hires; v1 = 120; v2 = 56; d120;
+        // stop; then a 16x16 sprite, all of it lit
+        #[rustfmt::skip]
+        let program = [
+            0x60, 0x04, 0xa2, 0x14, 0xd0, 0x00, 0xd0, 0x00,
+            0x00, 0xff, 0x61, 0x78, 0x62, 0x38, 0xd1, 0x20,
+            0x00, 0xfd, 0x00, 0x00,
+        ];
+        i.load_program(&mut &[&program[..], &[0xff; 32]].concat()[..])?;
+        for _ in 0..3 {
+            i.step()?;
+        }
+        // 16 rows of 16 pixels, from (4, 4)
+        let (addr, _, _) = i.display_geometry();
+        let page = i.memory().get_ro_slice(addr, 0x100)?.to_vec();
+        assert_eq!(page[3 * 8..3 * 8 + 3], [0, 0, 0]);
+        assert_eq!(page[4 * 8..4 * 8 + 3], [0x0f, 0xff, 0xf0]);
+        assert_eq!(page[19 * 8..19 * 8 + 3], [0x0f, 0xff, 0xf0]);
+        assert_eq!(page[20 * 8..20 * 8 + 3], [0, 0, 0]);
+        assert_eq!(i.v(0xf), 0);
+        // and off again, having collided
+        i.step()?;
+        assert!(i
+            .memory()
+            .get_ro_slice(addr, 0x100)?
+            .iter()
+            .all(|b| *b == 0));
+        assert_eq!(i.v(0xf), 1);
+
+        // in high resolution, clipped at the corner: the 8 rows off the
+        // bottom count as collisions
+        i.run_frames(1)?;
+        assert!(i.exited());
+        let (addr, _, _) = i.display_geometry();
+        let page = i.memory().get_ro_slice(addr, 0x400)?.to_vec();
+        assert_eq!(page[56 * 16 + 15], 0xff);
+        assert_eq!(page[63 * 16 + 15], 0xff);
+        assert_eq!(page[56 * 16 + 14], 0);
+        assert_eq!(i.v(0xf), 8);
+        Ok(())
+    }
+
+    #[test]
+    fn test_lores_scroll_sideways() -> Result<(), Chip8Error> {
+        // right 4 hi-res pixels is 2 lo-res ones
+        let page = run(&[0x00, 0xfb, 0x12, 0x02])?;
+        assert_eq!(page[..8], [0x3f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]);
+        let page = run(&[0x00, 0xfc, 0x12, 0x02])?;
+        assert_eq!(page[..8], [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfc]);
+        Ok(())
+    }
+}
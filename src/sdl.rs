@@ -0,0 +1,385 @@
+//! # an SDL window
+//!
+//! chip8 --frontend sdl game.ch8 (built with --features sdl, which needs
+//! SDL2 installed to link against) plays the game in a window of its own,
+//! drawn, read and beeped through SDL2, rather than the terminal. the keys
+//! are the keymap's, as they are in the terminal, the picture's fitted
+//! into the window as --scaling (or the config's scaling) says, and F11
+//! takes the window to the whole of its monitor and back. escape pauses as
+//! it does anywhere else, with the prompt in the terminal chip8 was started
+//! from, and so does closing the window, to quit from there.
+//!
+//! SDL wants everything done on the thread that started it, so unlike the
+//! gui there's no thread of its own: the window's events are read whenever
+//! the interpreter asks for keys, which is at least once a frame. the
+//! pixels are drawn as rectangles rather than a texture (which SDL ties to
+//! the lifetime of whatever made it), so the picture's always sharp, +smooth
+//! or not
+use crate::display::Display;
+use crate::error::Chip8Error;
+use crate::input::{Input, Keymap};
+use crate::keypad::{KeyFilter, KeyTransition};
+use crate::platform::{Platform, TerminalOptions};
+use crate::sound::{Sound, Volume};
+use crate::window::{Geometry, Scaling, Whereabouts};
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::WindowCanvas;
+use sdl2::video::FullscreenType;
+use sdl2::EventPump;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// how many of the screen's pixels to a CHIP-8 one, at the VIP's 64x32,
+/// for a window that's never been opened before
+const SDL_WINDOW_SCALE: u32 = 12;
+
+/// the buzzer's note, in Hz, and how loud at full volume, out of 1
+const SDL_TONE_PITCH: f32 = 440.0;
+const SDL_TONE_VOLUME: f32 = 0.1;
+
+fn sdl_error(e: impl ToString) -> Chip8Error {
+    Chip8Error::DisplayError(format!("SDL: {}", e.to_string()))
+}
+
+/// the window, and what was last drawn in it, to draw again when it's
+/// uncovered or resized between frames
+struct Screen {
+    canvas: WindowCanvas,
+    data: Vec<u8>,
+    width: usize,
+    height: usize,
+    scaling: Scaling,
+    whereabouts: Whereabouts,
+}
+
+impl Screen {
+    fn present(&mut self) -> Result<(), Chip8Error> {
+        let (width, height) = (self.width as u32, self.height as u32);
+        let placed = self.scaling.place(
+            (width, height),
+            self.canvas.output_size().map_err(sdl_error)?,
+        );
+        // each pixel's edges where they fall, so they meet whatever the fit
+        let edge = |n: u32, of: u32, start: u32, size: u32| {
+            (start as u64 + n as u64 * size as u64 / of as u64) as i32
+        };
+        let mut lit = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                let i = y as usize * self.width + x as usize;
+                if self
+                    .data
+                    .get(i / 8)
+                    .is_some_and(|b| b & (0x80 >> (i % 8)) != 0)
+                {
+                    let (x0, y0) = (
+                        edge(x, width, placed.x, placed.width),
+                        edge(y, height, placed.y, placed.height),
+                    );
+                    let (x1, y1) = (
+                        edge(x + 1, width, placed.x, placed.width),
+                        edge(y + 1, height, placed.y, placed.height),
+                    );
+                    lit.push(Rect::new(x0, y0, (x1 - x0) as u32, (y1 - y0) as u32));
+                }
+            }
+        }
+        self.canvas.set_draw_color(Color::BLACK);
+        self.canvas.clear();
+        self.canvas.set_draw_color(Color::WHITE);
+        self.canvas.fill_rects(&lit).map_err(sdl_error)?;
+        self.canvas.present();
+        Ok(())
+    }
+
+    /// tell whoever's keeping it where the window is now
+    fn moved(&self) {
+        let window = self.canvas.window();
+        let ((x, y), (width, height)) = (window.position(), window.size());
+        self.whereabouts.moved(Geometry {
+            x,
+            y,
+            width,
+            height,
+            fullscreen: window.fullscreen_state() != FullscreenType::Off,
+        });
+    }
+
+    /// borderless, at the desktop's own resolution, rather than changing it
+    fn toggle_fullscreen(&mut self) -> Result<(), Chip8Error> {
+        let window = self.canvas.window_mut();
+        match window.fullscreen_state() {
+            FullscreenType::Off => window.set_fullscreen(FullscreenType::Desktop),
+            _ => window.set_fullscreen(FullscreenType::Off),
+        }
+        .map_err(sdl_error)?;
+        self.moved();
+        Ok(())
+    }
+}
+
+/// draws each frame in the window
+pub struct SdlDisplay {
+    screen: Rc<RefCell<Screen>>,
+}
+
+impl Display for SdlDisplay {
+    fn draw(&mut self, data: &[u8]) -> Result<(), Chip8Error> {
+        let mut screen = self.screen.borrow_mut();
+        screen.data.clear();
+        screen.data.extend_from_slice(data);
+        screen.present()
+    }
+
+    fn get_display_size_bytes(&mut self) -> usize {
+        let screen = self.screen.borrow();
+        screen.width * screen.height / 8
+    }
+
+    fn set_mode(&mut self, width: usize, height: usize) -> Result<(), Chip8Error> {
+        let mut screen = self.screen.borrow_mut();
+        screen.data = vec![0; width * height / 8];
+        screen.width = width;
+        screen.height = height;
+        Ok(())
+    }
+
+    fn refresh(&mut self) -> Result<(), Chip8Error> {
+        self.screen.borrow_mut().present()
+    }
+}
+
+/// the window's keys, through the keymap, and whatever else happens to it
+pub struct SdlInput {
+    events: EventPump,
+    screen: Rc<RefCell<Screen>>,
+    // None when keys come from somewhere else, but the window still needs
+    // looking after
+    keymap: Option<Keymap>,
+    keys: KeyFilter,
+    transitions: Vec<KeyTransition>,
+    menu: bool,
+}
+
+impl SdlInput {
+    /// what's happened to the window since we last looked
+    fn read_window(&mut self) -> Result<(), Chip8Error> {
+        while let Some(event) = self.events.poll_event() {
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::ESCAPE),
+                    ..
+                } => self.menu = true,
+                Event::KeyDown {
+                    keycode: Some(Keycode::F11),
+                    repeat: false,
+                    ..
+                } => self.screen.borrow_mut().toggle_fullscreen()?,
+                Event::KeyDown {
+                    keycode: Some(k),
+                    repeat: false,
+                    ..
+                } => {
+                    if let Some(key) = self.key(k) {
+                        self.keys.seen(key);
+                    }
+                }
+                Event::KeyUp {
+                    keycode: Some(k), ..
+                } => {
+                    if let Some(key) = self.key(k) {
+                        self.keys.let_go(key);
+                    }
+                }
+                Event::Window { win_event, .. } => match win_event {
+                    WindowEvent::Exposed | WindowEvent::SizeChanged(..) => {
+                        self.screen.borrow_mut().present()?;
+                        self.screen.borrow().moved();
+                    }
+                    WindowEvent::Moved(..) => self.screen.borrow().moved(),
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// the keypad key a window key's mapped to, by the character on it,
+    /// e.g. 'q' for Q
+    fn key(&self, keycode: Keycode) -> Option<u8> {
+        let name = keycode.name();
+        let mut chars = name.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => self.keymap.as_ref()?.get(&c.to_ascii_lowercase()).copied(),
+            _ => None,
+        }
+    }
+}
+
+impl Input for SdlInput {
+    fn flush_keys(&mut self) -> Result<(), Chip8Error> {
+        self.keys.flush();
+        Ok(())
+    }
+
+    fn read_key(&mut self) -> Result<Option<u8>, Chip8Error> {
+        self.read_window()?;
+        Ok(self.keys.read())
+    }
+
+    fn tick(&mut self) -> Result<(), Chip8Error> {
+        self.read_window()?;
+        self.transitions = self.keys.tick();
+        Ok(())
+    }
+
+    fn transitions(&self) -> &[KeyTransition] {
+        &self.transitions
+    }
+
+    fn take_menu_request(&mut self) -> Result<bool, Chip8Error> {
+        Ok(std::mem::take(&mut self.menu))
+    }
+}
+
+/// a square wave, while the buzzer's on
+struct Buzzer {
+    on: bool,
+    volume: f32,
+    phase: f32,
+    step: f32,
+}
+
+impl AudioCallback for Buzzer {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        for sample in out.iter_mut() {
+            *sample = match (self.on, self.phase < 0.5) {
+                (false, _) => 0.0,
+                (true, true) => self.volume,
+                (true, false) => -self.volume,
+            };
+            self.phase = (self.phase + self.step) % 1.0;
+        }
+    }
+}
+
+/// the buzzer, through SDL's audio
+pub struct SdlSound {
+    device: AudioDevice<Buzzer>,
+}
+
+impl Sound for SdlSound {
+    fn beep(&mut self) -> Result<(), Chip8Error> {
+        self.device.lock().on = true;
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<(), Chip8Error> {
+        self.device.lock().on = false;
+        Ok(())
+    }
+
+    fn set_volume(&mut self, volume: Volume) -> Result<(), Chip8Error> {
+        self.device.lock().volume = SDL_TONE_VOLUME * volume.audible() as f32 / 100.0;
+        Ok(())
+    }
+}
+
+/// a window drawn, read and beeped through SDL2
+pub struct SdlPlatform {
+    display: SdlDisplay,
+    input: SdlInput,
+    sound: SdlSound,
+    whereabouts: Whereabouts,
+}
+
+impl SdlPlatform {
+    /// open the window. keymap's None when keys come from somewhere else
+    pub fn new(keymap: Option<Keymap>, options: TerminalOptions) -> Result<Self, Chip8Error> {
+        let sdl = sdl2::init().map_err(sdl_error)?;
+        let video = sdl.video().map_err(sdl_error)?;
+        // where it was left, if it's been opened before
+        let (width, height) = options
+            .window
+            .map_or((64 * SDL_WINDOW_SCALE, 32 * SDL_WINDOW_SCALE), |g| {
+                (g.width, g.height)
+            });
+        let mut window = video.window("chip8", width, height);
+        window.resizable();
+        match options.window {
+            Some(g) => window.position(g.x, g.y),
+            None => window.position_centered(),
+        };
+        let mut window = window.build().map_err(sdl_error)?;
+        if options.window.is_some_and(|g| g.fullscreen) {
+            window
+                .set_fullscreen(FullscreenType::Desktop)
+                .map_err(sdl_error)?;
+        }
+        let canvas = window.into_canvas().build().map_err(sdl_error)?;
+        let whereabouts = Whereabouts::new(options.window);
+        let screen = Rc::new(RefCell::new(Screen {
+            canvas,
+            data: vec![0; 64 * 32 / 8],
+            width: 64,
+            height: 32,
+            scaling: options.scaling,
+            whereabouts: whereabouts.clone(),
+        }));
+
+        let wanted = AudioSpecDesired {
+            freq: Some(44_100),
+            channels: Some(1),
+            samples: None,
+        };
+        let device = sdl
+            .audio()
+            .and_then(|audio| {
+                audio.open_playback(None, &wanted, |spec| Buzzer {
+                    on: false,
+                    volume: SDL_TONE_VOLUME,
+                    phase: 0.0,
+                    step: SDL_TONE_PITCH / spec.freq as f32,
+                })
+            })
+            .map_err(|e| Chip8Error::AudioError(format!("SDL: {}", e)))?;
+        device.resume();
+
+        let mut keys = KeyFilter::new(options.key_repeat);
+        keys.set_assist(options.assist);
+        let events = sdl.event_pump().map_err(sdl_error)?;
+        // the window needs its events read whoever's pressing the keys
+        let input = SdlInput {
+            events,
+            screen: screen.clone(),
+            keymap,
+            keys,
+            transitions: Vec::new(),
+            menu: false,
+        };
+        Ok(SdlPlatform {
+            display: SdlDisplay { screen },
+            input,
+            sound: SdlSound { device },
+            whereabouts,
+        })
+    }
+}
+
+impl Platform for SdlPlatform {
+    fn devices(&mut self) -> (&mut dyn Display, &mut dyn Input, &mut dyn Sound) {
+        (&mut self.display, &mut self.input, &mut self.sound)
+    }
+
+    fn whereabouts(&self) -> Option<Whereabouts> {
+        Some(self.whereabouts.clone())
+    }
+}
@@ -0,0 +1,156 @@
+use crate::error::Chip8Error;
+use crate::memory::{Chip8MemoryMap, MemoryMap};
+
+/// the 4K of RAM a CHIP-8 program can see
+const SEARCH_START: u16 = 0x0000;
+const SEARCH_LEN: usize = 0x1000;
+
+/// how to narrow down a memory search
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SearchFilter {
+    Equal(u8),
+    NotEqual(u8),
+    Greater(u8),
+    Less(u8),
+    /// different from last time we looked
+    Changed,
+    Unchanged,
+    /// bigger than last time we looked
+    Increased,
+    Decreased,
+}
+
+/// decimal, or hex with a 0x prefix
+fn parse_byte(s: &str) -> Option<u8> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u8::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+impl SearchFilter {
+    /// e.g. "= 3", "> 0x10", "changed", "unchanged", "up", "down"
+    pub fn parse(s: &str) -> Result<Self, Chip8Error> {
+        let bad = || Chip8Error::ConfigError(format!("don't know how to search for \"{}\"", s));
+        let mut words = s.split_whitespace();
+        let filter = match (words.next(), words.next()) {
+            (Some("changed"), None) => SearchFilter::Changed,
+            (Some("unchanged"), None) => SearchFilter::Unchanged,
+            (Some("up"), None) => SearchFilter::Increased,
+            (Some("down"), None) => SearchFilter::Decreased,
+            (Some(op), Some(v)) => {
+                let v = parse_byte(v).ok_or_else(bad)?;
+                match op {
+                    "=" | "==" => SearchFilter::Equal(v),
+                    "!=" => SearchFilter::NotEqual(v),
+                    ">" => SearchFilter::Greater(v),
+                    "<" => SearchFilter::Less(v),
+                    _ => return Err(bad()),
+                }
+            }
+            // a bare number is the most common search of all
+            (Some(v), None) => SearchFilter::Equal(parse_byte(v).ok_or_else(bad)?),
+            _ => return Err(bad()),
+        };
+        match words.next() {
+            Some(_) => Err(bad()),
+            None => Ok(filter),
+        }
+    }
+
+    fn matches(&self, before: u8, now: u8) -> bool {
+        match *self {
+            SearchFilter::Equal(v) => now == v,
+            SearchFilter::NotEqual(v) => now != v,
+            SearchFilter::Greater(v) => now > v,
+            SearchFilter::Less(v) => now < v,
+            SearchFilter::Changed => now != before,
+            SearchFilter::Unchanged => now == before,
+            SearchFilter::Increased => now > before,
+            SearchFilter::Decreased => now < before,
+        }
+    }
+}
+
+/// finds where a program keeps things (lives, score...) by repeatedly
+/// narrowing down the addresses whose values behave the right way, Cheat
+/// Engine style
+pub struct MemorySearch {
+    start: u16,
+    // what memory looked like last time we looked
+    snapshot: Vec<u8>,
+    candidates: Vec<u16>,
+}
+
+impl MemorySearch {
+    /// start a search over all of RAM
+    pub fn new(memory: &Chip8MemoryMap) -> Result<Self, Chip8Error> {
+        Self::with_range(memory, SEARCH_START, SEARCH_LEN)
+    }
+
+    pub fn with_range(memory: &Chip8MemoryMap, start: u16, len: usize) -> Result<Self, Chip8Error> {
+        Ok(MemorySearch {
+            start,
+            snapshot: memory.get_ro_slice(start, len)?.to_vec(),
+            candidates: (0..len).map(|a| start + a as u16).collect(),
+        })
+    }
+
+    /// keep only the addresses that match, returning how many are left
+    pub fn refine(
+        &mut self,
+        memory: &Chip8MemoryMap,
+        filter: SearchFilter,
+    ) -> Result<usize, Chip8Error> {
+        let now = memory.get_ro_slice(self.start, self.snapshot.len())?;
+        let start = self.start;
+        let snapshot = &self.snapshot;
+        self.candidates.retain(|a| {
+            let i = (a - start) as usize;
+            filter.matches(snapshot[i], now[i])
+        });
+        self.snapshot.copy_from_slice(now);
+        Ok(self.candidates.len())
+    }
+
+    /// the addresses still in the running
+    pub fn candidates(&self) -> &[u16] {
+        &self.candidates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() -> Result<(), Chip8Error> {
+        assert_eq!(SearchFilter::parse("3")?, SearchFilter::Equal(3));
+        assert_eq!(SearchFilter::parse("= 0x10")?, SearchFilter::Equal(0x10));
+        assert_eq!(SearchFilter::parse("> 4")?, SearchFilter::Greater(4));
+        assert_eq!(SearchFilter::parse("changed")?, SearchFilter::Changed);
+        assert_eq!(SearchFilter::parse("down")?, SearchFilter::Decreased);
+        assert!(SearchFilter::parse("").is_err());
+        assert!(SearchFilter::parse("~ 3").is_err());
+        assert!(SearchFilter::parse("= 300").is_err());
+        assert!(SearchFilter::parse("= 3 4").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_lives() -> Result<(), Chip8Error> {
+        let mut m = Chip8MemoryMap::new()?;
+        m.write(&[3, 3], 0x300, 2)?;
+        let mut s = MemorySearch::new(&m)?;
+        assert_eq!(s.candidates().len(), 0x1000);
+
+        s.refine(&m, SearchFilter::Equal(3))?;
+        assert!(s.candidates().contains(&0x300));
+        // lose a life
+        m.write(&[2], 0x300, 1)?;
+        assert_eq!(s.refine(&m, SearchFilter::Decreased)?, 1);
+        assert_eq!(s.candidates(), &[0x300]);
+        assert_eq!(s.refine(&m, SearchFilter::Unchanged)?, 1);
+        Ok(())
+    }
+}
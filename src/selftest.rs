@@ -0,0 +1,233 @@
+//! # self-test
+//!
+//! `chip8 selftest`: checks on the emulator itself, rather than on a ROM.
+//! so far that's a determinism audit: the same ROM with the same keys and
+//! the same seed has to do exactly the same thing every time, or replays,
+//! netplay, --compare and the optimiser's checking all quietly stop
+//! working. it's easy to break by accident (something seeded from the
+//! clock, or a host key getting through to the program), so the audit runs
+//! a ROM that does a bit of everything twice, a few different ways, and
+//! compares every instruction and every frame.
+//!
+//! then everything that's ever made the emulator panic is played back (see
+//! the corpus module), to check it doesn't any more
+use crate::asm;
+use crate::corpus::Case;
+use crate::differential;
+use crate::error::Chip8Error;
+use crate::interpreter::Chip8Interpreter;
+use crate::replay::{FrameHasher, PlaybackInput};
+use crate::sound::Mute;
+use crate::trace::{TraceEntry, Tracer};
+use std::fmt;
+
+/// how long each run lasts
+const SELFTEST_FRAMES: u64 = 600;
+/// the seed both runs start from
+const SELFTEST_SEED: u16 = 0x8a51;
+
+/// something of everything that could come out differently from one run
+/// to the next: random numbers, keys held and waited for, the timers,
+/// collisions
+const SELFTEST_SOURCE: &str = "
+: main
+  clear
+: frame
+  # a random digit somewhere random
+  v0 := random 0x3f
+  v1 := random 0x1f
+  v2 := random 0xf
+  i := hex v2
+  sprite v0 v1 5
+  # a count of the frames 5's held for, in the corner
+  v3 := 5
+  if v3 key then v4 += 1
+  i := score
+  bcd v4
+  load v2
+  i := hex v2
+  v0 := 0
+  v1 := 0
+  sprite v0 v1 5
+  # waiting for a key when 7's held
+  v3 := 7
+  if v3 key then v5 := key
+  # and a beep, and a wait for the timer
+  v0 := 2
+  delay := v0
+  buzzer := v0
+  loop
+    v0 := delay
+    while v0 != 0
+  again
+  jump frame
+: score
+  0 0 0
+";
+
+/// the keys held through the self-test's runs: 5 now and then, and 7
+/// less often
+fn selftest_keys() -> Vec<Option<u8>> {
+    (0..SELFTEST_FRAMES)
+        .map(|frame| match frame % 45 {
+            0..=9 => Some(0x5),
+            30..=32 => Some(0x7),
+            _ => None,
+        })
+        .collect()
+}
+
+/// how a machine's set up before it runs, e.g. with a quirk turned on
+pub type Setup = fn(&mut Chip8Interpreter);
+
+/// every instruction run, for comparing afterwards
+#[derive(Default)]
+struct TraceLog(Vec<TraceEntry>);
+
+impl Tracer for TraceLog {
+    fn trace(&mut self, entry: &TraceEntry) -> Result<(), Chip8Error> {
+        self.0.push(entry.clone());
+        Ok(())
+    }
+}
+
+/// what a run did: every instruction, a hash of every frame, and how it
+/// stopped if it did
+struct Run {
+    trace: Vec<TraceEntry>,
+    frames: Vec<u64>,
+    stopped: Option<String>,
+}
+
+fn run(rom: &[u8], keys: &[Option<u8>], frames: u64, setup: Setup) -> Result<Run, Chip8Error> {
+    let mut display = FrameHasher::new(None);
+    let mut input = PlaybackInput::new(keys.to_vec());
+    let mut sound = Mute::new();
+    let mut log = TraceLog::default();
+    let stopped = {
+        let mut machine = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+        machine.set_seed(SELFTEST_SEED);
+        setup(&mut machine);
+        machine.load_program(&mut &rom[..])?;
+        machine.add_tracer(&mut log);
+        machine.run_frames(frames).err().map(|e| e.to_string())
+    };
+    Ok(Run {
+        trace: log.0,
+        frames: display.hashes().to_vec(),
+        stopped,
+    })
+}
+
+/// run rom twice on keys for frames, set up the same way both times, and
+/// say how the second run went differently if it did
+pub fn determinism(
+    rom: &[u8],
+    keys: &[Option<u8>],
+    frames: u64,
+    setup: Setup,
+) -> Result<(), Chip8Error> {
+    let first = run(rom, keys, frames, setup)?;
+    let second = run(rom, keys, frames, setup)?;
+    let differs = |what: String| Err(Chip8Error::TestFailure(format!("the second run {}", what)));
+    if let Some((_, d)) = differential::trace_divergence(&first.trace, &second.trace) {
+        return differs(format!("{}", d));
+    }
+    if first.trace.len() != second.trace.len() {
+        return differs(format!(
+            "ran {} instructions, not {}",
+            second.trace.len(),
+            first.trace.len()
+        ));
+    }
+    if let Some(n) = (0..first.frames.len().max(second.frames.len()))
+        .find(|n| first.frames.get(*n) != second.frames.get(*n))
+    {
+        return differs(format!("drew something different in frame {}", n));
+    }
+    if first.stopped != second.stopped {
+        return differs(format!(
+            "stopped with {}, not {}",
+            second.stopped.as_deref().unwrap_or("nothing"),
+            first.stopped.as_deref().unwrap_or("nothing")
+        ));
+    }
+    Ok(())
+}
+
+/// one of the self-test's checks, and how it went
+pub struct Check {
+    pub name: String,
+    pub result: Result<(), Chip8Error>,
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.result {
+            Ok(()) => write!(f, "{}: ok", self.name),
+            Err(e) => write!(f, "{}: FAILED\n  {}", self.name, e),
+        }
+    }
+}
+
+/// all of the self-test's checks, with corpus played back
+pub fn selftest(corpus: &[Case]) -> Result<Vec<Check>, Chip8Error> {
+    let rom = asm::assemble("selftest", SELFTEST_SOURCE)?.rom;
+    let keys = selftest_keys();
+    let setups: [(&'static str, Setup); 3] = [
+        ("determinism", |_| {}),
+        ("determinism, split instructions", |m| {
+            m.set_split_instructions(true)
+        }),
+        ("determinism, shear", |m| m.set_shear(true)),
+    ];
+    let determinism = setups.into_iter().map(|(name, setup)| Check {
+        name: name.to_string(),
+        result: determinism(&rom, &keys, SELFTEST_FRAMES, setup),
+    });
+    let corpus = corpus.iter().map(|case| Check {
+        name: format!("corpus, {}", case.name),
+        result: case.replay(),
+    });
+    Ok(determinism.chain(corpus).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU16, Ordering};
+
+    #[test]
+    fn test_selftest() -> Result<(), Chip8Error> {
+        let checks = selftest(&Case::builtin())?;
+        for check in &checks {
+            assert!(check.result.is_ok(), "{}", check);
+        }
+        assert!(checks.iter().any(|c| c.name == "corpus, empty"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_workout() -> Result<(), Chip8Error> {
+        // it does get as far as waiting for a key, and carries on after
+        let rom = asm::assemble("selftest", SELFTEST_SOURCE)?.rom;
+        let run = run(&rom, &selftest_keys(), SELFTEST_FRAMES, |_| {})?;
+        let waits = run.trace.iter().filter(|e| e.opcode == 0xf50a).count();
+        assert!(waits > 1, "{} waits", waits);
+        assert!(run.trace.last().is_some_and(|e| e.frame > 500));
+        assert_eq!(run.stopped, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_nondeterminism() -> Result<(), Chip8Error> {
+        // a random number, drawn, with a seed that's different every run
+        static SEED: AtomicU16 = AtomicU16::new(0);
+        let rom = [0xc0, 0xff, 0xf0, 0x29, 0xd1, 0x15, 0x12, 0x00];
+        assert!(determinism(&rom, &[], 10, |_| {}).is_ok());
+        let reseed = |m: &mut Chip8Interpreter| m.set_seed(SEED.fetch_add(1, Ordering::Relaxed));
+        let e = determinism(&rom, &[], 10, reseed).unwrap_err();
+        assert!(e.to_string().contains("second run"), "{}", e);
+        Ok(())
+    }
+}
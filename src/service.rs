@@ -0,0 +1,1105 @@
+//! # emulator as a service
+//!
+//! chip8 serve ADDR runs machines for whoever asks over HTTP, for a web
+//! playground to draw, or a course's grader to check a student's ROM
+//! does what it should. it's the stable API (see stable.rs) with a socket
+//! in front, so it's every bit as deterministic: nothing runs until a
+//! client asks for frames, and a machine made with a seed does the same
+//! thing every time
+//!
+//! * POST /machines makes one, with a JSON body of how it's set up, e.g.
+//!   {"quirks": "chip48", "schip": false, "seed": 1} (all optional), and
+//!   answers with its id
+//! * GET /machines lists them, and DELETE /machines/ID gets rid of one
+//! * PUT /machines/ID/rom loads the body (the ROM's bytes) and starts over
+//! * POST /machines/ID/keys presses or lets go of a key, e.g.
+//!   {"key": 5, "down": true}
+//! * POST /machines/ID/run runs some frames, e.g. {"frames": 60} (one if
+//!   there's no body), and answers with the state
+//! * GET /machines/ID/state is the state: the frames run, whether it's
+//!   beeping and the screen, as rows of '#' and '.'
+//! * GET /machines/ID/frame.png is the screen as a PNG, a pixel a pixel
+//! * GET /metrics is how the server's getting on, in Prometheus' format
+//!
+//! anything that goes wrong comes back as {"error": "..."}.
+//!
+//! requests are answered at once, each on a thread of its own, so one
+//! class's worth of machines can run side by side. to keep any one of
+//! them from hogging the host there are Limits: how many machines there
+//! can be, and, for each, how many frames it can run a second and how much
+//! host time it can take doing it. a run asking for more than's left runs
+//! what it can (the state's frames say how many), and one with nothing
+//! left is told to come back later with a 429. a machine nobody's asked
+//! about for a while is got rid of, and so is a client that takes too long
+//! sending its request, or sends too much of one. only pages from the
+//! origins it's told about (--serve-origin) can call it from a browser,
+//! but anything else on the network can, so it's one for a trusted
+//! network, not the open internet
+use crate::error::Chip8Error;
+use crate::metrics::metric;
+use crate::png::{self, Picture};
+use crate::stable::{Config, Emulator, Frame, KeyEvent};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, TryLockError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// the biggest body worth reading: a ROM fills 3.5K, so this is plenty
+const SERVICE_MAX_BODY: usize = 0x10000;
+
+/// the most frames one request can run, so no one client hogs the server
+const SERVICE_MAX_FRAMES: u32 = 3600;
+
+/// how long a client gets to send its request before it's given up on,
+/// however slowly it's trickling in
+const SERVICE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// the most a request's line and headers can come to, and how many headers
+/// there can be: far more than any client needs
+const SERVICE_MAX_HEAD: usize = 0x2000;
+const SERVICE_MAX_HEADERS: usize = 64;
+
+/// how long to wait for the rest of a request that wasn't read, before
+/// closing the connection
+const SERVICE_LINGER: Duration = Duration::from_millis(100);
+
+/// how often idle machines are looked for
+const SERVICE_SWEEP: Duration = Duration::from_secs(10);
+
+/// an HTTP request, as far as the service cares
+#[derive(Debug, Clone, PartialEq)]
+pub struct Request {
+    pub method: String,
+    /// without any query
+    pub path: String,
+    pub body: Vec<u8>,
+    /// the page it came from, if it's a browser's
+    pub origin: Option<String>,
+}
+
+impl Request {
+    pub fn new(method: &str, path: &str, body: &[u8]) -> Self {
+        Request {
+            method: method.to_string(),
+            path: path.split('?').next().unwrap_or_default().to_string(),
+            body: body.to_vec(),
+            origin: None,
+        }
+    }
+}
+
+/// what to send back
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    fn json(status: u16, value: &impl Serialize) -> Self {
+        Response {
+            status,
+            content_type: "application/json",
+            body: serde_json::to_vec(value).unwrap_or_default(),
+        }
+    }
+
+    fn error(status: u16, message: impl ToString) -> Self {
+        Response::json(
+            status,
+            &ErrorBody {
+                error: message.to_string(),
+            },
+        )
+    }
+
+    fn empty(status: u16) -> Self {
+        Response {
+            status,
+            content_type: "text/plain",
+            body: Vec::new(),
+        }
+    }
+
+    fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            201 => "Created",
+            204 => "No Content",
+            400 => "Bad Request",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            408 => "Request Timeout",
+            409 => "Conflict",
+            413 => "Payload Too Large",
+            422 => "Unprocessable Entity",
+            429 => "Too Many Requests",
+            431 => "Request Header Fields Too Large",
+            500 => "Internal Server Error",
+            503 => "Service Unavailable",
+            _ => "",
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    error: String,
+}
+
+#[derive(Serialize)]
+struct Created {
+    id: u64,
+}
+
+/// how a machine's set up, as POST /machines takes it
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+struct Setup {
+    quirks: Option<String>,
+    schip: bool,
+    seed: Option<u16>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct KeyChange {
+    key: u8,
+    down: bool,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+struct Run {
+    frames: u32,
+}
+
+impl Default for Run {
+    fn default() -> Self {
+        Run { frames: 1 }
+    }
+}
+
+/// how a machine's getting on, as GET /machines/ID/state answers
+#[derive(Serialize, Debug, PartialEq)]
+pub struct State {
+    pub id: u64,
+    /// frames run since the ROM was loaded
+    pub frames: u64,
+    pub beeping: bool,
+    pub width: usize,
+    pub height: usize,
+    /// a row a string, '#' for a lit pixel and '.' for an unlit one
+    pub screen: Vec<String>,
+}
+
+/// a machine, and the ROM it's running (once it's been given one)
+struct Machine {
+    config: Config,
+    emulator: Option<Emulator>,
+    frame: Option<Frame>,
+    frames: u64,
+    budget: Budget,
+    /// when it was last asked about
+    used: Instant,
+}
+
+impl Machine {
+    fn state(&self, id: u64) -> State {
+        let frame = self.frame.as_ref();
+        let (width, height) = frame.map_or((64, 32), |f| (f.width(), f.height()));
+        let screen = (0..height)
+            .map(|y| {
+                (0..width)
+                    .map(|x| match frame.is_some_and(|f| f.pixel(x, y)) {
+                        true => '#',
+                        false => '.',
+                    })
+                    .collect()
+            })
+            .collect();
+        State {
+            id,
+            frames: self.frames,
+            beeping: self.emulator.as_ref().is_some_and(|e| e.beeping()),
+            width,
+            height,
+            screen,
+        }
+    }
+
+    fn emulator(&mut self) -> Result<&mut Emulator, Response> {
+        self.emulator
+            .as_mut()
+            .ok_or_else(|| Response::error(409, "load a ROM first"))
+    }
+
+    fn png(&self) -> Vec<u8> {
+        let state = self.state(0);
+        let mut picture = Picture::new(state.width, state.height, 0);
+        for (y, row) in state.screen.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                if c == '#' {
+                    picture.set(x, y, 0xff);
+                }
+            }
+        }
+        png::write(&picture)
+    }
+}
+
+/// how much any one machine (and all of them) can have
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+    /// machines at once
+    pub sessions: usize,
+    /// requests being answered at once
+    pub connections: usize,
+    /// frames a machine can run a second, on average; it can save up a
+    /// second's worth
+    pub frames_per_second: u32,
+    /// host time a machine can take a second running its frames, saved up
+    /// the same way
+    pub cpu_per_second: Duration,
+    /// a machine nobody's asked about for this long is got rid of
+    pub idle: Duration,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            sessions: 64,
+            connections: 32,
+            // ten times as fast as a VIP, which is plenty to grade with
+            frames_per_second: 600,
+            cpu_per_second: Duration::from_millis(250),
+            idle: Duration::from_secs(600),
+        }
+    }
+}
+
+/// what a machine's got left to spend, filling back up as time goes by
+#[derive(Debug)]
+struct Budget {
+    frames: f64,
+    cpu: Duration,
+    filled: Instant,
+}
+
+impl Budget {
+    fn full(limits: &Limits, now: Instant) -> Self {
+        Budget {
+            frames: limits.frames_per_second as f64,
+            cpu: limits.cpu_per_second,
+            filled: now,
+        }
+    }
+
+    fn fill(&mut self, limits: &Limits, now: Instant) {
+        let seconds = now.saturating_duration_since(self.filled).as_secs_f64();
+        let fps = limits.frames_per_second as f64;
+        self.frames = (self.frames + seconds * fps).min(fps);
+        self.cpu =
+            (self.cpu + limits.cpu_per_second.mul_f64(seconds.min(1.0))).min(limits.cpu_per_second);
+        self.filled = now;
+    }
+
+    /// how long until there's a frame to spend, in whole seconds
+    fn wait(&self, limits: &Limits) -> u64 {
+        let frames = match limits.frames_per_second {
+            0 => f64::INFINITY,
+            fps => (1.0 - self.frames).max(0.0) / fps as f64,
+        };
+        let cpu = match limits.cpu_per_second.is_zero() {
+            true => f64::INFINITY,
+            false => match self.cpu.is_zero() {
+                true => 1.0,
+                false => 0.0,
+            },
+        };
+        frames.max(cpu).ceil().min(u64::MAX as f64) as u64
+    }
+}
+
+/// what's been going on, for GET /metrics
+#[derive(Default)]
+struct Counters {
+    requests: AtomicU64,
+    created: AtomicU64,
+    expired: AtomicU64,
+    frames: AtomicU64,
+    throttled: AtomicU64,
+}
+
+/// the machines being run, by id. each has a lock of its own, so
+/// different machines run at once, on different requests' threads
+pub struct Service {
+    limits: Limits,
+    /// the pages a browser's let call it from, e.g. "https://example.com"
+    origins: Vec<String>,
+    machines: Mutex<BTreeMap<u64, Arc<Mutex<Machine>>>>,
+    next_id: AtomicU64,
+    counters: Counters,
+}
+
+/// an empty body's the same as {}
+fn parse<'a, T: Deserialize<'a> + Default>(body: &'a [u8]) -> Result<T, Response> {
+    match body.iter().all(u8::is_ascii_whitespace) {
+        true => Ok(T::default()),
+        false => serde_json::from_slice(body).map_err(|e| Response::error(400, e)),
+    }
+}
+
+impl Service {
+    pub fn new(limits: Limits) -> Self {
+        Service {
+            limits,
+            origins: Vec::new(),
+            machines: Mutex::new(BTreeMap::new()),
+            next_id: AtomicU64::new(0),
+            counters: Counters::default(),
+        }
+    }
+
+    /// let pages from origins call it from a browser, as well as anything
+    /// that isn't a browser
+    pub fn with_origins(mut self, origins: Vec<String>) -> Self {
+        self.origins = origins;
+        self
+    }
+
+    /// the origin to tell a browser it can read the response from, if the
+    /// request came from one it can
+    fn allowed<'a>(&self, request: &'a Request) -> Option<&'a str> {
+        request
+            .origin
+            .as_deref()
+            .filter(|o| self.origins.iter().any(|allowed| allowed == o))
+    }
+
+    /// answer one request
+    pub fn handle(&self, request: &Request) -> Response {
+        self.handle_at(request, Instant::now())
+    }
+
+    /// answer one request as if it's now (all the budgets and expiry go by)
+    pub fn handle_at(&self, request: &Request, now: Instant) -> Response {
+        self.counters.requests.fetch_add(1, Ordering::Relaxed);
+        self.expire(now);
+        match self.route(request, now) {
+            Ok(r) | Err(r) => r,
+        }
+    }
+
+    /// the machines there are. a panic running one of them leaves the list
+    /// as good as it was
+    fn machines(&self) -> MutexGuard<'_, BTreeMap<u64, Arc<Mutex<Machine>>>> {
+        self.machines.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// get rid of the machines that have been idle too long. one that's
+    /// busy (locked) isn't idle, and one that panicked is no use to anyone
+    pub fn expire(&self, now: Instant) {
+        let mut machines = self.machines();
+        let before = machines.len();
+        machines.retain(|_, m| match m.try_lock() {
+            Ok(m) => now.saturating_duration_since(m.used) < self.limits.idle,
+            Err(TryLockError::WouldBlock) => true,
+            Err(TryLockError::Poisoned(_)) => false,
+        });
+        let expired = (before - machines.len()) as u64;
+        self.counters.expired.fetch_add(expired, Ordering::Relaxed);
+    }
+
+    /// everything, in Prometheus' text format
+    pub fn render_metrics(&self) -> String {
+        let get = |n: &AtomicU64| n.load(Ordering::Relaxed);
+        let mut out = String::new();
+        let sessions = self.machines().len();
+        metric(
+            &mut out,
+            "sessions",
+            "gauge",
+            "Machines being run.",
+            sessions,
+        );
+        let counters = [
+            (
+                "requests_total",
+                "Requests answered.",
+                &self.counters.requests,
+            ),
+            (
+                "sessions_created_total",
+                "Machines made.",
+                &self.counters.created,
+            ),
+            (
+                "sessions_expired_total",
+                "Machines got rid of for being idle.",
+                &self.counters.expired,
+            ),
+            (
+                "frames_total",
+                "Frames run, over every machine.",
+                &self.counters.frames,
+            ),
+            (
+                "throttled_total",
+                "Runs turned down for being over budget.",
+                &self.counters.throttled,
+            ),
+        ];
+        for (name, help, n) in counters {
+            metric(&mut out, name, "counter", help, get(n));
+        }
+        out
+    }
+
+    fn route(&self, request: &Request, now: Instant) -> Result<Response, Response> {
+        let parts: Vec<&str> = request.path.split('/').filter(|p| !p.is_empty()).collect();
+        let (method, body) = (request.method.as_str(), &request.body[..]);
+        match (method, &parts[..]) {
+            // a browser asking whether it may, before it does
+            ("OPTIONS", _) => Ok(Response::empty(204)),
+            ("GET", ["metrics"]) => Ok(Response {
+                status: 200,
+                content_type: "text/plain; version=0.0.4",
+                body: self.render_metrics().into_bytes(),
+            }),
+            ("GET", ["machines"]) => {
+                let machines = self.machines();
+                Ok(Response::json(200, &machines.keys().collect::<Vec<_>>()))
+            }
+            ("POST", ["machines"]) => {
+                let setup: Setup = parse(body)?;
+                let mut config = Config::default();
+                if let Some(q) = setup.quirks {
+                    config.quirks = q;
+                }
+                config.schip = setup.schip;
+                config.seed = setup.seed;
+                // an empty ROM won't load, but one that does nothing will:
+                // check the quirks now rather than when the ROM comes
+                Emulator::new(&[0x12, 0x00], &config).map_err(|e| Response::error(400, e))?;
+                let mut machines = self.machines();
+                if machines.len() >= self.limits.sessions {
+                    return Err(Response::error(
+                        503,
+                        format!("{} machines at once at most", self.limits.sessions),
+                    ));
+                }
+                let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+                let machine = Machine {
+                    config,
+                    emulator: None,
+                    frame: None,
+                    frames: 0,
+                    budget: Budget::full(&self.limits, now),
+                    used: now,
+                };
+                machines.insert(id, Arc::new(Mutex::new(machine)));
+                self.counters.created.fetch_add(1, Ordering::Relaxed);
+                Ok(Response::json(201, &Created { id }))
+            }
+            (_, ["machines"]) => Err(Response::error(405, "GET or POST")),
+            (method, ["machines", id, rest @ ..]) => match id.parse() {
+                Ok(id) => self.machine(id, method, rest, body, now),
+                Err(_) => Err(Response::error(404, format!("no machine {}", id))),
+            },
+            _ => Err(Response::error(404, "try /machines")),
+        }
+    }
+
+    fn machine(
+        &self,
+        id: u64,
+        method: &str,
+        rest: &[&str],
+        body: &[u8],
+        now: Instant,
+    ) -> Result<Response, Response> {
+        if let ("DELETE", []) = (method, rest) {
+            return match self.machines().remove(&id) {
+                Some(_) => Ok(Response::empty(204)),
+                None => Err(Response::error(404, format!("no machine {}", id))),
+            };
+        }
+        // the list's only locked long enough to find the machine, so the
+        // others can carry on while this one runs
+        let machine = self.machines().get(&id).cloned();
+        let Some(machine) = machine else {
+            return Err(Response::error(404, format!("no machine {}", id)));
+        };
+        // one that panicked half way through running can't be trusted to
+        // be in any state at all
+        let Ok(mut machine) = machine.lock() else {
+            self.machines().remove(&id);
+            return Err(Response::error(
+                500,
+                format!("machine {} fell over, so it's been got rid of", id),
+            ));
+        };
+        machine.used = now;
+        match (method, rest) {
+            ("GET", [] | ["state"]) => Ok(Response::json(200, &machine.state(id))),
+            ("PUT" | "POST", ["rom"]) => {
+                let e =
+                    Emulator::new(body, &machine.config).map_err(|e| Response::error(400, e))?;
+                machine.emulator = Some(e);
+                machine.frame = None;
+                machine.frames = 0;
+                Ok(Response::json(200, &machine.state(id)))
+            }
+            ("POST", ["keys"]) => {
+                let change: KeyChange =
+                    serde_json::from_slice(body).map_err(|e| Response::error(400, e))?;
+                if change.key > 0xf {
+                    return Err(Response::error(
+                        400,
+                        format!("there's no key {:#x}", change.key),
+                    ));
+                }
+                machine.emulator()?.key(match change.down {
+                    true => KeyEvent::Down(change.key),
+                    false => KeyEvent::Up(change.key),
+                });
+                Ok(Response::empty(204))
+            }
+            ("POST", ["run"]) => {
+                let run: Run = parse(body)?;
+                if run.frames > SERVICE_MAX_FRAMES {
+                    return Err(Response::error(
+                        400,
+                        format!("{} frames at a time at most", SERVICE_MAX_FRAMES),
+                    ));
+                }
+                machine.emulator()?;
+                let frames = self.spend(&mut machine, run.frames, now)?;
+                self.counters.frames.fetch_add(frames, Ordering::Relaxed);
+                Ok(Response::json(200, &machine.state(id)))
+            }
+            ("GET", ["frame.png"]) => Ok(Response {
+                status: 200,
+                content_type: "image/png",
+                body: machine.png(),
+            }),
+            (_, [] | ["state"] | ["rom"] | ["keys"] | ["run"] | ["frame.png"]) => {
+                Err(Response::error(405, "not with that method"))
+            }
+            _ => Err(Response::error(
+                404,
+                "try state, rom, keys, run or frame.png",
+            )),
+        }
+    }
+
+    /// run up to frames frames, as many as machine's budget runs to, and
+    /// say how many that was. with nothing left to spend, it's a 429
+    fn spend(&self, machine: &mut Machine, frames: u32, now: Instant) -> Result<u64, Response> {
+        let limits = &self.limits;
+        machine.budget.fill(limits, now);
+        let affordable = (machine.budget.frames.max(0.0) as u32).min(frames);
+        if frames > 0 && (affordable == 0 || machine.budget.cpu.is_zero()) {
+            self.counters.throttled.fetch_add(1, Ordering::Relaxed);
+            return Err(Response::error(
+                429,
+                format!("over budget: try again in {}s", machine.budget.wait(limits)),
+            ));
+        }
+        let started = Instant::now();
+        let mut ran = 0;
+        let result = (|| {
+            while ran < affordable && started.elapsed() < machine.budget.cpu {
+                // the frames up to the one that went wrong still count
+                let frame = machine
+                    .emulator()?
+                    .run_frame()
+                    .map_err(|e| Response::error(422, e))?;
+                machine.frame = Some(frame);
+                machine.frames += 1;
+                ran += 1;
+            }
+            Ok(ran as u64)
+        })();
+        machine.budget.frames -= ran as f64;
+        machine.budget.cpu = machine.budget.cpu.saturating_sub(started.elapsed());
+        result
+    }
+}
+
+/// read a request off stream, or say why not. it all has to come within
+/// SERVICE_TIMEOUT, however slowly it's trickling in, and without too much
+/// before the body
+fn read_request(stream: &TcpStream) -> Result<Result<Request, Response>, Chip8Error> {
+    match read_before(stream, Instant::now() + SERVICE_TIMEOUT) {
+        Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+            Ok(Err(Response::error(408, "that took too long")))
+        }
+        read => Ok(read?),
+    }
+}
+
+fn read_before(stream: &TcpStream, deadline: Instant) -> io::Result<Result<Request, Response>> {
+    // however long each read's given, it's never past the deadline
+    let in_time = || match deadline.saturating_duration_since(Instant::now()) {
+        left if left.is_zero() => Err(io::Error::from(ErrorKind::TimedOut)),
+        left => stream.set_read_timeout(Some(left)),
+    };
+    let mut reader = BufReader::new(stream);
+    let mut head = (&mut reader).take(SERVICE_MAX_HEAD as u64);
+    let mut line = String::new();
+    in_time()?;
+    head.read_line(&mut line)?;
+    let (method, path) = match line.split_whitespace().take(2).collect::<Vec<_>>()[..] {
+        [method, path] => (method.to_string(), path.to_string()),
+        _ => return Ok(Err(Response::error(400, "that's not HTTP"))),
+    };
+    let (mut length, mut origin) = (0, None);
+    let mut header = String::new();
+    for headers in 0.. {
+        header.clear();
+        in_time()?;
+        head.read_line(&mut header)?;
+        // cut off before the end of the line: too much, or the client's gone
+        if !header.ends_with('\n') || headers > SERVICE_MAX_HEADERS {
+            return Ok(Err(Response::error(
+                431,
+                format!(
+                    "{} bytes and {} headers at most",
+                    SERVICE_MAX_HEAD, SERVICE_MAX_HEADERS
+                ),
+            )));
+        }
+        if header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            let name = name.trim();
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().unwrap_or(0);
+            } else if name.eq_ignore_ascii_case("origin") {
+                origin = Some(value.trim().to_string());
+            }
+        }
+    }
+    if length > SERVICE_MAX_BODY {
+        return Ok(Err(Response::error(
+            413,
+            format!("{} bytes at most", SERVICE_MAX_BODY),
+        )));
+    }
+    let mut body = Vec::with_capacity(length);
+    while body.len() < length {
+        in_time()?;
+        let start = body.len();
+        body.resize(length, 0);
+        match reader.read(&mut body[start..])? {
+            0 => return Err(ErrorKind::UnexpectedEof.into()),
+            n => body.truncate(start + n),
+        }
+    }
+    let mut request = Request::new(&method, &path, &body);
+    request.origin = origin;
+    Ok(Ok(request))
+}
+
+/// send response, readable from a browser's page at origin if it's one
+/// that's allowed
+fn respond(
+    stream: &mut TcpStream,
+    response: &Response,
+    origin: Option<&str>,
+) -> Result<(), Chip8Error> {
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n",
+        response.status,
+        response.reason(),
+        response.content_type,
+        response.body.len()
+    )?;
+    if let Some(origin) = origin {
+        write!(
+            stream,
+            "Access-Control-Allow-Origin: {}\r\nVary: Origin\r\n\
+             Access-Control-Allow-Methods: GET, POST, PUT, DELETE, OPTIONS\r\n\
+             Access-Control-Allow-Headers: Content-Type\r\n",
+            origin
+        )?;
+    }
+    write!(stream, "Connection: close\r\n\r\n")?;
+    stream.write_all(&response.body)?;
+    Ok(())
+}
+
+fn answer(mut stream: TcpStream, service: &Service) {
+    // a client that goes quiet, or away half way, isn't our problem
+    let _ = stream.set_write_timeout(Some(SERVICE_TIMEOUT));
+    let (response, origin) = match read_request(&stream) {
+        Ok(Ok(request)) => (
+            service.handle(&request),
+            service.allowed(&request).map(str::to_string),
+        ),
+        Ok(Err(response)) => (response, None),
+        Err(_) => return,
+    };
+    let _ = respond(&mut stream, &response, origin.as_deref());
+    // whatever's left of a request that wasn't read is thrown away before
+    // closing, or the client could lose the response to a reset
+    let _ = stream.shutdown(Shutdown::Write);
+    let _ = stream.set_read_timeout(Some(SERVICE_LINGER));
+    let _ = io::copy(
+        &mut (&stream).take(SERVICE_MAX_BODY as u64),
+        &mut io::sink(),
+    );
+}
+
+/// one of the connections being answered, counted for as long as it's kept
+struct Busy(Arc<AtomicUsize>);
+
+impl Busy {
+    fn new(busy: &Arc<AtomicUsize>) -> Self {
+        busy.fetch_add(1, Ordering::Relaxed);
+        Busy(busy.clone())
+    }
+}
+
+impl Drop for Busy {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// answer requests on listener, each on a thread of its own (up to the
+/// limit), for as long as the program runs. idle machines are swept up
+/// every so often, whether or not anyone's asking
+pub fn serve(listener: TcpListener, service: Service) {
+    let service = Arc::new(service);
+    let sweeper = service.clone();
+    thread::spawn(move || loop {
+        thread::sleep(SERVICE_SWEEP);
+        sweeper.expire(Instant::now());
+    });
+    let busy = Arc::new(AtomicUsize::new(0));
+    for mut stream in listener.incoming().flatten() {
+        if busy.load(Ordering::Relaxed) >= service.limits.connections {
+            let full = Response::error(503, "too busy: try again in a bit");
+            let _ = respond(&mut stream, &full, None);
+            continue;
+        }
+        let (service, busy) = (service.clone(), Busy::new(&busy));
+        thread::spawn(move || {
+            // let go of the connection however answering it ends, panics
+            // and all
+            let _busy = busy;
+            answer(stream, &service);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn json(response: &Response) -> serde_json::Value {
+        serde_json::from_slice(&response.body).expect("it's JSON")
+    }
+
+    #[test]
+    fn test_service() {
+        let service = Service::new(Limits::default());
+        let ask =
+            |method, path: &str, body: &[u8]| service.handle(&Request::new(method, path, body));
+        let made = ask("POST", "/machines", br#"{"seed": 1}"#);
+        assert_eq!(made.status, 201);
+        let id = json(&made)["id"].as_u64().expect("an id");
+        let path = format!("/machines/{}", id);
+
+        // nothing runs without a ROM
+        assert_eq!(ask("POST", &format!("{}/run", path), b"").status, 409);
+        // I := the font's 0 (V0's 0); wait for key 5; draw it at 0,0;
+        // then loop
+        let rom = [
+            0xf0, 0x29, 0x61, 0x05, 0xe1, 0x9e, 0x12, 0x04, 0xd0, 0x05, 0x12, 0x0a,
+        ];
+        assert_eq!(ask("PUT", &format!("{}/rom", path), &rom).status, 200);
+        let state = json(&ask("POST", &format!("{}/run", path), br#"{"frames": 3}"#));
+        assert_eq!(state["frames"], 3);
+        assert_eq!(state["width"], 64);
+        assert_eq!(state["screen"][0].as_str().map(|r| &r[..6]), Some("......"));
+
+        let key = |down| format!(r#"{{"key": 5, "down": {}}}"#, down);
+        assert_eq!(
+            ask("POST", &format!("{}/keys", path), key(true).as_bytes()).status,
+            204
+        );
+        // the VIP waits for the next frame to draw
+        let state = json(&ask("POST", &format!("{}/run", path), br#"{"frames": 2}"#));
+        assert_eq!(state["frames"], 5);
+        assert_eq!(state["screen"][0].as_str().map(|r| &r[..6]), Some("####.."));
+        assert_eq!(json(&ask("GET", &format!("{}/state", path), b"")), state);
+
+        let png = ask("GET", &format!("{}/frame.png", path), b"");
+        assert_eq!(png.content_type, "image/png");
+        let picture = png::read(&png.body).expect("a PNG");
+        assert_eq!(
+            (picture.width, picture.height, picture.get(0, 0)),
+            (64, 32, 0xff)
+        );
+
+        assert_eq!(json(&ask("GET", "/machines", b"")), serde_json::json!([id]));
+        assert_eq!(ask("DELETE", &path, b"").status, 204);
+        assert_eq!(ask("GET", &path, b"").status, 404);
+    }
+
+    #[test]
+    fn test_service_errors() {
+        let service = Service::new(Limits::default());
+        let ask =
+            |method, path: &str, body: &[u8]| service.handle(&Request::new(method, path, body));
+        assert_eq!(
+            ask("POST", "/machines", br#"{"quirks": "nonsense"}"#).status,
+            400
+        );
+        assert_eq!(ask("POST", "/machines", b"{\"colour\": 1}").status, 400);
+        assert_eq!(ask("POST", "/machines", b"").status, 201);
+        assert_eq!(
+            ask("POST", "/machines/0/keys", br#"{"key": 16, "down": true}"#).status,
+            400
+        );
+        assert_eq!(ask("PUT", "/machines/0/rom", b"").status, 400);
+        // FFFF isn't anything the interpreter can run
+        assert_eq!(ask("PUT", "/machines/0/rom", &[0xff, 0xff]).status, 200);
+        let failed = ask("POST", "/machines/0/run", b"");
+        assert_eq!(failed.status, 422);
+        assert!(json(&failed)["error"]
+            .as_str()
+            .is_some_and(|e| !e.is_empty()));
+        assert_eq!(
+            ask("POST", "/machines/0/run", b"{\"frames\": 100000}").status,
+            400
+        );
+        assert_eq!(ask("GET", "/machines/7", b"").status, 404);
+        assert_eq!(ask("GET", "/machines/0/nonsense", b"").status, 404);
+        assert_eq!(ask("PATCH", "/machines/0/rom", b"").status, 405);
+        assert_eq!(ask("GET", "/", b"").status, 404);
+    }
+
+    #[test]
+    fn test_panicked() {
+        let service = Service::new(Limits::default());
+        let ask = |method, path: &str| service.handle(&Request::new(method, path, b""));
+        assert_eq!(ask("POST", "/machines").status, 201);
+        assert_eq!(ask("POST", "/machines").status, 201);
+        // a panic with a machine, and with the list, locked
+        let machine = service.machines().get(&0).cloned().expect("it's there");
+        let _ = thread::spawn(move || {
+            let _locked = machine.lock();
+            panic!("running it");
+        })
+        .join();
+        thread::scope(|s| {
+            let _ = s
+                .spawn(|| {
+                    let _locked = service.machines();
+                    panic!("looking for it");
+                })
+                .join();
+        });
+        // the machine's got rid of, and the others carry on
+        assert_eq!(ask("GET", "/machines/0").status, 404);
+        assert_eq!(ask("GET", "/machines").body, b"[1]");
+        assert_eq!(ask("GET", "/machines/1").status, 200);
+        // and a connection's let go of, panic or not
+        let busy = Arc::new(AtomicUsize::new(0));
+        let held = Busy::new(&busy);
+        let _ = thread::spawn(move || {
+            let _busy = held;
+            panic!("answering it");
+        })
+        .join();
+        assert_eq!(busy.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn test_limits() {
+        let limits = Limits {
+            sessions: 2,
+            frames_per_second: 10,
+            idle: Duration::from_secs(60),
+            ..Limits::default()
+        };
+        let service = Service::new(limits);
+        let start = Instant::now();
+        let ask = |method, path: &str, body: &[u8], seconds: f64| {
+            let now = start + Duration::from_secs_f64(seconds);
+            service.handle_at(&Request::new(method, path, body), now)
+        };
+        assert_eq!(ask("POST", "/machines", b"", 0.0).status, 201);
+        assert_eq!(ask("POST", "/machines", b"", 0.0).status, 201);
+        assert_eq!(ask("POST", "/machines", b"", 0.0).status, 503);
+        ask("PUT", "/machines/0/rom", &[0x12, 0x00], 0.0);
+
+        // a second's worth saved up, then half a second's more
+        let run = |seconds| {
+            json(&ask(
+                "POST",
+                "/machines/0/run",
+                br#"{"frames": 25}"#,
+                seconds,
+            ))
+        };
+        assert_eq!(run(0.0)["frames"], 10);
+        let throttled = ask("POST", "/machines/0/run", b"", 0.0);
+        assert_eq!(throttled.status, 429);
+        assert!(json(&throttled)["error"]
+            .as_str()
+            .is_some_and(|e| e.ends_with("1s")));
+        assert_eq!(run(0.5)["frames"], 15);
+
+        // machine 1's not been asked about since it was made, so it goes
+        // first; then machine 0
+        assert_eq!(
+            json(&ask("GET", "/machines", b"", 60.2)),
+            serde_json::json!([0])
+        );
+        assert_eq!(ask("GET", "/machines/0", b"", 120.6).status, 404);
+        assert_eq!(ask("POST", "/machines", b"", 120.6).status, 201);
+
+        let metrics =
+            String::from_utf8(ask("GET", "/metrics", b"", 120.6).body).unwrap_or_default();
+        assert!(metrics.contains("\nchip8_sessions 1\n"));
+        assert!(metrics.contains("\nchip8_sessions_created_total 3\n"));
+        assert!(metrics.contains("\nchip8_sessions_expired_total 2\n"));
+        assert!(metrics.contains("\nchip8_frames_total 15\n"));
+        assert!(metrics.contains("\nchip8_throttled_total 1\n"));
+    }
+
+    #[test]
+    fn test_cpu_limit() {
+        let service = Service::new(Limits {
+            cpu_per_second: Duration::ZERO,
+            ..Limits::default()
+        });
+        let ask =
+            |method, path: &str, body: &[u8]| service.handle(&Request::new(method, path, body));
+        ask("POST", "/machines", b"");
+        ask("PUT", "/machines/0/rom", &[0x12, 0x00]);
+        assert_eq!(ask("POST", "/machines/0/run", b"").status, 429);
+        // asking for nothing is free
+        assert_eq!(
+            ask("POST", "/machines/0/run", br#"{"frames": 0}"#).status,
+            200
+        );
+    }
+
+    #[test]
+    fn test_side_by_side() {
+        // machines on different threads, running at the same time, each
+        // getting the frames it asked for
+        let service = Arc::new(Service::new(Limits::default()));
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let service = service.clone();
+                thread::spawn(move || {
+                    let ask = |method, path: &str, body: &[u8]| {
+                        service.handle(&Request::new(method, path, body))
+                    };
+                    let id = json(&ask("POST", "/machines", b""))["id"]
+                        .as_u64()
+                        .unwrap_or(99);
+                    ask("PUT", &format!("/machines/{}/rom", id), &[0x12, 0x00]);
+                    json(&ask(
+                        "POST",
+                        &format!("/machines/{}/run", id),
+                        br#"{"frames": 30}"#,
+                    ))
+                })
+            })
+            .collect();
+        for t in threads {
+            assert_eq!(t.join().expect("it runs")["frames"], 30);
+        }
+        let machines = json(&service.handle(&Request::new("GET", "/machines", b"")));
+        assert_eq!(machines, serde_json::json!([0, 1, 2, 3]));
+    }
+
+    #[test]
+    fn test_serve() -> Result<(), Chip8Error> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        let service =
+            Service::new(Limits::default()).with_origins(vec!["https://example.com".to_string()]);
+        thread::spawn(move || serve(listener, service));
+        let send = |request: &[u8]| -> Result<Vec<u8>, Chip8Error> {
+            let mut s = TcpStream::connect(addr)?;
+            s.write_all(request)?;
+            let mut response = Vec::new();
+            s.read_to_end(&mut response)?;
+            Ok(response)
+        };
+        let made = send(b"POST /machines HTTP/1.1\r\nContent-Length: 0\r\n\r\n")?;
+        assert!(made.starts_with(b"HTTP/1.1 201 Created\r\n"));
+        assert!(made.ends_with(b"{\"id\":0}"));
+        let rom = b"PUT /machines/0/rom HTTP/1.1\r\nContent-Length: 2\r\n\r\n\x12\x00";
+        assert!(send(rom)?.starts_with(b"HTTP/1.1 200 OK\r\n"));
+        let png = send(b"GET /machines/0/frame.png HTTP/1.1\r\n\r\n")?;
+        assert!(png.windows(4).any(|w| w == b"\x89PNG"));
+        let huge = send(b"PUT /machines/0/rom HTTP/1.1\r\nContent-Length: 999999\r\n\r\n")?;
+        assert!(huge.starts_with(b"HTTP/1.1 413"));
+        // too many headers, or too much of one, is as far as it gets
+        let mut many = b"GET /machines HTTP/1.1\r\n".to_vec();
+        many.extend(b"X-Padding: 1\r\n".repeat(SERVICE_MAX_HEADERS + 1));
+        many.extend(b"\r\n");
+        assert!(send(&many)?.starts_with(b"HTTP/1.1 431"));
+        let mut long = b"GET /machines HTTP/1.1\r\nX-Padding: ".to_vec();
+        long.extend(vec![b'x'; SERVICE_MAX_HEAD]);
+        assert!(send(&long)?.starts_with(b"HTTP/1.1 431"));
+        // a browser can only read it from a page it's been told about
+        let cors = |origin: &str| -> Result<bool, Chip8Error> {
+            let request = format!("GET /machines HTTP/1.1\r\nOrigin: {}\r\n\r\n", origin);
+            let response = String::from_utf8_lossy(&send(request.as_bytes())?).into_owned();
+            Ok(response.contains("Access-Control-Allow-Origin: https://example.com\r\n"))
+        };
+        assert!(cors("https://example.com")?);
+        assert!(!cors("https://example.org")?);
+        assert!(
+            !String::from_utf8_lossy(&send(b"GET /machines HTTP/1.1\r\n\r\n")?)
+                .contains("Access-Control")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_slow_request() -> Result<(), Chip8Error> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        thread::spawn(move || serve(listener, Service::new(Limits::default())));
+        // a header a second keeps each read going, but not the whole request
+        let mut s = TcpStream::connect(addr)?;
+        s.write_all(b"GET /machines HTTP/1.1\r\n")?;
+        let started = Instant::now();
+        s.set_read_timeout(Some(Duration::from_secs(1)))?;
+        let mut response = Vec::new();
+        while started.elapsed() < SERVICE_TIMEOUT * 3 {
+            let _ = s.read_to_end(&mut response);
+            if !response.is_empty() || s.write_all(b"X-Slow: 1\r\n").is_err() {
+                break;
+            }
+        }
+        assert!(
+            response.starts_with(b"HTTP/1.1 408"),
+            "{:?}",
+            started.elapsed()
+        );
+        assert!(started.elapsed() < SERVICE_TIMEOUT * 2);
+        Ok(())
+    }
+}
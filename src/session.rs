@@ -0,0 +1,254 @@
+//! # sessions
+//!
+//! everything needed to pick up where the last run left off: which ROM it
+//! was, the whole machine (so the quirks, the hires mode and the game
+//! itself come back as they were), and how the emulator was set up around
+//! it. --save-session writes one on the way out, and --resume reads it back.
+//! the pause menu's copy and paste share one as text.
+//!
+//! sessions say which version of the format they're in, and ones from
+//! older versions are migrated as they're loaded, so a change to how the
+//! machine's laid out doesn't lose anyone's place
+use crate::clipboard::{base64_decode, base64_encode, SHARE_PREFIX};
+use crate::error::Chip8Error;
+use crate::interpreter::MachineState;
+use crate::paths;
+use crate::persist;
+use crate::storage::Storage;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use toml::value::Table;
+
+/// the format sessions are saved in now. when Session or MachineState
+/// change so older sessions won't load as they are, bump this and add a
+/// migration for the old ones. sessions from before there was a version
+/// are version 1
+pub const SESSION_VERSION: i64 = 2;
+
+/// brings a session up a version, in place
+type Migration = fn(&mut Table) -> Result<(), Chip8Error>;
+
+/// MIGRATIONS[n] takes a session from version n + 1 to n + 2
+const MIGRATIONS: [Migration; 1] = [bytes_as_base64];
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Session {
+    /// rominfo::rom_name, for the keymap, cheats and achievements
+    pub rom_name: String,
+    /// the program as it was loaded, in case its file's gone
+    #[serde(with = "crate::clipboard::as_base64")]
+    pub rom: Vec<u8>,
+    pub speed: f64,
+    pub hud: bool,
+    pub schip: bool,
+    pub state: MachineState,
+}
+
+impl Session {
+    /// in the data directory
+    pub fn default_path() -> PathBuf {
+        paths::session_file()
+    }
+
+    /// the last session saved, if there was one
+    pub fn load(storage: &dyn Storage, path: &Path) -> Result<Option<Self>, Chip8Error> {
+        persist::load_text(storage, path, Self::from_toml)
+    }
+
+    /// write the session to a file, creating its directory if needed
+    pub fn save(&self, storage: &dyn Storage, path: &Path) -> Result<(), Chip8Error> {
+        persist::save_text(storage, path, &self.to_toml()?)
+    }
+
+    /// from any version, migrating it as needed
+    pub fn from_toml(s: &str) -> Result<Self, Chip8Error> {
+        let bad = |e: toml::de::Error| Chip8Error::ConfigError(e.to_string());
+        let mut table: Table = toml::from_str(s).map_err(bad)?;
+        let version = match table.remove("version") {
+            Some(toml::Value::Integer(v)) => v,
+            None => 1,
+            Some(_) => return Err(session_error("its version isn't a number")),
+        };
+        if !(1..=SESSION_VERSION).contains(&version) {
+            return Err(session_error(&format!(
+                "it's version {}, and this emulator only knows up to {}",
+                version, SESSION_VERSION
+            )));
+        }
+        for migrate in &MIGRATIONS[version as usize - 1..] {
+            migrate(&mut table)?;
+        }
+        toml::Value::Table(table).try_into().map_err(bad)
+    }
+
+    /// one line of text to share, e.g. in a bug report (see clipboard.rs)
+    pub fn to_share(&self) -> Result<String, Chip8Error> {
+        Ok(format!(
+            "{}{}",
+            SHARE_PREFIX,
+            base64_encode(self.to_toml()?.as_bytes())
+        ))
+    }
+
+    /// and back, from whatever was pasted
+    pub fn from_share(s: &str) -> Result<Self, Chip8Error> {
+        let data = s.trim().strip_prefix(SHARE_PREFIX).ok_or_else(|| {
+            Chip8Error::ConfigError(format!("a shared state starts with {}", SHARE_PREFIX))
+        })?;
+        let toml = String::from_utf8(base64_decode(data)?)
+            .map_err(|e| Chip8Error::ConfigError(e.to_string()))?;
+        Self::from_toml(&toml)
+    }
+
+    pub fn to_toml(&self) -> Result<String, Chip8Error> {
+        // going through a Value puts the tables after the plain values,
+        // which TOML insists on
+        let mut value =
+            toml::Value::try_from(self).map_err(|e| Chip8Error::ConfigError(e.to_string()))?;
+        if let Some(table) = value.as_table_mut() {
+            table.insert("version".to_string(), SESSION_VERSION.into());
+        }
+        toml::to_string(&value).map_err(|e| Chip8Error::ConfigError(e.to_string()))
+    }
+}
+
+fn session_error(why: &str) -> Chip8Error {
+    Chip8Error::ConfigError(format!("can't load that session: {}", why))
+}
+
+/// 1 to 2: the ROM and memory went from lists of numbers (nearly 200k of
+/// them for the memory) to base64
+fn bytes_as_base64(session: &mut Table) -> Result<(), Chip8Error> {
+    fn encode(value: Option<&mut toml::Value>) -> Result<(), Chip8Error> {
+        let value = value.ok_or_else(|| session_error("it's missing its ROM or memory"))?;
+        let bytes = value
+            .as_array()
+            .and_then(|a| {
+                a.iter()
+                    .map(|b| b.as_integer().and_then(|b| u8::try_from(b).ok()))
+                    .collect::<Option<Vec<u8>>>()
+            })
+            .ok_or_else(|| session_error("its ROM or memory isn't bytes"))?;
+        *value = base64_encode(&bytes).into();
+        Ok(())
+    }
+    encode(session.get_mut("rom"))?;
+    let memory = session
+        .get_mut("state")
+        .and_then(|s| s.get_mut("memory"))
+        .and_then(|m| m.as_table_mut());
+    encode(memory.and_then(|m| m.get_mut("bytes")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::DummyDisplay;
+    use crate::input::DummyInput;
+    use crate::interpreter::Chip8Interpreter;
+    use crate::sound::Mute;
+    use crate::storage::MemoryStorage;
+
+    #[test]
+    fn test_round_trip() -> Result<(), Chip8Error> {
+        // count up in V0 forever
+        let rom = [0x70, 0x01, 0x12, 0x00];
+        let mut display = DummyDisplay;
+        let mut input = DummyInput::new(&[]);
+        let mut sound = Mute::new();
+        let mut a = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+        a.load_program(&mut &rom[..])?;
+        a.run_frames(3)?;
+        let session = Session {
+            rom_name: "count".to_string(),
+            rom: rom.to_vec(),
+            speed: 2.0,
+            hud: true,
+            schip: false,
+            state: a.machine_state().clone(),
+        };
+        let loaded = Session::from_toml(&session.to_toml()?)?;
+        // sharing it as text comes out the same
+        let shared = Session::from_share(&session.to_share()?)?;
+        assert_eq!(shared.to_toml()?, session.to_toml()?);
+        assert!(Session::from_share("chip8-state:!!").is_err());
+        assert!(Session::from_share(&session.to_toml()?).is_err());
+        assert_eq!(loaded.rom_name, "count");
+        assert_eq!(loaded.rom, rom);
+        assert_eq!(loaded.speed, 2.0);
+        assert!(loaded.hud);
+
+        // and the machine carries on as if it had never stopped
+        let mut display = DummyDisplay;
+        let mut input = DummyInput::new(&[]);
+        let mut sound = Mute::new();
+        let mut b = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+        b.restore(loaded.state)?;
+        a.run_frames(5)?;
+        b.run_frames(5)?;
+        assert_eq!(a.pc(), b.pc());
+        assert_eq!(a.v(0), b.v(0));
+        assert_eq!(a.frames(), b.frames());
+        Ok(())
+    }
+
+    #[test]
+    fn test_migrate_from_version_1() -> Result<(), Chip8Error> {
+        let rom = [0x70, 0x01, 0x12, 0x00];
+        let mut display = DummyDisplay;
+        let mut input = DummyInput::new(&[]);
+        let mut sound = Mute::new();
+        let mut a = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+        a.load_program(&mut &rom[..])?;
+        a.run_frames(3)?;
+        let session = Session {
+            rom_name: "count".to_string(),
+            rom: rom.to_vec(),
+            speed: 1.0,
+            hud: false,
+            schip: false,
+            state: a.machine_state().clone(),
+        };
+
+        // make one the way version 1 did: no version, and bytes as lists
+        let mut old: Table = toml::from_str(&session.to_toml()?).unwrap();
+        assert_eq!(old.remove("version"), Some(SESSION_VERSION.into()));
+        let as_list = |v: &toml::Value| -> toml::Value {
+            let bytes = base64_decode(v.as_str().unwrap()).unwrap();
+            bytes
+                .into_iter()
+                .map(i64::from)
+                .collect::<Vec<i64>>()
+                .into()
+        };
+        let rom_list = as_list(&old["rom"]);
+        old.insert("rom".to_string(), rom_list);
+        let mut memory = old["state"]["memory"].as_table().unwrap().clone();
+        let bytes = as_list(&memory["bytes"]);
+        memory.insert("bytes".to_string(), bytes);
+        old.get_mut("state")
+            .and_then(|s| s.as_table_mut())
+            .unwrap()
+            .insert("memory".to_string(), memory.into());
+        let old = toml::to_string(&toml::Value::Table(old)).unwrap();
+        assert!(old.contains("rom = [112, 1, 18, 0]"));
+
+        // it loads, and comes out the same as the new one
+        let loaded = Session::from_toml(&old)?;
+        assert_eq!(loaded.rom, rom);
+        assert_eq!(loaded.to_toml()?, session.to_toml()?);
+        assert!(session.to_toml()?.contains("version = 2"));
+
+        // but not from the future
+        let newer = session.to_toml()?.replace("version = 2", "version = 99");
+        assert!(Session::from_toml(&newer).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_no_session() -> Result<(), Chip8Error> {
+        let storage = MemoryStorage::new();
+        assert!(Session::load(&storage, &Session::default_path())?.is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,164 @@
+//! # changing settings while playing
+//!
+//! the speed, the quirks and the theme can all be changed without stopping
+//! the game: from the command palette, the pause menu, or (the speed) the
+//! hotkeys. each change is kept, so any of them can be undone, and redone
+//! again, until the game's over. then, if anything's different from how
+//! it started, the player's asked whether to keep it for next time (in
+//! the config, for that ROM, and the theme for all of them) or forget it
+use crate::display::Theme;
+use crate::quirks::Quirks;
+
+/// what can be changed, and undone
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Settings {
+    pub speed: f64,
+    pub quirks: Quirks,
+    /// one of Theme::PRESETS, by name, or None for whatever it was started
+    /// with (e.g. --invert's)
+    pub theme: Option<&'static str>,
+}
+
+impl Settings {
+    /// the theme to draw with, starting from the one it was started with
+    /// (inverted, if --invert asked for it)
+    pub fn theme(&self, start: Theme, invert: bool) -> Theme {
+        match self.theme.and_then(|name| Theme::parse(name).ok()) {
+            // blocks or colours, and which way round, are the player's, not
+            // the theme's
+            Some(theme) => {
+                let theme = Theme {
+                    glyphs: start.glyphs,
+                    ..theme
+                };
+                match invert {
+                    true => theme.inverted(),
+                    false => theme,
+                }
+            }
+            None => start,
+        }
+    }
+}
+
+/// each change, and what it was called, e.g. "run at 2x speed"
+pub struct SettingsHistory {
+    start: Settings,
+    now: Settings,
+    // what each change was, and what the settings were before it
+    undo: Vec<(String, Settings)>,
+    // and what each was after it, once it's been undone
+    redo: Vec<(String, Settings)>,
+}
+
+impl SettingsHistory {
+    pub fn new(start: Settings) -> Self {
+        SettingsHistory {
+            start,
+            now: start,
+            undo: Vec::new(),
+            redo: Vec::new(),
+        }
+    }
+
+    pub fn now(&self) -> Settings {
+        self.now
+    }
+
+    /// has anything changed since the start?
+    pub fn changed(&self) -> bool {
+        self.now != self.start
+    }
+
+    /// what changed the settings, and to what. nothing's kept if nothing's
+    /// changed, and there's no redoing anything undone before
+    pub fn change(&mut self, what: &str, to: Settings) {
+        if to != self.now {
+            self.undo.push((what.to_string(), self.now));
+            self.redo.clear();
+            self.now = to;
+        }
+    }
+
+    /// go back to before the last change, if there's been one, saying what
+    /// it was
+    pub fn undo(&mut self) -> Option<(String, Settings)> {
+        let (what, before) = self.undo.pop()?;
+        self.redo.push((what.clone(), self.now));
+        self.now = before;
+        Some((what, before))
+    }
+
+    /// make the last change undone again, if there is one
+    pub fn redo(&mut self) -> Option<(String, Settings)> {
+        let (what, after) = self.redo.pop()?;
+        self.undo.push((what.clone(), self.now));
+        self.now = after;
+        Some((what, after))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_undo() {
+        let start = Settings {
+            speed: 1.0,
+            quirks: Quirks::VIP,
+            theme: None,
+        };
+        let mut history = SettingsHistory::new(start);
+        assert_eq!(history.undo(), None);
+        let fast = Settings {
+            speed: 2.0,
+            ..start
+        };
+        history.change("run at 2x speed", fast);
+        // not a change at all
+        history.change("run at 2x speed", fast);
+        let modern = Settings {
+            quirks: Quirks::MODERN,
+            ..fast
+        };
+        history.change("modern quirks", modern);
+        assert!(history.changed());
+
+        assert_eq!(history.undo(), Some(("modern quirks".to_string(), fast)));
+        assert_eq!(history.undo(), Some(("run at 2x speed".to_string(), start)));
+        assert_eq!(history.undo(), None);
+        assert!(!history.changed());
+        assert_eq!(history.redo(), Some(("run at 2x speed".to_string(), fast)));
+        assert_eq!(history.now(), fast);
+
+        // a new change is the end of redoing
+        let themed = Settings {
+            theme: Some("high-contrast"),
+            ..fast
+        };
+        history.change("use the high-contrast theme", themed);
+        assert_eq!(history.redo(), None);
+        assert_eq!(history.undo().map(|(_, s)| s), Some(fast));
+    }
+
+    #[test]
+    fn test_theme() {
+        let start = Theme {
+            glyphs: true,
+            ..Theme::CLASSIC.inverted()
+        };
+        let mut settings = Settings {
+            speed: 1.0,
+            quirks: Quirks::VIP,
+            theme: None,
+        };
+        assert_eq!(settings.theme(start, true), start);
+        settings.theme = Some("high-contrast");
+        let theme = settings.theme(start, true);
+        assert_eq!(theme.text, Theme::HIGH_CONTRAST.text);
+        assert_eq!(theme.lit, Theme::HIGH_CONTRAST.unlit);
+        assert!(theme.glyphs);
+        assert_eq!(settings.theme(start, false).lit, Theme::HIGH_CONTRAST.lit);
+    }
+}
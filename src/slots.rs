@@ -0,0 +1,166 @@
+//! # savestate slots
+//!
+//! ten places to keep a game for each ROM, to go back to a tricky bit or
+//! try something that might not work. each slot is a session, as
+//! --save-session writes, and a thumbnail of the screen as it was, so the
+//! picker can show what's in each without loading any of them. they're
+//! kept in the data directory, e.g. savestates/invaders/3.toml and 3.thumb
+use crate::error::Chip8Error;
+use crate::paths;
+use crate::session::Session;
+use crate::storage::Storage;
+use crate::thumbnail::{self, THUMBNAIL_BYTES};
+use std::path::{Path, PathBuf};
+
+/// how many slots each ROM has, numbered from 0
+pub const SLOT_COUNT: u8 = 10;
+
+/// how many slots go across the picker
+const SLOT_COLUMNS: usize = 5;
+/// how wide each one is there: small_braille's picture
+const SLOT_WIDTH: usize = 16;
+
+/// one ROM's slots
+pub struct SaveSlots<'s> {
+    storage: &'s dyn Storage,
+    dir: PathBuf,
+}
+
+impl<'s> SaveSlots<'s> {
+    pub fn new(storage: &'s dyn Storage, dir: &Path) -> Self {
+        SaveSlots {
+            storage,
+            dir: dir.to_path_buf(),
+        }
+    }
+
+    /// rom_name's, in the data directory
+    pub fn default_dir(rom_name: &str) -> PathBuf {
+        paths::savestates_dir().join(paths::file_name(rom_name))
+    }
+
+    fn session_path(&self, slot: u8) -> PathBuf {
+        self.dir.join(format!("{}.toml", slot))
+    }
+
+    fn thumbnail_path(&self, slot: u8) -> PathBuf {
+        self.dir.join(format!("{}.thumb", slot))
+    }
+
+    /// keep session in slot, with frame (what's on the screen, in either
+    /// resolution) to show for it, over whatever was there
+    pub fn save(&self, slot: u8, session: &Session, frame: &[u8]) -> Result<(), Chip8Error> {
+        session.save(self.storage, &self.session_path(slot))?;
+        self.storage
+            .write(&self.thumbnail_path(slot), &thumbnail::fit(frame))?;
+        Ok(())
+    }
+
+    /// what's in slot, if anything
+    pub fn load(&self, slot: u8) -> Result<Option<Session>, Chip8Error> {
+        Session::load(self.storage, &self.session_path(slot))
+    }
+
+    /// the screen when slot was saved, if it's got anything in it
+    pub fn thumbnail(&self, slot: u8) -> Option<Vec<u8>> {
+        if !self
+            .storage
+            .exists(&self.session_path(slot))
+            .unwrap_or(false)
+        {
+            return None;
+        }
+        // a slot saved without one (or with a damaged one) still loads
+        match self.storage.read(&self.thumbnail_path(slot)) {
+            Ok(Some(frame)) if frame.len() == THUMBNAIL_BYTES => Some(frame),
+            _ => Some(vec![0; THUMBNAIL_BYTES]),
+        }
+    }
+
+    /// the picker: each slot's number over a little picture of what's in
+    /// it, SLOT_COLUMNS across
+    pub fn picker(&self) -> Vec<String> {
+        let cells: Vec<Vec<String>> = (0..SLOT_COUNT)
+            .map(|slot| match self.thumbnail(slot) {
+                Some(frame) => {
+                    [vec![format!("{}", slot)], thumbnail::small_braille(&frame)].concat()
+                }
+                None => [vec![format!("{} (empty)", slot)], vec![String::new(); 4]].concat(),
+            })
+            .collect();
+        let mut lines = Vec::new();
+        for row in cells.chunks(SLOT_COLUMNS) {
+            if !lines.is_empty() {
+                lines.push(String::new());
+            }
+            for n in 0..row[0].len() {
+                let line: Vec<String> = row
+                    .iter()
+                    .map(|cell| format!("{:width$}", cell[n], width = SLOT_WIDTH))
+                    .collect();
+                lines.push(line.join("  ").trim_end().to_string());
+            }
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::DummyDisplay;
+    use crate::input::DummyInput;
+    use crate::interpreter::Chip8Interpreter;
+    use crate::sound::Mute;
+    use crate::storage::MemoryStorage;
+
+    #[test]
+    fn test_slots() -> Result<(), Chip8Error> {
+        let storage = MemoryStorage::new();
+        let slots = SaveSlots::new(&storage, &SaveSlots::default_dir("count"));
+        assert!(slots.load(3)?.is_none());
+        assert_eq!(slots.thumbnail(3), None);
+
+        // count up in V0 forever
+        let rom = [0x70, 0x01, 0x12, 0x00];
+        let mut display = DummyDisplay;
+        let mut input = DummyInput::new(&[]);
+        let mut sound = Mute::new();
+        let mut machine = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+        machine.load_program(&mut &rom[..])?;
+        machine.run_frames(3)?;
+        let session = Session {
+            rom_name: "count".to_string(),
+            rom: rom.to_vec(),
+            speed: 1.0,
+            hud: false,
+            schip: false,
+            state: machine.machine_state().clone(),
+        };
+        let mut frame = [0u8; 0x400];
+        frame[0] = 0x80;
+        slots.save(3, &session, &frame)?;
+        let loaded = slots.load(3)?.expect("slot 3 was saved");
+        assert_eq!(loaded.to_toml()?, session.to_toml()?);
+        let thumbnail = slots.thumbnail(3).expect("slot 3 was saved");
+        assert_eq!(thumbnail.len(), 0x100);
+        assert_eq!(thumbnail[0], 0x80);
+
+        // two rows of five, each a number and four lines of picture
+        let picker = slots.picker();
+        assert_eq!(picker.len(), 11);
+        assert!(picker[0].starts_with("0 (empty)"));
+        assert!(picker[0].ends_with("3                 4 (empty)"));
+        assert!(picker[1].contains('\u{2801}'));
+        assert!(picker[6].starts_with("5 (empty)"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_default_dir() {
+        // a name from a session file stays in the savestates directory
+        let dir = SaveSlots::default_dir("../../.config");
+        assert_eq!(dir.parent(), Some(paths::savestates_dir().as_path()));
+        assert_eq!(SaveSlots::default_dir("..").parent(), dir.parent());
+    }
+}
@@ -1,35 +1,171 @@
+use crate::error::Chip8Error;
+use crate::interrupt::RefreshRate;
+#[cfg(feature = "full")]
 use beep::beep;
-use std::error::Error;
+use std::io::{self, Read, Write};
+use std::time::Duration;
+
+/// how much louder or quieter each press of the volume keys makes it
+const VOLUME_STEP: u8 = 10;
+
+/// how loud the player wants it, as a percentage, and whether they've
+/// muted it (keeping the percentage for when they unmute)
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Volume {
+    pub level: u8,
+    pub muted: bool,
+}
+
+impl Volume {
+    /// how loud to actually be, as a percentage
+    pub fn audible(&self) -> u8 {
+        match self.muted {
+            true => 0,
+            false => self.level.min(100),
+        }
+    }
+
+    pub fn louder(self) -> Self {
+        Volume {
+            level: self.level.saturating_add(VOLUME_STEP).min(100),
+            ..self
+        }
+    }
+
+    pub fn quieter(self) -> Self {
+        Volume {
+            level: self.level.saturating_sub(VOLUME_STEP),
+            ..self
+        }
+    }
+}
+
+impl Default for Volume {
+    fn default() -> Self {
+        Volume {
+            level: 100,
+            muted: false,
+        }
+    }
+}
 
 pub trait Sound {
-    fn beep(&mut self) -> Result<(), Box<dyn Error>>;
-    fn stop(&mut self) -> Result<(), Box<dyn Error>>;
+    fn beep(&mut self) -> Result<(), Chip8Error>;
+    fn stop(&mut self) -> Result<(), Chip8Error>;
+
+    /// be this loud from now on, including a tone that's already going.
+    /// the interpreter doesn't beep at all while it's 0 (muted or not), so
+    /// anything that can only be on or off can leave this be
+    fn set_volume(&mut self, _volume: Volume) -> Result<(), Chip8Error> {
+        Ok(())
+    }
+
+    /// tell the sound device that an emulated frame has passed
+    fn tick(&mut self) -> Result<(), Chip8Error> {
+        Ok(())
+    }
+
+    /// frames pass this often from now on, for anything that makes a
+    /// frame's worth of sound at a time
+    fn set_refresh_rate(&mut self, _rate: RefreshRate) {}
+
+    /// how long until what the last tick rendered gets heard, for devices
+    /// that queue samples up (None if there's no telling)
+    fn latency(&self) -> Option<Duration> {
+        None
+    }
+
+    /// play one of the emulator's own sounds over the top of the program's,
+    /// if the device can mix them
+    fn cue(&mut self, _cue: UiCue) -> Result<(), Chip8Error> {
+        Ok(())
+    }
+}
+
+/// the emulator's own sounds, as opposed to the program's
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum UiCue {
+    /// something on a menu happened
+    Click,
+    /// something was done and dusted, e.g. a snapshot taken
+    Confirm,
+}
+
+impl UiCue {
+    /// the sound, at volume: (pitch in Hz, ms) notes of square wave
+    pub fn samples(&self, volume: Volume) -> Vec<i16> {
+        let notes: &[(u32, u32)] = match self {
+            UiCue::Click => &[(4000, 5)],
+            UiCue::Confirm => &[(1047, 60), (1568, 90)],
+        };
+        // UI sounds aren't the program's, so muting that doesn't mute them
+        let amplitude = TONE_AMPLITUDE as i32 / 2 * volume.level.min(100) as i32 / 100;
+        let mut samples = Vec::new();
+        for (pitch, ms) in notes {
+            let len = TONE_SAMPLE_RATE * ms / 1000;
+            samples.extend((0..len).map(|n| {
+                let high = (n * pitch * 2 / TONE_SAMPLE_RATE).is_multiple_of(2);
+                // fade out, so it doesn't end in a click of its own
+                let a = amplitude * (len - n) as i32 / len as i32;
+                (if high { a } else { -a }) as i16
+            }));
+        }
+        samples
+    }
 }
 
 const SIMPLEBEEP_PITCH: u16 = 2093; // C
 
+#[cfg(feature = "full")]
 pub struct SimpleBeep {
     is_beeping: bool,
+    volume: Volume,
 }
 
+#[cfg(feature = "full")]
 impl SimpleBeep {
     pub fn new() -> Self {
-        SimpleBeep { is_beeping: false }
+        SimpleBeep {
+            is_beeping: false,
+            volume: Volume::default(),
+        }
     }
 }
 
+#[cfg(feature = "full")]
+impl Default for SimpleBeep {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "full")]
 impl Sound for SimpleBeep {
-    fn beep(&mut self) -> Result<(), Box<dyn Error>> {
-        beep(SIMPLEBEEP_PITCH)?;
+    fn beep(&mut self) -> Result<(), Chip8Error> {
+        // the PC speaker's either on or off, so any volume's full volume
+        if self.volume.audible() > 0 {
+            beep(SIMPLEBEEP_PITCH).map_err(|e| Chip8Error::AudioError(e.to_string()))?;
+        }
         self.is_beeping = true;
         Ok(())
     }
 
-    fn stop(&mut self) -> Result<(), Box<dyn Error>> {
-        beep(0)?;
+    fn stop(&mut self) -> Result<(), Chip8Error> {
+        beep(0).map_err(|e| Chip8Error::AudioError(e.to_string()))?;
         self.is_beeping = false;
         Ok(())
     }
+
+    fn set_volume(&mut self, volume: Volume) -> Result<(), Chip8Error> {
+        let was_audible = self.volume.audible() > 0;
+        self.volume = volume;
+        match (self.is_beeping, was_audible, volume.audible() > 0) {
+            (true, true, false) => beep(0),
+            (true, false, true) => beep(SIMPLEBEEP_PITCH),
+            _ => Ok(()),
+        }
+        .map_err(|e| Chip8Error::AudioError(e.to_string()))
+    }
 }
 
 pub struct Mute {}
@@ -38,12 +174,489 @@ impl Mute {
         Mute {}
     }
 }
+impl Default for Mute {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 impl Sound for Mute {
-    fn beep(&mut self) -> Result<(), Box<dyn Error>> {
+    fn beep(&mut self) -> Result<(), Chip8Error> {
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<(), Chip8Error> {
+        Ok(())
+    }
+}
+
+/// rings the terminal's bell at the start of each tone, for when there's no
+/// speaker beep can get at (in a container, or over SSH). it can't say how
+/// long a tone goes on for, but it does say when one starts
+pub struct TerminalBell {
+    out: Box<dyn Write>,
+    is_beeping: bool,
+    volume: Volume,
+}
+
+impl TerminalBell {
+    pub fn new(out: Box<dyn Write>) -> Self {
+        TerminalBell {
+            out,
+            is_beeping: false,
+            volume: Volume::default(),
+        }
+    }
+}
+
+impl Sound for TerminalBell {
+    fn beep(&mut self) -> Result<(), Chip8Error> {
+        // a program topping the sound timer up is still the same tone. the
+        // bell's as loud as the terminal makes it, or off
+        if !self.is_beeping && self.volume.audible() > 0 {
+            self.out.write_all(b"\x07")?;
+            self.out.flush()?;
+        }
+        self.is_beeping = true;
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<(), Chip8Error> {
+        self.is_beeping = false;
+        Ok(())
+    }
+
+    fn set_volume(&mut self, volume: Volume) -> Result<(), Chip8Error> {
+        self.volume = volume;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "full")]
+/// the best way of beeping there is: a sound card if aplay can get at one,
+/// the speaker if beep can, or else the terminal's bell on stderr (which,
+/// unlike stdout, the display doesn't draw on)
+pub fn best_available(audio_buffer: Option<Duration>) -> Box<dyn Sound> {
+    if let Ok(s) = crate::audio::PipeSound::aplay(audio_buffer) {
+        return Box::new(s);
+    }
+    match beep(0) {
+        Ok(_) => Box::new(SimpleBeep::new()),
+        Err(_) => Box::new(TerminalBell::new(Box::new(io::stderr()))),
+    }
+}
+
+/// sample rate for rendered audio
+pub const TONE_SAMPLE_RATE: u32 = 44_100;
+
+/// how many samples make up one emulated frame, at the VIP's 60Hz
+pub const TONE_SAMPLES_PER_FRAME: u32 = TONE_SAMPLE_RATE / 60;
+
+/// how loud the rendered square wave is
+const TONE_AMPLITUDE: i16 = i16::MAX / 4;
+
+/// the buzzer as a 128-bit pattern played on a loop, each bit a high or low
+/// sample: half and half makes a square wave. XO-CHIP's audio is a pattern
+/// like this too, so it can come through here when that's emulated
+const TONE_PATTERN_BITS: u64 = 128;
+const TONE_SQUARE_PATTERN: [u8; 16] = [
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+/// renders the buzzer into PCM samples a frame at a time, for whatever
+/// wants samples rather than a speaker turned on and off
+pub struct Synth {
+    pattern: [u8; 16],
+    // how many bits of the pattern to play a second
+    rate: u64,
+    // where in the pattern we've got to, in bits * TONE_SAMPLE_RATE
+    phase: u64,
+    is_beeping: bool,
+    // the buzzer was on at some point during this frame
+    beeped_this_frame: bool,
+    volume: Volume,
+    // how long a frame is, and how many there have been at that length
+    refresh_rate: RefreshRate,
+    frames: u64,
+}
+
+impl Synth {
+    /// a square wave at SIMPLEBEEP_PITCH
+    pub fn new() -> Self {
+        Synth {
+            pattern: TONE_SQUARE_PATTERN,
+            rate: SIMPLEBEEP_PITCH as u64 * TONE_PATTERN_BITS,
+            phase: 0,
+            is_beeping: false,
+            beeped_this_frame: false,
+            volume: Volume::default(),
+            refresh_rate: RefreshRate::default(),
+            frames: 0,
+        }
+    }
+
+    /// play pattern instead, rate bits a second
+    pub fn set_pattern(&mut self, pattern: [u8; 16], rate: u32) {
+        self.pattern = pattern;
+        self.rate = rate as u64;
+    }
+
+    pub fn beep(&mut self) {
+        self.is_beeping = true;
+        self.beeped_this_frame = true;
+    }
+
+    pub fn stop(&mut self) {
+        self.is_beeping = false;
+    }
+
+    pub fn set_volume(&mut self, volume: Volume) {
+        self.volume = volume;
+    }
+
+    pub fn volume(&self) -> Volume {
+        self.volume
+    }
+
+    pub fn set_refresh_rate(&mut self, rate: RefreshRate) {
+        self.refresh_rate = rate;
+        self.frames = 0;
+    }
+
+    /// a frame's worth of samples onto out. a beep that started and
+    /// stopped within the frame still gets the whole frame, and the phase
+    /// carries over between frames so there aren't clicks at the joins
+    pub fn render_frame(&mut self, out: &mut Vec<i16>) {
+        let amplitude = (TONE_AMPLITUDE as i32 * self.volume.audible() as i32 / 100) as i16;
+        let period = TONE_PATTERN_BITS * TONE_SAMPLE_RATE as u64;
+        let (rate, n) = (self.refresh_rate, self.frames);
+        let len =
+            rate.samples_before(n + 1, TONE_SAMPLE_RATE) - rate.samples_before(n, TONE_SAMPLE_RATE);
+        self.frames += 1;
+        for _ in 0..len {
+            let bit = (self.phase / TONE_SAMPLE_RATE as u64) as usize;
+            let sample = if !self.beeped_this_frame {
+                0
+            } else if self.pattern[bit / 8] & (0x80 >> (bit % 8)) != 0 {
+                amplitude
+            } else {
+                -amplitude
+            };
+            out.push(sample);
+            self.phase = (self.phase + self.rate) % period;
+        }
+        self.beeped_this_frame = self.is_beeping;
+    }
+}
+
+impl Default for Synth {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// renders the buzzer into PCM samples against emulated frames rather than
+/// the wall clock, so beeps stay the right length however fast or slow the
+/// interpreter runs. useful for recording uncapped runs
+pub struct ToneRecorder {
+    samples: Vec<i16>,
+    synth: Synth,
+}
+
+impl ToneRecorder {
+    pub fn new() -> Self {
+        ToneRecorder {
+            samples: Vec::new(),
+            synth: Synth::new(),
+        }
+    }
+
+    /// everything rendered so far, as mono 16-bit PCM at TONE_SAMPLE_RATE
+    pub fn samples(&self) -> &[i16] {
+        &self.samples
+    }
+
+    /// write everything rendered so far as a WAV file
+    pub fn write_wav(&self, w: &mut impl io::Write) -> Result<(), Chip8Error> {
+        write_wav(w, &self.samples, TONE_SAMPLE_RATE)
+    }
+}
+
+impl Default for ToneRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Sound for ToneRecorder {
+    fn beep(&mut self) -> Result<(), Chip8Error> {
+        self.synth.beep();
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<(), Chip8Error> {
+        self.synth.stop();
+        Ok(())
+    }
+
+    fn set_volume(&mut self, volume: Volume) -> Result<(), Chip8Error> {
+        self.synth.set_volume(volume);
+        Ok(())
+    }
+
+    fn tick(&mut self) -> Result<(), Chip8Error> {
+        self.synth.render_frame(&mut self.samples);
+        Ok(())
+    }
+
+    fn set_refresh_rate(&mut self, rate: RefreshRate) {
+        self.synth.set_refresh_rate(rate);
+    }
+}
+
+/// write mono 16-bit PCM samples as a WAV file
+pub fn write_wav(
+    w: &mut impl io::Write,
+    samples: &[i16],
+    sample_rate: u32,
+) -> Result<(), Chip8Error> {
+    let data_len = 2 * samples.len() as u32;
+    w.write_all(b"RIFF")?;
+    w.write_all(&(36 + data_len).to_le_bytes())?;
+    w.write_all(b"WAVE")?;
+    // format chunk: PCM, 1 channel, 16 bits per sample
+    w.write_all(b"fmt ")?;
+    w.write_all(&16u32.to_le_bytes())?;
+    w.write_all(&1u16.to_le_bytes())?;
+    w.write_all(&1u16.to_le_bytes())?;
+    w.write_all(&sample_rate.to_le_bytes())?;
+    w.write_all(&(sample_rate * 2).to_le_bytes())?;
+    w.write_all(&2u16.to_le_bytes())?;
+    w.write_all(&16u16.to_le_bytes())?;
+    // data chunk
+    w.write_all(b"data")?;
+    w.write_all(&data_len.to_le_bytes())?;
+    for s in samples {
+        w.write_all(&s.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+/// read a PCM WAV file, e.g. one recorded from a real tape, as 16-bit
+/// samples and the sample rate. takes the first channel of stereo files
+pub fn read_wav(r: &mut impl io::Read) -> Result<(Vec<i16>, u32), Chip8Error> {
+    let bad = |why: &str| Chip8Error::AudioError(format!("can't read WAV: {}", why));
+    let mut header = [0; 12];
+    r.read_exact(&mut header)?;
+    if &header[0..4] != b"RIFF" || &header[8..12] != b"WAVE" {
+        return Err(bad("not a RIFF WAVE file"));
+    }
+    let mut format = None;
+    loop {
+        let mut chunk = [0; 8];
+        r.read_exact(&mut chunk)?;
+        let len = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]) as usize;
+        // the length's only the file's say-so, so what's there is read
+        // rather than room made for it up front
+        let mut body = Vec::new();
+        r.by_ref().take(len as u64).read_to_end(&mut body)?;
+        if body.len() < len {
+            return Err(bad("a chunk runs off the end of the file"));
+        }
+        // chunks are padded to an even length, except maybe the last
+        r.by_ref()
+            .take(len as u64 % 2)
+            .read_to_end(&mut Vec::new())?;
+        match &chunk[0..4] {
+            b"fmt " if len >= 16 => {
+                let word = |i: usize| u16::from_le_bytes([body[i], body[i + 1]]);
+                if word(0) != 1 {
+                    return Err(bad("only PCM is supported"));
+                }
+                let rate = u32::from_le_bytes([body[4], body[5], body[6], body[7]]);
+                format = Some((word(2) as usize, rate, word(14)));
+            }
+            b"data" => {
+                let (channels, rate, bits) = format.ok_or_else(|| bad("data before format"))?;
+                if channels == 0 {
+                    return Err(bad("no channels"));
+                }
+                let samples = match bits {
+                    8 => body
+                        .chunks(channels)
+                        .map(|s| ((s[0] as i16) - 0x80) << 8)
+                        .collect(),
+                    16 => body
+                        .chunks(2 * channels)
+                        .filter(|s| s.len() >= 2)
+                        .map(|s| i16::from_le_bytes([s[0], s[1]]))
+                        .collect(),
+                    _ => return Err(bad("only 8 or 16 bit samples are supported")),
+                };
+                return Ok((samples, rate));
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// somewhere for a TerminalBell to ring that a test can look at
+    #[derive(Clone, Default)]
+    struct Shared(std::rc::Rc<std::cell::RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_terminal_bell() -> Result<(), Chip8Error> {
+        let out = Shared::default();
+        let mut bell = TerminalBell::new(Box::new(out.clone()));
+        // once when it starts, however many times it's topped up
+        bell.beep()?;
+        bell.tick()?;
+        bell.beep()?;
+        assert_eq!(*out.0.borrow(), b"\x07");
+        bell.stop()?;
+        bell.beep()?;
+        assert_eq!(*out.0.borrow(), b"\x07\x07");
+        // and not at all when muted
+        bell.stop()?;
+        bell.set_volume(Volume {
+            level: 100,
+            muted: true,
+        })?;
+        bell.beep()?;
+        assert_eq!(*out.0.borrow(), b"\x07\x07");
+        Ok(())
+    }
+
+    #[test]
+    fn test_silent_frame() -> Result<(), Chip8Error> {
+        let mut r = ToneRecorder::new();
+        r.tick()?;
+        assert_eq!(r.samples().len(), TONE_SAMPLES_PER_FRAME as usize);
+        assert!(r.samples().iter().all(|s| *s == 0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_pal_frames() -> Result<(), Chip8Error> {
+        // a second's worth of frames is a second's worth of samples,
+        // whatever the frame rate
+        for (rate, frames) in [(RefreshRate::PAL, 50), (RefreshRate::parse("59.94")?, 5994)] {
+            let mut r = ToneRecorder::new();
+            r.set_refresh_rate(rate);
+            for _ in 0..frames {
+                r.tick()?;
+            }
+            let seconds = frames as f64 / rate.hz();
+            assert_eq!(
+                r.samples().len(),
+                (seconds * TONE_SAMPLE_RATE as f64) as usize
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_beep_lasts_whole_frames() -> Result<(), Chip8Error> {
+        let mut r = ToneRecorder::new();
+        r.beep()?;
+        r.tick()?;
+        r.tick()?;
+        r.stop()?;
+        r.tick()?;
+        r.tick()?;
+        let frame = TONE_SAMPLES_PER_FRAME as usize;
+        assert!(r.samples()[..frame * 3].iter().any(|s| *s != 0));
+        assert!(r.samples()[frame * 3..].iter().all(|s| *s == 0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_short_beep_still_heard() -> Result<(), Chip8Error> {
+        // a beep that starts and stops within a frame still gets a frame
+        let mut r = ToneRecorder::new();
+        r.beep()?;
+        r.stop()?;
+        r.tick()?;
+        assert!(r.samples().iter().any(|s| *s != 0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_volume() -> Result<(), Chip8Error> {
+        let v = Volume::default().quieter().quieter();
+        assert_eq!(v.audible(), 80);
+        assert_eq!(v.louder().louder().louder().audible(), 100);
+        assert_eq!(Volume { muted: true, ..v }.audible(), 0);
+        // the recording gets quieter too
+        let mut r = ToneRecorder::new();
+        r.set_volume(Volume { level: 50, ..v })?;
+        r.beep()?;
+        r.tick()?;
+        let loudest = r.samples().iter().map(|s| s.abs()).max();
+        assert_eq!(loudest, Some(TONE_AMPLITUDE / 2));
+        Ok(())
+    }
+
+    #[test]
+    fn test_pattern() {
+        // a pattern of all 1s, played slowly, is a steady high
+        let mut synth = Synth::new();
+        synth.set_pattern([0xff; 16], 4000);
+        synth.beep();
+        let mut out = Vec::new();
+        synth.render_frame(&mut out);
+        assert!(out.iter().all(|s| *s == TONE_AMPLITUDE));
+    }
+
+    #[test]
+    fn test_cue() {
+        let full = UiCue::Confirm.samples(Volume::default());
+        assert_eq!(full.len(), TONE_SAMPLE_RATE as usize * 150 / 1000);
+        // quieter with the volume, but not muted with the program
+        let quiet = UiCue::Confirm.samples(Volume {
+            level: 50,
+            muted: true,
+        });
+        assert_eq!(quiet[0], full[0] / 2);
+    }
+
+    #[test]
+    fn test_wav_header() -> Result<(), Chip8Error> {
+        let mut out = Vec::new();
+        write_wav(&mut out, &[0, 1, 2], 44_100)?;
+        assert_eq!(out.len(), 44 + 6);
+        assert_eq!(&out[0..4], b"RIFF");
+        assert_eq!(&out[8..16], b"WAVEfmt ");
+        assert_eq!(&out[40..44], &6u32.to_le_bytes());
         Ok(())
     }
 
-    fn stop(&mut self) -> Result<(), Box<dyn Error>> {
+    #[test]
+    fn test_read_wav() -> Result<(), Chip8Error> {
+        let mut out = Vec::new();
+        write_wav(&mut out, &[0, -1, 300], 22_050)?;
+        assert_eq!(read_wav(&mut &out[..])?, (vec![0, -1, 300], 22_050));
+        assert!(read_wav(&mut &b"RIFF\0\0\0\0AVI "[..]).is_err());
+        // no channels, or a chunk longer than the file, are errors
+        let mut none = out.clone();
+        none[22] = 0;
+        assert!(read_wav(&mut &none[..]).is_err());
+        let mut long = out.clone();
+        long[40..44].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(read_wav(&mut &long[..]).is_err());
         Ok(())
     }
 }
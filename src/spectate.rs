@@ -0,0 +1,312 @@
+use crate::error::Chip8Error;
+use crate::input::{Feedback, Focus, Input, SpeedRequest, VolumeRequest};
+use crate::keypad::KeyTransition;
+use crate::metrics::Metrics;
+use crate::replay::{self, Replay, ReplayFrame};
+use std::cell::Cell;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// streams a live run to spectators, in the replay file format. spectators
+/// who turn up late get everything so far, so they can catch up
+pub struct Broadcaster {
+    listener: TcpListener,
+    seed: u16,
+    history: Vec<ReplayFrame>,
+    spectators: Vec<TcpStream>,
+    // told how many are watching
+    metrics: Option<Arc<Metrics>>,
+}
+
+impl Broadcaster {
+    pub fn new(listener: TcpListener, seed: u16) -> Result<Self, Chip8Error> {
+        listener.set_nonblocking(true)?;
+        Ok(Broadcaster {
+            listener,
+            seed,
+            history: Vec::new(),
+            spectators: Vec::new(),
+            metrics: None,
+        })
+    }
+
+    /// keep metrics' count of spectators up to date
+    pub fn set_metrics(&mut self, metrics: Arc<Metrics>) {
+        self.metrics = Some(metrics);
+    }
+
+    fn count(&self) {
+        if let Some(m) = &self.metrics {
+            m.spectators
+                .store(self.spectators.len() as u64, Ordering::Relaxed);
+        }
+    }
+
+    /// let in anyone who's waiting, and bring them up to date
+    pub fn accept(&mut self) -> Result<(), Chip8Error> {
+        loop {
+            let mut s = match self.listener.accept() {
+                Ok((s, _)) => s,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    self.count();
+                    return Ok(());
+                }
+                Err(e) => return Err(e.into()),
+            };
+            s.set_nonblocking(false)?;
+            s.set_nodelay(true)?;
+            let replay = Replay {
+                seed: self.seed,
+                frames: self.history.clone(),
+            };
+            // someone who can't keep up isn't our problem
+            if replay.write(&mut s).is_ok() {
+                self.spectators.push(s);
+            }
+        }
+    }
+
+    /// send a frame to everyone watching
+    pub fn push(&mut self, frame: ReplayFrame) {
+        self.history.push(frame);
+        let mut line = Vec::new();
+        // writing to a Vec can't fail
+        let _ = replay::write_frame(&mut line, &frame);
+        self.spectators.retain_mut(|s| s.write_all(&line).is_ok());
+        self.count();
+    }
+
+    pub fn spectator_count(&self) -> usize {
+        self.spectators.len()
+    }
+}
+
+/// samples another Input once a frame, like RecordingInput, and broadcasts
+/// each frame's key along with the hash of what got drawn. as with
+/// RecordingInput, savestate slots and the command palette don't get
+/// through, or the spectators would see something else
+pub struct BroadcastInput<'a> {
+    inner: &'a mut dyn Input,
+    broadcaster: Broadcaster,
+    latest_hash: &'a Cell<Option<u64>>,
+    latched_key: Option<u8>,
+    // the previous frame's key, waiting for its hash
+    pending: Option<Option<u8>>,
+}
+
+impl<'a> BroadcastInput<'a> {
+    /// latest_hash should be fed by a HashTap on the display
+    pub fn new(
+        inner: &'a mut dyn Input,
+        broadcaster: Broadcaster,
+        latest_hash: &'a Cell<Option<u64>>,
+    ) -> Self {
+        BroadcastInput {
+            inner,
+            broadcaster,
+            latest_hash,
+            latched_key: None,
+            pending: None,
+        }
+    }
+}
+
+impl<'a> Input for BroadcastInput<'a> {
+    fn flush_keys(&mut self) -> Result<(), Chip8Error> {
+        self.latched_key = None;
+        self.inner.flush_keys()
+    }
+
+    fn read_key(&mut self) -> Result<Option<u8>, Chip8Error> {
+        Ok(self.latched_key)
+    }
+
+    fn tick(&mut self) -> Result<(), Chip8Error> {
+        self.broadcaster.accept()?;
+        if let (Some(key), Some(hash)) = (self.pending, self.latest_hash.get()) {
+            self.broadcaster.push(ReplayFrame { key, hash });
+        }
+        self.inner.tick()?;
+        self.latched_key = self.inner.read_key()?;
+        self.pending = Some(self.latched_key);
+        Ok(())
+    }
+
+    fn wait_for_event(&mut self, timeout: Duration) -> Result<bool, Chip8Error> {
+        self.inner.wait_for_event(timeout)
+    }
+
+    fn transitions(&self) -> &[KeyTransition] {
+        self.inner.transitions()
+    }
+
+    fn take_menu_request(&mut self) -> Result<bool, Chip8Error> {
+        self.inner.take_menu_request()
+    }
+
+    fn take_speed_request(&mut self) -> Result<Option<SpeedRequest>, Chip8Error> {
+        self.inner.take_speed_request()
+    }
+
+    fn take_hud_toggle(&mut self) -> Result<bool, Chip8Error> {
+        self.inner.take_hud_toggle()
+    }
+
+    fn take_help_toggle(&mut self) -> Result<bool, Chip8Error> {
+        self.inner.take_help_toggle()
+    }
+
+    fn take_volume_request(&mut self) -> Result<Option<VolumeRequest>, Chip8Error> {
+        self.inner.take_volume_request()
+    }
+
+    fn focus(&self) -> Focus {
+        self.inner.focus()
+    }
+
+    fn set_focus(&mut self, focus: Focus) {
+        self.inner.set_focus(focus)
+    }
+
+    fn feedback(&mut self, feedback: Feedback) {
+        self.inner.feedback(feedback)
+    }
+}
+
+/// plays along with a broadcast, a frame at a time. when the broadcast
+/// ends, tick returns an UnexpectedEof I/O error
+pub struct SpectatorInput {
+    reader: BufReader<TcpStream>,
+    seed: u16,
+    frames: Vec<ReplayFrame>,
+    latched_key: Option<u8>,
+}
+
+impl SpectatorInput {
+    pub fn connect(addr: impl ToSocketAddrs) -> Result<Self, Chip8Error> {
+        let mut reader = BufReader::new(TcpStream::connect(addr)?);
+        let seed = replay::read_header(&mut (&mut reader).lines())?;
+        Ok(SpectatorInput {
+            reader,
+            seed,
+            frames: Vec::new(),
+            latched_key: None,
+        })
+    }
+
+    /// the broadcaster's seed, which we need to use too
+    pub fn seed(&self) -> u16 {
+        self.seed
+    }
+
+    /// what we've seen so far, e.g. to check our frame hashes against
+    pub fn replay(&self) -> Replay {
+        Replay {
+            seed: self.seed,
+            frames: self.frames.clone(),
+        }
+    }
+}
+
+impl Input for SpectatorInput {
+    fn flush_keys(&mut self) -> Result<(), Chip8Error> {
+        self.latched_key = None;
+        Ok(())
+    }
+
+    fn read_key(&mut self) -> Result<Option<u8>, Chip8Error> {
+        Ok(self.latched_key)
+    }
+
+    fn tick(&mut self) -> Result<(), Chip8Error> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "broadcast ended").into());
+        }
+        let frame = replay::parse_frame(line.trim_end(), self.frames.len() + 3)?;
+        self.latched_key = frame.key;
+        self.frames.push(frame);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::DummyInput;
+    use std::net::SocketAddr;
+    use std::thread;
+
+    /// connecting blocks until the broadcaster lets us in
+    fn spectate(addr: SocketAddr) -> thread::JoinHandle<Result<SpectatorInput, Chip8Error>> {
+        thread::spawn(move || SpectatorInput::connect(addr))
+    }
+
+    /// the broadcaster polls for spectators, so keep trying until one's in
+    fn accept_one(b: &mut Broadcaster) -> Result<(), Chip8Error> {
+        while b.spectator_count() == 0 {
+            b.accept()?;
+            thread::yield_now();
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_late_spectator_catches_up() -> Result<(), Chip8Error> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        let mut b = Broadcaster::new(listener, 0xabcd)?;
+        b.push(ReplayFrame {
+            key: Some(1),
+            hash: 10,
+        });
+
+        let s = spectate(addr);
+        accept_one(&mut b)?;
+        let mut s = s.join().unwrap()?;
+        assert_eq!(s.seed(), 0xabcd);
+        b.push(ReplayFrame {
+            key: None,
+            hash: 11,
+        });
+        drop(b);
+
+        s.tick()?;
+        assert_eq!(s.read_key()?, Some(1));
+        s.tick()?;
+        assert_eq!(s.read_key()?, None);
+        assert!(
+            matches!(s.tick(), Err(Chip8Error::Io(e)) if e.kind() == io::ErrorKind::UnexpectedEof)
+        );
+        assert_eq!(s.replay().first_divergence(&[10, 11]), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_broadcast_input_waits_for_hash() -> Result<(), Chip8Error> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        let mut b = Broadcaster::new(listener, 0)?;
+        let s = spectate(addr);
+        accept_one(&mut b)?;
+        let mut s = s.join().unwrap()?;
+        let latest_hash = Cell::new(None);
+        let mut inner = DummyInput::new(&[0x7]);
+        let mut input = BroadcastInput::new(&mut inner, b, &latest_hash);
+
+        input.tick()?;
+        assert_eq!(input.read_key()?, Some(0x7));
+        latest_hash.set(Some(42));
+        // frame 0 only goes out once it's been drawn
+        input.tick()?;
+        drop(input);
+
+        s.tick()?;
+        assert_eq!(s.read_key()?, Some(0x7));
+        assert_eq!(s.replay().frames[0].hash, 42);
+        Ok(())
+    }
+}
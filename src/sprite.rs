@@ -0,0 +1,251 @@
+//! # sprites
+//!
+//! a ROM's graphics, for modding. the analyser knows what gets drawn (what
+//! I's pointed at just before a DXYN), so chip8 sprites game.ch8 sheet.png
+//! lays all of it out on a sheet to be edited in any paint program, and
+//! chip8 inject-sprites game.ch8 sheet.png modded.ch8 puts the sheet back
+//! into a copy of the ROM. the pause menu's sprites command puts it straight
+//! into memory instead, to see it in the game without starting again.
+//!
+//! the sheet's a grid of cells in address order, SPRITE_COLUMNS across,
+//! each big enough for the biggest sprite there is (SUPER-CHIP's 16x16),
+//! with the sprite in its top left corner and the rest grey. it's all drawn
+//! SPRITE_SCALE times bigger than it is, so there's something to see, and
+//! reading it back looks at the middle of each block: lit if it's nearer
+//! white than black
+use crate::analyse;
+use crate::error::Chip8Error;
+use crate::png::Picture;
+use std::fmt;
+
+/// where programs start
+const SPRITE_ORIGIN: u16 = 0x200;
+
+/// how many sprites go across the sheet
+const SPRITE_COLUMNS: usize = 8;
+
+/// how big a cell is, in sprite pixels, and the line between them
+const SPRITE_CELL: usize = 16;
+const SPRITE_GAP: usize = 1;
+
+/// how many pixels of the sheet a sprite's pixel is, each way
+const SPRITE_SCALE: usize = 4;
+
+/// the sheet's shades: the lines between cells, the bits of a cell the
+/// sprite doesn't fill, and the sprite's own pixels
+const SPRITE_GRID: u8 = 0x40;
+const SPRITE_SPARE: u8 = 0x80;
+const SPRITE_LIT: u8 = 0xff;
+const SPRITE_UNLIT: u8 = 0x00;
+
+/// something drawn
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sprite {
+    pub addr: u16,
+    pub rows: u16,
+    /// bytes a row: 1, or 2 for SUPER-CHIP's 16x16
+    pub width: u16,
+}
+
+impl Sprite {
+    /// how many bytes it is
+    pub fn size(&self) -> usize {
+        (self.rows * self.width) as usize
+    }
+
+    fn bytes<'r>(&self, rom: &'r [u8]) -> &'r [u8] {
+        let start = (self.addr - SPRITE_ORIGIN) as usize;
+        &rom[start..start + self.size()]
+    }
+}
+
+impl fmt::Display for Sprite {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:#05x}: {}x{}", self.addr, self.width * 8, self.rows)
+    }
+}
+
+/// the sprites in rom, in address order: everything drawn that's in the ROM
+/// and isn't code (some games draw their own code, for noise)
+pub fn find(rom: &[u8]) -> Vec<Sprite> {
+    let a = analyse::analyse(rom);
+    let end = SPRITE_ORIGIN as usize + rom.len();
+    a.sprites
+        .iter()
+        .map(|(addr, (rows, width))| Sprite {
+            addr: *addr,
+            rows: *rows,
+            width: *width,
+        })
+        .filter(|s| {
+            s.addr >= SPRITE_ORIGIN
+                && s.addr as usize + s.size() <= end
+                // an instruction's two bytes, so one starting just before
+                // counts too
+                && a.code
+                    .range(s.addr.saturating_sub(1)..s.addr + s.size() as u16)
+                    .next()
+                    .is_none()
+        })
+        .collect()
+}
+
+/// where the nth sprite's cell starts, in sprite pixels
+fn cell(n: usize) -> (usize, usize) {
+    let step = SPRITE_CELL + SPRITE_GAP;
+    (
+        SPRITE_GAP + n % SPRITE_COLUMNS * step,
+        SPRITE_GAP + n / SPRITE_COLUMNS * step,
+    )
+}
+
+/// how big a sheet of count sprites is, in the sheet's pixels
+fn sheet_size(count: usize) -> (usize, usize) {
+    let step = SPRITE_CELL + SPRITE_GAP;
+    let across = count.clamp(1, SPRITE_COLUMNS);
+    let down = count.div_ceil(SPRITE_COLUMNS).max(1);
+    (
+        (SPRITE_GAP + across * step) * SPRITE_SCALE,
+        (SPRITE_GAP + down * step) * SPRITE_SCALE,
+    )
+}
+
+/// rom's sprites, laid out on a sheet
+pub fn sheet(rom: &[u8], sprites: &[Sprite]) -> Picture {
+    let (width, height) = sheet_size(sprites.len());
+    let mut picture = Picture::new(width, height, SPRITE_GRID);
+    let mut fill = |x: usize, y: usize, shade: u8| {
+        for dy in 0..SPRITE_SCALE {
+            for dx in 0..SPRITE_SCALE {
+                picture.set(x * SPRITE_SCALE + dx, y * SPRITE_SCALE + dy, shade);
+            }
+        }
+    };
+    for (n, sprite) in sprites.iter().enumerate() {
+        let (left, top) = cell(n);
+        let bytes = sprite.bytes(rom);
+        for y in 0..SPRITE_CELL {
+            for x in 0..SPRITE_CELL {
+                let shade = match bytes.get(y * sprite.width as usize + x / 8) {
+                    Some(b) if x < sprite.width as usize * 8 => match b & (0x80 >> (x % 8)) {
+                        0 => SPRITE_UNLIT,
+                        _ => SPRITE_LIT,
+                    },
+                    _ => SPRITE_SPARE,
+                };
+                fill(left + x, top + y, shade);
+            }
+        }
+    }
+    picture
+}
+
+/// the sprites on an edited sheet that aren't what's in rom any more, and
+/// what they are now
+pub fn changes(
+    picture: &Picture,
+    rom: &[u8],
+    sprites: &[Sprite],
+) -> Result<Vec<(Sprite, Vec<u8>)>, Chip8Error> {
+    let (width, height) = sheet_size(sprites.len());
+    if (picture.width, picture.height) != (width, height) {
+        return Err(Chip8Error::BadImage(format!(
+            "the sheet's {}x{}, but this ROM's {} sprites make a {}x{} one: is it another ROM's?",
+            picture.width,
+            picture.height,
+            sprites.len(),
+            width,
+            height
+        )));
+    }
+    let lit = |x: usize, y: usize| {
+        let middle = SPRITE_SCALE / 2;
+        picture.get(x * SPRITE_SCALE + middle, y * SPRITE_SCALE + middle) > 0x7f
+    };
+    let mut changed = Vec::new();
+    for (n, sprite) in sprites.iter().enumerate() {
+        let (left, top) = cell(n);
+        let mut bytes = vec![0; sprite.size()];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            let (y, x) = (i / sprite.width as usize, i % sprite.width as usize * 8);
+            for bit in 0..8 {
+                if lit(left + x + bit, top + y) {
+                    *byte |= 0x80 >> bit;
+                }
+            }
+        }
+        if bytes != sprite.bytes(rom) {
+            changed.push((*sprite, bytes));
+        }
+    }
+    Ok(changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png;
+
+    /// draws a 3 row sprite, a 16x16 one, and some of its own code
+    #[rustfmt::skip]
+    const ROM: [u8; 56] = [
+        0xa2, 0x12, 0xd0, 0x13, 0xa2, 0x16, 0x00, 0xff,
+        0xd0, 0x10, 0xa2, 0x00, 0xd0, 0x12, 0x12, 0x10,
+        0x12, 0x10,
+        // 0x212
+        0xf0, 0x90, 0xf0, 0x00,
+        // 0x216
+        0x80, 0x01, 0x40, 0x02, 0x20, 0x04, 0x10, 0x08,
+        0x08, 0x10, 0x04, 0x20, 0x02, 0x40, 0x01, 0x80,
+        0x01, 0x80, 0x02, 0x40, 0x04, 0x20, 0x08, 0x10,
+        0x10, 0x08, 0x20, 0x04, 0x40, 0x02, 0x80, 0x01,
+        0xff, 0xff,
+    ];
+
+    #[test]
+    fn test_find() {
+        let sprites = find(&ROM);
+        assert_eq!(
+            sprites.iter().map(|s| s.to_string()).collect::<Vec<_>>(),
+            ["0x212: 8x3", "0x216: 16x16"]
+        );
+    }
+
+    #[test]
+    fn test_sheet_round_trip() -> Result<(), Chip8Error> {
+        let sprites = find(&ROM);
+        let picture = png::read(&png::write(&sheet(&ROM, &sprites)))?;
+        assert_eq!(
+            (picture.width, picture.height),
+            ((1 + 2 * 17) * SPRITE_SCALE, (1 + 17) * SPRITE_SCALE)
+        );
+        // the top left of the first sprite's lit, and its second row has a
+        // hole in it
+        assert_eq!(picture.get(4, 4), SPRITE_LIT);
+        assert_eq!(
+            picture.get(4 + SPRITE_SCALE, 4 + SPRITE_SCALE),
+            SPRITE_UNLIT
+        );
+        assert_eq!(picture.get(0, 0), SPRITE_GRID);
+        assert!(changes(&picture, &ROM, &sprites)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_changes() -> Result<(), Chip8Error> {
+        let sprites = find(&ROM);
+        let mut picture = sheet(&ROM, &sprites);
+        // fill in the first sprite's hole, not quite white and off centre,
+        // as a paint program might
+        let (x, y) = (2 * SPRITE_SCALE, 2 * SPRITE_SCALE);
+        for (dx, dy) in [(0, 0), (1, 1), (2, 2), (2, 1)] {
+            picture.set(x + dx, y + dy, 0xe0);
+        }
+        let changed = changes(&picture, &ROM, &sprites)?;
+        assert_eq!(changed, [(sprites[0], vec![0xf0, 0xd0, 0xf0])]);
+        // a sheet for something else
+        let e = changes(&Picture::new(10, 10, 0), &ROM, &sprites).unwrap_err();
+        assert!(e.to_string().contains("another ROM's"));
+        Ok(())
+    }
+}
@@ -0,0 +1,440 @@
+//! # the stable API
+//!
+//! everything else in this crate changes shape whenever it needs to, which
+//! is often. a frontend that only wants to run a ROM, press its keys and
+//! draw what comes out can use this instead, and it'll keep working from
+//! one release to the next: nothing here changes, or goes, without the
+//! major version going up. there's not much of it on purpose:
+//!
+//! * Emulator: a ROM, running a frame at a time, or each time the
+//!   frontend's own display starts one
+//! * Frames: an Emulator's frames one after another, as fast as they'll go
+//! * Config: how it's set up (quirks, SUPER-CHIP, the random seed)
+//! * KeyEvent: a keypad key going down or coming up
+//! * Frame: what's on the screen after a frame
+//! * Registers: what's in the registers, for a debugger to show
+//!
+//! and Error, for when something goes wrong. none of them give the
+//! internals away, and Config can grow fields without breaking anyone, as
+//! it's made by changing a default rather than spelt out.
+//!
+//! ```
+//! use chip8::stable::{Config, Emulator, KeyEvent};
+//!
+//! // V0 := 1, then skip back over itself for ever
+//! let mut emulator = Emulator::new(&[0x60, 0x01, 0x12, 0x02], &Config::default())?;
+//! emulator.key(KeyEvent::Down(0x5));
+//! let frame = emulator.run_frame()?;
+//! assert_eq!((frame.width(), frame.height()), (64, 32));
+//! assert!(!frame.pixel(0, 0));
+//! # Ok::<(), chip8::stable::Error>(())
+//! ```
+//!
+//! frames run as fast as they can, so it's the frontend's job to run
+//! sixty of them a second.
+//!
+//! some of the interpreter's left out on purpose. raising an interrupt
+//! (Chip8Interpreter::raise_interrupt) is only any use to an opcode
+//! extension, to hear of an External one, and there's no adding those
+//! here, so it's the interpreter's alone
+use crate::display::Display;
+use crate::error::Chip8Error;
+use crate::font::SchipFont;
+use crate::input::Input;
+use crate::interpreter::{Chip8Interpreter, MachineState};
+use crate::memory::MemoryMap;
+use crate::quirks::Quirks;
+use crate::schip::Schip;
+use crate::sound::Mute;
+use std::fmt;
+
+/// how an Emulator's set up: start from the default and change what needs
+/// changing
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct Config {
+    /// which interpreter's quirks to follow: "vip" (the default), "chip48"
+    /// or "modern", with any rarer ones on top, e.g. "vip+add-i-sets-vf"
+    pub quirks: String,
+    /// understand the SUPER-CHIP's extra instructions and 128x64 screen
+    pub schip: bool,
+    /// where the random numbers start, to get the same ones every time, or
+    /// None for different ones
+    pub seed: Option<u16>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            quirks: "vip".to_string(),
+            schip: false,
+            seed: None,
+        }
+    }
+}
+
+/// a key on the hex keypad, 0x0 to 0xf, going down or coming up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyEvent {
+    Down(u8),
+    Up(u8),
+}
+
+/// what's on the screen
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    width: usize,
+    height: usize,
+    // a bit a pixel, a row at a time, leftmost in the top bit
+    data: Vec<u8>,
+}
+
+impl Frame {
+    fn blank(width: usize, height: usize) -> Self {
+        Frame {
+            width,
+            height,
+            data: vec![0; (width * height).div_ceil(8)],
+        }
+    }
+
+    /// a frame of width x height, from data a bit a pixel, a row at a time
+    /// with the leftmost pixel in the top bit (as the interpreter keeps its
+    /// screen), e.g. to look at a screen got some other way. anything data
+    /// is too short for is unlit
+    pub fn new(width: usize, height: usize, data: &[u8]) -> Self {
+        let mut frame = Frame::blank(width, height);
+        let len = frame.data.len().min(data.len());
+        frame.data[..len].copy_from_slice(&data[..len]);
+        // nothing past the last pixel, where rows don't fill the last byte
+        let spare = frame.data.len() * 8 - width * height;
+        if let Some(last) = frame.data.last_mut() {
+            *last &= 0xffu8 << spare;
+        }
+        frame
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// is the pixel x across and y down lit? off the screen is unlit
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        if x >= self.width || y >= self.height {
+            return false;
+        }
+        // rows needn't start on a byte
+        let n = y * self.width + x;
+        self.data
+            .get(n / 8)
+            .is_some_and(|b| b & (0x80 >> (n % 8)) != 0)
+    }
+
+    /// where the lit pixels are, x across and y down, along each row from
+    /// the top
+    pub fn lit(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let width = self.width;
+        self.data
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| **b != 0)
+            .flat_map(move |(n, b)| {
+                (0..8)
+                    .filter(move |bit| b & (0x80 >> bit) != 0)
+                    .map(move |bit| ((n * 8 + bit) % width, (n * 8 + bit) / width))
+            })
+    }
+
+    /// how many pixels are lit
+    pub fn lit_count(&self) -> usize {
+        self.data.iter().map(|b| b.count_ones() as usize).sum()
+    }
+
+    /// the smallest rectangle with every lit pixel in, as x, y, width and
+    /// height, or None if nothing's lit
+    pub fn bounds(&self) -> Option<(usize, usize, usize, usize)> {
+        let (mut left, mut top) = (usize::MAX, usize::MAX);
+        let (mut right, mut bottom) = (0, 0);
+        for (x, y) in self.lit() {
+            (left, right) = (left.min(x), right.max(x));
+            (top, bottom) = (top.min(y), bottom.max(y));
+        }
+        (left != usize::MAX).then(|| (left, top, right - left + 1, bottom - top + 1))
+    }
+}
+
+/// what was in the registers after the last frame (or step), and the
+/// return addresses on the stack, outermost first
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Registers {
+    pub pc: u16,
+    pub i: u16,
+    pub v: [u8; 16],
+    pub delay: u8,
+    pub sound: u8,
+    pub stack: Vec<u16>,
+}
+
+/// something went wrong: the ROM, the Config, or the emulator
+#[derive(Debug)]
+pub struct Error(String);
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<Chip8Error> for Error {
+    fn from(e: Chip8Error) -> Self {
+        Error(e.to_string())
+    }
+}
+
+/// keeps whatever the interpreter draws
+struct Screen(Frame);
+
+impl Display for Screen {
+    fn draw(&mut self, data: &[u8]) -> Result<(), Chip8Error> {
+        self.0.data = data.to_vec();
+        Ok(())
+    }
+
+    fn get_display_size_bytes(&mut self) -> usize {
+        self.0.data.len()
+    }
+
+    fn set_mode(&mut self, width: usize, height: usize) -> Result<(), Chip8Error> {
+        // every frame's run by an interpreter of its own, which sets the
+        // mode it starts in: that's no reason to lose the picture before
+        // it's drawn again, which it won't be once the program's stopped
+        if (width, height) != (self.0.width, self.0.height) {
+            self.0 = Frame::blank(width, height);
+        }
+        Ok(())
+    }
+}
+
+/// the keys that are down, latest last. the interpreter sees one key at a
+/// time, so it's the one pressed most recently that's still held
+#[derive(Default)]
+struct Keypad(Vec<u8>);
+
+impl Input for Keypad {
+    fn flush_keys(&mut self) -> Result<(), Chip8Error> {
+        // a key's down until it comes up
+        Ok(())
+    }
+
+    fn read_key(&mut self) -> Result<Option<u8>, Chip8Error> {
+        Ok(self.0.last().copied())
+    }
+
+    fn tick(&mut self) -> Result<(), Chip8Error> {
+        Ok(())
+    }
+}
+
+/// a ROM, running
+pub struct Emulator {
+    // the machine's moved into an interpreter for each frame and back out
+    // after, as the interpreter borrows everything round it (and isn't
+    // Send). None if a panic took it with it
+    state: Option<MachineState>,
+    quirks: Quirks,
+    schip: Option<Schip>,
+    screen: Screen,
+    keypad: Keypad,
+    beeping: bool,
+    exited: bool,
+    // the frontend's vsync starts the frames, rather than the interpreter
+    vsync: bool,
+    // what a debugger can see, as of the last frame
+    registers: Registers,
+}
+
+/// the registers, as a debugger sees them
+fn inspect(machine: &Chip8Interpreter) -> Result<Registers, Chip8Error> {
+    Ok(Registers {
+        pc: machine.pc(),
+        i: machine.i(),
+        v: std::array::from_fn(|r| machine.v(r as u8)),
+        delay: machine.delay_timer(),
+        sound: machine.sound_timer(),
+        stack: machine.stack()?,
+    })
+}
+
+impl Emulator {
+    /// rom, loaded and ready to run, set up as config says
+    pub fn new(rom: &[u8], config: &Config) -> Result<Self, Error> {
+        let quirks = Quirks::profile(&config.quirks)?;
+        let mut schip = config.schip.then(Schip::new);
+        let mut screen = Screen(Frame::blank(64, 32));
+        let mut keypad = Keypad::default();
+        let mut sound = Mute::new();
+        let (state, registers) = {
+            let mut machine = Chip8Interpreter::new(&mut screen, &mut keypad, &mut sound)?;
+            if let Some(s) = &mut schip {
+                machine.add_extension(s);
+                machine.set_font(&SchipFont)?;
+            }
+            if let Some(seed) = config.seed {
+                machine.set_seed(seed);
+            }
+            machine.set_quirks(quirks);
+            machine.load_program(&mut &rom[..])?;
+            let registers = inspect(&machine)?;
+            (machine.into_machine_state(), registers)
+        };
+        Ok(Emulator {
+            state: Some(state),
+            quirks,
+            schip,
+            screen,
+            keypad,
+            beeping: false,
+            exited: false,
+            vsync: false,
+            registers,
+        })
+    }
+
+    /// press or let go of a key, from the next frame on
+    pub fn key(&mut self, event: KeyEvent) {
+        let (KeyEvent::Down(key) | KeyEvent::Up(key)) = event;
+        self.keypad.0.retain(|k| *k != key);
+        if let KeyEvent::Down(key @ 0..=0xf) = event {
+            self.keypad.0.push(key);
+        }
+    }
+
+    /// run until the next frame's drawn, and say what's on the screen. with
+    /// the frontend's vsync starting the frames, it's as if it had
+    pub fn run_frame(&mut self) -> Result<Frame, Error> {
+        match self.vsync {
+            true => self.on_vblank(),
+            false => self.run(|machine| machine.run_frames(1)),
+        }
+    }
+
+    /// have the frontend start each frame, by calling on_vblank when its
+    /// own display does, rather than the interpreter's keeping count of
+    /// the machine's time: with a real vsync to go by, there's nothing to
+    /// drift from it. false goes back to the interpreter's frames
+    pub fn set_host_vsync(&mut self, vsync: bool) -> Result<(), Error> {
+        self.run(|machine| {
+            machine.set_host_vsync(vsync);
+            Ok(())
+        })?;
+        self.vsync = vsync;
+        Ok(())
+    }
+
+    /// the frontend's display is starting a frame, so start one here, run
+    /// a frame's worth of the machine's time, and say what's on the
+    /// screen. set_host_vsync comes first, or the interpreter starts
+    /// frames of its own as well
+    pub fn on_vblank(&mut self) -> Result<Frame, Error> {
+        self.run(|machine| machine.on_vblank())
+    }
+
+    /// every frame from here on, run as fast as they'll go with no clock
+    /// to keep, e.g. to turn into a video:
+    /// `emulator.frames().take(600).collect::<Result<Vec<_>, _>>()`. they
+    /// stop after an error, or once the program's stopped itself
+    pub fn frames(&mut self) -> Frames<'_> {
+        Frames {
+            emulator: self,
+            done: false,
+        }
+    }
+
+    /// run just the next instruction (and any interrupt that comes due
+    /// while it does), and say what's on the screen, e.g. for a debugger
+    pub fn step(&mut self) -> Result<Frame, Error> {
+        self.run(|machine| machine.step())
+    }
+
+    /// run the machine as far as run says
+    fn run(
+        &mut self,
+        run: impl FnOnce(&mut Chip8Interpreter) -> Result<(), Chip8Error>,
+    ) -> Result<Frame, Error> {
+        let state = self
+            .state
+            .take()
+            .ok_or_else(|| Error("the machine went with a panic while it ran".to_string()))?;
+        let mut sound = Mute::new();
+        let mut machine =
+            Chip8Interpreter::with_machine(&mut self.screen, &mut self.keypad, &mut sound, state);
+        // whatever happens, the machine's put back after
+        let ran = (|| {
+            if let Some(s) = &mut self.schip {
+                machine.add_extension(s);
+                machine.set_font(&SchipFont)?;
+            }
+            machine.set_quirks(self.quirks);
+            machine.resume()?;
+            run(&mut machine)?;
+            self.beeping = machine.sound_timer() > 0;
+            self.exited = machine.exited();
+            self.registers = inspect(&machine)?;
+            Ok::<_, Chip8Error>(())
+        })();
+        self.state = Some(machine.into_machine_state());
+        ran?;
+        Ok(self.screen.0.clone())
+    }
+
+    /// has the program stopped itself (e.g. with SUPER-CHIP's 00FD), as of
+    /// the last frame?
+    pub fn exited(&self) -> bool {
+        self.exited
+    }
+
+    /// is the buzzer sounding, as of the last frame?
+    pub fn beeping(&self) -> bool {
+        self.beeping
+    }
+
+    /// what's in the registers, as of the last frame
+    pub fn registers(&self) -> &Registers {
+        &self.registers
+    }
+
+    /// what's in memory, as of the last frame, from 0000 (the interpreter's
+    /// own workings included)
+    pub fn memory(&self) -> &[u8] {
+        let memory = self.state.as_ref().map(MachineState::memory);
+        memory
+            .and_then(|m| m.get_ro_slice(0, m.size()).ok())
+            .unwrap_or_default()
+    }
+}
+
+/// an Emulator's frames, one after another: see Emulator::frames
+pub struct Frames<'a> {
+    emulator: &'a mut Emulator,
+    done: bool,
+}
+
+impl Iterator for Frames<'_> {
+    type Item = Result<Frame, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done || self.emulator.exited {
+            return None;
+        }
+        let frame = self.emulator.run_frame();
+        self.done = frame.is_err();
+        Some(frame)
+    }
+}
@@ -0,0 +1,240 @@
+//! # keeping count
+//!
+//! how often each ROM's been played, for how long all told, and the best
+//! score anyone's got at it, kept in the data directory (stats.toml) and
+//! shown in the gallery and by --info. a score's only kept for a ROM that
+//! says where its score is, in the ROM database or in config, e.g.
+//!
+//! ```toml
+//! [roms.brix]
+//! score = { addr = 0x3a0, len = 3, digits = true }
+//! ```
+use crate::error::Chip8Error;
+use crate::lang;
+use crate::memory::{Chip8MemoryMap, MemoryMap};
+use crate::paths;
+use crate::persist;
+use crate::storage::Storage;
+use crate::watch::MemoryWatch;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// where a ROM keeps its score
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Score {
+    pub addr: u16,
+    /// how many bytes it takes up, most significant first
+    #[serde(default = "one")]
+    pub len: u8,
+    /// a decimal digit to a byte, as FX33 leaves them, rather than binary
+    #[serde(default)]
+    pub digits: bool,
+}
+
+fn one() -> u8 {
+    1
+}
+
+impl Score {
+    /// what it is now, or None if it's off the end of memory
+    pub fn read(&self, memory: &Chip8MemoryMap) -> Option<u32> {
+        let bytes = memory.get_ro_slice(self.addr, self.len as usize).ok()?;
+        let base = if self.digits { 10 } else { 256 };
+        Some(
+            bytes
+                .iter()
+                .fold(0u32, |n, b| n.wrapping_mul(base).wrapping_add(*b as u32)),
+        )
+    }
+}
+
+/// keeps the best score while it's played, and says when the best there's
+/// ever been is beaten
+pub struct ScoreWatch {
+    score: Score,
+    best_ever: Option<u32>,
+    highest: Option<u32>,
+    // so it's only said the once
+    told: bool,
+}
+
+impl ScoreWatch {
+    pub fn new(score: Score, best_ever: Option<u32>) -> Self {
+        ScoreWatch {
+            score,
+            best_ever,
+            highest: None,
+            told: false,
+        }
+    }
+
+    /// the best this time round
+    pub fn highest(&self) -> Option<u32> {
+        self.highest
+    }
+}
+
+impl MemoryWatch for ScoreWatch {
+    fn frame(&mut self, memory: &Chip8MemoryMap) -> Result<Vec<String>, Chip8Error> {
+        let now = self.score.read(memory);
+        self.highest = self.highest.max(now);
+        match (now, self.best_ever) {
+            (Some(n), Some(best)) if n > best && !self.told => {
+                self.told = true;
+                Ok(vec![lang::format("stats.new-best", &[&n])])
+            }
+            _ => Ok(Vec::new()),
+        }
+    }
+}
+
+/// one ROM's
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
+pub struct RomStats {
+    #[serde(default)]
+    pub launches: u32,
+    /// playing time, all told, in seconds
+    #[serde(default)]
+    pub seconds: u64,
+    #[serde(default)]
+    pub best: Option<u32>,
+}
+
+impl RomStats {
+    /// another go at it, with the best score in it if there was one
+    pub fn record(&mut self, played: Duration, score: Option<u32>) {
+        self.launches += 1;
+        self.seconds += played.as_secs();
+        self.best = self.best.max(score);
+    }
+
+    /// e.g. "played 3 times, for 1h 05m; best score 120"
+    pub fn describe(&self) -> String {
+        if self.launches == 0 {
+            return lang::text("stats.never").to_string();
+        }
+        let played = match self.launches {
+            1 => lang::format("stats.played-once", &[&playtime(self.seconds)]),
+            n => lang::format("stats.played", &[&n, &playtime(self.seconds)]),
+        };
+        match self.best {
+            Some(b) => format!("{}; {}", played, lang::format("stats.best", &[&b])),
+            None => played,
+        }
+    }
+}
+
+/// e.g. "1h 05m", "12m" or "40s"
+fn playtime(seconds: u64) -> String {
+    match (seconds / 3600, seconds / 60 % 60) {
+        (0, 0) => format!("{}s", seconds),
+        (0, m) => format!("{}m", m),
+        (h, m) => format!("{}h {:02}m", h, m),
+    }
+}
+
+/// every ROM's, keyed on rominfo::rom_name
+#[derive(Serialize, Deserialize, Default, Debug, PartialEq)]
+pub struct Stats {
+    #[serde(default)]
+    pub roms: BTreeMap<String, RomStats>,
+}
+
+impl Stats {
+    /// in the data directory
+    pub fn default_path() -> PathBuf {
+        paths::stats_file()
+    }
+
+    /// what's been kept, or nothing if nothing's been played yet
+    pub fn load(storage: &dyn Storage, path: &Path) -> Result<Self, Chip8Error> {
+        Ok(persist::load_text(storage, path, Self::from_toml)?.unwrap_or_default())
+    }
+
+    pub fn save(&self, storage: &dyn Storage, path: &Path) -> Result<(), Chip8Error> {
+        persist::save_text(storage, path, &self.to_toml()?)
+    }
+
+    pub fn from_toml(s: &str) -> Result<Self, Chip8Error> {
+        toml::from_str(s).map_err(|e| Chip8Error::ConfigError(e.to_string()))
+    }
+
+    pub fn to_toml(&self) -> Result<String, Chip8Error> {
+        toml::to_string(self).map_err(|e| Chip8Error::ConfigError(e.to_string()))
+    }
+
+    /// a ROM's, which are all nothing if it's never been played
+    pub fn rom(&self, name: &str) -> RomStats {
+        self.roms.get(name).cloned().unwrap_or_default()
+    }
+
+    pub fn rom_mut(&mut self, name: &str) -> &mut RomStats {
+        self.roms.entry(name.to_string()).or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    #[test]
+    fn test_score() -> Result<(), Chip8Error> {
+        let mut memory = Chip8MemoryMap::new()?;
+        memory.write(&[1, 2, 3], 0x3a0, 3)?;
+        let digits = Score {
+            addr: 0x3a0,
+            len: 3,
+            digits: true,
+        };
+        assert_eq!(digits.read(&memory), Some(123));
+        let binary = Score {
+            digits: false,
+            len: 2,
+            ..digits
+        };
+        assert_eq!(binary.read(&memory), Some(0x0102));
+        let off_the_end = Score {
+            addr: 0xffff,
+            ..digits
+        };
+        assert_eq!(off_the_end.read(&memory), None);
+
+        let mut watch = ScoreWatch::new(digits, Some(200));
+        assert!(watch.frame(&memory)?.is_empty());
+        memory.write(&[2, 5, 0], 0x3a0, 3)?;
+        assert_eq!(watch.frame(&memory)?, ["a new best score: 250"]);
+        memory.write(&[2, 6, 0], 0x3a0, 3)?;
+        assert!(watch.frame(&memory)?.is_empty());
+        // starting again doesn't lose it
+        memory.write(&[0, 0, 0], 0x3a0, 3)?;
+        watch.frame(&memory)?;
+        assert_eq!(watch.highest(), Some(260));
+        Ok(())
+    }
+
+    #[test]
+    fn test_record() -> Result<(), Chip8Error> {
+        let mut stats = Stats::default();
+        assert_eq!(stats.rom("brix").describe(), "never played");
+        stats
+            .rom_mut("brix")
+            .record(Duration::from_secs(3000), Some(120));
+        stats.rom_mut("brix").record(Duration::from_secs(905), None);
+        assert_eq!(
+            stats.rom("brix").describe(),
+            "played 2 times, for 1h 05m; best score 120"
+        );
+        stats.rom_mut("pong").record(Duration::from_secs(40), None);
+        assert_eq!(stats.rom("pong").describe(), "played once, for 40s");
+
+        let storage = MemoryStorage::new();
+        let path = Stats::default_path();
+        assert_eq!(Stats::load(&storage, &path)?, Stats::default());
+        stats.save(&storage, &path)?;
+        assert_eq!(Stats::load(&storage, &path)?, stats);
+        Ok(())
+    }
+}
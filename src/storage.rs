@@ -0,0 +1,324 @@
+//! # where things are kept
+//!
+//! everything kept between runs -- config, sessions and savestate slots,
+//! stats, profiles and achievements -- is read and written through a
+//! Storage, rather than straight from the filesystem. the emulator keeps
+//! them in files, as FileStorage does, but tests can keep them in memory
+//! (MemoryStorage), so they don't leave anything behind or trip over each
+//! other, and a host without a filesystem can bring a Storage of its own.
+//! one that's got a browser's localStorage, or anything else like it that
+//! keeps text by name, only needs to say how to get at it (KeyValueStore),
+//! and KeyValueStorage does the rest. built for wasm32 with --features web,
+//! web_sys::Storage is one already, so a wasm frontend keeps everything in
+//! the page's localStorage, there after it's reloaded, with:
+//!
+//! ```text
+//! let local = web_sys::window()?.local_storage().ok()??;
+//! let storage = KeyValueStorage::new(local, "chip8:");
+//! ```
+//!
+//! things are found by the paths the paths module gives them, whatever's
+//! behind them: a Storage that isn't files is free to use them as keys
+use crate::clipboard::{base64_decode, base64_encode};
+use crate::error::Chip8Error;
+use crate::persist;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// somewhere to keep things between runs
+pub trait Storage {
+    /// what's kept at path, or None if there's nothing
+    fn read(&self, path: &Path) -> Result<Option<Vec<u8>>, Chip8Error>;
+
+    /// keep data at path, in place of whatever was there, all at once or
+    /// not at all. what was there is kept at persist::backup_path(path)
+    fn write(&self, path: &Path, data: &[u8]) -> Result<(), Chip8Error>;
+
+    /// forget what's at path (and its backup), if there's anything
+    fn remove(&self, path: &Path) -> Result<(), Chip8Error>;
+
+    /// everything kept directly in dir, in no particular order
+    fn list(&self, dir: &Path) -> Result<Vec<PathBuf>, Chip8Error>;
+
+    /// is there anything kept at path?
+    fn exists(&self, path: &Path) -> Result<bool, Chip8Error> {
+        Ok(self.read(path)?.is_some())
+    }
+}
+
+/// files, where the paths say
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FileStorage;
+
+impl Storage for FileStorage {
+    fn read(&self, path: &Path) -> Result<Option<Vec<u8>>, Chip8Error> {
+        match fs::read(path) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn write(&self, path: &Path, data: &[u8]) -> Result<(), Chip8Error> {
+        persist::write_atomic(path, data)
+    }
+
+    fn remove(&self, path: &Path) -> Result<(), Chip8Error> {
+        for p in [path.to_path_buf(), persist::backup_path(path)] {
+            match fs::remove_file(&p) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn list(&self, dir: &Path) -> Result<Vec<PathBuf>, Chip8Error> {
+        match fs::read_dir(dir) {
+            Ok(entries) => Ok(entries.flatten().map(|e| e.path()).collect()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// nothing but memory, gone when it is
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    files: Mutex<BTreeMap<PathBuf, Vec<u8>>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn files(&self) -> std::sync::MutexGuard<'_, BTreeMap<PathBuf, Vec<u8>>> {
+        // nothing's left half-written by a panic elsewhere
+        self.files.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn read(&self, path: &Path) -> Result<Option<Vec<u8>>, Chip8Error> {
+        Ok(self.files().get(path).cloned())
+    }
+
+    fn write(&self, path: &Path, data: &[u8]) -> Result<(), Chip8Error> {
+        let mut files = self.files();
+        if let Some(old) = files.insert(path.to_path_buf(), data.to_vec()) {
+            files.insert(persist::backup_path(path), old);
+        }
+        Ok(())
+    }
+
+    fn remove(&self, path: &Path) -> Result<(), Chip8Error> {
+        let mut files = self.files();
+        files.remove(path);
+        files.remove(&persist::backup_path(path));
+        Ok(())
+    }
+
+    fn list(&self, dir: &Path) -> Result<Vec<PathBuf>, Chip8Error> {
+        Ok(self
+            .files()
+            .keys()
+            .filter(|p| p.parent() == Some(dir))
+            .cloned()
+            .collect())
+    }
+}
+
+/// somewhere that keeps text by name, as a browser's localStorage does
+pub trait KeyValueStore {
+    fn get_item(&self, key: &str) -> Option<String>;
+
+    /// which can fail, e.g. if it's full
+    fn set_item(&self, key: &str, value: &str) -> Result<(), Chip8Error>;
+
+    fn remove_item(&self, key: &str);
+
+    /// the names of everything in it
+    fn keys(&self) -> Vec<String>;
+}
+
+/// a browser's localStorage (or sessionStorage), as web-sys has it
+#[cfg(all(feature = "web", target_arch = "wasm32"))]
+impl KeyValueStore for web_sys::Storage {
+    fn get_item(&self, key: &str) -> Option<String> {
+        web_sys::Storage::get_item(self, key).ok().flatten()
+    }
+
+    fn set_item(&self, key: &str, value: &str) -> Result<(), Chip8Error> {
+        // the browser's only reason for saying no is a full quota
+        web_sys::Storage::set_item(self, key, value).map_err(|_| {
+            Chip8Error::Io(io::Error::other(format!(
+                "the browser's storage won't take {} (is it full?)",
+                key
+            )))
+        })
+    }
+
+    fn remove_item(&self, key: &str) {
+        let _ = web_sys::Storage::remove_item(self, key);
+    }
+
+    fn keys(&self) -> Vec<String> {
+        let len = self.length().unwrap_or(0);
+        (0..len)
+            .filter_map(|i| self.key(i).ok().flatten())
+            .collect()
+    }
+}
+
+/// a Storage kept in a KeyValueStore: each path's a key, with prefix in
+/// front to keep clear of anything else kept there, and each value's
+/// base64, as what's kept needn't be text
+pub struct KeyValueStorage<S> {
+    store: S,
+    prefix: String,
+}
+
+impl<S: KeyValueStore> KeyValueStorage<S> {
+    pub fn new(store: S, prefix: &str) -> Self {
+        KeyValueStorage {
+            store,
+            prefix: prefix.to_string(),
+        }
+    }
+
+    /// e.g. "chip8:/home/me/.config/chip8/config.toml", the same on any host
+    fn key(&self, path: &Path) -> String {
+        let path = path.to_string_lossy().replace('\\', "/");
+        format!("{}{}", self.prefix, path)
+    }
+}
+
+impl<S: KeyValueStore> Storage for KeyValueStorage<S> {
+    fn read(&self, path: &Path) -> Result<Option<Vec<u8>>, Chip8Error> {
+        match self.store.get_item(&self.key(path)) {
+            Some(value) => base64_decode(&value).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn write(&self, path: &Path, data: &[u8]) -> Result<(), Chip8Error> {
+        let key = self.key(path);
+        // each item's set all at once, so the backup's all there is to do
+        if let Some(old) = self.store.get_item(&key) {
+            self.store
+                .set_item(&self.key(&persist::backup_path(path)), &old)?;
+        }
+        self.store.set_item(&key, &base64_encode(data))
+    }
+
+    fn remove(&self, path: &Path) -> Result<(), Chip8Error> {
+        self.store.remove_item(&self.key(path));
+        self.store
+            .remove_item(&self.key(&persist::backup_path(path)));
+        Ok(())
+    }
+
+    fn list(&self, dir: &Path) -> Result<Vec<PathBuf>, Chip8Error> {
+        let within = format!("{}/", self.key(dir).trim_end_matches('/'));
+        Ok(self
+            .store
+            .keys()
+            .iter()
+            .filter_map(|k| k.strip_prefix(&within))
+            .filter(|name| !name.contains('/'))
+            .map(|name| dir.join(name))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    /// what any Storage should do
+    fn check(storage: &dyn Storage, dir: &Path) -> Result<(), Chip8Error> {
+        let path = dir.join("a.txt");
+        assert_eq!(storage.read(&path)?, None);
+        assert!(!storage.exists(&path)?);
+        assert!(storage.list(dir)?.is_empty());
+        storage.write(&path, b"1")?;
+        storage.write(&path, b"2")?;
+        assert_eq!(storage.read(&path)?.as_deref(), Some(&b"2"[..]));
+        assert_eq!(
+            storage.read(&persist::backup_path(&path))?.as_deref(),
+            Some(&b"1"[..])
+        );
+        storage.write(&dir.join("b.txt"), b"3")?;
+        let mut listed = storage.list(dir)?;
+        listed.sort();
+        assert_eq!(
+            listed,
+            [path.clone(), persist::backup_path(&path), dir.join("b.txt")]
+        );
+        storage.remove(&path)?;
+        storage.remove(&path)?;
+        assert!(!storage.exists(&path)?);
+        assert_eq!(storage.list(dir)?, [dir.join("b.txt")]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_memory() -> Result<(), Chip8Error> {
+        check(&MemoryStorage::new(), Path::new("/chip8"))
+    }
+
+    /// as a browser would keep it
+    #[derive(Default)]
+    struct LocalStorage(Mutex<BTreeMap<String, String>>);
+
+    impl KeyValueStore for LocalStorage {
+        fn get_item(&self, key: &str) -> Option<String> {
+            self.0.lock().unwrap().get(key).cloned()
+        }
+
+        fn set_item(&self, key: &str, value: &str) -> Result<(), Chip8Error> {
+            self.0
+                .lock()
+                .unwrap()
+                .insert(key.to_string(), value.to_string());
+            Ok(())
+        }
+
+        fn remove_item(&self, key: &str) {
+            self.0.lock().unwrap().remove(key);
+        }
+
+        fn keys(&self) -> Vec<String> {
+            self.0.lock().unwrap().keys().cloned().collect()
+        }
+    }
+
+    #[test]
+    fn test_key_value() -> Result<(), Chip8Error> {
+        let storage = KeyValueStorage::new(LocalStorage::default(), "chip8:");
+        check(&storage, Path::new("/chip8"))?;
+        // binary's kept as text, and somebody else's keys are left alone
+        storage.write(Path::new("/chip8/3.thumb"), &[0, 0xff])?;
+        storage.store.set_item("theirs", "not base64!")?;
+        assert_eq!(
+            storage.store.get_item("chip8:/chip8/3.thumb").as_deref(),
+            Some("AP8=")
+        );
+        assert!(storage.list(Path::new("/"))?.is_empty());
+        assert_eq!(storage.list(Path::new("/chip8"))?.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_files() -> Result<(), Chip8Error> {
+        let dir = env::temp_dir().join(format!("chip8-storage-{}", std::process::id()));
+        check(&FileStorage, &dir)?;
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
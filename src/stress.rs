@@ -0,0 +1,299 @@
+//! # input stress test
+//!
+//! `chip8 stress game.ch8`: a ROM run headless for a while with the keypad
+//! hammered by random presses and releases, far faster than anyone could
+//! manage, to shake out anything in the input path that only goes wrong
+//! when keys change at an awkward moment. it checks nothing panics, and
+//! that every EX9E and EXA1 skips exactly when the key it asks about is (or
+//! isn't) the one held as it runs. --stress-rate sets how many presses and
+//! releases a second (there can be a few each frame), --stress-frames how
+//! long for, and --stress-seed which random ones, so a failure can be had
+//! again: the seed's in the report either way. a panic's kept in the
+//! corpus (see the corpus module), for the self-test to check on from then
+//! on
+use crate::error::Chip8Error;
+use crate::font::SchipFont;
+use crate::input::Input;
+use crate::interpreter::Chip8Interpreter;
+use crate::quirks::Quirks;
+use crate::recover;
+use crate::replay::FrameHasher;
+use crate::schip::Schip;
+use crate::sound::Mute;
+use crate::trace::{TraceEntry, Tracer};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::cell::Cell;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+
+/// presses and releases a second, unless told
+pub const STRESS_RATE: f64 = 30.0;
+/// as many as --stress-rate can ask for: a thousand a frame, past which
+/// the frames would take longer to make up than to run
+pub const STRESS_RATE_MAX: f64 = 60_000.0;
+/// a minute, at 60Hz
+pub const STRESS_FRAMES: u64 = 3600;
+/// frames a second, as far as the rate goes
+const STRESS_HZ: f64 = 60.0;
+
+/// how to run a stress test
+#[derive(Debug, Clone)]
+pub struct StressOptions {
+    /// presses and releases a second
+    pub rate: f64,
+    pub frames: u64,
+    pub seed: u64,
+    pub quirks: Quirks,
+    pub schip: bool,
+}
+
+impl Default for StressOptions {
+    fn default() -> Self {
+        StressOptions {
+            rate: STRESS_RATE,
+            frames: STRESS_FRAMES,
+            seed: rand::thread_rng().gen(),
+            quirks: Quirks::default(),
+            schip: false,
+        }
+    }
+}
+
+/// random keys, changing at the start of frames, with the last one read
+/// left where the checking can see it
+struct StressInput<'a> {
+    rng: StdRng,
+    /// how many presses and releases each frame, on average
+    per_frame: f64,
+    /// what's built up towards the next one
+    owed: f64,
+    held: Option<u8>,
+    read: &'a Cell<Option<u8>>,
+    events: u64,
+    /// the key held each frame, to have them again
+    keys: Vec<Option<u8>>,
+}
+
+impl Input for StressInput<'_> {
+    fn flush_keys(&mut self) -> Result<(), Chip8Error> {
+        // as StdinInput does: taken, it's gone until it's pressed again
+        self.held = None;
+        Ok(())
+    }
+
+    fn read_key(&mut self) -> Result<Option<u8>, Chip8Error> {
+        self.read.set(self.held);
+        Ok(self.held)
+    }
+
+    fn tick(&mut self) -> Result<(), Chip8Error> {
+        self.owed += self.per_frame;
+        while self.owed >= 1.0 {
+            self.owed -= 1.0;
+            self.events += 1;
+            // a press (often of another key while one's still down) as
+            // often as a release
+            self.held = match self.rng.gen_bool(0.5) {
+                true => Some(self.rng.gen_range(0..0x10)),
+                false => None,
+            };
+        }
+        self.keys.push(self.held);
+        Ok(())
+    }
+}
+
+/// checks each EX9E and EXA1 against the key it read, by where the next
+/// instruction is. (the key's looked at once it's run rather than as it's
+/// traced, as a frame can start between the two)
+struct KeyCheck<'a> {
+    read: &'a Cell<Option<u8>>,
+    // a skip waiting to see where it went
+    pending: Option<TraceEntry>,
+    checked: u64,
+}
+
+impl Tracer for KeyCheck<'_> {
+    fn trace(&mut self, entry: &TraceEntry) -> Result<(), Chip8Error> {
+        if let Some(skip) = self.pending.take() {
+            let held = self.read.get();
+            let x = (skip.opcode >> 8 & 0xf) as usize;
+            let pressed = held == Some(skip.v[x]);
+            let should_skip = match skip.opcode & 0xf0ff {
+                0xe09e => pressed,
+                _ => !pressed,
+            };
+            let skipped = entry.pc == skip.pc.wrapping_add(4);
+            if skipped != should_skip || !(skipped || entry.pc == skip.pc.wrapping_add(2)) {
+                return Err(Chip8Error::TestFailure(format!(
+                    "{:04x} at {:04x} in frame {}, with V{:X} = {:x} and {} held, went on to {:04x}",
+                    skip.opcode,
+                    skip.pc,
+                    skip.frame,
+                    x,
+                    skip.v[x],
+                    held.map_or("nothing".to_string(), |k| format!("{:x}", k)),
+                    entry.pc
+                )));
+            }
+            self.checked += 1;
+        }
+        if matches!(entry.opcode & 0xf0ff, 0xe09e | 0xe0a1) {
+            self.pending = Some(entry.clone());
+        }
+        Ok(())
+    }
+}
+
+/// how a stress test went
+#[derive(Debug)]
+pub struct StressReport {
+    pub seed: u64,
+    pub frames: u64,
+    /// presses and releases
+    pub events: u64,
+    /// EX9Es and EXA1s checked
+    pub checked: u64,
+    /// what went wrong, if anything did
+    pub failure: Option<String>,
+    /// whether that was a panic, rather than the ROM going wrong
+    pub panicked: bool,
+    /// the key held each frame
+    pub keys: Vec<Option<u8>>,
+}
+
+impl fmt::Display for StressReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} presses and releases over {} frames, {} key skips checked (seed {})",
+            self.events, self.frames, self.checked, self.seed
+        )?;
+        match &self.failure {
+            Some(failure) => write!(f, "\nFAILED: {}", failure),
+            None => write!(f, "\nok"),
+        }
+    }
+}
+
+/// run rom with the keypad hammered, as options say
+pub fn stress(rom: &[u8], options: &StressOptions) -> Result<StressReport, Chip8Error> {
+    let read = Cell::new(None);
+    let mut display = FrameHasher::new(None);
+    let mut input = StressInput {
+        rng: StdRng::seed_from_u64(options.seed),
+        per_frame: options.rate / STRESS_HZ,
+        owed: 0.0,
+        held: None,
+        read: &read,
+        events: 0,
+        keys: Vec::new(),
+    };
+    let mut sound = Mute::new();
+    let mut check = KeyCheck {
+        read: &read,
+        pending: None,
+        checked: 0,
+    };
+    let mut schip = Schip::new();
+    let (frames, failure, panicked) = {
+        let mut machine = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+        // the program's random numbers come from the same seed, so it's all
+        // the same again with it
+        machine.set_seed(options.seed as u16);
+        machine.set_quirks(options.quirks);
+        if options.schip {
+            machine.add_extension(&mut schip);
+            machine.set_font(&SchipFont)?;
+        }
+        machine.load_program(&mut &rom[..])?;
+        machine.add_tracer(&mut check);
+        let run = panic::catch_unwind(AssertUnwindSafe(|| machine.run_frames(options.frames)));
+        let (failure, panicked) = match run {
+            Ok(Ok(())) => (None, false),
+            Ok(Err(e)) => (Some(e.to_string()), false),
+            Err(payload) => (
+                Some(format!("panicked: {}", recover::message(payload.as_ref()))),
+                true,
+            ),
+        };
+        (machine.frames(), failure, panicked)
+    };
+    Ok(StressReport {
+        seed: options.seed,
+        frames,
+        events: input.events,
+        checked: check.checked,
+        failure,
+        panicked,
+        keys: input.keys,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm;
+
+    #[test]
+    fn test_stress() -> Result<(), Chip8Error> {
+        // counts the frames 5 is held for, and waits for 7
+        let rom = asm::assemble(
+            "stress",
+            "
+            : main
+              v0 := 5
+              if v0 key then v1 += 1
+              v0 := 7
+              if v0 -key then jump main
+              v2 := key
+              jump main
+            ",
+        )?
+        .rom;
+        let options = StressOptions {
+            rate: 120.0,
+            frames: 600,
+            seed: 1,
+            ..StressOptions::default()
+        };
+        let report = stress(&rom, &options)?;
+        assert_eq!(report.failure, None, "{}", report);
+        assert_eq!(report.frames, 600);
+        assert_eq!(report.events, 1200);
+        assert!(report.checked > 1000, "{}", report);
+        assert_eq!(report.keys.len(), 600);
+        assert!(!report.panicked);
+
+        // a broken ROM's stopped with, rather than taken down by
+        let report = stress(&[0x00, 0x00], &options)?;
+        assert!(report.failure.is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn test_key_check() {
+        let read = Cell::new(Some(0x5));
+        let mut check = KeyCheck {
+            read: &read,
+            pending: None,
+            checked: 0,
+        };
+        let entry = |pc: u16, opcode: u16| TraceEntry {
+            frame: 0,
+            pc,
+            opcode,
+            v: [0x5; 16],
+            i: 0,
+        };
+        // 5's held, so EX9E skips and EXA1 doesn't
+        assert!(check.trace(&entry(0x200, 0xe09e)).is_ok());
+        assert!(check.trace(&entry(0x204, 0xe0a1)).is_ok());
+        assert!(check.trace(&entry(0x206, 0x1200)).is_ok());
+        assert_eq!(check.checked, 2);
+        assert!(check.trace(&entry(0x200, 0xe09e)).is_ok());
+        let e = check.trace(&entry(0x202, 0x1200)).unwrap_err();
+        assert!(e.to_string().contains("e09e at 0200"), "{}", e);
+    }
+}
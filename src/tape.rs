@@ -0,0 +1,139 @@
+//! # the VIP's cassette interface
+//!
+//! the VIP saved memory to tape by toggling Q: a cycle of 2kHz for a 0 bit
+//! and a cycle of 800Hz for a 1. a tape starts with a few seconds of 0s to
+//! let the recorder settle, then each byte is a 1 start bit, 8 data bits
+//! (least significant first) and a parity bit making the 1s add up odd.
+//! these are real audio files, so tapes can go to and from real hardware
+use crate::error::Chip8Error;
+use crate::sound::{read_wav, write_wav, TONE_SAMPLE_RATE};
+use std::io;
+
+const TAPE_ZERO_HZ: u32 = 2000;
+const TAPE_ONE_HZ: u32 = 800;
+/// cycles slower than this are 1s, faster ones are 0s
+const TAPE_THRESHOLD_HZ: u32 = 1400;
+const TAPE_LEADER_SECONDS: u32 = 4;
+const TAPE_AMPLITUDE: i16 = i16::MAX / 2;
+
+/// render data as it'd sound on tape
+pub fn encode(data: &[u8], sample_rate: u32) -> Vec<i16> {
+    let mut samples = Vec::new();
+    let mut cycle = |one: bool| {
+        let hz = if one { TAPE_ONE_HZ } else { TAPE_ZERO_HZ };
+        let len = (sample_rate / hz) as usize;
+        samples.extend((0..len).map(|i| {
+            if i < len / 2 {
+                TAPE_AMPLITUDE
+            } else {
+                -TAPE_AMPLITUDE
+            }
+        }));
+    };
+    for _ in 0..TAPE_LEADER_SECONDS * TAPE_ZERO_HZ {
+        cycle(false);
+    }
+    for byte in data {
+        cycle(true);
+        for bit in 0..8 {
+            cycle(byte & (1 << bit) != 0);
+        }
+        cycle(byte.count_ones() % 2 == 0);
+    }
+    // a last 0 so the final bit's cycle has an end
+    cycle(false);
+    samples
+}
+
+/// read data back off tape. anything before the first start bit is taken
+/// as leader
+pub fn decode(samples: &[i16], sample_rate: u32) -> Result<Vec<u8>, Chip8Error> {
+    // time between rising edges gives each cycle's frequency
+    let threshold = (sample_rate / TAPE_THRESHOLD_HZ) as usize;
+    let mut bits = samples
+        .windows(2)
+        .enumerate()
+        .filter(|(_, w)| w[0] < 0 && w[1] >= 0)
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>()
+        .windows(2)
+        .map(|edges| edges[1] - edges[0] > threshold)
+        .collect::<Vec<_>>()
+        .into_iter();
+
+    let mut data = Vec::new();
+    // bytes can have 0s between them, as the leader does
+    while bits.any(|one| one) {
+        let mut byte = 0u8;
+        for bit in 0..8 {
+            match bits.next() {
+                Some(one) => byte |= (one as u8) << bit,
+                None => return Err(tape_error("tape ends mid-byte", data.len())),
+            }
+        }
+        let parity = bits.next().unwrap_or(false);
+        if (byte.count_ones() + parity as u32) % 2 != 1 {
+            return Err(tape_error("parity error", data.len()));
+        }
+        data.push(byte);
+    }
+    Ok(data)
+}
+
+fn tape_error(why: &str, at: usize) -> Chip8Error {
+    Chip8Error::AudioError(format!("{} at byte {:04x?} of tape", why, at))
+}
+
+/// save data to a tape, as a WAV file
+pub fn write_tape(w: &mut impl io::Write, data: &[u8]) -> Result<(), Chip8Error> {
+    write_wav(w, &encode(data, TONE_SAMPLE_RATE), TONE_SAMPLE_RATE)
+}
+
+/// load whatever's on a tape, from a WAV file
+pub fn read_tape(r: &mut impl io::Read) -> Result<Vec<u8>, Chip8Error> {
+    let (samples, sample_rate) = read_wav(r)?;
+    decode(&samples, sample_rate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() -> Result<(), Chip8Error> {
+        let data = [0x00, 0xff, 0x12, 0x34, 0xa5];
+        let mut wav = Vec::new();
+        write_tape(&mut wav, &data)?;
+        assert_eq!(read_tape(&mut &wav[..])?, data);
+        // an old recording at a lower rate
+        assert_eq!(decode(&encode(&data, 11_025), 11_025)?, data);
+        Ok(())
+    }
+
+    #[test]
+    fn test_byte_format() -> Result<(), Chip8Error> {
+        // skip the leader; then 0x01 is start, 1, seven 0s, parity 0 and the
+        // closing 0
+        let rate = 40_000;
+        let samples = encode(&[0x01], rate);
+        let zero = (rate / TAPE_ZERO_HZ) as usize;
+        let one = (rate / TAPE_ONE_HZ) as usize;
+        let byte = &samples[(TAPE_LEADER_SECONDS * TAPE_ZERO_HZ) as usize * zero..];
+        assert_eq!(byte.len(), 2 * one + 9 * zero);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parity_error() {
+        // swap the parity bit of the only byte for a 1
+        let rate = 40_000;
+        let mut samples = encode(&[0x01], rate);
+        let zero = (rate / TAPE_ZERO_HZ) as usize;
+        let one = (rate / TAPE_ONE_HZ) as usize;
+        let parity = samples.len() - 2 * zero;
+        samples.truncate(parity);
+        samples.extend((0..one).map(|i| if i < one / 2 { 1 } else { -1 }));
+        samples.extend((0..zero).map(|i| if i < zero / 2 { 1 } else { -1 }));
+        assert!(decode(&samples, rate).is_err());
+    }
+}
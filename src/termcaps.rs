@@ -0,0 +1,438 @@
+//! # what the terminal can do
+//!
+//! the terminal frontend used to take it for granted that it had a big
+//! terminal with all the colours and a font with block characters. this
+//! looks at what it's really got before starting: how many colours (from
+//! NO_COLOR, COLORTERM and TERM), whether it can show Unicode (from the
+//! locale), and how big it is. the picture's drawn with the biggest cells
+//! that fit (unless --cells or --scale say otherwise), colours it can't
+//! show come out as the nearest it can, and without Unicode it's drawn in
+//! ASCII. it only refuses to start when it can't draw the picture at all:
+//! a dumb terminal, or one too small for even the smallest cells.
+//!
+//! anything it gets wrong can be put right with --terminal-caps (or
+//! terminal_caps in the config), e.g. `--terminal-caps 256,unicode`, or
+//! `--terminal-caps truecolour,ascii,100x40`
+use crate::display::{Cells, Scale, Theme};
+use crate::error::Chip8Error;
+use tui::style::Color;
+
+/// how many colours the terminal shows
+#[derive(Debug, PartialEq, Eq, Clone, Copy, PartialOrd, Ord)]
+pub enum Colours {
+    /// none, as NO_COLOR asks: lit pixels are blocks in the terminal's own
+    /// colour, and unlit ones blank
+    Mono,
+    /// the sixteen named ones, whatever the terminal's palette makes of them
+    Ansi16,
+    /// xterm's 256
+    Ansi256,
+    /// any, as red, green and blue
+    TrueColour,
+}
+
+/// as many as there are, as was taken for granted before there was any
+/// looking
+impl Default for Colours {
+    fn default() -> Self {
+        Colours::TrueColour
+    }
+}
+
+/// the sixteen named colours, as xterm shows them
+const ANSI16: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::Red, (205, 0, 0)),
+    (Color::Green, (0, 205, 0)),
+    (Color::Yellow, (205, 205, 0)),
+    (Color::Blue, (0, 0, 238)),
+    (Color::Magenta, (205, 0, 205)),
+    (Color::Cyan, (0, 205, 205)),
+    (Color::Gray, (229, 229, 229)),
+    (Color::DarkGray, (127, 127, 127)),
+    (Color::LightRed, (255, 0, 0)),
+    (Color::LightGreen, (0, 255, 0)),
+    (Color::LightYellow, (255, 255, 0)),
+    (Color::LightBlue, (92, 92, 255)),
+    (Color::LightMagenta, (255, 0, 255)),
+    (Color::LightCyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+/// the levels of each of red, green and blue in xterm's 6x6x6 cube
+const CUBE: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+impl Colours {
+    pub fn parse(s: &str) -> Result<Self, Chip8Error> {
+        match s {
+            "mono" => Ok(Colours::Mono),
+            "16" => Ok(Colours::Ansi16),
+            "256" => Ok(Colours::Ansi256),
+            "truecolour" | "truecolor" | "24bit" => Ok(Colours::TrueColour),
+            _ => Err(Chip8Error::ConfigError(format!(
+                "no colours called \"{}\" (try mono, 16, 256 or truecolour)",
+                s
+            ))),
+        }
+    }
+
+    /// the nearest colour to colour the terminal can show
+    pub fn nearest(&self, colour: Color) -> Color {
+        match (self, colour) {
+            (Colours::TrueColour, c) => c,
+            (Colours::Mono, _) => Color::Reset,
+            (Colours::Ansi256, Color::Rgb(r, g, b)) => Color::Indexed(Self::index(r, g, b)),
+            (Colours::Ansi16, Color::Rgb(r, g, b)) => Self::named(r, g, b),
+            (Colours::Ansi16, Color::Indexed(i)) if i >= 16 => {
+                let (r, g, b) = Self::rgb(i);
+                Self::named(r, g, b)
+            }
+            (Colours::Ansi16, Color::Indexed(i)) => ANSI16[i as usize].0,
+            (_, c) => c,
+        }
+    }
+
+    /// theme, in colours the terminal can show: with none, lit pixels are
+    /// glyphs in its own colour
+    pub fn theme(&self, theme: Theme) -> Theme {
+        Theme {
+            lit: self.nearest(theme.lit),
+            unlit: self.nearest(theme.unlit),
+            text: self.nearest(theme.text),
+            glyphs: theme.glyphs || *self == Colours::Mono,
+            ..theme
+        }
+    }
+
+    /// the nearest of xterm's 256, from its cube or its greys
+    fn index(r: u8, g: u8, b: u8) -> u8 {
+        let level = |c: u8| {
+            (0..CUBE.len())
+                .min_by_key(|l| CUBE[*l].abs_diff(c))
+                .unwrap_or(0) as u8
+        };
+        let cube = 16 + 36 * level(r) + 6 * level(g) + level(b);
+        // the greys go from 8 to 238 in 24 steps of 10
+        let mean = ((r as u16 + g as u16 + b as u16) / 3) as u8;
+        let grey = 232 + (mean.saturating_sub(3) / 10).min(23);
+        match Self::distance((r, g, b), Self::rgb(grey))
+            < Self::distance((r, g, b), Self::rgb(cube))
+        {
+            true => grey,
+            false => cube,
+        }
+    }
+
+    /// what one of xterm's 256 is (past the first sixteen)
+    fn rgb(index: u8) -> (u8, u8, u8) {
+        match index {
+            0..=15 => ANSI16[index as usize].1,
+            16..=231 => {
+                let i = (index - 16) as usize;
+                (CUBE[i / 36], CUBE[i / 6 % 6], CUBE[i % 6])
+            }
+            _ => {
+                let l = 8 + 10 * (index - 232);
+                (l, l, l)
+            }
+        }
+    }
+
+    /// the nearest of the sixteen named colours
+    fn named(r: u8, g: u8, b: u8) -> Color {
+        ANSI16
+            .iter()
+            .min_by_key(|(_, rgb)| Self::distance((r, g, b), *rgb))
+            .map_or(Color::White, |(c, _)| *c)
+    }
+
+    fn distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+        let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2) as u32;
+        d(a.0, b.0) + d(a.1, b.1) + d(a.2, b.2)
+    }
+}
+
+/// ways to draw the picture, best first, with Unicode: a character a pixel
+/// as always, then quadrants at 2x1, which are square, then smaller
+const UNICODE_LAYOUTS: [(Cells, Scale); 4] = [
+    (Cells::Block, Scale(1, 1)),
+    (Cells::Quadrant, Scale(2, 1)),
+    (Cells::Quadrant, Scale(1, 1)),
+    (Cells::Sextant, Scale(1, 1)),
+];
+
+/// and without: there's only the one
+const ASCII_LAYOUTS: [(Cells, Scale); 1] = [(Cells::Ascii, Scale(1, 1))];
+
+/// the character cells a width x height picture takes drawn with cells at
+/// scale, across and down, with its border and the status line under it
+pub fn footprint(cells: Cells, scale: Scale, width: usize, height: usize) -> (u16, u16) {
+    let (w, h) = cells.size();
+    let Scale(sx, sy) = scale;
+    (
+        2 + (width * sx).div_ceil(w) as u16,
+        3 + (height * sy).div_ceil(h) as u16,
+    )
+}
+
+/// what the terminal can do
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Capabilities {
+    pub colours: Colours,
+    pub unicode: bool,
+    /// columns and rows, if there's any telling
+    pub size: Option<(u16, u16)>,
+    /// TERM=dumb, which can't move the cursor about
+    pub dumb: bool,
+}
+
+impl Capabilities {
+    /// have a look at the terminal we're in
+    pub fn detect() -> Self {
+        Self::from_env(
+            |name| std::env::var(name).ok(),
+            crossterm::terminal::size().ok(),
+        )
+    }
+
+    /// what a terminal of size can do, given the environment var gives
+    pub fn from_env(var: impl Fn(&str) -> Option<String>, size: Option<(u16, u16)>) -> Self {
+        let var = |name| var(name).filter(|v| !v.is_empty());
+        let term = var("TERM").unwrap_or_default();
+        let dumb = term == "dumb";
+        let colours = if var("NO_COLOR").is_some() || dumb {
+            Colours::Mono
+        } else if var("COLORTERM").is_some_and(|c| c == "truecolor" || c == "24bit")
+            || var("WT_SESSION").is_some()
+        {
+            Colours::TrueColour
+        } else if term.contains("256") {
+            Colours::Ansi256
+        } else {
+            Colours::Ansi16
+        };
+        // the first of these that's set is the locale's character set
+        let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+            .iter()
+            .find_map(|v| var(v))
+            .unwrap_or_default()
+            .to_lowercase();
+        let unicode = cfg!(windows) || locale.contains("utf-8") || locale.contains("utf8");
+        Capabilities {
+            colours,
+            unicode,
+            size,
+            dumb,
+        }
+    }
+
+    /// these, with what caps says put right, e.g. "256,ascii" or "80x25":
+    /// any of mono, 16, 256 or truecolour, unicode or ascii, and a size
+    pub fn overridden(mut self, caps: &str) -> Result<Self, Chip8Error> {
+        for cap in caps.split(',').map(str::trim) {
+            match cap {
+                "unicode" => self.unicode = true,
+                "ascii" => self.unicode = false,
+                _ => match (cap.split_once('x'), Colours::parse(cap)) {
+                    (_, Ok(c)) => {
+                        self.colours = c;
+                        // whatever TERM says, it's been told it's not dumb
+                        self.dumb = false;
+                    }
+                    (Some((w, h)), _) if w.parse::<u16>().is_ok() && h.parse::<u16>().is_ok() => {
+                        self.size = w.parse().ok().zip(h.parse().ok());
+                    }
+                    _ => {
+                        return Err(Chip8Error::ConfigError(format!(
+                            "can't tell what \"{}\" says about the terminal (try e.g. 256,unicode or 80x25)",
+                            cap
+                        )))
+                    }
+                },
+            }
+        }
+        Ok(self)
+    }
+
+    /// the ways there are to draw the picture, best first
+    fn layouts(&self) -> &'static [(Cells, Scale)] {
+        match self.unicode {
+            true => &UNICODE_LAYOUTS,
+            false => &ASCII_LAYOUTS,
+        }
+    }
+
+    /// the best way to draw a width x height picture that fits, falling
+    /// back on the best that fits the VIP's 64x32 (a bigger picture's
+    /// clipped), or an error saying what it'd take if not even that does.
+    /// if there's no telling how big the terminal is, the best there is
+    pub fn fit(&self, width: usize, height: usize) -> Result<(Cells, Scale), Chip8Error> {
+        if self.dumb {
+            return Err(Chip8Error::DisplayError(
+                "the terminal's dumb (TERM=dumb), so it can't draw the picture: try --frontend text, or --terminal-caps 16 if it's cleverer than that".to_string(),
+            ));
+        }
+        let layouts = self.layouts();
+        let Some((columns, rows)) = self.size else {
+            return Ok(layouts[0]);
+        };
+        let fits = |(w, h)| {
+            layouts.iter().copied().find(|(cells, scale)| {
+                let (c, r) = footprint(*cells, *scale, w, h);
+                c <= columns && r <= rows
+            })
+        };
+        if let Some(layout) = fits((width, height)).or_else(|| fits((64, 32))) {
+            return Ok(layout);
+        }
+        let (cells, scale) = layouts[layouts.len() - 1];
+        let (c, r) = footprint(cells, scale, 64, 32);
+        Err(Chip8Error::DisplayError(format!(
+            "the terminal's {}x{}, and the picture needs at least {}x{}: make it bigger (or the font smaller){}, or try --frontend text",
+            columns,
+            rows,
+            c,
+            r,
+            match self.unicode {
+                true => "",
+                false => ", let it use Unicode for smaller pixels if it can (--terminal-caps unicode)",
+            }
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env<'a>(vars: &'a [(&str, &str)]) -> impl Fn(&str) -> Option<String> + 'a {
+        move |name| {
+            vars.iter()
+                .find(|(n, _)| *n == name)
+                .map(|(_, v)| v.to_string())
+        }
+    }
+
+    #[test]
+    fn test_detect() {
+        let caps = Capabilities::from_env(
+            env(&[
+                ("TERM", "xterm-256color"),
+                ("COLORTERM", "truecolor"),
+                ("LANG", "en_GB.UTF-8"),
+            ]),
+            Some((200, 60)),
+        );
+        assert_eq!(caps.colours, Colours::TrueColour);
+        assert!(caps.unicode && !caps.dumb);
+        let caps = Capabilities::from_env(
+            env(&[
+                ("TERM", "xterm-256color"),
+                ("LC_ALL", "C"),
+                ("LANG", "en_GB.utf8"),
+            ]),
+            None,
+        );
+        assert_eq!(caps.colours, Colours::Ansi256);
+        assert_eq!(caps.unicode, cfg!(windows));
+        // NO_COLOR's over everything, and an empty one's not set
+        let caps = Capabilities::from_env(
+            env(&[("TERM", "linux"), ("NO_COLOR", "1"), ("COLORTERM", "")]),
+            None,
+        );
+        assert_eq!(caps.colours, Colours::Mono);
+        let caps = Capabilities::from_env(env(&[("TERM", "linux"), ("NO_COLOR", "")]), None);
+        assert_eq!(caps.colours, Colours::Ansi16);
+        let caps = Capabilities::from_env(env(&[("TERM", "dumb")]), None);
+        assert!(caps.dumb);
+    }
+
+    #[test]
+    fn test_overridden() -> Result<(), Chip8Error> {
+        let dumb = Capabilities::from_env(env(&[("TERM", "dumb")]), None);
+        let caps = dumb.overridden("256, unicode,80x25")?;
+        assert_eq!(
+            caps,
+            Capabilities {
+                colours: Colours::Ansi256,
+                unicode: true,
+                size: Some((80, 25)),
+                dumb: false,
+            }
+        );
+        assert!(!caps.overridden("ascii")?.unicode);
+        assert!(caps.overridden("sepia").is_err());
+        assert!(caps.overridden("80x").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_fit() -> Result<(), Chip8Error> {
+        let caps = |unicode, size| Capabilities {
+            colours: Colours::Ansi16,
+            unicode,
+            size,
+            dumb: false,
+        };
+        // a big one's as it always was
+        let block = (Cells::Block, Scale(1, 1));
+        assert_eq!(caps(true, Some((200, 60))).fit(64, 32)?, block);
+        assert_eq!(caps(true, None).fit(64, 32)?, block);
+        // 80x25 gets square quadrants
+        assert_eq!(
+            caps(true, Some((80, 25))).fit(64, 32)?,
+            (Cells::Quadrant, Scale(2, 1))
+        );
+        // and SCHIP's hires the smallest of all, in a small one
+        assert_eq!(
+            caps(true, Some((80, 25))).fit(128, 64)?,
+            (Cells::Sextant, Scale(1, 1))
+        );
+        // with too little room for hires, it's as if it were lores
+        assert_eq!(
+            caps(true, Some((66, 19))).fit(128, 64)?,
+            (Cells::Quadrant, Scale(2, 1))
+        );
+        // ASCII only has the one
+        assert_eq!(
+            caps(false, Some((66, 35))).fit(64, 32)?,
+            (Cells::Ascii, Scale(1, 1))
+        );
+        // and refuses to start with what it'd take
+        let e = caps(false, Some((80, 25))).fit(64, 32).unwrap_err();
+        assert!(e.to_string().contains("66x35"), "{}", e);
+        let e = caps(true, Some((20, 10))).fit(64, 32).unwrap_err();
+        assert!(e.to_string().contains("34x14"), "{}", e);
+        assert!(Capabilities::from_env(env(&[("TERM", "dumb")]), None)
+            .fit(64, 32)
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_colours() {
+        let orange = Color::Rgb(0xff, 0x66, 0x00);
+        assert_eq!(Colours::TrueColour.nearest(orange), orange);
+        assert_eq!(Colours::Ansi256.nearest(orange), Color::Indexed(202));
+        assert_eq!(
+            Colours::Ansi256.nearest(Color::Rgb(0x80, 0x80, 0x80)),
+            Color::Indexed(244)
+        );
+        assert_eq!(Colours::Ansi16.nearest(orange), Color::LightRed);
+        assert_eq!(
+            Colours::Ansi16.nearest(Color::Indexed(196)),
+            Color::LightRed
+        );
+        assert_eq!(Colours::Ansi16.nearest(Color::White), Color::White);
+        assert_eq!(Colours::Mono.nearest(orange), Color::Reset);
+        // high contrast's as near as it gets, and with none it's glyphs
+        let theme = Colours::Ansi16.theme(Theme::HIGH_CONTRAST);
+        assert_eq!(
+            (theme.lit, theme.unlit, theme.text),
+            (Color::White, Color::Black, Color::LightYellow)
+        );
+        assert!(!theme.glyphs && theme.bold);
+        assert!(Colours::Mono.theme(Theme::CLASSIC).glyphs);
+        assert!(Colours::parse("sepia").is_err());
+    }
+}
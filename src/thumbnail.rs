@@ -0,0 +1,241 @@
+//! # thumbnails
+//!
+//! a picture of what a ROM looks like, for the gallery and --info. it's
+//! made by running the ROM headless for a couple of seconds, which is
+//! deterministic (the seed's fixed and nobody presses anything), so the
+//! same ROM always gets the same thumbnail and it can be cached on disk
+//! under the ROM's hash
+use crate::display::Display;
+use crate::error::Chip8Error;
+use crate::input::DummyInput;
+use crate::interpreter::Chip8Interpreter;
+use crate::paths;
+use crate::replay::frame_hash;
+use crate::sound::Mute;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// how long to run a ROM for its thumbnail
+const THUMBNAIL_FRAMES: u64 = 120;
+/// bytes in a 64x32 frame, the only kind we keep
+pub const THUMBNAIL_BYTES: usize = 0x100;
+
+/// keeps the busiest frame it's shown. the last frame isn't always a good
+/// likeness: plenty of ROMs clear the screen and redraw everything each
+/// frame, and an interrupt halfway through catches it nearly empty
+struct Busiest {
+    frame: Vec<u8>,
+    lit: u32,
+}
+
+impl Display for Busiest {
+    fn draw(&mut self, data: &[u8]) -> Result<(), Chip8Error> {
+        // hires frames wouldn't fit in the thumbnail, so they're left out
+        let lit = data.iter().map(|b| b.count_ones()).sum();
+        if data.len() == THUMBNAIL_BYTES && lit >= self.lit {
+            self.frame = data.to_vec();
+            self.lit = lit;
+        }
+        Ok(())
+    }
+
+    fn get_display_size_bytes(&mut self) -> usize {
+        THUMBNAIL_BYTES
+    }
+
+    fn set_mode(&mut self, _width: usize, _height: usize) -> Result<(), Chip8Error> {
+        Ok(())
+    }
+}
+
+/// run rom for a couple of seconds, and give back its busiest 64x32 frame
+pub fn capture(rom: &[u8]) -> Result<Vec<u8>, Chip8Error> {
+    let mut display = Busiest {
+        frame: vec![0; THUMBNAIL_BYTES],
+        lit: 0,
+    };
+    let mut input = DummyInput::new(&[]);
+    let mut sound = Mute::new();
+    {
+        let mut machine = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+        machine.set_seed(0);
+        machine.load_program(&mut &rom[..])?;
+        machine.run_frames(THUMBNAIL_FRAMES)?;
+    }
+    Ok(display.frame)
+}
+
+/// a 64x32 frame as 32x8 braille characters, each 2x4 pixels
+pub fn braille(frame: &[u8]) -> Vec<String> {
+    braille_sized(frame, 64, 32)
+}
+
+/// a 64x32 frame at half the size, as 16x4 braille characters, where
+/// there isn't room for a few of braille()'s
+pub fn small_braille(frame: &[u8]) -> Vec<String> {
+    braille_sized(&halve(frame, 64, 32), 32, 16)
+}
+
+fn braille_sized(frame: &[u8], width: usize, height: usize) -> Vec<String> {
+    // braille dot bits, by (x, y) within the character
+    const DOTS: [[u32; 4]; 2] = [[0x01, 0x02, 0x04, 0x40], [0x08, 0x10, 0x20, 0x80]];
+    (0..height)
+        .step_by(4)
+        .map(|y| {
+            (0..width)
+                .step_by(2)
+                .map(|x| {
+                    let mut dots = 0;
+                    for (dx, column) in DOTS.iter().enumerate() {
+                        for (dy, dot) in column.iter().enumerate() {
+                            if lit(frame, width, x + dx, y + dy) {
+                                dots |= dot;
+                            }
+                        }
+                    }
+                    char::from_u32(0x2800 + dots).unwrap_or(' ')
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn lit(frame: &[u8], width: usize, x: usize, y: usize) -> bool {
+    x < width
+        && frame
+            .get((y * width + x) / 8)
+            .is_some_and(|b| b & (0x80 >> (x % 8)) != 0)
+}
+
+/// a frame at half the width and height, a pixel lit wherever any of the
+/// four it stands for were
+pub fn halve(frame: &[u8], width: usize, height: usize) -> Vec<u8> {
+    let mut half = vec![0; width * height / 32];
+    for y in 0..height / 2 {
+        for x in 0..width / 2 {
+            let any = (0..4).any(|n| lit(frame, width, x * 2 + n % 2, y * 2 + n / 2));
+            if any {
+                half[(y * width / 2 + x) / 8] |= 0x80 >> (x % 8);
+            }
+        }
+    }
+    half
+}
+
+/// a frame from either resolution as a 64x32 one, the only kind we keep
+pub fn fit(frame: &[u8]) -> Vec<u8> {
+    match frame.len() {
+        THUMBNAIL_BYTES => frame.to_vec(),
+        _ => halve(frame, 128, 64),
+    }
+}
+
+/// thumbnails we've already made, one file per ROM hash
+pub struct ThumbnailCache {
+    dir: PathBuf,
+}
+
+impl ThumbnailCache {
+    pub fn new(dir: &Path) -> Self {
+        ThumbnailCache {
+            dir: dir.to_path_buf(),
+        }
+    }
+
+    /// in the cache directory, e.g. ~/.cache/chip8/thumbnails
+    pub fn default_dir() -> PathBuf {
+        paths::thumbnails_dir()
+    }
+
+    fn path(&self, rom: &[u8]) -> PathBuf {
+        self.dir.join(format!("{:016x}.thumb", frame_hash(rom)))
+    }
+
+    /// rom's thumbnail frame, from the cache if it's there, otherwise
+    /// captured (and cached for next time)
+    pub fn get(&self, rom: &[u8]) -> Result<Vec<u8>, Chip8Error> {
+        let path = self.path(rom);
+        if let Ok(frame) = fs::read(&path) {
+            if frame.len() == THUMBNAIL_BYTES {
+                return Ok(frame);
+            }
+        }
+        let frame = capture(rom)?;
+        // the cache only saves time, so not being able to write it (e.g. a
+        // read-only home) isn't worth stopping for
+        if fs::create_dir_all(&self.dir).is_ok() {
+            let _ = fs::write(&path, &frame);
+        }
+        Ok(frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_braille() {
+        let mut frame = [0u8; 0x100];
+        // top left pixel, and the bottom right one
+        frame[0] = 0x80;
+        frame[0xff] = 0x01;
+        let t = braille(&frame);
+        assert_eq!(t.len(), 8);
+        assert!(t.iter().all(|row| row.chars().count() == 32));
+        assert!(t[0].starts_with('\u{2801}'));
+        assert!(t[7].ends_with('\u{2880}'));
+        assert_eq!(t[3], "\u{2800}".repeat(32));
+        // and at half the size, where they've both still got a dot
+        let t = small_braille(&frame);
+        assert_eq!(t.len(), 4);
+        assert!(t.iter().all(|row| row.chars().count() == 16));
+        assert!(t[0].starts_with('\u{2801}'));
+        assert!(t[3].ends_with('\u{2880}'));
+    }
+
+    #[test]
+    fn test_fit() {
+        // a hires frame with its top left pixel lit, and the bottom right
+        let mut frame = [0u8; 0x400];
+        frame[0] = 0x80;
+        frame[0x3ff] = 0x01;
+        let fitted = fit(&frame);
+        assert_eq!(fitted.len(), THUMBNAIL_BYTES);
+        assert_eq!(fitted[0], 0x80);
+        assert_eq!(fitted[0xff], 0x01);
+        assert_eq!(fitted.iter().map(|b| b.count_ones()).sum::<u32>(), 2);
+        assert_eq!(fit(&fitted), fitted);
+    }
+
+    #[test]
+    fn test_capture_keeps_the_busiest_frame() -> Result<(), Chip8Error> {
+        // draw a digit, rub it out, wait a bit, and stop on an empty screen
+        #[rustfmt::skip]
+        let rom = [
+            0x60, 0x08, 0xf0, 0x29, 0xd1, 0x15, // draw an 8
+            0x62, 0x04, 0xf2, 0x15, 0xf2, 0x07, 0x32, 0x00, 0x12, 0x0a,
+            0xd1, 0x15, 0x12, 0x12,             // rub it out, stop
+        ];
+        let frame = capture(&rom)?;
+        assert_eq!(frame.iter().map(|b| b.count_ones()).sum::<u32>(), 16);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cache() -> Result<(), Chip8Error> {
+        let dir = std::env::temp_dir().join(format!("chip8-thumbnails-{}", std::process::id()));
+        let cache = ThumbnailCache::new(&dir);
+        let rom = [0x60, 0x00, 0xf0, 0x29, 0xd0, 0x05, 0x12, 0x06];
+        let frame = cache.get(&rom)?;
+        assert_eq!(fs::read(cache.path(&rom))?, frame);
+
+        // whatever's in the cache wins, so long as it's the right size
+        fs::write(cache.path(&rom), [0xff; 0x100])?;
+        assert_eq!(cache.get(&rom)?, [0xff; 0x100]);
+        fs::write(cache.path(&rom), [0xff; 3])?;
+        assert_eq!(cache.get(&rom)?, frame);
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
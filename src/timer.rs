@@ -0,0 +1,124 @@
+#[cfg(feature = "full")]
+use serde::{Deserialize, Serialize};
+
+/// the CHIP-8 general (delay) and tone (sound) timers. these count down once
+/// per emulated frame, i.e. every CHIP8_FRAME_CYCLES machine cycles (3668,
+/// or 29,344 clock cycles at 1.76064 MHz), however fast the host is running
+/// us
+#[derive(Default, Clone)]
+#[cfg_attr(feature = "full", derive(Serialize, Deserialize))]
+pub struct Timers {
+    pub general: u8,
+    pub tone: u8,
+}
+
+/// the shortest tone the VIP can sound, in frames: its manual warns that a
+/// tone timer of 1 won't be heard
+pub const TONE_MIN_FRAMES: u8 = 2;
+
+/// what happened when the timers ticked
+#[derive(Debug, PartialEq)]
+pub struct TimerTick {
+    /// machine cycles the VIP's ISR spends updating the timers
+    pub cycles: usize,
+    /// the tone timer just ran out, so the buzzer should stop
+    pub tone_stopped: bool,
+}
+
+impl Timers {
+    pub fn new() -> Self {
+        Timers {
+            general: 0x00,
+            tone: 0x00,
+        }
+    }
+
+    /// will the tone timer, as it's just been set, be heard? anything
+    /// will, with short_tone, or else at least TONE_MIN_FRAMES
+    pub fn tone_audible(&self, short_tone: bool) -> bool {
+        match short_tone {
+            true => self.tone > 0,
+            false => self.tone >= TONE_MIN_FRAMES,
+        }
+    }
+
+    /// count down by one frame
+    pub fn tick(&mut self) -> TimerTick {
+        // from https://laurencescotford.com/chip-8-on-the-cosmac-vip-interrupts/
+        let mut tick = TimerTick {
+            cycles: 0,
+            tone_stopped: false,
+        };
+
+        // update general timer
+        if self.general > 0 {
+            self.general -= 1;
+            tick.cycles += 8;
+        }
+
+        // update tone timer
+        if self.tone > 0 {
+            self.tone -= 1;
+            tick.tone_stopped = self.tone == 0;
+            tick.cycles += 4;
+        }
+        tick
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_idle_tick() {
+        let mut t = Timers::new();
+        assert_eq!(
+            t.tick(),
+            TimerTick {
+                cycles: 0,
+                tone_stopped: false
+            }
+        );
+    }
+
+    #[test]
+    fn test_general_tick() {
+        let mut t = Timers::new();
+        t.general = 2;
+        assert_eq!(t.tick().cycles, 8);
+        assert_eq!(t.general, 1);
+    }
+
+    #[test]
+    fn test_tone_audible() {
+        let mut t = Timers::new();
+        t.tone = 1;
+        assert!(!t.tone_audible(false));
+        assert!(t.tone_audible(true));
+        t.tone = TONE_MIN_FRAMES;
+        assert!(t.tone_audible(false));
+        t.tone = 0;
+        assert!(!t.tone_audible(true));
+    }
+
+    #[test]
+    fn test_tone_stops() {
+        let mut t = Timers::new();
+        t.tone = 2;
+        assert!(!t.tick().tone_stopped);
+        let tick = t.tick();
+        assert!(tick.tone_stopped);
+        assert_eq!(tick.cycles, 4);
+        assert!(!t.tick().tone_stopped);
+    }
+
+    #[test]
+    fn test_frame_cycles() {
+        // what the doc up there says a frame is
+        use crate::cdp1802::CLOCKS_PER_MACHINE_CYCLE;
+        use crate::interpreter::CHIP8_FRAME_CYCLES;
+        assert_eq!(CHIP8_FRAME_CYCLES, 3668);
+        assert_eq!(CHIP8_FRAME_CYCLES * CLOCKS_PER_MACHINE_CYCLE, 29_344);
+    }
+}
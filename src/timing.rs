@@ -0,0 +1,140 @@
+//! # timing
+//!
+//! how many machine cycles the interpreter's own work costs, as opposed to
+//! the program's instructions: the display interrupt's routine, the 1861's
+//! DMA stealing cycles to put the picture out, and fetching and decoding
+//! each instruction. the VIP's figures are Laurence Scotford's, from
+//! stepping through the interpreter
+//! (<https://laurencescotford.com/chip-8-on-the-cosmac-vip-interrupts/>);
+//! there are no published measurements for the other interpreters of the
+//! time (the two-page display's, the patched ones), so there are no
+//! presets for them either: a made-up figure passed off as a measured one
+//! is worse than none, for anyone studying how the real thing ran. anyone
+//! who's timed one can give its figures on top of a preset, e.g.
+//! --timing vip+interrupt=850, or timing = "vip+interrupt=850" in the
+//! config, and see what difference it makes. what each instruction costs,
+//! rather than the interpreter's own work, is costs.rs's, and can be given
+//! the same way
+use crate::error::Chip8Error;
+#[cfg(feature = "full")]
+use serde::{Deserialize, Serialize};
+
+/// machine cycles the interpreter spends on things other than the program
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "full", derive(Serialize, Deserialize))]
+pub struct Timing {
+    /// the display interrupt's routine, not counting the DMA or the timers
+    pub interrupt: u32,
+    /// what the 1861's DMA steals to put a frame out
+    pub dma: u32,
+    /// fetching and decoding an instruction
+    pub fetch: u32,
+    /// fetching and decoding a 0NNN, which is quicker, as it doesn't need
+    /// to look up a handler
+    pub fetch_machine_code: u32,
+}
+
+impl Timing {
+    /// the VIP's own interpreter, as measured
+    pub const VIP: Timing = Timing {
+        interrupt: 807,
+        dma: 1024,
+        fetch: 68,
+        fetch_machine_code: 40,
+    };
+
+    /// an interrupt that costs the program nothing, as with interpreters
+    /// that count the timers down from another thread: every cycle of the
+    /// frame's the program's. instructions still take as long as the VIP's
+    pub const FREE: Timing = Timing {
+        interrupt: 0,
+        dma: 0,
+        ..Timing::VIP
+    };
+
+    /// the presets there are, by name: only what's been measured, and
+    /// what's free by definition
+    pub const PRESETS: [(&'static str, Timing); 2] = [("vip", Timing::VIP), ("free", Timing::FREE)];
+
+    /// the figures that can be given by hand, by name
+    pub const FIGURES: [&'static str; 4] = ["interrupt", "dma", "fetch", "fetch-0nnn"];
+
+    /// look up a preset by name, e.g. "vip", with any figures to change
+    /// after it, e.g. "vip+interrupt=850+dma=1000"
+    pub fn preset(name: &str) -> Result<Timing, Chip8Error> {
+        let mut parts = name.split('+');
+        let preset = parts.next().unwrap_or_default();
+        let mut timing = Self::PRESETS
+            .iter()
+            .find(|(n, _)| *n == preset)
+            .map(|(_, t)| *t)
+            .ok_or_else(|| {
+                Chip8Error::ConfigError(format!(
+                    "no timing preset called \"{}\" (try {})",
+                    preset,
+                    Self::PRESETS.map(|(n, _)| n).join(" or ")
+                ))
+            })?;
+        for figure in parts {
+            timing.set(figure)?;
+        }
+        Ok(timing)
+    }
+
+    /// change one figure, given as e.g. "dma=1000"
+    fn set(&mut self, figure: &str) -> Result<(), Chip8Error> {
+        let (name, cycles) = figure.split_once('=').unwrap_or((figure, ""));
+        let cycles = cycles.parse().map_err(|_| {
+            Chip8Error::ConfigError(format!(
+                "{} needs a number of machine cycles, e.g. {}=100",
+                name, name
+            ))
+        });
+        match name {
+            "interrupt" => self.interrupt = cycles?,
+            "dma" => self.dma = cycles?,
+            "fetch" => self.fetch = cycles?,
+            "fetch-0nnn" => self.fetch_machine_code = cycles?,
+            _ => {
+                return Err(Chip8Error::ConfigError(format!(
+                    "no timing figure called \"{}\" (try {})",
+                    name,
+                    Self::FIGURES.join(", ")
+                )))
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for Timing {
+    fn default() -> Self {
+        Timing::VIP
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_preset() -> Result<(), Chip8Error> {
+        assert_eq!(Timing::preset("vip")?, Timing::default());
+        assert_eq!(Timing::preset("free")?.interrupt, 0);
+        assert!(Timing::preset("eti660").is_err());
+
+        let timing = Timing::preset("vip+interrupt=850+fetch-0nnn=36")?;
+        assert_eq!(
+            timing,
+            Timing {
+                interrupt: 850,
+                fetch_machine_code: 36,
+                ..Timing::VIP
+            }
+        );
+        assert!(Timing::preset("vip+dma").is_err());
+        assert!(Timing::preset("vip+dma=lots").is_err());
+        assert!(Timing::preset("vip+halt=1").is_err());
+        Ok(())
+    }
+}
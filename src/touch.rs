@@ -0,0 +1,80 @@
+//! # touch
+//!
+//! a keypad on the screen, for playing on a tablet or a phone. there's no
+//! windowed or web frontend to put it in yet, but terminals on phones
+//! (Termux and the like) pass a tap on as a mouse click, so --touch draws
+//! the VIP's 4x4 keypad in the corner of the terminal and StdinInput turns
+//! clicks on it into key presses. terminals only report one pointer, so
+//! it's one key at a time, as it is from the keyboard
+use tui::layout::Rect;
+
+/// the VIP's keypad, as it's laid out on the case
+pub const KEYPAD_LAYOUT: [[u8; 4]; 4] = [
+    [0x1, 0x2, 0x3, 0xc],
+    [0x4, 0x5, 0x6, 0xd],
+    [0x7, 0x8, 0x9, 0xe],
+    [0xa, 0x0, 0xb, 0xf],
+];
+
+/// each button's size in characters, border and all: big enough for a
+/// finger
+pub const BUTTON_WIDTH: u16 = 6;
+pub const BUTTON_HEIGHT: u16 = 3;
+
+/// each button on a terminal of size, and its key. the keypad goes in the
+/// bottom right corner, over the picture if there's no room beside it
+pub fn buttons(size: Rect) -> Vec<(Rect, u8)> {
+    // counted back from the corner, so a small terminal loses the top left
+    let corner =
+        |end: u16, from_end: usize, step: u16| end as i32 - (from_end as i32 * step as i32);
+    KEYPAD_LAYOUT
+        .iter()
+        .enumerate()
+        .flat_map(|(y, row)| {
+            row.iter().enumerate().map(move |(x, key)| {
+                let left = corner(size.right(), 4 - x, BUTTON_WIDTH).max(size.left() as i32);
+                let top = corner(size.bottom(), 4 - y, BUTTON_HEIGHT).max(size.top() as i32);
+                let right = corner(size.right(), 3 - x, BUTTON_WIDTH);
+                let bottom = corner(size.bottom(), 3 - y, BUTTON_HEIGHT);
+                let button = Rect::new(
+                    left as u16,
+                    top as u16,
+                    (right - left).max(0) as u16,
+                    (bottom - top).max(0) as u16,
+                );
+                (button, *key)
+            })
+        })
+        .filter(|(button, _)| button.area() > 0)
+        .collect()
+}
+
+/// the key under a touch at column, row, if there is one
+pub fn key_at(size: Rect, column: u16, row: u16) -> Option<u8> {
+    buttons(size)
+        .into_iter()
+        .find(|(b, _)| {
+            (b.left()..b.right()).contains(&column) && (b.top()..b.bottom()).contains(&row)
+        })
+        .map(|(_, key)| key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_at() {
+        let size = Rect::new(0, 0, 100, 40);
+        assert_eq!(buttons(size).len(), 16);
+        // the top left button's 1, the bottom right's F
+        assert_eq!(key_at(size, 76, 28), Some(0x1));
+        assert_eq!(key_at(size, 99, 39), Some(0xf));
+        assert_eq!(key_at(size, 83, 37), Some(0x0));
+        assert_eq!(key_at(size, 75, 28), None);
+        assert_eq!(key_at(size, 10, 10), None);
+        // a terminal too small for all of it gets what fits
+        let small = Rect::new(0, 0, 12, 6);
+        assert_eq!(key_at(small, 0, 0), Some(0x9));
+    }
+}
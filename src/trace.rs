@@ -0,0 +1,509 @@
+//! # tracing
+//!
+//! a record of each instruction as it's about to run: which frame, where,
+//! what, and the registers going into it. hand the interpreter a Tracer
+//! with add_tracer; a TraceRing keeps just the last few, e.g. for a bug
+//! report, so tracing a long run doesn't fill up memory.
+//!
+//! --trace writes every entry to a file instead, in one of a few formats:
+//! text to read, JSON lines for jq or pandas, CSV for a spreadsheet, and a
+//! compact binary one for long runs (under half the size of the text).
+//! chip8 trace-convert turns any of them into any other.
+//!
+//! the JSON lines are the one to export to from other emulators, for chip8
+//! trace-diff: an object a line with `frame`, `pc`, `opcode`, `v` (all
+//! sixteen registers) and `i`, as plain numbers, taken just before each
+//! instruction runs, e.g.
+//!
+//! ```text
+//! {"frame":1,"pc":512,"opcode":28673,"v":[0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0],"i":0}
+//! ```
+use crate::error::Chip8Error;
+#[cfg(feature = "full")]
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{BufRead, Write};
+use std::path::Path;
+
+/// what a binary trace starts with; the last byte's the version
+const TRACE_MAGIC: &[u8; 4] = b"C8T\x01";
+/// how long each entry is in a binary trace
+const TRACE_RECORD_LEN: usize = 30;
+const TRACE_CSV_HEADER: &str = "frame,pc,opcode,v0,v1,v2,v3,v4,v5,v6,v7,v8,v9,va,vb,vc,vd,ve,vf,i";
+
+/// one instruction, as it was about to run
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "full", derive(Serialize, Deserialize))]
+pub struct TraceEntry {
+    pub frame: u64,
+    pub pc: u16,
+    pub opcode: u16,
+    pub v: [u8; 16],
+    pub i: u16,
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:09} {:04x}: {:04x}  v",
+            self.frame, self.pc, self.opcode
+        )?;
+        for v in self.v {
+            write!(f, " {:02x}", v)?;
+        }
+        write!(f, "  i {:04x}", self.i)
+    }
+}
+
+impl TraceEntry {
+    /// the other way from Display
+    fn parse_text(s: &str) -> Option<Self> {
+        let mut words = s.split_whitespace();
+        let frame = words.next()?.parse().ok()?;
+        let pc = u16::from_str_radix(words.next()?.strip_suffix(':')?, 16).ok()?;
+        let opcode = u16::from_str_radix(words.next()?, 16).ok()?;
+        if words.next()? != "v" {
+            return None;
+        }
+        let mut v = [0; 16];
+        for r in v.iter_mut() {
+            *r = u8::from_str_radix(words.next()?, 16).ok()?;
+        }
+        if words.next()? != "i" {
+            return None;
+        }
+        let i = u16::from_str_radix(words.next()?, 16).ok()?;
+        Some(TraceEntry {
+            frame,
+            pc,
+            opcode,
+            v,
+            i,
+        })
+    }
+
+    fn to_csv(&self) -> String {
+        let mut line = format!("{},{},{}", self.frame, self.pc, self.opcode);
+        for v in self.v {
+            line += &format!(",{}", v);
+        }
+        line + &format!(",{}", self.i)
+    }
+
+    fn parse_csv(s: &str) -> Option<Self> {
+        let fields = s
+            .split(',')
+            .map(|f| f.trim().parse::<u64>().ok())
+            .collect::<Option<Vec<u64>>>()?;
+        if fields.len() != 20 {
+            return None;
+        }
+        let mut v = [0; 16];
+        for (r, f) in v.iter_mut().zip(&fields[3..19]) {
+            *r = u8::try_from(*f).ok()?;
+        }
+        Some(TraceEntry {
+            frame: fields[0],
+            pc: u16::try_from(fields[1]).ok()?,
+            opcode: u16::try_from(fields[2]).ok()?,
+            v,
+            i: u16::try_from(fields[19]).ok()?,
+        })
+    }
+
+    fn to_record(&self) -> [u8; TRACE_RECORD_LEN] {
+        let mut record = [0; TRACE_RECORD_LEN];
+        record[..8].copy_from_slice(&self.frame.to_le_bytes());
+        record[8..10].copy_from_slice(&self.pc.to_le_bytes());
+        record[10..12].copy_from_slice(&self.opcode.to_le_bytes());
+        record[12..28].copy_from_slice(&self.v);
+        record[28..].copy_from_slice(&self.i.to_le_bytes());
+        record
+    }
+
+    fn from_record(record: &[u8; TRACE_RECORD_LEN]) -> Self {
+        let word = |n: usize| u16::from_le_bytes([record[n], record[n + 1]]);
+        TraceEntry {
+            frame: u64::from_le_bytes(record[..8].try_into().unwrap()),
+            pc: word(8),
+            opcode: word(10),
+            v: record[12..28].try_into().unwrap(),
+            i: word(28),
+        }
+    }
+}
+
+/// how a trace file's written
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum TraceFormat {
+    /// as TraceEntry displays, a line each
+    Text,
+    /// a JSON object per line
+    JsonLines,
+    /// with a header, numbers in decimal
+    Csv,
+    /// fixed-size little-endian records after a magic number
+    Binary,
+}
+
+impl TraceFormat {
+    /// a format by name, e.g. from --trace-format
+    pub fn parse(name: &str) -> Result<Self, Chip8Error> {
+        match name {
+            "text" => Ok(TraceFormat::Text),
+            "jsonl" => Ok(TraceFormat::JsonLines),
+            "csv" => Ok(TraceFormat::Csv),
+            "bin" | "binary" => Ok(TraceFormat::Binary),
+            _ => Err(Chip8Error::ConfigError(format!(
+                "no trace format called \"{}\" (try text, jsonl, csv or bin)",
+                name
+            ))),
+        }
+    }
+
+    /// going by the file's extension; text if it's not one we know
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("jsonl") | Some("json") => TraceFormat::JsonLines,
+            Some("csv") => TraceFormat::Csv,
+            Some("bin") | Some("c8t") => TraceFormat::Binary,
+            _ => TraceFormat::Text,
+        }
+    }
+}
+
+/// something that wants to hear about every instruction
+pub trait Tracer {
+    fn trace(&mut self, entry: &TraceEntry) -> Result<(), Chip8Error>;
+}
+
+// so it can still be got at while the interpreter has it, like a watch
+impl<T: Tracer> Tracer for &RefCell<T> {
+    fn trace(&mut self, entry: &TraceEntry) -> Result<(), Chip8Error> {
+        self.borrow_mut().trace(entry)
+    }
+}
+
+/// keeps the last few entries, forgetting the oldest
+pub struct TraceRing {
+    entries: VecDeque<TraceEntry>,
+    capacity: usize,
+}
+
+impl TraceRing {
+    pub fn new(capacity: usize) -> Self {
+        TraceRing {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// oldest first
+    pub fn entries(&self) -> impl Iterator<Item = &TraceEntry> {
+        self.entries.iter()
+    }
+}
+
+impl Tracer for TraceRing {
+    fn trace(&mut self, entry: &TraceEntry) -> Result<(), Chip8Error> {
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry.clone());
+        Ok(())
+    }
+}
+
+/// writes every entry to out
+pub struct TraceWriter<W: Write> {
+    out: W,
+    format: TraceFormat,
+    started: bool,
+}
+
+impl<W: Write> TraceWriter<W> {
+    pub fn new(out: W, format: TraceFormat) -> Self {
+        TraceWriter {
+            out,
+            format,
+            started: false,
+        }
+    }
+
+    /// make sure everything's been written
+    pub fn flush(&mut self) -> Result<(), Chip8Error> {
+        self.start()?;
+        Ok(self.out.flush()?)
+    }
+
+    // the header, if the format has one, even if nothing ever ran
+    fn start(&mut self) -> Result<(), Chip8Error> {
+        if !std::mem::replace(&mut self.started, true) {
+            match self.format {
+                TraceFormat::Csv => writeln!(self.out, "{}", TRACE_CSV_HEADER)?,
+                TraceFormat::Binary => self.out.write_all(TRACE_MAGIC)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Tracer for TraceWriter<W> {
+    fn trace(&mut self, entry: &TraceEntry) -> Result<(), Chip8Error> {
+        self.start()?;
+        match self.format {
+            TraceFormat::Text => writeln!(self.out, "{}", entry)?,
+            TraceFormat::JsonLines => writeln!(self.out, "{}", to_json(entry)?)?,
+            TraceFormat::Csv => writeln!(self.out, "{}", entry.to_csv())?,
+            TraceFormat::Binary => self.out.write_all(&entry.to_record())?,
+        }
+        Ok(())
+    }
+}
+
+// JSON comes with the full emulator, and the core does without
+#[cfg(feature = "full")]
+fn to_json(entry: &TraceEntry) -> Result<String, Chip8Error> {
+    serde_json::to_string(entry).map_err(|e| Chip8Error::ConfigError(e.to_string()))
+}
+
+#[cfg(feature = "full")]
+fn from_json(line: &str) -> Option<TraceEntry> {
+    serde_json::from_str(line).ok()
+}
+
+#[cfg(not(feature = "full"))]
+fn to_json(_entry: &TraceEntry) -> Result<String, Chip8Error> {
+    Err(Chip8Error::ConfigError(
+        "JSON traces need the full feature".to_string(),
+    ))
+}
+
+#[cfg(not(feature = "full"))]
+fn from_json(_line: &str) -> Option<TraceEntry> {
+    None
+}
+
+fn bad_trace(at: &str, n: usize) -> Chip8Error {
+    Chip8Error::ConfigError(format!("bad trace file at {} {}", at, n))
+}
+
+/// reads a trace back, an entry at a time, so long ones needn't all fit in
+/// memory
+pub struct TraceReader<R: BufRead> {
+    input: R,
+    format: TraceFormat,
+    // lines (or records) read so far, for error messages
+    read: usize,
+}
+
+impl<R: BufRead> TraceReader<R> {
+    pub fn new(mut input: R, format: TraceFormat) -> Result<Self, Chip8Error> {
+        let mut read = 0;
+        match format {
+            TraceFormat::Binary => {
+                let mut magic = [0; 4];
+                input.read_exact(&mut magic)?;
+                if &magic != TRACE_MAGIC {
+                    return Err(Chip8Error::ConfigError(
+                        "that's not a binary trace (or not one this version reads)".to_string(),
+                    ));
+                }
+            }
+            TraceFormat::Csv => {
+                let mut header = String::new();
+                input.read_line(&mut header)?;
+                if header.trim_end() != TRACE_CSV_HEADER {
+                    return Err(bad_trace("line", 1));
+                }
+                read = 1;
+            }
+            _ => {}
+        }
+        Ok(TraceReader {
+            input,
+            format,
+            read,
+        })
+    }
+
+    fn next_entry(&mut self) -> Result<Option<TraceEntry>, Chip8Error> {
+        if self.format == TraceFormat::Binary {
+            let mut record = [0; TRACE_RECORD_LEN];
+            // a clean end only between records
+            if self.input.fill_buf()?.is_empty() {
+                return Ok(None);
+            }
+            self.read += 1;
+            self.input
+                .read_exact(&mut record)
+                .map_err(|_| bad_trace("record", self.read))?;
+            return Ok(Some(TraceEntry::from_record(&record)));
+        }
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.input.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            self.read += 1;
+            if !line.trim().is_empty() {
+                break;
+            }
+        }
+        let entry = match self.format {
+            TraceFormat::JsonLines => from_json(&line),
+            TraceFormat::Csv => TraceEntry::parse_csv(&line),
+            _ => TraceEntry::parse_text(&line),
+        };
+        entry.map(Some).ok_or_else(|| bad_trace("line", self.read))
+    }
+}
+
+impl<R: BufRead> Iterator for TraceReader<R> {
+    type Item = Result<TraceEntry, Chip8Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_entry().transpose()
+    }
+}
+
+/// copy a trace from one format to another, returning how many entries
+/// there were
+pub fn convert(
+    input: impl BufRead,
+    from: TraceFormat,
+    output: impl Write,
+    to: TraceFormat,
+) -> Result<usize, Chip8Error> {
+    let mut writer = TraceWriter::new(output, to);
+    let mut n = 0;
+    for entry in TraceReader::new(input, from)? {
+        writer.trace(&entry?)?;
+        n += 1;
+    }
+    writer.flush()?;
+    Ok(n)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::DummyDisplay;
+    use crate::input::DummyInput;
+    use crate::interpreter::Chip8Interpreter;
+    use crate::sound::Mute;
+
+    #[test]
+    fn test_trace_ring() -> Result<(), Chip8Error> {
+        // v0 += 1 forever
+        let ring = RefCell::new(TraceRing::new(3));
+        let mut tracer = &ring;
+        let mut display = DummyDisplay::new()?;
+        let mut input = DummyInput::new(&[]);
+        let mut sound = Mute::new();
+        let mut machine = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+        machine.add_tracer(&mut tracer);
+        let mut rom: &[u8] = &[0x70, 0x01, 0x12, 0x00];
+        machine.load_program(&mut rom)?;
+        for _ in 0..5 {
+            machine.step()?;
+        }
+        drop(machine);
+        let ring = ring.borrow();
+        let entries: Vec<&TraceEntry> = ring.entries().collect();
+        // the last three of the five: add, jump, add
+        assert_eq!(entries.len(), 3);
+        assert_eq!((entries[1].pc, entries[1].opcode), (0x202, 0x1200));
+        assert_eq!((entries[2].pc, entries[2].v[0]), (0x200, 2));
+        assert_eq!(
+            entries[0].to_string(),
+            "000000001 0200: 7001  v 01 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00  i 0000"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_formats() -> Result<(), Chip8Error> {
+        let entries = [
+            TraceEntry {
+                frame: 1,
+                pc: 0x200,
+                opcode: 0x6a05,
+                v: [0; 16],
+                i: 0,
+            },
+            TraceEntry {
+                frame: 70_000,
+                pc: 0x202,
+                opcode: 0xd015,
+                v: [0xff, 0, 0, 0, 0, 0, 0, 0, 0, 0, 5, 0, 0, 0, 0, 1],
+                i: 0x3a0,
+            },
+        ];
+        let text = TraceFormat::Text;
+        let mut original = Vec::new();
+        let mut writer = TraceWriter::new(&mut original, text);
+        for e in &entries {
+            writer.trace(e)?;
+        }
+        writer.flush()?;
+        // every format there and back again gives the same text
+        for format in [
+            TraceFormat::JsonLines,
+            TraceFormat::Csv,
+            TraceFormat::Binary,
+        ] {
+            // JSON comes with the full emulator
+            if format == TraceFormat::JsonLines && !cfg!(feature = "full") {
+                continue;
+            }
+            let mut converted = Vec::new();
+            assert_eq!(convert(&original[..], text, &mut converted, format)?, 2);
+            let read: Vec<TraceEntry> =
+                TraceReader::new(&converted[..], format)?.collect::<Result<_, _>>()?;
+            assert_eq!(read, entries);
+            let mut back = Vec::new();
+            convert(&converted[..], format, &mut back, text)?;
+            assert_eq!(back, original);
+        }
+
+        let mut csv = Vec::new();
+        convert(&original[..], text, &mut csv, TraceFormat::Csv)?;
+        let csv = String::from_utf8(csv).unwrap();
+        assert_eq!(
+            csv.lines().nth(2),
+            Some("70000,514,53269,255,0,0,0,0,0,0,0,0,0,5,0,0,0,0,1,928")
+        );
+        let mut bin = Vec::new();
+        convert(&original[..], text, &mut bin, TraceFormat::Binary)?;
+        assert_eq!(bin.len(), 4 + 2 * TRACE_RECORD_LEN);
+        // cut off mid-record
+        let broken = TraceReader::new(&bin[..40], TraceFormat::Binary)?.collect::<Vec<_>>();
+        assert!(broken[0].is_ok() && broken[1].is_err());
+        assert!(TraceReader::new(&b"nonsense"[..], TraceFormat::Binary).is_err());
+        let mut lines = TraceReader::new(&b"1 0200: 6a05  v 00\n"[..], text)?;
+        assert!(lines.next().unwrap().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_from_path() -> Result<(), Chip8Error> {
+        for (path, format) in [
+            ("run.jsonl", TraceFormat::JsonLines),
+            ("run.csv", TraceFormat::Csv),
+            ("run.bin", TraceFormat::Binary),
+            ("run.txt", TraceFormat::Text),
+            ("run", TraceFormat::Text),
+        ] {
+            assert_eq!(TraceFormat::from_path(Path::new(path)), format);
+        }
+        assert_eq!(TraceFormat::parse("jsonl")?, TraceFormat::JsonLines);
+        assert!(TraceFormat::parse("xml").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,316 @@
+//! # the whole COSMAC VIP
+//!
+//! rather than interpreting CHIP-8 directly, this runs the VIP's own ROM and
+//! interpreter on the 1802, with the 1861 video chip and hex keypad wired up
+//! to it. slower, but it means booting into the ROM's hex monitor, just like
+//! holding C while flipping the RUN switch on the real thing
+use crate::cdp1802::{Cdp1802, Cdp1802Io};
+use crate::display::Display;
+use crate::error::Chip8Error;
+use crate::input::Input;
+use crate::interpreter::{RunOutcome, CHIP8_FRAME_CYCLES};
+use crate::memory::{Chip8MemoryMap, MemoryMap};
+use crate::sound::Sound;
+use std::cell::Cell;
+use std::time;
+
+/// the 1861 draws a line every 14 machine cycles, 262 lines a frame
+const VIP_LINE_CYCLES: u64 = 14;
+/// it asks for an interrupt two lines before the picture starts...
+const VIP_INTERRUPT_LINE: u64 = 78;
+/// ...which is 128 lines of 8 bytes, fetched by DMA
+const VIP_DISPLAY_LINE: u64 = 80;
+const VIP_DISPLAY_LINES: u64 = 128;
+/// each line's DMA comes a few cycles in, late enough for the interrupt
+/// routine to read R0 before the first one
+const VIP_DMA_OFFSET: u64 = 4;
+/// and EF1 warns of the picture starting and ending, four lines ahead
+const VIP_EF1_LINES: u64 = 4;
+/// how long to hold C down for when booting into the monitor
+const VIP_MONITOR_HOLD_FRAMES: u32 = 10;
+
+/// the VIP's address decoding: 4K of RAM, repeated below 0x8000, and the
+/// 512 byte ROM, repeated above. after a reset the ROM also shows up at 0000
+/// until something touches the top half of memory
+struct VipMemory {
+    memory: Chip8MemoryMap,
+    booting: Cell<bool>,
+    // writes to ROM go nowhere
+    rom_sink: [u8; 1],
+}
+
+impl VipMemory {
+    fn map(&self, addr: u16) -> u16 {
+        if addr & 0x8000 != 0 {
+            self.booting.set(false);
+        }
+        if addr & 0x8000 != 0 || self.booting.get() {
+            0x8000 | (addr & 0x1ff)
+        } else {
+            addr & 0xfff
+        }
+    }
+}
+
+impl MemoryMap for VipMemory {
+    fn get_rw_slice(&mut self, addr: u16, len: usize) -> Result<&mut [u8], Chip8Error> {
+        match self.map(addr) {
+            a if a & 0x8000 != 0 && len == 1 => Ok(&mut self.rom_sink),
+            a => self.memory.get_rw_slice(a, len),
+        }
+    }
+
+    fn get_ro_slice(&self, addr: u16, len: usize) -> Result<&[u8], Chip8Error> {
+        self.memory.get_ro_slice(self.map(addr), len)
+    }
+}
+
+/// the 1861 and the keypad, as far as the 1802 can see them
+#[derive(Default)]
+struct VipIo {
+    display_on: bool,
+    // the key OUT 2 asked about
+    latched_key: u8,
+    // the key being held down this frame
+    held_key: Option<u8>,
+    line: u64,
+}
+
+impl Cdp1802Io for VipIo {
+    fn output(&mut self, port: u8, value: u8) -> Result<(), Chip8Error> {
+        match port {
+            1 => self.display_on = false,
+            2 => self.latched_key = value & 0xf,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn input(&mut self, port: u8) -> Result<u8, Chip8Error> {
+        if port == 1 {
+            self.display_on = true;
+        }
+        Ok(0)
+    }
+
+    fn flag(&mut self, line: u8) -> bool {
+        let display_end = VIP_DISPLAY_LINE + VIP_DISPLAY_LINES;
+        match line {
+            1 => {
+                (VIP_DISPLAY_LINE - VIP_EF1_LINES..VIP_DISPLAY_LINE).contains(&self.line)
+                    || (display_end - VIP_EF1_LINES..display_end).contains(&self.line)
+            }
+            3 => self.held_key == Some(self.latched_key),
+            _ => false,
+        }
+    }
+}
+
+/// a COSMAC VIP, run a frame at a time
+pub struct VipMachine {
+    cpu: Cdp1802,
+    memory: VipMemory,
+    io: VipIo,
+    // what the 1861 fetched this frame, a line at a time
+    raster: Vec<u8>,
+    // cycles the last frame ran over by
+    overrun: u64,
+    hold_c_frames: u32,
+}
+
+impl VipMachine {
+    /// a VIP with memory in it, e.g. with a program loaded, waiting for reset
+    pub fn new(memory: Chip8MemoryMap) -> Self {
+        let mut vip = VipMachine {
+            cpu: Cdp1802::new(),
+            memory: VipMemory {
+                memory,
+                booting: Cell::new(true),
+                rom_sink: [0],
+            },
+            io: VipIo::default(),
+            raster: vec![0; (VIP_DISPLAY_LINES * 8) as usize],
+            overrun: 0,
+            hold_c_frames: 0,
+        };
+        vip.reset(false);
+        vip
+    }
+
+    /// flip the RUN switch off and on again. with monitor, hold down C while
+    /// doing it to get the ROM's hex monitor; otherwise the ROM runs whatever's
+    /// at 0000, which is the CHIP-8 interpreter
+    pub fn reset(&mut self, monitor: bool) {
+        self.cpu.reset();
+        self.memory.booting.set(true);
+        self.io.display_on = false;
+        self.hold_c_frames = if monitor { VIP_MONITOR_HOLD_FRAMES } else { 0 };
+    }
+
+    pub fn cpu(&self) -> &Cdp1802 {
+        &self.cpu
+    }
+
+    pub fn memory(&self) -> &Chip8MemoryMap {
+        &self.memory.memory
+    }
+
+    /// run frame_count frames in real time, or until the player wants the menu
+    pub fn main_loop(
+        &mut self,
+        frame_count: usize,
+        display: &mut dyn Display,
+        input: &mut dyn Input,
+        sound: &mut dyn Sound,
+    ) -> Result<RunOutcome, Chip8Error> {
+        let frame = time::Duration::from_secs(1) / 60;
+        let mut next = time::Instant::now();
+        for _ in 0..frame_count {
+            self.run_frame(display, input, sound)?;
+            if input.take_menu_request()? {
+                return Ok(RunOutcome::MenuRequested);
+            }
+            next += frame;
+            spin_sleep::sleep(next.saturating_duration_since(time::Instant::now()));
+        }
+        Ok(RunOutcome::Finished)
+    }
+
+    /// run for a frame, then show what the 1861 drew
+    pub fn run_frame(
+        &mut self,
+        display: &mut dyn Display,
+        input: &mut dyn Input,
+        sound: &mut dyn Sound,
+    ) -> Result<(), Chip8Error> {
+        input.tick()?;
+        self.io.held_key = input.read_key()?;
+        if self.hold_c_frames > 0 {
+            self.hold_c_frames -= 1;
+            self.io.held_key = Some(0xc);
+        }
+
+        self.raster.fill(0);
+        let mut cycle = self.overrun;
+        let mut next_dma_line = VIP_DISPLAY_LINE;
+        let mut interrupted = false;
+        while cycle < CHIP8_FRAME_CYCLES {
+            let line = cycle / VIP_LINE_CYCLES;
+            self.io.line = line;
+            if !self.io.display_on {
+                next_dma_line = VIP_DISPLAY_LINE + VIP_DISPLAY_LINES;
+            }
+            // DMA steals 8 cycles from each displayed line. the
+            // interrupt routine's timed to the cycle to move R0 between them
+            if next_dma_line < VIP_DISPLAY_LINE + VIP_DISPLAY_LINES
+                && cycle >= next_dma_line * VIP_LINE_CYCLES + VIP_DMA_OFFSET
+            {
+                let start = ((next_dma_line - VIP_DISPLAY_LINE) * 8) as usize;
+                for byte in &mut self.raster[start..start + 8] {
+                    *byte = self.cpu.dma_out(&self.memory)?;
+                }
+                cycle += 8;
+                next_dma_line += 1;
+                continue;
+            }
+            if self.io.display_on
+                && !interrupted
+                && (VIP_INTERRUPT_LINE..VIP_DISPLAY_LINE).contains(&line)
+                && self.cpu.ie
+            {
+                cycle += self.cpu.interrupt() as u64;
+                interrupted = true;
+                continue;
+            }
+            // Q drives the buzzer
+            let q = self.cpu.q;
+            cycle += self.cpu.step(&mut self.memory, &mut self.io)? as u64;
+            match (q, self.cpu.q) {
+                (false, true) => sound.beep()?,
+                (true, false) => sound.stop()?,
+                _ => {}
+            }
+        }
+        self.overrun = cycle - CHIP8_FRAME_CYCLES;
+        sound.tick()?;
+
+        // each row of pixels is four lines tall
+        let frame: Vec<u8> = self
+            .raster
+            .chunks(8)
+            .step_by(4)
+            .flatten()
+            .copied()
+            .collect();
+        display.draw(&frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::fixtures::LastFrame;
+    use crate::input::DummyInput;
+    use crate::ocr;
+    use crate::sound::Mute;
+
+    fn run(
+        program: &[u8],
+        monitor: bool,
+        frames: usize,
+    ) -> Result<(VipMachine, Vec<u8>), Chip8Error> {
+        let mut memory = Chip8MemoryMap::new()?;
+        memory.load_program(&mut &program[..])?;
+        let mut vip = VipMachine::new(memory);
+        vip.reset(monitor);
+        let mut display = LastFrame(vec![]);
+        let mut input = DummyInput::new(&[]);
+        let mut sound = Mute::new();
+        for _ in 0..frames {
+            vip.run_frame(&mut display, &mut input, &mut sound)?;
+        }
+        Ok((vip, display.0))
+    }
+
+    #[test]
+    fn test_runs_chip8_on_the_real_interpreter() -> Result<(), Chip8Error> {
+        // v0 = 2a; v1 = 2; draw the glyph for v1 at (v0, v0); loop forever.
+        // the VIP wraps y, so it ends up on row 10
+        #[rustfmt::skip]
+        let program = [
+            0x60, 0x2a, 0x61, 0x02, 0xf1, 0x29, 0xd0, 0x05, 0x12, 0x08,
+        ];
+        let (vip, frame) = run(&program, false, 10)?;
+        assert_eq!(vip.memory().get_ro_slice(0xef0, 2)?, &[0x2a, 0x02]);
+        let reader = ocr::DigitReader::from_memory(vip.memory())?;
+        assert_eq!(reader.find_digits(&frame), vec![(0x2a, 10, 2)]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_boot_time() -> Result<(), Chip8Error> {
+        // the display's off until the interpreter's own 004B, so nothing
+        // gets in the way of stepping through the boot
+        let mut memory = Chip8MemoryMap::new()?;
+        memory.load_program(&mut &[0x12, 0x00][..])?;
+        let mut vip = VipMachine::new(memory);
+        let mut cycles = 0;
+        while !vip.io.display_on {
+            cycles += vip.cpu.step(&mut vip.memory, &mut vip.io)? as u64;
+            assert!(cycles < CHIP8_FRAME_CYCLES);
+        }
+        // which is the last thing before the program
+        assert_eq!(vip.cpu.r[5], 0x200);
+        assert_eq!(cycles, crate::boot::VIP_BOOT_CYCLES);
+        Ok(())
+    }
+
+    #[test]
+    fn test_boots_into_monitor() -> Result<(), Chip8Error> {
+        let (vip, frame) = run(&[0x12, 0x00], true, 10)?;
+        // still running the ROM, showing something
+        assert!(vip.cpu().r[vip.cpu().p as usize] >= 0x8000);
+        assert!(frame.iter().any(|b| *b != 0));
+        Ok(())
+    }
+}
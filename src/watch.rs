@@ -0,0 +1,232 @@
+#[cfg(feature = "full")]
+use crate::cheat::parse_addr;
+use crate::error::Chip8Error;
+#[cfg(feature = "full")]
+use crate::memory::XO_CHIP_RAM_BYTES;
+use crate::memory::{Chip8MemoryMap, MemoryMap};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+
+/// something that keeps an eye on memory as a program runs, e.g. to spot
+/// when the player's done something notable
+pub trait MemoryWatch {
+    /// called at the end of every frame, once the display has been drawn.
+    /// returns anything worth telling the player about
+    fn frame(&mut self, memory: &Chip8MemoryMap) -> Result<Vec<String>, Chip8Error>;
+}
+
+/// something that gets to change memory as a program runs, e.g. cheats
+pub trait MemoryPatch {
+    /// called after every instruction completes
+    fn after_instruction(&mut self, memory: &mut Chip8MemoryMap) -> Result<(), Chip8Error>;
+}
+
+// so that a watch or patch can be handed to the interpreter and still be
+// got at between runs, e.g. from the emulator's menu
+
+impl<T: MemoryWatch> MemoryWatch for &RefCell<T> {
+    fn frame(&mut self, memory: &Chip8MemoryMap) -> Result<Vec<String>, Chip8Error> {
+        self.borrow_mut().frame(memory)
+    }
+}
+
+impl<T: MemoryPatch> MemoryPatch for &RefCell<T> {
+    fn after_instruction(&mut self, memory: &mut Chip8MemoryMap) -> Result<(), Chip8Error> {
+        self.borrow_mut().after_instruction(memory)
+    }
+}
+
+/// how many frames a Watch remembers: a minute's worth
+pub const WATCH_HISTORY: usize = 3600;
+
+/// what a sparkline's drawn with, lowest first
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// a few bytes of memory, sampled every frame, e.g. to find which one's
+/// the lives by watching them go down
+pub struct Watch {
+    pub addr: u16,
+    pub len: usize,
+    // oldest first
+    samples: VecDeque<Vec<u8>>,
+}
+
+impl Watch {
+    pub fn new(addr: u16, len: usize) -> Self {
+        Watch {
+            addr,
+            len,
+            samples: VecDeque::new(),
+        }
+    }
+
+    /// parse "addr" or "addr:len", e.g. "0x3a0:2". it has to fit in the
+    /// most memory any variant has, as which the ROM runs as isn't known yet
+    #[cfg(feature = "full")]
+    pub fn parse(s: &str) -> Result<Self, Chip8Error> {
+        let (addr, len) = s.split_once(':').unwrap_or((s, "1"));
+        let len = len
+            .parse()
+            .map_err(|_| Chip8Error::ConfigError(format!("\"{}\" isn't a length", len)))?;
+        let addr = parse_addr(addr)?;
+        if len == 0 || addr as usize + len > XO_CHIP_RAM_BYTES {
+            return Err(Chip8Error::ConfigError(format!(
+                "\"{}\" isn't in memory (which ends at {:#x})",
+                s,
+                XO_CHIP_RAM_BYTES - 1
+            )));
+        }
+        Ok(Watch::new(addr, len))
+    }
+
+    /// every frame's bytes, oldest first
+    pub fn samples(&self) -> impl Iterator<Item = &[u8]> {
+        self.samples.iter().map(|s| s.as_slice())
+    }
+
+    /// one byte's values over time, oldest first
+    pub fn series(&self, byte: usize) -> Vec<u8> {
+        self.samples
+            .iter()
+            .filter_map(|s| s.get(byte).copied())
+            .collect()
+    }
+
+    /// a line for each byte: where it is, what it is now, and a sparkline
+    /// of its last width frames
+    pub fn plot(&self, width: usize) -> Vec<String> {
+        (0..self.len)
+            .map(|byte| {
+                let series = self.series(byte);
+                let recent = &series[series.len().saturating_sub(width)..];
+                let (low, high) = (
+                    recent.iter().min().copied().unwrap_or(0),
+                    recent.iter().max().copied().unwrap_or(0),
+                );
+                format!(
+                    "{:#05x} = {:3}  {}  ({}..{})",
+                    self.addr as usize + byte,
+                    recent.last().copied().unwrap_or(0),
+                    sparkline(recent),
+                    low,
+                    high
+                )
+            })
+            .collect()
+    }
+}
+
+impl MemoryWatch for Watch {
+    fn frame(&mut self, memory: &Chip8MemoryMap) -> Result<Vec<String>, Chip8Error> {
+        if self.samples.len() == WATCH_HISTORY {
+            self.samples.pop_front();
+        }
+        self.samples
+            .push_back(memory.get_ro_slice(self.addr, self.len)?.to_vec());
+        Ok(Vec::new())
+    }
+}
+
+/// values as a line of bars, scaled so the lowest is the shortest and the
+/// highest the tallest
+pub fn sparkline(values: &[u8]) -> String {
+    let low = values.iter().min().copied().unwrap_or(0) as usize;
+    let high = values.iter().max().copied().unwrap_or(0) as usize;
+    values
+        .iter()
+        .map(|v| match high - low {
+            0 => SPARKS[0],
+            range => SPARKS[(*v as usize - low) * (SPARKS.len() - 1) / range],
+        })
+        .collect()
+}
+
+/// all the watches, added and taken away from the menu
+#[derive(Default)]
+pub struct WatchSet {
+    watches: Vec<Watch>,
+}
+
+impl WatchSet {
+    /// watch, replacing any there was already at its address
+    pub fn add(&mut self, watch: Watch) {
+        self.remove(watch.addr);
+        self.watches.push(watch);
+    }
+
+    /// stop watching addr; true if it was being watched
+    pub fn remove(&mut self, addr: u16) -> bool {
+        let before = self.watches.len();
+        self.watches.retain(|w| w.addr != addr);
+        self.watches.len() != before
+    }
+
+    pub fn watches(&self) -> &[Watch] {
+        &self.watches
+    }
+}
+
+impl MemoryWatch for WatchSet {
+    fn frame(&mut self, memory: &Chip8MemoryMap) -> Result<Vec<String>, Chip8Error> {
+        for w in self.watches.iter_mut() {
+            w.frame(memory)?;
+        }
+        Ok(Vec::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watch() -> Result<(), Chip8Error> {
+        let mut memory = Chip8MemoryMap::new()?;
+        let mut watch = Watch::new(0x3a0, 2);
+        for lives in [3, 3, 2, 1, 0] {
+            memory.get_rw_slice(0x3a0, 2)?.copy_from_slice(&[lives, 7]);
+            watch.frame(&memory)?;
+        }
+        assert_eq!(watch.series(0), [3, 3, 2, 1, 0]);
+        assert_eq!(watch.samples().count(), 5);
+        assert_eq!(
+            watch.plot(4),
+            ["0x3a0 =   0  █▅▃▁  (0..3)", "0x3a1 =   7  ▁▁▁▁  (7..7)"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_watch_history() -> Result<(), Chip8Error> {
+        let memory = Chip8MemoryMap::new()?;
+        let mut watches = WatchSet::default();
+        watches.add(Watch::new(0x200, 1));
+        watches.add(Watch::new(0x200, 2));
+        assert_eq!(watches.watches().len(), 1);
+        for _ in 0..WATCH_HISTORY + 10 {
+            watches.frame(&memory)?;
+        }
+        assert_eq!(watches.watches()[0].samples().count(), WATCH_HISTORY);
+        assert!(watches.remove(0x200));
+        assert!(!watches.remove(0x200));
+        Ok(())
+    }
+
+    #[cfg(feature = "full")]
+    mod full {
+        use super::*;
+
+        #[test]
+        fn test_parse() -> Result<(), Chip8Error> {
+            let watch = Watch::parse("0x3a0:2")?;
+            assert_eq!((watch.addr, watch.len), (0x3a0, 2));
+            assert_eq!(Watch::parse("0x3a0")?.len, 1);
+            assert!(Watch::parse("0x3a0:two").is_err());
+            assert!(Watch::parse("0x3a0:0").is_err());
+            assert!(Watch::parse("0xffff:2").is_err());
+            assert!(Watch::parse("0x10000").is_err());
+            assert_eq!(Watch::parse("0xffff")?.addr, 0xffff);
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,279 @@
+//! # drawing in a window
+//!
+//! a frontend with a window of its own (the gui, or the SDL example) has a
+//! 64x32 picture, or 128x64, to fit into however big the window's been
+//! made. stretched to fill it, the pixels come out all different sizes and
+//! the wrong shape, and blended into each other they come out blurred, so
+//! by default the picture's scaled by whole multiples, sharp, and kept the
+//! shape it is, with black round what's left over:
+//!
+//! * integer: as big as it'll go in whole multiples, so every pixel's the
+//!   same size
+//! * aspect: as big as it'll go without changing shape, which may leave
+//!   some pixels a screen pixel bigger than others
+//! * stretch: filling the window, whatever shape that makes the pixels
+//!
+//! with "+smooth" on any of them, e.g. "aspect+smooth", to blend the
+//! pixels rather than take the nearest (which hides the uneven ones)
+//!
+//! F11 takes a window to the whole of the monitor it's on, without its
+//! borders, and back. where it was left, and how big, and whether it was
+//! fullscreen, is kept (in config) to open it the same way next time: on
+//! the same monitor, as that's where its position says it is
+use crate::error::Chip8Error;
+#[cfg(feature = "full")]
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// how the picture's fitted into the window
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Fit {
+    Integer,
+    Aspect,
+    Stretch,
+}
+
+impl Fit {
+    pub const ALL: [Fit; 3] = [Fit::Integer, Fit::Aspect, Fit::Stretch];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Fit::Integer => "integer",
+            Fit::Aspect => "aspect",
+            Fit::Stretch => "stretch",
+        }
+    }
+}
+
+/// how a window draws the picture
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Scaling {
+    pub fit: Fit,
+    /// blend neighbouring pixels, rather than take the nearest
+    pub smooth: bool,
+}
+
+impl Default for Scaling {
+    fn default() -> Self {
+        Scaling {
+            fit: Fit::Integer,
+            smooth: false,
+        }
+    }
+}
+
+impl fmt::Display for Scaling {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.smooth {
+            true => write!(f, "{}+smooth", self.fit.name()),
+            false => write!(f, "{}", self.fit.name()),
+        }
+    }
+}
+
+/// where the picture goes in the window, in the window's pixels
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Viewport {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// where a window was and how big, in the desktop's coordinates, which
+/// span all the monitors
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "full", derive(Serialize, Deserialize))]
+pub struct Geometry {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    /// taking up the whole of its monitor, to go back to the rest of it
+    /// when it isn't
+    #[cfg_attr(feature = "full", serde(default))]
+    pub fullscreen: bool,
+}
+
+/// where a window is, kept up to date by whatever's drawing it, for
+/// whoever wants to know where it was left
+#[derive(Debug, Clone, Default)]
+pub struct Whereabouts(Arc<Mutex<Option<Geometry>>>);
+
+impl Whereabouts {
+    /// somewhere to start, if it's been somewhere before
+    pub fn new(start: Option<Geometry>) -> Self {
+        Whereabouts(Arc::new(Mutex::new(start)))
+    }
+
+    pub fn get(&self) -> Option<Geometry> {
+        *self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// it's been moved or resized, or gone fullscreen (or come back).
+    /// fullscreen, it's where it was before that's worth keeping, to come
+    /// back to
+    pub fn moved(&self, now: Geometry) {
+        let mut was = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        match (now.fullscreen, was.as_mut()) {
+            (true, Some(was)) => was.fullscreen = true,
+            _ => *was = Some(now),
+        }
+    }
+}
+
+impl Scaling {
+    /// e.g. "integer", or "aspect+smooth"
+    pub fn parse(s: &str) -> Result<Self, Chip8Error> {
+        let (fit, smooth) = match s.split_once('+') {
+            Some((fit, "smooth")) => (fit, true),
+            Some(_) => ("", false),
+            None => (s, false),
+        };
+        match Fit::ALL.iter().find(|f| f.name() == fit) {
+            Some(fit) => Ok(Scaling { fit: *fit, smooth }),
+            None => Err(Chip8Error::ConfigError(format!(
+                "can't scale to \"{}\" (try integer, aspect or stretch, with +smooth to blend)",
+                s
+            ))),
+        }
+    }
+
+    /// where a picture of size (width, height) goes in a window of size
+    /// (width, height), centred. a window smaller than the picture gets it
+    /// at its own size, cut off
+    pub fn place(&self, picture: (u32, u32), window: (u32, u32)) -> Viewport {
+        let (pw, ph) = (picture.0.max(1), picture.1.max(1));
+        let (ww, wh) = window;
+        let (width, height) = match self.fit {
+            Fit::Stretch => (ww, wh),
+            Fit::Aspect => {
+                // whichever way round runs out of room first
+                match ww as u64 * ph as u64 <= wh as u64 * pw as u64 {
+                    true => (ww, (ww as u64 * ph as u64 / pw as u64) as u32),
+                    false => ((wh as u64 * pw as u64 / ph as u64) as u32, wh),
+                }
+            }
+            Fit::Integer => {
+                let n = (ww / pw).min(wh / ph).max(1);
+                (pw * n, ph * n)
+            }
+        };
+        Viewport {
+            x: ww.saturating_sub(width) / 2,
+            y: wh.saturating_sub(height) / 2,
+            width,
+            height,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() -> Result<(), Chip8Error> {
+        assert_eq!(Scaling::parse("integer")?, Scaling::default());
+        let smooth = Scaling::parse("aspect+smooth")?;
+        assert_eq!(
+            smooth,
+            Scaling {
+                fit: Fit::Aspect,
+                smooth: true
+            }
+        );
+        assert_eq!(smooth.to_string(), "aspect+smooth");
+        assert!(Scaling::parse("stretch+blur").is_err());
+        assert!(Scaling::parse("fill").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_whereabouts() {
+        let windowed = Geometry {
+            x: 1920,
+            y: 40,
+            width: 640,
+            height: 320,
+            fullscreen: false,
+        };
+        let whereabouts = Whereabouts::new(None);
+        let seen = whereabouts.clone();
+        whereabouts.moved(windowed);
+        assert_eq!(seen.get(), Some(windowed));
+        // the whole of the second monitor, which is worth knowing, but not
+        // where it goes back to
+        whereabouts.moved(Geometry {
+            x: 1920,
+            y: 0,
+            width: 2560,
+            height: 1440,
+            fullscreen: true,
+        });
+        assert_eq!(
+            seen.get(),
+            Some(Geometry {
+                fullscreen: true,
+                ..windowed
+            })
+        );
+        whereabouts.moved(windowed);
+        assert_eq!(seen.get(), Some(windowed));
+    }
+
+    #[test]
+    fn test_place() {
+        let place = |fit, window| Scaling { fit, smooth: false }.place((64, 32), window);
+        // 1000/64 is 15 and a bit, so the pixels are 15 across and 15 down
+        assert_eq!(
+            place(Fit::Integer, (1000, 600)),
+            Viewport {
+                x: 20,
+                y: 60,
+                width: 960,
+                height: 480
+            }
+        );
+        // as wide as it goes, with bars above and below
+        assert_eq!(
+            place(Fit::Aspect, (1000, 600)),
+            Viewport {
+                x: 0,
+                y: 50,
+                width: 1000,
+                height: 500
+            }
+        );
+        // as tall as it goes, with bars either side
+        assert_eq!(
+            place(Fit::Aspect, (1000, 300)),
+            Viewport {
+                x: 200,
+                y: 0,
+                width: 600,
+                height: 300
+            }
+        );
+        assert_eq!(
+            place(Fit::Stretch, (1000, 300)),
+            Viewport {
+                x: 0,
+                y: 0,
+                width: 1000,
+                height: 300
+            }
+        );
+        // too small for even one to one
+        assert_eq!(
+            place(Fit::Integer, (50, 20)),
+            Viewport {
+                x: 0,
+                y: 0,
+                width: 64,
+                height: 32
+            }
+        );
+    }
+}
@@ -0,0 +1,42 @@
+//! a display recording standing in for the interpreter: what a backend
+//! draws from a run played back is what it drew live
+#![cfg(feature = "full")]
+use chip8::asm;
+use chip8::error::Chip8Error;
+use chip8::input::DummyInput;
+use chip8::interpreter::Chip8Interpreter;
+use chip8::record::{DisplayRecording, RecordingDisplay, VideoRecorder};
+use chip8::sound::Mute;
+use std::path::Path;
+
+const FRAMES: u64 = 120;
+
+#[test]
+fn test_play_into_another_backend() -> Result<(), Chip8Error> {
+    let path = Path::new("tests").join("programs").join("bounce.8o");
+    let rom = asm::assemble_file(&path)?.rom;
+
+    // the run, recorded, and as a video
+    let (mut recording, mut live) = (Vec::new(), Vec::new());
+    {
+        let mut video = VideoRecorder::new(&mut live, 64, 32, 1, None);
+        let mut display = RecordingDisplay::new(&mut recording, 64, 32, Some(&mut video));
+        let mut input = DummyInput::new(&[]);
+        let mut sound = Mute::new();
+        let mut i = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+        i.set_seed(0);
+        i.load_program(&mut &rom[..])?;
+        i.run_frames(FRAMES)?;
+    }
+
+    // and the recording played into a video of its own, with no interpreter
+    let recording = DisplayRecording::read(&recording[..])?;
+    let mut played = Vec::new();
+    let mut video = VideoRecorder::new(&mut played, 64, 32, 1, None);
+    let drawn = recording.play(&mut video)?;
+    assert!(drawn >= FRAMES - 1, "{} frames", drawn);
+    assert_eq!(played, live);
+    // and something was drawn, not just nothing twice over
+    assert!(recording.frames().any(|(_, f)| f.iter().any(|b| *b != 0)));
+    Ok(())
+}
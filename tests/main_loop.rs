@@ -0,0 +1,91 @@
+//! main_loop, the way the emulator runs a game for real, from end to end:
+//! everything it's usually given (a display, input and sound), but headless
+//! and on a ManualClock, so the sleeping it does to keep time costs nothing
+#![cfg(feature = "full")]
+use chip8::asm;
+use chip8::clock::{Clock, ManualClock};
+use chip8::display::{self, DummyDisplay};
+use chip8::error::Chip8Error;
+use chip8::input::DummyInput;
+use chip8::interpreter::{Chip8Interpreter, RunOutcome};
+use chip8::memory::MemoryMap;
+use chip8::ocr;
+use chip8::sound::Mute;
+use chip8::stable::Frame;
+use std::path::Path;
+use std::time::Duration;
+
+/// long enough for the ball to have been all the way down and back up
+const FRAMES: usize = 300;
+
+/// a frame of the VIP's, near enough
+const FRAME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+fn bounce() -> Result<Vec<u8>, Chip8Error> {
+    let path = Path::new("tests").join("programs").join("bounce.8o");
+    Ok(asm::assemble_file(&path)?.rom)
+}
+
+/// how things are after FRAMES frames of the program
+struct After {
+    memory: Vec<u8>,
+    screen: Vec<u8>,
+    /// where the ball is
+    ball: (usize, usize),
+}
+
+/// run rom for FRAMES frames: through main_loop keeping time with clock, or
+/// flat out with none
+fn after(rom: &[u8], clock: Option<&ManualClock>) -> Result<After, Chip8Error> {
+    let mut display = DummyDisplay::new()?;
+    let mut input = DummyInput::new(&[]);
+    let mut sound = Mute::new();
+    let mut i = Chip8Interpreter::new(&mut display, &mut input, &mut sound)?;
+    i.set_seed(0);
+    i.load_program(&mut &rom[..])?;
+    match clock {
+        Some(clock) => {
+            i.set_clock(clock);
+            assert_eq!(i.main_loop(FRAMES)?, RunOutcome::Finished);
+        }
+        None => i.run_frames(FRAMES as u64)?,
+    }
+    assert_eq!(i.frames(), FRAMES as u64);
+    Ok(After {
+        memory: i.memory().get_ro_slice(0, 0x1000)?.to_vec(),
+        screen: ocr::framebuffer(i.memory())?.to_vec(),
+        ball: (i.v(0) as usize, i.v(1) as usize),
+    })
+}
+
+#[test]
+fn test_main_loop() -> Result<(), Chip8Error> {
+    let rom = bounce()?;
+    let clock = ManualClock::new();
+    let run = after(&rom, Some(&clock))?;
+
+    // five seconds kept, without waiting five seconds for them
+    assert!(clock.now() >= (FRAMES as u32 - 1) * FRAME);
+    assert!(clock.now() <= (FRAMES as u32 + 1) * FRAME);
+
+    // the ball's moved, and it's on the screen where the program says
+    let (x, y) = run.ball;
+    assert_ne!((x, y), (10, 5));
+    let screen = Frame::new(64, 32, &run.screen);
+    assert!(screen.pixel(x + 1, y));
+    assert!(screen.pixel(x, y + 1));
+    assert!(!screen.pixel(x, y));
+    // and nowhere else
+    assert_eq!(screen.bounds(), Some((x, y, 4, 4)));
+    assert_eq!(
+        screen.lit_count(),
+        12,
+        "\n{}",
+        display::to_ascii(&run.screen, 64)
+    );
+
+    // which is just where it'd be run flat out, without a clock
+    let flat_out = after(&rom, None)?;
+    assert_eq!(run.memory, flat_out.memory);
+    Ok(())
+}
@@ -0,0 +1,84 @@
+//! the example programs in tests/programs, assembled and run: they're
+//! there to show what the assembler can do, so they'd better work
+#![cfg(feature = "full")]
+use chip8::asm;
+use chip8::decompile;
+use chip8::error::Chip8Error;
+use chip8::ocr::{self, DigitReader};
+use chip8::romtest::RomTest;
+use std::path::Path;
+
+fn assemble(name: &str) -> Result<asm::Assembled, Chip8Error> {
+    asm::assemble_file(&Path::new("tests").join("programs").join(name))
+}
+
+#[test]
+fn test_score() -> Result<(), Chip8Error> {
+    let program = assemble("score.8o")?;
+    let halt = program.labels["halt"];
+    RomTest::run(&program.rom, |t| {
+        while t.interpreter().pc() != halt {
+            t.interpreter().step()?;
+        }
+        let memory = t.interpreter().memory();
+        let score =
+            DigitReader::from_memory(memory)?.read_number(ocr::framebuffer(memory)?, 24, 12, 3, 5);
+        assert_eq!(score, Some(123));
+        Ok(())
+    })
+}
+
+#[test]
+fn test_drift() -> Result<(), Chip8Error> {
+    let program = assemble("drift.8o")?;
+    // the ball's sprite was aligned
+    assert_eq!(program.labels["ball"] % 16, 0);
+    let top_of_loop = program.labels["frame"];
+    RomTest::run(&program.rom, |t| {
+        // round the loop until the ball's gone off the bottom and come back
+        // round to the top
+        while t.interpreter().pc() != top_of_loop || t.interpreter().v(0) != 40 {
+            t.interpreter().step()?;
+        }
+        assert_eq!(t.interpreter().v(1), 0);
+        t.expect_pixel(40, 0, false)?;
+        t.expect_pixel(41, 0, true)?;
+        t.expect_pixel(40, 1, true)?;
+        // and it's been rubbed out everywhere it's been
+        t.expect_pixel(39, 31, false)?;
+        t.expect_pixel(10, 2, false)
+    })
+}
+
+#[test]
+fn test_bounce() -> Result<(), Chip8Error> {
+    let program = assemble("bounce.8o")?;
+    RomTest::run(&program.rom, |t| {
+        let (mut left, mut right, mut top, mut bottom) = (u8::MAX, 0, u8::MAX, 0);
+        for _ in 0..1000 {
+            t.run_frames(1)?;
+            let (x, y) = (t.interpreter().v(0), t.interpreter().v(1));
+            (left, right) = (left.min(x), right.max(x));
+            (top, bottom) = (top.min(y), bottom.max(y));
+        }
+        // it's been all the way across, both ways, and never off the edge
+        assert_eq!((left, right), (0, 60));
+        assert_eq!((top, bottom), (0, 28));
+        Ok(())
+    })
+}
+
+#[test]
+fn test_decompile() -> Result<(), Chip8Error> {
+    // each of them, decompiled, builds back into the same ROM, with its
+    // loops and sprites found again
+    for name in ["score.8o", "drift.8o", "bounce.8o"] {
+        let rom = assemble(name)?.rom;
+        let source = decompile::decompile(name, &rom);
+        assert_eq!(asm::assemble(name, &source)?.rom, rom, "{}", source);
+    }
+    let source = decompile::decompile("bounce.ch8", &assemble("bounce.8o")?.rom);
+    assert!(source.contains("\n      while v4 != 0\n    again\n"));
+    assert!(source.contains("\n: sprite-23e\n  0b01100000\n  0b11110000\n"));
+    Ok(())
+}
@@ -0,0 +1,227 @@
+//! the stable API, used only as a frontend outside the crate would: if
+//! these need changing to compile, the API's broken its promise
+#![cfg(feature = "full")]
+use chip8::asm;
+use chip8::stable::{Config, Emulator, Error, Frame, KeyEvent, Registers};
+
+fn rom(source: &str) -> Vec<u8> {
+    asm::assemble("stable", source).expect("it assembles").rom
+}
+
+fn run(emulator: &mut Emulator, frames: usize) -> Result<Frame, Error> {
+    let mut frame = emulator.run_frame()?;
+    for _ in 1..frames {
+        frame = emulator.run_frame()?;
+    }
+    Ok(frame)
+}
+
+#[test]
+fn test_keys_and_frames() -> Result<(), Error> {
+    // 5, drawn in the corner once it's pressed
+    let rom = rom("
+        : main
+          v0 := 5
+          if v0 -key then jump main
+          v1 := 0
+          i := hex v0
+          sprite v1 v1 5
+        : halt
+          jump halt
+        ");
+    let mut emulator = Emulator::new(&rom, &Config::default())?;
+    let frame = run(&mut emulator, 3)?;
+    assert_eq!((frame.width(), frame.height()), (64, 32));
+    assert!(!frame.pixel(0, 0));
+
+    // another key held on top of 5 hides it, until it's let go
+    emulator.key(KeyEvent::Down(0x5));
+    emulator.key(KeyEvent::Down(0x6));
+    assert!(!run(&mut emulator, 3)?.pixel(0, 0));
+    emulator.key(KeyEvent::Up(0x6));
+    let frame = run(&mut emulator, 3)?;
+    // 5's top row is 0xf0
+    assert!(frame.pixel(0, 0) && frame.pixel(3, 0) && !frame.pixel(4, 0));
+    assert!(!frame.pixel(64, 0));
+    emulator.key(KeyEvent::Up(0x5));
+    assert_eq!(run(&mut emulator, 3)?, frame);
+    Ok(())
+}
+
+#[test]
+fn test_config() -> Result<(), Error> {
+    let mut config = Config::default();
+    config.quirks = "nonsense".to_string();
+    let e = Emulator::new(&[0x12, 0x00], &config)
+        .err()
+        .expect("no such quirks");
+    assert!(e.to_string().contains("nonsense"));
+
+    // high resolution, and a beep
+    let mut config = Config::default();
+    config.schip = true;
+    config.seed = Some(1);
+    let rom = rom("
+          hires
+          v0 := 10
+          buzzer := v0
+        : halt
+          jump halt
+        ");
+    let mut emulator = Emulator::new(&rom, &config)?;
+    let frame = run(&mut emulator, 2)?;
+    assert_eq!((frame.width(), frame.height()), (128, 64));
+    assert!(emulator.beeping());
+    run(&mut emulator, 10)?;
+    assert!(!emulator.beeping());
+    Ok(())
+}
+
+#[test]
+fn test_debugging() -> Result<(), Error> {
+    let rom = rom("
+          v3 := 0x42
+          i := 0x300
+          spin
+        : spin
+          jump spin
+        ");
+    let mut emulator = Emulator::new(&rom, &Config::default())?;
+    assert_eq!(emulator.registers().pc, 0x200);
+    assert_eq!(&emulator.memory()[0x200..0x204], &rom[..4]);
+
+    // an instruction at a time
+    emulator.step()?;
+    assert_eq!(emulator.registers().v[3], 0x42);
+    emulator.step()?;
+    emulator.step()?;
+    let Registers { pc, i, stack, .. } = emulator.registers().clone();
+    assert_eq!((pc, i, stack), (0x206, 0x300, vec![0x206]));
+    run(&mut emulator, 2)?;
+    assert_eq!(emulator.registers().pc, 0x206);
+    Ok(())
+}
+
+#[test]
+fn test_step_waiting_for_a_key() -> Result<(), Error> {
+    // a step onto FX0A comes back with no key, rather than waiting for one
+    let rom = rom("
+          v0 := key
+          v1 := 1
+        : halt
+          jump halt
+        ");
+    let mut emulator = Emulator::new(&rom, &Config::default())?;
+    emulator.step()?;
+    emulator.step()?;
+    assert_eq!(emulator.registers().pc, 0x202);
+    // the VIP's FX0A wants it held for a few frames
+    emulator.key(KeyEvent::Down(0x7));
+    run(&mut emulator, 6)?;
+    emulator.step()?;
+    let Registers { pc, v, .. } = emulator.registers().clone();
+    assert_eq!((pc, v[0], v[1]), (0x204, 7, 1));
+    Ok(())
+}
+
+#[test]
+fn test_frames() -> Result<(), Error> {
+    // a dot walking across, a frame at a time
+    let rom = rom("
+          v0 := 0
+          i := dot
+        : walk
+          sprite v0 v0 1
+          v0 += 1
+          v1 := 1
+          delay := v1
+        : wait
+          v1 := delay
+          if v1 != 0 then jump wait
+          if v0 != 4 then jump walk
+          exit
+        : dot
+          0b10000000
+        ");
+    let mut config = Config::default();
+    config.schip = true;
+    let mut emulator = Emulator::new(&rom, &config)?;
+    let frames = emulator.frames().take(3).collect::<Result<Vec<_>, _>>()?;
+    let mut again = Emulator::new(&rom, &config)?;
+    assert_eq!(
+        frames,
+        vec![again.run_frame()?, again.run_frame()?, again.run_frame()?]
+    );
+
+    // and they stop when the program does
+    let rest = emulator.frames().collect::<Result<Vec<_>, _>>()?;
+    assert!(!rest.is_empty() && rest.len() < 10);
+    assert!(emulator.exited());
+    let last = rest.last().expect("a frame");
+    assert!((0..4).all(|n| last.pixel(n, n)));
+    assert_eq!(emulator.frames().count(), 0);
+    Ok(())
+}
+
+#[test]
+fn test_host_vsync() -> Result<(), Error> {
+    // a dot a frame, down the side, for as long as the frontend's vsync
+    // says there are frames
+    let rom = rom("
+          v0 := 0
+          i := dot
+        : walk
+          sprite v0 v0 1
+          v0 += 1
+          v1 := 1
+          delay := v1
+        : wait
+          v1 := delay
+          if v1 != 0 then jump wait
+          jump walk
+        : dot
+          0b10000000
+        ");
+    let mut emulator = Emulator::new(&rom, &Config::default())?;
+    emulator.set_host_vsync(true)?;
+    let mut vsynced = emulator.on_vblank()?;
+    for _ in 0..7 {
+        vsynced = emulator.on_vblank()?;
+    }
+    assert!(vsynced.pixel(2, 2));
+    // and frames carry on being run either way
+    assert!(run(&mut emulator, 3)?.pixel(3, 3));
+    emulator.set_host_vsync(false)?;
+    assert!(run(&mut emulator, 3)?.pixel(4, 4));
+    Ok(())
+}
+
+#[test]
+fn test_frame_geometry() {
+    let mut data = [0; 8 * 32];
+    // a 2x2 block at (9, 1), and a dot at (63, 3)
+    data[9] = 0b0110_0000;
+    data[17] = 0b0110_0000;
+    data[31] = 0b0000_0001;
+    let frame = Frame::new(64, 32, &data);
+    assert!(frame.pixel(9, 1) && frame.pixel(10, 2) && !frame.pixel(11, 1));
+    assert_eq!(
+        frame.lit().collect::<Vec<_>>(),
+        [(9, 1), (10, 1), (9, 2), (10, 2), (63, 3)]
+    );
+    assert_eq!(frame.lit_count(), 5);
+    assert_eq!(frame.bounds(), Some((9, 1, 55, 3)));
+
+    // rows that don't fill a byte, and nothing read past the end
+    let frame = Frame::new(3, 3, &[0b1000_0000, 0xff]);
+    assert!(frame.pixel(0, 0) && frame.pixel(2, 2) && !frame.pixel(1, 0));
+    assert!(!frame.pixel(3, 0) && !frame.pixel(0, 3));
+    assert_eq!(frame.lit().collect::<Vec<_>>(), [(0, 0), (2, 2)]);
+    assert_eq!(frame.lit_count(), 2);
+
+    // nothing lit, from too little data
+    let blank = Frame::new(128, 64, &[]);
+    assert_eq!((blank.width(), blank.height()), (128, 64));
+    assert_eq!(blank.lit().count(), 0);
+    assert_eq!(blank.bounds(), None);
+}